@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// 输入布局: old_len: u16 LE | old | patch
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+    let old_len = u16::from_le_bytes([data[0], data[1]]) as usize;
+    let rest = &data[2..];
+    let (old, patch) = rest.split_at(old_len.min(rest.len()));
+    let _ = bsdiff_rust_core::fuzz_patch(old, patch);
+});
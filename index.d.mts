@@ -0,0 +1,5 @@
+// ESM 入口的类型声明：与 CommonJS 入口 (index.d.ts) 共用同一份声明
+import * as binding from './index.js'
+
+export * from './index.js'
+export default binding
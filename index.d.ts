@@ -1,12 +1,147 @@
 /* auto-generated by NAPI-RS */
 /* eslint-disable */
 
-/** JavaScript 补丁信息结构 */
-export interface PatchInfoJs {
+// napi build 把本文件原样放在生成的声明之前 (package.json 的 napi.dtsHeaderFile)：
+// 声明绑定中以类型别名或 ts_arg_type 引用的类型，以及无法从 Rust 签名生成的重载
+
+/** 文件路径：字符串，或以原始字节给出的 Buffer (用于非 UTF-8 文件名) */
+export type PathLike = string | Buffer
+
+/** 补丁/输出参数：路径 (字符串或 Buffer)、文件描述符或 fs.promises.FileHandle (仅 Unix 支持描述符) */
+export type FileArg = PathLike | number | import('node:fs/promises').FileHandle
+
+/** 流式输入：文件路径、文件描述符、Buffer，或产出 Buffer 的 Readable 流 (任意异步可迭代对象) */
+export type StreamSource = string | number | Buffer | NodeJS.ReadableStream | AsyncIterable<Buffer>
+
+/** 流式输出：文件描述符或 Writable 流 (成功后调用 end()；文件描述符不会被关闭) */
+export type StreamSink = number | NodeJS.WritableStream
+
+/** 要求返回统计的 diff 选项：diff/diffSync/diffRange 的结果不为 null */
+export type DiffOptionsWithStats = DiffOptions & { stats: true }
+
+export declare function diff(oldStr: PathLike | undefined | null, newStr: PathLike, patch: FileArg, options: DiffOptionsWithStats, signal?: AbortSignal | undefined | null): Promise<DiffStatsJs>
+
+/** @deprecated 请改用 diff (参数与结果相同) */
+export declare function diffAsync(oldStr: PathLike | undefined | null, newStr: PathLike, patch: FileArg, options: DiffOptionsWithStats, signal?: AbortSignal | undefined | null): Promise<DiffStatsJs>
+
+/** @deprecated 旧的参数位置：signal 作为第 4 个参数；请改为 verifyPatch(oldStr, newStr, patch, options, signal) */
+export declare function verifyPatch(oldStr: PathLike, newStr: PathLike, patch: PathLike, signal: AbortSignal): Promise<boolean>
+
+export declare function diffSync(oldStr: PathLike | undefined | null, newStr: PathLike, patch: FileArg, options: DiffOptionsWithStats): DiffStatsJs
+
+export declare function diffRange(oldStr: PathLike, oldOffset: number, oldLength: number, newStr: PathLike, newOffset: number, newLength: number, patch: PathLike, options: DiffOptionsWithStats, signal?: AbortSignal | undefined | null): Promise<DiffStatsJs>
+
+export declare function diffRangeSync(oldStr: PathLike, oldOffset: number, oldLength: number, newStr: PathLike, newOffset: number, newLength: number, patch: PathLike, options: DiffOptionsWithStats): DiffStatsJs
+
+export declare function diffBuffersToFile(old: Buffer, new: Buffer, patch: PathLike, options: DiffOptionsWithStats): Promise<DiffStatsJs>
+
+export declare function diffBuffersToFileSync(old: Buffer, new: Buffer, patch: PathLike, options: DiffOptionsWithStats): DiffStatsJs
+
+/** 预计算旧文件后缀数组，对多个新文件重复生成补丁 */
+export declare class PreparedBase {
+  /** 映射旧文件并构建后缀数组 */
+  constructor(oldStr: PathLike)
+  /** 对新文件生成补丁，复用已构建的后缀数组 */
+  add(newStr: PathLike, patch: PathLike): void
+}
+
+/** JavaScript A/B 槽位 */
+export interface AbSlotsJs {
+  /** 槽位 A 的目录 */
+  slotA: PathLike
+  /** 槽位 B 的目录 */
+  slotB: PathLike
+  /** 状态文件路径 */
+  stateFile: PathLike
+}
+
+/** JavaScript 算法版本信息结构 */
+export interface AlgorithmInfoJs {
+  /** 内置 diff 算法的版本 (移植自的 bsdiff 版本，与补丁信息中的 bsdiffVersion 对应) */
+  algorithmVersion: string
+  zstdVersion: string
+  formatVersion: number
+}
+
+/** 获取编译进来的算法版本信息 */
+export declare function algorithmInfoSync(): AlgorithmInfoJs
+
+/** 把 delta 生成的补丁原地应用到旧文件 (异步，同 patchInPlace) */
+export declare function apply(oldStr: PathLike, patch: PathLike, options?: PatchOptions | undefined | null, signal?: AbortSignal | undefined | null): Promise<number>
+
+/** 应用补丁包 (异步) */
+export declare function applyBundle(bundle: PathLike, mapping: Record<string, BundleTargetJs>, options?: PatchOptions | undefined | null): Promise<Array<BundleFileJs>>
+
+/** 应用补丁包：`mapping` 以包内名称为键给出旧文件与输出路径，须恰好覆盖包内每个条目 */
+export declare function applyBundleSync(bundle: PathLike, mapping: Record<string, BundleTargetJs>, options?: PatchOptions | undefined | null): Array<BundleFileJs>
+
+/** 并排应用补丁包或目录差分包 (异步) */
+export declare function applyBundleToDir(bundle: PathLike, sourceDir: PathLike, targetDir: PathLike, options?: PatchOptions | undefined | null): Promise<DirDiffSummaryJs>
+
+/** 并排应用补丁包或目录差分包：在 `targetDir` (必须不存在) 生成更新后的完整目录树，`sourceDir` 保持不变 */
+export declare function applyBundleToDirSync(bundle: PathLike, sourceDir: PathLike, targetDir: PathLike, options?: PatchOptions | undefined | null): DirDiffSummaryJs
+
+/** 把 delta 生成的补丁应用到旧文件本身 (原地替换，同 patchInPlace)，返回新文件大小 */
+export declare function applySync(oldStr: PathLike, patch: PathLike, options?: PatchOptions | undefined | null): number
+
+/** 启动时调用：消耗一次试运行机会并返回应运行的槽位，机会用尽时回到可用槽位 */
+export declare function bootSlotSync(slots: AbSlotsJs): SlotStateJs
+
+/** 将多个补丁打包为一个带 (from, to) 索引的补丁集文件 */
+export declare function buildPatchSetSync(entries: Array<PatchSetEntryJs>, out: PathLike): void
+
+/** JavaScript 补丁包条目 */
+export interface BundleEntryJs {
+  /** 旧文件路径 */
+  old: PathLike
+  /** 新文件路径 */
+  new: PathLike
+  /** 包内名称 (通常为相对路径)，applyBundle 时据此查找映射 */
+  name: string
+}
+
+/** JavaScript 补丁包逐项结果 */
+export interface BundleFileJs {
+  /** 包内名称 */
+  name: string
+  /** createBundle 时为补丁大小，applyBundle 时为生成的新文件大小 (字节) */
   size: number
-  compressed: boolean
 }
 
+/** JavaScript 补丁包应用目标 */
+export interface BundleTargetJs {
+  /** 旧文件路径 */
+  old: PathLike
+  /** 输出新文件路径 */
+  new: PathLike
+}
+
+/** JavaScript diff 报告中的变更区域 */
+export interface ChangedRegionJs {
+  /** 区域在新文件中的偏移 */
+  offset: number
+  /** 区域长度 (含区域内相距不超过 16 字节的未变字节) */
+  length: number
+  /** 区域内相对旧文件改动的字节数 */
+  modifiedBytes: number
+  /** 区域内直接插入的字节数 */
+  insertedBytes: number
+  /** 区域内变更字节的香农熵 (比特/字节，0-8) */
+  entropy: number
+}
+
+/** 详细检查文件访问情况 */
+export declare function checkFileAccessDetailedSync(filePath: PathLike): FileAccessJs
+
+/** 检查文件访问权限 */
+export declare function checkFileAccessSync(filePath: PathLike): void
+
+/** 校验单个分片的头与 CRC-32，损坏时抛出异常 */
+export declare function checkPatchPartSync(part: PathLike): PatchPartJs
+
+/** 新版本运行正常：待确认槽位成为可用槽位 */
+export declare function commitUpdateSync(slots: AbSlotsJs): SlotStateJs
+
 /** JavaScript 压缩比信息结构 */
 export interface CompressionRatioJs {
   oldSize: number
@@ -15,27 +150,725 @@ export interface CompressionRatioJs {
   ratio: number
 }
 
-// 核心 API - 异步版本
-export declare function diff(oldStr: string, newStr: string, patch: string): Promise<void>
-export declare function patch(oldStr: string, newStr: string, patch: string): Promise<void>
-export declare function verifyPatch(oldStr: string, newStr: string, patch: string): Promise<boolean>
+/** JavaScript bsdiff 控制三元组 */
+export interface ControlEntryJs {
+  /** 以旧文件为基准差分生成的字节数 */
+  copyLength: number
+  /** 补丁中直接携带的新增字节数 */
+  extraLength: number
+  /** 处理完本块后旧文件位置的偏移量 (可为负) */
+  seek: number
+  /** 差分数据对应的旧文件起始位置 */
+  oldOffset: number
+  /** 本块输出在新文件中的起始位置 */
+  newOffset: number
+}
 
-// 核心 API - 同步版本
-export declare function diffSync(oldStr: string, newStr: string, patch: string): void
-export declare function patchSync(oldStr: string, newStr: string, patch: string): void
+/** 在 zstd 与经典 BSDIFF40 补丁格式之间转换 (无需源文件) */
+export declare function convertPatchSync(inPatch: PathLike, outPatch: PathLike, targetFormat: string): void
 
-/** 验证补丁文件完整性 */
-export declare function verifyPatchSync(oldStr: string, newStr: string, patch: string): boolean
+/** 为多个文件并行生成补丁并打包 (异步) */
+export declare function createBundle(entries: Array<BundleEntryJs>, bundle: PathLike, options?: DiffOptions | undefined | null): Promise<Array<BundleFileJs>>
 
-// 工具方法
-/** 获取补丁文件信息 */
-export declare function getPatchInfoSync(patch: string): PatchInfoJs
+/** 为多个文件并行生成补丁，打包为一个补丁包 */
+export declare function createBundleSync(entries: Array<BundleEntryJs>, bundle: PathLike, options?: DiffOptions | undefined | null): Array<BundleFileJs>
 
-/** 获取文件大小 */
-export declare function getFileSizeSync(filePath: string): number
+export declare function createManifest(dir: PathLike): Promise<string>
 
-/** 检查文件访问权限 */
-export declare function checkFileAccessSync(filePath: string): void
+/** 生成目录清单 JSON：每个文件的相对路径 → 大小与 SHA-256 */
+export declare function createManifestSync(dir: PathLike): string
+
+/** 只凭旧文件签名对新文件生成补丁 (异步) */
+export declare function delta(signatureFile: PathLike, newStr: PathLike, patch: PathLike, options?: DiffOptions | undefined | null, signal?: AbortSignal | undefined | null): Promise<void>
+
+/** JavaScript 相似度估算结果 */
+export interface DeltaEstimateJs {
+  /** 相似度 (0-1)：新文件中可在旧文件里找到的内容比例 */
+  score: number
+  /** 预计补丁大小下限 (字节) */
+  minPatchSize: number
+  /** 预计补丁大小上限 (字节) */
+  maxPatchSize: number
+}
+
+/** 只凭旧文件签名对新文件生成补丁 */
+export declare function deltaSync(signatureFile: PathLike, newStr: PathLike, patch: PathLike, options?: DiffOptions | undefined | null): void
+
+export declare function diff(oldStr: PathLike | undefined | null, newStr: PathLike, patch: FileArg, options?: DiffOptions | undefined | null, signal?: AbortSignal | undefined | null): Promise<DiffStatsJs | null>
+
+/** @deprecated 请改用 diff (参数与结果相同) */
+export declare function diffAsync(oldStr: PathLike | undefined | null, newStr: PathLike, patch: FileArg, options?: DiffOptions | undefined | null, signal?: AbortSignal | undefined | null): Promise<DiffStatsJs | null>
+
+/** 在单个 libuv 任务中并发生成多个补丁，避免在 JavaScript 循环中逐个调用 diff() 占满线程池 */
+export declare function diffBatch(pairs: Array<DiffPairJs>, options?: DiffOptions | undefined | null, concurrency?: number | undefined | null, signal?: AbortSignal | undefined | null): Promise<Array<DiffBatchResultJs>>
+
+/** JavaScript 批量 diff 单项结果 */
+export interface DiffBatchResultJs {
+  /** 补丁输出路径 (与传入的值相同) */
+  patch: PathLike
+  /** 是否成功 */
+  ok: boolean
+  /** 成功时的补丁大小 (字节) */
+  patchSize?: number
+  /** 失败时的错误信息 */
+  error?: string
+}
+
+/** 并发生成多个补丁，返回与输入顺序一致的逐项结果；单项失败不影响其他任务 */
+export declare function diffBatchSync(pairs: Array<DiffPairJs>, options?: DiffOptions | undefined | null, concurrency?: number | undefined | null): Array<DiffBatchResultJs>
+
+/** 一次生成正向 (a→b) 与反向 (b→a) 补丁 */
+export declare function diffBidirectionalSync(a: PathLike, b: PathLike, forwardPatch: PathLike, reversePatch: PathLike): void
+
+export declare function diffBuffer(old: Buffer, new: Buffer, options?: DiffOptions | undefined | null): Promise<Buffer>
+
+export declare function diffBuffersToFile(old: Buffer, new: Buffer, patch: PathLike, options?: DiffOptions | undefined | null): Promise<DiffStatsJs | null>
+
+/** 对内存中的新旧文件数据生成补丁，流式写入补丁文件；`stats: true` 时返回补丁统计 */
+export declare function diffBuffersToFileSync(old: Buffer, new: Buffer, patch: PathLike, options?: DiffOptions | undefined | null): DiffStatsJs | null
+
+/** 在内存中生成补丁，返回补丁数据 */
+export declare function diffBufferSync(old: Buffer, new: Buffer, options?: DiffOptions | undefined | null): Buffer
+
+export declare function diffDir(oldDir: PathLike, newDir: PathLike, bundle: PathLike, options?: DiffOptions | undefined | null): Promise<DirDiffSummaryJs>
+
+/** 递归比较两个目录，生成目录差分包 */
+export declare function diffDirSync(oldDir: PathLike, newDir: PathLike, bundle: PathLike, options?: DiffOptions | undefined | null): DirDiffSummaryJs
+
+/** JavaScript diff 选项 */
+export interface DiffOptions {
+  /** diff 模式: "full" (默认) 或 "append" */
+  mode?: string
+  /** 安全模式：zstd 使用保守配置 (默认读取环境变量 BSDIFF_ZSTD_SAFE=1) */
+  safeMode?: boolean
+  /**
+   * Zstd 压缩级别 (1-22，默认 3)：越高补丁越小、diff 越慢；"auto" 在原始 diff 输出的样本上实测几个级别，
+   * 选出当前机器上每秒压缩率最高的级别 (由 stats 的 compressionLevel 报告)
+   */
+  compressionLevel?: number | 'auto'
+  /** 先写入快速临时目录再移动到目标路径 (默认 true) */
+  useFastTempDir?: boolean
+  /** 快速临时目录 (默认读取环境变量 BSDIFF_TEMP_DIR，未设置时自动选择)；剩余空间不足时改为写在输出旁边 */
+  tempDir?: PathLike
+  /** 补丁格式: "zstd" (默认)、"bsdiff40" (与原版 bsdiff 工具兼容) 或 "vcdiff" (RFC 3284，与 xdelta3 兼容) */
+  format?: string
+  /** 补丁负载压缩算法: "zstd" (默认)、"xz"、"brotli"、"gzip" 或 "none"，记录在补丁头中，patch 时自动识别；compressionLevel 只作用于 zstd */
+  compression?: string
+  /** 分窗口 diff 的新文件窗口大小 (字节)：限制超大文件 diff 的内存占用，补丁略大 */
+  windowSize?: number
+  /** 分窗口 diff 的并行线程数 (默认 CPU 核数) */
+  threads?: number
+  /** 写入补丁头的键值元数据 (如 `{ appVersion: '1.2.3', channel: 'beta' }`)，getPatchInfo 可读出；仅 zstd 格式 */
+  metadata?: Record<string, string>
+  /**
+   * 可执行文件感知 diff (默认 false)：新旧文件是同一架构的 PE/ELF/Mach-O 时先规范化代码段中的相对跳转目标，
+   * 与不变换的补丁比较后保留较小的一个 (diff 耗时约为两倍)；仅完整 diff 与 zstd 格式
+   */
+  exeTransform?: boolean
+  /**
+   * 压缩包感知 diff (默认 false)：新文件是 ZIP 时先解压 deflate 条目再 diff，应用时按记录的 zlib 级别重新压缩，
+   * 还原出逐字节相同的文件；仅完整 diff 与 zstd 格式
+   */
+  archiveTransform?: boolean
+  /**
+   * 内容去重 (默认 false)：diffDir 的新增文件按 FastCDC 分块，跨文件重复或旧目录中已有的块只存一次；
+   * createBundle 中内容相同的补丁只存一份。其他 diff 忽略
+   */
+  dedup?: boolean
+  /** diffDir 记录新目录中的符号链接与文件权限位 (默认 false)，patchDir 重建链接并恢复可执行位等权限 */
+  preserveMetadata?: boolean
+  /** diffDir 额外记录文件修改时间 (默认 false，隐含 preserveMetadata) */
+  preserveMtimes?: boolean
+  /**
+   * 分卷大小 (字节，仅 diff/diffSync)：补丁切成 `<patch>.001`、`<patch>.002`…… 不超过该大小的分卷，
+   * patch 路径写为分卷索引；patch/patchSync 接受该索引或分卷所在目录
+   */
+  volumeSize?: number
+  /** diff/diffSync 完成后返回补丁统计 (控制块数、复制与新增字节数等；默认 false，VCDIFF 格式与加密补丁不支持) */
+  stats?: boolean
+  /** 用 AES-256-GCM 加密整个补丁 (nonce 与认证标签保存在信封中)；patch 时需提供相同的 decryptionKey。目录包不支持 */
+  encrypt?: EncryptOptionsJs
+  /**
+   * 共享 zstd 字典 (如 trainDictionary 的结果)，用于压缩补丁负载并把字典 ID 写入补丁头；
+   * patch 时需提供相同的字典。仅 zstd 格式与压缩
+   */
+  dictionary?: Buffer
+  /** 可复现输出 (默认 false)：相同输入与选项在任意次运行、任意平台上生成逐字节相同的补丁 (固定 zstd 参数，忽略 safeMode) */
+  deterministic?: boolean
+  /** 补丁头记录新旧文件摘要所用的算法: "sha256" (默认) 或 "blake3" (多线程计算，大文件校验更快)；patch 时自动识别 */
+  hashAlgorithm?: 'sha256' | 'blake3'
+  /**
+   * diff 算法: "bsdiff" (默认) 或 "blockdelta" (rsync 风格的块级增量：耗时与内存近似线性，补丁较大，
+   * 适合数十 GB 的输入)；补丁格式不变，patch 无需区分
+   */
+  algorithm?: 'bsdiff' | 'blockdelta'
+  /** 时长上限 (毫秒，从调用时起算)：后缀排序与 diff 循环超过该时长即中止，清理临时输出并以 ERR_TIMEOUT 失败 */
+  timeoutMs?: number
+  /**
+   * 补丁文件已存在时: "replace" (默认，直接覆盖)、"error" (开始前以 ERR_OUTPUT_EXISTS 拒绝) 或
+   * "backup" (写入前重命名为 `<补丁>.bak`)；仅 diff/diffSync
+   */
+  overwrite?: 'error' | 'replace' | 'backup'
+  /** 完成输出时文件被暂时锁住 (杀毒软件扫描等) 的重试策略；Windows 默认重试，其他平台默认不重试 */
+  retry?: RetryOptionsJs
+  /**
+   * 补丁缓存目录 (仅 diff/diffSync)：以新旧文件摘要与影响补丁内容的选项为键保存补丁，
+   * 再次请求相同的文件对时直接复制缓存的补丁。加密补丁不缓存
+   */
+  cacheDir?: PathLike
+  /**
+   * 完整文件回退 (仅 zstd 格式)：补丁超过新文件大小的该比例 (true 为 0.9) 时改为直接携带新文件，
+   * stats 的 fullFile 表示是否回退；应用补丁时无需区分
+   */
+  fallbackToFull?: boolean | number
+  /** 进度回调，按已处理的新文件字节数上报 (节流到约 1% 一次) */
+  onProgress?: (progress: DiffProgressJs) => void
+  /** 分阶段进度回调：read (映射输入、计算摘要) → apply (按新文件字节数) → write (移动到补丁路径) */
+  onProgressEvent?: (event: ProgressEventJs) => void
+}
+
+/** JavaScript 批量 diff 任务 */
+export interface DiffPairJs {
+  /** 旧文件路径 */
+  old: PathLike
+  /** 新文件路径 */
+  new: PathLike
+  /** 补丁输出路径 */
+  patch: PathLike
+}
+
+/** JavaScript diff 进度 */
+export interface DiffProgressJs {
+  /** 已处理的新文件字节数 */
+  processed: number
+  /** 新文件总字节数 */
+  total: number
+  /** 完成百分比 (0-100) */
+  percent: number
+}
+
+/** 只对新旧文件中的指定区间生成补丁 (异步) */
+export declare function diffRange(oldStr: PathLike, oldOffset: number, oldLength: number, newStr: PathLike, newOffset: number, newLength: number, patch: PathLike, options?: DiffOptions | undefined | null, signal?: AbortSignal | undefined | null): Promise<DiffStatsJs | null>
+
+/** 只对新旧文件中的指定区间 (偏移 + 长度) 生成补丁，例如磁盘镜像中的某个分区 */
+export declare function diffRangeSync(oldStr: PathLike, oldOffset: number, oldLength: number, newStr: PathLike, newOffset: number, newLength: number, patch: PathLike, options?: DiffOptions | undefined | null): DiffStatsJs | null
+
+export declare function diffReport(oldStr: PathLike, newStr: PathLike, options?: DiffOptions | undefined | null): Promise<DiffReportJs>
+
+/** JavaScript diff 报告 */
+export interface DiffReportJs {
+  oldSize: number
+  newSize: number
+  /** bsdiff 控制块数 */
+  controlBlocks: number
+  /** 与旧文件对应位置相同的新文件字节数 */
+  unchangedBytes: number
+  /** 由旧文件对应位置改动而来的新文件字节数 */
+  modifiedBytes: number
+  /** 旧文件中没有对应内容、直接插入的新文件字节数 */
+  insertedBytes: number
+  /** 未被新文件引用的旧文件字节数 */
+  deletedBytes: number
+  /** 变动率 (0-1)：修改与插入的字节占新文件的比例 */
+  churn: number
+  /** 所有变更字节的香农熵 (比特/字节，0-8) */
+  changedEntropy: number
+  /** 变更区域总数 */
+  regionCount: number
+  /** 变更区域 (按偏移排序)，最多 256 个最大的区域 */
+  regions: Array<ChangedRegionJs>
+}
+
+/** 在内存中运行 diff 但不写出补丁，返回变更区域、修改/插入/删除字节数与变更内容的熵 */
+export declare function diffReportSync(oldStr: PathLike, newStr: PathLike, options?: DiffOptions | undefined | null): DiffReportJs
+
+/** JavaScript diff 统计 */
+export interface DiffStatsJs {
+  /** 控制块 (控制三元组) 数 */
+  controlBlocks: number
+  /** 以旧文件为基准差分生成的字节数 */
+  copiedBytes: number
+  /** 补丁中直接携带的新增字节数 */
+  literalBytes: number
+  /** 最长的单段新增数据 */
+  largestLiteralRun: number
+  /** 新增字节占新文件的比例 (0-1)，接近 1 时可改为完整下载 */
+  literalRatio: number
+  /** compressionLevel 为 "auto" 时选定的压缩级别 */
+  compressionLevel?: number
+  /** 补丁直接携带完整的新文件 (fallbackToFull 回退) */
+  fullFile: boolean
+}
+
+/** 从流生成补丁并写入流 (输入读入内存后 diff，补丁按块写出) */
+export declare function diffStream(old: StreamSource, new: StreamSource, output: StreamSink, options?: DiffOptions | undefined | null, signal?: AbortSignal | undefined | null): Promise<void>
+
+export declare function diffSync(oldStr: PathLike | undefined | null, newStr: PathLike, patch: FileArg, options?: DiffOptions | undefined | null): DiffStatsJs | null
+
+/** 生成补丁并写入命名共享内存对象，返回写入的字节数 */
+export declare function diffToShmSync(oldStr: PathLike, newStr: PathLike, shmName: string, level?: number | undefined | null): number
+
+/** JavaScript 目录差分统计 */
+export interface DirDiffSummaryJs {
+  added: number
+  deleted: number
+  patched: number
+  renamed: number
+  unchanged: number
+}
+
+/** JavaScript 补丁加密选项 */
+export interface EncryptOptionsJs {
+  /** 32 字节 AES-256 密钥 */
+  key: Buffer
+}
+
+export declare function estimateDelta(oldStr: PathLike, newStr: PathLike): Promise<DeltaEstimateJs>
+
+/** 不运行 diff，采样滚动哈希快速估算新旧文件的相似度 (0-1) 与补丁大小范围 */
+export declare function estimateDeltaSync(oldStr: PathLike, newStr: PathLike): DeltaEstimateJs
+
+/** 从补丁集中提取 from → to 的补丁 */
+export declare function extractPatchSync(set: PathLike, from: string, to: string, out: PathLike): void
+
+/** JavaScript 详细文件访问检查结构 */
+export interface FileAccessJs {
+  exists: boolean
+  isFile: boolean
+  readable: boolean
+  isSymlink: boolean
+  permissionDenied: boolean
+}
 
 /** 获取压缩比信息 */
-export declare function getCompressionRatioSync(oldStr: string, newStr: string, patch: string): CompressionRatioJs
+export declare function getCompressionRatioSync(oldStr: PathLike, newStr: PathLike, patch: PathLike): CompressionRatioJs
+
+/** 统计补丁的控制块数、复制与新增字节数 (不需要旧文件；VCDIFF 补丁不支持，字典压缩的补丁需提供字典) */
+export declare function getDiffStatsSync(patch: PathLike, dictionary?: Buffer | undefined | null): DiffStatsJs
+
+/** 获取文件大小 */
+export declare function getFileSizeSync(filePath: PathLike): number
+
+/** 异步获取补丁文件信息 (流式压缩的补丁需要解压负载才能得到 uncompressedSize) */
+export declare function getPatchInfo(patch: PathLike): Promise<PatchInfoJs>
+
+/** 异步获取内存中补丁的信息；负载未记录解压大小时需要解压计数，较大的补丁宜用此版本 */
+export declare function getPatchInfoFromBuffer(patch: Buffer): Promise<PatchInfoJs>
+
+/** 获取内存中补丁的信息 (不访问磁盘) */
+export declare function getPatchInfoFromBufferSync(patch: Buffer): PatchInfoJs
+
+/** 获取补丁文件信息 */
+export declare function getPatchInfoSync(patch: PathLike): PatchInfoJs
+
+/** JavaScript inspectPatch 选项 */
+export interface InspectOptions {
+  /** 跳过前若干个控制块 (默认 0)，与 limit 配合分页 */
+  offset?: number
+  /** 最多返回的控制块数 (默认 10000) */
+  limit?: number
+  /** 字典压缩的补丁需提供相同的字典 */
+  dictionary?: Buffer
+}
+
+export declare function inspectPatch(patch: PathLike, options?: InspectOptions | undefined | null): Promise<Array<ControlEntryJs>>
+
+/** 列出补丁的 bsdiff 控制三元组 (不需要旧文件)，用于排查补丁为何偏大 */
+export declare function inspectPatchSync(patch: PathLike, options?: InspectOptions | undefined | null): Array<ControlEntryJs>
+
+/** 异步迭代器 `next()` 的结果 */
+export interface IteratorResultJs {
+  done?: boolean
+  value?: Buffer | string
+}
+
+/** 将分片 (顺序任意) 合并为完整补丁 */
+export declare function joinPatchSync(parts: Array<PathLike>, out: PathLike): void
+
+/** JavaScript 清单不一致项 */
+export interface ManifestMismatchJs {
+  /** 相对路径 */
+  path: string
+  /** "missing" 或 "modified" */
+  reason: string
+  /** 清单记录的大小 */
+  expectedSize: number
+  /** 清单记录的 SHA-256 (十六进制) */
+  expectedSha256: string
+  /** 实际大小 (文件缺失时为空) */
+  actualSize?: number
+  /** 实际 SHA-256 (文件缺失时为空) */
+  actualSha256?: string
+}
+
+export declare function patch(oldStr: PathLike | undefined | null, newStr: FileArg, patch: FileArg, options?: PatchOptions | undefined | null, signal?: AbortSignal | undefined | null): Promise<number>
+
+/** @deprecated 请改用 patch (参数与结果相同) */
+export declare function patchAsync(oldStr: PathLike | undefined | null, newStr: FileArg, patchFile: FileArg, options?: PatchOptions | undefined | null, signal?: AbortSignal | undefined | null): Promise<number>
+
+export declare function patchBuffer(old: Buffer, patch: Buffer, options?: PatchOptions | undefined | null): Promise<Buffer>
+
+/** 在内存中应用补丁，返回新文件数据 */
+export declare function patchBufferSync(old: Buffer, patch: Buffer, options?: PatchOptions | undefined | null): Buffer
+
+export declare function patchBufferToFile(old: Buffer, patch: PathLike, newStr: PathLike, options?: PatchOptions | undefined | null): Promise<number>
+
+/** 对内存中的旧文件数据应用补丁文件，流式写入新文件，返回新文件大小 */
+export declare function patchBufferToFileSync(old: Buffer, patch: PathLike, newStr: PathLike, options?: PatchOptions | undefined | null): number
+
+export declare function patchChain(oldStr: PathLike, patches: Array<PathLike>, newStr: PathLike, options?: PatchOptions | undefined | null, signal?: AbortSignal | undefined | null): Promise<number>
+
+/** 依次应用一串增量补丁，中间版本只保存在内存中；返回新文件大小 */
+export declare function patchChainSync(oldStr: PathLike, patches: Array<PathLike>, newStr: PathLike, options?: PatchOptions | undefined | null): number
+
+export declare function patchDir(oldDir: PathLike, newDir: PathLike, bundle: PathLike, options?: PatchOptions | undefined | null): Promise<DirDiffSummaryJs>
+
+/** 将目录差分包应用到旧目录，生成新目录 (`newDir` 必须不存在) */
+export declare function patchDirSync(oldDir: PathLike, newDir: PathLike, bundle: PathLike, options?: PatchOptions | undefined | null): DirDiffSummaryJs
+
+/** 基于同一旧文件映射并发应用多个补丁 */
+export declare function patchFanoutSync(oldStr: PathLike, jobs: Array<PatchJobJs>, concurrency?: number | undefined | null): void
+
+export declare function patchFileWithPatchBuffer(oldStr: PathLike, patch: Buffer, newStr: PathLike, options?: PatchOptions | undefined | null): Promise<number>
+
+/** 对磁盘上的旧文件应用内存中的补丁数据，流式写入新文件，返回新文件大小 */
+export declare function patchFileWithPatchBufferSync(oldStr: PathLike, patch: Buffer, newStr: PathLike, options?: PatchOptions | undefined | null): number
+
+/** 直接从分片应用补丁，不生成完整补丁文件 */
+export declare function patchFromChunksSync(oldStr: PathLike, newStr: PathLike, parts: Array<PathLike>, options?: PatchOptions | undefined | null): void
+
+/** 从 http:// 或 https:// URL 流式下载补丁并应用，不保存补丁文件；旧文件与补丁头记录的摘要不符时在下载负载前中止 */
+export declare function patchFromUrl(oldStr: PathLike, url: string, newStr: PathLike, urlOptions?: UrlOptionsJs | undefined | null, options?: PatchOptions | undefined | null, signal?: AbortSignal | undefined | null): Promise<number>
+
+/** 从 http:// 或 https:// URL 流式下载补丁并应用到旧文件，返回新文件大小 */
+export declare function patchFromUrlSync(oldStr: PathLike, url: string, newStr: PathLike, urlOptions?: UrlOptionsJs | undefined | null, options?: PatchOptions | undefined | null): number
+
+/** JavaScript 补丁信息结构 */
+export interface PatchInfoJs {
+  size: number
+  compressed: boolean
+  /** 容器类型: "bsrz"、"zstd" (旧版无头补丁)、"bsdiff40"、"vcdiff" 或 "encrypted" */
+  container: string
+  formatVersion?: number
+  /** 负载压缩算法: "zstd"、"xz"、"brotli"、"gzip"、"bzip2" 或 "none" */
+  compression: string
+  flags: number
+  bsdiffVersion?: string
+  sourceSize?: number
+  targetSize?: number
+  /** 负载解压后的大小；BSDIFF40、加密与字典压缩的补丁省略 */
+  uncompressedSize?: number
+  /** 补丁头记录的旧文件摘要 (十六进制，算法见 hashAlgorithm) */
+  sourceSha256?: string
+  /** 补丁头记录的新文件摘要 (十六进制，算法见 hashAlgorithm) */
+  targetSha256?: string
+  /** 文件摘要算法: "sha256" 或 "blake3"，仅 bsrz 补丁提供 */
+  hashAlgorithm?: string
+  /** 补丁头中的键值元数据，未设置时省略 */
+  metadata?: Record<string, string>
+  /** 压缩负载所用 zstd 字典的 ID，未使用字典时省略 */
+  dictionaryId?: number
+  /** 补丁直接携带完整的新文件，不引用旧文件内容 */
+  fullFile: boolean
+}
+
+export declare function patchInPlace(file: PathLike, patch: PathLike, options?: PatchOptions | undefined | null, signal?: AbortSignal | undefined | null): Promise<number>
+
+/** 原地打补丁：写入同目录临时文件、fsync 后原子替换 `file`，失败时恢复原文件；返回新文件大小 */
+export declare function patchInPlaceSync(file: PathLike, patch: PathLike, options?: PatchOptions | undefined | null): number
+
+/** JavaScript fan-out 补丁任务 */
+export interface PatchJobJs {
+  /** 补丁文件路径 */
+  patch: PathLike
+  /** 输出文件路径 */
+  out: PathLike
+}
+
+/** JavaScript patch 选项 */
+export interface PatchOptions {
+  /** 安全模式：zstd 使用保守配置 (默认读取环境变量 BSDIFF_ZSTD_SAFE=1) */
+  safeMode?: boolean
+  /** 严格模式：拒绝未携带源文件校验和的补丁 (默认 false，旧版补丁不校验直接应用) */
+  strict?: boolean
+  /** 按控制块上报的进度回调，补丁头未记录控制块总数时不调用 */
+  onProgress?: (progress: PatchProgressJs) => void
+  /** 分阶段进度回调：decode (签名、解密、补丁头) → read (校验旧文件) → apply (按补丁字节数) → write (移动到输出路径) */
+  onProgressEvent?: (event: ProgressEventJs) => void
+  /** 32 字节 Ed25519 公钥，与 signature 一起提供时先校验补丁签名再应用 */
+  publicKey?: Buffer
+  /** signPatch 生成的 64 字节分离式签名 */
+  signature?: Buffer
+  /** 32 字节 AES-256 密钥，用于应用 diff 时以 encrypt 加密的补丁；对未加密的补丁没有影响 */
+  decryptionKey?: Buffer
+  /** 补丁负载使用字典压缩时所需的 zstd 字典 (与 diff 时相同)；对未使用字典的补丁没有影响 */
+  dictionary?: Buffer
+  /** 按补丁头声明的目标大小预分配输出文件并通过内存映射写入 (默认 false) */
+  mmapOutput?: boolean
+  /** 以 seek 跳过输出中全零的 4KB 块，生成稀疏文件 (默认 false，优先于 mmapOutput) */
+  sparse?: boolean
+  /** 试运行 (默认 false，仅 patch/patchSync)：完整解码并校验补丁输出，返回将要写出的大小，不写入任何文件 */
+  dryRun?: boolean
+  /** 快速临时目录 (默认读取环境变量 BSDIFF_TEMP_DIR，未设置时自动选择)；剩余空间不足时改为写在输出旁边 */
+  tempDir?: PathLike
+  /** 允许的最大输出字节数：补丁头声明的目标大小超过时在写出前拒绝，未声明大小的补丁在输出达到上限时中止 */
+  maxOutputSize?: number
+  /**
+   * 应用补丁时缓冲数据的内存上限 (字节)：zstd 解码窗口、整体读入的加密/BSDIFF40/VCDIFF 补丁、
+   * 可执行文件变换与输出到内存的结果超过时以 ERR_LIMIT_EXCEEDED 失败
+   */
+  maxMemory?: number
+  /** 磁盘写入速率上限 (字节/秒)：多 GB 的更新不会占满磁盘带宽拖慢前台应用；设置后不使用 mmapOutput 与快速临时目录 */
+  maxWriteBytesPerSec?: number
+  /**
+   * 输出文件已存在时: "replace" (默认，直接覆盖)、"error" (开始前以 ERR_OUTPUT_EXISTS 拒绝) 或
+   * "backup" (写入前重命名为 `<输出>.bak`)；仅 patch/patchSync
+   */
+  overwrite?: 'error' | 'replace' | 'backup'
+  /** 完成输出时文件被暂时锁住 (杀毒软件扫描等) 的重试策略；Windows 默认重试，其他平台默认不重试 */
+  retry?: RetryOptionsJs
+  /** 写出新文件后 fsync 文件及其所在目录再返回 (默认 false)，返回后即可安全地把更新标记为已提交 */
+  durable?: boolean
+  /**
+   * 内容未变与仅移动的文件的生成方式 (仅 patchDir/applyBundleToDir)："reflink" (默认，写时复制克隆，不支持时复制)、
+   * "hardlink" (硬链接到旧文件) 或 "copy"
+   */
+  linkMode?: 'copy' | 'reflink' | 'hardlink'
+  /**
+   * 沙箱根目录 (仅 patchDir/applyBundle/applyBundleToDir)：所有读写路径都必须位于其下，相对路径基于它解析，
+   * 经由 `..`、绝对路径或符号链接越界时在读取补丁前失败
+   */
+  baseDir?: PathLike
+  /** 审计日志文件：以追加模式每步写一行 JSON 事件 (补丁头校验、签名与摘要校验、写出字节数、rename 提交等) */
+  logFile?: PathLike
+  /** 审计事件回调，参数为与 logFile 相同的一行 JSON；可与 logFile 同时使用 */
+  onAuditEvent?: (event: string) => void
+}
+
+/** JavaScript 分片信息 */
+export interface PatchPartJs {
+  /** 分片序号 (从 0 开始) */
+  index: number
+  /** 分片总数 */
+  count: number
+  /** 完整补丁大小 */
+  patchSize: number
+  /** 完整补丁的 SHA-256 (十六进制) */
+  patchSha256: string
+  /** 分片数据在完整补丁中的偏移 */
+  offset: number
+  /** 分片数据长度 */
+  length: number
+  /** 分片数据的 CRC-32 */
+  crc32: number
+}
+
+/** JavaScript patch 进度 */
+export interface PatchProgressJs {
+  /** 已应用的控制块数 */
+  blocksApplied: number
+  /** 控制块总数 */
+  totalBlocks: number
+  /** 完成百分比 (0-100) */
+  percent: number
+}
+
+/** JavaScript 补丁集条目 */
+export interface PatchSetEntryJs {
+  /** 源版本文件 SHA-256 (十六进制) */
+  from: string
+  /** 目标版本文件 SHA-256 (十六进制) */
+  to: string
+  /** 补丁文件路径 */
+  patch: PathLike
+}
+
+/** 从流读取补丁，应用后将新文件数据按块写入流 */
+export declare function patchStream(old: StreamSource, patch: StreamSource, output: StreamSink, options?: PatchOptions | undefined | null, signal?: AbortSignal | undefined | null): Promise<void>
+
+export declare function patchSync(oldStr: PathLike | undefined | null, newStr: FileArg, patch: FileArg, options?: PatchOptions | undefined | null): number
+
+export declare function patchToArrayBuffer(oldStr: PathLike, patch: PathLike, options?: PatchOptions | undefined | null): Promise<ArrayBuffer>
+
+/**
+ * 同 patchToBufferSync，但返回可通过 postMessage 转移给 Worker 的 ArrayBuffer；
+ * 补丁头声明了目标大小时新文件直接写入 ArrayBuffer 的存储，不经过中间复制
+ */
+export declare function patchToArrayBufferSync(oldStr: PathLike, patch: PathLike, options?: PatchOptions | undefined | null): ArrayBuffer
+
+export declare function patchToBuffer(oldStr: PathLike, patch: PathLike, options?: PatchOptions | undefined | null): Promise<Buffer>
+
+/** 对磁盘上的旧文件应用补丁文件，直接返回新文件数据而不写入磁盘 */
+export declare function patchToBufferSync(oldStr: PathLike, patch: PathLike, options?: PatchOptions | undefined | null): Buffer
+
+/** 将补丁应用到固定大小的区域，目标大于 `regionSize` 时在写入前报错；返回写入的字节数 */
+export declare function patchToFixedRegionSync(oldStr: PathLike, patch: PathLike, out: PathLike, regionSize: number, zeroPad?: boolean | undefined | null): number
+
+/** 比较新旧清单 JSON，规划更新操作并估算下载量；只使用清单，不读取任何文件 */
+export declare function planUpdateSync(oldManifest: string, newManifest: string): UpdatePlanJs
+
+export declare function preflight(oldStr: PathLike, newStr: PathLike, patch: PathLike, options?: DiffOptions | undefined | null): Promise<PreflightJs>
+
+/** JavaScript diff 预检结果 */
+export interface PreflightJs {
+  /** 补丁先写入的快速临时目录；未启用或空间不足时省略 (直接写在补丁路径旁边) */
+  tempDir?: string
+  /** 临时目录的剩余空间 (字节)；不使用临时目录或无法获取时省略 */
+  tempDirFree?: number
+  /** 补丁路径所在的目录 */
+  destinationDir: string
+  /** 目标目录的剩余空间 (字节)；无法获取时省略 */
+  destinationFree?: number
+  /** 预计补丁大小 (字节) */
+  estimatedPatchSize: number
+  /** 预计峰值堆内存 (字节，不含内存映射的输入) */
+  estimatedPeakMemory: number
+  /** 新旧文件都能内存映射 */
+  mmap: boolean
+  /** 目标目录的剩余空间足以容纳预计大小的补丁；为 false 时 diff 以 ERR_INSUFFICIENT_SPACE 失败 */
+  sufficientSpace: boolean
+}
+
+/** diff 预检：补丁将写入的临时目录、临时目录与目标目录的剩余空间、预计峰值内存以及能否内存映射输入 */
+export declare function preflightSync(oldStr: PathLike, newStr: PathLike, patch: PathLike, options?: DiffOptions | undefined | null): PreflightJs
+
+/** 清空非可用槽位并返回其目录，作为 applyBundleToDir 等的目标；已有待确认的更新时拒绝 */
+export declare function prepareUpdateSync(slots: AbSlotsJs): string
+
+/** JavaScript 分阶段进度事件 */
+export interface ProgressEventJs {
+  phase: 'read' | 'decode' | 'apply' | 'write'
+  /** 当前阶段已处理的字节数 */
+  bytesDone: number
+  /** 当前阶段的总字节数 */
+  bytesTotal: number
+  /** 按当前阶段速率估算的剩余毫秒数，尚无法估算时省略；阶段结束时为 0 */
+  etaMs?: number
+}
+
+/** 由 Ed25519 私钥计算 32 字节公钥 (patch 选项中的 publicKey) */
+export declare function publicKeyFromPrivateSync(privateKey: Buffer): Buffer
+
+/** 以新的压缩级别重新压缩已有补丁 (补丁头与原始 bsdiff 流保持不变) */
+export declare function recompressPatchSync(inPatch: PathLike, outPatch: PathLike, newLevel: number): void
+
+/** JavaScript 重试选项 */
+export interface RetryOptionsJs {
+  /** 总尝试次数 (含首次，默认 5)，1 表示不重试 */
+  attempts?: number
+  /** 首次重试前的等待时间 (毫秒，默认 100)，之后每次翻倍，最多 5 秒 */
+  delayMs?: number
+}
+
+/** 放弃待确认的更新；没有待确认的更新时切换回另一个槽位 */
+export declare function rollbackUpdateSync(slots: AbSlotsJs): SlotStateJs
+
+/** JavaScript 补丁自检结果结构 */
+export interface SelfCheckJs {
+  ok: boolean
+  reason?: string
+}
+
+/** 设置日志回调 `(level, message) => void`，传入 null 取消 */
+export declare function setLogCallback(callback?: ((level: string, message: string) => void) | undefined | null): void
+
+/** 计算旧文件的块签名 (异步) */
+export declare function signature(oldStr: PathLike, signatureFile: PathLike, options?: DiffOptions | undefined | null): Promise<void>
+
+/** 计算旧文件的块签名 (rsync 风格) 写入 `signatureFile`，供服务器在没有旧文件的情况下生成增量 */
+export declare function signatureSync(oldStr: PathLike, signatureFile: PathLike, options?: DiffOptions | undefined | null): void
+
+export declare function signPatch(patch: string | Buffer, privateKey: Buffer): Promise<Buffer>
+
+/** 用 Ed25519 私钥 (32 字节种子或 64 字节种子 + 公钥) 对补丁文件或补丁 Buffer 签名，返回 64 字节分离式签名 */
+export declare function signPatchSync(patch: string | Buffer, privateKey: Buffer): Buffer
+
+/** JavaScript A/B 槽位状态 */
+export interface SlotStateJs {
+  /** 最近确认可用的槽位 */
+  active: 'a' | 'b'
+  /** 等待确认的槽位 */
+  pending?: 'a' | 'b'
+  /** 待确认槽位剩余的试运行次数 */
+  triesLeft: number
+  /** 当前应运行的槽位 (试运行中为 pending，否则为 active) */
+  current: 'a' | 'b'
+  /** 当前应运行的槽位目录 */
+  currentDir: string
+}
+
+/** 读取 A/B 槽位状态；状态文件不存在时槽位 A 为可用槽位 */
+export declare function slotStateSync(slots: AbSlotsJs): SlotStateJs
+
+/** 按 `chunkSize` 字节将补丁拆分为 `<patch>.000`、`<patch>.001`…… 分片，每片带 CRC-32；返回分片路径 */
+export declare function splitPatchSync(patch: PathLike, chunkSize: number): Array<PathLike>
+
+/** 更新已写入非可用槽位：标记为待确认，最多试运行 `maxTries` 次 (默认 3) */
+export declare function stageUpdateSync(slots: AbSlotsJs, maxTries?: number | undefined | null): SlotStateJs
+
+/** 以一组相似资源的补丁为样本训练共享 zstd 字典，maxSize 为字典大小上限 (默认 110 KB) */
+export declare function trainDictionary(patches: Array<PathLike>, maxSize?: number | undefined | null): Promise<Buffer>
+
+/** 以一组相似资源的补丁为样本训练共享 zstd 字典，返回字典数据 */
+export declare function trainDictionarySync(patches: Array<PathLike>, maxSize?: number | undefined | null): Buffer
+
+/** JavaScript 更新操作 */
+export interface UpdateOperationJs {
+  /** "patch"、"add"、"delete" 或 "rename" */
+  op: 'patch' | 'add' | 'delete' | 'rename'
+  /** 相对路径 (rename 为目标路径，delete 为被删除的路径) */
+  path: string
+  /** rename 的源路径 */
+  from?: string
+  /** patch 的旧文件大小 */
+  oldSize?: number
+  /** 更新后的文件大小 (delete 为 0) */
+  size: number
+  /** 预计下载字节数 (rename 与 delete 为 0) */
+  estimatedSize: number
+}
+
+/** JavaScript 更新计划 */
+export interface UpdatePlanJs {
+  /** 按新路径排列的 patch/add/rename，随后是 delete */
+  operations: Array<UpdateOperationJs>
+  /** 内容未变的文件数 */
+  unchanged: number
+  /** 预计下载总字节数 */
+  downloadSize: number
+}
+
+/** JavaScript patchFromUrl 请求选项 */
+export interface UrlOptionsJs {
+  /** 附加请求头 (如 Authorization)，重定向到其他源后不再发送 */
+  headers?: Record<string, string>
+  /** 连接与等待响应头的超时 (毫秒)，默认不超时 */
+  timeout?: number
+  /** 补丁大小上限 (字节)，超过时中止下载；签名补丁需整体读入内存，未指定时上限为 512 MiB */
+  maxPatchSize?: number
+}
+
+/** 不依赖旧文件自检补丁是否完整 (补丁头、Zstd 负载、bsdiff 控制流与目标大小) */
+export declare function validatePatchSelfSync(patch: PathLike): SelfCheckJs
+
+export declare function verifyManifest(dir: PathLike, manifest: string): Promise<Array<ManifestMismatchJs>>
+
+/** 按清单 JSON 校验目录，返回缺失或被修改的文件；清单之外的文件不影响结果 */
+export declare function verifyManifestSync(dir: PathLike, manifest: string): Array<ManifestMismatchJs>
+
+/**
+ * 应用补丁并与 newStr 比较；options 同 patch (strict、签名、解密密钥)。
+ * 旧版本的 signal 位于第 4 个参数，此处传入 AbortSignal 时仍按 signal 处理
+ */
+export declare function verifyPatch(oldStr: PathLike, newStr: PathLike, patch: PathLike, options?: PatchOptions | undefined | null, signal?: AbortSignal | undefined | null): Promise<boolean>
+
+export declare function verifyPatchAgainstHash(oldStr: PathLike, patch: PathLike, expectedSha256: string, options?: PatchOptions | undefined | null, signal?: AbortSignal | undefined | null): Promise<boolean>
+
+/** 只用旧文件与补丁校验：在内存中流式应用补丁，比较新文件 SHA-256 (十六进制) 与期望值 */
+export declare function verifyPatchAgainstHashSync(oldStr: PathLike, patch: PathLike, expectedSha256: string, options?: PatchOptions | undefined | null): boolean
+
+/** @deprecated 请改用 verifyPatch (参数与结果相同) */
+export declare function verifyPatchAsync(oldStr: PathLike, newStr: PathLike, patch: PathLike, options?: PatchOptions | undefined | null, signal?: AbortSignal | undefined | null): Promise<boolean>
+
+/** 验证补丁文件完整性 */
+export declare function verifyPatchSync(oldStr: PathLike, newStr: PathLike, patch: PathLike): boolean
@@ -0,0 +1,39 @@
+/* auto-generated by NAPI-RS */
+/* eslint-disable */
+
+// napi build 把本文件原样放在生成的声明之前 (package.json 的 napi.dtsHeaderFile)：
+// 声明绑定中以类型别名或 ts_arg_type 引用的类型，以及无法从 Rust 签名生成的重载
+
+/** 文件路径：字符串，或以原始字节给出的 Buffer (用于非 UTF-8 文件名) */
+export type PathLike = string | Buffer
+
+/** 补丁/输出参数：路径 (字符串或 Buffer)、文件描述符或 fs.promises.FileHandle (仅 Unix 支持描述符) */
+export type FileArg = PathLike | number | import('node:fs/promises').FileHandle
+
+/** 流式输入：文件路径、文件描述符、Buffer，或产出 Buffer 的 Readable 流 (任意异步可迭代对象) */
+export type StreamSource = string | number | Buffer | NodeJS.ReadableStream | AsyncIterable<Buffer>
+
+/** 流式输出：文件描述符或 Writable 流 (成功后调用 end()；文件描述符不会被关闭) */
+export type StreamSink = number | NodeJS.WritableStream
+
+/** 要求返回统计的 diff 选项：diff/diffSync/diffRange 的结果不为 null */
+export type DiffOptionsWithStats = DiffOptions & { stats: true }
+
+export declare function diff(oldStr: PathLike | undefined | null, newStr: PathLike, patch: FileArg, options: DiffOptionsWithStats, signal?: AbortSignal | undefined | null): Promise<DiffStatsJs>
+
+/** @deprecated 请改用 diff (参数与结果相同) */
+export declare function diffAsync(oldStr: PathLike | undefined | null, newStr: PathLike, patch: FileArg, options: DiffOptionsWithStats, signal?: AbortSignal | undefined | null): Promise<DiffStatsJs>
+
+/** @deprecated 旧的参数位置：signal 作为第 4 个参数；请改为 verifyPatch(oldStr, newStr, patch, options, signal) */
+export declare function verifyPatch(oldStr: PathLike, newStr: PathLike, patch: PathLike, signal: AbortSignal): Promise<boolean>
+
+export declare function diffSync(oldStr: PathLike | undefined | null, newStr: PathLike, patch: FileArg, options: DiffOptionsWithStats): DiffStatsJs
+
+export declare function diffRange(oldStr: PathLike, oldOffset: number, oldLength: number, newStr: PathLike, newOffset: number, newLength: number, patch: PathLike, options: DiffOptionsWithStats, signal?: AbortSignal | undefined | null): Promise<DiffStatsJs>
+
+export declare function diffRangeSync(oldStr: PathLike, oldOffset: number, oldLength: number, newStr: PathLike, newOffset: number, newLength: number, patch: PathLike, options: DiffOptionsWithStats): DiffStatsJs
+
+export declare function diffBuffersToFile(old: Buffer, new: Buffer, patch: PathLike, options: DiffOptionsWithStats): Promise<DiffStatsJs>
+
+export declare function diffBuffersToFileSync(old: Buffer, new: Buffer, patch: PathLike, options: DiffOptionsWithStats): DiffStatsJs
+
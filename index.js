@@ -508,13 +508,105 @@ if (!nativeBinding) {
 }
 
 module.exports = nativeBinding
+module.exports.PreparedBase = nativeBinding.PreparedBase
+module.exports.algorithmInfoSync = nativeBinding.algorithmInfoSync
+module.exports.apply = nativeBinding.apply
+module.exports.applyBundle = nativeBinding.applyBundle
+module.exports.applyBundleSync = nativeBinding.applyBundleSync
+module.exports.applyBundleToDir = nativeBinding.applyBundleToDir
+module.exports.applyBundleToDirSync = nativeBinding.applyBundleToDirSync
+module.exports.applySync = nativeBinding.applySync
+module.exports.bootSlotSync = nativeBinding.bootSlotSync
+module.exports.buildPatchSetSync = nativeBinding.buildPatchSetSync
+module.exports.checkFileAccessDetailedSync = nativeBinding.checkFileAccessDetailedSync
 module.exports.checkFileAccessSync = nativeBinding.checkFileAccessSync
+module.exports.checkPatchPartSync = nativeBinding.checkPatchPartSync
+module.exports.commitUpdateSync = nativeBinding.commitUpdateSync
+module.exports.convertPatchSync = nativeBinding.convertPatchSync
+module.exports.createBundle = nativeBinding.createBundle
+module.exports.createBundleSync = nativeBinding.createBundleSync
+module.exports.createManifest = nativeBinding.createManifest
+module.exports.createManifestSync = nativeBinding.createManifestSync
+module.exports.delta = nativeBinding.delta
+module.exports.deltaSync = nativeBinding.deltaSync
 module.exports.diff = nativeBinding.diff
+module.exports.diffAsync = nativeBinding.diffAsync
+module.exports.diffBatch = nativeBinding.diffBatch
+module.exports.diffBatchSync = nativeBinding.diffBatchSync
+module.exports.diffBidirectionalSync = nativeBinding.diffBidirectionalSync
+module.exports.diffBuffer = nativeBinding.diffBuffer
+module.exports.diffBufferSync = nativeBinding.diffBufferSync
+module.exports.diffBuffersToFile = nativeBinding.diffBuffersToFile
+module.exports.diffBuffersToFileSync = nativeBinding.diffBuffersToFileSync
+module.exports.diffDir = nativeBinding.diffDir
+module.exports.diffDirSync = nativeBinding.diffDirSync
+module.exports.diffRange = nativeBinding.diffRange
+module.exports.diffRangeSync = nativeBinding.diffRangeSync
+module.exports.diffReport = nativeBinding.diffReport
+module.exports.diffReportSync = nativeBinding.diffReportSync
+module.exports.diffStream = nativeBinding.diffStream
 module.exports.diffSync = nativeBinding.diffSync
+module.exports.diffToShmSync = nativeBinding.diffToShmSync
+module.exports.estimateDelta = nativeBinding.estimateDelta
+module.exports.estimateDeltaSync = nativeBinding.estimateDeltaSync
+module.exports.extractPatchSync = nativeBinding.extractPatchSync
 module.exports.getCompressionRatioSync = nativeBinding.getCompressionRatioSync
+module.exports.getDiffStatsSync = nativeBinding.getDiffStatsSync
 module.exports.getFileSizeSync = nativeBinding.getFileSizeSync
+module.exports.getPatchInfo = nativeBinding.getPatchInfo
+module.exports.getPatchInfoFromBuffer = nativeBinding.getPatchInfoFromBuffer
+module.exports.getPatchInfoFromBufferSync = nativeBinding.getPatchInfoFromBufferSync
 module.exports.getPatchInfoSync = nativeBinding.getPatchInfoSync
+module.exports.inspectPatch = nativeBinding.inspectPatch
+module.exports.inspectPatchSync = nativeBinding.inspectPatchSync
+module.exports.joinPatchSync = nativeBinding.joinPatchSync
 module.exports.patch = nativeBinding.patch
+module.exports.patchAsync = nativeBinding.patchAsync
+module.exports.patchBuffer = nativeBinding.patchBuffer
+module.exports.patchBufferSync = nativeBinding.patchBufferSync
+module.exports.patchBufferToFile = nativeBinding.patchBufferToFile
+module.exports.patchBufferToFileSync = nativeBinding.patchBufferToFileSync
+module.exports.patchChain = nativeBinding.patchChain
+module.exports.patchChainSync = nativeBinding.patchChainSync
+module.exports.patchDir = nativeBinding.patchDir
+module.exports.patchDirSync = nativeBinding.patchDirSync
+module.exports.patchFanoutSync = nativeBinding.patchFanoutSync
+module.exports.patchFileWithPatchBuffer = nativeBinding.patchFileWithPatchBuffer
+module.exports.patchFileWithPatchBufferSync = nativeBinding.patchFileWithPatchBufferSync
+module.exports.patchFromChunksSync = nativeBinding.patchFromChunksSync
+module.exports.patchFromUrl = nativeBinding.patchFromUrl
+module.exports.patchFromUrlSync = nativeBinding.patchFromUrlSync
+module.exports.patchInPlace = nativeBinding.patchInPlace
+module.exports.patchInPlaceSync = nativeBinding.patchInPlaceSync
+module.exports.patchStream = nativeBinding.patchStream
 module.exports.patchSync = nativeBinding.patchSync
+module.exports.patchToArrayBuffer = nativeBinding.patchToArrayBuffer
+module.exports.patchToArrayBufferSync = nativeBinding.patchToArrayBufferSync
+module.exports.patchToBuffer = nativeBinding.patchToBuffer
+module.exports.patchToBufferSync = nativeBinding.patchToBufferSync
+module.exports.patchToFixedRegionSync = nativeBinding.patchToFixedRegionSync
+module.exports.planUpdateSync = nativeBinding.planUpdateSync
+module.exports.preflight = nativeBinding.preflight
+module.exports.preflightSync = nativeBinding.preflightSync
+module.exports.prepareUpdateSync = nativeBinding.prepareUpdateSync
+module.exports.publicKeyFromPrivateSync = nativeBinding.publicKeyFromPrivateSync
+module.exports.recompressPatchSync = nativeBinding.recompressPatchSync
+module.exports.rollbackUpdateSync = nativeBinding.rollbackUpdateSync
+module.exports.setLogCallback = nativeBinding.setLogCallback
+module.exports.signPatch = nativeBinding.signPatch
+module.exports.signPatchSync = nativeBinding.signPatchSync
+module.exports.signature = nativeBinding.signature
+module.exports.signatureSync = nativeBinding.signatureSync
+module.exports.slotStateSync = nativeBinding.slotStateSync
+module.exports.splitPatchSync = nativeBinding.splitPatchSync
+module.exports.stageUpdateSync = nativeBinding.stageUpdateSync
+module.exports.trainDictionary = nativeBinding.trainDictionary
+module.exports.trainDictionarySync = nativeBinding.trainDictionarySync
+module.exports.validatePatchSelfSync = nativeBinding.validatePatchSelfSync
+module.exports.verifyManifest = nativeBinding.verifyManifest
+module.exports.verifyManifestSync = nativeBinding.verifyManifestSync
 module.exports.verifyPatch = nativeBinding.verifyPatch
+module.exports.verifyPatchAgainstHash = nativeBinding.verifyPatchAgainstHash
+module.exports.verifyPatchAgainstHashSync = nativeBinding.verifyPatchAgainstHashSync
+module.exports.verifyPatchAsync = nativeBinding.verifyPatchAsync
 module.exports.verifyPatchSync = nativeBinding.verifyPatchSync
@@ -0,0 +1,112 @@
+// ESM 入口：通过 createRequire 加载 CommonJS 绑定 (index.js 负责选择平台对应的原生模块)，重新导出全部具名导出
+import { createRequire } from 'node:module'
+
+const require = createRequire(import.meta.url)
+const binding = require('./index.js')
+
+export const {
+  PreparedBase,
+  algorithmInfoSync,
+  apply,
+  applyBundle,
+  applyBundleSync,
+  applyBundleToDir,
+  applyBundleToDirSync,
+  applySync,
+  bootSlotSync,
+  buildPatchSetSync,
+  checkFileAccessDetailedSync,
+  checkFileAccessSync,
+  checkPatchPartSync,
+  commitUpdateSync,
+  convertPatchSync,
+  createBundle,
+  createBundleSync,
+  createManifest,
+  createManifestSync,
+  delta,
+  deltaSync,
+  diff,
+  diffAsync,
+  diffBatch,
+  diffBatchSync,
+  diffBidirectionalSync,
+  diffBuffer,
+  diffBufferSync,
+  diffBuffersToFile,
+  diffBuffersToFileSync,
+  diffDir,
+  diffDirSync,
+  diffRange,
+  diffRangeSync,
+  diffReport,
+  diffReportSync,
+  diffStream,
+  diffSync,
+  diffToShmSync,
+  estimateDelta,
+  estimateDeltaSync,
+  extractPatchSync,
+  getCompressionRatioSync,
+  getDiffStatsSync,
+  getFileSizeSync,
+  getPatchInfo,
+  getPatchInfoFromBuffer,
+  getPatchInfoFromBufferSync,
+  getPatchInfoSync,
+  inspectPatch,
+  inspectPatchSync,
+  joinPatchSync,
+  patch,
+  patchAsync,
+  patchBuffer,
+  patchBufferSync,
+  patchBufferToFile,
+  patchBufferToFileSync,
+  patchChain,
+  patchChainSync,
+  patchDir,
+  patchDirSync,
+  patchFanoutSync,
+  patchFileWithPatchBuffer,
+  patchFileWithPatchBufferSync,
+  patchFromChunksSync,
+  patchFromUrl,
+  patchFromUrlSync,
+  patchInPlace,
+  patchInPlaceSync,
+  patchStream,
+  patchSync,
+  patchToArrayBuffer,
+  patchToArrayBufferSync,
+  patchToBuffer,
+  patchToBufferSync,
+  patchToFixedRegionSync,
+  planUpdateSync,
+  preflight,
+  preflightSync,
+  prepareUpdateSync,
+  publicKeyFromPrivateSync,
+  recompressPatchSync,
+  rollbackUpdateSync,
+  setLogCallback,
+  signPatch,
+  signPatchSync,
+  signature,
+  signatureSync,
+  slotStateSync,
+  splitPatchSync,
+  stageUpdateSync,
+  trainDictionary,
+  trainDictionarySync,
+  validatePatchSelfSync,
+  verifyManifest,
+  verifyManifestSync,
+  verifyPatch,
+  verifyPatchAgainstHash,
+  verifyPatchAgainstHashSync,
+  verifyPatchAsync,
+  verifyPatchSync,
+} = binding
+
+export default binding
@@ -0,0 +1,286 @@
+//! A/B 双槽位更新：应用固定装在两个目录 (槽位) 之一，更新写入另一个槽位，
+//! 以启动计数的方式试运行新版本，确认后提交，失败或未确认时回到旧槽位。
+//!
+//! 状态保存在一个很小的 JSON 文件中，每次改动都先写临时文件再原子改名：
+//! `{"version": 1, "active": "a", "pending": "b", "triesLeft": 2}`。
+//! `active` 为最近确认可用的槽位，`pending` 为试运行中的槽位，`triesLeft` 为其剩余的启动次数。
+//! 状态文件不存在时视为槽位 A 可用、没有待确认的更新。
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::bsdiff_rust::BsdiffRust;
+use crate::error::BsdiffError;
+use crate::json::{self, Value};
+use crate::logger;
+use crate::paths;
+
+/// 当前状态文件格式版本
+pub const SLOT_STATE_VERSION: u64 = 1;
+
+/// 槽位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    A,
+    B,
+}
+
+impl Slot {
+    pub fn name(self) -> &'static str {
+        match self {
+            Slot::A => "a",
+            Slot::B => "b",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self, BsdiffError> {
+        match name {
+            "a" => Ok(Slot::A),
+            "b" => Ok(Slot::B),
+            other => Err(format!("Unknown slot: {} (expected \"a\" or \"b\")", other).into()),
+        }
+    }
+
+    /// 另一个槽位
+    pub fn other(self) -> Self {
+        match self {
+            Slot::A => Slot::B,
+            Slot::B => Slot::A,
+        }
+    }
+}
+
+/// 双槽位状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotState {
+    /// 最近确认可用的槽位
+    pub active: Slot,
+    /// 已写入更新、等待确认的槽位
+    pub pending: Option<Slot>,
+    /// 待确认槽位剩余的试运行次数；为 0 时下一次启动回到 `active`
+    pub tries_left: u32,
+}
+
+impl Default for SlotState {
+    fn default() -> Self {
+        Self { active: Slot::A, pending: None, tries_left: 0 }
+    }
+}
+
+impl SlotState {
+    /// 当前应运行的槽位：试运行中为待确认槽位，否则为可用槽位
+    pub fn current(&self) -> Slot {
+        self.pending.unwrap_or(self.active)
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = format!("{{\"version\": {}, \"active\": ", SLOT_STATE_VERSION);
+        json::push_string(&mut out, self.active.name());
+        out.push_str(", \"pending\": ");
+        match self.pending {
+            Some(slot) => json::push_string(&mut out, slot.name()),
+            None => out.push_str("null"),
+        }
+        out.push_str(&format!(", \"triesLeft\": {}}}\n", self.tries_left));
+        out
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, BsdiffError> {
+        let invalid = |reason: &str| format!("Invalid slot state: {}", reason);
+        let root = json::parse(text).ok_or_else(|| invalid("not valid JSON"))?;
+        let root = root.as_object().ok_or_else(|| invalid("expected an object"))?;
+        match root.get("version").and_then(Value::as_u64) {
+            Some(version) if version <= SLOT_STATE_VERSION => {}
+            Some(version) => return Err(format!("Unsupported slot state version: {} (supported up to {})", version, SLOT_STATE_VERSION).into()),
+            None => return Err(invalid("missing version").into()),
+        }
+        let active = Slot::parse(root.get("active").and_then(Value::as_str).ok_or_else(|| invalid("missing active"))?)?;
+        let pending = match root.get("pending") {
+            None | Some(Value::Null) => None,
+            Some(value) => Some(Slot::parse(value.as_str().ok_or_else(|| invalid("bad pending"))?)?),
+        };
+        if pending == Some(active) {
+            return Err(invalid("pending slot is the active slot").into());
+        }
+        let tries_left = root
+            .get("triesLeft")
+            .and_then(Value::as_u64)
+            .and_then(|tries| u32::try_from(tries).ok())
+            .ok_or_else(|| invalid("bad triesLeft"))?;
+        Ok(Self { active, pending, tries_left })
+    }
+}
+
+/// 两个槽位目录与状态文件
+#[derive(Debug, Clone)]
+pub struct AbSlots {
+    pub slot_a: PathBuf,
+    pub slot_b: PathBuf,
+    pub state_file: PathBuf,
+}
+
+impl AbSlots {
+    pub fn new(slot_a: &Path, slot_b: &Path, state_file: &Path) -> Self {
+        Self { slot_a: slot_a.to_path_buf(), slot_b: slot_b.to_path_buf(), state_file: state_file.to_path_buf() }
+    }
+
+    /// 槽位对应的目录
+    pub fn dir(&self, slot: Slot) -> &Path {
+        match slot {
+            Slot::A => &self.slot_a,
+            Slot::B => &self.slot_b,
+        }
+    }
+
+    /// 读取状态；状态文件不存在时返回默认状态 (槽位 A 可用)
+    pub fn state(&self) -> Result<SlotState, BsdiffError> {
+        match fs::read_to_string(&self.state_file) {
+            Ok(text) => SlotState::from_json(&text).map_err(|e| format!("{}: {}", self.state_file.display(), e).into()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SlotState::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 原子地写入状态：先写临时文件并 fsync，再改名覆盖
+    fn save(&self, state: &SlotState) -> Result<(), BsdiffError> {
+        let temp = paths::with_suffix(&self.state_file, ".tmp");
+        let result = File::create(&temp)
+            .and_then(|mut file| {
+                file.write_all(state.to_json().as_bytes())?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&temp, &self.state_file));
+        if let Err(e) = result {
+            let _ = fs::remove_file(&temp);
+            return Err(e.into());
+        }
+        BsdiffRust::sync_parent_dir(&self.state_file);
+        Ok(())
+    }
+
+    /// 准备写入更新：清空非可用槽位的目录并返回其路径 (供 applyBundleToDir 等作为目标目录)。
+    /// 已有待确认的更新时拒绝，需先提交或回滚
+    pub fn prepare_update(&self) -> Result<PathBuf, BsdiffError> {
+        let state = self.state()?;
+        if let Some(pending) = state.pending {
+            return Err(format!("Slot {} is still pending; commit or roll back first", pending.name()).into());
+        }
+        let target = self.dir(state.active.other()).to_path_buf();
+        if fs::symlink_metadata(&target).is_ok() {
+            fs::remove_dir_all(&target)?;
+        }
+        Ok(target)
+    }
+
+    /// 更新已写入非可用槽位：将其标记为待确认，最多试运行 `max_tries` 次
+    pub fn stage_update(&self, max_tries: u32) -> Result<SlotState, BsdiffError> {
+        if max_tries == 0 {
+            return Err("An update needs at least one try".into());
+        }
+        let mut state = self.state()?;
+        let slot = state.active.other();
+        if !self.dir(slot).is_dir() {
+            return Err(format!("Slot {} has no update: {} is not a directory", slot.name(), self.dir(slot).display()).into());
+        }
+        state.pending = Some(slot);
+        state.tries_left = max_tries;
+        self.save(&state)?;
+        Ok(state)
+    }
+
+    /// 启动时调用，返回本次应运行的槽位
+    ///
+    /// 有待确认的更新时消耗一次试运行机会并运行它；机会用尽 (新版本多次启动都未提交) 时
+    /// 放弃该更新，回到可用槽位
+    pub fn boot(&self) -> Result<SlotState, BsdiffError> {
+        let mut state = self.state()?;
+        let Some(pending) = state.pending else {
+            return Ok(state);
+        };
+        if state.tries_left > 0 {
+            state.tries_left -= 1;
+        } else {
+            logger::warn(&format!("Slot {} was never committed; rolling back to slot {}", pending.name(), state.active.name()));
+            state.pending = None;
+        }
+        self.save(&state)?;
+        Ok(state)
+    }
+
+    /// 新版本运行正常：待确认槽位成为可用槽位。没有待确认的更新时不做任何事
+    pub fn commit_update(&self) -> Result<SlotState, BsdiffError> {
+        let mut state = self.state()?;
+        if let Some(pending) = state.pending {
+            state = SlotState { active: pending, pending: None, tries_left: 0 };
+            self.save(&state)?;
+        }
+        Ok(state)
+    }
+
+    /// 回滚：有待确认的更新时放弃它；否则切换回另一个槽位 (上一次提交前的版本，须仍然存在)
+    pub fn rollback_update(&self) -> Result<SlotState, BsdiffError> {
+        let mut state = self.state()?;
+        if state.pending.is_some() {
+            state.pending = None;
+        } else {
+            let previous = state.active.other();
+            if !self.dir(previous).is_dir() {
+                return Err(format!("Nothing to roll back to: {} is not a directory", self.dir(previous).display()).into());
+            }
+            state.active = previous;
+        }
+        state.tries_left = 0;
+        self.save(&state)?;
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_ab_update_cycle() {
+        let dir = TempDir::new().unwrap();
+        let slots = AbSlots::new(&dir.path().join("a"), &dir.path().join("b"), &dir.path().join("slots.json"));
+        fs::create_dir(&slots.slot_a).unwrap();
+        assert_eq!(slots.state().unwrap(), SlotState::default());
+        assert!(slots.rollback_update().is_err());
+        assert!(slots.stage_update(2).is_err());
+
+        // 写入槽位 B 并试运行：两次机会用尽后第三次启动回到 A
+        fs::create_dir(&slots.slot_b).unwrap();
+        fs::write(slots.slot_b.join("stale"), b"old").unwrap();
+        assert_eq!(slots.prepare_update().unwrap(), slots.slot_b);
+        assert!(!slots.slot_b.exists());
+        fs::create_dir(&slots.slot_b).unwrap();
+        slots.stage_update(2).unwrap();
+        assert!(slots.prepare_update().is_err());
+        for tries_left in [1, 0] {
+            let state = slots.boot().unwrap();
+            assert_eq!((state.current(), state.tries_left), (Slot::B, tries_left));
+        }
+        let state = slots.boot().unwrap();
+        assert_eq!((state.current(), state.pending), (Slot::A, None));
+
+        // 再次试运行并提交
+        slots.stage_update(3).unwrap();
+        assert_eq!(slots.boot().unwrap().current(), Slot::B);
+        let state = slots.commit_update().unwrap();
+        assert_eq!(state, SlotState { active: Slot::B, pending: None, tries_left: 0 });
+        assert_eq!(slots.commit_update().unwrap(), state);
+        assert_eq!(slots.boot().unwrap().current(), Slot::B);
+        assert_eq!(SlotState::from_json(&fs::read_to_string(&slots.state_file).unwrap()).unwrap(), state);
+
+        // 提交后回滚切换回 A；待确认时回滚只放弃更新
+        assert_eq!(slots.rollback_update().unwrap().current(), Slot::A);
+        slots.stage_update(1).unwrap();
+        assert_eq!(slots.rollback_update().unwrap(), SlotState::default());
+
+        for bad in ["{}", "{\"version\": 2, \"active\": \"a\", \"triesLeft\": 0}", "{\"version\": 1, \"active\": \"a\", \"pending\": \"a\", \"triesLeft\": 1}"] {
+            assert!(SlotState::from_json(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,423 @@
+//! 压缩包感知的预处理：ZIP 条目先解压再 diff (思路同 Google archive-patcher)
+//!
+//! 压缩后的条目内容哪怕只改动一个字节，整个压缩流也会随之改变，直接对 ZIP 做 bsdiff 几乎得不到有效的差分。
+//! diff 前把新旧文件中 deflate 压缩的条目替换为解压后的内容 (展开)，差分在展开后的数据上进行；
+//! 新文件只展开能以 zlib 某个压缩级别逐字节重新压缩出原数据的条目，级别随条目记录在展开数据中，
+//! 应用补丁时按记录的级别重新压缩，还原出与原文件完全相同的 ZIP。无法复现的条目保持压缩状态原样 diff。
+//!
+//! Electron 的 `app.asar` 本身不压缩文件内容 (Pickle 头 + JSON 目录 + 原始文件数据)，直接 diff 即可，无需变换。
+
+use rayon::prelude::*;
+
+use crate::deflate;
+use crate::error::BsdiffError;
+
+type Result<T> = std::result::Result<T, BsdiffError>;
+
+/// 展开后的数据总量上限，防止压缩炸弹耗尽内存 (按条目声明的解压大小依次累计)
+const MAX_EXPANDED: u64 = 1 << 32;
+
+/// 尝试复现压缩数据时依次使用的 zlib 级别 (默认级别 6 最常见，其次为最高与最快)
+const LEVEL_ORDER: [u8; 9] = [6, 9, 1, 5, 4, 7, 8, 2, 3];
+
+/// ZIP 压缩方法：deflate
+const METHOD_DEFLATE: u16 = 8;
+
+/// 展开数据中的记录类型：原样保留的字节
+const RECORD_RAW: u8 = 0;
+/// 展开数据中的记录类型：解压后的条目
+const RECORD_ENTRY: u8 = 1;
+
+/// 补丁头字段中的容器类型：ZIP
+const KIND_ZIP: u8 = 1;
+
+/// 补丁使用的压缩包变换
+///
+/// 展开数据是一串记录：原样字节为 `0 | len: u64 LE | 字节`，解压后的条目为
+/// `1 | level: u8 | compressed_len: u64 LE | len: u64 LE | 解压后的字节`。
+/// 旧文件展开时不检查能否复现，`level` 记为 0；新文件的 `level` 即重新压缩所用的 zlib 级别。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArchiveTransform {
+    /// 新文件展开后的大小 (补丁负载生成的数据大小)
+    pub expanded_size: u64,
+    /// 新文件中展开的条目数
+    pub entries: u64,
+}
+
+/// ZIP 中的一个 deflate 条目
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    /// 压缩数据在文件中的偏移
+    offset: usize,
+    /// 压缩数据长度
+    compressed_len: usize,
+    /// 中央目录声明的解压后大小
+    len: u64,
+}
+
+impl ArchiveTransform {
+    /// 为 diff 展开新旧文件，返回变换与展开后的旧、新数据；新文件不是 ZIP 或没有可复现的条目时返回 `None`
+    pub fn plan(old: &[u8], new: &[u8]) -> Option<(Self, Vec<u8>, Vec<u8>)> {
+        let entries = zip_entries(new)?;
+        let expanded: Vec<_> = inflate_entries(new, &entries)
+            .into_par_iter()
+            .map(|(entry, data)| {
+                let level = LEVEL_ORDER
+                    .into_iter()
+                    .find(|&level| deflate::reproduces(&data, level, &new[entry.offset..entry.offset + entry.compressed_len]))?;
+                Some((entry, level, data))
+            })
+            .flatten()
+            .collect();
+        if expanded.is_empty() {
+            return None;
+        }
+        let new_data = serialize(new, expanded.iter().map(|(entry, level, data)| (*entry, *level, &data[..])));
+        let transform = Self { expanded_size: new_data.len() as u64, entries: expanded.len() as u64 };
+        Some((transform, Self::expand_old(old), new_data))
+    }
+
+    /// 展开旧文件：能完整解压的 deflate 条目都替换为解压后的内容；不是 ZIP 时整个文件作为一条原样记录
+    ///
+    /// 只依赖旧文件内容，diff 与应用补丁时得到相同的结果。
+    pub fn expand_old(old: &[u8]) -> Vec<u8> {
+        let entries = zip_entries(old).unwrap_or_default();
+        let expanded = inflate_entries(old, &entries);
+        serialize(old, expanded.iter().map(|(entry, data)| (*entry, 0, &data[..])))
+    }
+
+    /// 把补丁生成的展开数据还原为新文件：按记录的级别重新压缩每个条目
+    pub fn rewrap(&self, expanded: &[u8]) -> Result<Vec<u8>> {
+        if expanded.len() as u64 != self.expanded_size {
+            return Err(format!("Expanded archive is {} bytes, header declares {}", expanded.len(), self.expanded_size).into());
+        }
+        let records = parse_records(expanded)?;
+        let parts = records
+            .into_par_iter()
+            .map(|record| match record {
+                Record::Raw(data) => Ok(data.to_vec()),
+                Record::Entry { level, compressed_len, data } => {
+                    let compressed = deflate::deflate(data, level).map_err(|e| e.to_string())?;
+                    if compressed.len() as u64 != compressed_len {
+                        return Err(format!(
+                            "Archive entry recompressed to {} bytes, expected {}",
+                            compressed.len(), compressed_len
+                        ));
+                    }
+                    Ok(compressed)
+                }
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(parts.concat())
+    }
+
+    pub fn to_field(self) -> Vec<u8> {
+        let mut out = vec![KIND_ZIP];
+        out.extend_from_slice(&self.expanded_size.to_le_bytes());
+        out.extend_from_slice(&self.entries.to_le_bytes());
+        out
+    }
+
+    pub fn from_field(value: &[u8]) -> Option<Self> {
+        if value.len() != 17 || value[0] != KIND_ZIP {
+            return None;
+        }
+        Some(Self {
+            expanded_size: u64::from_le_bytes(value[1..9].try_into().ok()?),
+            entries: u64::from_le_bytes(value[9..17].try_into().ok()?),
+        })
+    }
+}
+
+/// 数据是否为 Electron ASAR 归档 (Pickle 头中的 JSON 目录以 `{"files":` 开头)
+pub fn is_asar(data: &[u8]) -> bool {
+    let json_len = data.get(12..16).map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize);
+    data.get(0..4) == Some(&4u32.to_le_bytes()[..])
+        && json_len.is_some_and(|len| len >= 9)
+        && data.get(16..25) == Some(&b"{\"files\":"[..])
+}
+
+/// 解压条目：只保留能完整解压 (恰好消耗全部压缩数据且大小与声明一致) 的条目，解压总量受 [`MAX_EXPANDED`] 限制
+fn inflate_entries(data: &[u8], entries: &[Entry]) -> Vec<(Entry, Vec<u8>)> {
+    let mut total = 0u64;
+    let allowed: Vec<Entry> = entries
+        .iter()
+        .copied()
+        .filter(|entry| {
+            total = total.saturating_add(entry.len);
+            total <= MAX_EXPANDED
+        })
+        .collect();
+    allowed
+        .into_par_iter()
+        .filter_map(|entry| {
+            let compressed = &data[entry.offset..entry.offset + entry.compressed_len];
+            let (inflated, consumed) = deflate::inflate(compressed, usize::try_from(entry.len).ok()?)?;
+            (consumed == compressed.len() && inflated.len() as u64 == entry.len).then_some((entry, inflated))
+        })
+        .collect()
+}
+
+/// 按偏移顺序写出展开数据：条目之间的字节原样记录
+fn serialize<'a>(data: &[u8], entries: impl Iterator<Item = (Entry, u8, &'a [u8])>) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    let push_raw = |out: &mut Vec<u8>, raw: &[u8]| {
+        if !raw.is_empty() {
+            out.push(RECORD_RAW);
+            out.extend_from_slice(&(raw.len() as u64).to_le_bytes());
+            out.extend_from_slice(raw);
+        }
+    };
+    for (entry, level, inflated) in entries {
+        push_raw(&mut out, &data[pos..entry.offset]);
+        out.push(RECORD_ENTRY);
+        out.push(level);
+        out.extend_from_slice(&(entry.compressed_len as u64).to_le_bytes());
+        out.extend_from_slice(&(inflated.len() as u64).to_le_bytes());
+        out.extend_from_slice(inflated);
+        pos = entry.offset + entry.compressed_len;
+    }
+    push_raw(&mut out, &data[pos..]);
+    out
+}
+
+enum Record<'a> {
+    Raw(&'a [u8]),
+    Entry { level: u8, compressed_len: u64, data: &'a [u8] },
+}
+
+fn parse_records(mut data: &[u8]) -> Result<Vec<Record<'_>>> {
+    fn take<'a>(data: &mut &'a [u8], len: u64) -> Result<&'a [u8]> {
+        let len = usize::try_from(len).ok().filter(|len| *len <= data.len()).ok_or("Truncated expanded archive")?;
+        let (head, rest) = data.split_at(len);
+        *data = rest;
+        Ok(head)
+    }
+    fn take_u64(data: &mut &[u8]) -> Result<u64> {
+        Ok(u64::from_le_bytes(take(data, 8)?.try_into().unwrap()))
+    }
+
+    let mut records = Vec::new();
+    while !data.is_empty() {
+        match take(&mut data, 1)?[0] {
+            RECORD_RAW => {
+                let len = take_u64(&mut data)?;
+                records.push(Record::Raw(take(&mut data, len)?));
+            }
+            RECORD_ENTRY => {
+                let level = take(&mut data, 1)?[0];
+                if !(1..=9).contains(&level) {
+                    return Err(format!("Bad compression level {} in expanded archive", level).into());
+                }
+                let compressed_len = take_u64(&mut data)?;
+                let len = take_u64(&mut data)?;
+                records.push(Record::Entry { level, compressed_len, data: take(&mut data, len)? });
+            }
+            kind => return Err(format!("Bad record type {} in expanded archive", kind).into()),
+        }
+    }
+    Ok(records)
+}
+
+/// 由中央目录列出 ZIP 中的 deflate 条目 (按偏移排序，去掉相互重叠的条目)；不是 ZIP 时返回 `None`
+fn zip_entries(data: &[u8]) -> Option<Vec<Entry>> {
+    const EOCD_LEN: usize = 22;
+    let search_start = data.len().checked_sub(EOCD_LEN)?;
+    let eocd = (search_start.saturating_sub(u16::MAX as usize)..=search_start)
+        .rev()
+        .find(|&pos| u32_at(data, pos) == Some(0x0605_4b50))?;
+    let mut count = u16_at(data, eocd + 10)? as u64;
+    let mut cd_offset = u32_at(data, eocd + 16)? as u64;
+    if count == 0xFFFF || cd_offset == 0xFFFF_FFFF {
+        // ZIP64：定位器在 EOCD 之前 20 字节
+        let locator = eocd.checked_sub(20)?;
+        if u32_at(data, locator)? != 0x0706_4b50 {
+            return None;
+        }
+        let eocd64 = usize::try_from(u64_at(data, locator + 8)?).ok()?;
+        if u32_at(data, eocd64)? != 0x0606_4b50 {
+            return None;
+        }
+        count = u64_at(data, eocd64 + 32)?;
+        cd_offset = u64_at(data, eocd64 + 48)?;
+    }
+
+    let mut entries = Vec::new();
+    let mut pos = usize::try_from(cd_offset).ok()?;
+    for _ in 0..count {
+        if u32_at(data, pos)? != 0x0201_4b50 {
+            return None;
+        }
+        let method = u16_at(data, pos + 10)?;
+        let mut compressed_len = u32_at(data, pos + 20)? as u64;
+        let mut len = u32_at(data, pos + 24)? as u64;
+        let name_len = u16_at(data, pos + 28)? as usize;
+        let extra_len = u16_at(data, pos + 30)? as usize;
+        let comment_len = u16_at(data, pos + 32)? as usize;
+        let mut local = u32_at(data, pos + 42)? as u64;
+        let extra = data.get(pos + 46 + name_len..pos + 46 + name_len + extra_len)?;
+        read_zip64_extra(extra, &mut len, &mut compressed_len, &mut local);
+        pos += 46 + name_len + extra_len + comment_len;
+
+        if method != METHOD_DEFLATE {
+            continue;
+        }
+        let local = usize::try_from(local).ok()?;
+        if u32_at(data, local) != Some(0x0403_4b50) {
+            continue;
+        }
+        let offset = local + 30 + u16_at(data, local + 26)? as usize + u16_at(data, local + 28)? as usize;
+        let Some(compressed_len) = usize::try_from(compressed_len).ok().filter(|len| offset.saturating_add(*len) <= data.len()) else {
+            continue;
+        };
+        entries.push(Entry { offset, compressed_len, len });
+    }
+
+    entries.sort_by_key(|entry| entry.offset);
+    let mut end = 0;
+    entries.retain(|entry| {
+        let keep = entry.offset >= end;
+        if keep {
+            end = entry.offset + entry.compressed_len;
+        }
+        keep
+    });
+    Some(entries)
+}
+
+/// 中央目录项的 ZIP64 扩展字段：按顺序给出取值为 0xFFFFFFFF 的解压大小、压缩大小与本地头偏移
+fn read_zip64_extra(mut extra: &[u8], len: &mut u64, compressed_len: &mut u64, local: &mut u64) {
+    while extra.len() >= 4 {
+        let id = u16::from_le_bytes([extra[0], extra[1]]);
+        let size = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+        let Some(body) = extra.get(4..4 + size) else {
+            return;
+        };
+        if id == 0x0001 {
+            let mut values = body.chunks_exact(8).map(|value| u64::from_le_bytes(value.try_into().unwrap()));
+            for field in [len, compressed_len, local] {
+                if *field == 0xFFFF_FFFF {
+                    match values.next() {
+                        Some(value) => *field = value,
+                        None => return,
+                    }
+                }
+            }
+            return;
+        }
+        extra = &extra[4 + size..];
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..offset.checked_add(2)?)?.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset.checked_add(4)?)?.try_into().ok()?))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(offset..offset.checked_add(8)?)?.try_into().ok()?))
+}
+
+/// 测试用的最小 ZIP：`level` 为 `None` 时以存储方式写入，`Some(0)` 时写入单个存储块组成的 deflate 流
+/// (同 zlib 级别 0，不在尝试复现的级别之内)，否则用 zlib 兼容的 deflate 压缩
+#[cfg(all(test, feature = "zlib"))]
+pub(crate) fn test_zip(entries: &[(&str, &[u8], Option<u8>)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut central = Vec::new();
+    for (name, data, level) in entries {
+        let (method, body) = match level {
+            Some(0) => {
+                let mut body = vec![0x01];
+                body.extend_from_slice(&(data.len() as u16).to_le_bytes());
+                body.extend_from_slice(&(!(data.len() as u16)).to_le_bytes());
+                body.extend_from_slice(data);
+                (METHOD_DEFLATE, body)
+            }
+            Some(level) => (METHOD_DEFLATE, deflate::deflate(data, *level).unwrap()),
+            None => (0, data.to_vec()),
+        };
+        let offset = out.len() as u32;
+        let mut fields = Vec::new();
+        fields.extend_from_slice(&method.to_le_bytes());
+        fields.extend_from_slice(&[0; 8]); // 修改时间与 CRC-32 (展开与还原都不读取)
+        fields.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+
+        out.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        out.extend_from_slice(&[20, 0, 0, 0]);
+        out.extend_from_slice(&fields);
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(&body);
+
+        central.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        central.extend_from_slice(&[20, 0, 20, 0, 0, 0]);
+        central.extend_from_slice(&fields);
+        central.extend_from_slice(&[0; 10]);
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+    }
+    let cd_offset = out.len() as u32;
+    out.extend_from_slice(&central);
+    out.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    out.extend_from_slice(&cd_offset.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out
+}
+
+#[cfg(all(test, feature = "zlib"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_transform() {
+        let text = |seed: usize| -> Vec<u8> { (0..20_000).flat_map(|i| format!("line {} of {}\n", i * seed % 977, seed).into_bytes()).collect() };
+        let (a, b, c) = (text(3), text(5), text(7));
+        let old = test_zip(&[("a.txt", &a, Some(6)), ("b.txt", &b, Some(9))]);
+        let new = test_zip(&[("a.txt", &a, Some(6)), ("b.txt", &c, Some(9)), ("c.bin", b"raw", None), ("d.txt", b"hello", Some(1))]);
+        let entries = zip_entries(&new).unwrap();
+        assert_eq!(entries.len(), 3);
+
+        let (transform, old_data, new_data) = ArchiveTransform::plan(&old, &new).unwrap();
+        assert_eq!(transform.entries, 3);
+        assert_eq!(transform.expanded_size, new_data.len() as u64);
+        assert!(new_data.windows(a.len()).any(|window| window == &a[..]));
+        assert_eq!(old_data, ArchiveTransform::expand_old(&old));
+        assert_eq!(transform.rewrap(&new_data).unwrap(), new);
+        assert_eq!(ArchiveTransform::from_field(&transform.to_field()), Some(transform));
+        assert_eq!(ArchiveTransform::from_field(&[2; 17]), None);
+
+        // 存储块组成的 deflate 流能解压但无法复现：保持压缩状态，其余条目照常展开
+        let new = test_zip(&[("a.txt", &a, Some(6)), ("b.txt", &c, Some(9)), ("d.txt", b"hello", Some(0))]);
+        let (transform, _, new_data) = ArchiveTransform::plan(&old, &new).unwrap();
+        assert_eq!(transform.entries, 2);
+        assert_eq!(transform.rewrap(&new_data).unwrap(), new);
+
+        // 展开数据被篡改或截断时报错而不是输出错误的文件
+        assert!(transform.rewrap(&new_data[..new_data.len() - 1]).is_err());
+        let shorter = ArchiveTransform { expanded_size: new_data.len() as u64 - 1, ..transform };
+        assert!(shorter.rewrap(&new_data[..new_data.len() - 1]).is_err());
+
+        // 不是 ZIP：旧文件作为一条原样记录，新文件不做变换
+        assert!(ArchiveTransform::plan(&old, &a).is_none());
+        assert_eq!(ArchiveTransform::expand_old(b"plain").len(), 1 + 8 + 5);
+        assert!(ArchiveTransform::plan(&old, &test_zip(&[("c.bin", b"raw", None)])).is_none());
+
+        let mut asar = Vec::new();
+        for value in [4u32, 40, 36, 30] {
+            asar.extend_from_slice(&value.to_le_bytes());
+        }
+        asar.extend_from_slice(b"{\"files\":{}}");
+        assert!(is_asar(&asar));
+        assert!(!is_asar(&old));
+    }
+}
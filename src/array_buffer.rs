@@ -0,0 +1,63 @@
+//! 由 V8 分配、可通过 postMessage 转移给 Worker 的 ArrayBuffer：补丁输出直接写入其存储，
+//! 避免先生成 Vec 再复制 (外部存储的 ArrayBuffer 在 Node 中不可转移，Electron 中还会被复制)
+
+use std::ptr;
+
+use napi::bindgen_prelude::*;
+use napi::sys;
+
+/// 主线程上分配并持有强引用的 ArrayBuffer，工作线程可写入其存储
+pub(crate) struct OutputArrayBuffer {
+    reference: sys::napi_ref,
+    data: *mut u8,
+    len: usize,
+}
+
+// 存储由 V8 分配且在强引用释放前不会移动或回收；解析前 JavaScript 拿不到该对象，
+// 工作线程独占写入，引用只在主线程上创建与释放
+unsafe impl Send for OutputArrayBuffer {}
+
+impl OutputArrayBuffer {
+    /// 在主线程上分配 `len` 字节的 ArrayBuffer
+    pub(crate) fn new(env: &Env, len: usize) -> Result<Self> {
+        let mut value = ptr::null_mut();
+        let mut data = ptr::null_mut();
+        check_status!(
+            unsafe { sys::napi_create_arraybuffer(env.raw(), len, &mut data, &mut value) },
+            "Failed to allocate an ArrayBuffer of {} bytes",
+            len
+        )?;
+        let mut reference = ptr::null_mut();
+        check_status!(unsafe { sys::napi_create_reference(env.raw(), value, 1, &mut reference) })?;
+        Ok(Self { reference, data: data.cast(), len })
+    }
+
+    /// 复制 `data` 到新分配的 ArrayBuffer (补丁头未声明目标大小、无法预先分配时使用)
+    pub(crate) fn copy_from(env: &Env, data: &[u8]) -> Result<ArrayBuffer<'static>> {
+        let mut output = Self::new(env, data.len())?;
+        output.as_mut_slice().copy_from_slice(data);
+        output.into_value(env)
+    }
+
+    /// ArrayBuffer 的存储
+    pub(crate) fn as_mut_slice(&mut self) -> &mut [u8] {
+        if self.len == 0 {
+            return &mut [];
+        }
+        unsafe { std::slice::from_raw_parts_mut(self.data, self.len) }
+    }
+
+    /// 释放引用并返回 ArrayBuffer (主线程)
+    pub(crate) fn into_value(self, env: &Env) -> Result<ArrayBuffer<'static>> {
+        let mut value = ptr::null_mut();
+        let status = unsafe { sys::napi_get_reference_value(env.raw(), self.reference, &mut value) };
+        self.release(env)?;
+        check_status!(status)?;
+        unsafe { ArrayBuffer::from_napi_value(env.raw(), value) }
+    }
+
+    /// 释放引用，之后 ArrayBuffer 由 GC 回收 (主线程)
+    pub(crate) fn release(self, env: &Env) -> Result<()> {
+        check_status!(unsafe { sys::napi_delete_reference(env.raw(), self.reference) })
+    }
+}
@@ -0,0 +1,169 @@
+//! 应用补丁的审计日志：每一步 (补丁头校验、签名与摘要校验、写出字节数、rename 提交) 记录一行 JSON 事件，
+//! 追加到日志文件或交给回调，供企业部署核查更新程序在每台机器上具体做了什么。
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::json;
+use crate::logger;
+
+/// 审计回调，参数为一行 JSON (不含换行)；可能在任意工作线程中被调用
+pub type AuditCallback = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// 事件字段的值
+#[derive(Debug, Clone, Copy)]
+pub enum Field<'a> {
+    Null,
+    Bool(bool),
+    U64(u64),
+    Str(&'a str),
+    Path(&'a Path),
+}
+
+impl From<bool> for Field<'_> {
+    fn from(value: bool) -> Self {
+        Field::Bool(value)
+    }
+}
+
+impl From<u64> for Field<'_> {
+    fn from(value: u64) -> Self {
+        Field::U64(value)
+    }
+}
+
+impl<'a> From<&'a str> for Field<'a> {
+    fn from(value: &'a str) -> Self {
+        Field::Str(value)
+    }
+}
+
+impl<'a> From<&'a String> for Field<'a> {
+    fn from(value: &'a String) -> Self {
+        Field::Str(value)
+    }
+}
+
+impl<'a> From<&'a Path> for Field<'a> {
+    fn from(value: &'a Path) -> Self {
+        Field::Path(value)
+    }
+}
+
+impl<'a, T: Into<Field<'a>>> From<Option<T>> for Field<'a> {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Field::Null, Into::into)
+    }
+}
+
+/// 审计日志：事件依次交给每个输出 (日志文件、回调)
+///
+/// 每个事件是一行 JSON 对象，固定包含 `ts` (Unix 毫秒时间戳)、`pid` 与 `event`，其余字段随事件而定。
+#[derive(Clone)]
+pub struct AuditLog {
+    sinks: Vec<AuditCallback>,
+}
+
+impl AuditLog {
+    /// 把事件交给回调
+    pub fn callback(callback: AuditCallback) -> Self {
+        Self { sinks: vec![callback] }
+    }
+
+    /// 以追加模式打开 (不存在时创建) 日志文件，每个事件写一行 (JSON Lines)
+    ///
+    /// 每行以一次 write 追加，多个进程写同一个日志时行不会交错。写入失败只输出警告，不影响补丁应用
+    pub fn file(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let display = path.display().to_string();
+        Ok(Self::callback(Arc::new(move |line: &str| {
+            if let Err(e) = (&file).write_all(format!("{}\n", line).as_bytes()) {
+                logger::warn(&format!("Cannot write audit log {}: {}", display, e));
+            }
+        })))
+    }
+
+    /// 合并两个审计日志，事件依次写入两者
+    pub fn and(mut self, other: AuditLog) -> Self {
+        self.sinks.extend(other.sinks);
+        self
+    }
+
+    /// 记录一个事件
+    pub fn record(&self, event: &str, fields: &[(&str, Field)]) {
+        let line = to_json(now_ms(), event, fields);
+        for sink in &self.sinks {
+            sink(&line);
+        }
+    }
+}
+
+impl std::fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuditLog").field("sinks", &self.sinks.len()).finish()
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+fn to_json(ts: u64, event: &str, fields: &[(&str, Field)]) -> String {
+    let mut out = format!("{{\"ts\":{},\"pid\":{},\"event\":", ts, std::process::id());
+    json::push_string(&mut out, event);
+    for (key, value) in fields {
+        out.push(',');
+        json::push_string(&mut out, key);
+        out.push(':');
+        match value {
+            Field::Null => out.push_str("null"),
+            Field::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+            Field::U64(value) => out.push_str(&value.to_string()),
+            Field::Str(value) => json::push_string(&mut out, value),
+            Field::Path(value) => json::push_string(&mut out, &value.to_string_lossy()),
+        }
+    }
+    out.push('}');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_audit_log() {
+        let line = to_json(7, "bytes_written", &[
+            ("path", Path::new("a\"b").into()),
+            ("bytes", 42u64.into()),
+            ("ok", true.into()),
+            ("code", None::<&str>.into()),
+        ]);
+        let value = json::parse(&line).unwrap();
+        let object = value.as_object().unwrap();
+        assert_eq!(object["ts"].as_u64(), Some(7));
+        assert_eq!(object["event"].as_str(), Some("bytes_written"));
+        assert_eq!(object["path"].as_str(), Some("a\"b"));
+        assert_eq!(object["bytes"].as_u64(), Some(42));
+        assert_eq!(object["ok"], json::Value::Bool(true));
+        assert_eq!(object["code"], json::Value::Null);
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("audit.log");
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let log = AuditLog::file(&path).unwrap().and(AuditLog::callback(Arc::new(move |line: &str| sink.lock().unwrap().push(line.to_string()))));
+        log.record("first", &[]);
+        // 重新打开时追加而不是截断
+        AuditLog::file(&path).unwrap().record("second", &[("n", 2u64.into())]);
+        let text = std::fs::read_to_string(&path).unwrap();
+        let events: Vec<_> = text.lines().map(|line| json::parse(line).unwrap().as_object().unwrap()["event"].as_str().unwrap().to_string()).collect();
+        assert_eq!(events, ["first", "second"]);
+        assert_eq!(seen.lock().unwrap().len(), 1);
+    }
+}
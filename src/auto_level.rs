@@ -0,0 +1,81 @@
+//! 自动选择压缩级别：在原始 bsdiff 流的样本上实测几个 zstd 级别，按当前机器上的
+//! 每秒压缩率 (压缩率 ÷ 耗时) 选出最合适的级别
+
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// 参与测试的级别；不低于默认级别 3，自动选择不会得到比默认更大的补丁
+pub const CANDIDATE_LEVELS: [i32; 3] = [3, 9, 19];
+/// 用于测试的原始 bsdiff 流样本长度
+pub const SAMPLE_LEN: usize = 1 << 20;
+/// 每个级别至少重复压缩到该时长，减少计时误差
+const MIN_BENCH_TIME: Duration = Duration::from_millis(5);
+
+/// 自动级别标记：diff 压缩负载前在样本上选定级别并记录在此，供调用方读出 (克隆共享同一记录)
+#[derive(Debug, Clone, Default)]
+pub struct AutoLevel(Arc<AtomicI32>);
+
+impl AutoLevel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 选定的级别；尚未压缩过负载时为 `None`
+    pub fn chosen(&self) -> Option<i32> {
+        match self.0.load(Ordering::Relaxed) {
+            0 => None,
+            level => Some(level),
+        }
+    }
+
+    pub(crate) fn record(&self, level: i32) {
+        self.0.store(level, Ordering::Relaxed);
+    }
+}
+
+/// 在样本上测试 [`CANDIDATE_LEVELS`]，返回压缩率 ÷ 耗时最高的级别
+pub fn pick_level(sample: &[u8]) -> i32 {
+    let mut best = (CANDIDATE_LEVELS[0], 0.0);
+    for level in CANDIDATE_LEVELS {
+        let Some((size, elapsed)) = bench_level(sample, level) else {
+            continue;
+        };
+        let ratio = sample.len().max(1) as f64 / size.max(1) as f64;
+        let score = ratio / elapsed.as_secs_f64().max(1e-6);
+        if score > best.1 {
+            best = (level, score);
+        }
+    }
+    best.0
+}
+
+/// 压缩样本直到累计耗时达到 [`MIN_BENCH_TIME`]，返回压缩后大小与单次平均耗时
+fn bench_level(sample: &[u8], level: i32) -> Option<(usize, Duration)> {
+    let mut compressor = zstd::bulk::Compressor::new(level).ok()?;
+    let start = Instant::now();
+    let mut runs = 0u32;
+    let mut size = 0;
+    while runs == 0 || start.elapsed() < MIN_BENCH_TIME {
+        size = compressor.compress(sample).ok()?.len();
+        runs += 1;
+    }
+    Some((size, start.elapsed() / runs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_level() {
+        let sample: Vec<u8> = (0..SAMPLE_LEN as u32 / 4).flat_map(|i| [0, 0, (i % 7) as u8, (i * 31 % 251) as u8]).collect();
+        assert!(CANDIDATE_LEVELS.contains(&pick_level(&sample)));
+        assert!(CANDIDATE_LEVELS.contains(&pick_level(b"")));
+
+        let auto = AutoLevel::new();
+        assert_eq!(auto.chosen(), None);
+        auto.clone().record(9);
+        assert_eq!(auto.chosen(), Some(9));
+    }
+}
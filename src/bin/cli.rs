@@ -0,0 +1,457 @@
+//! bsdiff-rs 命令行工具：在 shell 脚本与 CI 中直接使用 bsdiff-rust 核心，无需 Node.js
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bsdiff_rust_core::preflight::{self, Preflight};
+use bsdiff_rust_core::{paths, report};
+use bsdiff_rust_core::utils::{get_patch_info, verify_patch, PatchInfo};
+use bsdiff_rust_core::{set_logger, AuditLog, AutoLevel, BsdiffRust, CancelToken, Compression, Dictionary, DiffAlgorithm, DiffMode, EncryptionKey, HashAlgorithm, LogLevel, OptimizationConfig, OverwritePolicy, PatchFormat, RetryPolicy, WriteLimit};
+
+const USAGE: &str = "\
+Usage:
+  bsdiff-rs diff <old> <new> <patch> [--format zstd|bsdiff40|vcdiff] [--compression zstd|xz|brotli|gzip|none] [--level N|auto] [--mode full|append] [--window BYTES] [--threads N] [--metadata KEY=VALUE]... [--exe-transform] [--archive-transform] [--key-file FILE] [--dictionary FILE] [--deterministic] [--hash sha256|blake3] [--algorithm bsdiff|blockdelta] [--timeout MS] [--volume-size BYTES] [--cache-dir DIR] [--fallback-to-full RATIO] [--retry N] [--retry-delay MS] [--overwrite error|replace|backup]
+  bsdiff-rs patch <old> <new> <patch> [--strict] [--mmap-output] [--sparse] [--dry-run] [--key-file FILE] [--dictionary FILE] [--max-output BYTES] [--max-memory BYTES] [--max-write-rate BYTES] [--retry N] [--retry-delay MS] [--overwrite error|replace|backup] [--durable] [--log-file FILE]
+  bsdiff-rs verify <old> <new> <patch> [--strict] [--key-file FILE] [--dictionary FILE] [--max-output BYTES] [--max-memory BYTES] [--log-file FILE]
+  bsdiff-rs info <patch>
+  bsdiff-rs preflight <old> <new> <patch> [diff options]
+  bsdiff-rs report <old> <new> [--algorithm bsdiff|blockdelta] [--window BYTES] [--threads N] [--timeout MS]
+
+Options:
+  --format <name>   Patch format written by diff (default: zstd)
+  --compression <name>
+                    Payload compression written by diff (default: zstd)
+  --level <n>       Zstd compression level for diff (default: 3); auto benchmarks a few levels on a
+                    sample of the diff output and picks the best ratio per second
+  --mode <name>     Diff mode (default: full)
+  --window <bytes>  Diff in windows of this many new-file bytes to bound memory on huge inputs
+  --threads <n>     Threads used to diff windows in parallel (default: CPU cores)
+  --metadata <key=value>
+                    Store a key/value pair in the patch header (repeatable)
+  --exe-transform   Normalize branch targets in PE/ELF/Mach-O code sections for smaller patches
+  --archive-transform
+                    Diff the decompressed entries of ZIP files and recompress them on apply
+  --deterministic   Byte-identical patches across runs and platforms (fixed zstd parameters)
+  --hash <name>     Hash algorithm for the file digests in the patch header (default: sha256)
+  --algorithm <name>
+                    bsdiff (default) or blockdelta: rsync-style block matching, much faster on huge files, larger patches
+  --timeout <ms>    Abort diff and remove its partial output if it takes longer than this
+  --volume-size <bytes>
+                    Split the patch into <patch>.001, <patch>.002, ... volumes of at most this size and
+                    write a volume index to <patch>; patch accepts the index or the volume directory
+  --cache-dir <dir> Reuse patches cached in <dir> for identical inputs and options; store new ones there
+  --fallback-to-full <ratio>
+                    Store the whole new file instead when the patch exceeds this fraction of its size (e.g. 0.9)
+  --key-file <file> 32-byte AES-256 key: diff encrypts the patch, patch/verify decrypt it
+  --dictionary <file>
+                    Shared zstd dictionary: diff compresses with it, patch/verify need the same one
+  --strict          Reject patches without a source checksum
+  --mmap-output     Preallocate the patched file and write it through a memory map
+  --sparse          Write all-zero blocks of the patched file as holes (sparse file)
+  --dry-run         Apply the patch in memory and verify it without writing the new file
+  --durable         Fsync the new file and its directory before exiting
+  --log-file <file> Append a JSON line for every step of applying the patch (audit log)
+  --max-output <bytes>
+                    Reject patches that would produce more than this many bytes
+  --max-memory <bytes>
+                    Fail instead of buffering more than this many bytes while applying a patch
+  --max-write-rate <bytes>
+                    Throttle writing the patched file to this many bytes per second
+  --retry <n>       Attempts (including the first) for renaming output that is briefly locked,
+                    e.g. by antivirus (default: 5 on Windows, 1 elsewhere)
+  --retry-delay <ms>
+                    Wait before the first retry, doubling each time up to 5 s (default: 100)
+  --overwrite <policy>
+                    Existing output of diff/patch: replace it (default), fail (error) or move it to .bak (backup)
+  --safe-mode       Use conservative zstd settings
+  --temp-dir <dir>  Directory for temporary output (default: $BSDIFF_TEMP_DIR or a RAM-backed directory)
+  --no-fast-temp    Write temporary output next to the target instead of a RAM-backed directory
+  -h, --help        Show this help
+
+Exit status: 0 on success, 1 on failure, verify mismatch or a failed preflight, 2 on usage errors.";
+
+/// 子命令
+#[derive(Debug, PartialEq)]
+enum Command {
+    Diff { old: PathBuf, new: PathBuf, patch: PathBuf },
+    Patch { old: PathBuf, new: PathBuf, patch: PathBuf },
+    Verify { old: PathBuf, new: PathBuf, patch: PathBuf },
+    Info { patch: PathBuf },
+    Report { old: PathBuf, new: PathBuf },
+    Preflight { old: PathBuf, new: PathBuf, patch: PathBuf },
+    Help,
+}
+
+/// 解析命令行参数 (不含程序名)，返回子命令与对应配置
+fn parse_args(args: &[String]) -> Result<(Command, OptimizationConfig), String> {
+    let mut config = OptimizationConfig::default();
+    let mut positional = Vec::new();
+    // 仅适用于 diff 的选项，出现在其他子命令中时报错
+    let mut diff_only: Option<&str> = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or_else(|| format!("Missing value for {}", arg));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok((Command::Help, config)),
+            "--format" => {
+                config.format = PatchFormat::parse(value()?).map_err(|e| e.to_string())?;
+                diff_only = Some("--format");
+            }
+            "--compression" => {
+                config.compression = Compression::parse(value()?).map_err(|e| e.to_string())?;
+                diff_only = Some("--compression");
+            }
+            "--level" => {
+                let raw = value()?;
+                if raw == "auto" {
+                    config.auto_level = Some(AutoLevel::new());
+                } else {
+                    config.compression_level = raw
+                        .parse()
+                        .ok()
+                        .filter(|level| zstd::compression_level_range().contains(level))
+                        .ok_or_else(|| format!("Invalid compression level: {}", raw))?;
+                }
+                diff_only = Some("--level");
+            }
+            "--mode" => {
+                config.mode = match value()?.as_str() {
+                    "full" => DiffMode::Full,
+                    "append" => DiffMode::Append,
+                    other => return Err(format!("Unknown diff mode: {}", other)),
+                };
+                diff_only = Some("--mode");
+            }
+            "--window" => {
+                let raw = value()?;
+                config.window_size = Some(raw.parse().ok().filter(|size| *size > 0).ok_or_else(|| format!("Invalid window size: {}", raw))?);
+                diff_only = Some("--window");
+            }
+            "--volume-size" => {
+                let raw = value()?;
+                config.volume_size = Some(raw.parse().ok().filter(|size| *size > 0).ok_or_else(|| format!("Invalid volume size: {}", raw))?);
+                diff_only = Some("--volume-size");
+            }
+            "--threads" => {
+                let raw = value()?;
+                config.threads = Some(raw.parse().ok().filter(|threads| *threads > 0).ok_or_else(|| format!("Invalid thread count: {}", raw))?);
+                diff_only = Some("--threads");
+            }
+            "--timeout" => {
+                let raw = value()?;
+                let timeout = raw.parse().ok().filter(|ms| *ms > 0).ok_or_else(|| format!("Invalid timeout: {}", raw))?;
+                config.cancel = Some(CancelToken::new().with_timeout(Duration::from_millis(timeout)));
+                diff_only = Some("--timeout");
+            }
+            "--metadata" => {
+                let raw = value()?;
+                let (key, entry) = raw.split_once('=').filter(|(key, _)| !key.is_empty()).ok_or_else(|| format!("Invalid metadata (expected KEY=VALUE): {}", raw))?;
+                config.metadata.insert(key.to_string(), entry.to_string());
+                diff_only = Some("--metadata");
+            }
+            "--exe-transform" => {
+                config.exe_transform = true;
+                diff_only = Some("--exe-transform");
+            }
+            "--archive-transform" => {
+                config.archive_transform = true;
+                diff_only = Some("--archive-transform");
+            }
+            "--deterministic" => {
+                config.deterministic = true;
+                diff_only = Some("--deterministic");
+            }
+            "--hash" => {
+                config.hash_algorithm = HashAlgorithm::parse(value()?).map_err(|e| e.to_string())?;
+                diff_only = Some("--hash");
+            }
+            "--algorithm" => {
+                config.algorithm = DiffAlgorithm::parse(value()?).map_err(|e| e.to_string())?;
+                diff_only = Some("--algorithm");
+            }
+            "--key-file" => {
+                let path = value()?;
+                let key = std::fs::read(paths::native(path.clone())).map_err(|e| format!("Cannot read key file {}: {}", path, e))?;
+                config.encryption_key = Some(EncryptionKey::new(&key).map_err(|e| format!("{}: {}", path, e))?);
+            }
+            "--dictionary" => {
+                let path = value()?;
+                let dictionary = std::fs::read(paths::native(path.clone())).map_err(|e| format!("Cannot read dictionary {}: {}", path, e))?;
+                config.dictionary = Some(Dictionary::new(&dictionary).map_err(|e| format!("{}: {}", path, e))?);
+            }
+            "--log-file" => {
+                let path = value()?;
+                let log = AuditLog::file(&paths::native(path.clone())).map_err(|e| format!("Cannot open log file {}: {}", path, e))?;
+                config.audit_log = Some(log);
+            }
+            "--max-output" => {
+                let raw = value()?;
+                config.max_output_size = Some(raw.parse().ok().filter(|size| *size > 0).ok_or_else(|| format!("Invalid output limit: {}", raw))?);
+            }
+            "--max-memory" => {
+                let raw = value()?;
+                config.max_memory = Some(raw.parse().ok().filter(|size| *size > 0).ok_or_else(|| format!("Invalid memory limit: {}", raw))?);
+            }
+            "--max-write-rate" => {
+                let raw = value()?;
+                let rate = raw.parse().map_err(|_| format!("Invalid write rate: {}", raw))?;
+                config.write_limit = Some(WriteLimit::new(rate).map_err(|e| e.to_string())?);
+            }
+            "--cache-dir" => {
+                config.cache_dir = Some(paths::native(value()?.clone()));
+                diff_only = Some("--cache-dir");
+            }
+            "--fallback-to-full" => {
+                let raw = value()?;
+                let threshold = raw.parse().ok().filter(|ratio: &f64| ratio.is_finite() && *ratio > 0.0);
+                config.fallback_to_full = Some(threshold.ok_or_else(|| format!("Invalid fallback ratio: {}", raw))?);
+                diff_only = Some("--fallback-to-full");
+            }
+            "--retry" => {
+                let raw = value()?;
+                let attempts = raw.parse().map_err(|_| format!("Invalid retry count: {}", raw))?;
+                // 未指定 --retry-delay 时首次等待 100ms
+                let delay = if config.retry.delay.is_zero() { Duration::from_millis(100) } else { config.retry.delay };
+                config.retry = RetryPolicy::new(attempts, delay).map_err(|e| e.to_string())?;
+            }
+            "--retry-delay" => {
+                let raw = value()?;
+                config.retry.delay = Duration::from_millis(raw.parse().map_err(|_| format!("Invalid retry delay: {}", raw))?);
+            }
+            "--strict" => config.strict = true,
+            "--mmap-output" => config.mmap_output = true,
+            "--sparse" => config.sparse = true,
+            "--dry-run" => config.dry_run = true,
+            "--durable" => config.durable = true,
+            "--overwrite" => config.overwrite = OverwritePolicy::parse(value()?).map_err(|e| e.to_string())?,
+            "--safe-mode" => config.safe_mode = true,
+            "--temp-dir" => config.temp_dir = Some(paths::native(value()?.clone())),
+            "--no-fast-temp" => config.use_fast_temp_dir = false,
+            flag if flag.starts_with('-') && flag.len() > 1 => return Err(format!("Unknown option: {}", flag)),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let name = positional.next().ok_or("Missing command")?;
+    let rest: Vec<PathBuf> = positional.map(paths::native).collect();
+    let command = match (name.as_str(), <[PathBuf; 3]>::try_from(rest.clone())) {
+        ("diff", Ok([old, new, patch])) => Command::Diff { old, new, patch },
+        ("patch", Ok([old, new, patch])) => Command::Patch { old, new, patch },
+        ("verify", Ok([old, new, patch])) => Command::Verify { old, new, patch },
+        ("preflight", Ok([old, new, patch])) => Command::Preflight { old, new, patch },
+        ("info", _) if rest.len() == 1 => Command::Info { patch: rest[0].clone() },
+        ("report", _) if rest.len() == 2 => Command::Report { old: rest[0].clone(), new: rest[1].clone() },
+        ("diff" | "patch" | "verify" | "info" | "report" | "preflight", _) => return Err(format!("Wrong number of arguments for {}", name)),
+        (other, _) => return Err(format!("Unknown command: {}", other)),
+    };
+    // report 在内存中运行 diff、preflight 预检 diff，同样接受 diff 选项
+    if let (Some(flag), false) = (diff_only, matches!(command, Command::Diff { .. } | Command::Report { .. } | Command::Preflight { .. })) {
+        return Err(format!("{} only applies to diff", flag));
+    }
+    if config.deterministic && config.auto_level.is_some() {
+        return Err("--level auto depends on machine speed and cannot be combined with --deterministic".to_string());
+    }
+    Ok((command, config))
+}
+
+/// 执行子命令；verify 不匹配时返回 `Ok(false)`
+fn run(command: Command, config: &OptimizationConfig) -> Result<bool, Box<dyn std::error::Error>> {
+    match command {
+        Command::Diff { old, new, patch } => {
+            BsdiffRust::diff_optimized(&old, &new, &patch, config)?;
+            if let Some(level) = config.auto_level.as_ref().and_then(AutoLevel::chosen) {
+                println!("compression level: {}", level);
+            }
+        }
+        Command::Patch { old, new, patch } => {
+            let size = BsdiffRust::patch_optimized(&old, &new, &patch, config)?;
+            if config.dry_run {
+                println!("dry run OK: would write {} bytes to {}", size, new.display());
+            }
+        }
+        Command::Verify { old, new, patch } => {
+            let ok = verify_patch(&old, &new, &patch, config)?;
+            println!("{}", if ok { "OK" } else { "MISMATCH" });
+            return Ok(ok);
+        }
+        Command::Info { patch } => print_info(&get_patch_info(&patch)?),
+        Command::Report { old, new } => print!("{}", report::diff_report(&old, &new, config)?.to_json()),
+        Command::Preflight { old, new, patch } => {
+            let report = preflight::preflight(&old, &new, &patch, config)?;
+            print_preflight(&report);
+            return Ok(report.sufficient_space && report.mmap);
+        }
+        Command::Help => println!("{}", USAGE),
+    }
+    Ok(true)
+}
+
+fn print_info(info: &PatchInfo) {
+    let optional = |value: Option<u64>| value.map_or_else(|| "-".to_string(), |v| v.to_string());
+    println!("size:           {}", info.size);
+    println!("container:      {}", info.container);
+    println!("format_version: {}", optional(info.format_version.map(u64::from)));
+    println!("compression:    {}", info.compression);
+    println!("flags:          {:#04x}", info.flags);
+    println!("bsdiff_version: {}", info.bsdiff_version.as_deref().unwrap_or("-"));
+    println!("source_size:    {}", optional(info.source_size));
+    println!("target_size:    {}", optional(info.target_size));
+    println!("uncompressed:   {}", optional(info.uncompressed_size));
+    println!("source_sha256:  {}", info.source_sha256.as_deref().unwrap_or("-"));
+    println!("target_sha256:  {}", info.target_sha256.as_deref().unwrap_or("-"));
+    println!("hash_algorithm: {}", info.hash_algorithm.unwrap_or("-"));
+    println!("dictionary_id:  {}", optional(info.dictionary_id.map(u64::from)));
+    println!("full_file:      {}", info.full_file);
+    for (key, value) in &info.metadata {
+        println!("metadata:       {}={}", key, value);
+    }
+}
+
+fn print_preflight(report: &Preflight) {
+    let optional = |value: Option<u64>| value.map_or_else(|| "-".to_string(), |v| v.to_string());
+    let temp_dir = report.temp_dir.as_ref().map_or_else(|| "-".to_string(), |dir| dir.display().to_string());
+    println!("temp_dir:          {}", temp_dir);
+    println!("temp_dir_free:     {}", optional(report.temp_dir_free));
+    println!("destination_dir:   {}", report.destination_dir.display());
+    println!("destination_free:  {}", optional(report.destination_free));
+    println!("patch_size (est.): {}", report.estimated_patch_size);
+    println!("memory (est.):     {}", report.estimated_peak_memory);
+    println!("mmap:              {}", report.mmap);
+    println!("sufficient_space:  {}", report.sufficient_space);
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (command, config) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            eprintln!("bsdiff-rs: {}\n\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    set_logger(Some(Arc::new(|level: LogLevel, message: &str| {
+        eprintln!("bsdiff-rs: {}: {}", level.as_str(), message);
+    })));
+
+    match run(command, &config) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("bsdiff-rs: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<(Command, OptimizationConfig), String> {
+        parse_args(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let (command, config) = parse(&["diff", "a", "b", "p", "--format", "bsdiff40", "--level", "9"]).unwrap();
+        assert_eq!(command, Command::Diff { old: "a".into(), new: "b".into(), patch: "p".into() });
+        assert_eq!(config.format, PatchFormat::Bsdiff40);
+        assert_eq!(config.compression_level, 9);
+
+        let (_, config) = parse(&["diff", "a", "b", "p", "--compression", "none"]).unwrap();
+        assert_eq!(config.compression, Compression::None);
+        assert!(parse(&["diff", "a", "b", "p", "--compression", "lz4"]).is_err());
+        if cfg!(feature = "zlib") {
+            assert_eq!(parse(&["diff", "a", "b", "p", "--compression", "gzip"]).unwrap().1.compression, Compression::Gzip);
+        }
+        assert_eq!(parse(&["diff", "a", "b", "p", "--window", "1048576"]).unwrap().1.window_size, Some(1 << 20));
+        assert!(parse(&["diff", "a", "b", "p", "--window", "0"]).is_err());
+        assert_eq!(parse(&["diff", "a", "b", "p", "--volume-size", "4096"]).unwrap().1.volume_size, Some(4096));
+        assert!(parse(&["patch", "a", "b", "p", "--volume-size", "4096"]).is_err());
+        assert_eq!(parse(&["diff", "a", "b", "p", "--threads", "4"]).unwrap().1.threads, Some(4));
+        assert!(parse(&["diff", "a", "b", "p", "--timeout", "30000"]).unwrap().1.cancel.is_some());
+        assert!(parse(&["diff", "a", "b", "p", "--timeout", "0"]).is_err());
+        assert!(parse(&["patch", "a", "b", "p", "--timeout", "30000"]).is_err());
+        let (_, config) = parse(&["diff", "a", "b", "p", "--metadata", "channel=beta", "--metadata", "note=a=b"]).unwrap();
+        assert_eq!(config.metadata.get("channel").map(String::as_str), Some("beta"));
+        assert_eq!(config.metadata.get("note").map(String::as_str), Some("a=b"));
+        assert!(parse(&["diff", "a", "b", "p", "--metadata", "novalue"]).is_err());
+        assert!(parse(&["diff", "a", "b", "p", "--exe-transform"]).unwrap().1.exe_transform);
+        assert!(parse(&["patch", "a", "b", "p", "--exe-transform"]).is_err());
+        assert!(parse(&["diff", "a", "b", "p", "--archive-transform"]).unwrap().1.archive_transform);
+        assert!(parse(&["patch", "a", "b", "p", "--archive-transform"]).is_err());
+        assert!(parse(&["diff", "a", "b", "p", "--deterministic"]).unwrap().1.deterministic);
+        assert_eq!(parse(&["diff", "a", "b", "p", "--hash", "blake3"]).unwrap().1.hash_algorithm, HashAlgorithm::Blake3);
+        assert!(parse(&["diff", "a", "b", "p", "--hash", "md5"]).is_err());
+        assert!(parse(&["verify", "a", "b", "p", "--hash", "blake3"]).is_err());
+        assert_eq!(parse(&["diff", "a", "b", "p", "--algorithm", "blockdelta"]).unwrap().1.algorithm, DiffAlgorithm::BlockDelta);
+        assert!(parse(&["patch", "a", "b", "p", "--algorithm", "blockdelta"]).is_err());
+
+        let key_file = tempfile::NamedTempFile::new().unwrap();
+        let key_path = key_file.path().to_str().unwrap();
+        std::fs::write(key_path, [7u8; 32]).unwrap();
+        assert!(parse(&["diff", "a", "b", "p", "--key-file", key_path]).unwrap().1.encryption_key.is_some());
+        assert!(parse(&["patch", "a", "b", "p", "--key-file", key_path]).unwrap().1.encryption_key.is_some());
+        std::fs::write(key_path, [7u8; 16]).unwrap();
+        assert!(parse(&["diff", "a", "b", "p", "--key-file", key_path]).is_err());
+        assert!(parse(&["diff", "a", "b", "p", "--key-file", "/nonexistent/key"]).is_err());
+        let dictionary = parse(&["patch", "a", "b", "p", "--dictionary", key_path]).unwrap().1.dictionary;
+        assert_eq!(dictionary, Some(Dictionary::new(&[7u8; 16]).unwrap()));
+
+        let (command, config) = parse(&["patch", "--strict", "a", "b", "p", "--mmap-output"]).unwrap();
+        assert_eq!(command, Command::Patch { old: "a".into(), new: "b".into(), patch: "p".into() });
+        assert!(config.strict);
+        assert!(config.mmap_output);
+        assert!(parse(&["patch", "a", "b", "p", "--dry-run"]).unwrap().1.dry_run);
+        assert!(parse(&["patch", "a", "b", "p", "--durable"]).unwrap().1.durable);
+        let log_path = key_file.path().with_extension("log");
+        assert!(parse(&["patch", "a", "b", "p", "--log-file", log_path.to_str().unwrap()]).unwrap().1.audit_log.is_some());
+        assert!(parse(&["patch", "a", "b", "p", "--log-file", "/nonexistent/audit.log"]).is_err());
+        let _ = std::fs::remove_file(log_path);
+        assert!(parse(&["patch", "a", "b", "p", "--sparse"]).unwrap().1.sparse);
+        let (_, config) = parse(&["patch", "a", "b", "p", "--max-output", "4096", "--max-memory", "65536"]).unwrap();
+        assert_eq!((config.max_output_size, config.max_memory), (Some(4096), Some(65536)));
+        assert!(parse(&["patch", "a", "b", "p", "--max-memory", "0"]).is_err());
+        let write_limit = parse(&["patch", "a", "b", "p", "--max-write-rate", "1048576"]).unwrap().1.write_limit;
+        assert_eq!(write_limit.map(|limit| limit.bytes_per_sec()), Some(1 << 20));
+        assert!(parse(&["patch", "a", "b", "p", "--max-write-rate", "0"]).is_err());
+        let retry = parse(&["patch", "a", "b", "p", "--retry-delay", "50", "--retry", "3"]).unwrap().1.retry;
+        assert_eq!(retry, RetryPolicy::new(3, Duration::from_millis(50)).unwrap());
+        assert_eq!(parse(&["diff", "a", "b", "p", "--retry", "4"]).unwrap().1.retry.attempts, 4);
+        assert!(parse(&["patch", "a", "b", "p", "--retry", "0"]).is_err());
+        assert_eq!(parse(&["diff", "a", "b", "p", "--cache-dir", "/tmp/c"]).unwrap().1.cache_dir, Some("/tmp/c".into()));
+        assert!(parse(&["patch", "a", "b", "p", "--cache-dir", "/tmp/c"]).is_err());
+        assert_eq!(parse(&["diff", "a", "b", "p", "--fallback-to-full", "0.8"]).unwrap().1.fallback_to_full, Some(0.8));
+        assert!(parse(&["diff", "a", "b", "p", "--fallback-to-full", "0"]).is_err());
+        assert!(parse(&["patch", "a", "b", "p", "--fallback-to-full", "0.8"]).is_err());
+        assert_eq!(parse(&["patch", "a", "b", "p", "--overwrite", "backup"]).unwrap().1.overwrite, OverwritePolicy::Backup);
+        assert!(parse(&["diff", "a", "b", "p", "--overwrite", "skip"]).is_err());
+        assert_eq!(parse(&["diff", "a", "b", "p", "--temp-dir", "/tmp/x"]).unwrap().1.temp_dir, Some("/tmp/x".into()));
+        assert!(parse(&["diff", "a", "b", "p", "--temp-dir"]).is_err());
+
+        assert_eq!(parse(&["info", "p"]).unwrap().0, Command::Info { patch: "p".into() });
+        let (command, config) = parse(&["report", "a", "b", "--algorithm", "blockdelta"]).unwrap();
+        assert_eq!(command, Command::Report { old: "a".into(), new: "b".into() });
+        assert_eq!(config.algorithm, DiffAlgorithm::BlockDelta);
+        assert!(parse(&["report", "a"]).is_err());
+        let (command, config) = parse(&["preflight", "a", "b", "p", "--window", "4096"]).unwrap();
+        assert_eq!(command, Command::Preflight { old: "a".into(), new: "b".into(), patch: "p".into() });
+        assert_eq!(config.window_size, Some(4096));
+        assert!(parse(&["preflight", "a", "b"]).is_err());
+        assert_eq!(parse(&["--help"]).unwrap().0, Command::Help);
+
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["info"]).is_err());
+        assert!(parse(&["diff", "a", "b"]).is_err());
+        assert!(parse(&["diff", "a", "b", "p", "--level", "99"]).is_err());
+        assert!(parse(&["diff", "a", "b", "p", "--level", "auto"]).unwrap().1.auto_level.is_some());
+        assert!(parse(&["diff", "a", "b", "p", "--level", "auto", "--deterministic"]).is_err());
+        assert!(parse(&["patch", "a", "b", "p", "--format", "zstd"]).is_err());
+        assert!(parse(&["verify", "a", "b", "p", "--bogus"]).is_err());
+        assert!(parse(&["merge", "a"]).is_err());
+    }
+}
@@ -0,0 +1,3360 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use napi::bindgen_prelude::*;
+use napi::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi_derive::napi;
+
+use crate::{ab_update, block_delta, bsdiff40, bundle, dictionary, dir_diff, fd, header, http, logger, manifest, patch_chunks, patch_set, paths, preflight, prepared, progress, report, shm, signing, similarity};
+use crate::array_buffer::OutputArrayBuffer;
+use crate::error::{BsdiffError, ErrorCode};
+use crate::stream::{StreamSink, StreamSinkJs, StreamSource, StreamSourceJs, STREAM_CHUNK_SIZE};
+use crate::utils::{self, verify_patch as verify_patch_util, verify_patch_against_hash as verify_patch_against_hash_util, get_patch_info as get_patch_info_util, get_patch_info_from_bytes, get_diff_stats, DiffStats, get_file_size, check_file_access, get_compression_ratio, algorithm_info, validate_patch_self, check_file_access_detailed};
+use crate::{AuditLog, AutoLevel, BsdiffRust, CancelToken, Dictionary, DEFAULT_FALLBACK_THRESHOLD, DiffAlgorithm, DiffMode, EncryptionKey, HashAlgorithm, LinkMode, OptimizationConfig, OverwritePolicy, RetryPolicy, WriteLimit};
+
+/// JavaScript 日志回调 `(level, message) => void`：以 `FnArgs` 展开为两个参数 (元组本身会作为一个数组传入)
+type LogCallbackJs = ThreadsafeFunction<FnArgs<(String, String)>, (), FnArgs<(String, String)>, Status, false, true>;
+
+/// JavaScript diff 进度回调 `({ processed, total, percent }) => void`
+type DiffProgressCallbackJs = ThreadsafeFunction<DiffProgressJs, (), DiffProgressJs, Status, false, true>;
+
+/// JavaScript patch 进度回调 `({ blocksApplied, totalBlocks, percent }) => void`
+type PatchProgressCallbackJs = ThreadsafeFunction<PatchProgressJs, (), PatchProgressJs, Status, false, true>;
+
+/// JavaScript 分阶段进度回调 `({ phase, bytesDone, bytesTotal, etaMs }) => void`
+type ProgressEventCallbackJs = ThreadsafeFunction<ProgressEventJs, (), ProgressEventJs, Status, false, true>;
+
+/// JavaScript 审计事件回调 `(event: string) => void`，参数为一行 JSON
+type AuditCallbackJs = ThreadsafeFunction<String, (), String, Status, false, true>;
+
+/// 将 JavaScript 分阶段进度回调包装为内部回调
+fn phase_callback(tsfn: ProgressEventCallbackJs) -> progress::PhaseCallback {
+  Arc::new(move |event: &progress::ProgressEvent| {
+    tsfn.call(
+      ProgressEventJs {
+        phase: event.phase.name().to_string(),
+        bytes_done: event.bytes_done as f64,
+        bytes_total: event.bytes_total as f64,
+        eta_ms: event.eta_ms.map(|eta| eta as f64),
+      },
+      ThreadsafeFunctionCallMode::NonBlocking,
+    );
+  })
+}
+
+/// `old_str` 为 `None` 时不依赖旧文件，生成只含新增数据的完整补丁 (用于新增文件)
+fn call_bsdiff(
+  old_str: Option<&Path>,
+  new_str: &Path,
+  patch: &fd::FileArg,
+  config: &OptimizationConfig,
+) -> Result<()> {
+  match (old_str, patch) {
+    (_, fd::FileArg::Fd(out)) => fd::diff_to_fd(old_str, new_str, out, config),
+    (Some(old_str), fd::FileArg::Path(patch)) => BsdiffRust::diff_optimized(old_str, new_str, patch, config),
+    (None, fd::FileArg::Path(patch)) => BsdiffRust::create_full_patch(new_str, patch, config),
+  }
+  .map_err(napi_error)
+}
+
+/// 路径参数：字符串，或以原始字节给出路径的 `Buffer` (用于非 UTF-8 文件名)
+type PathLike = Either<String, Buffer>;
+
+/// 解析路径参数并按平台规范化 (见 [`paths`])
+fn path_arg(path: &PathLike) -> Result<PathBuf> {
+  match path {
+    Either::A(path) => Ok(paths::native(path.clone())),
+    Either::B(path) => paths::from_bytes(path).map_err(|e| Error::new(Status::InvalidArg, e.to_string())),
+  }
+}
+
+/// 返回给 JavaScript 的路径：能表示为字符串时为字符串，否则为原始字节的 `Buffer`
+fn path_js(path: PathBuf) -> PathLike {
+  match path.into_os_string().into_string() {
+    Ok(path) => Either::A(path),
+    Err(path) => Either::B(paths::into_bytes(path).into()),
+  }
+}
+
+/// 补丁/输出参数：路径 (字符串或 `Buffer`)、文件描述符，或 `fs.promises.FileHandle` (读取其 `fd` 属性)
+type FileArg<'a> = Either4<String, Buffer, i64, Object<'a>>;
+
+/// 解析补丁/输出参数；描述符在 JS 线程上立即复制，之后调用方关闭自己的描述符也不受影响
+fn file_arg(arg: FileArg) -> Result<fd::FileArg> {
+  let fd = match arg {
+    Either4::A(path) => return Ok(fd::FileArg::Path(paths::native(path))),
+    Either4::B(path) => return path_arg(&Either::B(path)).map(fd::FileArg::Path),
+    Either4::C(fd) => fd,
+    Either4::D(handle) => handle
+      .get_named_property::<Option<i64>>("fd")?
+      .ok_or_else(|| Error::new(Status::InvalidArg, "Expected a path, a file descriptor or a FileHandle".to_string()))?,
+  };
+  fd::dup_fd(fd).map(fd::FileArg::Fd).map_err(|e| Error::new(Status::InvalidArg, e.to_string()))
+}
+
+/// diff 的补丁输出参数；stats 需要重新读取补丁，只支持路径
+fn patch_output_arg(arg: FileArg, stats: bool) -> Result<fd::FileArg> {
+  match file_arg(arg)? {
+    fd::FileArg::Fd(_) if stats => Err(Error::new(Status::InvalidArg, "stats needs a patch path, not a file descriptor".to_string())),
+    patch => Ok(patch),
+  }
+}
+
+/// 将核心错误转换为 napi 错误；可归类的错误码暂存在 `cause` 中，
+/// 回到 JS 线程后由 [`with_code`] 转为 Error 对象的 `code` 属性
+fn napi_error(e: BsdiffError) -> Error {
+  let mut error = Error::from_reason(e.to_string());
+  if let Some(code) = e.code() {
+    error.set_cause(Error::new(Status::GenericFailure, code.as_str()));
+  }
+  error
+}
+
+/// 在 JS 线程上展开 [`napi_error`] 暂存的错误码：构造 `code` 为 `ERR_*` 的 Error 对象，
+/// 其他错误 (如参数错误) 原样返回
+fn with_code(env: &Env, mut err: Error) -> Error {
+  let Some(code) = err.cause.as_ref().and_then(|cause| ErrorCode::parse(&cause.reason)) else {
+    return err;
+  };
+  err.cause = None;
+  let coded = Error::new(code.as_str(), std::mem::take(&mut err.reason));
+  Error::from(JsError::from(coded).into_unknown(*env))
+}
+
+/// 同步 API 的错误转换：[`napi_error`] + [`with_code`]
+fn js_error(env: &Env, e: BsdiffError) -> Error {
+  with_code(env, napi_error(e))
+}
+
+/// 将 JavaScript diff 选项转换为内部配置
+fn diff_config(options: Option<DiffOptions>) -> Result<OptimizationConfig> {
+  let mut config = OptimizationConfig::default();
+  if let Some(options) = options {
+    if let Some(mode) = options.mode {
+      config.mode = match mode.as_str() {
+        "full" => DiffMode::Full,
+        "append" => DiffMode::Append,
+        other => return Err(Error::new(Status::InvalidArg, format!("Unknown diff mode: {}", other))),
+      };
+    }
+    if let Some(safe_mode) = options.safe_mode {
+      config.safe_mode = safe_mode;
+    }
+    match options.compression_level {
+      Some(Either::A(level)) => config.compression_level = check_compression_level(level)?,
+      Some(Either::B(level)) if level == "auto" => config.auto_level = Some(AutoLevel::new()),
+      Some(Either::B(level)) => {
+        return Err(Error::new(Status::InvalidArg, format!("Invalid compression level: {}", level)));
+      }
+      None => {}
+    }
+    if let Some(use_fast_temp_dir) = options.use_fast_temp_dir {
+      config.use_fast_temp_dir = use_fast_temp_dir;
+    }
+    if let Some(temp_dir) = options.temp_dir {
+      config.temp_dir = Some(path_arg(&temp_dir)?);
+    }
+    if let Some(format) = options.format {
+      config.format = bsdiff40::PatchFormat::parse(&format).map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?;
+    }
+    if let Some(compression) = options.compression {
+      config.compression = header::Compression::parse(&compression).map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?;
+    }
+    if let Some(window_size) = options.window_size {
+      config.window_size = Some(
+        u64::try_from(window_size)
+          .ok()
+          .filter(|size| *size > 0)
+          .ok_or_else(|| Error::new(Status::InvalidArg, format!("Invalid window size: {}", window_size)))?,
+      );
+    }
+    if let Some(threads) = options.threads {
+      if threads == 0 {
+        return Err(Error::new(Status::InvalidArg, "threads must be at least 1".to_string()));
+      }
+      config.threads = Some(threads as usize);
+    }
+    if let Some(metadata) = options.metadata {
+      config.metadata = metadata.into_iter().collect();
+      let len = header::metadata_to_json(&config.metadata).len();
+      if len > header::MAX_METADATA_LEN {
+        return Err(Error::new(
+          Status::InvalidArg,
+          format!("metadata is {} bytes as JSON, the limit is {}", len, header::MAX_METADATA_LEN),
+        ));
+      }
+    }
+    if let Some(exe_transform) = options.exe_transform {
+      config.exe_transform = exe_transform;
+    }
+    if let Some(archive_transform) = options.archive_transform {
+      config.archive_transform = archive_transform;
+    }
+    if let Some(dedup) = options.dedup {
+      config.dedup = dedup;
+    }
+    if let Some(preserve_metadata) = options.preserve_metadata {
+      config.preserve_metadata = preserve_metadata;
+    }
+    if let Some(preserve_mtimes) = options.preserve_mtimes {
+      config.preserve_mtimes = preserve_mtimes;
+    }
+    if let Some(volume_size) = options.volume_size {
+      if options.stats == Some(true) {
+        return Err(Error::new(Status::InvalidArg, "stats is not available for multi-volume patches".to_string()));
+      }
+      config.volume_size = Some(byte_limit(volume_size, "volumeSize")?);
+    }
+    if let Some(encrypt) = options.encrypt {
+      if options.stats == Some(true) {
+        return Err(Error::new(Status::InvalidArg, "stats is not available for encrypted patches".to_string()));
+      }
+      config.encryption_key =
+        Some(EncryptionKey::new(&encrypt.key).map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?);
+    }
+    if let Some(dictionary) = options.dictionary {
+      config.dictionary = Some(Dictionary::new(&dictionary).map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?);
+    }
+    if let Some(deterministic) = options.deterministic {
+      config.deterministic = deterministic;
+    }
+    if let Some(hash_algorithm) = options.hash_algorithm {
+      config.hash_algorithm =
+        HashAlgorithm::parse(&hash_algorithm).map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?;
+    }
+    if let Some(algorithm) = options.algorithm {
+      config.algorithm = DiffAlgorithm::parse(&algorithm).map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?;
+    }
+    if let Some(timeout_ms) = options.timeout_ms {
+      let timeout = u64::try_from(timeout_ms)
+        .ok()
+        .filter(|timeout| *timeout > 0)
+        .ok_or_else(|| Error::new(Status::InvalidArg, format!("Invalid timeoutMs: {}", timeout_ms)))?;
+      config.cancel = Some(CancelToken::new().with_timeout(Duration::from_millis(timeout)));
+    }
+    if let Some(overwrite) = options.overwrite {
+      config.overwrite = OverwritePolicy::parse(&overwrite).map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?;
+    }
+    if let Some(retry) = options.retry {
+      config.retry = retry_policy(retry)?;
+    }
+    if let Some(cache_dir) = options.cache_dir {
+      config.cache_dir = Some(path_arg(&cache_dir)?);
+    }
+    config.fallback_to_full = match options.fallback_to_full {
+      None | Some(Either::A(false)) => None,
+      Some(Either::A(true)) => Some(DEFAULT_FALLBACK_THRESHOLD),
+      Some(Either::B(threshold)) if threshold.is_finite() && threshold > 0.0 => Some(threshold),
+      Some(Either::B(threshold)) => return Err(Error::new(Status::InvalidArg, format!("Invalid fallbackToFull: {}", threshold))),
+    };
+    if config.fallback_to_full.is_some() && config.format != bsdiff40::PatchFormat::Zstd {
+      return Err(Error::new(Status::InvalidArg, "fallbackToFull requires the zstd format".to_string()));
+    }
+    if config.deterministic && config.auto_level.is_some() {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "compressionLevel 'auto' depends on machine speed and cannot be combined with deterministic".to_string(),
+      ));
+    }
+    config.progress = options.on_progress.map(|tsfn| {
+      Arc::new(move |processed: u64, total: u64| {
+        tsfn.call(
+          DiffProgressJs { processed: processed as f64, total: total as f64, percent: percent(processed, total) },
+          ThreadsafeFunctionCallMode::NonBlocking,
+        );
+      }) as progress::ProgressCallback
+    });
+    config.phase_progress = options.on_progress_event.map(phase_callback);
+  }
+  Ok(config)
+}
+
+/// 进度百分比 (总量为 0 时视为已完成)
+fn percent(done: u64, total: u64) -> f64 {
+  if total == 0 {
+    100.0
+  } else {
+    done as f64 * 100.0 / total as f64
+  }
+}
+
+/// 将 JavaScript patch 选项转换为内部配置
+fn patch_config(options: Option<PatchOptions>) -> Result<OptimizationConfig> {
+  let mut config = OptimizationConfig::default();
+  let Some(options) = options else {
+    return Ok(config);
+  };
+  if let Some(safe_mode) = options.safe_mode {
+    config.safe_mode = safe_mode;
+  }
+  if let Some(strict) = options.strict {
+    config.strict = strict;
+  }
+  if let Some(mmap_output) = options.mmap_output {
+    config.mmap_output = mmap_output;
+  }
+  if let Some(sparse) = options.sparse {
+    config.sparse = sparse;
+  }
+  if let Some(dry_run) = options.dry_run {
+    config.dry_run = dry_run;
+  }
+  if let Some(durable) = options.durable {
+    config.durable = durable;
+  }
+  if let Some(temp_dir) = options.temp_dir {
+    config.temp_dir = Some(path_arg(&temp_dir)?);
+  }
+  if let Some(base_dir) = options.base_dir {
+    config.base_dir = Some(path_arg(&base_dir)?);
+  }
+  config.max_output_size = options.max_output_size.map(|size| byte_limit(size, "maxOutputSize")).transpose()?;
+  config.max_memory = options.max_memory.map(|size| byte_limit(size, "maxMemory")).transpose()?;
+  if let Some(rate) = options.max_write_bytes_per_sec {
+    let rate = byte_limit(rate, "maxWriteBytesPerSec")?;
+    config.write_limit = Some(WriteLimit::new(rate).map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?);
+  }
+  if let Some(overwrite) = options.overwrite {
+    config.overwrite = OverwritePolicy::parse(&overwrite).map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?;
+  }
+  if let Some(retry) = options.retry {
+    config.retry = retry_policy(retry)?;
+  }
+  if let Some(link_mode) = options.link_mode {
+    config.link_mode = LinkMode::parse(&link_mode).map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?;
+  }
+  if let Some(key) = options.decryption_key {
+    config.encryption_key = Some(EncryptionKey::new(&key).map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?);
+  }
+  if let Some(dictionary) = options.dictionary {
+    config.dictionary = Some(Dictionary::new(&dictionary).map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?);
+  }
+  config.signature = match (options.public_key, options.signature) {
+    (Some(public_key), Some(signature)) => Some(
+      signing::PatchSignature::new(&public_key, &signature).map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?,
+    ),
+    (None, None) => None,
+    _ => return Err(Error::new(Status::InvalidArg, "publicKey and signature must be provided together".to_string())),
+  };
+  config.block_progress = options.on_progress.map(|tsfn| {
+    Arc::new(move |blocks_applied: u64, total_blocks: u64| {
+      tsfn.call(
+        PatchProgressJs {
+          blocks_applied: blocks_applied as f64,
+          total_blocks: total_blocks as f64,
+          percent: percent(blocks_applied, total_blocks),
+        },
+        ThreadsafeFunctionCallMode::NonBlocking,
+      );
+    }) as progress::ProgressCallback
+  });
+  config.phase_progress = options.on_progress_event.map(phase_callback);
+  let log_file = match options.log_file {
+    Some(log_file) => {
+      let log_file = path_arg(&log_file)?;
+      let audit_log = AuditLog::file(&log_file)
+        .map_err(|e| Error::new(Status::InvalidArg, format!("Cannot open log file {}: {}", log_file.display(), e)))?;
+      Some(audit_log)
+    }
+    None => None,
+  };
+  let on_audit_event = options.on_audit_event.map(|tsfn| {
+    AuditLog::callback(Arc::new(move |line: &str| {
+      tsfn.call(line.to_string(), ThreadsafeFunctionCallMode::NonBlocking);
+    }))
+  });
+  config.audit_log = match (log_file, on_audit_event) {
+    (Some(file), Some(callback)) => Some(file.and(callback)),
+    (file, callback) => file.or(callback),
+  };
+  Ok(config)
+}
+
+/// 校验 JavaScript 传入的字节数上限 (正整数)
+fn byte_limit(value: i64, name: &str) -> Result<u64> {
+  u64::try_from(value)
+    .ok()
+    .filter(|limit| *limit > 0)
+    .ok_or_else(|| Error::new(Status::InvalidArg, format!("Invalid {}: {}", name, value)))
+}
+
+/// 转换重试选项：未指定的字段取 Windows 默认值 (共 5 次，首次等待 100ms)
+fn retry_policy(options: RetryOptionsJs) -> Result<RetryPolicy> {
+  RetryPolicy::new(options.attempts.unwrap_or(5), Duration::from_millis(options.delay_ms.unwrap_or(100).into()))
+    .map_err(|e| Error::new(Status::InvalidArg, e.to_string()))
+}
+
+/// 校验 Zstd 压缩级别
+fn check_compression_level(level: i32) -> Result<i32> {
+  if !zstd::compression_level_range().contains(&level) {
+    return Err(Error::new(Status::InvalidArg, format!("Invalid compression level: {}", level)));
+  }
+  Ok(level)
+}
+
+/// DiffOptions 中是否要求返回 diff 统计
+fn wants_stats(options: &Option<DiffOptions>) -> bool {
+  options.as_ref().and_then(|options| options.stats).unwrap_or(false)
+}
+
+/// 按需统计刚生成的补丁
+fn collect_stats(patch: &Path, enabled: bool, dictionary: Option<&Dictionary>) -> Result<Option<DiffStats>> {
+  if !enabled {
+    return Ok(None);
+  }
+  get_diff_stats(patch, dictionary).map(Some).map_err(napi_error)
+}
+
+/// `old_str` 为 `None` 时应用不依赖旧文件的完整补丁；补丁或输出为描述符时走 [`fd::patch_with`]
+fn call_bspatch(
+  old_str: Option<&Path>,
+  new_str: &fd::FileArg,
+  patch: &fd::FileArg,
+  config: &OptimizationConfig,
+) -> Result<u64> {
+  match (old_str, new_str, patch) {
+    (Some(old_str), fd::FileArg::Path(new_str), fd::FileArg::Path(patch)) => BsdiffRust::patch_optimized(old_str, new_str, patch, config),
+    (None, fd::FileArg::Path(new_str), fd::FileArg::Path(patch)) => BsdiffRust::apply_full_patch(new_str, patch, config),
+    _ => fd::patch_with(old_str, new_str, patch, config),
+  }
+  .map_err(napi_error)
+}
+
+#[napi]
+pub fn diff_sync(
+  env: Env,
+  old_str: Option<PathLike>,
+  new_str: PathLike,
+  patch: FileArg,
+  options: Option<DiffOptions>,
+) -> Result<Option<DiffStatsJs>> {
+  let old_str = old_str.as_ref().map(path_arg).transpose()?;
+  let new_str = path_arg(&new_str)?;
+  let stats = wants_stats(&options);
+  let patch = patch_output_arg(patch, stats)?;
+  let config = diff_config(options)?;
+  call_bsdiff(old_str.as_deref(), &new_str, &patch, &config)
+    .and_then(|()| patch_stats(&patch, stats, config.dictionary.as_ref()))
+    .map(|stats| stats.map(|stats| diff_stats_js(stats, &config)))
+    .map_err(|e| with_code(&env, e))
+}
+
+/// 补丁以路径给出时按需统计 (描述符输出已在 [`patch_output_arg`] 中拒绝 stats)
+fn patch_stats(patch: &fd::FileArg, enabled: bool, dictionary: Option<&Dictionary>) -> Result<Option<DiffStats>> {
+  match patch {
+    fd::FileArg::Path(patch) => collect_stats(patch, enabled, dictionary),
+    fd::FileArg::Fd(_) => Ok(None),
+  }
+}
+
+#[napi]
+pub fn patch_sync(
+  env: Env,
+  old_str: Option<PathLike>,
+  new_str: FileArg,
+  patch: FileArg,
+  options: Option<PatchOptions>,
+) -> Result<f64> {
+  let old_str = old_str.as_ref().map(path_arg).transpose()?;
+  call_bspatch(old_str.as_deref(), &file_arg(new_str)?, &file_arg(patch)?, &patch_config(options)?)
+    .map(|size| size as f64)
+    .map_err(|e| with_code(&env, e))
+}
+
+/// 只对新旧文件中的指定区间 (偏移 + 长度) 生成补丁，例如磁盘镜像中的某个分区
+#[napi]
+#[allow(clippy::too_many_arguments)]
+pub fn diff_range_sync(
+  env: Env,
+  old_str: PathLike,
+  old_offset: i64,
+  old_length: i64,
+  new_str: PathLike,
+  new_offset: i64,
+  new_length: i64,
+  patch: PathLike,
+  options: Option<DiffOptions>,
+) -> Result<Option<DiffStatsJs>> {
+  let (old_str, new_str, patch) = (path_arg(&old_str)?, path_arg(&new_str)?, path_arg(&patch)?);
+  let (old_range, new_range) = (file_range(old_offset, old_length)?, file_range(new_offset, new_length)?);
+  let stats = wants_stats(&options);
+  let config = diff_config(options)?;
+  BsdiffRust::diff_range(&old_str, old_range, &new_str, new_range, &patch, &config)
+    .map_err(napi_error)
+    .and_then(|()| collect_stats(&patch, stats, config.dictionary.as_ref()))
+    .map(|stats| stats.map(|stats| diff_stats_js(stats, &config)))
+    .map_err(|e| with_code(&env, e))
+}
+
+fn file_range(offset: i64, length: i64) -> Result<(u64, u64)> {
+  match (u64::try_from(offset), u64::try_from(length)) {
+    (Ok(offset), Ok(length)) => Ok((offset, length)),
+    _ => Err(Error::new(Status::InvalidArg, format!("Invalid file range: offset {}, length {}", offset, length))),
+  }
+}
+
+/// 从 http:// 或 https:// URL 流式下载补丁并应用到旧文件，返回新文件大小
+#[napi]
+pub fn patch_from_url_sync(
+  env: Env,
+  old_str: PathLike,
+  url: String,
+  new_str: PathLike,
+  url_options: Option<UrlOptionsJs>,
+  options: Option<PatchOptions>,
+) -> Result<f64> {
+  let old_str = path_arg(&old_str)?;
+  let new_str = path_arg(&new_str)?;
+  let config = patch_config(options)?;
+  let url_options = url_options.map(http::HttpOptions::try_from).transpose()?.unwrap_or_default();
+  http::patch_from_url(&old_str, &url, &new_str, &url_options, &config)
+    .map(|size| size as f64)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 在内存中生成补丁，返回补丁数据
+#[napi]
+pub fn diff_buffer_sync(env: Env, old: Buffer, new: Buffer, options: Option<DiffOptions>) -> Result<Buffer> {
+  BsdiffRust::diff_bytes(&old, &new, &diff_config(options)?)
+    .map(Buffer::from)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 对内存中的新旧文件数据生成补丁，流式写入补丁文件；`stats: true` 时返回补丁统计
+#[napi]
+pub fn diff_buffers_to_file_sync(env: Env, old: Buffer, new: Buffer, patch: PathLike, options: Option<DiffOptions>) -> Result<Option<DiffStatsJs>> {
+  let patch = path_arg(&patch)?;
+  let stats = wants_stats(&options);
+  let config = diff_config(options)?;
+  BsdiffRust::diff_bytes_to_file(&old, &new, &patch, &config).map_err(|e| js_error(&env, e))?;
+  Ok(collect_stats(&patch, stats, config.dictionary.as_ref())?.map(|stats| diff_stats_js(stats, &config)))
+}
+
+/// 在内存中应用补丁，返回新文件数据
+#[napi]
+pub fn patch_buffer_sync(env: Env, old: Buffer, patch: Buffer, options: Option<PatchOptions>) -> Result<Buffer> {
+  BsdiffRust::patch_bytes(&old, &patch, &patch_config(options)?)
+    .map(Buffer::from)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 对内存中的旧文件数据应用补丁文件，流式写入新文件，返回新文件大小
+#[napi]
+pub fn patch_buffer_to_file_sync(env: Env, old: Buffer, patch: PathLike, new_str: PathLike, options: Option<PatchOptions>) -> Result<f64> {
+  let patch = path_arg(&patch)?;
+  let new_str = path_arg(&new_str)?;
+  BsdiffRust::patch_bytes_to_file(&old, &patch, &new_str, &patch_config(options)?)
+    .map(|size| size as f64)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 对磁盘上的旧文件应用内存中的补丁数据，流式写入新文件，返回新文件大小
+#[napi]
+pub fn patch_file_with_patch_buffer_sync(env: Env, old_str: PathLike, patch: Buffer, new_str: PathLike, options: Option<PatchOptions>) -> Result<f64> {
+  let old_str = path_arg(&old_str)?;
+  let new_str = path_arg(&new_str)?;
+  BsdiffRust::patch_file_with_patch_bytes(&old_str, &patch, &new_str, &patch_config(options)?)
+    .map(|size| size as f64)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 对磁盘上的旧文件应用补丁文件，直接返回新文件数据而不写入磁盘
+#[napi]
+pub fn patch_to_buffer_sync(env: Env, old_str: PathLike, patch: PathLike, options: Option<PatchOptions>) -> Result<Buffer> {
+  let old_str = path_arg(&old_str)?;
+  let patch = path_arg(&patch)?;
+  BsdiffRust::patch_to_bytes(&old_str, &patch, &patch_config(options)?)
+    .map(Buffer::from)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 同 patchToBufferSync，但返回可通过 postMessage 转移给 Worker 的 ArrayBuffer；
+/// 补丁头声明了目标大小时新文件直接写入 ArrayBuffer 的存储，不经过中间复制
+#[napi]
+pub fn patch_to_array_buffer_sync(env: Env, old_str: PathLike, patch: PathLike, options: Option<PatchOptions>) -> Result<ArrayBuffer<'static>> {
+  let old_str = path_arg(&old_str)?;
+  let patch = path_arg(&patch)?;
+  let config = patch_config(options)?;
+  let mut output = match BsdiffRust::declared_output_size(&patch) {
+    Ok(Some(size)) => allocate_output(&env, size)?,
+    _ => {
+      let data = BsdiffRust::patch_to_bytes(&old_str, &patch, &config).map_err(|e| js_error(&env, e))?;
+      return OutputArrayBuffer::copy_from(&env, &data);
+    }
+  };
+  match BsdiffRust::patch_into_slice(&old_str, &patch, output.as_mut_slice(), &config) {
+    Ok(_) => output.into_value(&env),
+    Err(e) => {
+      output.release(&env)?;
+      Err(js_error(&env, e))
+    }
+  }
+}
+
+/// 为补丁输出分配 ArrayBuffer
+fn allocate_output(env: &Env, size: u64) -> Result<OutputArrayBuffer> {
+  let len = usize::try_from(size)
+    .map_err(|_| Error::new(Status::InvalidArg, format!("Patch output of {} bytes does not fit in memory", size)))?;
+  OutputArrayBuffer::new(env, len)
+}
+
+/// 递归比较两个目录，生成目录差分包
+#[napi]
+pub fn diff_dir_sync(env: Env, old_dir: PathLike, new_dir: PathLike, bundle: PathLike, options: Option<DiffOptions>) -> Result<DirDiffSummaryJs> {
+  let old_dir = path_arg(&old_dir)?;
+  let new_dir = path_arg(&new_dir)?;
+  let bundle = path_arg(&bundle)?;
+  dir_diff::diff_dir(&old_dir, &new_dir, &bundle, &diff_config(options)?)
+    .map(DirDiffSummaryJs::from)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 将目录差分包应用到旧目录，生成新目录 (`newDir` 必须不存在)
+#[napi]
+pub fn patch_dir_sync(env: Env, old_dir: PathLike, new_dir: PathLike, bundle: PathLike, options: Option<PatchOptions>) -> Result<DirDiffSummaryJs> {
+  let old_dir = path_arg(&old_dir)?;
+  let new_dir = path_arg(&new_dir)?;
+  let bundle = path_arg(&bundle)?;
+  dir_diff::patch_dir(&old_dir, &new_dir, &bundle, &patch_config(options)?)
+    .map(DirDiffSummaryJs::from)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 为多个文件并行生成补丁，打包为一个补丁包
+#[napi]
+pub fn create_bundle_sync(
+  env: Env,
+  entries: Vec<BundleEntryJs>,
+  bundle: PathLike,
+  options: Option<DiffOptions>,
+) -> Result<Vec<BundleFileJs>> {
+  let bundle = path_arg(&bundle)?;
+  bundle::create_bundle(&bundle_entries(entries)?, &bundle, &diff_config(options)?)
+    .map(bundle_index_js)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 应用补丁包：`mapping` 以包内名称为键给出旧文件与输出路径，须恰好覆盖包内每个条目
+#[napi]
+pub fn apply_bundle_sync(
+  env: Env,
+  bundle: PathLike,
+  mapping: HashMap<String, BundleTargetJs>,
+  options: Option<PatchOptions>,
+) -> Result<Vec<BundleFileJs>> {
+  let bundle = path_arg(&bundle)?;
+  bundle::apply_bundle(&bundle, &bundle_mapping(mapping)?, &patch_config(options)?)
+    .map(bundle_applied_js)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 并排应用补丁包或目录差分包：在 `targetDir` (必须不存在) 生成更新后的完整目录树，`sourceDir` 保持不变
+#[napi]
+pub fn apply_bundle_to_dir_sync(
+  env: Env,
+  bundle: PathLike,
+  source_dir: PathLike,
+  target_dir: PathLike,
+  options: Option<PatchOptions>,
+) -> Result<DirDiffSummaryJs> {
+  let bundle = path_arg(&bundle)?;
+  let source_dir = path_arg(&source_dir)?;
+  let target_dir = path_arg(&target_dir)?;
+  bundle::apply_bundle_to_dir(&bundle, &source_dir, &target_dir, &patch_config(options)?)
+    .map(DirDiffSummaryJs::from)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 计算旧文件的块签名 (rsync 风格) 写入 `signatureFile`，供服务器在没有旧文件的情况下生成增量
+#[napi]
+pub fn signature_sync(env: Env, old_str: PathLike, signature_file: PathLike, options: Option<DiffOptions>) -> Result<()> {
+  let old_str = path_arg(&old_str)?;
+  let signature_file = path_arg(&signature_file)?;
+  block_delta::write_signature(&old_str, &signature_file, &diff_config(options)?)
+    .map(|_| ())
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 只凭旧文件签名对新文件生成补丁
+#[napi]
+pub fn delta_sync(env: Env, signature_file: PathLike, new_str: PathLike, patch: PathLike, options: Option<DiffOptions>) -> Result<()> {
+  let signature_file = path_arg(&signature_file)?;
+  let new_str = path_arg(&new_str)?;
+  let patch = path_arg(&patch)?;
+  block_delta::delta(&signature_file, &new_str, &patch, &diff_config(options)?).map_err(|e| js_error(&env, e))
+}
+
+/// 把 delta 生成的补丁应用到旧文件本身 (原地替换，同 patchInPlace)，返回新文件大小
+#[napi]
+pub fn apply_sync(env: Env, old_str: PathLike, patch: PathLike, options: Option<PatchOptions>) -> Result<f64> {
+  patch_in_place_sync(env, old_str, patch, options)
+}
+
+fn bundle_entries(entries: Vec<BundleEntryJs>) -> Result<Vec<bundle::BundleEntry>> {
+  entries
+    .into_iter()
+    .map(|entry| Ok(bundle::BundleEntry { old: path_arg(&entry.old)?, new: path_arg(&entry.new)?, name: entry.name }))
+    .collect()
+}
+
+fn bundle_mapping(mapping: HashMap<String, BundleTargetJs>) -> Result<BTreeMap<String, bundle::BundleTarget>> {
+  mapping
+    .into_iter()
+    .map(|(name, target)| Ok((name, bundle::BundleTarget { old: path_arg(&target.old)?, new: path_arg(&target.new)? })))
+    .collect()
+}
+
+fn bundle_index_js(index: Vec<bundle::BundleIndexEntry>) -> Vec<BundleFileJs> {
+  index.into_iter().map(|entry| BundleFileJs { name: entry.name, size: entry.length as f64 }).collect()
+}
+
+fn bundle_applied_js(applied: Vec<(String, u64)>) -> Vec<BundleFileJs> {
+  applied.into_iter().map(|(name, size)| BundleFileJs { name, size: size as f64 }).collect()
+}
+
+/// 基于同一旧文件映射并发应用多个补丁
+#[napi]
+pub fn patch_fanout_sync(env: Env, old_str: PathLike, jobs: Vec<PatchJobJs>, concurrency: Option<u32>) -> Result<()> {
+  let old_str = path_arg(&old_str)?;
+  let jobs = jobs.iter().map(|job| Ok((path_arg(&job.patch)?, path_arg(&job.out)?))).collect::<Result<Vec<_>>>()?;
+  BsdiffRust::patch_fanout(&old_str, &jobs, concurrency.map(|n| n as usize), &OptimizationConfig::default())
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 并发生成多个补丁，返回与输入顺序一致的逐项结果；单项失败不影响其他任务
+#[napi]
+pub fn diff_batch_sync(env: Env, pairs: Vec<DiffPairJs>, options: Option<DiffOptions>, concurrency: Option<u32>) -> Result<Vec<DiffBatchResultJs>> {
+  run_diff_batch(pairs, concurrency, &diff_config(options)?).map_err(|e| with_code(&env, e))
+}
+
+fn run_diff_batch(pairs: Vec<DiffPairJs>, concurrency: Option<u32>, config: &OptimizationConfig) -> Result<Vec<DiffBatchResultJs>> {
+  let jobs = pairs
+    .iter()
+    .map(|pair| Ok((path_arg(&pair.old)?, path_arg(&pair.new)?, path_arg(&pair.patch)?)))
+    .collect::<Result<Vec<_>>>()?;
+  let results = BsdiffRust::diff_batch(&jobs, concurrency.map(|n| n as usize), config)
+    .map_err(napi_error)?;
+  Ok(pairs.into_iter().zip(results).map(|(pair, result)| DiffBatchResultJs {
+    patch: pair.patch,
+    ok: result.is_ok(),
+    patch_size: result.as_ref().ok().map(|size| *size as f64),
+    error: result.err(),
+  }).collect())
+}
+
+/// 依次应用一串增量补丁，中间版本只保存在内存中；返回新文件大小
+#[napi]
+pub fn patch_chain_sync(env: Env, old_str: PathLike, patches: Vec<PathLike>, new_str: PathLike, options: Option<PatchOptions>) -> Result<f64> {
+  let old_str = path_arg(&old_str)?;
+  let patches = patches.iter().map(path_arg).collect::<Result<Vec<_>>>()?;
+  let new_str = path_arg(&new_str)?;
+  BsdiffRust::patch_chain(&old_str, &patches, &new_str, &patch_config(options)?)
+    .map(|written| written as f64)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 原地打补丁：写入同目录临时文件、fsync 后原子替换 `file`，失败时恢复原文件；返回新文件大小
+#[napi]
+pub fn patch_in_place_sync(env: Env, file: PathLike, patch: PathLike, options: Option<PatchOptions>) -> Result<f64> {
+  let file = path_arg(&file)?;
+  let patch = path_arg(&patch)?;
+  BsdiffRust::patch_in_place(&file, &patch, &patch_config(options)?)
+    .map(|written| written as f64)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 将补丁应用到固定大小的区域，目标大于 `regionSize` 时在写入前报错；返回写入的字节数
+#[napi]
+pub fn patch_to_fixed_region_sync(
+  env: Env,
+  old_str: PathLike,
+  patch: PathLike,
+  out: PathLike,
+  region_size: i64,
+  zero_pad: Option<bool>,
+) -> Result<f64> {
+  let old_str = path_arg(&old_str)?;
+  let patch = path_arg(&patch)?;
+  let out = path_arg(&out)?;
+  let region_size = u64::try_from(region_size)
+    .map_err(|_| Error::new(Status::InvalidArg, format!("Invalid region size: {}", region_size)))?;
+  BsdiffRust::patch_to_fixed_region(&old_str, &out, &patch, region_size, zero_pad.unwrap_or(false), &OptimizationConfig::default())
+    .map(|written| written as f64)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 一次生成正向 (a→b) 与反向 (b→a) 补丁
+#[napi]
+pub fn diff_bidirectional_sync(env: Env, a: PathLike, b: PathLike, forward_patch: PathLike, reverse_patch: PathLike) -> Result<()> {
+  let a = path_arg(&a)?;
+  let b = path_arg(&b)?;
+  let forward_patch = path_arg(&forward_patch)?;
+  let reverse_patch = path_arg(&reverse_patch)?;
+  BsdiffRust::diff_bidirectional(&a, &b, &forward_patch, &reverse_patch, &OptimizationConfig::default())
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 以新的压缩级别重新压缩已有补丁 (补丁头与原始 bsdiff 流保持不变)
+#[napi]
+pub fn recompress_patch_sync(env: Env, in_patch: PathLike, out_patch: PathLike, new_level: i32) -> Result<()> {
+  let in_patch = path_arg(&in_patch)?;
+  let out_patch = path_arg(&out_patch)?;
+  let config = OptimizationConfig { compression_level: check_compression_level(new_level)?, ..Default::default() };
+  BsdiffRust::recompress_patch(&in_patch, &out_patch, &config)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 生成目录清单 JSON：每个文件的相对路径 → 大小与 SHA-256
+#[napi]
+pub fn create_manifest_sync(env: Env, dir: PathLike) -> Result<String> {
+  let dir = path_arg(&dir)?;
+  run_create_manifest(&dir).map_err(|e| with_code(&env, e))
+}
+
+fn run_create_manifest(dir: &Path) -> Result<String> {
+  manifest::create_manifest(dir)
+    .map(|manifest| manifest::manifest_to_json(&manifest))
+    .map_err(napi_error)
+}
+
+/// 按清单 JSON 校验目录，返回缺失或被修改的文件；清单之外的文件不影响结果
+#[napi]
+pub fn verify_manifest_sync(env: Env, dir: PathLike, manifest: String) -> Result<Vec<ManifestMismatchJs>> {
+  let dir = path_arg(&dir)?;
+  run_verify_manifest(&dir, &manifest).map_err(|e| with_code(&env, e))
+}
+
+fn run_verify_manifest(dir: &Path, manifest: &str) -> Result<Vec<ManifestMismatchJs>> {
+  manifest::manifest_from_json(manifest)
+    .and_then(|manifest| manifest::verify_manifest(dir, &manifest))
+    .map(|mismatches| mismatches.into_iter().map(ManifestMismatchJs::from).collect())
+    .map_err(napi_error)
+}
+
+/// 比较新旧清单 JSON，规划更新操作并估算下载量；只使用清单，不读取任何文件
+#[napi]
+pub fn plan_update_sync(env: Env, old_manifest: String, new_manifest: String) -> Result<UpdatePlanJs> {
+  run_plan_update(&old_manifest, &new_manifest).map_err(|e| with_code(&env, e))
+}
+
+fn run_plan_update(old_manifest: &str, new_manifest: &str) -> Result<UpdatePlanJs> {
+  let old = manifest::manifest_from_json(old_manifest).map_err(napi_error)?;
+  let new = manifest::manifest_from_json(new_manifest).map_err(napi_error)?;
+  Ok(manifest::plan_update(&old, &new).into())
+}
+
+fn ab_slots(slots: &AbSlotsJs) -> Result<ab_update::AbSlots> {
+  Ok(ab_update::AbSlots::new(&path_arg(&slots.slot_a)?, &path_arg(&slots.slot_b)?, &path_arg(&slots.state_file)?))
+}
+
+fn slot_state_js(slots: &ab_update::AbSlots, state: ab_update::SlotState) -> SlotStateJs {
+  SlotStateJs {
+    active: state.active.name().to_string(),
+    pending: state.pending.map(|slot| slot.name().to_string()),
+    tries_left: state.tries_left,
+    current: state.current().name().to_string(),
+    current_dir: slots.dir(state.current()).to_string_lossy().into_owned(),
+  }
+}
+
+/// 读取 A/B 槽位状态；状态文件不存在时槽位 A 为可用槽位
+#[napi]
+pub fn slot_state_sync(env: Env, slots: AbSlotsJs) -> Result<SlotStateJs> {
+  let slots = ab_slots(&slots)?;
+  slots.state().map(|state| slot_state_js(&slots, state)).map_err(|e| js_error(&env, e))
+}
+
+/// 清空非可用槽位并返回其目录，作为 applyBundleToDir 等的目标；已有待确认的更新时拒绝
+#[napi]
+pub fn prepare_update_sync(env: Env, slots: AbSlotsJs) -> Result<String> {
+  ab_slots(&slots)?
+    .prepare_update()
+    .map(|dir| dir.to_string_lossy().into_owned())
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 更新已写入非可用槽位：标记为待确认，最多试运行 `maxTries` 次 (默认 3)
+#[napi]
+pub fn stage_update_sync(env: Env, slots: AbSlotsJs, max_tries: Option<u32>) -> Result<SlotStateJs> {
+  let slots = ab_slots(&slots)?;
+  slots.stage_update(max_tries.unwrap_or(3)).map(|state| slot_state_js(&slots, state)).map_err(|e| js_error(&env, e))
+}
+
+/// 启动时调用：消耗一次试运行机会并返回应运行的槽位，机会用尽时回到可用槽位
+#[napi]
+pub fn boot_slot_sync(env: Env, slots: AbSlotsJs) -> Result<SlotStateJs> {
+  let slots = ab_slots(&slots)?;
+  slots.boot().map(|state| slot_state_js(&slots, state)).map_err(|e| js_error(&env, e))
+}
+
+/// 新版本运行正常：待确认槽位成为可用槽位
+#[napi]
+pub fn commit_update_sync(env: Env, slots: AbSlotsJs) -> Result<SlotStateJs> {
+  let slots = ab_slots(&slots)?;
+  slots.commit_update().map(|state| slot_state_js(&slots, state)).map_err(|e| js_error(&env, e))
+}
+
+/// 放弃待确认的更新；没有待确认的更新时切换回另一个槽位
+#[napi]
+pub fn rollback_update_sync(env: Env, slots: AbSlotsJs) -> Result<SlotStateJs> {
+  let slots = ab_slots(&slots)?;
+  slots.rollback_update().map(|state| slot_state_js(&slots, state)).map_err(|e| js_error(&env, e))
+}
+
+/// 将多个补丁打包为一个带 (from, to) 索引的补丁集文件
+#[napi]
+pub fn build_patch_set_sync(env: Env, entries: Vec<PatchSetEntryJs>, out: PathLike) -> Result<()> {
+  let out = path_arg(&out)?;
+  let entries = entries
+    .into_iter()
+    .map(|entry| Ok(patch_set::PatchSetEntry { patch: path_arg(&entry.patch)?, from: entry.from, to: entry.to }))
+    .collect::<Result<Vec<_>>>()?;
+  patch_set::build_patch_set(&entries, &out, &OptimizationConfig::default())
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 从补丁集中提取 from → to 的补丁
+#[napi]
+pub fn extract_patch_sync(env: Env, set: PathLike, from: String, to: String, out: PathLike) -> Result<()> {
+  let set = path_arg(&set)?;
+  let out = path_arg(&out)?;
+  patch_set::extract_patch(&set, &from, &to, &out, &OptimizationConfig::default())
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 按 `chunkSize` 字节将补丁拆分为 `<patch>.000`、`<patch>.001`…… 分片，每片带 CRC-32；返回分片路径
+#[napi]
+pub fn split_patch_sync(env: Env, patch: PathLike, chunk_size: i64) -> Result<Vec<PathLike>> {
+  let patch = path_arg(&patch)?;
+  let chunk_size = u64::try_from(chunk_size)
+    .map_err(|_| Error::new(Status::InvalidArg, format!("Invalid chunk size: {}", chunk_size)))?;
+  patch_chunks::split_patch(&patch, chunk_size, &OptimizationConfig::default())
+    .map(|parts| parts.into_iter().map(path_js).collect())
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 校验单个分片的头与 CRC-32，损坏时抛出异常
+#[napi]
+pub fn check_patch_part_sync(env: Env, part: PathLike) -> Result<PatchPartJs> {
+  let part = path_arg(&part)?;
+  let part = patch_chunks::check_patch_part(&part).map_err(|e| js_error(&env, e))?;
+  Ok(PatchPartJs {
+    index: part.index,
+    count: part.count,
+    patch_size: part.patch_size as f64,
+    patch_sha256: part.patch_sha256_hex(),
+    offset: part.offset as f64,
+    length: part.length,
+    crc32: part.crc32,
+  })
+}
+
+/// 将分片 (顺序任意) 合并为完整补丁
+#[napi]
+pub fn join_patch_sync(env: Env, parts: Vec<PathLike>, out: PathLike) -> Result<()> {
+  let parts = parts.iter().map(path_arg).collect::<Result<Vec<_>>>()?;
+  let out = path_arg(&out)?;
+  patch_chunks::join_patch(&parts, &out, &OptimizationConfig::default())
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 直接从分片应用补丁，不生成完整补丁文件
+#[napi]
+pub fn patch_from_chunks_sync(env: Env, old_str: PathLike, new_str: PathLike, parts: Vec<PathLike>, options: Option<PatchOptions>) -> Result<()> {
+  let old_str = path_arg(&old_str)?;
+  let new_str = path_arg(&new_str)?;
+  let parts = parts.iter().map(path_arg).collect::<Result<Vec<_>>>()?;
+  patch_chunks::patch_from_chunks(&old_str, &new_str, &parts, &patch_config(options)?)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 用 Ed25519 私钥 (32 字节种子或 64 字节种子 + 公钥) 对补丁文件或补丁 Buffer 签名，返回 64 字节分离式签名
+#[napi]
+pub fn sign_patch_sync(env: Env, patch: Either<String, Buffer>, private_key: Buffer) -> Result<Buffer> {
+  sign_patch_input(&patch, &private_key)
+    .map(|signature| signature.to_vec().into())
+    .map_err(|e| with_code(&env, e))
+}
+
+fn sign_patch_input(patch: &Either<String, Buffer>, private_key: &[u8]) -> Result<[u8; 64]> {
+  match patch {
+    Either::A(path) => signing::sign_patch(&paths::native(path.clone()), private_key),
+    Either::B(buffer) => signing::sign_patch_bytes(buffer, private_key),
+  }
+  .map_err(napi_error)
+}
+
+/// 由 Ed25519 私钥计算 32 字节公钥 (patch 选项中的 publicKey)
+#[napi]
+pub fn public_key_from_private_sync(private_key: Buffer) -> Result<Buffer> {
+  signing::public_key_from_private(&private_key)
+    .map(|public_key| public_key.to_vec().into())
+    .map_err(|e| Error::new(Status::InvalidArg, e.to_string()))
+}
+
+/// 在 zstd 与经典 BSDIFF40 补丁格式之间转换 (无需源文件)
+#[napi]
+pub fn convert_patch_sync(env: Env, in_patch: PathLike, out_patch: PathLike, target_format: String) -> Result<()> {
+  let in_patch = path_arg(&in_patch)?;
+  let out_patch = path_arg(&out_patch)?;
+  let target = bsdiff40::PatchFormat::parse(&target_format)
+    .map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?;
+  bsdiff40::convert_patch(&in_patch, &out_patch, target, &OptimizationConfig::default())
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 验证补丁文件完整性
+#[napi]
+pub fn verify_patch_sync(env: Env, old_str: PathLike, new_str: PathLike, patch: PathLike) -> Result<bool> {
+  let old_str = path_arg(&old_str)?;
+  let new_str = path_arg(&new_str)?;
+  let patch = path_arg(&patch)?;
+  verify_patch_util(&old_str, &new_str, &patch, &OptimizationConfig::default())
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 只用旧文件与补丁校验：在内存中流式应用补丁，比较新文件 SHA-256 (十六进制) 与期望值
+#[napi]
+pub fn verify_patch_against_hash_sync(env: Env, old_str: PathLike, patch: PathLike, expected_sha256: String, options: Option<PatchOptions>) -> Result<bool> {
+  let old_str = path_arg(&old_str)?;
+  let patch = path_arg(&patch)?;
+  verify_patch_against_hash_util(&old_str, &patch, &expected_sha256, &patch_config(options)?)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 不依赖旧文件自检补丁是否完整 (补丁头、Zstd 负载、bsdiff 控制流与目标大小)
+#[napi]
+pub fn validate_patch_self_sync(env: Env, patch: PathLike) -> Result<SelfCheckJs> {
+  let patch = path_arg(&patch)?;
+  let check = validate_patch_self(&patch)
+    .map_err(|e| js_error(&env, e))?;
+
+  Ok(SelfCheckJs {
+    ok: check.ok,
+    reason: check.reason,
+  })
+}
+
+/// 统计补丁的控制块数、复制与新增字节数 (不需要旧文件；VCDIFF 补丁不支持，字典压缩的补丁需提供字典)
+#[napi]
+pub fn get_diff_stats_sync(env: Env, patch: PathLike, dictionary: Option<Buffer>) -> Result<DiffStatsJs> {
+  let patch = path_arg(&patch)?;
+  let dictionary = dictionary
+    .map(|dictionary| Dictionary::new(&dictionary))
+    .transpose()
+    .map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?;
+  get_diff_stats(&patch, dictionary.as_ref())
+    .map(DiffStatsJs::from)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 列出补丁的 bsdiff 控制三元组 (不需要旧文件)，用于排查补丁为何偏大
+#[napi]
+pub fn inspect_patch_sync(env: Env, patch: PathLike, options: Option<InspectOptions>) -> Result<Vec<ControlEntryJs>> {
+  let patch = path_arg(&patch)?;
+  let (offset, limit, dictionary) = inspect_config(options)?;
+  utils::inspect_patch(&patch, offset, limit, dictionary.as_ref())
+    .map(|records| records.into_iter().map(ControlEntryJs::from).collect())
+    .map_err(|e| js_error(&env, e))
+}
+
+fn inspect_config(options: Option<InspectOptions>) -> Result<(usize, usize, Option<Dictionary>)> {
+  let options = options.unwrap_or_default();
+  let count = |value: Option<i64>, default: usize, name: &str| {
+    value.map_or(Ok(default), |value| {
+      usize::try_from(value).map_err(|_| Error::new(Status::InvalidArg, format!("Invalid {}: {}", name, value)))
+    })
+  };
+  let dictionary = options
+    .dictionary
+    .map(|dictionary| Dictionary::new(&dictionary))
+    .transpose()
+    .map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?;
+  Ok((count(options.offset, 0, "offset")?, count(options.limit, utils::DEFAULT_INSPECT_LIMIT, "limit")?, dictionary))
+}
+
+/// 以一组相似资源的补丁为样本训练共享 zstd 字典，返回字典数据
+#[napi]
+pub fn train_dictionary_sync(env: Env, patches: Vec<PathLike>, max_size: Option<u32>) -> Result<Buffer> {
+  let patches = patches.iter().map(path_arg).collect::<Result<Vec<_>>>()?;
+  dictionary::train_dictionary(&patches, max_size.map_or(dictionary::DEFAULT_MAX_SIZE, |size| size as usize))
+    .map(Buffer::from)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 获取补丁文件信息
+#[napi]
+pub fn get_patch_info_sync(env: Env, patch: PathLike) -> Result<PatchInfoJs> {
+  let patch = path_arg(&patch)?;
+  get_patch_info_util(&patch)
+    .map(PatchInfoJs::from)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 获取内存中补丁的信息 (不访问磁盘)
+#[napi]
+pub fn get_patch_info_from_buffer_sync(env: Env, patch: Buffer) -> Result<PatchInfoJs> {
+  get_patch_info_from_bytes(&patch)
+    .map(PatchInfoJs::from)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 获取文件大小
+#[napi]
+pub fn get_file_size_sync(env: Env, file_path: PathLike) -> Result<f64> {
+  let file_path = path_arg(&file_path)?;
+  get_file_size(&file_path)
+    .map(|size| size as f64)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 检查文件访问权限
+#[napi]
+pub fn check_file_access_sync(env: Env, file_path: PathLike) -> Result<()> {
+  let file_path = path_arg(&file_path)?;
+  check_file_access(&file_path)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 详细检查文件访问情况
+#[napi]
+pub fn check_file_access_detailed_sync(file_path: PathLike) -> Result<FileAccessJs> {
+  let file_path = path_arg(&file_path)?;
+  let access = check_file_access_detailed(&file_path);
+  Ok(FileAccessJs {
+    exists: access.exists,
+    is_file: access.is_file,
+    readable: access.readable,
+    is_symlink: access.is_symlink,
+    permission_denied: access.permission_denied,
+  })
+}
+
+/// 获取压缩比信息
+#[napi]
+pub fn get_compression_ratio_sync(env: Env, old_str: PathLike, new_str: PathLike, patch: PathLike) -> Result<CompressionRatioJs> {
+  let old_str = path_arg(&old_str)?;
+  let new_str = path_arg(&new_str)?;
+  let patch = path_arg(&patch)?;
+  let ratio = get_compression_ratio(&old_str, &new_str, &patch)
+    .map_err(|e| js_error(&env, e))?;
+  
+  Ok(CompressionRatioJs {
+    old_size: ratio.old_size as f64,
+    new_size: ratio.new_size as f64,
+    patch_size: ratio.patch_size as f64,
+    ratio: ratio.ratio,
+  })
+}
+
+/// 不运行 diff，采样滚动哈希快速估算新旧文件的相似度 (0-1) 与补丁大小范围
+#[napi]
+pub fn estimate_delta_sync(env: Env, old_str: PathLike, new_str: PathLike) -> Result<DeltaEstimateJs> {
+  let old_str = path_arg(&old_str)?;
+  let new_str = path_arg(&new_str)?;
+  similarity::estimate_similarity_files(&old_str, &new_str)
+    .map(DeltaEstimateJs::from)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// diff 预检：补丁将写入的临时目录、临时目录与目标目录的剩余空间、预计峰值内存以及能否内存映射输入
+#[napi]
+pub fn preflight_sync(env: Env, old_str: PathLike, new_str: PathLike, patch: PathLike, options: Option<DiffOptions>) -> Result<PreflightJs> {
+  let (old_str, new_str, patch) = (path_arg(&old_str)?, path_arg(&new_str)?, path_arg(&patch)?);
+  let config = diff_config(options)?;
+  preflight::preflight(&old_str, &new_str, &patch, &config)
+    .map(PreflightJs::from)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 在内存中运行 diff 但不写出补丁，返回变更区域、修改/插入/删除字节数与变更内容的熵
+#[napi]
+pub fn diff_report_sync(env: Env, old_str: PathLike, new_str: PathLike, options: Option<DiffOptions>) -> Result<DiffReportJs> {
+  let old_str = path_arg(&old_str)?;
+  let new_str = path_arg(&new_str)?;
+  let config = diff_config(options)?;
+  report::diff_report(&old_str, &new_str, &config)
+    .map(DiffReportJs::from)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 生成补丁并写入命名共享内存对象，返回写入的字节数
+#[napi]
+pub fn diff_to_shm_sync(env: Env, old_str: PathLike, new_str: PathLike, shm_name: String, level: Option<i32>) -> Result<f64> {
+  let old_str = path_arg(&old_str)?;
+  let new_str = path_arg(&new_str)?;
+  let mut config = OptimizationConfig::default();
+  if let Some(level) = level {
+    config.compression_level = check_compression_level(level)?;
+  }
+  shm::diff_to_shm(&old_str, &new_str, &shm_name, &config)
+    .map(|written| written as f64)
+    .map_err(|e| js_error(&env, e))
+}
+
+/// 获取编译进来的算法版本信息
+#[napi]
+pub fn algorithm_info_sync() -> AlgorithmInfoJs {
+  let info = algorithm_info();
+  AlgorithmInfoJs {
+    algorithm_version: info.algorithm_version,
+    zstd_version: info.zstd_version,
+    format_version: info.format_version as u32,
+  }
+}
+
+/// 设置日志回调 `(level, message) => void`，传入 null 取消
+#[napi]
+pub fn set_log_callback(#[napi(ts_arg_type = "((level: string, message: string) => void) | undefined | null")] callback: Option<LogCallbackJs>) {
+  logger::set_logger(callback.map(|tsfn| {
+    Arc::new(move |level: logger::LogLevel, message: &str| {
+      tsfn.call(
+        (level.as_str().to_string(), message.to_string()).into(),
+        ThreadsafeFunctionCallMode::NonBlocking,
+      );
+    }) as logger::LogCallback
+  }));
+}
+
+/// 预计算旧文件后缀数组，对多个新文件重复生成补丁
+#[napi(js_name = "PreparedBase")]
+pub struct PreparedBaseJs {
+  base: prepared::PreparedBase<memmap2::Mmap>,
+  digest: header::FileDigest,
+}
+
+#[napi]
+impl PreparedBaseJs {
+  /// 映射旧文件并构建后缀数组
+  #[napi(constructor)]
+  pub fn new(env: Env, old_str: PathLike) -> Result<Self> {
+    let old_str = path_arg(&old_str)?;
+    let file = std::fs::File::open(&old_str)
+      .map_err(|e| js_error(&env, std::io::Error::new(e.kind(), format!("Old file not found: {}: {}", old_str.display(), e)).into()))?;
+    let old_mmap = unsafe { memmap2::MmapOptions::new().map(&file) }
+      .map_err(|e| js_error(&env, e.into()))?;
+    let base = prepared::PreparedBase::new(old_mmap);
+    let digest = header::FileDigest::of_bytes(base.old());
+    Ok(Self { base, digest })
+  }
+
+  /// 对新文件生成补丁，复用已构建的后缀数组
+  #[napi]
+  pub fn add(&self, env: Env, new_str: PathLike, patch: PathLike) -> Result<()> {
+    let (new_str, patch) = (path_arg(&new_str)?, path_arg(&patch)?);
+    BsdiffRust::diff_prepared(&self.base, self.digest, &new_str, &patch, &OptimizationConfig::default())
+      .map_err(|e| js_error(&env, e))
+  }
+}
+
+/// JavaScript 算法版本信息结构
+#[napi(object)]
+pub struct AlgorithmInfoJs {
+  /// 内置 diff 算法的版本 (移植自的 bsdiff 版本，与补丁信息中的 bsdiffVersion 对应)
+  pub algorithm_version: String,
+  pub zstd_version: String,
+  pub format_version: u32,
+}
+
+/// JavaScript diff 选项
+#[napi(object, object_to_js = false)]
+pub struct DiffOptions {
+  /// diff 模式: "full" (默认) 或 "append"
+  pub mode: Option<String>,
+  /// 安全模式：zstd 使用保守配置 (默认读取环境变量 BSDIFF_ZSTD_SAFE=1)
+  pub safe_mode: Option<bool>,
+  /// Zstd 压缩级别 (1-22，默认 3)：越高补丁越小、diff 越慢；"auto" 在原始 diff 输出的样本上实测几个级别，
+  /// 选出当前机器上每秒压缩率最高的级别 (由 stats 的 compressionLevel 报告)
+  #[napi(ts_type = "number | 'auto'")]
+  pub compression_level: Option<Either<i32, String>>,
+  /// 先写入快速临时目录再移动到目标路径 (默认 true)
+  pub use_fast_temp_dir: Option<bool>,
+  /// 快速临时目录 (默认读取环境变量 BSDIFF_TEMP_DIR，未设置时自动选择)；剩余空间不足时改为写在输出旁边
+  pub temp_dir: Option<PathLike>,
+  /// 补丁格式: "zstd" (默认)、"bsdiff40" (与原版 bsdiff 工具兼容) 或 "vcdiff" (RFC 3284，与 xdelta3 兼容)
+  pub format: Option<String>,
+  /// 补丁负载压缩算法: "zstd" (默认)、"xz"、"brotli"、"gzip" 或 "none"，记录在补丁头中，patch 时自动识别；compressionLevel 只作用于 zstd
+  pub compression: Option<String>,
+  /// 分窗口 diff 的新文件窗口大小 (字节)：限制超大文件 diff 的内存占用，补丁略大
+  pub window_size: Option<i64>,
+  /// 分窗口 diff 的并行线程数 (默认 CPU 核数)
+  pub threads: Option<u32>,
+  /// 写入补丁头的键值元数据 (如 `{ appVersion: '1.2.3', channel: 'beta' }`)，getPatchInfo 可读出；仅 zstd 格式
+  pub metadata: Option<HashMap<String, String>>,
+  /// 可执行文件感知 diff (默认 false)：新旧文件是同一架构的 PE/ELF/Mach-O 时先规范化代码段中的相对跳转目标，
+  /// 与不变换的补丁比较后保留较小的一个 (diff 耗时约为两倍)；仅完整 diff 与 zstd 格式
+  pub exe_transform: Option<bool>,
+  /// 压缩包感知 diff (默认 false)：新文件是 ZIP 时先解压 deflate 条目再 diff，应用时按记录的 zlib 级别重新压缩，
+  /// 还原出逐字节相同的文件；仅完整 diff 与 zstd 格式
+  pub archive_transform: Option<bool>,
+  /// 内容去重 (默认 false)：diffDir 的新增文件按 FastCDC 分块，跨文件重复或旧目录中已有的块只存一次；
+  /// createBundle 中内容相同的补丁只存一份。其他 diff 忽略
+  pub dedup: Option<bool>,
+  /// diffDir 记录新目录中的符号链接与文件权限位 (默认 false)，patchDir 重建链接并恢复可执行位等权限
+  pub preserve_metadata: Option<bool>,
+  /// diffDir 额外记录文件修改时间 (默认 false，隐含 preserveMetadata)
+  pub preserve_mtimes: Option<bool>,
+  /// 分卷大小 (字节，仅 diff/diffSync)：补丁切成 `<patch>.001`、`<patch>.002`…… 不超过该大小的分卷，
+  /// patch 路径写为分卷索引；patch/patchSync 接受该索引或分卷所在目录
+  pub volume_size: Option<i64>,
+  /// diff/diffSync 完成后返回补丁统计 (控制块数、复制与新增字节数等；默认 false，VCDIFF 格式与加密补丁不支持)
+  pub stats: Option<bool>,
+  /// 用 AES-256-GCM 加密整个补丁 (nonce 与认证标签保存在信封中)；patch 时需提供相同的 decryptionKey。目录包不支持
+  pub encrypt: Option<EncryptOptionsJs>,
+  /// 共享 zstd 字典 (如 trainDictionary 的结果)，用于压缩补丁负载并把字典 ID 写入补丁头；
+  /// patch 时需提供相同的字典。仅 zstd 格式与压缩
+  pub dictionary: Option<Buffer>,
+  /// 可复现输出 (默认 false)：相同输入与选项在任意次运行、任意平台上生成逐字节相同的补丁 (固定 zstd 参数，忽略 safeMode)
+  pub deterministic: Option<bool>,
+  /// 补丁头记录新旧文件摘要所用的算法: "sha256" (默认) 或 "blake3" (多线程计算，大文件校验更快)；patch 时自动识别
+  #[napi(ts_type = "'sha256' | 'blake3'")]
+  pub hash_algorithm: Option<String>,
+  /// diff 算法: "bsdiff" (默认) 或 "blockdelta" (rsync 风格的块级增量：耗时与内存近似线性，补丁较大，
+  /// 适合数十 GB 的输入)；补丁格式不变，patch 无需区分
+  #[napi(ts_type = "'bsdiff' | 'blockdelta'")]
+  pub algorithm: Option<String>,
+  /// 时长上限 (毫秒，从调用时起算)：后缀排序与 diff 循环超过该时长即中止，清理临时输出并以 ERR_TIMEOUT 失败
+  pub timeout_ms: Option<i64>,
+  /// 补丁文件已存在时: "replace" (默认，直接覆盖)、"error" (开始前以 ERR_OUTPUT_EXISTS 拒绝) 或
+  /// "backup" (写入前重命名为 `<补丁>.bak`)；仅 diff/diffSync
+  #[napi(ts_type = "'error' | 'replace' | 'backup'")]
+  pub overwrite: Option<String>,
+  /// 完成输出时文件被暂时锁住 (杀毒软件扫描等) 的重试策略；Windows 默认重试，其他平台默认不重试
+  pub retry: Option<RetryOptionsJs>,
+  /// 补丁缓存目录 (仅 diff/diffSync)：以新旧文件摘要与影响补丁内容的选项为键保存补丁，
+  /// 再次请求相同的文件对时直接复制缓存的补丁。加密补丁不缓存
+  pub cache_dir: Option<PathLike>,
+  /// 完整文件回退 (仅 zstd 格式)：补丁超过新文件大小的该比例 (true 为 0.9) 时改为直接携带新文件，
+  /// stats 的 fullFile 表示是否回退；应用补丁时无需区分
+  pub fallback_to_full: Option<Either<bool, f64>>,
+  /// 进度回调，按已处理的新文件字节数上报 (节流到约 1% 一次)
+  #[napi(ts_type = "(progress: DiffProgressJs) => void")]
+  pub on_progress: Option<DiffProgressCallbackJs>,
+  /// 分阶段进度回调：read (映射输入、计算摘要) → apply (按新文件字节数) → write (移动到补丁路径)
+  #[napi(ts_type = "(event: ProgressEventJs) => void")]
+  pub on_progress_event: Option<ProgressEventCallbackJs>,
+}
+
+/// JavaScript 补丁加密选项
+#[napi(object, object_to_js = false)]
+pub struct EncryptOptionsJs {
+  /// 32 字节 AES-256 密钥
+  pub key: Buffer,
+}
+
+/// JavaScript 重试选项
+#[napi(object, object_to_js = false)]
+pub struct RetryOptionsJs {
+  /// 总尝试次数 (含首次，默认 5)，1 表示不重试
+  pub attempts: Option<u32>,
+  /// 首次重试前的等待时间 (毫秒，默认 100)，之后每次翻倍，最多 5 秒
+  pub delay_ms: Option<u32>,
+}
+
+/// JavaScript diff 进度
+#[napi(object)]
+pub struct DiffProgressJs {
+  /// 已处理的新文件字节数
+  pub processed: f64,
+  /// 新文件总字节数
+  pub total: f64,
+  /// 完成百分比 (0-100)
+  pub percent: f64,
+}
+
+/// JavaScript patch 选项
+#[napi(object, object_to_js = false)]
+pub struct PatchOptions {
+  /// 安全模式：zstd 使用保守配置 (默认读取环境变量 BSDIFF_ZSTD_SAFE=1)
+  pub safe_mode: Option<bool>,
+  /// 严格模式：拒绝未携带源文件校验和的补丁 (默认 false，旧版补丁不校验直接应用)
+  pub strict: Option<bool>,
+  /// 按控制块上报的进度回调，补丁头未记录控制块总数时不调用
+  #[napi(ts_type = "(progress: PatchProgressJs) => void")]
+  pub on_progress: Option<PatchProgressCallbackJs>,
+  /// 分阶段进度回调：decode (签名、解密、补丁头) → read (校验旧文件) → apply (按补丁字节数) → write (移动到输出路径)
+  #[napi(ts_type = "(event: ProgressEventJs) => void")]
+  pub on_progress_event: Option<ProgressEventCallbackJs>,
+  /// 32 字节 Ed25519 公钥，与 signature 一起提供时先校验补丁签名再应用
+  pub public_key: Option<Buffer>,
+  /// signPatch 生成的 64 字节分离式签名
+  pub signature: Option<Buffer>,
+  /// 32 字节 AES-256 密钥，用于应用 diff 时以 encrypt 加密的补丁；对未加密的补丁没有影响
+  pub decryption_key: Option<Buffer>,
+  /// 补丁负载使用字典压缩时所需的 zstd 字典 (与 diff 时相同)；对未使用字典的补丁没有影响
+  pub dictionary: Option<Buffer>,
+  /// 按补丁头声明的目标大小预分配输出文件并通过内存映射写入 (默认 false)
+  pub mmap_output: Option<bool>,
+  /// 以 seek 跳过输出中全零的 4KB 块，生成稀疏文件 (默认 false，优先于 mmapOutput)
+  pub sparse: Option<bool>,
+  /// 试运行 (默认 false，仅 patch/patchSync)：完整解码并校验补丁输出，返回将要写出的大小，不写入任何文件
+  pub dry_run: Option<bool>,
+  /// 快速临时目录 (默认读取环境变量 BSDIFF_TEMP_DIR，未设置时自动选择)；剩余空间不足时改为写在输出旁边
+  pub temp_dir: Option<PathLike>,
+  /// 允许的最大输出字节数：补丁头声明的目标大小超过时在写出前拒绝，未声明大小的补丁在输出达到上限时中止
+  pub max_output_size: Option<i64>,
+  /// 应用补丁时缓冲数据的内存上限 (字节)：zstd 解码窗口、整体读入的加密/BSDIFF40/VCDIFF 补丁、
+  /// 可执行文件变换与输出到内存的结果超过时以 ERR_LIMIT_EXCEEDED 失败
+  pub max_memory: Option<i64>,
+  /// 磁盘写入速率上限 (字节/秒)：多 GB 的更新不会占满磁盘带宽拖慢前台应用；设置后不使用 mmapOutput 与快速临时目录
+  pub max_write_bytes_per_sec: Option<i64>,
+  /// 输出文件已存在时: "replace" (默认，直接覆盖)、"error" (开始前以 ERR_OUTPUT_EXISTS 拒绝) 或
+  /// "backup" (写入前重命名为 `<输出>.bak`)；仅 patch/patchSync
+  #[napi(ts_type = "'error' | 'replace' | 'backup'")]
+  pub overwrite: Option<String>,
+  /// 完成输出时文件被暂时锁住 (杀毒软件扫描等) 的重试策略；Windows 默认重试，其他平台默认不重试
+  pub retry: Option<RetryOptionsJs>,
+  /// 写出新文件后 fsync 文件及其所在目录再返回 (默认 false)，返回后即可安全地把更新标记为已提交
+  pub durable: Option<bool>,
+  /// 内容未变与仅移动的文件的生成方式 (仅 patchDir/applyBundleToDir)："reflink" (默认，写时复制克隆，不支持时复制)、
+  /// "hardlink" (硬链接到旧文件) 或 "copy"
+  #[napi(ts_type = "'copy' | 'reflink' | 'hardlink'")]
+  pub link_mode: Option<String>,
+  /// 沙箱根目录 (仅 patchDir/applyBundle/applyBundleToDir)：所有读写路径都必须位于其下，相对路径基于它解析，
+  /// 经由 `..`、绝对路径或符号链接越界时在读取补丁前失败
+  pub base_dir: Option<PathLike>,
+  /// 审计日志文件：以追加模式每步写一行 JSON 事件 (补丁头校验、签名与摘要校验、写出字节数、rename 提交等)
+  pub log_file: Option<PathLike>,
+  /// 审计事件回调，参数为与 logFile 相同的一行 JSON；可与 logFile 同时使用
+  #[napi(ts_type = "(event: string) => void")]
+  pub on_audit_event: Option<AuditCallbackJs>,
+}
+
+/// JavaScript patchFromUrl 请求选项
+#[napi(object, object_to_js = false)]
+pub struct UrlOptionsJs {
+  /// 附加请求头 (如 Authorization)，重定向到其他源后不再发送
+  pub headers: Option<HashMap<String, String>>,
+  /// 连接与等待响应头的超时 (毫秒)，默认不超时
+  pub timeout: Option<u32>,
+  /// 补丁大小上限 (字节)，超过时中止下载；签名补丁需整体读入内存，未指定时上限为 512 MiB
+  pub max_patch_size: Option<i64>,
+}
+
+impl TryFrom<UrlOptionsJs> for http::HttpOptions {
+  type Error = Error;
+
+  fn try_from(options: UrlOptionsJs) -> Result<Self> {
+    Ok(Self {
+      headers: options.headers.unwrap_or_default().into_iter().collect(),
+      timeout: options.timeout.filter(|ms| *ms > 0).map(|ms| std::time::Duration::from_millis(ms.into())),
+      max_size: options.max_patch_size.map(|size| byte_limit(size, "maxPatchSize")).transpose()?,
+    })
+  }
+}
+
+/// JavaScript patch 进度
+#[napi(object)]
+pub struct PatchProgressJs {
+  /// 已应用的控制块数
+  pub blocks_applied: f64,
+  /// 控制块总数
+  pub total_blocks: f64,
+  /// 完成百分比 (0-100)
+  pub percent: f64,
+}
+
+/// JavaScript 分阶段进度事件
+#[napi(object)]
+pub struct ProgressEventJs {
+  #[napi(ts_type = "'read' | 'decode' | 'apply' | 'write'")]
+  pub phase: String,
+  /// 当前阶段已处理的字节数
+  pub bytes_done: f64,
+  /// 当前阶段的总字节数
+  pub bytes_total: f64,
+  /// 按当前阶段速率估算的剩余毫秒数，尚无法估算时省略；阶段结束时为 0
+  pub eta_ms: Option<f64>,
+}
+
+/// JavaScript fan-out 补丁任务
+#[napi(object, object_to_js = false)]
+pub struct PatchJobJs {
+  /// 补丁文件路径
+  pub patch: PathLike,
+  /// 输出文件路径
+  pub out: PathLike,
+}
+
+/// JavaScript 批量 diff 任务
+#[napi(object, object_to_js = false)]
+pub struct DiffPairJs {
+  /// 旧文件路径
+  pub old: PathLike,
+  /// 新文件路径
+  pub new: PathLike,
+  /// 补丁输出路径
+  pub patch: PathLike,
+}
+
+/// JavaScript 批量 diff 单项结果
+#[napi(object)]
+pub struct DiffBatchResultJs {
+  /// 补丁输出路径 (与传入的值相同)
+  pub patch: PathLike,
+  /// 是否成功
+  pub ok: bool,
+  /// 成功时的补丁大小 (字节)
+  pub patch_size: Option<f64>,
+  /// 失败时的错误信息
+  pub error: Option<String>,
+}
+
+/// JavaScript 补丁包条目
+#[napi(object, object_to_js = false)]
+pub struct BundleEntryJs {
+  /// 旧文件路径
+  pub old: PathLike,
+  /// 新文件路径
+  pub new: PathLike,
+  /// 包内名称 (通常为相对路径)，applyBundle 时据此查找映射
+  pub name: String,
+}
+
+/// JavaScript 补丁包应用目标
+#[napi(object, object_to_js = false)]
+pub struct BundleTargetJs {
+  /// 旧文件路径
+  pub old: PathLike,
+  /// 输出新文件路径
+  pub new: PathLike,
+}
+
+/// JavaScript 补丁包逐项结果
+#[napi(object)]
+pub struct BundleFileJs {
+  /// 包内名称
+  pub name: String,
+  /// createBundle 时为补丁大小，applyBundle 时为生成的新文件大小 (字节)
+  pub size: f64,
+}
+
+/// JavaScript 补丁集条目
+#[napi(object, object_to_js = false)]
+pub struct PatchSetEntryJs {
+  /// 源版本文件 SHA-256 (十六进制)
+  pub from: String,
+  /// 目标版本文件 SHA-256 (十六进制)
+  pub to: String,
+  /// 补丁文件路径
+  pub patch: PathLike,
+}
+
+/// JavaScript 分片信息
+#[napi(object)]
+pub struct PatchPartJs {
+  /// 分片序号 (从 0 开始)
+  pub index: u32,
+  /// 分片总数
+  pub count: u32,
+  /// 完整补丁大小
+  pub patch_size: f64,
+  /// 完整补丁的 SHA-256 (十六进制)
+  pub patch_sha256: String,
+  /// 分片数据在完整补丁中的偏移
+  pub offset: f64,
+  /// 分片数据长度
+  pub length: u32,
+  /// 分片数据的 CRC-32
+  pub crc32: u32,
+}
+
+/// JavaScript 目录差分统计
+#[napi(object)]
+pub struct DirDiffSummaryJs {
+  pub added: u32,
+  pub deleted: u32,
+  pub patched: u32,
+  pub renamed: u32,
+  pub unchanged: u32,
+}
+
+impl From<dir_diff::DirDiffSummary> for DirDiffSummaryJs {
+  fn from(summary: dir_diff::DirDiffSummary) -> Self {
+    Self {
+      added: summary.added,
+      deleted: summary.deleted,
+      patched: summary.patched,
+      renamed: summary.renamed,
+      unchanged: summary.unchanged,
+    }
+  }
+}
+
+/// JavaScript 清单不一致项
+#[napi(object)]
+pub struct ManifestMismatchJs {
+  /// 相对路径
+  pub path: String,
+  /// "missing" 或 "modified"
+  pub reason: String,
+  /// 清单记录的大小
+  pub expected_size: f64,
+  /// 清单记录的 SHA-256 (十六进制)
+  pub expected_sha256: String,
+  /// 实际大小 (文件缺失时为空)
+  pub actual_size: Option<f64>,
+  /// 实际 SHA-256 (文件缺失时为空)
+  pub actual_sha256: Option<String>,
+}
+
+impl From<manifest::ManifestMismatch> for ManifestMismatchJs {
+  fn from(mismatch: manifest::ManifestMismatch) -> Self {
+    let (path, reason, expected, actual) = match mismatch {
+      manifest::ManifestMismatch::Missing { path, expected } => (path, "missing", expected, None),
+      manifest::ManifestMismatch::Modified { path, expected, actual } => (path, "modified", expected, Some(actual)),
+    };
+    Self {
+      path,
+      reason: reason.to_string(),
+      expected_size: expected.size as f64,
+      expected_sha256: expected.hex(),
+      actual_size: actual.map(|actual| actual.size as f64),
+      actual_sha256: actual.map(|actual| actual.hex()),
+    }
+  }
+}
+
+/// JavaScript 更新操作
+#[napi(object)]
+pub struct UpdateOperationJs {
+  /// "patch"、"add"、"delete" 或 "rename"
+  #[napi(ts_type = "'patch' | 'add' | 'delete' | 'rename'")]
+  pub op: String,
+  /// 相对路径 (rename 为目标路径，delete 为被删除的路径)
+  pub path: String,
+  /// rename 的源路径
+  pub from: Option<String>,
+  /// patch 的旧文件大小
+  pub old_size: Option<f64>,
+  /// 更新后的文件大小 (delete 为 0)
+  pub size: f64,
+  /// 预计下载字节数 (rename 与 delete 为 0)
+  pub estimated_size: f64,
+}
+
+impl From<manifest::UpdateOperation> for UpdateOperationJs {
+  fn from(operation: manifest::UpdateOperation) -> Self {
+    let estimated_size = operation.estimated_size() as f64;
+    let (op, path, from, old_size, size) = match operation {
+      manifest::UpdateOperation::Patch { path, old_size, new_size, .. } => ("patch", path, None, Some(old_size as f64), new_size),
+      manifest::UpdateOperation::Add { path, size, .. } => ("add", path, None, None, size),
+      manifest::UpdateOperation::Delete { path } => ("delete", path, None, None, 0),
+      manifest::UpdateOperation::Rename { from, to, size } => ("rename", to, Some(from), None, size),
+    };
+    Self { op: op.to_string(), path, from, old_size, size: size as f64, estimated_size }
+  }
+}
+
+/// JavaScript A/B 槽位
+#[napi(object, object_to_js = false)]
+pub struct AbSlotsJs {
+  /// 槽位 A 的目录
+  pub slot_a: PathLike,
+  /// 槽位 B 的目录
+  pub slot_b: PathLike,
+  /// 状态文件路径
+  pub state_file: PathLike,
+}
+
+/// JavaScript A/B 槽位状态
+#[napi(object)]
+pub struct SlotStateJs {
+  /// 最近确认可用的槽位
+  #[napi(ts_type = "'a' | 'b'")]
+  pub active: String,
+  /// 等待确认的槽位
+  #[napi(ts_type = "'a' | 'b'")]
+  pub pending: Option<String>,
+  /// 待确认槽位剩余的试运行次数
+  pub tries_left: u32,
+  /// 当前应运行的槽位 (试运行中为 pending，否则为 active)
+  #[napi(ts_type = "'a' | 'b'")]
+  pub current: String,
+  /// 当前应运行的槽位目录
+  pub current_dir: String,
+}
+
+/// JavaScript 更新计划
+#[napi(object)]
+pub struct UpdatePlanJs {
+  /// 按新路径排列的 patch/add/rename，随后是 delete
+  pub operations: Vec<UpdateOperationJs>,
+  /// 内容未变的文件数
+  pub unchanged: u32,
+  /// 预计下载总字节数
+  pub download_size: f64,
+}
+
+impl From<manifest::UpdatePlan> for UpdatePlanJs {
+  fn from(plan: manifest::UpdatePlan) -> Self {
+    Self {
+      operations: plan.operations.into_iter().map(UpdateOperationJs::from).collect(),
+      unchanged: plan.unchanged as u32,
+      download_size: plan.download_size as f64,
+    }
+  }
+}
+
+/// JavaScript 详细文件访问检查结构
+#[napi(object)]
+pub struct FileAccessJs {
+  pub exists: bool,
+  pub is_file: bool,
+  pub readable: bool,
+  pub is_symlink: bool,
+  pub permission_denied: bool,
+}
+
+/// JavaScript 补丁自检结果结构
+#[napi(object)]
+pub struct SelfCheckJs {
+  pub ok: bool,
+  pub reason: Option<String>,
+}
+
+/// JavaScript 补丁信息结构
+#[napi(object)]
+pub struct PatchInfoJs {
+  pub size: f64,
+  pub compressed: bool,
+  /// 容器类型: "bsrz"、"zstd" (旧版无头补丁)、"bsdiff40"、"vcdiff" 或 "encrypted"
+  pub container: String,
+  pub format_version: Option<u32>,
+  /// 负载压缩算法: "zstd"、"xz"、"brotli"、"gzip"、"bzip2" 或 "none"
+  pub compression: String,
+  pub flags: u32,
+  pub bsdiff_version: Option<String>,
+  pub source_size: Option<f64>,
+  pub target_size: Option<f64>,
+  /// 负载解压后的大小；BSDIFF40、加密与字典压缩的补丁省略
+  pub uncompressed_size: Option<f64>,
+  /// 补丁头记录的旧文件摘要 (十六进制，算法见 hashAlgorithm)
+  pub source_sha256: Option<String>,
+  /// 补丁头记录的新文件摘要 (十六进制，算法见 hashAlgorithm)
+  pub target_sha256: Option<String>,
+  /// 文件摘要算法: "sha256" 或 "blake3"，仅 bsrz 补丁提供
+  pub hash_algorithm: Option<String>,
+  /// 补丁头中的键值元数据，未设置时省略
+  pub metadata: Option<HashMap<String, String>>,
+  /// 压缩负载所用 zstd 字典的 ID，未使用字典时省略
+  pub dictionary_id: Option<u32>,
+  /// 补丁直接携带完整的新文件，不引用旧文件内容
+  pub full_file: bool,
+}
+
+impl From<utils::PatchInfo> for PatchInfoJs {
+  fn from(info: utils::PatchInfo) -> Self {
+    Self {
+      size: info.size as f64,
+      compressed: info.compressed,
+      container: info.container.to_string(),
+      format_version: info.format_version.map(u32::from),
+      compression: info.compression.to_string(),
+      flags: info.flags as u32,
+      bsdiff_version: info.bsdiff_version,
+      source_size: info.source_size.map(|size| size as f64),
+      target_size: info.target_size.map(|size| size as f64),
+      uncompressed_size: info.uncompressed_size.map(|size| size as f64),
+      source_sha256: info.source_sha256,
+      target_sha256: info.target_sha256,
+      hash_algorithm: info.hash_algorithm.map(str::to_string),
+      metadata: (!info.metadata.is_empty()).then(|| info.metadata.into_iter().collect()),
+      dictionary_id: info.dictionary_id,
+      full_file: info.full_file,
+    }
+  }
+}
+
+/// JavaScript inspectPatch 选项
+#[napi(object, object_to_js = false)]
+#[derive(Default)]
+pub struct InspectOptions {
+  /// 跳过前若干个控制块 (默认 0)，与 limit 配合分页
+  pub offset: Option<i64>,
+  /// 最多返回的控制块数 (默认 10000)
+  pub limit: Option<i64>,
+  /// 字典压缩的补丁需提供相同的字典
+  pub dictionary: Option<Buffer>,
+}
+
+/// JavaScript bsdiff 控制三元组
+#[napi(object)]
+pub struct ControlEntryJs {
+  /// 以旧文件为基准差分生成的字节数
+  pub copy_length: f64,
+  /// 补丁中直接携带的新增字节数
+  pub extra_length: f64,
+  /// 处理完本块后旧文件位置的偏移量 (可为负)
+  pub seek: f64,
+  /// 差分数据对应的旧文件起始位置
+  pub old_offset: f64,
+  /// 本块输出在新文件中的起始位置
+  pub new_offset: f64,
+}
+
+impl From<utils::ControlRecord> for ControlEntryJs {
+  fn from(record: utils::ControlRecord) -> Self {
+    Self {
+      copy_length: record.mix_len as f64,
+      extra_length: record.copy_len as f64,
+      seek: record.seek as f64,
+      old_offset: record.old_offset as f64,
+      new_offset: record.new_offset as f64,
+    }
+  }
+}
+
+/// JavaScript diff 统计
+#[napi(object)]
+pub struct DiffStatsJs {
+  /// 控制块 (控制三元组) 数
+  pub control_blocks: f64,
+  /// 以旧文件为基准差分生成的字节数
+  pub copied_bytes: f64,
+  /// 补丁中直接携带的新增字节数
+  pub literal_bytes: f64,
+  /// 最长的单段新增数据
+  pub largest_literal_run: f64,
+  /// 新增字节占新文件的比例 (0-1)，接近 1 时可改为完整下载
+  pub literal_ratio: f64,
+  /// compressionLevel 为 "auto" 时选定的压缩级别
+  pub compression_level: Option<i32>,
+  /// 补丁直接携带完整的新文件 (fallbackToFull 回退)
+  pub full_file: bool,
+}
+
+/// diff 返回的统计信息，附带自动选定的压缩级别
+fn diff_stats_js(stats: DiffStats, config: &OptimizationConfig) -> DiffStatsJs {
+  DiffStatsJs { compression_level: config.auto_level.as_ref().and_then(AutoLevel::chosen), ..stats.into() }
+}
+
+impl From<DiffStats> for DiffStatsJs {
+  fn from(stats: DiffStats) -> Self {
+    Self {
+      control_blocks: stats.control_blocks as f64,
+      copied_bytes: stats.copied_bytes as f64,
+      literal_bytes: stats.literal_bytes as f64,
+      largest_literal_run: stats.largest_literal_run as f64,
+      literal_ratio: stats.literal_ratio(),
+      compression_level: None,
+      full_file: stats.full_file,
+    }
+  }
+}
+
+/// JavaScript diff 预检结果
+#[napi(object)]
+pub struct PreflightJs {
+  /// 补丁先写入的快速临时目录；未启用或空间不足时省略 (直接写在补丁路径旁边)
+  pub temp_dir: Option<String>,
+  /// 临时目录的剩余空间 (字节)；不使用临时目录或无法获取时省略
+  pub temp_dir_free: Option<f64>,
+  /// 补丁路径所在的目录
+  pub destination_dir: String,
+  /// 目标目录的剩余空间 (字节)；无法获取时省略
+  pub destination_free: Option<f64>,
+  /// 预计补丁大小 (字节)
+  pub estimated_patch_size: f64,
+  /// 预计峰值堆内存 (字节，不含内存映射的输入)
+  pub estimated_peak_memory: f64,
+  /// 新旧文件都能内存映射
+  pub mmap: bool,
+  /// 目标目录的剩余空间足以容纳预计大小的补丁；为 false 时 diff 以 ERR_INSUFFICIENT_SPACE 失败
+  pub sufficient_space: bool,
+}
+
+impl From<preflight::Preflight> for PreflightJs {
+  fn from(report: preflight::Preflight) -> Self {
+    Self {
+      temp_dir: report.temp_dir.map(|dir| dir.to_string_lossy().into_owned()),
+      temp_dir_free: report.temp_dir_free.map(|free| free as f64),
+      destination_dir: report.destination_dir.to_string_lossy().into_owned(),
+      destination_free: report.destination_free.map(|free| free as f64),
+      estimated_patch_size: report.estimated_patch_size as f64,
+      estimated_peak_memory: report.estimated_peak_memory as f64,
+      mmap: report.mmap,
+      sufficient_space: report.sufficient_space,
+    }
+  }
+}
+
+/// JavaScript 相似度估算结果
+#[napi(object)]
+pub struct DeltaEstimateJs {
+  /// 相似度 (0-1)：新文件中可在旧文件里找到的内容比例
+  pub score: f64,
+  /// 预计补丁大小下限 (字节)
+  pub min_patch_size: f64,
+  /// 预计补丁大小上限 (字节)
+  pub max_patch_size: f64,
+}
+
+impl From<similarity::SimilarityEstimate> for DeltaEstimateJs {
+  fn from(estimate: similarity::SimilarityEstimate) -> Self {
+    Self {
+      score: estimate.score,
+      min_patch_size: estimate.min_patch_size as f64,
+      max_patch_size: estimate.max_patch_size as f64,
+    }
+  }
+}
+
+/// JavaScript diff 报告中的变更区域
+#[napi(object)]
+pub struct ChangedRegionJs {
+  /// 区域在新文件中的偏移
+  pub offset: f64,
+  /// 区域长度 (含区域内相距不超过 16 字节的未变字节)
+  pub length: f64,
+  /// 区域内相对旧文件改动的字节数
+  pub modified_bytes: f64,
+  /// 区域内直接插入的字节数
+  pub inserted_bytes: f64,
+  /// 区域内变更字节的香农熵 (比特/字节，0-8)
+  pub entropy: f64,
+}
+
+/// JavaScript diff 报告
+#[napi(object)]
+pub struct DiffReportJs {
+  pub old_size: f64,
+  pub new_size: f64,
+  /// bsdiff 控制块数
+  pub control_blocks: f64,
+  /// 与旧文件对应位置相同的新文件字节数
+  pub unchanged_bytes: f64,
+  /// 由旧文件对应位置改动而来的新文件字节数
+  pub modified_bytes: f64,
+  /// 旧文件中没有对应内容、直接插入的新文件字节数
+  pub inserted_bytes: f64,
+  /// 未被新文件引用的旧文件字节数
+  pub deleted_bytes: f64,
+  /// 变动率 (0-1)：修改与插入的字节占新文件的比例
+  pub churn: f64,
+  /// 所有变更字节的香农熵 (比特/字节，0-8)
+  pub changed_entropy: f64,
+  /// 变更区域总数
+  pub region_count: f64,
+  /// 变更区域 (按偏移排序)，最多 256 个最大的区域
+  pub regions: Vec<ChangedRegionJs>,
+}
+
+impl From<report::DiffReport> for DiffReportJs {
+  fn from(report: report::DiffReport) -> Self {
+    Self {
+      old_size: report.old_size as f64,
+      new_size: report.new_size as f64,
+      control_blocks: report.control_blocks as f64,
+      unchanged_bytes: report.unchanged_bytes as f64,
+      modified_bytes: report.modified_bytes as f64,
+      inserted_bytes: report.inserted_bytes as f64,
+      deleted_bytes: report.deleted_bytes as f64,
+      churn: report.churn(),
+      changed_entropy: report.changed_entropy,
+      region_count: report.region_count as f64,
+      regions: report
+        .regions
+        .into_iter()
+        .map(|region| ChangedRegionJs {
+          offset: region.offset as f64,
+          length: region.length as f64,
+          modified_bytes: region.modified_bytes as f64,
+          inserted_bytes: region.inserted_bytes as f64,
+          entropy: region.entropy,
+        })
+        .collect(),
+    }
+  }
+}
+
+/// JavaScript 压缩比信息结构
+#[napi(object)]
+pub struct CompressionRatioJs {
+  pub old_size: f64,
+  pub new_size: f64,
+  pub patch_size: f64,
+  pub ratio: f64,
+}
+
+// 异步版本，进度通过选项中的 onProgress 回调上报
+pub struct DiffTask {
+  old_str: Option<PathBuf>,
+  new_str: PathBuf,
+  patch: fd::FileArg,
+  config: OptimizationConfig,
+  stats: bool,
+}
+
+#[napi]
+impl Task for DiffTask {
+  type Output = Option<DiffStats>;
+  type JsValue = Option<DiffStatsJs>;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    call_bsdiff(self.old_str.as_deref(), &self.new_str, &self.patch, &self.config)?;
+    patch_stats(&self.patch, self.stats, self.config.dictionary.as_ref())
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output.map(|stats| diff_stats_js(stats, &self.config)))
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct DiffRangeTask {
+  old_str: PathBuf,
+  old_range: (u64, u64),
+  new_str: PathBuf,
+  new_range: (u64, u64),
+  patch: PathBuf,
+  config: OptimizationConfig,
+  stats: bool,
+}
+
+#[napi]
+impl Task for DiffRangeTask {
+  type Output = Option<DiffStats>;
+  type JsValue = Option<DiffStatsJs>;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    BsdiffRust::diff_range(&self.old_str, self.old_range, &self.new_str, self.new_range, &self.patch, &self.config)
+      .map_err(napi_error)?;
+    collect_stats(&self.patch, self.stats, self.config.dictionary.as_ref())
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output.map(|stats| diff_stats_js(stats, &self.config)))
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct PatchTask {
+  old_str: Option<PathBuf>,
+  new_str: fd::FileArg,
+  patch: fd::FileArg,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for PatchTask {
+  type Output = u64;
+  type JsValue = f64;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    call_bspatch(self.old_str.as_deref(), &self.new_str, &self.patch, &self.config)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output as f64)
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct PatchFromUrlTask {
+  old_str: PathBuf,
+  url: String,
+  new_str: PathBuf,
+  options: http::HttpOptions,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for PatchFromUrlTask {
+  type Output = u64;
+  type JsValue = f64;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    http::patch_from_url(&self.old_str, &self.url, &self.new_str, &self.options, &self.config).map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output as f64)
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct PatchChainTask {
+  old_str: PathBuf,
+  patches: Vec<PathBuf>,
+  new_str: PathBuf,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for PatchChainTask {
+  type Output = u64;
+  type JsValue = f64;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    BsdiffRust::patch_chain(&self.old_str, &self.patches, &self.new_str, &self.config)
+      .map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output as f64)
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct PatchInPlaceTask {
+  file: PathBuf,
+  patch: PathBuf,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for PatchInPlaceTask {
+  type Output = u64;
+  type JsValue = f64;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    BsdiffRust::patch_in_place(&self.file, &self.patch, &self.config)
+      .map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output as f64)
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct DiffBufferTask {
+  old: Buffer,
+  new: Buffer,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for DiffBufferTask {
+  type Output = Vec<u8>;
+  type JsValue = Buffer;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    BsdiffRust::diff_bytes(&self.old, &self.new, &self.config)
+      .map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output.into())
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct DiffBuffersToFileTask {
+  old: Buffer,
+  new: Buffer,
+  patch: PathBuf,
+  config: OptimizationConfig,
+  stats: bool,
+}
+
+#[napi]
+impl Task for DiffBuffersToFileTask {
+  type Output = Option<DiffStats>;
+  type JsValue = Option<DiffStatsJs>;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    BsdiffRust::diff_bytes_to_file(&self.old, &self.new, &self.patch, &self.config)
+      .map_err(napi_error)?;
+    collect_stats(&self.patch, self.stats, self.config.dictionary.as_ref())
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output.map(|stats| diff_stats_js(stats, &self.config)))
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct PatchBufferTask {
+  old: Buffer,
+  patch: Buffer,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for PatchBufferTask {
+  type Output = Vec<u8>;
+  type JsValue = Buffer;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    BsdiffRust::patch_bytes(&self.old, &self.patch, &self.config)
+      .map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output.into())
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct PatchBufferToFileTask {
+  old: Buffer,
+  patch: PathBuf,
+  new_str: PathBuf,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for PatchBufferToFileTask {
+  type Output = u64;
+  type JsValue = f64;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    BsdiffRust::patch_bytes_to_file(&self.old, &self.patch, &self.new_str, &self.config)
+      .map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output as f64)
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct PatchFileWithPatchBufferTask {
+  old_str: PathBuf,
+  patch: Buffer,
+  new_str: PathBuf,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for PatchFileWithPatchBufferTask {
+  type Output = u64;
+  type JsValue = f64;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    BsdiffRust::patch_file_with_patch_bytes(&self.old_str, &self.patch, &self.new_str, &self.config)
+      .map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output as f64)
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct PatchToBufferTask {
+  old_str: PathBuf,
+  patch: PathBuf,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for PatchToBufferTask {
+  type Output = Vec<u8>;
+  type JsValue = Buffer;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    BsdiffRust::patch_to_bytes(&self.old_str, &self.patch, &self.config)
+      .map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output.into())
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct PatchToArrayBufferTask {
+  old_str: PathBuf,
+  patch: PathBuf,
+  config: OptimizationConfig,
+  /// 预先在主线程分配的输出；补丁头未声明目标大小时为 None，先生成 Vec 再复制
+  output: Option<OutputArrayBuffer>,
+}
+
+#[napi]
+impl Task for PatchToArrayBufferTask {
+  type Output = Option<Vec<u8>>;
+  type JsValue = ArrayBuffer<'static>;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    match &mut self.output {
+      Some(output) => BsdiffRust::patch_into_slice(&self.old_str, &self.patch, output.as_mut_slice(), &self.config).map(|_| None),
+      None => BsdiffRust::patch_to_bytes(&self.old_str, &self.patch, &self.config).map(Some),
+    }
+    .map_err(napi_error)
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    match (output, self.output.take()) {
+      (None, Some(output)) => output.into_value(&env),
+      (data, _) => OutputArrayBuffer::copy_from(&env, &data.unwrap_or_default()),
+    }
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+
+  fn finally(mut self, env: Env) -> Result<()> {
+    match self.output.take() {
+      Some(output) => output.release(&env),
+      None => Ok(()),
+    }
+  }
+}
+
+pub struct SignPatchTask {
+  patch: Either<String, Buffer>,
+  private_key: Buffer,
+}
+
+#[napi]
+impl Task for SignPatchTask {
+  type Output = [u8; 64];
+  type JsValue = Buffer;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    sign_patch_input(&self.patch, &self.private_key)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output.to_vec().into())
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct DiffStreamTask {
+  old: StreamSource,
+  new: StreamSource,
+  output: StreamSink,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for DiffStreamTask {
+  type Output = ();
+  type JsValue = ();
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    let mut run = || -> std::result::Result<(), BsdiffError> {
+      let patch = BsdiffRust::diff_bytes(&self.old.load()?, &self.new.load()?, &self.config)?;
+      for chunk in patch.chunks(STREAM_CHUNK_SIZE) {
+        self.output.write_all(chunk)?;
+      }
+      Ok(self.output.finish()?)
+    };
+    run().map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, _output: Self::Output) -> Result<Self::JsValue> {
+    Ok(())
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct PatchStreamTask {
+  old: StreamSource,
+  patch: StreamSource,
+  output: StreamSink,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for PatchStreamTask {
+  type Output = ();
+  type JsValue = ();
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    let mut run = || -> std::result::Result<(), BsdiffError> {
+      let writer = BufWriter::with_capacity(STREAM_CHUNK_SIZE, &mut self.output);
+      BsdiffRust::patch_bytes_to_writer(&self.old.load()?, &self.patch.load()?, writer, &self.config)?;
+      Ok(self.output.finish()?)
+    };
+    run().map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, _output: Self::Output) -> Result<Self::JsValue> {
+    Ok(())
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct CreateManifestTask {
+  dir: PathBuf,
+}
+
+#[napi]
+impl Task for CreateManifestTask {
+  type Output = String;
+  type JsValue = String;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    run_create_manifest(&self.dir)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output)
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct EstimateDeltaTask {
+  old_str: PathBuf,
+  new_str: PathBuf,
+}
+
+#[napi]
+impl Task for EstimateDeltaTask {
+  type Output = similarity::SimilarityEstimate;
+  type JsValue = DeltaEstimateJs;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    similarity::estimate_similarity_files(&self.old_str, &self.new_str)
+      .map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output.into())
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct PreflightTask {
+  old_str: PathBuf,
+  new_str: PathBuf,
+  patch: PathBuf,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for PreflightTask {
+  type Output = preflight::Preflight;
+  type JsValue = PreflightJs;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    preflight::preflight(&self.old_str, &self.new_str, &self.patch, &self.config)
+      .map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output.into())
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct DiffReportTask {
+  old_str: PathBuf,
+  new_str: PathBuf,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for DiffReportTask {
+  type Output = report::DiffReport;
+  type JsValue = DiffReportJs;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    report::diff_report(&self.old_str, &self.new_str, &self.config)
+      .map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output.into())
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct TrainDictionaryTask {
+  patches: Vec<PathBuf>,
+  max_size: usize,
+}
+
+#[napi]
+impl Task for TrainDictionaryTask {
+  type Output = Vec<u8>;
+  type JsValue = Buffer;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    dictionary::train_dictionary(&self.patches, self.max_size).map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output.into())
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+/// 补丁来源：文件路径或内存中的补丁
+pub struct GetPatchInfoTask {
+  patch: Either<PathBuf, Buffer>,
+}
+
+#[napi]
+impl Task for GetPatchInfoTask {
+  type Output = utils::PatchInfo;
+  type JsValue = PatchInfoJs;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    match &self.patch {
+      Either::A(path) => get_patch_info_util(path),
+      Either::B(patch) => get_patch_info_from_bytes(patch),
+    }
+    .map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output.into())
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct InspectPatchTask {
+  patch: PathBuf,
+  offset: usize,
+  limit: usize,
+  dictionary: Option<Dictionary>,
+}
+
+#[napi]
+impl Task for InspectPatchTask {
+  type Output = Vec<utils::ControlRecord>;
+  type JsValue = Vec<ControlEntryJs>;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    utils::inspect_patch(&self.patch, self.offset, self.limit, self.dictionary.as_ref()).map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output.into_iter().map(ControlEntryJs::from).collect())
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct VerifyManifestTask {
+  dir: PathBuf,
+  manifest: String,
+}
+
+#[napi]
+impl Task for VerifyManifestTask {
+  type Output = Vec<ManifestMismatchJs>;
+  type JsValue = Vec<ManifestMismatchJs>;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    run_verify_manifest(&self.dir, &self.manifest)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output)
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct DiffDirTask {
+  old_dir: PathBuf,
+  new_dir: PathBuf,
+  bundle: PathBuf,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for DiffDirTask {
+  type Output = dir_diff::DirDiffSummary;
+  type JsValue = DirDiffSummaryJs;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    dir_diff::diff_dir(&self.old_dir, &self.new_dir, &self.bundle, &self.config)
+      .map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output.into())
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct CreateBundleTask {
+  entries: Vec<bundle::BundleEntry>,
+  bundle: PathBuf,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for CreateBundleTask {
+  type Output = Vec<bundle::BundleIndexEntry>;
+  type JsValue = Vec<BundleFileJs>;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    bundle::create_bundle(&self.entries, &self.bundle, &self.config).map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(bundle_index_js(output))
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct ApplyBundleTask {
+  bundle: PathBuf,
+  mapping: BTreeMap<String, bundle::BundleTarget>,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for ApplyBundleTask {
+  type Output = Vec<(String, u64)>;
+  type JsValue = Vec<BundleFileJs>;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    bundle::apply_bundle(&self.bundle, &self.mapping, &self.config).map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(bundle_applied_js(output))
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct ApplyBundleToDirTask {
+  bundle: PathBuf,
+  source_dir: PathBuf,
+  target_dir: PathBuf,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for ApplyBundleToDirTask {
+  type Output = dir_diff::DirDiffSummary;
+  type JsValue = DirDiffSummaryJs;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    bundle::apply_bundle_to_dir(&self.bundle, &self.source_dir, &self.target_dir, &self.config).map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output.into())
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct SignatureTask {
+  old_str: PathBuf,
+  signature_file: PathBuf,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for SignatureTask {
+  type Output = ();
+  type JsValue = ();
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    block_delta::write_signature(&self.old_str, &self.signature_file, &self.config)
+      .map(|_| ())
+      .map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, _output: Self::Output) -> Result<Self::JsValue> {
+    Ok(())
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct DeltaTask {
+  signature_file: PathBuf,
+  new_str: PathBuf,
+  patch: PathBuf,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for DeltaTask {
+  type Output = ();
+  type JsValue = ();
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    block_delta::delta(&self.signature_file, &self.new_str, &self.patch, &self.config).map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, _output: Self::Output) -> Result<Self::JsValue> {
+    Ok(())
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct PatchDirTask {
+  old_dir: PathBuf,
+  new_dir: PathBuf,
+  bundle: PathBuf,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for PatchDirTask {
+  type Output = dir_diff::DirDiffSummary;
+  type JsValue = DirDiffSummaryJs;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    dir_diff::patch_dir(&self.old_dir, &self.new_dir, &self.bundle, &self.config)
+      .map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output.into())
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct VerifyPatchTask {
+  old_str: PathBuf,
+  new_str: PathBuf,
+  patch: PathBuf,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for VerifyPatchTask {
+  type Output = bool;
+  type JsValue = bool;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    verify_patch_util(&self.old_str, &self.new_str, &self.patch, &self.config)
+      .map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output)
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct DiffBatchTask {
+  pairs: Vec<DiffPairJs>,
+  concurrency: Option<u32>,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for DiffBatchTask {
+  type Output = Vec<DiffBatchResultJs>;
+  type JsValue = Vec<DiffBatchResultJs>;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    run_diff_batch(std::mem::take(&mut self.pairs), self.concurrency, &self.config)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output)
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+pub struct VerifyPatchAgainstHashTask {
+  old_str: PathBuf,
+  patch: PathBuf,
+  expected_sha256: String,
+  config: OptimizationConfig,
+}
+
+#[napi]
+impl Task for VerifyPatchAgainstHashTask {
+  type Output = bool;
+  type JsValue = bool;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    verify_patch_against_hash_util(&self.old_str, &self.patch, &self.expected_sha256, &self.config)
+      .map_err(napi_error)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output)
+  }
+
+  fn reject(&mut self, env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(with_code(&env, err))
+  }
+}
+
+/// 将 AbortSignal 绑定到取消标记：排队中的任务由 napi 直接取消，运行中的任务在数据块之间检查标记。
+/// napi 只监听之后触发的 abort 事件，已中止的信号需读取 `aborted` 预先取消。
+fn bind_signal(env: &Env, signal: Option<Object>) -> Result<(Option<CancelToken>, Option<AbortSignal>)> {
+  let Some(object) = signal else {
+    return Ok((None, None));
+  };
+  let token = CancelToken::new();
+  if object.get_named_property::<Option<bool>>("aborted")?.unwrap_or(false) {
+    token.cancel();
+  }
+  let signal = unsafe { AbortSignal::from_napi_value(env.raw(), object.raw())? };
+  let flag = token.clone();
+  signal.on_abort(move || flag.cancel());
+  Ok((Some(token), Some(signal)))
+}
+
+/// 以 AbortSignal 的取消标记替换配置中的标记，保留 timeoutMs 设置的期限
+fn with_cancel(config: OptimizationConfig, cancel: Option<CancelToken>) -> OptimizationConfig {
+  let cancel = match (cancel, &config.cancel) {
+    (Some(token), Some(timed)) => Some(token.with_deadline_of(timed)),
+    (token, timed) => token.or_else(|| timed.clone()),
+  };
+  OptimizationConfig { cancel, ..config }
+}
+
+#[napi]
+pub fn diff(
+  env: Env,
+  old_str: Option<PathLike>,
+  new_str: PathLike,
+  patch: FileArg,
+  options: Option<DiffOptions>,
+  #[napi(ts_arg_type = "AbortSignal | undefined | null")] signal: Option<Object>,
+) -> Result<AsyncTask<DiffTask>> {
+  let old_str = old_str.as_ref().map(path_arg).transpose()?;
+  let new_str = path_arg(&new_str)?;
+  let (cancel, signal) = bind_signal(&env, signal)?;
+  let stats = wants_stats(&options);
+  let patch = patch_output_arg(patch, stats)?;
+  let config = with_cancel(diff_config(options)?, cancel);
+  Ok(AsyncTask::with_optional_signal(DiffTask { old_str, new_str, patch, config, stats }, signal))
+}
+
+/// 只对新旧文件中的指定区间生成补丁 (异步)
+#[napi]
+#[allow(clippy::too_many_arguments)]
+pub fn diff_range(
+  env: Env,
+  old_str: PathLike,
+  old_offset: i64,
+  old_length: i64,
+  new_str: PathLike,
+  new_offset: i64,
+  new_length: i64,
+  patch: PathLike,
+  options: Option<DiffOptions>,
+  #[napi(ts_arg_type = "AbortSignal | undefined | null")] signal: Option<Object>,
+) -> Result<AsyncTask<DiffRangeTask>> {
+  let (old_str, new_str, patch) = (path_arg(&old_str)?, path_arg(&new_str)?, path_arg(&patch)?);
+  let (old_range, new_range) = (file_range(old_offset, old_length)?, file_range(new_offset, new_length)?);
+  let (cancel, signal) = bind_signal(&env, signal)?;
+  let stats = wants_stats(&options);
+  let config = with_cancel(diff_config(options)?, cancel);
+  Ok(AsyncTask::with_optional_signal(
+    DiffRangeTask { old_str, old_range, new_str, new_range, patch, config, stats },
+    signal,
+  ))
+}
+
+/// 在单个 libuv 任务中并发生成多个补丁，避免在 JavaScript 循环中逐个调用 diff() 占满线程池
+#[napi]
+pub fn diff_batch(
+  env: Env,
+  pairs: Vec<DiffPairJs>,
+  options: Option<DiffOptions>,
+  concurrency: Option<u32>,
+  #[napi(ts_arg_type = "AbortSignal | undefined | null")] signal: Option<Object>,
+) -> Result<AsyncTask<DiffBatchTask>> {
+  let (cancel, signal) = bind_signal(&env, signal)?;
+  let config = with_cancel(diff_config(options)?, cancel);
+  Ok(AsyncTask::with_optional_signal(DiffBatchTask { pairs, concurrency, config }, signal))
+}
+
+#[napi]
+pub fn patch(
+  env: Env,
+  old_str: Option<PathLike>,
+  new_str: FileArg,
+  patch: FileArg,
+  options: Option<PatchOptions>,
+  #[napi(ts_arg_type = "AbortSignal | undefined | null")] signal: Option<Object>,
+) -> Result<AsyncTask<PatchTask>> {
+  let old_str = old_str.as_ref().map(path_arg).transpose()?;
+  let (new_str, patch) = (file_arg(new_str)?, file_arg(patch)?);
+  let (cancel, signal) = bind_signal(&env, signal)?;
+  let config = OptimizationConfig { cancel, ..patch_config(options)? };
+  Ok(AsyncTask::with_optional_signal(PatchTask { old_str, new_str, patch, config }, signal))
+}
+
+/// 从 http:// 或 https:// URL 流式下载补丁并应用，不保存补丁文件；旧文件与补丁头记录的摘要不符时在下载负载前中止
+#[napi]
+pub fn patch_from_url(
+  env: Env,
+  old_str: PathLike,
+  url: String,
+  new_str: PathLike,
+  url_options: Option<UrlOptionsJs>,
+  options: Option<PatchOptions>,
+  #[napi(ts_arg_type = "AbortSignal | undefined | null")] signal: Option<Object>,
+) -> Result<AsyncTask<PatchFromUrlTask>> {
+  let old_str = path_arg(&old_str)?;
+  let new_str = path_arg(&new_str)?;
+  let (cancel, signal) = bind_signal(&env, signal)?;
+  let config = OptimizationConfig { cancel, ..patch_config(options)? };
+  let url_options = url_options.map(http::HttpOptions::try_from).transpose()?.unwrap_or_default();
+  Ok(AsyncTask::with_optional_signal(PatchFromUrlTask { old_str, url, new_str, options: url_options, config }, signal))
+}
+
+#[napi]
+pub fn patch_chain(
+  env: Env,
+  old_str: PathLike,
+  patches: Vec<PathLike>,
+  new_str: PathLike,
+  options: Option<PatchOptions>,
+  #[napi(ts_arg_type = "AbortSignal | undefined | null")] signal: Option<Object>,
+) -> Result<AsyncTask<PatchChainTask>> {
+  let old_str = path_arg(&old_str)?;
+  let patches = patches.iter().map(path_arg).collect::<Result<Vec<_>>>()?;
+  let new_str = path_arg(&new_str)?;
+  let (cancel, signal) = bind_signal(&env, signal)?;
+  let config = OptimizationConfig { cancel, ..patch_config(options)? };
+  Ok(AsyncTask::with_optional_signal(PatchChainTask { old_str, patches, new_str, config }, signal))
+}
+
+#[napi]
+pub fn patch_in_place(
+  env: Env,
+  file: PathLike,
+  patch: PathLike,
+  options: Option<PatchOptions>,
+  #[napi(ts_arg_type = "AbortSignal | undefined | null")] signal: Option<Object>,
+) -> Result<AsyncTask<PatchInPlaceTask>> {
+  let file = path_arg(&file)?;
+  let patch = path_arg(&patch)?;
+  let (cancel, signal) = bind_signal(&env, signal)?;
+  let config = OptimizationConfig { cancel, ..patch_config(options)? };
+  Ok(AsyncTask::with_optional_signal(PatchInPlaceTask { file, patch, config }, signal))
+}
+
+#[napi]
+pub fn diff_buffer(old: Buffer, new: Buffer, options: Option<DiffOptions>) -> Result<AsyncTask<DiffBufferTask>> {
+  let config = diff_config(options)?;
+  Ok(AsyncTask::new(DiffBufferTask { old, new, config }))
+}
+
+#[napi]
+pub fn diff_buffers_to_file(old: Buffer, new: Buffer, patch: PathLike, options: Option<DiffOptions>) -> Result<AsyncTask<DiffBuffersToFileTask>> {
+  let patch = path_arg(&patch)?;
+  let stats = wants_stats(&options);
+  let config = diff_config(options)?;
+  Ok(AsyncTask::new(DiffBuffersToFileTask { old, new, patch, config, stats }))
+}
+
+#[napi]
+pub fn patch_buffer(old: Buffer, patch: Buffer, options: Option<PatchOptions>) -> Result<AsyncTask<PatchBufferTask>> {
+  let config = patch_config(options)?;
+  Ok(AsyncTask::new(PatchBufferTask { old, patch, config }))
+}
+
+#[napi]
+pub fn patch_buffer_to_file(old: Buffer, patch: PathLike, new_str: PathLike, options: Option<PatchOptions>) -> Result<AsyncTask<PatchBufferToFileTask>> {
+  let patch = path_arg(&patch)?;
+  let new_str = path_arg(&new_str)?;
+  let config = patch_config(options)?;
+  Ok(AsyncTask::new(PatchBufferToFileTask { old, patch, new_str, config }))
+}
+
+#[napi]
+pub fn patch_file_with_patch_buffer(old_str: PathLike, patch: Buffer, new_str: PathLike, options: Option<PatchOptions>) -> Result<AsyncTask<PatchFileWithPatchBufferTask>> {
+  let old_str = path_arg(&old_str)?;
+  let new_str = path_arg(&new_str)?;
+  let config = patch_config(options)?;
+  Ok(AsyncTask::new(PatchFileWithPatchBufferTask { old_str, patch, new_str, config }))
+}
+
+#[napi]
+pub fn patch_to_buffer(old_str: PathLike, patch: PathLike, options: Option<PatchOptions>) -> Result<AsyncTask<PatchToBufferTask>> {
+  let old_str = path_arg(&old_str)?;
+  let patch = path_arg(&patch)?;
+  let config = patch_config(options)?;
+  Ok(AsyncTask::new(PatchToBufferTask { old_str, patch, config }))
+}
+
+#[napi]
+pub fn patch_to_array_buffer(env: Env, old_str: PathLike, patch: PathLike, options: Option<PatchOptions>) -> Result<AsyncTask<PatchToArrayBufferTask>> {
+  let old_str = path_arg(&old_str)?;
+  let patch = path_arg(&patch)?;
+  let config = patch_config(options)?;
+  // 只读取补丁头；补丁不存在等错误留到工作线程中按常规路径报告
+  let output = match BsdiffRust::declared_output_size(&patch) {
+    Ok(Some(size)) => Some(allocate_output(&env, size)?),
+    _ => None,
+  };
+  Ok(AsyncTask::new(PatchToArrayBufferTask { old_str, patch, config, output }))
+}
+
+#[napi]
+pub fn sign_patch(patch: Either<String, Buffer>, private_key: Buffer) -> AsyncTask<SignPatchTask> {
+  AsyncTask::new(SignPatchTask { patch, private_key })
+}
+
+/// 从流生成补丁并写入流 (输入读入内存后 diff，补丁按块写出)
+#[napi]
+pub fn diff_stream(
+  env: Env,
+  #[napi(ts_arg_type = "StreamSource")] old: StreamSourceJs,
+  #[napi(ts_arg_type = "StreamSource")] new: StreamSourceJs,
+  #[napi(ts_arg_type = "StreamSink")] output: StreamSinkJs,
+  options: Option<DiffOptions>,
+  #[napi(ts_arg_type = "AbortSignal | undefined | null")] signal: Option<Object>,
+) -> Result<AsyncTask<DiffStreamTask>> {
+  let (cancel, signal) = bind_signal(&env, signal)?;
+  let config = with_cancel(diff_config(options)?, cancel);
+  let old = StreamSource::from_js(&env, old)?;
+  let new = StreamSource::from_js(&env, new)?;
+  let output = StreamSink::from_js(output)?;
+  Ok(AsyncTask::with_optional_signal(DiffStreamTask { old, new, output, config }, signal))
+}
+
+/// 从流读取补丁，应用后将新文件数据按块写入流
+#[napi]
+pub fn patch_stream(
+  env: Env,
+  #[napi(ts_arg_type = "StreamSource")] old: StreamSourceJs,
+  #[napi(ts_arg_type = "StreamSource")] patch: StreamSourceJs,
+  #[napi(ts_arg_type = "StreamSink")] output: StreamSinkJs,
+  options: Option<PatchOptions>,
+  #[napi(ts_arg_type = "AbortSignal | undefined | null")] signal: Option<Object>,
+) -> Result<AsyncTask<PatchStreamTask>> {
+  let (cancel, signal) = bind_signal(&env, signal)?;
+  let config = OptimizationConfig { cancel, ..patch_config(options)? };
+  let old = StreamSource::from_js(&env, old)?;
+  let patch = StreamSource::from_js(&env, patch)?;
+  let output = StreamSink::from_js(output)?;
+  Ok(AsyncTask::with_optional_signal(PatchStreamTask { old, patch, output, config }, signal))
+}
+
+#[napi]
+pub fn estimate_delta(old_str: PathLike, new_str: PathLike) -> Result<AsyncTask<EstimateDeltaTask>> {
+  let old_str = path_arg(&old_str)?;
+  let new_str = path_arg(&new_str)?;
+  Ok(AsyncTask::new(EstimateDeltaTask { old_str, new_str }))
+}
+
+#[napi]
+pub fn preflight(old_str: PathLike, new_str: PathLike, patch: PathLike, options: Option<DiffOptions>) -> Result<AsyncTask<PreflightTask>> {
+  let (old_str, new_str, patch) = (path_arg(&old_str)?, path_arg(&new_str)?, path_arg(&patch)?);
+  let config = diff_config(options)?;
+  Ok(AsyncTask::new(PreflightTask { old_str, new_str, patch, config }))
+}
+
+#[napi]
+pub fn diff_report(old_str: PathLike, new_str: PathLike, options: Option<DiffOptions>) -> Result<AsyncTask<DiffReportTask>> {
+  let old_str = path_arg(&old_str)?;
+  let new_str = path_arg(&new_str)?;
+  let config = diff_config(options)?;
+  Ok(AsyncTask::new(DiffReportTask { old_str, new_str, config }))
+}
+
+#[napi]
+pub fn diff_dir(old_dir: PathLike, new_dir: PathLike, bundle: PathLike, options: Option<DiffOptions>) -> Result<AsyncTask<DiffDirTask>> {
+  let old_dir = path_arg(&old_dir)?;
+  let new_dir = path_arg(&new_dir)?;
+  let bundle = path_arg(&bundle)?;
+  let config = diff_config(options)?;
+  Ok(AsyncTask::new(DiffDirTask { old_dir, new_dir, bundle, config }))
+}
+
+#[napi]
+pub fn patch_dir(old_dir: PathLike, new_dir: PathLike, bundle: PathLike, options: Option<PatchOptions>) -> Result<AsyncTask<PatchDirTask>> {
+  let old_dir = path_arg(&old_dir)?;
+  let new_dir = path_arg(&new_dir)?;
+  let bundle = path_arg(&bundle)?;
+  let config = patch_config(options)?;
+  Ok(AsyncTask::new(PatchDirTask { old_dir, new_dir, bundle, config }))
+}
+
+/// 为多个文件并行生成补丁并打包 (异步)
+#[napi]
+pub fn create_bundle(
+  entries: Vec<BundleEntryJs>,
+  bundle: PathLike,
+  options: Option<DiffOptions>,
+) -> Result<AsyncTask<CreateBundleTask>> {
+  let bundle = path_arg(&bundle)?;
+  let config = diff_config(options)?;
+  Ok(AsyncTask::new(CreateBundleTask { entries: bundle_entries(entries)?, bundle, config }))
+}
+
+/// 应用补丁包 (异步)
+#[napi]
+pub fn apply_bundle(
+  bundle: PathLike,
+  mapping: HashMap<String, BundleTargetJs>,
+  options: Option<PatchOptions>,
+) -> Result<AsyncTask<ApplyBundleTask>> {
+  let bundle = path_arg(&bundle)?;
+  let config = patch_config(options)?;
+  Ok(AsyncTask::new(ApplyBundleTask { bundle, mapping: bundle_mapping(mapping)?, config }))
+}
+
+/// 并排应用补丁包或目录差分包 (异步)
+#[napi]
+pub fn apply_bundle_to_dir(
+  bundle: PathLike,
+  source_dir: PathLike,
+  target_dir: PathLike,
+  options: Option<PatchOptions>,
+) -> Result<AsyncTask<ApplyBundleToDirTask>> {
+  let bundle = path_arg(&bundle)?;
+  let source_dir = path_arg(&source_dir)?;
+  let target_dir = path_arg(&target_dir)?;
+  let config = patch_config(options)?;
+  Ok(AsyncTask::new(ApplyBundleToDirTask { bundle, source_dir, target_dir, config }))
+}
+
+/// 计算旧文件的块签名 (异步)
+#[napi]
+pub fn signature(old_str: PathLike, signature_file: PathLike, options: Option<DiffOptions>) -> Result<AsyncTask<SignatureTask>> {
+  let old_str = path_arg(&old_str)?;
+  let signature_file = path_arg(&signature_file)?;
+  let config = diff_config(options)?;
+  Ok(AsyncTask::new(SignatureTask { old_str, signature_file, config }))
+}
+
+/// 只凭旧文件签名对新文件生成补丁 (异步)
+#[napi]
+pub fn delta(
+  env: Env,
+  signature_file: PathLike,
+  new_str: PathLike,
+  patch: PathLike,
+  options: Option<DiffOptions>,
+  #[napi(ts_arg_type = "AbortSignal | undefined | null")] signal: Option<Object>,
+) -> Result<AsyncTask<DeltaTask>> {
+  let signature_file = path_arg(&signature_file)?;
+  let new_str = path_arg(&new_str)?;
+  let patch = path_arg(&patch)?;
+  let (cancel, signal) = bind_signal(&env, signal)?;
+  let config = with_cancel(diff_config(options)?, cancel);
+  Ok(AsyncTask::with_optional_signal(DeltaTask { signature_file, new_str, patch, config }, signal))
+}
+
+/// 把 delta 生成的补丁原地应用到旧文件 (异步，同 patchInPlace)
+#[napi]
+pub fn apply(
+  env: Env,
+  old_str: PathLike,
+  patch: PathLike,
+  options: Option<PatchOptions>,
+  #[napi(ts_arg_type = "AbortSignal | undefined | null")] signal: Option<Object>,
+) -> Result<AsyncTask<PatchInPlaceTask>> {
+  patch_in_place(env, old_str, patch, options, signal)
+}
+
+#[napi]
+pub fn create_manifest(dir: PathLike) -> Result<AsyncTask<CreateManifestTask>> {
+  let dir = path_arg(&dir)?;
+  Ok(AsyncTask::new(CreateManifestTask { dir }))
+}
+
+/// 以一组相似资源的补丁为样本训练共享 zstd 字典，maxSize 为字典大小上限 (默认 110 KB)
+#[napi]
+pub fn train_dictionary(patches: Vec<PathLike>, max_size: Option<u32>) -> Result<AsyncTask<TrainDictionaryTask>> {
+  let patches = patches.iter().map(path_arg).collect::<Result<Vec<_>>>()?;
+  let max_size = max_size.map_or(dictionary::DEFAULT_MAX_SIZE, |size| size as usize);
+  Ok(AsyncTask::new(TrainDictionaryTask { patches, max_size }))
+}
+
+/// 异步获取补丁文件信息 (流式压缩的补丁需要解压负载才能得到 uncompressedSize)
+#[napi]
+pub fn get_patch_info(patch: PathLike) -> Result<AsyncTask<GetPatchInfoTask>> {
+  let patch = path_arg(&patch)?;
+  Ok(AsyncTask::new(GetPatchInfoTask { patch: Either::A(patch) }))
+}
+
+/// 异步获取内存中补丁的信息；负载未记录解压大小时需要解压计数，较大的补丁宜用此版本
+#[napi]
+pub fn get_patch_info_from_buffer(patch: Buffer) -> AsyncTask<GetPatchInfoTask> {
+  AsyncTask::new(GetPatchInfoTask { patch: Either::B(patch) })
+}
+
+#[napi]
+pub fn inspect_patch(patch: PathLike, options: Option<InspectOptions>) -> Result<AsyncTask<InspectPatchTask>> {
+  let patch = path_arg(&patch)?;
+  let (offset, limit, dictionary) = inspect_config(options)?;
+  Ok(AsyncTask::new(InspectPatchTask { patch, offset, limit, dictionary }))
+}
+
+#[napi]
+pub fn verify_manifest(dir: PathLike, manifest: String) -> Result<AsyncTask<VerifyManifestTask>> {
+  let dir = path_arg(&dir)?;
+  Ok(AsyncTask::new(VerifyManifestTask { dir, manifest }))
+}
+
+/// 应用补丁并与 newStr 比较；options 同 patch (strict、签名、解密密钥)。
+/// 旧版本的 signal 位于第 4 个参数，此处传入 AbortSignal 时仍按 signal 处理
+#[napi]
+pub fn verify_patch(
+  env: Env,
+  old_str: PathLike,
+  new_str: PathLike,
+  patch: PathLike,
+  #[napi(ts_arg_type = "PatchOptions | undefined | null")] options: Option<Object>,
+  #[napi(ts_arg_type = "AbortSignal | undefined | null")] signal: Option<Object>,
+) -> Result<AsyncTask<VerifyPatchTask>> {
+  let old_str = path_arg(&old_str)?;
+  let new_str = path_arg(&new_str)?;
+  let patch = path_arg(&patch)?;
+  let (options, signal) = match options {
+    Some(object) if is_abort_signal(&object)? => (None, Some(object)),
+    Some(object) => (Some(unsafe { PatchOptions::from_napi_value(env.raw(), object.raw())? }), signal),
+    None => (None, signal),
+  };
+  let (cancel, signal) = bind_signal(&env, signal)?;
+  let config = OptimizationConfig { cancel, ..patch_config(options)? };
+  Ok(AsyncTask::with_optional_signal(VerifyPatchTask { old_str, new_str, patch, config }, signal))
+}
+
+/// 判断参数是否为 AbortSignal (有 `aborted` 与 `addEventListener`，PatchOptions 没有这两个字段)
+fn is_abort_signal(object: &Object) -> Result<bool> {
+  Ok(object.has_named_property("aborted")? && object.has_named_property("addEventListener")?)
+}
+
+// fs.promises 风格的旧别名：与 diff/patch/verifyPatch 相同，保留给已有调用方
+
+/// @deprecated 请改用 diff (参数与结果相同)
+#[napi]
+pub fn diff_async(
+  env: Env,
+  old_str: Option<PathLike>,
+  new_str: PathLike,
+  patch: FileArg,
+  options: Option<DiffOptions>,
+  #[napi(ts_arg_type = "AbortSignal | undefined | null")] signal: Option<Object>,
+) -> Result<AsyncTask<DiffTask>> {
+  diff(env, old_str, new_str, patch, options, signal)
+}
+
+/// @deprecated 请改用 patch (参数与结果相同)
+#[napi]
+pub fn patch_async(
+  env: Env,
+  old_str: Option<PathLike>,
+  new_str: FileArg,
+  patch_file: FileArg,
+  options: Option<PatchOptions>,
+  #[napi(ts_arg_type = "AbortSignal | undefined | null")] signal: Option<Object>,
+) -> Result<AsyncTask<PatchTask>> {
+  patch(env, old_str, new_str, patch_file, options, signal)
+}
+
+/// @deprecated 请改用 verifyPatch (参数与结果相同)
+#[napi]
+pub fn verify_patch_async(
+  env: Env,
+  old_str: PathLike,
+  new_str: PathLike,
+  patch: PathLike,
+  #[napi(ts_arg_type = "PatchOptions | undefined | null")] options: Option<Object>,
+  #[napi(ts_arg_type = "AbortSignal | undefined | null")] signal: Option<Object>,
+) -> Result<AsyncTask<VerifyPatchTask>> {
+  verify_patch(env, old_str, new_str, patch, options, signal)
+}
+
+#[napi]
+pub fn verify_patch_against_hash(
+  env: Env,
+  old_str: PathLike,
+  patch: PathLike,
+  expected_sha256: String,
+  options: Option<PatchOptions>,
+  #[napi(ts_arg_type = "AbortSignal | undefined | null")] signal: Option<Object>,
+) -> Result<AsyncTask<VerifyPatchAgainstHashTask>> {
+  let old_str = path_arg(&old_str)?;
+  let patch = path_arg(&patch)?;
+  let (cancel, signal) = bind_signal(&env, signal)?;
+  let config = OptimizationConfig { cancel, ..patch_config(options)? };
+  Ok(AsyncTask::with_optional_signal(VerifyPatchAgainstHashTask { old_str, patch, expected_sha256, config }, signal))
+}
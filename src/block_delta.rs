@@ -0,0 +1,526 @@
+//! 块级增量 (rsync/rdiff 风格)：把旧文件切成定长块并计算滚动校验和，在新文件上逐字节滑动窗口查找
+//! 相同的块，输出 "复制旧块 / 原样插入" 指令。
+//!
+//! 不构建后缀数组，耗时与内存近似线性 (每块约 8 字节索引)，适合完整 bsdiff 难以承受的超大输入；
+//! 只能识别按块对齐的相同内容，补丁比 bsdiff 大。指令编码为普通的 bsdiff 控制流 (复制块对应全零的
+//! 差分数据)，补丁容器与应用方式与其他补丁完全相同。
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use rayon::prelude::*;
+
+use crate::bsdiff40::PatchFormat;
+use crate::bsdiff_rust::{BsdiffRust, OptimizationConfig};
+use crate::cancel::{self, CancelToken};
+use crate::control::ControlEntry;
+use crate::encryption;
+use crate::error::BsdiffError;
+use crate::header::{FileDigest, HashAlgorithm, PatchHeader, DIGEST_LEN};
+
+/// 签名文件魔数
+pub const SIGNATURE_MAGIC: [u8; 5] = *b"BSRS\x01";
+
+/// 当前签名文件格式版本
+pub const SIGNATURE_FORMAT_VERSION: u8 = 1;
+
+/// 强校验和长度 (字节)
+const STRONG_LEN: usize = 16;
+
+/// 块大小下限与上限 (字节)
+const MIN_BLOCK_SIZE: usize = 1 << 10;
+const MAX_BLOCK_SIZE: usize = 1 << 20;
+/// 新文件按此大小分段并行查找匹配，段边界处的匹配会丢失
+const SEGMENT_SIZE: usize = 32 << 20;
+/// 校验和预过滤位图的位数 (2^24 位 = 2 MB)，绝大多数未命中的窗口无需查索引
+const FILTER_BITS: u32 = 24;
+/// rsync 滚动校验和的字符偏移
+const CHAR_OFFSET: u32 = 31;
+
+/// 按旧文件大小选择块大小：约为 √len，取 2 的幂并限制在 1 KB 到 1 MB 之间
+///
+/// 块越小匹配越精细、补丁越小，但索引越大、查找越慢；√len 使块数与块大小同阶。
+pub fn block_size_for(old_len: u64) -> usize {
+    let sqrt = (old_len as f64).sqrt() as usize;
+    sqrt.next_power_of_two().clamp(MIN_BLOCK_SIZE, MAX_BLOCK_SIZE)
+}
+
+/// rsync 滚动校验和：窗口右移一个字节时 O(1) 更新
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Rollsum {
+    a: u32,
+    b: u32,
+    len: u32,
+}
+
+impl Rollsum {
+    pub(crate) fn of(data: &[u8]) -> Self {
+        let mut sum = Self::default();
+        for &byte in data {
+            sum.a = sum.a.wrapping_add(byte as u32 + CHAR_OFFSET);
+            sum.b = sum.b.wrapping_add(sum.a);
+        }
+        sum.len = data.len() as u32;
+        sum
+    }
+
+    /// 移出窗口首字节 `out`，移入新字节 `input`
+    #[inline]
+    pub(crate) fn roll(&mut self, out: u8, input: u8) {
+        let (out, input) = (out as u32 + CHAR_OFFSET, input as u32 + CHAR_OFFSET);
+        self.a = self.a.wrapping_add(input).wrapping_sub(out);
+        self.b = self.b.wrapping_add(self.a).wrapping_sub(self.len.wrapping_mul(out));
+    }
+
+    #[inline]
+    pub(crate) fn digest(&self) -> u32 {
+        (self.b << 16) | (self.a & 0xFFFF)
+    }
+}
+
+/// 旧文件各完整块的弱校验和索引 (末尾不足一块的部分不参与匹配)
+pub(crate) struct BlockIndex {
+    pub(crate) block_size: usize,
+    /// 按块序号排列的弱校验和
+    pub(crate) weak: Vec<u32>,
+    /// 按 (校验和, 块序号) 排序，用于二分查找
+    sorted: Vec<(u32, u32)>,
+    filter: Vec<u64>,
+}
+
+impl BlockIndex {
+    pub(crate) fn new(block_size: usize, weak: Vec<u32>) -> Self {
+        let mut sorted: Vec<(u32, u32)> = weak.iter().enumerate().map(|(block, &sum)| (sum, block as u32)).collect();
+        sorted.par_sort_unstable();
+        let mut filter = vec![0u64; 1 << (FILTER_BITS - 6)];
+        for &(sum, _) in &sorted {
+            let bit = Self::filter_bit(sum);
+            filter[bit / 64] |= 1 << (bit % 64);
+        }
+        Self { block_size, weak, sorted, filter }
+    }
+
+    /// 计算 `old` 每个完整块的弱校验和并建立索引
+    pub(crate) fn of(old: &[u8], block_size: usize) -> Self {
+        let weak = old.par_chunks_exact(block_size).map(|block| Rollsum::of(block).digest()).collect();
+        Self::new(block_size, weak)
+    }
+
+    #[inline]
+    fn filter_bit(sum: u32) -> usize {
+        (sum.wrapping_mul(0x9E37_79B1) >> (32 - FILTER_BITS)) as usize
+    }
+
+    /// 弱校验和为 `sum` 的所有块序号
+    #[inline]
+    fn candidates(&self, sum: u32) -> impl Iterator<Item = u32> + '_ {
+        let bit = Self::filter_bit(sum);
+        let hit = self.filter[bit / 64] & (1 << (bit % 64)) != 0;
+        let start = if hit { self.sorted.partition_point(|&(s, _)| s < sum) } else { self.sorted.len() };
+        self.sorted[start..].iter().take_while(move |&&(s, _)| s == sum).map(|&(_, block)| block)
+    }
+}
+
+/// 新文件中的一段：复制旧文件的第 `block` 块，或原样插入新文件的某个区间
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum BlockOp {
+    Copy(u32),
+    Literal(Range<usize>),
+}
+
+/// 在 `new[range]` 中查找与旧文件块相同的窗口；弱校验和命中后由 `confirm(块序号, 窗口)` 确认
+///
+/// 确认匹配后优先尝试紧随其后的旧块，连续未改动的区域不必查索引。
+pub(crate) fn find_blocks<F>(index: &BlockIndex, new: &[u8], range: Range<usize>, confirm: F) -> Vec<BlockOp>
+where
+    F: Fn(u32, &[u8]) -> bool,
+{
+    let bs = index.block_size;
+    let mut ops = Vec::new();
+    let (mut literal_start, mut pos) = (range.start, range.start);
+    let mut sum: Option<Rollsum> = None;
+    let mut expected: Option<u32> = None;
+    while pos + bs <= range.end {
+        let window = &new[pos..pos + bs];
+        let rolling = *sum.get_or_insert_with(|| Rollsum::of(window));
+        let digest = rolling.digest();
+        let matched = expected
+            .filter(|&next| index.weak.get(next as usize) == Some(&digest) && confirm(next, window))
+            .or_else(|| index.candidates(digest).find(|&block| confirm(block, window)));
+        if let Some(block) = matched {
+            if literal_start < pos {
+                ops.push(BlockOp::Literal(literal_start..pos));
+            }
+            ops.push(BlockOp::Copy(block));
+            pos += bs;
+            literal_start = pos;
+            sum = None;
+            expected = Some(block + 1);
+            continue;
+        }
+        if pos + bs < range.end {
+            sum.as_mut().expect("rolling sum is initialized").roll(new[pos], new[pos + bs]);
+        }
+        pos += 1;
+        expected = None;
+    }
+    if literal_start < range.end {
+        ops.push(BlockOp::Literal(literal_start..range.end));
+    }
+    ops
+}
+
+/// 把块指令写成 bsdiff 控制流：连续的复制合并为一段全零差分数据，其后的插入作为新增数据，
+/// 到下一次复制的旧文件位移记在 seek 中
+pub(crate) struct BlockEmitter<'a> {
+    new: &'a [u8],
+    block_size: u64,
+    mix_start: u64,
+    mix_len: u64,
+    literals: Vec<Range<usize>>,
+    literal_len: usize,
+}
+
+impl<'a> BlockEmitter<'a> {
+    pub(crate) fn new(new: &'a [u8], block_size: usize) -> Self {
+        Self { new, block_size: block_size as u64, mix_start: 0, mix_len: 0, literals: Vec::new(), literal_len: 0 }
+    }
+
+    pub(crate) fn push<W: Write + ?Sized>(&mut self, op: BlockOp, writer: &mut W) -> io::Result<()> {
+        match op {
+            BlockOp::Copy(block) => {
+                let offset = block as u64 * self.block_size;
+                if self.literals.is_empty() && self.mix_start + self.mix_len == offset {
+                    self.mix_len += self.block_size;
+                    return Ok(());
+                }
+                let seek = offset as i64 - (self.mix_start + self.mix_len) as i64;
+                self.flush(seek, writer)?;
+                self.mix_start = offset;
+                self.mix_len = self.block_size;
+            }
+            BlockOp::Literal(range) => {
+                self.literal_len += range.len();
+                self.literals.push(range);
+            }
+        }
+        Ok(())
+    }
+
+    /// 写出剩余的指令
+    pub(crate) fn finish<W: Write + ?Sized>(mut self, writer: &mut W) -> io::Result<()> {
+        if self.mix_len > 0 || self.literal_len > 0 {
+            self.flush(0, writer)?;
+        }
+        Ok(())
+    }
+
+    fn flush<W: Write + ?Sized>(&mut self, seek: i64, writer: &mut W) -> io::Result<()> {
+        ControlEntry { mix_len: self.mix_len, copy_len: self.literal_len as u64, seek }.write_to(writer)?;
+        io::copy(&mut io::repeat(0).take(self.mix_len), writer)?;
+        for range in self.literals.drain(..) {
+            writer.write_all(&self.new[range])?;
+        }
+        self.literal_len = 0;
+        Ok(())
+    }
+}
+
+fn thread_pool(threads: Option<usize>) -> io::Result<rayon::ThreadPool> {
+    let mut builder = rayon::ThreadPoolBuilder::new();
+    if let Some(threads) = threads {
+        builder = builder.num_threads(threads.max(1));
+    }
+    builder.build().map_err(io::Error::other)
+}
+
+/// 以块级增量生成原始 bsdiff 流；新文件按段在 `threads` 个线程上并行查找匹配，按顺序写出，
+/// 输出与线程数无关
+pub(crate) fn diff(
+    old: &[u8],
+    new: &[u8],
+    threads: Option<usize>,
+    cancel: Option<&CancelToken>,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    let pool = thread_pool(threads)?;
+    let block_size = block_size_for(old.len() as u64);
+    let index = pool.install(|| BlockIndex::of(old, block_size));
+    let confirm = |block: u32, window: &[u8]| {
+        let start = block as usize * block_size;
+        old[start..start + block_size] == *window
+    };
+    scan(&pool, &index, new, confirm, cancel, writer)
+}
+
+/// 分段并行查找匹配并按顺序写出控制流
+fn scan<F>(
+    pool: &rayon::ThreadPool,
+    index: &BlockIndex,
+    new: &[u8],
+    confirm: F,
+    cancel: Option<&CancelToken>,
+    writer: &mut dyn Write,
+) -> io::Result<()>
+where
+    F: Fn(u32, &[u8]) -> bool + Sync,
+{
+    let segment = SEGMENT_SIZE.max(index.block_size * 16);
+    let segments: Vec<Range<usize>> = (0..new.len()).step_by(segment).map(|start| start..(start + segment).min(new.len())).collect();
+    let mut emitter = BlockEmitter::new(new, index.block_size);
+    for batch in segments.chunks(pool.current_num_threads()) {
+        cancel::check(cancel)?;
+        let found: Vec<Vec<BlockOp>> =
+            pool.install(|| batch.par_iter().map(|range| find_blocks(index, new, range.clone(), &confirm)).collect());
+        for op in found.into_iter().flatten() {
+            emitter.push(op, writer)?;
+        }
+    }
+    emitter.finish(writer)
+}
+
+/// 块的强校验和：BLAKE3 截断到 128 位
+fn strong_sum(block: &[u8]) -> [u8; STRONG_LEN] {
+    let mut sum = [0u8; STRONG_LEN];
+    sum.copy_from_slice(&blake3::hash(block).as_bytes()[..STRONG_LEN]);
+    sum
+}
+
+/// 旧文件签名：只含各块的弱/强校验和与整个文件的摘要，不含文件内容
+///
+/// 客户端生成签名交给服务器，服务器据此对新文件生成增量 ([`delta`])，无需持有客户端的旧文件。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub block_size: usize,
+    /// 旧文件摘要，写入补丁头的源文件摘要，应用时据此校验旧文件
+    pub source: FileDigest,
+    pub weak: Vec<u32>,
+    pub strong: Vec<[u8; STRONG_LEN]>,
+}
+
+impl Signature {
+    /// 计算旧文件的签名，摘要算法取 `config.hash_algorithm`
+    pub fn of(old: &[u8], config: &OptimizationConfig) -> io::Result<Self> {
+        let pool = thread_pool(config.threads)?;
+        let block_size = block_size_for(old.len() as u64);
+        let (source, sums) = pool.install(|| {
+            rayon::join(
+                || FileDigest::of_bytes_with(config.hash_algorithm, old),
+                || -> Vec<(u32, [u8; STRONG_LEN])> {
+                    old.par_chunks_exact(block_size).map(|block| (Rollsum::of(block).digest(), strong_sum(block))).collect()
+                },
+            )
+        });
+        let (weak, strong) = sums.into_iter().unzip();
+        Ok(Self { block_size, source, weak, strong })
+    }
+
+    /// 布局: `SIGNATURE_MAGIC | version: u8 | 摘要算法: u8 | block_size: u32 LE | 旧文件大小: u64 LE |
+    /// 旧文件摘要 (32 字节) | 每块 weak: u32 LE + strong (16 字节)`；块数由文件大小与块大小得出
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&SIGNATURE_MAGIC)?;
+        writer.write_all(&[SIGNATURE_FORMAT_VERSION, self.source.algorithm.id()])?;
+        writer.write_all(&(self.block_size as u32).to_le_bytes())?;
+        writer.write_all(&self.source.size.to_le_bytes())?;
+        writer.write_all(&self.source.hash)?;
+        for (weak, strong) in self.weak.iter().zip(&self.strong) {
+            writer.write_all(&weak.to_le_bytes())?;
+            writer.write_all(strong)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, BsdiffError> {
+        let mut fixed = [0u8; SIGNATURE_MAGIC.len() + 2 + 4 + 8 + DIGEST_LEN];
+        reader.read_exact(&mut fixed).map_err(|_| "Not a signature file")?;
+        let (magic, rest) = fixed.split_at(SIGNATURE_MAGIC.len());
+        if magic != SIGNATURE_MAGIC {
+            return Err("Not a signature file".into());
+        }
+        if rest[0] > SIGNATURE_FORMAT_VERSION {
+            return Err(format!("Unsupported signature format version {} (max {})", rest[0], SIGNATURE_FORMAT_VERSION).into());
+        }
+        let algorithm = HashAlgorithm::from_id(rest[1]).ok_or_else(|| format!("Unknown signature hash algorithm: {}", rest[1]))?;
+        let block_size = u32::from_le_bytes(rest[2..6].try_into().expect("4-byte block size")) as usize;
+        let size = u64::from_le_bytes(rest[6..14].try_into().expect("8-byte size"));
+        let mut hash = [0u8; DIGEST_LEN];
+        hash.copy_from_slice(&rest[14..]);
+        if !(MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&block_size) {
+            return Err(format!("Corrupt signature: invalid block size {}", block_size).into());
+        }
+
+        let blocks = usize::try_from(size / block_size as u64)?;
+        let mut sums = Vec::new();
+        reader.take(blocks as u64 * (4 + STRONG_LEN) as u64).read_to_end(&mut sums)?;
+        if sums.len() != blocks * (4 + STRONG_LEN) || reader.read(&mut [0u8; 1])? != 0 {
+            return Err("Corrupt signature: block count does not match the file size".into());
+        }
+        let (weak, strong) = sums
+            .chunks_exact(4 + STRONG_LEN)
+            .map(|entry| {
+                let weak = u32::from_le_bytes(entry[..4].try_into().expect("4-byte checksum"));
+                let strong: [u8; STRONG_LEN] = entry[4..].try_into().expect("16-byte checksum");
+                (weak, strong)
+            })
+            .unzip();
+        Ok(Self { block_size, source: FileDigest { size, algorithm, hash }, weak, strong })
+    }
+}
+
+/// 计算旧文件签名并写入 `signature_file`，返回签名
+pub fn write_signature(
+    old_file: &Path,
+    signature_file: &Path,
+    config: &OptimizationConfig
+) -> Result<Signature, BsdiffError> {
+    if !Path::new(old_file).exists() {
+        return Err(BsdiffError::FileNotFound { kind: "Old file", path: old_file.display().to_string() });
+    }
+    BsdiffRust::prepare_output(signature_file, config)?;
+    let old = BsdiffRust::create_single_memory_map(old_file)?;
+    let signature = Signature::of(&old, config)?;
+
+    let out_path = BsdiffRust::get_optimal_output_path(signature_file, config, (signature.weak.len() * (4 + STRONG_LEN)) as u64)?;
+    let result = (|| -> io::Result<()> {
+        let mut writer = BufWriter::with_capacity(64 * 1024, File::create(&out_path)?);
+        signature.write_to(&mut writer)?;
+        writer.flush()
+    })();
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&out_path);
+        return Err(e.into());
+    }
+    BsdiffRust::finalize_output(&out_path, signature_file, config)?;
+    Ok(signature)
+}
+
+/// 只凭旧文件签名对新文件生成补丁：弱校验和命中后以强校验和确认，其余内容原样插入
+///
+/// 补丁与 `algorithm: blockdelta` 生成的补丁格式相同，补丁头记录签名中的旧文件摘要，
+/// 可用任何 patch API 应用。VCDIFF 需要旧文件内容，不支持。
+pub fn delta(
+    signature_file: &Path,
+    new_file: &Path,
+    patch_file: &Path,
+    config: &OptimizationConfig
+) -> Result<(), BsdiffError> {
+    if config.format == PatchFormat::Vcdiff {
+        return Err("VCDIFF patches need the old file contents and cannot be generated from a signature".into());
+    }
+    if !Path::new(new_file).exists() {
+        return Err(BsdiffError::FileNotFound { kind: "New file", path: new_file.display().to_string() });
+    }
+    let signature = Signature::read_from(&mut BufReader::new(File::open(signature_file)?))
+        .map_err(|e| format!("{}: {}", signature_file.display(), e))?;
+    BsdiffRust::prepare_output(patch_file, config)?;
+    let new = BsdiffRust::create_single_memory_map(new_file)?;
+
+    let patch = delta_bytes(&signature, &new, config)?;
+    let out_path = BsdiffRust::get_optimal_output_path(patch_file, config, patch.len() as u64)?;
+    if let Err(e) = std::fs::write(&out_path, &patch) {
+        let _ = std::fs::remove_file(&out_path);
+        return Err(e.into());
+    }
+    BsdiffRust::finalize_output(&out_path, patch_file, config)
+}
+
+fn delta_bytes(signature: &Signature, new: &[u8], config: &OptimizationConfig) -> Result<Vec<u8>, BsdiffError> {
+    if let Some(key) = &config.encryption_key {
+        let patch = delta_bytes(signature, new, &OptimizationConfig { encryption_key: None, ..config.clone() })?;
+        return encryption::seal(key, &patch);
+    }
+    let pool = thread_pool(config.threads)?;
+    let index = pool.install(|| BlockIndex::new(signature.block_size, signature.weak.clone()));
+    let confirm = |block: u32, window: &[u8]| strong_sum(window) == signature.strong[block as usize];
+    let header = PatchHeader::with_digests(signature.source, FileDigest::of_bytes_with(signature.source.algorithm, new));
+    BsdiffRust::encode_patch(Vec::new(), header, config, &[], new.len() as u64, |payload| {
+        scan(&pool, &index, new, confirm, config.cancel.as_ref(), payload)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_delta_round_trip() {
+        let old: Vec<u8> = (0..400_000u32).flat_map(|i| (i.wrapping_mul(2_654_435_761) >> 13).to_le_bytes()).collect();
+        let mut new = old.clone();
+        new[100_000..100_050].fill(0xAA);
+        new.splice(700_000..700_000, b"inserted bytes".iter().copied());
+        new.drain(1_200_000..1_210_000);
+        new.extend_from_slice(b"appended tail");
+
+        let mut raw = Vec::new();
+        diff(&old, &new, Some(2), None, &mut raw).unwrap();
+        let mut patched = Vec::new();
+        bsdiff::patch(&old, &mut raw.as_slice(), &mut patched).unwrap();
+        assert_eq!(patched, new);
+        // 只有改动附近的块需要原样插入
+        let mut literal = 0;
+        crate::bsdiff40::for_each_entry(&raw, |entry, _, _| {
+            literal += entry.copy_len;
+            Ok(())
+        })
+        .unwrap();
+        assert!(literal < 8 * block_size_for(old.len() as u64) as u64, "{} literal bytes", literal);
+
+        // 旧文件为空、新文件为空
+        for (old, new) in [(&[][..], &new[..1000]), (&old[..], &[][..])] {
+            let mut raw = Vec::new();
+            diff(old, new, None, None, &mut raw).unwrap();
+            let mut patched = Vec::new();
+            bsdiff::patch(old, &mut raw.as_slice(), &mut patched).unwrap();
+            assert_eq!(patched, new);
+        }
+    }
+
+    #[test]
+    fn test_signature_delta_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        let old: Vec<u8> = (0..200_000u32).flat_map(|i| (i.wrapping_mul(2_654_435_761) >> 7).to_le_bytes()).collect();
+        let mut new = old.clone();
+        new[300_000..300_010].fill(0);
+        new.splice(10_000..10_000, b"server side".iter().copied());
+        std::fs::write(path("old"), &old).unwrap();
+        std::fs::write(path("new"), &new).unwrap();
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+
+        // 签名远小于旧文件，读回与写入一致
+        let signature = write_signature(&path("old"), &path("sig"), &config).unwrap();
+        assert!(std::fs::metadata(path("sig")).unwrap().len() < old.len() as u64 / 50);
+        let read = Signature::read_from(&mut File::open(path("sig")).unwrap()).unwrap();
+        assert_eq!(read, signature);
+
+        delta(&path("sig"), &path("new"), &path("patch"), &config).unwrap();
+        assert!(std::fs::metadata(path("patch")).unwrap().len() < 16 * 1024);
+        BsdiffRust::patch_in_place(&path("old"), &path("patch"), &config).unwrap();
+        assert_eq!(std::fs::read(path("old")).unwrap(), new);
+
+        // 旧文件已变化时与补丁头记录的大小不符；截断的签名与 VCDIFF 被拒绝
+        let err = BsdiffRust::patch_in_place(&path("old"), &path("patch"), &config).unwrap_err();
+        assert!(matches!(err, BsdiffError::BaseMismatch { .. }));
+        let data = std::fs::read(path("sig")).unwrap();
+        std::fs::write(path("short.sig"), &data[..data.len() - 1]).unwrap();
+        assert!(delta(&path("short.sig"), &path("new"), &path("p2"), &config).is_err());
+        assert!(delta(&path("new"), &path("new"), &path("p2"), &config).is_err());
+        let vcdiff = OptimizationConfig { format: PatchFormat::Vcdiff, ..config.clone() };
+        assert!(delta(&path("sig"), &path("new"), &path("p2"), &vcdiff).is_err());
+    }
+
+    #[test]
+    fn test_rollsum_matches_recomputed() {
+        let data: Vec<u8> = (0..5000u32).map(|i| (i * 7 + i / 13) as u8).collect();
+        let mut sum = Rollsum::of(&data[..1024]);
+        for start in 1..data.len() - 1024 {
+            sum.roll(data[start - 1], data[start + 1023]);
+            assert_eq!(sum.digest(), Rollsum::of(&data[start..start + 1024]).digest());
+        }
+        assert_eq!(block_size_for(0), MIN_BLOCK_SIZE);
+        assert_eq!(block_size_for(100 << 20), 16 << 10);
+        assert_eq!(block_size_for(50 << 30), 256 << 10);
+    }
+}
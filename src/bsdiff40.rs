@@ -0,0 +1,294 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::bsdiff_rust::{BsdiffRust, OptimizationConfig};
+use crate::control::{offtin, offtout, ControlEntry, CONTROL_SIZE};
+use crate::error::BsdiffError;
+use crate::formats::vcdiff;
+use crate::header::PatchHeader;
+
+/// 经典 BSDIFF40 补丁魔数
+pub const BSDIFF40_MAGIC: [u8; 8] = *b"BSDIFF40";
+
+/// BSDIFF40 头部长度: 魔数 | 控制块压缩长度 | 差分块压缩长度 | 新文件大小
+const BSDIFF40_HEADER_SIZE: usize = 32;
+
+/// 补丁格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchFormat {
+    /// 本库的补丁头 + Zstd 格式
+    Zstd,
+    /// 经典 bsdiff 4.x 格式 (三段 bzip2)
+    Bsdiff40,
+    /// VCDIFF (RFC 3284)，与 xdelta3 互通
+    Vcdiff,
+}
+
+impl PatchFormat {
+    pub fn parse(name: &str) -> Result<Self, BsdiffError> {
+        match name {
+            "zstd" => Ok(Self::Zstd),
+            "bsdiff40" => Ok(Self::Bsdiff40),
+            "vcdiff" => Ok(Self::Vcdiff),
+            other => Err(format!("Unknown patch format: {} (expected \"zstd\", \"bsdiff40\" or \"vcdiff\")", other).into()),
+        }
+    }
+}
+
+/// 在两种补丁格式之间转换，无需源文件
+///
+/// 原始 bsdiff 控制/差分/新增数据保持不变。转为 BSDIFF40 时补丁头中的摘要会丢失；
+/// 从 BSDIFF40 转入时写入不含摘要的默认补丁头。VCDIFF 的 COPY/ADD 需要旧文件内容才能与
+/// bsdiff 流互转，不支持转换；使用可执行文件变换或压缩包变换的补丁也无法转为 BSDIFF40。
+pub fn convert_patch(
+    in_patch: &Path,
+    out_patch: &Path,
+    target: PatchFormat,
+    config: &OptimizationConfig,
+) -> Result<(), BsdiffError> {
+    if target == PatchFormat::Vcdiff {
+        return Err("Converting to VCDIFF requires the old file; run diff with format \"vcdiff\" instead".into());
+    }
+    let (header, raw) = read_raw_patch(in_patch)?;
+    if target == PatchFormat::Bsdiff40 && header.as_ref().is_some_and(|h| h.exe_transform.is_some()) {
+        return Err("Patches using the executable transform cannot be converted to BSDIFF40".into());
+    }
+    if target == PatchFormat::Bsdiff40 && header.as_ref().is_some_and(|h| h.archive_transform.is_some()) {
+        return Err("Patches using the archive transform cannot be converted to BSDIFF40".into());
+    }
+
+    // 目标格式由参数决定，不受配置中的输出格式影响
+    let config = &OptimizationConfig { format: PatchFormat::Zstd, ..config.clone() };
+    let out_path = BsdiffRust::get_optimal_output_path(out_patch, config, raw.len() as u64)?;
+    let mut writer = BufWriter::with_capacity(64 * 1024, File::create(&out_path)?);
+    match target {
+        PatchFormat::Zstd => {
+            let header = header.unwrap_or_default();
+            let new_len = new_size(&raw)?;
+            writer = BsdiffRust::encode_patch(writer, header, config, &[], new_len, |payload| payload.write_all(&raw))?;
+        }
+        PatchFormat::Bsdiff40 => writer.write_all(&to_bsdiff40(&raw)?)?,
+        PatchFormat::Vcdiff => unreachable!("rejected above"),
+    }
+    writer.flush()?;
+    drop(writer);
+
+    BsdiffRust::finalize_output(&out_path, out_patch, config)
+}
+
+/// 判断文件是否为 BSDIFF40 补丁
+pub fn is_bsdiff40(data: &[u8]) -> bool {
+    data.starts_with(&BSDIFF40_MAGIC)
+}
+
+/// 读取任意格式补丁，返回补丁头 (若有) 与原始 bsdiff 流
+fn read_raw_patch(patch_file: &Path) -> Result<(Option<PatchHeader>, Vec<u8>), BsdiffError> {
+    let mut magic = [0u8; BSDIFF40_MAGIC.len()];
+    let mut file = File::open(patch_file)?;
+    let is_classic = file.read_exact(&mut magic).is_ok() && is_bsdiff40(&magic);
+    if vcdiff::is_vcdiff(&magic) {
+        return Err("Converting VCDIFF patches is not supported".into());
+    }
+
+    if is_classic {
+        return Ok((None, from_bsdiff40(&std::fs::read(patch_file)?)?));
+    }
+    let (header, mut decoder) = BsdiffRust::open_patch(patch_file, None)?;
+    let mut raw = Vec::new();
+    decoder.read_to_end(&mut raw)?;
+    Ok((header, raw))
+}
+
+/// 遍历原始 bsdiff 流，返回其生成的新文件大小
+fn new_size(raw: &[u8]) -> io::Result<u64> {
+    let mut size = 0u64;
+    for_each_entry(raw, |entry, _, _| {
+        size += entry.mix_len + entry.copy_len;
+        Ok(())
+    })?;
+    Ok(size)
+}
+
+/// 逐个访问控制块及其差分/新增数据
+pub(crate) fn for_each_entry<F>(raw: &[u8], mut visit: F) -> io::Result<()>
+where
+    F: FnMut(ControlEntry, &[u8], &[u8]) -> io::Result<()>,
+{
+    let corrupt = || io::Error::new(io::ErrorKind::InvalidData, "Corrupt bsdiff stream");
+    let mut pos = 0usize;
+    while pos < raw.len() {
+        let control: &[u8; CONTROL_SIZE] = raw
+            .get(pos..pos + CONTROL_SIZE)
+            .and_then(|c| c.try_into().ok())
+            .ok_or_else(corrupt)?;
+        let entry = ControlEntry::from_bytes(control);
+        pos += CONTROL_SIZE;
+        let mix_end = usize::try_from(entry.mix_len).ok().and_then(|len| pos.checked_add(len)).ok_or_else(corrupt)?;
+        let copy_end = usize::try_from(entry.copy_len).ok().and_then(|len| mix_end.checked_add(len)).ok_or_else(corrupt)?;
+        let diff = raw.get(pos..mix_end).ok_or_else(corrupt)?;
+        let extra = raw.get(mix_end..copy_end).ok_or_else(corrupt)?;
+        visit(entry, diff, extra)?;
+        pos = copy_end;
+    }
+    Ok(())
+}
+
+/// 原始 bsdiff 流 → BSDIFF40
+pub(crate) fn to_bsdiff40(raw: &[u8]) -> Result<Vec<u8>, BsdiffError> {
+    let mut controls = Vec::new();
+    let mut diffs = Vec::new();
+    let mut extras = Vec::new();
+    let mut new_size = 0u64;
+    for_each_entry(raw, |entry, diff, extra| {
+        controls.extend_from_slice(&entry.to_bytes());
+        diffs.extend_from_slice(diff);
+        extras.extend_from_slice(extra);
+        new_size += entry.mix_len + entry.copy_len;
+        Ok(())
+    })?;
+
+    let controls = compress(&controls)?;
+    let diffs = compress(&diffs)?;
+    let extras = compress(&extras)?;
+
+    let mut out = Vec::with_capacity(BSDIFF40_HEADER_SIZE + controls.len() + diffs.len() + extras.len());
+    out.extend_from_slice(&BSDIFF40_MAGIC);
+    out.extend_from_slice(&offtout(controls.len() as i64));
+    out.extend_from_slice(&offtout(diffs.len() as i64));
+    out.extend_from_slice(&offtout(new_size as i64));
+    out.extend_from_slice(&controls);
+    out.extend_from_slice(&diffs);
+    out.extend_from_slice(&extras);
+    Ok(out)
+}
+
+/// BSDIFF40 → 原始 bsdiff 流
+pub(crate) fn from_bsdiff40(data: &[u8]) -> Result<Vec<u8>, BsdiffError> {
+    if data.len() < BSDIFF40_HEADER_SIZE || !is_bsdiff40(data) {
+        return Err("Corrupt BSDIFF40 patch: bad header".into());
+    }
+    let field = |i: usize| offtin(data[8 + i * 8..16 + i * 8].try_into().expect("8-byte field"));
+    let (controls_len, diffs_len, new_size) = (field(0), field(1), field(2));
+    let controls_end = usize::try_from(controls_len).ok().and_then(|len| BSDIFF40_HEADER_SIZE.checked_add(len));
+    let diffs_end = controls_end.zip(usize::try_from(diffs_len).ok()).and_then(|(end, len)| end.checked_add(len));
+    let (controls_end, diffs_end) = match (controls_end, diffs_end) {
+        (Some(c), Some(d)) if d <= data.len() && new_size >= 0 => (c, d),
+        _ => return Err("Corrupt BSDIFF40 patch: bad block lengths".into()),
+    };
+
+    // 差分与新增数据都不会超过新文件大小；控制块数不超过新文件字节数 + 1 (末尾可有一个空块)
+    let new_size = new_size as u64;
+    let controls_limit = new_size.saturating_add(1).saturating_mul(CONTROL_SIZE as u64);
+    let controls = decompress(&data[BSDIFF40_HEADER_SIZE..controls_end], controls_limit, "control")?;
+    let diffs = decompress(&data[controls_end..diffs_end], new_size, "diff")?;
+    let extras = decompress(&data[diffs_end..], new_size, "extra")?;
+    if controls.len() % CONTROL_SIZE != 0 {
+        return Err("Corrupt BSDIFF40 patch: truncated control block".into());
+    }
+
+    let mut raw = Vec::with_capacity(controls.len() + diffs.len() + extras.len());
+    let (mut diff_pos, mut extra_pos, mut produced) = (0usize, 0usize, 0u64);
+    for control in controls.chunks_exact(CONTROL_SIZE) {
+        let entry = ControlEntry::from_bytes(control.try_into().expect("control block"));
+        let diff = usize::try_from(entry.mix_len).ok()
+            .and_then(|len| diffs.get(diff_pos..diff_pos.checked_add(len)?))
+            .ok_or("Corrupt BSDIFF40 patch: diff block too short")?;
+        let extra = usize::try_from(entry.copy_len).ok()
+            .and_then(|len| extras.get(extra_pos..extra_pos.checked_add(len)?))
+            .ok_or("Corrupt BSDIFF40 patch: extra block too short")?;
+        raw.extend_from_slice(control);
+        raw.extend_from_slice(diff);
+        raw.extend_from_slice(extra);
+        diff_pos += diff.len();
+        extra_pos += extra.len();
+        produced += entry.mix_len + entry.copy_len;
+    }
+    if produced != new_size {
+        return Err(format!("Corrupt BSDIFF40 patch: controls produce {} bytes, header declares {}", produced, new_size).into());
+    }
+    Ok(raw)
+}
+
+/// 把一段数据压缩为 bzip2 流 (900KB 块)
+fn compress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::best());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// 解压一段 bzip2 数据 (支持串联的流)；解压结果超过 `limit` 字节即视为损坏，不会无限制地分配内存
+fn decompress(data: &[u8], limit: u64, block: &str) -> Result<Vec<u8>, BsdiffError> {
+    let mut out = Vec::new();
+    bzip2::read::MultiBzDecoder::new(data)
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut out)
+        .map_err(|e| format!("Corrupt BSDIFF40 patch: {} block: {}", block, e))?;
+    if out.len() as u64 > limit {
+        return Err(format!("Corrupt BSDIFF40 patch: {} block is larger than the declared new file", block).into());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_convert_round_trip() {
+        let old_content = b"classic bsdiff interop old ".repeat(40);
+        let new_content = b"classic bsdiff interop NEW!".repeat(44);
+        let old_file = NamedTempFile::new().unwrap();
+        let new_file = NamedTempFile::new().unwrap();
+        let patch_file = NamedTempFile::new().unwrap();
+        fs::write(&old_file, &old_content).unwrap();
+        fs::write(&new_file, &new_content).unwrap();
+
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        BsdiffRust::diff_optimized(
+            old_file.path(),
+            new_file.path(),
+            patch_file.path(),
+            &config
+        ).unwrap();
+
+        let classic = NamedTempFile::new().unwrap();
+        convert_patch(patch_file.path(), classic.path(), PatchFormat::Bsdiff40, &config).unwrap();
+        let classic_bytes = fs::read(classic.path()).unwrap();
+        assert!(is_bsdiff40(&classic_bytes));
+
+        // BSDIFF40 的三段数据可直接还原出同样的原始流
+        let (_, raw) = read_raw_patch(patch_file.path()).unwrap();
+        assert_eq!(from_bsdiff40(&classic_bytes).unwrap(), raw);
+
+        let back = NamedTempFile::new().unwrap();
+        convert_patch(classic.path(), back.path(), PatchFormat::Zstd, &config).unwrap();
+        let generated = NamedTempFile::new().unwrap();
+        BsdiffRust::patch_optimized(
+            old_file.path(),
+            generated.path(),
+            back.path(),
+            &config
+        ).unwrap();
+        assert_eq!(fs::read(generated.path()).unwrap(), new_content);
+
+        assert!(PatchFormat::parse("gzip").is_err());
+
+        // 新增数据解压后超过头部声明的新文件大小：视为损坏，不继续解压
+        let mut bomb = classic_bytes[..BSDIFF40_HEADER_SIZE].to_vec();
+        let controls = compress(&ControlEntry { mix_len: 0, copy_len: 1, seek: 0 }.to_bytes()).unwrap();
+        let diffs = compress(&[]).unwrap();
+        bomb[8..16].copy_from_slice(&offtout(controls.len() as i64));
+        bomb[16..24].copy_from_slice(&offtout(diffs.len() as i64));
+        bomb[24..32].copy_from_slice(&offtout(1));
+        bomb.extend_from_slice(&controls);
+        bomb.extend_from_slice(&diffs);
+        bomb.extend_from_slice(&compress(&vec![0u8; 1 << 20]).unwrap());
+        let err = from_bsdiff40(&bomb).unwrap_err();
+        assert!(err.to_string().contains("extra block is larger than the declared new file"), "{}", err);
+        bomb.truncate(bomb.len() - 10);
+        assert!(from_bsdiff40(&bomb).is_err());
+    }
+}
@@ -1,285 +1,5172 @@
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::{Write, BufWriter};
+use std::io::{self, Read, Seek, SeekFrom, Write, BufReader, BufWriter};
 use std::path::{Path, PathBuf};
 use zstd::stream::{Encoder as ZstdEncoder, Decoder as ZstdDecoder};
 use memmap2::MmapOptions;
+use sha2::{Digest, Sha256};
 
-/// 最优配置结构体 - 简化版本，只保留核心参数
-#[derive(Debug, Clone)]
+use crate::archive_transform::{self, ArchiveTransform};
+use crate::audit::{self, AuditLog};
+use crate::auto_level::{self, AutoLevel};
+use crate::block_delta;
+use crate::bsdiff40::{self, PatchFormat};
+use crate::cancel::{self, CancelToken};
+use crate::codecs;
+use crate::control::ControlEntry;
+use crate::deflate;
+use crate::dictionary::Dictionary;
+use crate::encryption::{self, EncryptionKey};
+use crate::error::{BsdiffError, ErrorCode};
+use crate::exe_transform::ExeTransform;
+use crate::formats::vcdiff;
+use crate::header::{self, Compression, FileDigest, HashAlgorithm, Hasher, PatchHeader, PayloadReader, PayloadWriter, ALGORITHM_VERSION, DIGEST_LEN, MAGIC, PAYLOAD_TRAILER_LEN, ZSTD_MAGIC};
+use crate::logger;
+use crate::manifest;
+use crate::patch_cache;
+use crate::paths;
+use crate::preflight;
+use crate::prepared::{self, PreparedBase};
+use crate::progress::{self, DiffProgressWriter, PhaseCallback, ProgressCallback, ProgressPhase, ProgressReader, ProgressReporter};
+use crate::retry::RetryPolicy;
+use crate::signing::PatchSignature;
+use crate::throttle::{ThrottledWriter, WriteLimit};
+use crate::volumes;
+
+/// 检查临时目录剩余空间时，在预计输出大小之外额外保留的余量
+const TEMP_SPACE_HEADROOM: u64 = 16 << 20;
+
+/// 目录所在文件系统对当前用户可用的字节数；无法获取时返回 `None` (不做检查)
+#[cfg(unix)]
+pub(crate) fn available_space(dir: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    let stat = unsafe { stat.assume_init() };
+    // 字段宽度随平台而异 (macOS 上 f_bavail 为 u32)
+    #[allow(clippy::useless_conversion)]
+    Some(u64::from(stat.f_bavail).saturating_mul(u64::from(stat.f_frsize)))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn available_space(_dir: &Path) -> Option<u64> {
+    None
+}
+
+/// 共享临时目录中的文件名：`bsdiff_<pid>_<随机数>_<原文件名>`，同名文件并发写入时互不覆盖
+///
+/// 随机数由每次新建的 `RandomState` 与进程内计数器混合得到，同一进程内也不会重复
+fn temp_file_name(file_name: &std::ffi::OsStr) -> std::ffi::OsString {
+    use std::hash::{BuildHasher, Hasher};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    let mut name = std::ffi::OsString::from(format!("bsdiff_{}_{:016x}_", std::process::id(), hasher.finish()));
+    name.push(file_name);
+    name
+}
+
+/// `target` 同目录下的隐藏临时文件：`.bsdiff_<pid>_<随机数>_<原文件名>.<suffix>`，命名同 [`temp_file_name`]
+fn sibling_temp_path(target: &Path, suffix: &str) -> io::Result<PathBuf> {
+    let file_name = target.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid file path"))?;
+    let mut name = std::ffi::OsString::from(".");
+    name.push(temp_file_name(file_name));
+    name.push(".");
+    name.push(suffix);
+    Ok(target.with_file_name(name))
+}
+
+/// 写入预分配的可写内存映射，超出映射长度时报错
+struct MmapWriter {
+    map: memmap2::MmapMut,
+    pos: usize,
+}
+
+impl Write for MmapWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let end = self.pos.checked_add(buf.len()).filter(|end| *end <= self.map.len())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::WriteZero, "patch output exceeds the preallocated size"))?;
+        self.map[self.pos..end].copy_from_slice(buf);
+        self.pos = end;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// 转发写入，同时 (给出计算器时) 计算写入数据的摘要
+struct DigestingWriter<'a, W: Write + ?Sized> {
+    inner: &'a mut W,
+    hasher: Option<Hasher>,
+}
+
+impl<W: Write + ?Sized> Write for DigestingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// 稀疏输出检测全零数据的块大小 (常见文件系统块大小)
+const SPARSE_BLOCK_SIZE: usize = 4096;
+
+/// 稀疏文件写入器：按文件内对齐的块检测全零数据，以 seek 跳过而不实际写入，文件系统支持时形成空洞
+struct SparseWriter {
+    inner: BufWriter<ThrottledWriter<File>>,
+    block: Vec<u8>,
+    /// 尚未 seek 跳过的全零字节数 (遇到下一个非零块时才 seek)
+    pending_hole: u64,
+    len: u64,
+    skipped: u64,
+}
+
+impl SparseWriter {
+    fn new(file: File, limit: Option<WriteLimit>) -> Self {
+        Self {
+            inner: BufWriter::with_capacity(64 * 1024, ThrottledWriter::new(file, limit)),
+            block: Vec::with_capacity(SPARSE_BLOCK_SIZE),
+            pending_hole: 0,
+            len: 0,
+            skipped: 0,
+        }
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.block.iter().all(|&byte| byte == 0) {
+            self.pending_hole += self.block.len() as u64;
+            self.skipped += self.block.len() as u64;
+        } else {
+            if self.pending_hole > 0 {
+                self.inner.seek(SeekFrom::Current(self.pending_hole as i64))?;
+                self.pending_hole = 0;
+            }
+            self.inner.write_all(&self.block)?;
+        }
+        self.block.clear();
+        Ok(())
+    }
+
+    /// 写出剩余数据并把文件长度设为写入的总长度 (末尾的空洞由此形成)，返回跳过的全零字节数
+    fn finish(mut self) -> io::Result<u64> {
+        if !self.block.is_empty() {
+            self.write_block()?;
+        }
+        let file = self.inner.into_inner().map_err(io::IntoInnerError::into_error)?.into_inner();
+        file.set_len(self.len)?;
+        Ok(self.skipped)
+    }
+}
+
+impl Write for SparseWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let take = (SPARSE_BLOCK_SIZE - self.block.len()).min(rest.len());
+            self.block.extend_from_slice(&rest[..take]);
+            rest = &rest[take..];
+            if self.block.len() == SPARSE_BLOCK_SIZE {
+                self.write_block()?;
+            }
+        }
+        self.len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    /// 不完整的块留到凑满或 `finish` 时再判断是否为零
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// 自动级别的压缩写入器：先缓存原始 bsdiff 流的前 [`auto_level::SAMPLE_LEN`] 字节，
+/// 在样本上选定级别后创建编码器，写出样本并继续流式压缩
+enum LevelProbe<'a, W: Write> {
+    Sampling { writer: W, sample: Vec<u8>, config: &'a OptimizationConfig, auto: &'a AutoLevel },
+    Encoding(ZstdEncoder<'static, W>),
+    /// 创建编码器失败后的状态
+    Failed,
+}
+
+impl<'a, W: Write> LevelProbe<'a, W> {
+    fn new(writer: W, config: &'a OptimizationConfig, auto: &'a AutoLevel) -> Self {
+        Self::Sampling { writer, sample: Vec::new(), config, auto }
+    }
+
+    fn start_encoding(&mut self) -> io::Result<()> {
+        if !matches!(self, Self::Sampling { .. }) {
+            return Ok(());
+        }
+        let Self::Sampling { writer, sample, config, auto } = std::mem::replace(self, Self::Failed) else {
+            unreachable!("checked above");
+        };
+        let mut level = auto_level::pick_level(&sample);
+        if config.safe_mode {
+            level = level.min(SAFE_MODE_MAX_LEVEL);
+        }
+        auto.record(level);
+        logger::info(&format!("Auto compression level: {} (sampled {} bytes)", level, sample.len()));
+        let config = OptimizationConfig { compression_level: level, ..config.clone() };
+        let mut encoder = BsdiffRust::create_zstd_encoder(writer, &config)?;
+        encoder.write_all(&sample)?;
+        *self = Self::Encoding(encoder);
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        self.start_encoding()?;
+        match self {
+            Self::Encoding(encoder) => encoder.finish(),
+            _ => Err(io::Error::other("zstd encoder was not created")),
+        }
+    }
+}
+
+impl<W: Write> Write for LevelProbe<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Sampling { sample, .. } => {
+                let take = (auto_level::SAMPLE_LEN - sample.len()).min(buf.len());
+                sample.extend_from_slice(&buf[..take]);
+                if sample.len() == auto_level::SAMPLE_LEN {
+                    self.start_encoding()?;
+                }
+                Ok(take)
+            }
+            Self::Encoding(encoder) => encoder.write(buf),
+            Self::Failed => Err(io::Error::other("zstd encoder was not created")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Encoding(encoder) => encoder.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// 常见非补丁文件格式的魔数，用于检测参数颠倒
+const KNOWN_FILE_MAGICS: &[(&[u8], &str)] = &[
+    (b"\x7fELF", "an ELF executable"),
+    (b"MZ", "a PE/Windows executable"),
+    (&[0xCF, 0xFA, 0xED, 0xFE], "a Mach-O executable"),
+    (&[0xCE, 0xFA, 0xED, 0xFE], "a Mach-O executable"),
+    (&[0xCA, 0xFE, 0xBA, 0xBE], "a Mach-O universal binary"),
+    (b"\x89PNG", "a PNG image"),
+    (&[0xFF, 0xD8, 0xFF], "a JPEG image"),
+    (b"GIF8", "a GIF image"),
+    (b"PK\x03\x04", "a ZIP archive"),
+    (&[0x1F, 0x8B], "a gzip archive"),
+    (b"BZh", "a bzip2 archive"),
+    (b"%PDF", "a PDF document"),
+];
+
+/// 文件头嗅探结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SniffedKind {
+    /// bsdiff-rust 补丁 (带补丁头或旧版裸 zstd)
+    Patch,
+    /// 明确的非补丁格式
+    Known(&'static str),
+    /// 无法判断
+    Unknown,
+}
+
+/// 追加模式下共同前缀至少占旧文件的比例 (百分比)
+const APPEND_MIN_PREFIX_PERCENT: u64 = 90;
+
+/// 安全模式下的压缩级别上限
+const SAFE_MODE_MAX_LEVEL: i32 = 3;
+
+/// 安全模式下固定的窗口大小 (8MB)
+const SAFE_MODE_WINDOW_LOG: u32 = 23;
+
+/// 可复现模式下固定的窗口大小 (8MB)
+const DETERMINISTIC_WINDOW_LOG: u32 = 23;
+
+/// 流式应用补丁时差分数据的分块大小
+const PATCH_CHUNK_SIZE: usize = 64 * 1024;
+
+/// zstd 帧的解码窗口超过 `window_log_max` 时的错误信息
+const ZSTD_WINDOW_TOO_LARGE: &str = "Frame requires too much memory for decoding";
+/// zstd 允许的解码窗口对数范围
+const ZSTD_MIN_WINDOW_LOG: u32 = 10;
+const ZSTD_MAX_WINDOW_LOG: u32 = 31;
+
+/// 视为 zstd 内部错误 (而非数据损坏) 的错误信息，出现时以安全模式重试
+const ZSTD_INTERNAL_ERRORS: &[&str] = &[
+    "Error (generic)",
+    "Allocation error : not enough memory",
+    "workSpace buffer is not large enough",
+    "Operation not authorized at current processing stage",
+    "Context should be init first",
+    "Unsupported parameter",
+    "Unsupported combination of parameters",
+    "Parameter is out of bound",
+    "Operation made no progress",
+];
+
+/// diff 模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiffMode {
+    /// 完整 bsdiff 算法
+    #[default]
+    Full,
+    /// 追加模式：共同前缀足够长时只输出新增尾部，常量内存；否则回退到完整 bsdiff
+    Append,
+}
+
+/// diff 算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiffAlgorithm {
+    /// bsdiff 后缀数组算法：补丁最小，内存约为旧文件大小的 9 倍 (可用 `window_size` 限制)
+    #[default]
+    Bsdiff,
+    /// rsync 风格的块级增量：按块匹配旧文件内容，耗时与内存近似线性，补丁较大，适合超大输入
+    BlockDelta,
+}
+
+impl DiffAlgorithm {
+    pub fn parse(name: &str) -> Result<Self, BsdiffError> {
+        match name {
+            "bsdiff" => Ok(DiffAlgorithm::Bsdiff),
+            "blockdelta" => Ok(DiffAlgorithm::BlockDelta),
+            other => Err(format!("Unknown diff algorithm: {} (expected \"bsdiff\" or \"blockdelta\")", other).into()),
+        }
+    }
+}
+
+/// 输出文件已存在时的处理方式 (diff/patch 写文件时生效)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverwritePolicy {
+    /// 直接覆盖 (默认)
+    #[default]
+    Replace,
+    /// 开始前即拒绝，返回 [`BsdiffError::OutputExists`]
+    Error,
+    /// 写入前把已有文件重命名为 `<输出>.bak` (替换已有的 .bak)
+    Backup,
+}
+
+impl OverwritePolicy {
+    pub fn parse(name: &str) -> Result<Self, BsdiffError> {
+        match name {
+            "replace" => Ok(OverwritePolicy::Replace),
+            "error" => Ok(OverwritePolicy::Error),
+            "backup" => Ok(OverwritePolicy::Backup),
+            other => Err(format!("Unknown overwrite policy: {} (expected \"error\", \"replace\" or \"backup\")", other).into()),
+        }
+    }
+}
+
+/// 目录差分包应用时生成内容未变的文件 (未被提及与仅移动的文件) 的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LinkMode {
+    /// 完整复制
+    Copy,
+    /// 写时复制克隆 (Linux FICLONE、macOS clonefile)，几乎不耗时也不占额外空间；文件系统不支持时复制 (默认)
+    #[default]
+    Reflink,
+    /// 硬链接到旧目录中的文件，失败时退回克隆或复制。新旧文件共享同一份数据，之后修改其一会同时改变另一个；
+    /// 差分包记录了元数据的文件不使用硬链接，以免改动旧文件的权限与时间戳
+    HardLink,
+}
+
+impl LinkMode {
+    pub fn parse(name: &str) -> Result<Self, BsdiffError> {
+        match name {
+            "copy" => Ok(LinkMode::Copy),
+            "reflink" => Ok(LinkMode::Reflink),
+            "hardlink" => Ok(LinkMode::HardLink),
+            other => Err(format!("Unknown link mode: {} (expected \"copy\", \"reflink\" or \"hardlink\")", other).into()),
+        }
+    }
+}
+
+/// diff 与 patch 的全部选项：压缩、格式、校验、资源上限、进度与取消等；`Default` 给出推荐配置
+#[derive(Clone)]
 pub struct OptimizationConfig {
     /// Zstd 压缩级别 (1-22，推荐3)
     pub compression_level: i32,
     /// 是否使用快速临时目录
     pub use_fast_temp_dir: bool,
+    /// 快速临时目录，`None` 时自动选择 (Linux 的 /dev/shm、macOS 的 RAM 盘或系统临时目录)。
+    /// 默认读取环境变量 `BSDIFF_TEMP_DIR`；剩余空间不足时改为写在输出文件旁边
+    pub temp_dir: Option<PathBuf>,
+    /// diff 模式
+    pub mode: DiffMode,
+    /// 进度回调 (节流到约 1% 一次)；diff 按新文件字节数、patch 按补丁文件字节数上报。
+    /// 线程语义见 [`ProgressCallback`]
+    pub progress: Option<ProgressCallback>,
+    /// 安全模式：zstd 使用保守的可移植配置 (限制压缩级别、关闭高级参数)。
+    /// 默认读取环境变量 `BSDIFF_ZSTD_SAFE=1`
+    pub safe_mode: bool,
+    /// 完整 diff 时在后台线程并行计算文件摘要，隐藏哈希耗时 (默认开启)
+    pub overlap_hashing: bool,
+    /// 应用补丁时允许的最大输出字节数 (`None` 表示只受补丁头声明的目标大小限制)。
+    /// 补丁头声明的目标大小超过上限时在写出任何数据前拒绝
+    pub max_output_size: Option<u64>,
+    /// 应用补丁时本库缓冲数据的内存上限 (zstd 解码窗口、整体读入的加密/BSDIFF40/VCDIFF 补丁、
+    /// 可执行文件变换与输出到内存的结果)，超出时返回 [`BsdiffError::LimitExceeded`] 而不是耗尽内存
+    pub max_memory: Option<u64>,
+    /// patch 方向按控制块上报的进度 `(已应用控制块数, 控制块总数)`，单调递增且与目标大小无关。
+    /// 仅当补丁头记录了控制块总数时上报 (旧版本生成的补丁不上报)
+    pub block_progress: Option<ProgressCallback>,
+    /// 分阶段进度事件 (read/decode/apply/write，含剩余时间估算)；apply 阶段与 `progress` 同样节流
+    pub phase_progress: Option<PhaseCallback>,
+    /// diff 输出的补丁格式；`Bsdiff40` 时生成与原版 bsdiff 4.x 工具兼容的补丁 (不含补丁头)。
+    /// 应用补丁时按魔数自动识别，不受此选项影响
+    pub format: PatchFormat,
+    /// 严格模式：拒绝未携带源文件摘要的补丁 (旧版补丁、BSDIFF40)。
+    /// 补丁头带有摘要时无论是否严格都会校验旧文件
+    pub strict: bool,
+    /// 取消标记：diff 在写出每段 bsdiff 数据前、patch 在每个数据块之间检查
+    pub cancel: Option<CancelToken>,
+    /// diff 输出补丁负载的压缩算法，记录在补丁头中；应用补丁时按补丁头自动识别
+    pub compression: Compression,
+    /// 分窗口 diff 的新文件窗口大小 (字节)，`None` 表示整体 diff。
+    /// 每个窗口只与旧文件对应偏移附近约两倍窗口大小的区间比较，内存随窗口而非文件大小增长，
+    /// 代价是跨窗口的大范围数据移动无法匹配、补丁略大
+    pub window_size: Option<u64>,
+    /// 分窗口 diff 的并行线程数，`None` 时使用 rayon 默认线程数 (CPU 核数)。整体 diff 不受影响
+    pub threads: Option<usize>,
+    /// 应用补丁前校验的分离式 Ed25519 签名；校验失败返回 [`BsdiffError::InvalidSignature`] 且不产生输出
+    pub signature: Option<PatchSignature>,
+    /// diff 时写入补丁头的键值元数据 (如应用版本、发布渠道)，可由 `get_patch_info` 读出。
+    /// 仅 zstd 格式的补丁头可以携带
+    pub metadata: BTreeMap<String, String>,
+    /// 应用补丁写文件时，按补丁头声明的目标大小预分配输出文件并通过可写内存映射写入，
+    /// 省去逐块 write 系统调用；补丁头未声明目标大小 (旧版、BSDIFF40、VCDIFF) 或映射大小超过 `max_memory` 时退回普通写入
+    pub mmap_output: bool,
+    /// 试运行：应用补丁时完整解码并在内存中流式校验输出 (大小与补丁头记录的目标摘要)，不创建任何文件，
+    /// 供安装程序在真正写盘前预检更新
+    pub dry_run: bool,
+    /// 可执行文件感知 diff：新旧文件都是同一架构的 PE/ELF/Mach-O 时，先把代码段中相对跳转的目标
+    /// 改写为绝对地址再 diff；变换记录在补丁头中，应用时自动逆变换。变换并不总是让补丁变小，
+    /// 因此会分别 diff 变换前后的输入并保留较小的补丁 (diff 耗时约为两倍)。
+    /// 仅完整 diff 与 zstd 格式，其他输入按普通文件处理
+    pub exe_transform: bool,
+    /// 压缩包感知 diff：新文件是 ZIP (含 APK/JAR/NuGet 等) 时先把 deflate 条目解压展开再 diff，
+    /// 应用时按条目记录的 zlib 级别重新压缩，还原出逐字节相同的文件。只展开能用 zlib 复现压缩数据的条目；
+    /// 仅完整 diff 与 zstd 格式，优先于 `exe_transform`，其他输入 (含本身不压缩的 ASAR) 按普通文件处理
+    pub archive_transform: bool,
+    /// AES-256-GCM 密钥：diff 时把完整补丁封装进加密信封，patch 时解密加密补丁 (未加密的补丁不受影响)。
+    /// 密钥错误或信封被篡改返回 [`BsdiffError::DecryptionFailed`]，加密补丁缺少密钥返回 [`BsdiffError::Encrypted`]
+    pub encryption_key: Option<EncryptionKey>,
+    /// 共享 zstd 字典：diff 时用于压缩负载并把字典 ID 写入补丁头 (仅 zstd 格式与压缩)；
+    /// patch 时补丁头记录了字典 ID 则必须提供相同的字典，否则返回 [`BsdiffError::DictionaryMismatch`]
+    pub dictionary: Option<Dictionary>,
+    /// 可复现输出：相同输入与选项在任意次运行、任意平台上生成逐字节相同的补丁，便于按内容寻址缓存。
+    /// 显式固定 zstd 帧参数 (窗口、无长距离匹配、无校验和与内容大小字段) 并忽略安全模式；
+    /// 分窗口 diff 与摘要计算的并行结果本就与线程数无关，补丁头也不含时间戳等易变字段。
+    /// 加密信封每次使用随机 nonce，加密补丁不可复现
+    pub deterministic: bool,
+    /// 稀疏输出：应用补丁写文件时以 seek 跳过全零的 4KB 块，文件系统支持时生成稀疏文件，
+    /// 磁盘/虚拟机镜像中的大片空白区域不再实际写盘。优先于 `mmap_output`
+    pub sparse: bool,
+    /// 自动选择压缩级别：压缩负载前在原始 bsdiff 流的前 1MB 上实测几个级别，按每秒压缩率选出级别
+    /// (替代 `compression_level`) 并记录在标记中。选择取决于机器性能，`deterministic` 时忽略
+    pub auto_level: Option<AutoLevel>,
+    /// diff 时补丁头记录源/目标摘要所用的哈希算法 (默认 SHA-256，BLAKE3 可多线程计算)。
+    /// 应用补丁时不论算法，都会校验旧文件并流式校验输出与目标摘要一致
+    pub hash_algorithm: HashAlgorithm,
+    /// diff/patch 的输出文件已存在时的处理方式 (默认直接覆盖)；试运行不写文件，不受影响
+    pub overwrite: OverwritePolicy,
+    /// 应用补丁写出新文件后 fsync 文件及其所在目录 (默认 false)，返回后断电也不会留下截断的输出
+    pub durable: bool,
+    /// diff 算法 (默认 bsdiff)；块级增量忽略 `window_size`
+    pub algorithm: DiffAlgorithm,
+    /// 内容去重 (默认 false)：目录差分包中的新增文件按 FastCDC 分块，重复的块 (跨文件或与旧目录中的数据相同)
+    /// 只存一次；补丁包中内容完全相同的条目补丁只存一份。单文件 diff 不受影响
+    pub dedup: bool,
+    /// 目录差分包记录新目录中的符号链接与文件权限位 (默认 false)，patchDir 按记录重建链接并恢复可执行位等权限
+    pub preserve_metadata: bool,
+    /// 目录差分包额外记录文件修改时间 (默认 false，隐含 `preserve_metadata`)
+    pub preserve_mtimes: bool,
+    /// 沙箱根目录：应用目录差分包与补丁包时所有读写路径都必须位于其下 (相对路径基于它解析，
+    /// 符号链接展开后检查)，越界时在读取补丁前失败。补丁包的临时输出改写在目标旁边
+    pub base_dir: Option<PathBuf>,
+    /// 应用补丁时的磁盘写入速率上限 (默认不限速)，避免多 GB 的更新占满磁盘带宽拖慢前台应用。
+    /// 设置后不使用 `mmap_output` 与快速临时目录 (输出直接写在目标旁边)；目录差分包的重建不受限速
+    pub write_limit: Option<WriteLimit>,
+    /// 分卷大小 (字节，默认不分卷)：diff 把补丁切成 `<补丁>.001`、`<补丁>.002`…… 不超过该大小的分卷，
+    /// 补丁路径本身写为分卷索引；应用时传入索引或分卷所在目录
+    pub volume_size: Option<u64>,
+    /// 完成输出 (rename/跨设备复制) 时遇到文件被暂时锁住的重试策略；
+    /// Windows 上默认重试 5 次以应对杀毒软件扫描刚写出的文件，其他平台默认不重试
+    pub retry: RetryPolicy,
+    /// 应用目录差分包时内容未变的文件的生成方式 (默认写时复制克隆，不支持时复制)
+    pub link_mode: LinkMode,
+    /// 补丁缓存目录 (默认不缓存)：diff 以新旧文件摘要与影响补丁内容的选项为键保存补丁，
+    /// 再次请求相同的文件对时直接复制缓存的补丁。加密补丁不缓存
+    pub cache_dir: Option<PathBuf>,
+    /// 完整文件回退 (默认关闭)：diff 生成的补丁超过新文件大小的该比例时，改为以空内容为旧文件重新生成
+    /// 并在补丁头标记 `full_file` (即补丁直接携带新文件)，只在其更小时替换。应用补丁时无需区分；仅 zstd 格式
+    pub fallback_to_full: Option<f64>,
+    /// 审计日志 (默认不记录)：应用补丁的每一步 (补丁头、签名与摘要校验、写出字节数、rename 提交) 记录一行 JSON 事件
+    pub audit_log: Option<AuditLog>,
 }
 
+/// `fallbackToFull: true` 时的阈值：补丁超过新文件大小的 90% 时回退为完整文件
+pub const DEFAULT_FALLBACK_THRESHOLD: f64 = 0.9;
+
 impl Default for OptimizationConfig {
     fn default() -> Self {
         Self {
             compression_level: 3,    // 平衡速度和压缩比的最佳选择
             use_fast_temp_dir: true, // 默认启用快速临时目录
+            temp_dir: std::env::var_os("BSDIFF_TEMP_DIR").filter(|dir| !dir.is_empty()).map(PathBuf::from),
+            mode: DiffMode::Full,
+            progress: None,
+            safe_mode: std::env::var("BSDIFF_ZSTD_SAFE").is_ok_and(|v| v == "1"),
+            overlap_hashing: true,
+            max_output_size: None,
+            max_memory: None,
+            block_progress: None,
+            phase_progress: None,
+            format: PatchFormat::Zstd,
+            strict: false,
+            cancel: None,
+            compression: Compression::Zstd,
+            window_size: None,
+            threads: None,
+            signature: None,
+            metadata: BTreeMap::new(),
+            mmap_output: false,
+            dry_run: false,
+            exe_transform: false,
+            archive_transform: false,
+            encryption_key: None,
+            dictionary: None,
+            deterministic: false,
+            sparse: false,
+            auto_level: None,
+            hash_algorithm: HashAlgorithm::Sha256,
+            overwrite: OverwritePolicy::Replace,
+            durable: false,
+            algorithm: DiffAlgorithm::Bsdiff,
+            dedup: false,
+            preserve_metadata: false,
+            preserve_mtimes: false,
+            base_dir: None,
+            write_limit: None,
+            volume_size: None,
+            retry: RetryPolicy::default(),
+            link_mode: LinkMode::default(),
+            cache_dir: None,
+            fallback_to_full: None,
+            audit_log: None,
+        }
+    }
+}
+
+impl OptimizationConfig {
+    /// apply 阶段的进度上报器 (原始进度与分阶段事件共用节流)
+    pub(crate) fn apply_reporter(&self, total: u64) -> Option<ProgressReporter> {
+        ProgressReporter::of(self.progress.as_ref(), self.phase_progress.as_ref().map(|callback| (callback, ProgressPhase::Apply)), total)
+    }
+
+    /// 上报不逐字节推进的阶段的开始 (`done` 为 0) 或结束
+    pub(crate) fn report_phase(&self, phase: ProgressPhase, done: u64, total: u64) {
+        progress::report_phase(self.phase_progress.as_ref(), phase, done, total);
+    }
+
+    /// 记录一个审计事件，未设置审计日志时忽略
+    pub(crate) fn audit(&self, event: &str, fields: &[(&str, audit::Field)]) {
+        if let Some(log) = &self.audit_log {
+            log.record(event, fields);
         }
     }
+
+    /// 返回启用安全模式的配置副本
+    pub fn safe(&self) -> Self {
+        Self { safe_mode: true, ..self.clone() }
+    }
 }
 
+impl std::fmt::Debug for OptimizationConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OptimizationConfig")
+            .field("compression_level", &self.compression_level)
+            .field("use_fast_temp_dir", &self.use_fast_temp_dir)
+            .field("temp_dir", &self.temp_dir)
+            .field("mode", &self.mode)
+            .field("progress", &self.progress.is_some())
+            .field("safe_mode", &self.safe_mode)
+            .field("overlap_hashing", &self.overlap_hashing)
+            .field("max_output_size", &self.max_output_size)
+            .field("max_memory", &self.max_memory)
+            .field("block_progress", &self.block_progress.is_some())
+            .field("phase_progress", &self.phase_progress.is_some())
+            .field("format", &self.format)
+            .field("strict", &self.strict)
+            .field("cancel", &self.cancel.is_some())
+            .field("compression", &self.compression)
+            .field("window_size", &self.window_size)
+            .field("threads", &self.threads)
+            .field("signature", &self.signature.is_some())
+            .field("metadata", &self.metadata)
+            .field("mmap_output", &self.mmap_output)
+            .field("dry_run", &self.dry_run)
+            .field("exe_transform", &self.exe_transform)
+            .field("archive_transform", &self.archive_transform)
+            .field("encryption_key", &self.encryption_key.is_some())
+            .field("dictionary", &self.dictionary.as_ref().map(Dictionary::id))
+            .field("deterministic", &self.deterministic)
+            .field("sparse", &self.sparse)
+            .field("auto_level", &self.auto_level)
+            .field("hash_algorithm", &self.hash_algorithm)
+            .field("overwrite", &self.overwrite)
+            .field("durable", &self.durable)
+            .field("algorithm", &self.algorithm)
+            .field("dedup", &self.dedup)
+            .field("preserve_metadata", &self.preserve_metadata)
+            .field("preserve_mtimes", &self.preserve_mtimes)
+            .field("base_dir", &self.base_dir)
+            .field("write_limit", &self.write_limit)
+            .field("volume_size", &self.volume_size)
+            .field("retry", &self.retry)
+            .field("link_mode", &self.link_mode)
+            .field("cache_dir", &self.cache_dir)
+            .field("fallback_to_full", &self.fallback_to_full)
+            .field("audit_log", &self.audit_log)
+            .finish()
+    }
+}
+
+/// 定位到补丁负载处、按补丁头压缩算法解码的读取器
+pub(crate) type PatchDecoder = Box<dyn Read>;
+
 pub struct BsdiffRust;
 
 impl BsdiffRust {
     /// 生成 bsdiff 补丁文件 (使用最优配置)
-    pub fn diff(old_file: &str, new_file: &str, patch_file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    #[allow(dead_code)] // napi 层统一走 diff_optimized，保留给 Rust 调用方
+    pub fn diff(old_file: &Path, new_file: &Path, patch_file: &Path) -> Result<(), BsdiffError> {
         Self::diff_optimized(old_file, new_file, patch_file, &OptimizationConfig::default())
     }
 
     /// 使用最优配置生成补丁 (内部优化实现)
     pub fn diff_optimized(
-        old_file: &str, 
-        new_file: &str, 
-        patch_file: &str,
+        old_file: &Path, 
+        new_file: &Path, 
+        patch_file: &Path,
         config: &OptimizationConfig
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    ) -> Result<(), BsdiffError> {
         // 快速验证输入文件
         Self::validate_files(old_file, new_file)?;
+        Self::prepare_output(patch_file, config)?;
 
-        // 内存映射文件 - 零拷贝高性能I/O
-        let (old_mmap, new_mmap) = Self::create_memory_maps(old_file, new_file)?;
+        // 补丁最终写到补丁路径所在目录：预计放不下时在 diff 之前失败
+        let new_len = std::fs::metadata(new_file)?.len();
+        let estimated_size = manifest::estimate_patch_size(std::fs::metadata(old_file)?.len(), new_len);
+        preflight::check_destination_space(patch_file, estimated_size)?;
 
-        // 智能选择输出路径 (临时目录优化)
-        let patch_path = Self::get_optimal_output_path(patch_file, config.use_fast_temp_dir)?;
+        // 智能选择输出路径 (临时目录优化)，补丁通常不大于新文件
+        let mut patch_path = Self::get_optimal_output_path(patch_file, config, new_len)?;
+        // 分卷时补丁路径最终写为索引，完整补丁先写到旁边
+        if config.volume_size.is_some() && patch_path == patch_file {
+            patch_path = paths::with_suffix(patch_file, ".partial");
+        }
 
-        // 创建高性能Zstd编码器
-        let mut encoder = Self::create_zstd_encoder(&patch_path, config.compression_level)?;
+        // 补丁缓存：相同的文件对与选项直接复制缓存的补丁
+        let cache = match &config.cache_dir {
+            Some(cache_dir) => patch_cache::cache_key(old_file, new_file, config)?.map(|key| (cache_dir, key)),
+            None => None,
+        };
+        let cached = match &cache {
+            Some((cache_dir, key)) => patch_cache::fetch(cache_dir, key, &patch_path)?,
+            None => false,
+        };
 
-        // 执行核心diff算法
-        bsdiff::diff(&old_mmap[..], &new_mmap[..], &mut encoder)?;
-        encoder.finish()?;
+        if !cached {
+            // 64KB 缓冲写入补丁 (zstd 内部错误时以安全模式重试一次)，失败或取消时删除未完成的输出
+            let result = Self::with_safe_mode_retry(config, "diff", |config| {
+                let writer = BufWriter::with_capacity(64 * 1024, File::create(&patch_path)?);
+                Self::write_patch(old_file, new_file, writer, config)?.flush()?;
+                Ok(())
+            });
+            let result = result.and_then(|()| match config.fallback_to_full {
+                Some(threshold) => Self::fallback_to_full_file(old_file, new_file, &patch_path, threshold, config).map(|_| ()),
+                None => Ok(()),
+            });
+            if let Err(e) = result {
+                let _ = std::fs::remove_file(&patch_path);
+                return Err(e);
+            }
+            if let Some((cache_dir, key)) = &cache {
+                patch_cache::store(cache_dir, key, &patch_path);
+            }
+        }
 
-        // 原子性移动到最终位置
-        Self::finalize_output(&patch_path, patch_file)?;
+        if let Some(volume_size) = config.volume_size {
+            let result = volumes::write_volumes(&patch_path, patch_file, volume_size, config);
+            let _ = std::fs::remove_file(&patch_path);
+            return result.map(|_| ());
+        }
 
-        Ok(())
+        // 原子性移动到最终位置
+        Self::finalize_reported(&patch_path, patch_file, config)
     }
 
-    /// 应用 bsdiff 补丁文件 (使用最优配置)
-    pub fn patch(old_file: &str, new_file: &str, patch_file: &str) -> Result<(), Box<dyn std::error::Error>> {
-        Self::patch_optimized(old_file, new_file, patch_file, &OptimizationConfig::default())
+    /// 生成补丁并写入任意输出 (补丁头 + Zstd负载)，返回写入器
+    pub fn diff_to_writer<W: Write>(
+        old_file: &Path,
+        new_file: &Path,
+        writer: W,
+        config: &OptimizationConfig
+    ) -> Result<W, BsdiffError> {
+        Self::validate_files(old_file, new_file)?;
+        Self::write_patch(old_file, new_file, writer, config)
     }
 
-    /// 使用最优配置应用补丁 (内部优化实现)
-    pub fn patch_optimized(
-        old_file: &str, 
-        new_file: &str, 
-        patch_file: &str,
-        config: &OptimizationConfig
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        // 快速验证输入文件
-        Self::validate_patch_files(old_file, patch_file)?;
+    /// 在内存中生成补丁 (补丁头 + Zstd负载)，不经过文件系统
+    pub fn diff_bytes(old: &[u8], new: &[u8], config: &OptimizationConfig) -> Result<Vec<u8>, BsdiffError> {
+        let patch = Self::with_safe_mode_retry(config, "diff", |config| Self::write_patch_bytes(old, new, Vec::new(), config))?;
+        match config.fallback_to_full {
+            Some(threshold) if Self::exceeds_fallback_threshold(patch.len() as u64, new.len() as u64, threshold, config) => {
+                let full = Self::with_safe_mode_retry(config, "diff", |config| Self::write_full_file_patch(old, new, Vec::new(), config))?;
+                Ok(if full.len() < patch.len() { full } else { patch })
+            }
+            _ => Ok(patch),
+        }
+    }
 
-        // 内存映射旧文件 - 零拷贝读取
-        let old_mmap = Self::create_single_memory_map(old_file)?;
+    /// 补丁大小是否超过新文件大小的 `threshold` 倍 (只对 zstd 格式回退，其他格式没有补丁头可标记)
+    fn exceeds_fallback_threshold(patch_len: u64, new_len: u64, threshold: f64, config: &OptimizationConfig) -> bool {
+        config.format == PatchFormat::Zstd && patch_len as f64 > new_len as f64 * threshold
+    }
 
-        // 创建高性能Zstd解码器并应用补丁
-        let new_data = Self::decode_and_patch(&old_mmap, patch_file)?;
+    /// `fallback_to_full`：`patch_path` 处的补丁过大时在旁边生成完整文件补丁，更小时替换，返回是否替换
+    fn fallback_to_full_file(
+        old_file: &Path,
+        new_file: &Path,
+        patch_path: &Path,
+        threshold: f64,
+        config: &OptimizationConfig
+    ) -> Result<bool, BsdiffError> {
+        let patch_len = std::fs::metadata(patch_path)?.len();
+        let new_len = std::fs::metadata(new_file)?.len();
+        if !Self::exceeds_fallback_threshold(patch_len, new_len, threshold, config) {
+            return Ok(false);
+        }
+        let (old_mmap, new_mmap) = Self::create_memory_maps(old_file, new_file)?;
+        let full_path = paths::with_suffix(patch_path, ".full");
+        let result = Self::with_safe_mode_retry(config, "diff", |config| {
+            let writer = BufWriter::with_capacity(64 * 1024, File::create(&full_path)?);
+            Self::write_full_file_patch(&old_mmap, &new_mmap, writer, config)?.flush()?;
+            Ok(std::fs::metadata(&full_path)?.len())
+        });
+        let full_len = match result {
+            Ok(full_len) if full_len < patch_len => full_len,
+            result => {
+                let _ = std::fs::remove_file(&full_path);
+                return result.map(|_| false);
+            }
+        };
+        logger::info(&format!(
+            "Patch is {} bytes for a {} byte file, storing the new file instead ({} bytes)",
+            patch_len, new_len, full_len
+        ));
+        std::fs::rename(&full_path, patch_path)?;
+        Ok(true)
+    }
 
-        // 智能选择输出路径并写入
-        Self::write_patched_data(&new_data, new_file, config.use_fast_temp_dir)?;
+    /// 完整文件补丁：补丁头照常记录新旧文件摘要并标记 `full_file`，负载以空内容为旧文件 diff
+    fn write_full_file_patch<W: Write>(
+        old: &[u8],
+        new: &[u8],
+        writer: W,
+        config: &OptimizationConfig
+    ) -> Result<W, BsdiffError> {
+        if let Some(key) = &config.encryption_key {
+            let patch = Self::write_full_file_patch(old, new, Vec::new(), &OptimizationConfig { encryption_key: None, ..config.clone() })?;
+            let mut writer = writer;
+            writer.write_all(&encryption::seal(key, &patch)?)?;
+            return Ok(writer);
+        }
+        let digest = |data: &[u8]| FileDigest::of_bytes_with(config.hash_algorithm, data);
+        let header = PatchHeader { full_file: true, ..PatchHeader::with_digests(digest(old), digest(new)).with_source_prefix(old) };
+        Self::encode_patch(writer, header, config, &[], new.len() as u64, |payload| Self::diff_raw(&[], new, config, payload))
+    }
 
-        Ok(())
+    /// 对内存中的新旧文件数据生成补丁，流式写入 `patch_file` (适合构建服务器上已解压到内存的产物)
+    pub fn diff_bytes_to_file(
+        old: &[u8],
+        new: &[u8],
+        patch_file: &Path,
+        config: &OptimizationConfig
+    ) -> Result<(), BsdiffError> {
+        Self::prepare_output(patch_file, config)?;
+        let patch_path = Self::get_optimal_output_path(patch_file, config, new.len() as u64)?;
+        let result = Self::with_safe_mode_retry(config, "diff", |config| {
+            let writer = BufWriter::with_capacity(64 * 1024, File::create(&patch_path)?);
+            Self::write_patch_bytes(old, new, writer, config)?.flush()?;
+            Ok(())
+        });
+        if let Err(e) = result {
+            let _ = std::fs::remove_file(&patch_path);
+            return Err(e);
+        }
+        Self::finalize_reported(&patch_path, patch_file, config)
     }
 
-    // === 核心优化方法 ===
+    /// 内存数据的 diff 流程，返回写入器
+    fn write_patch_bytes<W: Write>(
+        old: &[u8],
+        new: &[u8],
+        writer: W,
+        config: &OptimizationConfig
+    ) -> Result<W, BsdiffError> {
+        if let Some(key) = &config.encryption_key {
+            let patch = Self::write_patch_bytes(old, new, Vec::new(), &OptimizationConfig { encryption_key: None, ..config.clone() })?;
+            let mut writer = writer;
+            writer.write_all(&encryption::seal(key, &patch)?)?;
+            return Ok(writer);
+        }
+        let input_len = (old.len() + new.len()) as u64;
+        config.report_phase(ProgressPhase::Read, 0, input_len);
+        let header = PatchHeader::with_digests(
+            FileDigest::of_bytes_with(config.hash_algorithm, old),
+            FileDigest::of_bytes_with(config.hash_algorithm, new),
+        )
+        .with_source_prefix(old);
+        config.report_phase(ProgressPhase::Read, input_len, input_len);
+        let append_prefix = match config.mode {
+            DiffMode::Full => None,
+            DiffMode::Append => {
+                let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count() as u64;
+                let prefix = Self::accept_append_prefix(prefix, old.len() as u64);
+                if prefix.is_none() {
+                    logger::info("Common prefix too short for append mode, falling back to full bsdiff");
+                }
+                prefix
+            }
+        };
 
-    /// 创建内存映射 (双文件版本)
-    #[inline]
-    fn create_memory_maps(old_file: &str, new_file: &str) -> Result<(memmap2::Mmap, memmap2::Mmap), Box<dyn std::error::Error>> {
-        let old_file_handle = File::open(old_file)?;
-        let new_file_handle = File::open(new_file)?;
-        
-        let old_mmap = unsafe { MmapOptions::new().map(&old_file_handle)? };
-        let new_mmap = unsafe { MmapOptions::new().map(&new_file_handle)? };
-        
-        Ok((old_mmap, new_mmap))
-    }
+        let (archive, expanded) = match append_prefix.is_none().then(|| Self::plan_archive_transform(old, new, config)).flatten() {
+            Some((archive, old, new)) => (Some(archive), Some((old, new))),
+            None => (None, None),
+        };
+        if let Some(transform) = (append_prefix.is_none() && archive.is_none()).then(|| Self::plan_exe_transform(old, new, config)).flatten() {
+            return Self::encode_exe_patch(writer, header, transform, config, old, new);
+        }
+        let (diff_old, diff_new) = expanded.as_ref().map_or((old, new), |(old, new)| (&old[..], &new[..]));
+        let header = PatchHeader { archive_transform: archive, ..header };
 
-    /// 创建内存映射 (单文件版本)
-    #[inline]
-    fn create_single_memory_map(file_path: &str) -> Result<memmap2::Mmap, Box<dyn std::error::Error>> {
-        let file_handle = File::open(file_path)?;
-        Ok(unsafe { MmapOptions::new().map(&file_handle)? })
+        match append_prefix {
+            Some(prefix_len) => {
+                let header = PatchHeader { control_blocks: Some(1), ..header };
+                Self::encode_patch(writer, header, config, old, new.len() as u64, |payload| {
+                    let copy_len = new.len() as u64 - prefix_len;
+                    ControlEntry { mix_len: prefix_len, copy_len, seek: 0 }.write_to(payload)?;
+                    io::copy(&mut io::repeat(0).take(prefix_len), payload)?;
+                    payload.write_all(&new[prefix_len as usize..])
+                })
+            }
+            None => Self::encode_patch(writer, header, config, diff_old, diff_new.len() as u64, |payload| {
+                Self::diff_raw(diff_old, diff_new, config, payload)
+            }),
+        }
     }
 
-    /// 创建高性能Zstd编码器
-    #[inline]
-    fn create_zstd_encoder(output_path: &Path, compression_level: i32) -> Result<ZstdEncoder<'_, BufWriter<File>>, Box<dyn std::error::Error>> {
-        let file_handle = File::create(output_path)?;
-        let writer = BufWriter::with_capacity(64 * 1024, file_handle); // 64KB 缓冲区
-        Ok(ZstdEncoder::new(writer, compression_level)?)
-    }
+    /// 只对新旧文件中的指定区间 `(offset, len)` 生成补丁，适合磁盘镜像中只有已知分区 (如 rootfs) 需要差分的场景
+    ///
+    /// 两个区间直接按偏移映射为切片，不复制整个文件；补丁头记录的是区间内容的摘要，
+    /// 应用时以旧区间的内容 (单独的分区设备或提取出的分区文件) 作为旧文件。
+    pub fn diff_range(
+        old_file: &Path,
+        old_range: (u64, u64),
+        new_file: &Path,
+        new_range: (u64, u64),
+        patch_file: &Path,
+        config: &OptimizationConfig
+    ) -> Result<(), BsdiffError> {
+        Self::validate_files(old_file, new_file)?;
+        let old_mmap = Self::create_range_memory_map(old_file, old_range)?;
+        let new_mmap = Self::create_range_memory_map(new_file, new_range)?;
 
-    /// 解码补丁并应用
-    #[inline]
-    fn decode_and_patch(old_data: &[u8], patch_file: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let patch_file_handle = File::open(patch_file)?;
-        let mut decoder = ZstdDecoder::new(patch_file_handle)?;
-        
-        let mut new_data = Vec::new();
-        bsdiff::patch(old_data, &mut decoder, &mut new_data)?;
-        
-        Ok(new_data)
+        let patch_path = Self::get_optimal_output_path(patch_file, config, new_range.1)?;
+        let result = Self::diff_bytes(&old_mmap, &new_mmap, config).and_then(|patch| Ok(std::fs::write(&patch_path, patch)?));
+        if let Err(e) = result {
+            let _ = std::fs::remove_file(&patch_path);
+            return Err(e);
+        }
+        Self::finalize_reported(&patch_path, patch_file, config)
     }
 
-    /// 写入补丁数据到文件
-    #[inline]
-    fn write_patched_data(data: &[u8], output_file: &str, use_fast_temp: bool) -> Result<(), Box<dyn std::error::Error>> {
-        let output_path = Self::get_optimal_output_path(output_file, use_fast_temp)?;
-        
-        let mut writer = BufWriter::with_capacity(64 * 1024, File::create(&output_path)?);
-        writer.write_all(data)?;
-        writer.flush()?;
-        
-        Self::finalize_output(&output_path, output_file)
+    /// 启用 `exe_transform` 时为新旧文件规划可执行文件变换；不是同一架构的可执行文件时按普通文件 diff
+    fn plan_exe_transform(old: &[u8], new: &[u8], config: &OptimizationConfig) -> Option<ExeTransform> {
+        if !config.exe_transform {
+            return None;
+        }
+        let transform = ExeTransform::plan(old, new);
+        match &transform {
+            Some(transform) => logger::info(&format!(
+                "Executable transform: {} ({} old / {} new code ranges)",
+                transform.arch.name(), transform.old_ranges.len(), transform.new_ranges.len()
+            )),
+            None => logger::info("Inputs are not executables of the same architecture, diffing without executable transform"),
+        }
+        transform
     }
 
-    /// 获取最优输出路径
-    #[inline]
-    fn get_optimal_output_path(original_path: &str, use_fast_temp: bool) -> Result<PathBuf, Box<dyn std::error::Error>> {
-        if use_fast_temp {
-            let fast_temp_dir = Self::get_fast_temp_dir();
-            let file_name = Path::new(original_path)
-                .file_name()
-                .ok_or("Invalid file path")?;
-            Ok(fast_temp_dir.join(format!("bsdiff_{}", file_name.to_string_lossy())))
-        } else {
-            Ok(PathBuf::from(original_path))
-        }
+    /// 分别生成经过可执行文件变换与直接 diff 的补丁，写出较小的一个
+    ///
+    /// 变换只在调用目标位置不变的指令 (调用 PLT/导入表与未移动的函数) 上有效，重新链接后
+    /// 调用者与被调用者一起移动时反而让原本相同的字节变得不同，只能实际 diff 两次比较。
+    /// 第一次 diff 不上报进度，进度回调只经历一次完整的 diff。
+    fn encode_exe_patch<W: Write>(
+        mut writer: W,
+        header: PatchHeader,
+        transform: ExeTransform,
+        config: &OptimizationConfig,
+        old: &[u8],
+        new: &[u8]
+    ) -> Result<W, BsdiffError> {
+        let (old_data, new_data) = (transform.encode_old(old)?, transform.encode_new(new)?);
+        let quiet = OptimizationConfig { progress: None, phase_progress: None, block_progress: None, ..config.clone() };
+        let transformed_header = PatchHeader { exe_transform: Some(transform), ..header.clone() };
+        let transformed = Self::encode_patch(Vec::new(), transformed_header, &quiet, &old_data, new.len() as u64, |payload| {
+            Self::diff_raw(&old_data, &new_data, &quiet, payload)
+        })?;
+        drop((old_data, new_data));
+        let plain = Self::encode_patch(Vec::new(), header, config, old, new.len() as u64, |payload| {
+            Self::diff_raw(old, new, config, payload)
+        })?;
+        logger::info(&format!(
+            "Executable transform: {} bytes with, {} bytes without; keeping the {} patch",
+            transformed.len(),
+            plain.len(),
+            if transformed.len() < plain.len() { "transformed" } else { "plain" }
+        ));
+        writer.write_all(if transformed.len() < plain.len() { &transformed } else { &plain })?;
+        Ok(writer)
     }
 
-    /// 原子性完成输出
-    #[inline]
-    fn finalize_output(temp_path: &Path, final_path: &str) -> Result<(), Box<dyn std::error::Error>> {
-        if temp_path != Path::new(final_path) {
-            std::fs::rename(temp_path, final_path)?;
+    /// 启用 `archive_transform` 时解压展开新旧文件，返回变换与展开后的旧、新数据；新文件不是 ZIP 或条目都无法复现时按普通文件 diff
+    fn plan_archive_transform(old: &[u8], new: &[u8], config: &OptimizationConfig) -> Option<(ArchiveTransform, Vec<u8>, Vec<u8>)> {
+        if !config.archive_transform {
+            return None;
         }
-        Ok(())
+        if !deflate::AVAILABLE {
+            logger::warn("Archive transform needs zlib, which is not available in this build; diffing without it");
+            return None;
+        }
+        if archive_transform::is_asar(new) {
+            logger::info("ASAR archives store files uncompressed, diffing without archive transform");
+            return None;
+        }
+        let planned = ArchiveTransform::plan(old, new);
+        match &planned {
+            Some((transform, _, _)) => logger::info(&format!(
+                "Archive transform: {} entries expanded ({} -> {} bytes)",
+                transform.entries, new.len(), transform.expanded_size
+            )),
+            None => logger::info("New file is not a ZIP with exactly recompressible entries, diffing without archive transform"),
+        }
+        planned
     }
 
-    /// 获取最快的临时目录
-    #[inline]
-    fn get_fast_temp_dir() -> PathBuf {
-        // Linux: 内存盘优先
-        if cfg!(target_os = "linux") && Path::new("/dev/shm").exists() {
-            return PathBuf::from("/dev/shm");
+    /// 核心diff流程
+    fn write_patch<W: Write>(
+        old_file: &Path,
+        new_file: &Path,
+        writer: W,
+        config: &OptimizationConfig
+    ) -> Result<W, BsdiffError> {
+        // 加密：先在内存中生成明文补丁，再整体封装
+        if let Some(key) = &config.encryption_key {
+            let patch = Self::write_patch(old_file, new_file, Vec::new(), &OptimizationConfig { encryption_key: None, ..config.clone() })?;
+            let mut writer = writer;
+            writer.write_all(&encryption::seal(key, &patch)?)?;
+            return Ok(writer);
         }
-        
-        // macOS: 检查RAM盘
-        if cfg!(target_os = "macos") {
-            if let Ok(entries) = std::fs::read_dir("/Volumes") {
-                for entry in entries.flatten() {
-                    if entry.file_name().to_string_lossy().contains("RAM") {
-                        return entry.path();
-                    }
+
+        // read 阶段：映射输入并计算摘要 (重叠计算摘要时与 apply 阶段同时进行)
+        let input_len = std::fs::metadata(old_file)?.len() + std::fs::metadata(new_file)?.len();
+        config.report_phase(ProgressPhase::Read, 0, input_len);
+        let inputs_read = || config.report_phase(ProgressPhase::Read, input_len, input_len);
+
+        // 追加模式：流式比较共同前缀
+        let append_prefix = match config.mode {
+            DiffMode::Full => None,
+            DiffMode::Append => {
+                let prefix = Self::find_append_prefix(old_file, new_file)?;
+                if prefix.is_none() {
+                    logger::info("Common prefix too short for append mode, falling back to full bsdiff");
+                }
+                prefix
+            }
+        };
+
+        match append_prefix {
+            Some(prefix_len) => {
+                // 追加模式流式计算摘要，保持常量内存
+                let old_mmap = Self::create_single_memory_map(old_file)?;
+                let header = PatchHeader {
+                    control_blocks: Some(1),
+                    ..PatchHeader::with_digests(
+                        FileDigest::of_file_with(config.hash_algorithm, old_file)?,
+                        FileDigest::of_file_with(config.hash_algorithm, new_file)?,
+                    )
+                    .with_source_prefix(&old_mmap)
+                };
+                let new_len = header.target.map_or(0, |target| target.size);
+                inputs_read();
+                Self::encode_patch(writer, header, config, &old_mmap, new_len, |payload| {
+                    Self::write_append_patch(new_file, prefix_len, payload)
+                })
+            }
+            None => {
+                // 内存映射文件 - 零拷贝高性能I/O
+                let (old_mmap, new_mmap) = Self::create_memory_maps(old_file, new_file)?;
+                let new_len = new_mmap.len() as u64;
+                let digest = |data: &[u8]| FileDigest::of_bytes_with(config.hash_algorithm, data);
+                if let Some((transform, old_data, new_data)) = Self::plan_archive_transform(&old_mmap, &new_mmap, config) {
+                    let header = PatchHeader {
+                        archive_transform: Some(transform),
+                        ..PatchHeader::with_digests(digest(&old_mmap), digest(&new_mmap)).with_source_prefix(&old_mmap)
+                    };
+                    inputs_read();
+                    return Self::encode_patch(writer, header, config, &old_data, new_data.len() as u64, |payload| {
+                        Self::diff_raw(&old_data, &new_data, config, payload)
+                    });
+                }
+                if let Some(transform) = Self::plan_exe_transform(&old_mmap, &new_mmap, config) {
+                    let header = PatchHeader::with_digests(digest(&old_mmap), digest(&new_mmap)).with_source_prefix(&old_mmap);
+                    inputs_read();
+                    return Self::encode_exe_patch(writer, header, transform, config, &old_mmap, &new_mmap);
                 }
+                let diff_payload = |payload: &mut dyn Write| Self::diff_raw(&old_mmap, &new_mmap, config, payload);
+
+                if !config.overlap_hashing || config.format != PatchFormat::Zstd {
+                    let header = PatchHeader::with_digests(digest(&old_mmap), digest(&new_mmap)).with_source_prefix(&old_mmap);
+                    inputs_read();
+                    return Self::encode_patch(writer, header, config, &old_mmap, new_len, diff_payload);
+                }
+                inputs_read();
+
+                // 摘要计算与 diff 并行：两者只读同一份映射，补丁头在汇合后写入
+                let ((old_digest, new_digest), payload) = rayon::join(
+                    || (digest(&old_mmap), digest(&new_mmap)),
+                    || Self::compress_payload(Vec::new(), config, new_len, diff_payload),
+                );
+                let (payload, control_blocks) = payload?;
+                let header = PatchHeader {
+                    compression: config.compression,
+                    control_blocks: Some(control_blocks),
+                    metadata: config.metadata.clone(),
+                    dictionary_id: config.dictionary.as_ref().map(Dictionary::id),
+                    payload_crc: true,
+                    ..PatchHeader::with_digests(old_digest, new_digest).with_source_prefix(&old_mmap)
+                };
+                let mut writer = writer;
+                header.write_to(&mut writer)?;
+                Ok(Self::write_payload(writer, &payload)?)
             }
         }
-        
-        std::env::temp_dir()
     }
 
-    // === 验证方法 ===
+    /// 生成原始 bsdiff 流；给出窗口大小且输入超过窗口时分窗口 diff，限制后缀数组的内存占用
+    ///
+    /// 新文件按 `window_size` 切分，每个窗口只与旧文件中相同偏移两侧各扩展半个窗口的区间 diff，
+    /// 峰值内存随窗口大小而非文件大小增长。窗口之间插入只含 seek 的空控制块，把旧文件游标移到
+    /// 下一个区间的起点，拼接后仍是合法的 bsdiff 流，应用补丁时无需区分。
+    ///
+    /// 窗口按 `threads` 个一批并行 diff (后缀排序各自独立)，按顺序写出，输出与线程数无关；
+    /// 峰值内存约为线程数 × 单窗口占用。
+    pub(crate) fn diff_raw(old: &[u8], new: &[u8], config: &OptimizationConfig, mut payload: &mut dyn Write) -> io::Result<()> {
+        use rayon::prelude::*;
 
-    /// 验证diff输入文件
-    #[inline]
-    fn validate_files(old_file: &str, new_file: &str) -> Result<(), Box<dyn std::error::Error>> {
-        if !Path::new(old_file).exists() {
-            return Err(format!("Old file not found: {}", old_file).into());
+        if config.algorithm == DiffAlgorithm::BlockDelta {
+            return block_delta::diff(old, new, config.threads, config.cancel.as_ref(), payload);
         }
-        if !Path::new(new_file).exists() {
-            return Err(format!("New file not found: {}", new_file).into());
+        let window = match config.window_size {
+            Some(size) if old.len().max(new.len()) as u64 > size => usize::try_from(size.max(1)).unwrap_or(usize::MAX),
+            _ => return prepared::diff(old, new, config.cancel.as_ref(), &mut payload),
+        };
+        let margin = window / 2;
+        let windows: Vec<(usize, &[u8])> = new.chunks(window).enumerate().map(|(index, chunk)| (index * window, chunk)).collect();
+
+        // 窗口输出先缓冲 (约为窗口大小)，以便求出窗口结束时的旧文件游标
+        let diff_window = |&(start, new_window): &(usize, &[u8])| -> io::Result<(usize, Vec<u8>, i64)> {
+            let old_start = start.saturating_sub(margin).min(old.len());
+            let old_end = start.saturating_add(new_window.len()).saturating_add(margin).min(old.len());
+            let mut raw = Vec::new();
+            prepared::diff(&old[old_start..old_end], new_window, config.cancel.as_ref(), &mut raw)?;
+            let mut window_pos: i64 = 0;
+            bsdiff40::for_each_entry(&raw, |entry, _, _| {
+                window_pos += entry.mix_len as i64 + entry.seek;
+                Ok(())
+            })?;
+            Ok((old_start, raw, window_pos))
+        };
+
+        let mut builder = rayon::ThreadPoolBuilder::new();
+        if let Some(threads) = config.threads {
+            builder = builder.num_threads(threads.max(1));
+        }
+        let pool = builder.build().map_err(io::Error::other)?;
+
+        let mut old_pos: i64 = 0;
+        for batch in windows.chunks(pool.current_num_threads()) {
+            cancel::check(config.cancel.as_ref())?;
+            let results: Vec<io::Result<(usize, Vec<u8>, i64)>> = pool.install(|| batch.par_iter().map(diff_window).collect());
+            for result in results {
+                let (old_start, raw, window_pos) = result?;
+                if old_start as i64 != old_pos {
+                    ControlEntry { mix_len: 0, copy_len: 0, seek: old_start as i64 - old_pos }.write_to(&mut *payload)?;
+                }
+                payload.write_all(&raw)?;
+                old_pos = old_start as i64 + window_pos;
+            }
         }
         Ok(())
     }
 
-    /// 验证patch输入文件
-    #[inline]
-    fn validate_patch_files(old_file: &str, patch_file: &str) -> Result<(), Box<dyn std::error::Error>> {
-        if !Path::new(old_file).exists() {
-            return Err(format!("Old file not found: {}", old_file).into());
+    /// 写入补丁头，并将原始bsdiff流按配置的压缩算法写出
+    ///
+    /// 补丁头未给出控制块总数时，负载先压缩到内存并在压缩过程中计数，再连同补丁头写出。
+    ///
+    /// `old` 为旧文件数据，仅 VCDIFF 格式需要 (差分字节需还原为新文件内容)。
+    pub(crate) fn encode_patch<W, F>(
+        mut writer: W,
+        mut header: PatchHeader,
+        config: &OptimizationConfig,
+        old: &[u8],
+        new_len: u64,
+        write_payload: F
+    ) -> Result<W, BsdiffError>
+    where
+        W: Write,
+        F: FnOnce(&mut dyn Write) -> io::Result<()>,
+    {
+        match config.format {
+            PatchFormat::Zstd => {}
+            _ if !config.metadata.is_empty() => {
+                return Err("Patch metadata requires the zstd format (BSDIFF40 and VCDIFF patches have no header)".into());
+            }
+            _ if header.exe_transform.is_some() => {
+                return Err("The executable transform requires the zstd format (BSDIFF40 and VCDIFF patches have no header)".into());
+            }
+            _ if header.archive_transform.is_some() => {
+                return Err("The archive transform requires the zstd format (BSDIFF40 and VCDIFF patches have no header)".into());
+            }
+            _ if config.dictionary.is_some() => {
+                return Err("A zstd dictionary requires the zstd format (BSDIFF40 and VCDIFF patches have no header)".into());
+            }
+            PatchFormat::Bsdiff40 if config.compression != Compression::Zstd => {
+                return Err("The compression option does not apply to BSDIFF40 patches (always bzip2)".into());
+            }
+            PatchFormat::Vcdiff if config.compression != Compression::Zstd => {
+                return Err("The compression option does not apply to VCDIFF patches (always uncompressed)".into());
+            }
+            PatchFormat::Bsdiff40 => {
+                return Self::encode_converted(writer, config, new_len, write_payload, bsdiff40::to_bsdiff40);
+            }
+            PatchFormat::Vcdiff => {
+                return Self::encode_converted(writer, config, new_len, write_payload, |raw| Ok(vcdiff::from_raw(old, raw)?));
+            }
         }
-        if !Path::new(patch_file).exists() {
-            return Err(format!("Patch file not found: {}", patch_file).into());
+        header.compression = config.compression;
+        header.metadata.extend(config.metadata.clone());
+        header.dictionary_id = config.dictionary.as_ref().map(Dictionary::id);
+        header.payload_crc = true;
+        if header.control_blocks.is_some() {
+            header.write_to(&mut writer)?;
+            let (payload, _) = Self::compress_payload(PayloadWriter::new(writer), config, new_len, write_payload)?;
+            return Ok(payload.finish()?);
         }
-        Ok(())
+        let (payload, control_blocks) = Self::compress_payload(Vec::new(), config, new_len, write_payload)?;
+        header.control_blocks = Some(control_blocks);
+        header.write_to(&mut writer)?;
+        Ok(Self::write_payload(writer, &payload)?)
     }
 
+    /// 写出已压缩的负载及其 CRC-32C 尾部
+    fn write_payload<W: Write>(writer: W, payload: &[u8]) -> io::Result<W> {
+        let mut writer = PayloadWriter::new(writer);
+        writer.write_all(payload)?;
+        writer.finish()
+    }
 
-}
+    /// 收集原始bsdiff流，整体转换为第三方格式写出 (BSDIFF40 三段 bzip2 / VCDIFF，均无补丁头)
+    fn encode_converted<W, F, C>(
+        mut writer: W,
+        config: &OptimizationConfig,
+        new_len: u64,
+        write_payload: F,
+        convert: C
+    ) -> Result<W, BsdiffError>
+    where
+        W: Write,
+        F: FnOnce(&mut dyn Write) -> io::Result<()>,
+        C: FnOnce(&[u8]) -> Result<Vec<u8>, BsdiffError>,
+    {
+        let mut raw = Vec::new();
+        let reporter = config.apply_reporter(new_len);
+        write_payload(&mut DiffProgressWriter::new(&mut raw, reporter, config.cancel.clone()))?;
+        writer.write_all(&convert(&raw)?)?;
+        Ok(writer)
+    }
+
+    /// 将原始bsdiff流按配置的压缩算法写出 (不含补丁头)，同时返回写出的控制块数量
+    fn compress_payload<W, F>(
+        mut writer: W,
+        config: &OptimizationConfig,
+        new_len: u64,
+        write_payload: F
+    ) -> io::Result<(W, u64)>
+    where
+        W: Write,
+        F: FnOnce(&mut dyn Write) -> io::Result<()>,
+    {
+        let reporter = config.apply_reporter(new_len);
+        match config.compression {
+            compression if compression != Compression::Zstd && config.dictionary.is_some() => {
+                Err(io::Error::new(io::ErrorKind::InvalidInput, "A zstd dictionary requires zstd compression"))
+            }
+            Compression::None => {
+                let mut tracked = DiffProgressWriter::new(&mut writer, reporter, config.cancel.clone());
+                write_payload(&mut tracked)?;
+                let control_blocks = tracked.control_blocks();
+                Ok((writer, control_blocks))
+            }
+            Compression::Zstd => {
+                // 自动级别：在原始流样本上选定级别后再创建编码器 (可复现模式下使用固定级别)
+                if let Some(auto) = config.auto_level.as_ref().filter(|_| !config.deterministic) {
+                    let mut probe = LevelProbe::new(writer, config, auto);
+                    let mut tracked = DiffProgressWriter::new(&mut probe, reporter, config.cancel.clone());
+                    write_payload(&mut tracked)?;
+                    let control_blocks = tracked.control_blocks();
+                    return Ok((probe.finish()?, control_blocks));
+                }
+                // 创建高性能Zstd编码器
+                let mut encoder = Self::create_zstd_encoder(writer, config)?;
+                let mut tracked = DiffProgressWriter::new(&mut encoder, reporter, config.cancel.clone());
+                write_payload(&mut tracked)?;
+                let control_blocks = tracked.control_blocks();
+                Ok((encoder.finish()?, control_blocks))
+            }
+            compression => {
+                let mut encoder = codecs::Encoder::new(compression, writer)?;
+                let mut tracked = DiffProgressWriter::new(&mut encoder, reporter, config.cancel.clone());
+                write_payload(&mut tracked)?;
+                let control_blocks = tracked.control_blocks();
+                Ok((encoder.finish()?, control_blocks))
+            }
+        }
+    }
+
+    /// 按压缩算法包装补丁负载读取器
+    fn payload_reader<'a, R: Read + 'a>(
+        compression: Compression,
+        dictionary: Option<&Dictionary>,
+        reader: R
+    ) -> io::Result<Box<dyn Read + 'a>> {
+        Self::limited_payload_reader(compression, dictionary, reader, None)
+    }
+
+    /// 同 [`Self::payload_reader`]，给出 `max_memory` 时 zstd 拒绝解码窗口超过该大小的帧
+    fn limited_payload_reader<'a, R: Read + 'a>(
+        compression: Compression,
+        dictionary: Option<&Dictionary>,
+        reader: R,
+        max_memory: Option<u64>
+    ) -> io::Result<Box<dyn Read + 'a>> {
+        let window_log_max = max_memory.map(|limit| (63 - limit.max(1).leading_zeros()).clamp(ZSTD_MIN_WINDOW_LOG, ZSTD_MAX_WINDOW_LOG));
+        Ok(match (compression, dictionary) {
+            (Compression::None, _) => Box::new(BufReader::with_capacity(64 * 1024, reader)),
+            (Compression::Zstd, None) => {
+                let mut decoder = ZstdDecoder::new(reader)?;
+                if let Some(log) = window_log_max {
+                    decoder.window_log_max(log)?;
+                }
+                Box::new(decoder)
+            }
+            (Compression::Zstd, Some(dictionary)) => {
+                let mut decoder = ZstdDecoder::with_dictionary(BufReader::new(reader), dictionary.as_bytes())?;
+                if let Some(log) = window_log_max {
+                    decoder.window_log_max(log)?;
+                }
+                Box::new(decoder)
+            }
+            (compression, _) => codecs::decoder(compression, reader, max_memory)?,
+        })
+    }
+
+    /// 需要在内存中缓冲 `required` 字节时检查 `max_memory`
+    fn check_memory(required: u64, patch_file: &Path, config: &OptimizationConfig) -> Result<(), BsdiffError> {
+        match config.max_memory {
+            Some(limit) if required > limit => Err(BsdiffError::LimitExceeded {
+                patch_file: patch_file.display().to_string(),
+                resource: "memory",
+                limit,
+                required: Some(required),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// 补丁头声明的目标大小超过 `max_output_size` 时返回 [`BsdiffError::LimitExceeded`]
+    fn check_output_size(target: u64, patch_file: &Path, config: &OptimizationConfig) -> Result<(), BsdiffError> {
+        match config.max_output_size {
+            Some(limit) if target > limit => Err(BsdiffError::LimitExceeded {
+                patch_file: patch_file.display().to_string(),
+                resource: "output",
+                limit,
+                required: Some(target),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// 补丁头记录了字典 ID 时返回与之匹配的字典；未提供或 ID 不同返回 [`BsdiffError::DictionaryMismatch`]。
+    /// 补丁未使用字典时忽略提供的字典
+    fn payload_dictionary<'a>(
+        header: Option<&PatchHeader>,
+        dictionary: Option<&'a Dictionary>,
+        patch_file: &Path
+    ) -> Result<Option<&'a Dictionary>, BsdiffError> {
+        match header.and_then(|header| header.dictionary_id) {
+            None => Ok(None),
+            Some(expected) if dictionary.is_some_and(|dictionary| dictionary.id() == expected) => Ok(dictionary),
+            Some(expected) => Err(BsdiffError::DictionaryMismatch {
+                patch_file: patch_file.display().to_string(),
+                expected,
+                actual: dictionary.map(Dictionary::id),
+            }),
+        }
+    }
+
+    /// 基于预计算后缀数组的旧文件生成补丁，适合同一基准对多个新文件批量 diff
+    ///
+    /// `base_digest` 为旧文件摘要，由调用方在准备基准时计算一次。
+    pub fn diff_prepared<D: AsRef<[u8]>>(
+        base: &PreparedBase<D>,
+        base_digest: FileDigest,
+        new_file: &Path,
+        patch_file: &Path,
+        config: &OptimizationConfig
+    ) -> Result<(), BsdiffError> {
+        if !Path::new(new_file).exists() {
+            return Err(BsdiffError::FileNotFound { kind: "New file", path: new_file.display().to_string() });
+        }
+
+        let new_mmap = Self::create_single_memory_map(new_file)?;
+        let header = PatchHeader::with_digests(base_digest, FileDigest::of_bytes_with(base_digest.algorithm, &new_mmap))
+            .with_source_prefix(base.old());
+        let patch_path = Self::get_optimal_output_path(patch_file, config, new_mmap.len() as u64)?;
+
+        Self::with_safe_mode_retry(config, "diff", |config| {
+            let writer = BufWriter::with_capacity(64 * 1024, File::create(&patch_path)?);
+            Self::encode_patch(writer, header.clone(), config, base.old(), new_mmap.len() as u64, |payload| {
+                base.diff(&new_mmap, payload)
+            })?
+            .flush()?;
+            Ok(())
+        })?;
+
+        Self::finalize_output(&patch_path, patch_file, config)
+    }
+
+    /// 一次生成正向 (a→b) 与反向 (b→a) 补丁，用于回滚
+    ///
+    /// 两个文件只读取/映射和计算摘要一次，两个方向的 diff 并行执行。
+    /// 双向 diff 始终使用完整 bsdiff 算法，且不上报进度。
+    pub fn diff_bidirectional(
+        a_file: &Path,
+        b_file: &Path,
+        forward_patch: &Path,
+        reverse_patch: &Path,
+        config: &OptimizationConfig
+    ) -> Result<(), BsdiffError> {
+        Self::validate_files(a_file, b_file)?;
+
+        let (a_mmap, b_mmap) = Self::create_memory_maps(a_file, b_file)?;
+        let digest = |data: &[u8]| FileDigest::of_bytes_with(config.hash_algorithm, data);
+        let (a_digest, b_digest) = rayon::join(|| digest(&a_mmap), || digest(&b_mmap));
+
+        let forward_path = Self::get_optimal_output_path(forward_patch, config, b_mmap.len() as u64)?;
+        let reverse_path = Self::get_optimal_output_path(reverse_patch, config, a_mmap.len() as u64)?;
+        let config = OptimizationConfig { progress: None, phase_progress: None, ..config.clone() };
+
+        let write_one = |old: &[u8], new: &[u8], header: PatchHeader, path: &Path| -> Result<(), String> {
+            let file = File::create(path).map_err(|e| e.to_string())?;
+            let writer = BufWriter::with_capacity(64 * 1024, file);
+            Self::encode_patch(writer, header, &config, old, new.len() as u64, |payload| {
+                Self::diff_raw(old, new, &config, payload)
+            })
+            .and_then(|mut writer| Ok(writer.flush()?))
+            .map_err(|e| e.to_string())
+        };
+        let (forward, reverse) = rayon::join(
+            || write_one(&a_mmap, &b_mmap, PatchHeader::with_digests(a_digest, b_digest).with_source_prefix(&a_mmap), &forward_path),
+            || write_one(&b_mmap, &a_mmap, PatchHeader::with_digests(b_digest, a_digest).with_source_prefix(&b_mmap), &reverse_path),
+        );
+        forward?;
+        reverse?;
+
+        Self::finalize_output(&forward_path, forward_patch, &config)?;
+        Self::finalize_output(&reverse_path, reverse_patch, &config)?;
+        Ok(())
+    }
+
+    /// 以新的压缩级别重新压缩已有补丁
+    ///
+    /// 补丁头原样保留，只替换 Zstd 压缩层 (未压缩的补丁原样复制)；写出后重新解码校验原始 bsdiff 流逐字节一致。
+    pub fn recompress_patch(
+        in_patch: &Path,
+        out_patch: &Path,
+        config: &OptimizationConfig
+    ) -> Result<(), BsdiffError> {
+        if !Path::new(in_patch).exists() {
+            return Err(BsdiffError::FileNotFound { kind: "Patch file", path: in_patch.display().to_string() });
+        }
+
+        // 补丁头原始字节 (旧版裸 zstd 补丁为空)
+        let mut patch_file_handle = File::open(in_patch)?;
+        let header = Self::read_patch_header(&mut patch_file_handle, in_patch)?;
+        let compression = header.as_ref().map_or(Compression::Zstd, |header| header.compression);
+        let dictionary = Self::payload_dictionary(header.as_ref(), config.dictionary.as_ref(), in_patch)?;
+        let header_len = patch_file_handle.stream_position()?;
+        let mut header_bytes = vec![0u8; header_len as usize];
+        patch_file_handle.seek(SeekFrom::Start(0))?;
+        patch_file_handle.read_exact(&mut header_bytes)?;
+
+        let out_path = Self::get_optimal_output_path(out_patch, config, patch_file_handle.metadata()?.len())?;
+        let mut writer = BufWriter::with_capacity(64 * 1024, File::create(&out_path)?);
+        writer.write_all(&header_bytes)?;
+
+        // 流式解码 → 重新编码，同时计算原始流摘要；负载改变，尾部按新负载重新计算
+        let payload = Self::payload_source(header.as_ref(), patch_file_handle, in_patch)?;
+        let mut decoder = Self::payload_reader(compression, dictionary, payload)?;
+        let mut hasher = Sha256::new();
+        let mut writer = PayloadWriter::new(writer);
+        match compression {
+            Compression::None => {
+                Self::copy_hashed(&mut decoder, &mut writer, &mut hasher)?;
+            }
+            Compression::Zstd => {
+                let mut encoder = Self::create_zstd_encoder_with(&mut writer, config, dictionary)?;
+                Self::copy_hashed(&mut decoder, &mut encoder, &mut hasher)?;
+                encoder.finish()?;
+            }
+            // 其他算法使用固定参数，按同一算法重新编码
+            compression => {
+                let mut encoder = codecs::Encoder::new(compression, &mut writer)?;
+                Self::copy_hashed(&mut decoder, &mut encoder, &mut hasher)?;
+                encoder.finish()?;
+            }
+        }
+        let mut writer = match header.as_ref().is_some_and(|header| header.payload_crc) {
+            true => writer.finish()?,
+            false => writer.into_inner(),
+        };
+        writer.flush()?;
+        let expected: [u8; DIGEST_LEN] = hasher.finalize().into();
+
+        // 校验：头部字节与原始 bsdiff 流均未改变
+        let mut written = File::open(&out_path)?;
+        let mut written_header = vec![0u8; header_bytes.len()];
+        written.read_exact(&mut written_header)?;
+        let written = Self::payload_source(header.as_ref(), written, out_patch)?;
+        if written_header != header_bytes || Self::raw_stream_digest(compression, dictionary, written)? != expected {
+            let _ = std::fs::remove_file(&out_path);
+            return Err("Recompressed patch verification failed: bsdiff stream mismatch".into());
+        }
+
+        Self::finalize_output(&out_path, out_patch, config)?;
+        Ok(())
+    }
+
+    /// 复制数据并同时计算摘要
+    fn copy_hashed<R: Read + ?Sized, W: Write>(reader: &mut R, writer: &mut W, hasher: &mut Sha256) -> io::Result<()> {
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                return Ok(());
+            }
+            hasher.update(&buf[..n]);
+            writer.write_all(&buf[..n])?;
+        }
+    }
+
+    /// 计算负载解码后原始 bsdiff 流的摘要
+    fn raw_stream_digest<R: Read>(
+        compression: Compression,
+        dictionary: Option<&Dictionary>,
+        reader: R
+    ) -> Result<[u8; DIGEST_LEN], BsdiffError> {
+        let mut decoder = Self::payload_reader(compression, dictionary, reader)?;
+        let mut hasher = Sha256::new();
+        Self::copy_hashed(&mut decoder, &mut io::sink(), &mut hasher)?;
+        Ok(hasher.finalize().into())
+    }
+
+    /// 应用 bsdiff 补丁文件 (使用最优配置)
+    #[allow(dead_code)] // napi 层统一走 patch_optimized，保留给 Rust 调用方
+    pub fn patch(old_file: &Path, new_file: &Path, patch_file: &Path) -> Result<(), BsdiffError> {
+        Self::patch_optimized(old_file, new_file, patch_file, &OptimizationConfig::default())?;
+        Ok(())
+    }
+
+    /// 使用最优配置应用补丁 (内部优化实现)
+    pub fn patch_optimized(
+        old_file: &Path, 
+        new_file: &Path, 
+        patch_file: &Path,
+        config: &OptimizationConfig
+    ) -> Result<u64, BsdiffError> {
+        Self::audited(Some(old_file), new_file, patch_file, config, || Self::patch_file(old_file, new_file, patch_file, config))
+    }
+
+    /// 运行一次补丁应用，并在审计日志中记录开始与结束 (成功时的字节数或失败时的错误码) 事件
+    fn audited<F>(
+        old_file: Option<&Path>,
+        new_file: &Path,
+        patch_file: &Path,
+        config: &OptimizationConfig,
+        run: F
+    ) -> Result<u64, BsdiffError>
+    where
+        F: FnOnce() -> Result<u64, BsdiffError>,
+    {
+        config.audit("patch_started", &[
+            ("old", old_file.into()),
+            ("new", new_file.into()),
+            ("patch", patch_file.into()),
+            ("dryRun", config.dry_run.into()),
+        ]);
+        let result = run();
+        match &result {
+            Ok(written) => config.audit("patch_finished", &[("bytes", (*written).into())]),
+            Err(e) => {
+                let message = e.to_string();
+                config.audit("patch_failed", &[
+                    ("code", e.code().map(ErrorCode::as_str).into()),
+                    ("message", (&message).into()),
+                ]);
+            }
+        }
+        result
+    }
+
+    /// [`Self::patch_optimized`] 的实现
+    fn patch_file(
+        old_file: &Path,
+        new_file: &Path,
+        patch_file: &Path,
+        config: &OptimizationConfig
+    ) -> Result<u64, BsdiffError> {
+        // 快速验证输入文件
+        Self::validate_patch_files(old_file, patch_file)?;
+        if let Some(volumes) = volumes::find_volumes(patch_file)? {
+            return volumes::patch_from_volumes(old_file, new_file, &volumes, config);
+        }
+        Self::check_arguments_order(old_file, patch_file)?;
+
+        // 内存映射旧文件 - 零拷贝读取
+        let old_mmap = Self::create_single_memory_map(old_file)?;
+
+        if config.dry_run {
+            return Self::patch_dry_run(&old_mmap, patch_file, config);
+        }
+        Self::prepare_output(new_file, config)?;
+
+        // 流式解码并写出，峰值内存与新文件大小无关
+        Self::patch_to_file(&old_mmap, patch_file, new_file, None, config)
+    }
+
+    /// 试运行：流式应用补丁并丢弃输出 (应用时已校验补丁头记录的目标摘要)，返回将要写出的字节数
+    fn patch_dry_run(old_data: &[u8], patch_file: &Path, config: &OptimizationConfig) -> Result<u64, BsdiffError> {
+        let size = Self::with_safe_mode_retry(config, "patch", |config| {
+            let patch_file_handle = File::open(patch_file)?;
+            let total = patch_file_handle.metadata()?.len();
+            Self::patch_reader_into(old_data, patch_file_handle, total, patch_file, &mut io::sink(), config)
+        })?;
+        logger::info(&format!("Dry run: {} would produce {} bytes", patch_file.display(), size));
+        Ok(size)
+    }
+
+    /// 依次应用一串增量补丁 (如 v1→v2→v3)，中间版本只保存在内存中，返回新文件大小
+    ///
+    /// 每一步都会校验补丁头记录的源文件与目标文件摘要 (若有)，任何一步失败都不会产生输出。
+    pub fn patch_chain(
+        old_file: &Path,
+        patch_files: &[PathBuf],
+        new_file: &Path,
+        config: &OptimizationConfig
+    ) -> Result<u64, BsdiffError> {
+        if patch_files.is_empty() {
+            return Err("Patch chain is empty".into());
+        }
+        for patch_file in patch_files {
+            Self::validate_patch_files(old_file, patch_file)?;
+            Self::check_arguments_order(old_file, patch_file)?;
+        }
+
+        let old_mmap = Self::create_single_memory_map(old_file)?;
+        let mut current: Option<Vec<u8>> = None;
+        for patch_file in patch_files {
+            let input = current.as_deref().unwrap_or(&old_mmap);
+            let output = Self::with_safe_mode_retry(config, "patch", |config| {
+                let patch_file_handle = File::open(patch_file)?;
+                let total = patch_file_handle.metadata()?.len();
+                Self::patch_from_reader(input, patch_file_handle, total, patch_file, config)
+            })?;
+            current = Some(output);
+        }
+        let new_data = current.expect("patch chain is not empty");
+
+        let output_path = Self::get_streaming_output_path(new_file, config, new_data.len() as u64)?;
+        let write = File::create(&output_path)
+            .and_then(|file| ThrottledWriter::new(file, config.write_limit.clone()).write_all(&new_data));
+        match write {
+            Ok(()) => Self::finalize_output(&output_path, new_file, config)?,
+            Err(e) => {
+                let _ = std::fs::remove_file(&output_path);
+                return Err(e.into());
+            }
+        }
+        if config.durable {
+            Self::sync_output(new_file)?;
+        }
+        Ok(new_data.len() as u64)
+    }
+
+    /// 比较输出与补丁头记录的目标摘要，并记录审计事件
+    fn check_target_digest(
+        expected: FileDigest,
+        actual: FileDigest,
+        patch_file: &Path,
+        config: &OptimizationConfig
+    ) -> Result<(), BsdiffError> {
+        let ok = actual == expected;
+        config.audit("target_checked", &[
+            ("algorithm", expected.algorithm.name().into()),
+            ("expected", (&expected.hex()).into()),
+            ("actual", (&actual.hex()).into()),
+            ("size", actual.size.into()),
+            ("ok", ok.into()),
+        ]);
+        if !ok {
+            return Err(BsdiffError::TargetMismatch { patch_file: patch_file.display().to_string(), expected, actual });
+        }
+        Ok(())
+    }
+
+    /// 原地打补丁：用补丁后的内容原子替换 `file`，返回新文件大小
+    ///
+    /// 新内容先写入同目录下的临时文件并 fsync，再把原文件改名为备份、临时文件改名为 `file`；
+    /// 任何一步失败都会删除临时文件并恢复原文件。先改名而非直接覆盖，
+    /// 使 Windows 上正在运行的可执行文件也能被替换。新文件沿用原文件的权限。
+    pub fn patch_in_place(
+        file: &Path,
+        patch_file: &Path,
+        config: &OptimizationConfig
+    ) -> Result<u64, BsdiffError> {
+        Self::audited(Some(file), file, patch_file, config, || Self::replace_in_place(file, patch_file, config))
+    }
+
+    /// [`Self::patch_in_place`] 的实现
+    fn replace_in_place(
+        file: &Path,
+        patch_file: &Path,
+        config: &OptimizationConfig
+    ) -> Result<u64, BsdiffError> {
+        Self::validate_patch_files(file, patch_file)?;
+        Self::check_arguments_order(file, patch_file)?;
+
+        let target = Path::new(file);
+        let (temp_path, backup_path) = (sibling_temp_path(target, "bsdiff-tmp")?, sibling_temp_path(target, "bsdiff-old")?);
+        let permissions = std::fs::metadata(file)?.permissions();
+
+        let result = {
+            let old_mmap = Self::create_single_memory_map(file)?;
+            Self::with_safe_mode_retry(config, "patch", |config| {
+                let patch_file_handle = File::open(patch_file)?;
+                let total = patch_file_handle.metadata()?.len();
+                let output = ThrottledWriter::new(File::create(&temp_path)?, config.write_limit.clone());
+                let mut writer = BufWriter::with_capacity(64 * 1024, output);
+                let written = Self::patch_reader_into(&old_mmap, patch_file_handle, total, patch_file, &mut writer, config)?;
+                let output = writer.into_inner().map_err(|e| e.into_error())?.into_inner();
+                output.set_permissions(permissions.clone())?;
+                output.sync_all()?;
+                Ok(written)
+            })
+        };
+        let written = match result {
+            Ok(written) => written,
+            Err(e) => {
+                let _ = std::fs::remove_file(&temp_path);
+                return Err(e);
+            }
+        };
+        config.audit("bytes_written", &[("path", temp_path.as_path().into()), ("bytes", written.into())]);
+
+        if let Err(e) = config.retry.run("Moving target aside", || std::fs::rename(target, &backup_path)) {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(e.into());
+        }
+        if let Err(e) = config.retry.run("Renaming output", || std::fs::rename(&temp_path, target)) {
+            let _ = std::fs::rename(&backup_path, target);
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(e.into());
+        }
+        Self::sync_parent_dir(target);
+        config.audit("rename_committed", &[("from", temp_path.as_path().into()), ("to", target.into()), ("crossDevice", false.into())]);
+        // Windows 上运行中的可执行文件无法删除，备份留待下次清理
+        if let Err(e) = std::fs::remove_file(&backup_path) {
+            logger::warn(&format!("Could not remove backup {}: {}", backup_path.display(), e));
+        }
+        Ok(written)
+    }
+
+    /// 刷新目录项使改名持久化；仅 Unix 支持对目录 fsync，失败不影响结果
+    pub(crate) fn sync_parent_dir(path: &Path) {
+        #[cfg(unix)]
+        if let Some(dir) = path.parent().map(|dir| if dir.as_os_str().is_empty() { Path::new(".") } else { dir }) {
+            let _ = File::open(dir).and_then(|dir| dir.sync_all());
+        }
+        #[cfg(not(unix))]
+        let _ = path;
+    }
+
+    /// 将补丁应用到固定大小的区域 (如嵌入式设备的 flash 分区)
+    ///
+    /// 写入前先确定目标大小：优先使用补丁头声明的大小，旧版补丁则完整扫描一遍控制块。
+    /// 超过 `region_size` 时返回 [`BsdiffError::TargetExceedsRegion`] 且不创建输出文件；
+    /// `zero_pad` 为 true 时输出以零填充到 `region_size`。返回实际写入的字节数。
+    pub fn patch_to_fixed_region(
+        old_file: &Path,
+        new_file: &Path,
+        patch_file: &Path,
+        region_size: u64,
+        zero_pad: bool,
+        config: &OptimizationConfig
+    ) -> Result<u64, BsdiffError> {
+        Self::validate_patch_files(old_file, patch_file)?;
+        Self::check_arguments_order(old_file, patch_file)?;
+
+        let (header, decoder) = Self::open_patch(patch_file, config.dictionary.as_ref()).map_err(|e| Self::corrupt_header(patch_file, e))?;
+        let target_size = match header.and_then(|h| h.target) {
+            Some(target) => target.size,
+            None => Self::raw_output_size(decoder).map_err(|e| Self::classify_patch_error(patch_file, e))?,
+        };
+        if target_size > region_size {
+            return Err(BsdiffError::TargetExceedsRegion {
+                patch_file: patch_file.display().to_string(),
+                target_size,
+                region_size,
+            });
+        }
+
+        let old_mmap = Self::create_single_memory_map(old_file)?;
+        let config = OptimizationConfig { max_output_size: Some(config.max_output_size.map_or(region_size, |max| max.min(region_size))), ..config.clone() };
+        Self::patch_to_file(&old_mmap, patch_file, new_file, zero_pad.then_some(region_size), &config)
+    }
+
+    /// 扫描原始 bsdiff 流的控制块，返回其生成的新文件大小 (不应用补丁)
+    fn raw_output_size<R: Read>(patch: R) -> io::Result<u64> {
+        let mut reader = BufReader::with_capacity(64 * 1024, patch);
+        let mut size: u64 = 0;
+        while let Some(entry) = ControlEntry::read_from(&mut reader)? {
+            let len = entry.mix_len.checked_add(entry.copy_len)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "control lengths overflow"))?;
+            let skipped = io::copy(&mut (&mut reader).take(len), &mut io::sink())?;
+            if skipped != len {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated patch data"));
+            }
+            size = size.checked_add(len).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "control lengths overflow"))?;
+        }
+        Ok(size)
+    }
+
+    /// 在内存中应用补丁，返回新文件数据
+    pub fn patch_bytes(old: &[u8], patch: &[u8], config: &OptimizationConfig) -> Result<Vec<u8>, BsdiffError> {
+        Self::with_safe_mode_retry(config, "patch", |config| {
+            Self::patch_from_reader(old, io::Cursor::new(patch), patch.len() as u64, Path::new("<buffer>"), config)
+        })
+    }
+
+    /// 在内存中应用补丁，逐块写入 `writer` (如网络流)，返回写入的字节数
+    ///
+    /// 输出写出后无法撤回，因此不做 safe mode 重试；失败时 `writer` 中可能已有部分数据。
+    pub fn patch_bytes_to_writer<W: Write>(
+        old: &[u8],
+        patch: &[u8],
+        mut writer: W,
+        config: &OptimizationConfig
+    ) -> Result<u64, BsdiffError> {
+        let written = Self::patch_reader_into(old, io::Cursor::new(patch), patch.len() as u64, Path::new("<stream>"), &mut writer, config)?;
+        writer.flush()?;
+        Ok(written)
+    }
+
+    /// 对磁盘上的旧文件应用补丁文件，直接返回新文件数据而不写入磁盘
+    pub fn patch_to_bytes(old_file: &Path, patch_file: &Path, config: &OptimizationConfig) -> Result<Vec<u8>, BsdiffError> {
+        Self::validate_patch_files(old_file, patch_file)?;
+        Self::check_arguments_order(old_file, patch_file)?;
+        let old_mmap = Self::create_single_memory_map(old_file)?;
+        Self::with_safe_mode_retry(config, "patch", |config| {
+            let patch_file_handle = File::open(patch_file)?;
+            let total = patch_file_handle.metadata()?.len();
+            Self::patch_from_reader(&old_mmap, patch_file_handle, total, patch_file, config)
+        })
+    }
+
+    /// 对内存中的旧文件数据应用补丁文件，流式写入 `new_file`，返回新文件大小
+    pub fn patch_bytes_to_file(
+        old: &[u8],
+        patch_file: &Path,
+        new_file: &Path,
+        config: &OptimizationConfig
+    ) -> Result<u64, BsdiffError> {
+        if !Path::new(patch_file).exists() {
+            return Err(BsdiffError::FileNotFound { kind: "Patch file", path: patch_file.display().to_string() });
+        }
+        if config.dry_run {
+            return Self::patch_dry_run(old, patch_file, config);
+        }
+        Self::patch_to_file(old, patch_file, new_file, None, config)
+    }
+
+    /// 对磁盘上的旧文件应用内存中的补丁数据，流式写入 `new_file`，返回新文件大小 (不支持 dry run)
+    pub fn patch_file_with_patch_bytes(
+        old_file: &Path,
+        patch: &[u8],
+        new_file: &Path,
+        config: &OptimizationConfig
+    ) -> Result<u64, BsdiffError> {
+        if !Path::new(old_file).exists() {
+            return Err(BsdiffError::FileNotFound { kind: "Old file", path: old_file.display().to_string() });
+        }
+        if config.dry_run {
+            return Err("dryRun is not supported when the patch is a buffer".into());
+        }
+        let old_mmap = Self::create_single_memory_map(old_file)?;
+        let open_patch = || Ok((io::Cursor::new(patch), patch.len() as u64));
+        Self::patch_reader_to_file(&old_mmap, open_patch, Path::new("<buffer>"), new_file, None, config)
+    }
+
+    /// 不依赖旧文件生成补丁：以空内容为旧文件 diff，补丁只含新增数据，容器与普通补丁相同
+    ///
+    /// 用于目录级更新中的新增文件。新文件经内存映射读取，补丁先在内存中生成再写出。
+    pub fn create_full_patch(new_file: &Path, patch_file: &Path, config: &OptimizationConfig) -> Result<(), BsdiffError> {
+        if !Path::new(new_file).exists() {
+            return Err(BsdiffError::FileNotFound { kind: "New file", path: new_file.display().to_string() });
+        }
+        Self::prepare_output(patch_file, config)?;
+        let new_mmap = Self::create_single_memory_map(new_file)?;
+        let patch = Self::with_safe_mode_retry(config, "diff", |config| Self::diff_bytes(&[], &new_mmap, config))?;
+
+        let patch_path = Self::get_optimal_output_path(patch_file, config, patch.len() as u64)?;
+        if let Err(e) = std::fs::write(&patch_path, &patch) {
+            let _ = std::fs::remove_file(&patch_path);
+            return Err(e.into());
+        }
+        Self::finalize_reported(&patch_path, patch_file, config)
+    }
+
+    /// 不依赖旧文件应用补丁 (由 [`Self::create_full_patch`] 生成)，返回新文件大小；
+    /// 对普通补丁调用时因源文件摘要不符而失败
+    pub fn apply_full_patch(new_file: &Path, patch_file: &Path, config: &OptimizationConfig) -> Result<u64, BsdiffError> {
+        Self::audited(None, new_file, patch_file, config, || {
+            if !config.dry_run {
+                Self::prepare_output(new_file, config)?;
+            }
+            Self::patch_bytes_to_file(&[], patch_file, new_file, config)
+        })
+    }
+
+    /// 补丁头声明的新文件大小；旧版、BSDIFF40、VCDIFF 补丁返回 `None`
+    pub fn declared_output_size(patch_file: &Path) -> Result<Option<u64>, BsdiffError> {
+        Ok(Self::declared_target_size(&mut BufReader::new(File::open(patch_file)?))?)
+    }
+
+    /// 应用补丁，把新文件直接写入调用方分配的 `output` (长度须等于新文件大小)，返回写入的字节数
+    pub fn patch_into_slice(
+        old_file: &Path,
+        patch_file: &Path,
+        output: &mut [u8],
+        config: &OptimizationConfig
+    ) -> Result<u64, BsdiffError> {
+        Self::validate_patch_files(old_file, patch_file)?;
+        Self::check_arguments_order(old_file, patch_file)?;
+        let old_mmap = Self::create_single_memory_map(old_file)?;
+        let written = Self::with_safe_mode_retry(config, "patch", |config| {
+            let patch_file_handle = File::open(patch_file)?;
+            let total = patch_file_handle.metadata()?.len();
+            let mut writer = &mut output[..];
+            Self::patch_reader_into(&old_mmap, patch_file_handle, total, patch_file, &mut writer, config)
+        })?;
+        if written != output.len() as u64 {
+            return Err(BsdiffError::CorruptPatch {
+                patch_file: patch_file.display().to_string(),
+                reason: format!("produced {} bytes, expected {}", written, output.len()),
+            });
+        }
+        Ok(written)
+    }
+
+    /// 对同一旧文件并发应用多个补丁 `(patch_file, new_file)`
+    ///
+    /// 旧文件只映射一次，在各线程间只读共享；每个任务解码自己的补丁并写入各自的输出。
+    /// `concurrency` 为 `None` 时使用 rayon 默认线程数。fan-out 不上报进度。
+    pub fn patch_fanout(
+        old_file: &Path,
+        jobs: &[(PathBuf, PathBuf)],
+        concurrency: Option<usize>,
+        config: &OptimizationConfig
+    ) -> Result<(), BsdiffError> {
+        use rayon::prelude::*;
+
+        if !Path::new(old_file).exists() {
+            return Err(BsdiffError::FileNotFound { kind: "Old file", path: old_file.display().to_string() });
+        }
+        for (patch_file, _) in jobs {
+            Self::validate_patch_files(old_file, patch_file)?;
+            Self::check_arguments_order(old_file, patch_file)?;
+        }
+
+        let old_mmap = Self::create_single_memory_map(old_file)?;
+        let config = OptimizationConfig { progress: None, phase_progress: None, ..config.clone() };
+        let apply = |(patch_file, new_file): &(PathBuf, PathBuf)| -> Result<(), String> {
+            Self::patch_to_file(&old_mmap, patch_file, new_file, None, &config)
+                .map(|_| ())
+                .map_err(|e| format!("{} -> {}: {}", patch_file.display(), new_file.display(), e))
+        };
+
+        let mut builder = rayon::ThreadPoolBuilder::new();
+        if let Some(threads) = concurrency {
+            builder = builder.num_threads(threads.max(1));
+        }
+        let pool = builder.build()?;
+        let errors: Vec<String> = pool.install(|| {
+            jobs.par_iter().filter_map(|job| apply(job).err()).collect()
+        });
+
+        if !errors.is_empty() {
+            return Err(format!("{} of {} patch jobs failed: {}", errors.len(), jobs.len(), errors.join("; ")).into());
+        }
+        Ok(())
+    }
+
+    /// 批量生成补丁 `(old_file, new_file, patch_file)`，返回与输入顺序一致的逐项结果 (补丁大小或错误信息)
+    ///
+    /// 所有任务在同一个线程池中并发执行，单项失败不影响其他任务；被取消或超时时整体返回 [`BsdiffError::Cancelled`] / [`BsdiffError::TimedOut`]。
+    /// `concurrency` 为 `None` 时使用 rayon 默认线程数。批量 diff 不上报进度。
+    pub fn diff_batch(
+        pairs: &[(PathBuf, PathBuf, PathBuf)],
+        concurrency: Option<usize>,
+        config: &OptimizationConfig
+    ) -> Result<Vec<Result<u64, String>>, BsdiffError> {
+        use rayon::prelude::*;
+
+        let config = OptimizationConfig { progress: None, phase_progress: None, ..config.clone() };
+        let run = |(old_file, new_file, patch_file): &(PathBuf, PathBuf, PathBuf)| -> Result<u64, BsdiffError> {
+            Self::diff_optimized(old_file, new_file, patch_file, &config)?;
+            Ok(std::fs::metadata(patch_file)?.len())
+        };
+
+        let mut builder = rayon::ThreadPoolBuilder::new();
+        if let Some(threads) = concurrency {
+            builder = builder.num_threads(threads.max(1));
+        }
+        let pool = builder.build()?;
+        let results = pool.install(|| pairs.par_iter().map(|pair| run(pair).map_err(|e| e.to_string())).collect());
+        if let Some(interruption) = config.cancel.as_ref().and_then(CancelToken::interruption) {
+            return Err(interruption);
+        }
+        Ok(results)
+    }
+
+    // === 核心优化方法 ===
+
+    /// 创建内存映射 (双文件版本)
+    #[inline]
+    pub(crate) fn create_memory_maps(old_file: &Path, new_file: &Path) -> Result<(memmap2::Mmap, memmap2::Mmap), BsdiffError> {
+        let old_file_handle = File::open(old_file)?;
+        let new_file_handle = File::open(new_file)?;
+        
+        let old_mmap = unsafe { MmapOptions::new().map(&old_file_handle)? };
+        let new_mmap = unsafe { MmapOptions::new().map(&new_file_handle)? };
+        
+        Ok((old_mmap, new_mmap))
+    }
+
+    /// 创建内存映射 (单文件版本)
+    #[inline]
+    pub(crate) fn create_single_memory_map(file_path: &Path) -> Result<memmap2::Mmap, BsdiffError> {
+        let file_handle = File::open(file_path)?;
+        Ok(unsafe { MmapOptions::new().map(&file_handle)? })
+    }
+
+    /// 创建文件区间 `(offset, len)` 的内存映射；文件大小按 seek 到末尾求得，块设备同样适用
+    fn create_range_memory_map(file_path: &Path, (offset, len): (u64, u64)) -> Result<memmap2::Mmap, BsdiffError> {
+        let mut file_handle = File::open(file_path)?;
+        let file_len = file_handle.seek(SeekFrom::End(0))?;
+        if offset.checked_add(len).is_none_or(|end| end > file_len) {
+            return Err(format!(
+                "Range {}+{} exceeds the size of {} ({} bytes)", offset, len, file_path.display(), file_len
+            ).into());
+        }
+        let len = usize::try_from(len)?;
+        Ok(unsafe { MmapOptions::new().offset(offset).len(len).map(&file_handle)? })
+    }
+
+    /// 创建高性能Zstd编码器
+    #[inline]
+    fn create_zstd_encoder<W: Write>(writer: W, config: &OptimizationConfig) -> io::Result<ZstdEncoder<'static, W>> {
+        Self::create_zstd_encoder_with(writer, config, config.dictionary.as_ref())
+    }
+
+    /// 按配置创建使用指定字典 (或不使用字典) 的Zstd编码器
+    fn create_zstd_encoder_with<W: Write>(
+        writer: W,
+        config: &OptimizationConfig,
+        dictionary: Option<&Dictionary>
+    ) -> io::Result<ZstdEncoder<'static, W>> {
+        // 可复现模式不受安全模式 (含 BSDIFF_ZSTD_SAFE 环境变量) 影响，不同机器上参数一致
+        let safe_mode = config.safe_mode && !config.deterministic;
+        let level = if safe_mode { config.compression_level.min(SAFE_MODE_MAX_LEVEL) } else { config.compression_level };
+        let mut encoder = match dictionary {
+            Some(dictionary) => ZstdEncoder::with_dictionary(writer, level, dictionary.as_bytes())?,
+            None => ZstdEncoder::new(writer, level)?,
+        };
+        if config.deterministic {
+            // 显式固定所有影响输出的帧参数，不依赖 zstd 按级别与输入大小推导的默认值
+            encoder.long_distance_matching(false)?;
+            encoder.window_log(DETERMINISTIC_WINDOW_LOG)?;
+            encoder.include_checksum(false)?;
+            encoder.include_contentsize(false)?;
+            encoder.include_dictid(true)?;
+        } else if safe_mode {
+            // 安全模式：限制压缩级别并固定参数，避开高级匹配路径
+            encoder.long_distance_matching(false)?;
+            encoder.window_log(SAFE_MODE_WINDOW_LOG)?;
+        }
+        Ok(encoder)
+    }
+
+    /// 执行操作；非安全模式下遇到 zstd 内部错误时记录警告并以安全模式重试一次
+    fn with_safe_mode_retry<T, F>(config: &OptimizationConfig, operation: &str, mut run: F) -> Result<T, BsdiffError>
+    where
+        F: FnMut(&OptimizationConfig) -> Result<T, BsdiffError>,
+    {
+        match run(config) {
+            Err(BsdiffError::Io(e)) if !config.safe_mode && Self::is_zstd_internal_error(&e) => {
+                logger::warn(&format!("zstd internal error during {} ({}), retrying in safe mode", operation, e));
+                run(&config.safe())
+            }
+            result => result,
+        }
+    }
+
+    /// 判断错误是否为 zstd 内部错误 (数据损坏等错误不重试)
+    fn is_zstd_internal_error(error: &io::Error) -> bool {
+        if error.kind() != io::ErrorKind::Other || error.raw_os_error().is_some() {
+            return false;
+        }
+        let message = error.to_string();
+        ZSTD_INTERNAL_ERRORS.iter().any(|known| message == *known)
+    }
+
+    /// 流式比较共同前缀，前缀足够长时返回其长度 (常量内存)
+    fn find_append_prefix(old_file: &Path, new_file: &Path) -> Result<Option<u64>, BsdiffError> {
+        let old_len = std::fs::metadata(old_file)?.len();
+        let new_len = std::fs::metadata(new_file)?.len();
+
+        let mut old_reader = BufReader::with_capacity(64 * 1024, File::open(old_file)?);
+        let mut new_reader = BufReader::with_capacity(64 * 1024, File::open(new_file)?);
+        let mut old_buf = vec![0u8; 64 * 1024];
+        let mut new_buf = vec![0u8; 64 * 1024];
+
+        let limit = old_len.min(new_len);
+        let mut prefix = 0u64;
+        while prefix < limit {
+            let want = ((limit - prefix) as usize).min(old_buf.len());
+            old_reader.read_exact(&mut old_buf[..want])?;
+            new_reader.read_exact(&mut new_buf[..want])?;
+            match old_buf[..want].iter().zip(&new_buf[..want]).position(|(a, b)| a != b) {
+                Some(pos) => {
+                    prefix += pos as u64;
+                    break;
+                }
+                None => prefix += want as u64,
+            }
+        }
+
+        Ok(Self::accept_append_prefix(prefix, old_len))
+    }
+
+    /// 共同前缀覆盖旧文件足够比例时才使用追加模式
+    fn accept_append_prefix(prefix: u64, old_len: u64) -> Option<u64> {
+        (prefix * 100 >= old_len * APPEND_MIN_PREFIX_PERCENT).then_some(prefix)
+    }
+
+    /// 写入追加模式补丁：一个控制块 + 全零差分 + 新文件尾部
+    fn write_append_patch<W: Write + ?Sized>(new_file: &Path, prefix_len: u64, writer: &mut W) -> io::Result<()> {
+        let mut new_handle = File::open(new_file)?;
+        let new_len = new_handle.metadata()?.len();
+        let copy_len = new_len - prefix_len;
+
+        ControlEntry { mix_len: prefix_len, copy_len, seek: 0 }.write_to(writer)?;
+
+        // 共同前缀的差分数据全部为零
+        let zeros = [0u8; 64 * 1024];
+        let mut remaining = prefix_len;
+        while remaining > 0 {
+            let n = remaining.min(zeros.len() as u64) as usize;
+            writer.write_all(&zeros[..n])?;
+            remaining -= n as u64;
+        }
+
+        new_handle.seek(SeekFrom::Start(prefix_len))?;
+        let copied = io::copy(&mut BufReader::with_capacity(64 * 1024, new_handle).take(copy_len), writer)?;
+        if copied != copy_len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("New file changed while diffing: {}", new_file.display())));
+        }
+        Ok(())
+    }
+
+    /// 打开补丁文件：解析补丁头并返回定位到负载处的Zstd解码器
+    ///
+    /// 补丁头记录了字典 ID 时 `dictionary` 必须与之匹配。
+    pub(crate) fn open_patch(
+        patch_file: &Path,
+        dictionary: Option<&Dictionary>
+    ) -> Result<(Option<PatchHeader>, PatchDecoder), BsdiffError> {
+        let mut patch_file_handle = File::open(patch_file)?;
+        let header = Self::read_patch_header(&mut patch_file_handle, patch_file)?;
+        let compression = header.as_ref().map_or(Compression::Zstd, |header| header.compression);
+        let dictionary = Self::payload_dictionary(header.as_ref(), dictionary, patch_file)?;
+        let payload = Self::payload_source(header.as_ref(), patch_file_handle, patch_file)?;
+        let decoder = Self::payload_reader(compression, dictionary, payload)?;
+        Ok((header, decoder))
+    }
+
+    /// 补丁头之后的负载读取器；补丁头声明了尾部时边读边校验 CRC-32C
+    ///
+    /// 读取器可以定位到末尾时先检查尾部与补丁大小是否相符，截断的补丁在写出任何输出之前即返回
+    /// [`BsdiffError::CorruptPatch`]；网络流等无法定位的来源在读到末尾时校验。
+    pub(crate) fn payload_source<R: Read + Seek>(
+        header: Option<&PatchHeader>,
+        mut reader: R,
+        patch_file: &Path
+    ) -> Result<PayloadReader<R>, BsdiffError> {
+        let checked = Self::check_payload_trailer(header, &mut reader, patch_file)?;
+        Ok(PayloadReader::new(reader, checked))
+    }
+
+    /// [`Self::payload_source`] 的尾部预检查，返回补丁是否带尾部；读取器回到负载开头
+    fn check_payload_trailer<R: Read + Seek>(
+        header: Option<&PatchHeader>,
+        reader: &mut R,
+        patch_file: &Path
+    ) -> Result<bool, BsdiffError> {
+        let checked = header.is_some_and(|header| header.payload_crc);
+        if checked {
+            let start = reader.stream_position()?;
+            if let Ok(end) = reader.seek(SeekFrom::End(0)) {
+                let mut trailer = [0u8; PAYLOAD_TRAILER_LEN];
+                let declared = match end.checked_sub(PAYLOAD_TRAILER_LEN as u64).filter(|pos| *pos >= start) {
+                    Some(pos) => {
+                        reader.seek(SeekFrom::Start(pos))?;
+                        reader.read_exact(&mut trailer)?;
+                        header::parse_payload_trailer(&trailer).map(|(len, _)| len)
+                    }
+                    None => None,
+                };
+                let actual = end.saturating_sub(start).saturating_sub(PAYLOAD_TRAILER_LEN as u64);
+                if declared != Some(actual) {
+                    return Err(BsdiffError::CorruptPatch {
+                        patch_file: patch_file.display().to_string(),
+                        reason: match declared {
+                            Some(len) => format!("payload is {} bytes, trailer declares {}", actual, len),
+                            None => "payload trailer missing, the patch is truncated".to_string(),
+                        },
+                    });
+                }
+            }
+            reader.seek(SeekFrom::Start(start))?;
+        }
+        Ok(checked)
+    }
+
+    /// 读取补丁头；没有补丁头时要求数据以 zstd 帧开头 (旧版补丁)，否则报告不是补丁文件
+    pub(crate) fn read_patch_header<R: Read + Seek>(
+        reader: &mut R,
+        patch_file: &Path
+    ) -> Result<Option<PatchHeader>, BsdiffError> {
+        let header = PatchHeader::read_from(reader).map_err(|e| Self::corrupt_header(patch_file, e))?;
+        if header.is_none() {
+            let mut head = Vec::with_capacity(encryption::ENVELOPE_MAGIC.len());
+            reader.by_ref().take(encryption::ENVELOPE_MAGIC.len() as u64).read_to_end(&mut head)?;
+            let is_legacy = header::is_zstd_frame(&head);
+            reader.rewind()?;
+            if encryption::is_encrypted(&head) {
+                return Err(BsdiffError::Encrypted { patch_file: patch_file.display().to_string() });
+            }
+            if !is_legacy {
+                return Err(BsdiffError::NotAPatch { patch_file: patch_file.display().to_string() });
+            }
+        }
+        Ok(header)
+    }
+
+    /// 解码补丁并流式写入输出文件，返回写入的字节数
+    ///
+    /// 先写入临时路径，成功后再原子替换目标文件；失败时删除临时文件，原有输出保持不变。
+    /// `pad_to` 为 Some 时输出以零填充到该长度。
+    fn patch_to_file(
+        old_data: &[u8],
+        patch_file: &Path,
+        new_file: &Path,
+        pad_to: Option<u64>,
+        config: &OptimizationConfig
+    ) -> Result<u64, BsdiffError> {
+        let open_patch = || {
+            let patch_file_handle = File::open(patch_file)?;
+            let total = patch_file_handle.metadata()?.len();
+            Ok((patch_file_handle, total))
+        };
+        Self::patch_reader_to_file(old_data, open_patch, patch_file, new_file, pad_to, config)
+    }
+
+    /// 将 `open_patch` 打开的补丁读取器应用到 `new_file`，失败时删除未完成的输出
+    ///
+    /// safe mode 重试时会重新调用 `open_patch`，它返回读取器与补丁总长度。
+    pub(crate) fn patch_reader_to_file<R, F>(
+        old_data: &[u8],
+        mut open_patch: F,
+        patch_file: &Path,
+        new_file: &Path,
+        pad_to: Option<u64>,
+        config: &OptimizationConfig
+    ) -> Result<u64, BsdiffError>
+    where
+        R: Read + Seek,
+        F: FnMut() -> io::Result<(R, u64)>,
+    {
+        // 补丁头声明的目标大小最准确；旧版补丁按旧文件加补丁长度估算
+        let (mut reader, total) = open_patch()?;
+        let expected_size = Self::declared_target_size(&mut reader)?.unwrap_or(old_data.len() as u64 + total);
+        let output_path = Self::get_streaming_output_path(new_file, config, expected_size.max(pad_to.unwrap_or(0)))?;
+        // 首次尝试复用已打开的补丁 (网络来源无需重复请求)，安全模式重试时重新打开
+        let mut first = Some((reader, total));
+        let result = Self::with_safe_mode_retry(config, "patch", |config| {
+            let (mut reader, total) = match first.take() {
+                Some(opened) => opened,
+                None => open_patch()?,
+            };
+            // 预分配的大小来自补丁头，签名补丁在验签前不信任它
+            if config.mmap_output && !config.sparse && config.write_limit.is_none() && config.signature.is_none() {
+                if let Some(target) = Self::declared_target_size(&mut reader)? {
+                    // 补丁头尚未校验：扩展文件与映射前先按 max_output_size 检查；
+                    // 映射的脏页计入内存，超过 max_memory 时改为流式写出
+                    Self::check_output_size(target, patch_file, config)?;
+                    let len = pad_to.map_or(target, |pad| pad.max(target));
+                    if len > 0 && config.max_memory.is_none_or(|limit| len <= limit) {
+                        return Self::patch_into_mapped_file(old_data, reader, total, patch_file, &output_path, len, config)
+                            .map(|written| pad_to.map_or(written, |pad| pad.max(written)));
+                    }
+                }
+            }
+            let file = File::create(&output_path)?;
+            if config.sparse {
+                let mut writer = SparseWriter::new(file, config.write_limit.clone());
+                let written = Self::patch_padded_into(old_data, reader, total, patch_file, &mut writer, pad_to, config)?;
+                let skipped = writer.finish()?;
+                logger::info(&format!("Sparse output: skipped {} of {} bytes (all zero)", skipped, written));
+                return Ok(written);
+            }
+            let mut writer = BufWriter::with_capacity(64 * 1024, ThrottledWriter::new(file, config.write_limit.clone()));
+            let written = Self::patch_padded_into(old_data, reader, total, patch_file, &mut writer, pad_to, config)?;
+            writer.flush()?;
+            Ok(written)
+        });
+
+        match result {
+            Ok(written) => {
+                config.audit("bytes_written", &[("path", output_path.as_path().into()), ("bytes", written.into())]);
+                Self::finalize_reported(&output_path, new_file, config)?;
+                Ok(written)
+            }
+            Err(e) => {
+                let _ = std::fs::remove_file(&output_path);
+                Err(e)
+            }
+        }
+    }
+
+    /// 应用补丁写入 `writer`，给出 `pad_to` 时在输出之后补零到该长度；返回写入的总字节数
+    fn patch_padded_into<R: Read + Seek, W: Write>(
+        old_data: &[u8],
+        reader: R,
+        total: u64,
+        patch_file: &Path,
+        writer: &mut W,
+        pad_to: Option<u64>,
+        config: &OptimizationConfig
+    ) -> Result<u64, BsdiffError> {
+        let mut written = Self::patch_reader_into(old_data, reader, total, patch_file, writer, config)?;
+        if let Some(len) = pad_to {
+            written += io::copy(&mut io::repeat(0).take(len.saturating_sub(written)), writer)?;
+        }
+        Ok(written)
+    }
+
+    /// 读取补丁头声明的目标大小 (无补丁头或补丁头无法解析时返回 `None`)，读取后回到补丁开头
+    fn declared_target_size<R: Read + Seek>(reader: &mut R) -> io::Result<Option<u64>> {
+        let target = PatchHeader::read_from(reader).ok().flatten().and_then(|header| header.target);
+        reader.rewind()?;
+        Ok(target.map(|target| target.size))
+    }
+
+    /// 预分配 `len` 字节的输出文件并通过可写内存映射写入补丁输出；超出部分保持为零
+    fn patch_into_mapped_file<R: Read + Seek>(
+        old_data: &[u8],
+        reader: R,
+        total: u64,
+        patch_file: &Path,
+        output_path: &Path,
+        len: u64,
+        config: &OptimizationConfig
+    ) -> Result<u64, BsdiffError> {
+        let file = std::fs::OpenOptions::new().read(true).write(true).create(true).truncate(true).open(output_path)?;
+        file.set_len(len)?;
+        // 映射期间文件只由本函数写入
+        let map = unsafe { MmapOptions::new().map_mut(&file)? };
+        let mut writer = MmapWriter { map, pos: 0 };
+        Self::patch_reader_into(old_data, reader, total, patch_file, &mut writer, config)
+    }
+
+    /// 从任意补丁读取器解码并应用补丁，返回内存中的新文件数据
+    pub(crate) fn patch_from_reader<R: Read + Seek>(
+        old_data: &[u8],
+        mut reader: R,
+        total: u64,
+        patch_file: &Path,
+        config: &OptimizationConfig
+    ) -> Result<Vec<u8>, BsdiffError> {
+        // 结果整体保存在内存中：声明的目标大小计入内存上限，未声明时输出同样不能超过该上限
+        let limited;
+        let config = match config.max_memory {
+            Some(limit) => {
+                Self::check_memory(Self::declared_target_size(&mut reader)?.unwrap_or(0), patch_file, config)?;
+                limited = OptimizationConfig {
+                    max_output_size: Some(config.max_output_size.map_or(limit, |max| max.min(limit))),
+                    ..config.clone()
+                };
+                &limited
+            }
+            None => config,
+        };
+        let mut new_data = Vec::new();
+        Self::patch_reader_into(old_data, reader, total, patch_file, &mut new_data, config)?;
+        Ok(new_data)
+    }
+
+    /// 从任意补丁读取器解码并应用补丁，逐块写入 `writer`，返回写入的字节数
+    ///
+    /// 补丁内容不可信：所有长度都经过校验，输出大小受补丁头声明的目标大小
+    /// (及 `max_output_size`) 限制，损坏的补丁返回 [`BsdiffError::CorruptPatch`]。
+    pub(crate) fn patch_reader_into<R: Read + Seek, W: Write>(
+        old_data: &[u8],
+        mut reader: R,
+        total: u64,
+        patch_file: &Path,
+        writer: &mut W,
+        config: &OptimizationConfig
+    ) -> Result<u64, BsdiffError> {
+        // decode 阶段：签名校验、解密与补丁头解析 (字节数为补丁大小)
+        config.report_phase(ProgressPhase::Decode, 0, total);
+        if let Some(signature) = &config.signature {
+            // 签名先于解析任何补丁内容校验；补丁整体读入内存，之后只使用已校验的字节，
+            // 校验与应用之间补丁文件被替换也不受影响
+            // 长度未知的来源 (HTTP 流) 声明的 total 为 0，读取时同样受内存上限约束
+            Self::check_memory(total, patch_file, config)?;
+            let mut patch = Vec::new();
+            reader.by_ref().take(config.max_memory.map_or(u64::MAX, |limit| limit.saturating_add(1))).read_to_end(&mut patch)?;
+            Self::check_memory(patch.len() as u64, patch_file, config)?;
+            let ok = signature.verify(&patch);
+            config.audit("signature_checked", &[("patch", patch_file.into()), ("ok", ok.into())]);
+            if !ok {
+                return Err(BsdiffError::InvalidSignature { patch_file: patch_file.display().to_string() });
+            }
+            let total = patch.len() as u64;
+            let config = OptimizationConfig { signature: None, ..config.clone() };
+            return Self::patch_reader_into(old_data, io::Cursor::new(patch), total, patch_file, writer, &config);
+        }
+        Self::check_base(old_data, &mut reader, patch_file)?;
+
+        // 加密信封：整体解密后按明文补丁应用 (签名覆盖的是信封本身，已在上面校验)
+        let mut magic = [0u8; encryption::ENVELOPE_MAGIC.len()];
+        let is_encrypted = reader.read_exact(&mut magic).is_ok() && encryption::is_encrypted(&magic);
+        reader.rewind()?;
+        if is_encrypted {
+            // 密文与明文同时在内存中
+            Self::check_memory(total.saturating_mul(2), patch_file, config)?;
+            let plain = Self::decrypt_patch(reader, patch_file, config)?;
+            let total = plain.len() as u64;
+            config.audit("patch_decrypted", &[("patch", patch_file.into()), ("bytes", total.into())]);
+            let config = OptimizationConfig { signature: None, encryption_key: None, ..config.clone() };
+            return Self::patch_reader_into(old_data, io::Cursor::new(plain), total, patch_file, writer, &config);
+        }
+
+        let mut magic = [0u8; bsdiff40::BSDIFF40_MAGIC.len()];
+        let is_classic = reader.read_exact(&mut magic).is_ok() && bsdiff40::is_bsdiff40(&magic);
+        reader.rewind()?;
+        if is_classic {
+            config.report_phase(ProgressPhase::Decode, total, total);
+            config.audit("header_validated", &[("patch", patch_file.into()), ("format", "bsdiff40".into())]);
+            Self::check_source(old_data, None, patch_file, config)?;
+            Self::check_memory(total, patch_file, config)?;
+            return Self::patch_bsdiff40_into(old_data, reader, patch_file, writer, config);
+        }
+        let mut magic = [0u8; vcdiff::VCDIFF_MAGIC.len()];
+        let is_vcdiff = reader.read_exact(&mut magic).is_ok() && vcdiff::is_vcdiff(&magic);
+        reader.rewind()?;
+        if is_vcdiff {
+            config.report_phase(ProgressPhase::Decode, total, total);
+            config.audit("header_validated", &[("patch", patch_file.into()), ("format", "vcdiff".into())]);
+            Self::check_source(old_data, None, patch_file, config)?;
+            Self::check_memory(total, patch_file, config)?;
+            return Self::patch_vcdiff_into(old_data, reader, patch_file, writer, config);
+        }
+
+        let header = Self::read_patch_header(&mut reader, patch_file)?;
+        Self::check_algorithm_version(header.as_ref(), patch_file);
+        config.report_phase(ProgressPhase::Decode, total, total);
+        config.audit("header_validated", &[
+            ("patch", patch_file.into()),
+            ("format", if header.is_some() { "zstd" } else { "legacy" }.into()),
+            ("compression", header.as_ref().map(|h| h.compression.name()).into()),
+            ("targetSize", header.as_ref().and_then(|h| h.target).map(|target| target.size).into()),
+            ("controlBlocks", header.as_ref().and_then(|h| h.control_blocks).into()),
+            ("fullFile", header.as_ref().is_some_and(|h| h.full_file).into()),
+        ]);
+        // read 阶段：校验旧文件摘要
+        let old_len = old_data.len() as u64;
+        config.report_phase(ProgressPhase::Read, 0, old_len);
+        Self::check_source(old_data, header.as_ref().and_then(|h| h.source), patch_file, config)?;
+        config.report_phase(ProgressPhase::Read, old_len, old_len);
+
+        let target_size = header.as_ref().and_then(|h| h.target).map(|target| target.size);
+        let control_blocks = header.as_ref().and_then(|h| h.control_blocks);
+        let compression = header.as_ref().map_or(Compression::Zstd, |h| h.compression);
+        let dictionary = Self::payload_dictionary(header.as_ref(), config.dictionary.as_ref(), patch_file)?;
+        let mut block_reporter = config.block_progress.as_ref()
+            .zip(control_blocks)
+            .map(|(callback, total)| ProgressReporter::new(callback.clone(), total));
+        if let Some(target) = target_size {
+            Self::check_output_size(target, patch_file, config)?;
+        }
+        // 声明了目标大小时超出即为损坏；否则受 max_output_size 限制
+        let output_limit = if target_size.is_some() { None } else { config.max_output_size };
+        // 压缩包变换的负载生成的是展开后的数据
+        let archive = header.as_ref().and_then(|h| h.archive_transform);
+        let expected_output = archive.map(|archive| archive.expanded_size).or(target_size);
+        let max_output = expected_output.or(output_limit).unwrap_or(u64::MAX);
+
+        // 可执行文件变换：对旧文件做同样的正向变换，输出先收集到内存，逆变换后再写出
+        // 完整文件补丁的负载不引用旧文件 (源文件摘要已在上面校验)
+        let old_data = if header.as_ref().is_some_and(|h| h.full_file) { &[][..] } else { old_data };
+        let transform = header.as_ref().and_then(|h| h.exe_transform.as_ref());
+        if transform.is_some() {
+            Self::check_memory((old_data.len() as u64).saturating_add(target_size.unwrap_or(0)), patch_file, config)?;
+        }
+        let transformed_old = match transform {
+            Some(transform) => Some(transform.encode_old(old_data).map_err(|e| BsdiffError::CorruptPatch {
+                patch_file: patch_file.display().to_string(),
+                reason: e.to_string(),
+            })?),
+            None => None,
+        };
+        let old_data = transformed_old.as_deref().unwrap_or(old_data);
+        // 压缩包变换：旧文件同样解压展开，展开后的输出先收集到内存，重新压缩条目后再写出
+        let expanded_old = match archive {
+            Some(_) if !deflate::AVAILABLE => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "Archive transform patches need zlib, which is not available in this build",
+                )
+                .into());
+            }
+            Some(archive) => {
+                let required = (old_data.len() as u64).saturating_add(archive.expanded_size).saturating_add(target_size.unwrap_or(0));
+                Self::check_memory(required, patch_file, config)?;
+                Some(ArchiveTransform::expand_old(old_data))
+            }
+            None => None,
+        };
+        let old_data = expanded_old.as_deref().unwrap_or(old_data);
+        // 边写边计算输出摘要，结束后与补丁头记录的目标摘要比较；校验失败时调用方不会提交输出
+        let expected_target = header.as_ref().and_then(|h| h.target);
+        let mut writer = DigestingWriter { inner: writer, hasher: expected_target.map(|target| Hasher::new(target.algorithm)) };
+        let mut transformed_new = Vec::new();
+        let output: &mut dyn Write = if transform.is_some() || archive.is_some() { &mut transformed_new } else { &mut writer };
+
+        // 截断的补丁在写出输出之前报错；负载在应用过程中校验 CRC-32C
+        let checked = Self::check_payload_trailer(header.as_ref(), &mut reader, patch_file)?;
+        let mut apply = |payload: &mut dyn Read| {
+            Self::limited_payload_reader(compression, dictionary, payload, config.max_memory).and_then(|mut decoder| {
+                Self::apply_raw_patch(old_data, &mut decoder, output, max_output, block_reporter.as_mut(), config.cancel.as_ref())
+            })
+        };
+        let result = match config.apply_reporter(total) {
+            Some(reporter) => {
+                // 按已读取的补丁文件字节数上报进度 (含尾部)
+                let already_read = reader.stream_position()?;
+                let mut payload = PayloadReader::new(ProgressReader::new(reader, reporter, already_read), checked);
+                apply(&mut payload).and_then(|applied| payload.finish().map(|_| applied))
+            }
+            None => {
+                let mut payload = PayloadReader::new(reader, checked);
+                apply(&mut payload).and_then(|applied| payload.finish().map(|_| applied))
+            }
+        };
+        let (written, applied) = result.map_err(|e| Self::classify_apply_error(patch_file, e, output_limit, config.max_memory))?;
+        if checked {
+            config.audit("payload_checked", &[("patch", patch_file.into()), ("crc32c", true.into())]);
+        }
+
+        if let Some(total) = control_blocks.filter(|total| *total != applied) {
+            return Err(BsdiffError::CorruptPatch {
+                patch_file: patch_file.display().to_string(),
+                reason: format!("applied {} control blocks, header declares {}", applied, total),
+            });
+        }
+
+        if let Some(expected) = expected_output {
+            if written != expected {
+                return Err(BsdiffError::CorruptPatch {
+                    patch_file: patch_file.display().to_string(),
+                    reason: format!("produced {} bytes, header declares {}", written, expected),
+                });
+            }
+        }
+        if let Some(transform) = transform {
+            transform.decode_new(&mut transformed_new).map_err(|e| BsdiffError::CorruptPatch {
+                patch_file: patch_file.display().to_string(),
+                reason: e.to_string(),
+            })?;
+            writer.write_all(&transformed_new)?;
+        }
+        let written = match archive {
+            Some(archive) => {
+                let corrupt = |reason: String| BsdiffError::CorruptPatch { patch_file: patch_file.display().to_string(), reason };
+                let rewrapped = archive.rewrap(&transformed_new).map_err(|e| corrupt(e.to_string()))?;
+                let written = rewrapped.len() as u64;
+                if let Some(target) = target_size.filter(|target| *target != written) {
+                    return Err(corrupt(format!("rewrapped archive is {} bytes, header declares {}", written, target)));
+                }
+                writer.write_all(&rewrapped)?;
+                written
+            }
+            None => written,
+        };
+        if let (Some(expected), Some(hasher)) = (expected_target, writer.hasher) {
+            let actual = FileDigest { size: written, algorithm: expected.algorithm, hash: hasher.finalize() };
+            Self::check_target_digest(expected, actual, patch_file, config)?;
+        }
+        Ok(written)
+    }
+
+    /// 读取整个加密补丁并用配置中的密钥解密；未提供密钥时返回 [`BsdiffError::Encrypted`]
+    fn decrypt_patch<R: Read>(
+        mut reader: R,
+        patch_file: &Path,
+        config: &OptimizationConfig
+    ) -> Result<Vec<u8>, BsdiffError> {
+        let key = config.encryption_key.as_ref().ok_or_else(|| BsdiffError::Encrypted { patch_file: patch_file.display().to_string() })?;
+        let mut envelope = Vec::new();
+        reader.read_to_end(&mut envelope)?;
+        encryption::open(key, envelope, patch_file)
+    }
+
+    /// 快速预检：补丁头记录的旧文件大小或开头片段摘要与旧文件不符时立即失败，
+    /// 不必校验签名、解密、哈希整个旧文件或解码负载。没有补丁头的格式 (加密信封、BSDIFF40 等) 跳过
+    fn check_base<R: Read + Seek>(old_data: &[u8], reader: &mut R, patch_file: &Path) -> Result<(), BsdiffError> {
+        // 头部损坏留给随后的完整解析报告
+        let header = PatchHeader::read_from(reader).ok().flatten();
+        reader.rewind()?;
+        let Some((source, prefix)) = header.and_then(|header| Some((header.source?, header.source_prefix))) else {
+            return Ok(());
+        };
+        let actual_size = old_data.len() as u64;
+        let prefix_differs = prefix.is_some_and(|prefix| prefix != header::source_prefix_digest(old_data));
+        if source.size != actual_size || prefix_differs {
+            return Err(BsdiffError::BaseMismatch { patch_file: patch_file.display().to_string(), expected_size: source.size, actual_size });
+        }
+        Ok(())
+    }
+
+    /// 校验旧文件与补丁头记录的源文件摘要，避免把补丁应用到错误的基准文件上
+    fn check_source(
+        old_data: &[u8],
+        source: Option<FileDigest>,
+        patch_file: &Path,
+        config: &OptimizationConfig
+    ) -> Result<(), BsdiffError> {
+        match source {
+            Some(expected) => {
+                let actual = FileDigest::of_bytes_with(expected.algorithm, old_data);
+                config.audit("source_checked", &[
+                    ("algorithm", expected.algorithm.name().into()),
+                    ("expected", (&expected.hex()).into()),
+                    ("actual", (&actual.hex()).into()),
+                    ("size", actual.size.into()),
+                    ("ok", (actual == expected).into()),
+                ]);
+                if actual != expected {
+                    return Err(BsdiffError::SourceMismatch { patch_file: patch_file.display().to_string(), expected, actual });
+                }
+                Ok(())
+            }
+            None => {
+                config.audit("source_unverified", &[("patch", patch_file.into()), ("strict", config.strict.into())]);
+                if config.strict {
+                    return Err(BsdiffError::MissingSourceChecksum { patch_file: patch_file.display().to_string() });
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// 应用经典 BSDIFF40 补丁 (bzip2 三段需整体解压，不上报进度)
+    fn patch_bsdiff40_into<R: Read, W: Write>(
+        old_data: &[u8],
+        mut reader: R,
+        patch_file: &Path,
+        writer: &mut W,
+        config: &OptimizationConfig
+    ) -> Result<u64, BsdiffError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let raw = bsdiff40::from_bsdiff40(&data).map_err(|e| BsdiffError::CorruptPatch {
+            patch_file: patch_file.display().to_string(),
+            reason: e.to_string(),
+        })?;
+        let max_output = config.max_output_size.unwrap_or(u64::MAX);
+        let (written, _) = Self::apply_raw_patch(old_data, &mut &raw[..], writer, max_output, None, config.cancel.as_ref())
+            .map_err(|e| Self::classify_apply_error(patch_file, e, config.max_output_size, None))?;
+        Ok(written)
+    }
+
+    /// 应用 VCDIFF 补丁 (整体读入后逐窗口解码，不上报进度)
+    fn patch_vcdiff_into<R: Read, W: Write>(
+        old_data: &[u8],
+        mut reader: R,
+        patch_file: &Path,
+        writer: &mut W,
+        config: &OptimizationConfig
+    ) -> Result<u64, BsdiffError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let max_output = config.max_output_size.unwrap_or(u64::MAX);
+        vcdiff::apply(old_data, &data, writer, max_output, config.cancel.as_ref())
+            .map_err(|e| Self::classify_apply_error(patch_file, e, config.max_output_size, None))
+    }
+
+    /// 应用原始 bsdiff 流 (带边界检查)，逐块写入 `writer`，输出不超过 `max_output` 字节
+    ///
+    /// 差分/新增数据按固定大小分块处理，峰值内存与新文件大小无关，每块之间检查取消标记。
+    /// 返回写入的字节数与已应用的控制块数量；`block_reporter` 每应用一个控制块前进一次。
+    fn apply_raw_patch<R: Read, W: Write + ?Sized>(
+        old: &[u8],
+        patch: &mut R,
+        writer: &mut W,
+        max_output: u64,
+        mut block_reporter: Option<&mut ProgressReporter>,
+        cancel: Option<&CancelToken>
+    ) -> io::Result<(u64, u64)> {
+        let corrupt = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, reason.to_string());
+        let mut chunk = vec![0u8; PATCH_CHUNK_SIZE];
+        let mut written: u64 = 0;
+        let mut old_pos: u64 = 0;
+        let mut applied: u64 = 0;
+
+        while let Some(entry) = ControlEntry::read_from(patch)? {
+            let len = entry.mix_len.checked_add(entry.copy_len).ok_or_else(|| corrupt("control lengths overflow"))?;
+            if written.checked_add(len).is_none_or(|end| end > max_output) {
+                return Err(io::Error::new(io::ErrorKind::FileTooLarge, format!("output exceeds {} bytes", max_output)));
+            }
+            let old_end = old_pos
+                .checked_add(entry.mix_len)
+                .filter(|end| *end <= old.len() as u64)
+                .ok_or_else(|| corrupt("diff block reads past the end of the old file"))?;
+
+            // 差分数据：与旧文件对应区间逐字节相加
+            for old_chunk in old[old_pos as usize..old_end as usize].chunks(PATCH_CHUNK_SIZE) {
+                cancel::check(cancel)?;
+                let buf = &mut chunk[..old_chunk.len()];
+                patch.read_exact(buf)?;
+                for (n, o) in buf.iter_mut().zip(old_chunk) {
+                    *n = n.wrapping_add(*o);
+                }
+                writer.write_all(buf)?;
+            }
+
+            // 新增数据：原样复制
+            let mut remaining = entry.copy_len;
+            while remaining > 0 {
+                cancel::check(cancel)?;
+                let buf = &mut chunk[..remaining.min(PATCH_CHUNK_SIZE as u64) as usize];
+                patch.read_exact(buf)?;
+                writer.write_all(buf)?;
+                remaining -= buf.len() as u64;
+            }
+            written += len;
+
+            old_pos = i64::try_from(old_end)
+                .ok()
+                .and_then(|pos| pos.checked_add(entry.seek))
+                .and_then(|pos| u64::try_from(pos).ok())
+                .ok_or_else(|| corrupt("seek moves outside the old file"))?;
+
+            applied += 1;
+            if let Some(reporter) = block_reporter.as_deref_mut() {
+                reporter.advance(1);
+            }
+        }
+        Ok((written, applied))
+    }
+
+    /// 补丁头解析失败：除底层读取错误外都视为补丁损坏
+    fn corrupt_header(patch_file: &Path, error: BsdiffError) -> BsdiffError {
+        match error {
+            BsdiffError::Io(e) if e.raw_os_error().is_some() => BsdiffError::Io(e),
+            BsdiffError::Io(_) | BsdiffError::Other(_) => {
+                BsdiffError::CorruptPatch { patch_file: patch_file.display().to_string(), reason: error.to_string() }
+            }
+            error => error,
+        }
+    }
+
+    /// 区分补丁损坏与其他错误 (系统 I/O 错误、内存不足、可重试的 zstd 内部错误)
+    fn classify_patch_error(patch_file: &Path, error: io::Error) -> BsdiffError {
+        if let Some(interruption) = cancel::interruption_of(&error) {
+            return interruption;
+        }
+        let corrupt = match error.kind() {
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof | io::ErrorKind::FileTooLarge => true,
+            io::ErrorKind::Other => error.raw_os_error().is_none() && !Self::is_zstd_internal_error(&error),
+            _ => false,
+        };
+        if corrupt {
+            BsdiffError::CorruptPatch { patch_file: patch_file.display().to_string(), reason: error.to_string() }
+        } else {
+            error.into()
+        }
+    }
+
+    /// 应用补丁时的错误归类：输出超过 `output_limit` (max_output_size，而不是补丁头声明的目标大小)
+    /// 或 zstd 帧窗口超过 `max_memory` 时返回 [`BsdiffError::LimitExceeded`]，其余同 [`Self::classify_patch_error`]
+    fn classify_apply_error(
+        patch_file: &Path,
+        error: io::Error,
+        output_limit: Option<u64>,
+        max_memory: Option<u64>
+    ) -> BsdiffError {
+        let exceeded = |resource, limit| BsdiffError::LimitExceeded { patch_file: patch_file.display().to_string(), resource, limit, required: None };
+        match (output_limit, max_memory) {
+            (Some(limit), _) if error.kind() == io::ErrorKind::FileTooLarge => exceeded("output", limit),
+            (_, Some(limit)) if error.raw_os_error().is_none() && error.to_string() == ZSTD_WINDOW_TOO_LARGE => exceeded("memory", limit),
+            _ => Self::classify_patch_error(patch_file, error),
+        }
+    }
+
+    /// 流式输出的临时路径：未启用快速临时目录 (或其空间不足) 时写入同目录的 `.partial` 文件，
+    /// 避免失败时留下截断的目标文件
+    fn get_streaming_output_path(output_file: &Path, config: &OptimizationConfig, expected_size: u64) -> Result<PathBuf, BsdiffError> {
+        Ok(Self::fast_temp_path(output_file, config, expected_size)?
+            .unwrap_or_else(|| paths::with_suffix(output_file, ".partial")))
+    }
+
+    /// 获取最优输出路径；`expected_size` 为预计写入的字节数，用于检查临时目录剩余空间
+    #[inline]
+    pub(crate) fn get_optimal_output_path(original_path: &Path, config: &OptimizationConfig, expected_size: u64) -> Result<PathBuf, BsdiffError> {
+        Ok(Self::fast_temp_path(original_path, config, expected_size)?
+            .unwrap_or_else(|| PathBuf::from(original_path)))
+    }
+
+    /// 快速临时目录中的输出路径；未启用或剩余空间不足以容纳 `expected_size` 时返回 `None`
+    fn fast_temp_path(original_path: &Path, config: &OptimizationConfig, expected_size: u64) -> Result<Option<PathBuf>, BsdiffError> {
+        let file_name = original_path
+            .file_name()
+            .ok_or("Invalid file path")?;
+        Ok(Self::fast_temp_dir(original_path, config, expected_size)?.map(|temp_dir| temp_dir.join(temp_file_name(file_name))))
+    }
+
+    /// 为 `original_path` 的输出选择快速临时目录；未启用或剩余空间不足以容纳 `expected_size` 时返回 `None`
+    pub(crate) fn fast_temp_dir(original_path: &Path, config: &OptimizationConfig, expected_size: u64) -> Result<Option<PathBuf>, BsdiffError> {
+        // 限速时输出直接写在目标旁边：写入内存盘再复制到磁盘会绕过限速；
+        // 稀疏输出同样如此：跨设备复制会把空洞写成零块
+        if !config.use_fast_temp_dir || config.write_limit.is_some() || config.sparse {
+            return Ok(None);
+        }
+        let temp_dir = match &config.temp_dir {
+            Some(dir) if !dir.is_dir() => return Err(BsdiffError::FileNotFound { kind: "Temp directory", path: dir.display().to_string() }),
+            Some(dir) => dir.clone(),
+            None => Self::get_fast_temp_dir(),
+        };
+
+        // 小容量 tmpfs 写满会在 diff 中途报 ENOSPC，空间不足时提前改为写在输出旁边
+        let needed = expected_size.saturating_add(TEMP_SPACE_HEADROOM);
+        if let Some(available) = available_space(&temp_dir).filter(|available| *available < needed) {
+            logger::info(&format!(
+                "Temp directory {} has {} bytes free, about {} needed; writing next to {} instead",
+                temp_dir.display(), available, needed, original_path.display()
+            ));
+            return Ok(None);
+        }
+        Ok(Some(temp_dir))
+    }
+
+    /// 按 [`OverwritePolicy`] 处理已存在的输出文件：`Error` 时拒绝，`Backup` 时重命名为 `.bak`
+    pub(crate) fn prepare_output(output_file: &Path, config: &OptimizationConfig) -> Result<(), BsdiffError> {
+        if config.overwrite == OverwritePolicy::Replace || std::fs::symlink_metadata(output_file).is_err() {
+            return Ok(());
+        }
+        if config.overwrite == OverwritePolicy::Error {
+            return Err(BsdiffError::OutputExists { path: output_file.display().to_string() });
+        }
+        let backup = paths::with_suffix(output_file, ".bak");
+        config.retry.run("Moving existing output", || std::fs::rename(output_file, &backup))?;
+        logger::info(&format!("Moved existing {} to {}", output_file.display(), backup.display()));
+        Ok(())
+    }
+
+    /// 完成输出并上报 write 阶段 (字节数为输出大小)；`durable` 时随后 fsync 输出
+    fn finalize_reported(temp_path: &Path, final_path: &Path, config: &OptimizationConfig) -> Result<(), BsdiffError> {
+        let len = std::fs::metadata(temp_path).map_or(0, |metadata| metadata.len());
+        config.report_phase(ProgressPhase::Write, 0, len);
+        Self::finalize_output(temp_path, final_path, config)?;
+        if config.durable {
+            Self::sync_output(final_path)?;
+            config.audit("output_synced", &[("path", final_path.into())]);
+        }
+        config.report_phase(ProgressPhase::Write, len, len);
+        Ok(())
+    }
+
+    /// 原子性完成输出
+    ///
+    /// 临时文件与输出不在同一文件系统 (如 /dev/shm 与磁盘) 时 rename 返回 EXDEV，
+    /// 改为经 [`Self::copy_across_devices`] 在输出目录内完成替换。
+    /// 文件被暂时锁住 (如杀毒软件扫描) 时按 `config.retry` 重试
+    pub(crate) fn finalize_output(temp_path: &Path, final_path: &Path, config: &OptimizationConfig) -> Result<(), BsdiffError> {
+        if temp_path == Path::new(final_path) {
+            return Ok(());
+        }
+        let retry = &config.retry;
+        let cross_device = match retry.run("Renaming output", || std::fs::rename(temp_path, final_path)) {
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                let result = Self::copy_across_devices(temp_path, Path::new(final_path), retry);
+                let _ = std::fs::remove_file(temp_path);
+                result?;
+                true
+            }
+            result => {
+                result?;
+                false
+            }
+        };
+        config.audit("rename_committed", &[("from", temp_path.into()), ("to", final_path.into()), ("crossDevice", cross_device.into())]);
+        Ok(())
+    }
+
+    /// 跨文件系统完成输出：复制到输出目录下的临时文件并 fsync，再在目录内 rename，
+    /// 输出文件不会出现写了一半的状态；最后 fsync 目录使改名落盘
+    fn copy_across_devices(temp_path: &Path, final_path: &Path, retry: &RetryPolicy) -> io::Result<()> {
+        let staging = sibling_temp_path(final_path, "bsdiff-tmp")?;
+        let result = retry
+            .run("Copying output", || std::fs::copy(temp_path, &staging))
+            .and_then(|_| std::fs::OpenOptions::new().write(true).open(&staging)?.sync_all())
+            .and_then(|_| retry.run("Renaming output", || std::fs::rename(&staging, final_path)));
+        if let Err(e) = result {
+            let _ = std::fs::remove_file(&staging);
+            return Err(e);
+        }
+        Self::sync_parent_dir(final_path);
+        Ok(())
+    }
+
+    /// fsync 已完成的输出文件及其所在目录，使文件内容与改名都已落盘
+    pub(crate) fn sync_output(path: &Path) -> io::Result<()> {
+        File::open(path)?.sync_all()?;
+        Self::sync_parent_dir(Path::new(path));
+        Ok(())
+    }
+
+    /// 获取最快的临时目录
+    #[inline]
+    fn get_fast_temp_dir() -> PathBuf {
+        // Linux: 内存盘优先
+        if cfg!(target_os = "linux") && Path::new("/dev/shm").exists() {
+            return PathBuf::from("/dev/shm");
+        }
+        
+        // macOS: 检查RAM盘
+        if cfg!(target_os = "macos") {
+            if let Ok(entries) = std::fs::read_dir("/Volumes") {
+                for entry in entries.flatten() {
+                    if entry.file_name().to_string_lossy().contains("RAM") {
+                        return entry.path();
+                    }
+                }
+            }
+        }
+        
+        std::env::temp_dir()
+    }
+
+    /// 补丁由不同算法版本生成时通过日志回调发出警告
+    #[inline]
+    fn check_algorithm_version(header: Option<&PatchHeader>, patch_file: &Path) {
+        match header {
+            Some(header) if !header.is_algorithm_compatible() => logger::warn(&format!(
+                "Patch {} was produced by bsdiff {}, applying with bsdiff {}",
+                patch_file.display(), header.bsdiff_version, ALGORITHM_VERSION
+            )),
+            Some(_) => {}
+            None => logger::info(&format!("Patch {} has no header, treating it as a legacy zstd stream", patch_file.display())),
+        }
+    }
+
+    // === 验证方法 ===
+
+    /// 嗅探文件头部
+    fn sniff_file(path: &Path) -> Result<SniffedKind, BsdiffError> {
+        let mut head = [0u8; 8];
+        let mut filled = 0;
+        let mut handle = File::open(path)?;
+        while filled < head.len() {
+            let n = handle.read(&mut head[filled..])?;
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        let head = &head[..filled];
+
+        if head.starts_with(&MAGIC) || head.starts_with(&ZSTD_MAGIC) || bsdiff40::is_bsdiff40(head) || vcdiff::is_vcdiff(head) || encryption::is_encrypted(head) {
+            return Ok(SniffedKind::Patch);
+        }
+        Ok(KNOWN_FILE_MAGICS
+            .iter()
+            .find(|(magic, _)| head.starts_with(magic))
+            .map_or(SniffedKind::Unknown, |(_, name)| SniffedKind::Known(name)))
+    }
+
+    /// 启发式检查旧文件与补丁参数是否颠倒；无法判断时放行，交给解码器报错
+    fn check_arguments_order(old_file: &Path, patch_file: &Path) -> Result<(), BsdiffError> {
+        let detected = match Self::sniff_file(patch_file)? {
+            SniffedKind::Patch => return Ok(()),
+            SniffedKind::Known(name) => name,
+            SniffedKind::Unknown => match Self::sniff_file(old_file)? {
+                SniffedKind::Patch => "a non-patch file while the old file looks like a patch",
+                _ => return Ok(()),
+            },
+        };
+        Err(BsdiffError::ArgumentsLikelySwapped {
+            patch_file: patch_file.display().to_string(),
+            detected,
+        })
+    }
+
+    /// 验证diff输入文件
+    #[inline]
+    pub(crate) fn validate_files(old_file: &Path, new_file: &Path) -> Result<(), BsdiffError> {
+        if !Path::new(old_file).exists() {
+            return Err(BsdiffError::FileNotFound { kind: "Old file", path: old_file.display().to_string() });
+        }
+        if !Path::new(new_file).exists() {
+            return Err(BsdiffError::FileNotFound { kind: "New file", path: new_file.display().to_string() });
+        }
+        Ok(())
+    }
+
+    /// 验证patch输入文件
+    #[inline]
+    fn validate_patch_files(old_file: &Path, patch_file: &Path) -> Result<(), BsdiffError> {
+        if !Path::new(old_file).exists() {
+            return Err(BsdiffError::FileNotFound { kind: "Old file", path: old_file.display().to_string() });
+        }
+        if !Path::new(patch_file).exists() {
+            return Err(BsdiffError::FileNotFound { kind: "Patch file", path: patch_file.display().to_string() });
+        }
+        Ok(())
+    }
+
+
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::fs;
+    use std::time::Duration;
     use tempfile::NamedTempFile;
+    use crate::error::ErrorCode;
+    use crate::signing;
     
     #[test]
-    fn test_optimized_diff_patch() {
-        let old_content = b"Hello World! This is the old version with some content.";
-        let new_content = b"Hello World! This is the new version with more content and changes.";
-        
+    fn test_optimized_diff_patch() {
+        let old_content = b"Hello World! This is the old version with some content.";
+        let new_content = b"Hello World! This is the new version with more content and changes.";
+        
+        let old_file = NamedTempFile::new().unwrap();
+        let new_file = NamedTempFile::new().unwrap();
+        let patch_file = NamedTempFile::new().unwrap();
+        
+        fs::write(&old_file, old_content).unwrap();
+        fs::write(&new_file, new_content).unwrap();
+        
+        // 测试最优配置
+        BsdiffRust::diff_optimized(
+            old_file.path(),
+            new_file.path(),
+            patch_file.path(),
+            &OptimizationConfig::default()
+        ).unwrap();
+        
+        let generated_file = NamedTempFile::new().unwrap();
+        BsdiffRust::patch_optimized(
+            old_file.path(),
+            generated_file.path(),
+            patch_file.path(),
+            &OptimizationConfig::default()
+        ).unwrap();
+        
+        let generated_content = fs::read(generated_file.path()).unwrap();
+        assert_eq!(generated_content, new_content);
+    }
+
+
+
+    #[test]
+    fn test_default_methods() {
+        let old_content = b"Test content for default methods.";
+        let new_content = b"Test content for default methods with changes.";
+        
+        let old_file = NamedTempFile::new().unwrap();
+        let new_file = NamedTempFile::new().unwrap();
+        let patch_file = NamedTempFile::new().unwrap();
+        
+        fs::write(&old_file, old_content).unwrap();
+        fs::write(&new_file, new_content).unwrap();
+        
+        // 测试默认方法 (内部使用最优配置)
+        BsdiffRust::diff(
+            old_file.path(),
+            new_file.path(),
+            patch_file.path()
+        ).unwrap();
+        
+        let generated_file = NamedTempFile::new().unwrap();
+        BsdiffRust::patch(
+            old_file.path(),
+            generated_file.path(),
+            patch_file.path()
+        ).unwrap();
+        
+        let generated_content = fs::read(generated_file.path()).unwrap();
+        assert_eq!(generated_content, new_content);
+    }
+
+    #[test]
+    fn test_patch_header_and_legacy_patch() {
+        let old_content = b"Header test content, version one.";
+        let new_content = b"Header test content, version two with extra bytes.";
+
+        let old_file = NamedTempFile::new().unwrap();
+        let new_file = NamedTempFile::new().unwrap();
+        let patch_file = NamedTempFile::new().unwrap();
+        fs::write(&old_file, old_content).unwrap();
+        fs::write(&new_file, new_content).unwrap();
+
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        BsdiffRust::diff_optimized(
+            old_file.path(),
+            new_file.path(),
+            patch_file.path(),
+            &config
+        ).unwrap();
+
+        // 新补丁带有补丁头
+        let (header, _) = BsdiffRust::open_patch(patch_file.path(), None).unwrap();
+        let header = header.expect("patch should carry a header");
+        assert_eq!(header.bsdiff_version, ALGORITHM_VERSION);
+        assert_eq!(header.format_version, header::FORMAT_VERSION);
+        assert_eq!(header.compression, header::Compression::Zstd);
+        assert!(header.is_algorithm_compatible());
+
+        // 旧版裸 zstd 补丁仍然可以应用
+        let mut raw = Vec::new();
+        bsdiff::diff(old_content, new_content, &mut raw).unwrap();
+        let legacy_patch = NamedTempFile::new().unwrap();
+        fs::write(&legacy_patch, zstd::encode_all(&raw[..], 3).unwrap()).unwrap();
+
+        let generated_file = NamedTempFile::new().unwrap();
+        BsdiffRust::patch_optimized(
+            old_file.path(),
+            generated_file.path(),
+            legacy_patch.path(),
+            &config
+        ).unwrap();
+        assert_eq!(fs::read(generated_file.path()).unwrap(), new_content);
+
+        // v1 头部没有压缩算法 id 与标志位
+        let mut v1_patch = MAGIC.to_vec();
+        v1_patch.push(1);
+        v1_patch.extend_from_slice(&0u32.to_le_bytes());
+        v1_patch.extend_from_slice(&zstd::encode_all(&raw[..], 3).unwrap());
+        assert_eq!(BsdiffRust::patch_bytes(old_content, &v1_patch, &config).unwrap(), new_content);
+
+        // 未知标志位与压缩算法被拒绝
+        let patch = fs::read(patch_file.path()).unwrap();
+        let flag_offset = MAGIC.len() + 2;
+        for (offset, value) in [(flag_offset - 1, 0x7F), (flag_offset, 0x80)] {
+            let mut tampered = patch.clone();
+            tampered[offset] = value;
+            let err = BsdiffRust::patch_bytes(old_content, &tampered, &config).unwrap_err();
+            assert!(matches!(err, BsdiffError::CorruptPatch { .. }));
+        }
+
+        // 既无魔数也不是 zstd 帧
+        let err = BsdiffRust::patch_bytes(old_content, b"definitely not a patch", &config).unwrap_err();
+        assert!(matches!(err, BsdiffError::NotAPatch { .. }));
+    }
+
+    #[test]
+    fn test_append_mode() {
+        let old_content = b"line 1\nline 2\nline 3\n".repeat(50);
+        let mut new_content = old_content.clone();
+        new_content.extend_from_slice(b"line 4\nline 5\n");
+
+        let old_file = NamedTempFile::new().unwrap();
+        let new_file = NamedTempFile::new().unwrap();
+        let patch_file = NamedTempFile::new().unwrap();
+        fs::write(&old_file, &old_content).unwrap();
+        fs::write(&new_file, &new_content).unwrap();
+
+        let config = OptimizationConfig { use_fast_temp_dir: false, mode: DiffMode::Append, ..Default::default() };
+        assert_eq!(
+            BsdiffRust::find_append_prefix(old_file.path(), new_file.path()).unwrap(),
+            Some(old_content.len() as u64)
+        );
+        BsdiffRust::diff_optimized(
+            old_file.path(),
+            new_file.path(),
+            patch_file.path(),
+            &config
+        ).unwrap();
+
+        let generated_file = NamedTempFile::new().unwrap();
+        BsdiffRust::patch_optimized(
+            old_file.path(),
+            generated_file.path(),
+            patch_file.path(),
+            &config
+        ).unwrap();
+        assert_eq!(fs::read(generated_file.path()).unwrap(), new_content);
+
+        // 前缀过短时回退到完整 bsdiff
+        fs::write(&new_file, b"completely different content").unwrap();
+        assert_eq!(
+            BsdiffRust::find_append_prefix(old_file.path(), new_file.path()).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_progress_callback() {
+        use std::sync::{Arc, Mutex};
+
+        let old_content: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let mut new_content = old_content.clone();
+        new_content[1000..1100].fill(7);
+        new_content.extend_from_slice(&[42u8; 5000]);
+
+        let old_file = NamedTempFile::new().unwrap();
+        let new_file = NamedTempFile::new().unwrap();
+        let patch_file = NamedTempFile::new().unwrap();
+        fs::write(&old_file, &old_content).unwrap();
+        fs::write(&new_file, &new_content).unwrap();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let config = OptimizationConfig {
+            use_fast_temp_dir: false,
+            progress: Some(Arc::new(move |done, total| sink.lock().unwrap().push((done, total)))),
+            ..Default::default()
+        };
+
+        BsdiffRust::diff_optimized(
+            old_file.path(),
+            new_file.path(),
+            patch_file.path(),
+            &config
+        ).unwrap();
+        let diff_events = std::mem::take(&mut *events.lock().unwrap());
+        assert!(diff_events.windows(2).all(|w| w[0].0 <= w[1].0));
+        assert!(diff_events.len() <= 102);
+        assert_eq!(diff_events.last().copied(), Some((new_content.len() as u64, new_content.len() as u64)));
+
+        let generated_file = NamedTempFile::new().unwrap();
+        BsdiffRust::patch_optimized(
+            old_file.path(),
+            generated_file.path(),
+            patch_file.path(),
+            &config
+        ).unwrap();
+        let patch_size = fs::metadata(patch_file.path()).unwrap().len();
+        let patch_events = events.lock().unwrap().clone();
+        assert!(patch_events.windows(2).all(|w| w[0].0 <= w[1].0));
+        assert_eq!(patch_events.last().copied(), Some((patch_size, patch_size)));
+        assert_eq!(fs::read(generated_file.path()).unwrap(), new_content);
+    }
+
+    #[test]
+    fn test_phase_progress() {
+        use crate::progress::ProgressEvent;
+        use std::sync::{Arc, Mutex};
+
+        let old: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let mut new = old.clone();
+        new[5000..5100].fill(3);
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old"), &old).unwrap();
+        fs::write(path("new"), &new).unwrap();
+
+        let events = Arc::new(Mutex::new(Vec::<ProgressEvent>::new()));
+        let sink = events.clone();
+        let config = OptimizationConfig {
+            use_fast_temp_dir: false,
+            phase_progress: Some(Arc::new(move |event: &ProgressEvent| sink.lock().unwrap().push(*event))),
+            ..Default::default()
+        };
+        // 依次经过的阶段 (相邻重复合并)，以及每个阶段以 done == total 且 eta 为 0 结束
+        let phases = |events: &[ProgressEvent]| {
+            let mut phases: Vec<&str> = events.iter().map(|event| event.phase.name()).collect();
+            phases.dedup();
+            phases
+        };
+        let finished = |events: &[ProgressEvent], phase: ProgressPhase| {
+            events.iter().rev().find(|event| event.phase == phase).is_some_and(|event| {
+                event.bytes_done == event.bytes_total && event.eta_ms == Some(0)
+            })
+        };
+
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("patch"), &config).unwrap();
+        let diff_events = std::mem::take(&mut *events.lock().unwrap());
+        assert_eq!(phases(&diff_events), ["read", "apply", "write"]);
+        assert_eq!(diff_events[0].bytes_total, (old.len() + new.len()) as u64);
+        assert!(finished(&diff_events, ProgressPhase::Apply) && finished(&diff_events, ProgressPhase::Write));
+        let apply: Vec<_> = diff_events.iter().filter(|event| event.phase == ProgressPhase::Apply).collect();
+        assert!(apply.windows(2).all(|w| w[0].bytes_done <= w[1].bytes_done));
+        assert_eq!(apply.last().unwrap().bytes_total, new.len() as u64);
+
+        BsdiffRust::patch_optimized(&path("old"), &path("out"), &path("patch"), &config).unwrap();
+        let patch_events = events.lock().unwrap().clone();
+        assert_eq!(phases(&patch_events), ["decode", "read", "apply", "write"]);
+        for phase in [ProgressPhase::Decode, ProgressPhase::Read, ProgressPhase::Apply, ProgressPhase::Write] {
+            assert!(finished(&patch_events, phase), "{:?}", phase);
+        }
+        assert_eq!(fs::read(path("out")).unwrap(), new);
+    }
+
+    #[test]
+    fn test_streaming_patch_keeps_output_on_failure() {
+        let old: Vec<u8> = (0..300_000u32).map(|i| (i % 253) as u8).collect();
+        let mut new = old.clone();
+        new[150_000..150_100].fill(9);
+        let dir = tempfile::TempDir::new().unwrap();
+        let (old_file, new_file, patch_file, out) =
+            (dir.path().join("old"), dir.path().join("new"), dir.path().join("patch"), dir.path().join("out"));
+        fs::write(&old_file, &old).unwrap();
+        fs::write(&new_file, &new).unwrap();
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        BsdiffRust::diff_optimized(&old_file, &new_file, &patch_file, &config).unwrap();
+
+        // 差分数据跨越多个分块
+        BsdiffRust::patch_optimized(&old_file, &out, &patch_file, &config).unwrap();
+        assert_eq!(fs::read(&out).unwrap(), new);
+
+        // 截断的补丁：已有输出保持不变，不留下临时文件
+        let patch = fs::read(&patch_file).unwrap();
+        fs::write(&patch_file, &patch[..patch.len() - 8]).unwrap();
+        assert!(BsdiffRust::patch_optimized(&old_file, &out, &patch_file, &config).is_err());
+        assert_eq!(fs::read(&out).unwrap(), new);
+        assert!(!dir.path().join("out.partial").exists());
+    }
+
+    #[test]
+    fn test_payload_crc_trailer() {
+        let old = b"payload trailer base content ".repeat(400);
+        let mut new = old.clone();
+        new[3000..3200].fill(b'#');
+        let config = OptimizationConfig { use_fast_temp_dir: false, compression: Compression::None, ..Default::default() };
+        let patch = BsdiffRust::diff_bytes(&old, &new, &config).unwrap();
+        let mut cursor = io::Cursor::new(&patch);
+        assert!(PatchHeader::read_from(&mut cursor).unwrap().unwrap().payload_crc);
+        let payload_len = patch.len() - cursor.position() as usize - PAYLOAD_TRAILER_LEN;
+        assert_eq!(header::parse_payload_trailer(&patch[patch.len() - PAYLOAD_TRAILER_LEN..]).map(|(len, _)| len), Some(payload_len as u64));
+        let corrupt_reason = |result: Result<Vec<u8>, BsdiffError>| match result.unwrap_err() {
+            BsdiffError::CorruptPatch { reason, .. } => reason,
+            other => panic!("expected CorruptPatch, got {:?}", other),
+        };
+
+        // 截断的补丁 (如未下载完整) 在写出输出前即被拒绝
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old"), &old).unwrap();
+        fs::write(path("patch"), &patch[..patch.len() - 100]).unwrap();
+        let err = BsdiffRust::patch_optimized(&path("old"), &path("out"), &path("patch"), &config).unwrap_err();
+        assert!(matches!(err, BsdiffError::CorruptPatch { .. }));
+        assert!(!path("out").exists() && !path("out.partial").exists());
+
+        // 负载内容被改动 (未压缩负载自身无法发现)：CRC-32C 不符
+        let mut tampered = patch.clone();
+        tampered[patch.len() - PAYLOAD_TRAILER_LEN - 10] ^= 0x01;
+        assert!(corrupt_reason(BsdiffRust::patch_bytes(&old, &tampered, &config)).contains("CRC-32C"));
+
+        // 无法定位到末尾的来源 (HTTP 流) 读到末尾时校验
+        #[cfg(feature = "http")]
+        {
+            let streamed = |patch: &[u8]| {
+                let reader = crate::http::Rewindable::new(io::Cursor::new(patch.to_vec()), 1 << 20);
+                BsdiffRust::patch_from_reader(&old, reader, patch.len() as u64, Path::new("stream"), &config)
+            };
+            assert_eq!(streamed(&patch).unwrap(), new);
+            assert!(corrupt_reason(streamed(&tampered)).contains("CRC-32C"));
+            assert!(corrupt_reason(streamed(&patch[..patch.len() - 100])).contains("truncated"));
+        }
+
+        // 未声明尾部的补丁 (旧版本生成) 照常应用
+        let mut untagged = Vec::new();
+        PatchHeader::default().write_to(&mut untagged).unwrap();
+        let mut raw = Vec::new();
+        bsdiff::diff(&old, &new, &mut raw).unwrap();
+        untagged.extend_from_slice(&zstd::encode_all(&raw[..], 3).unwrap());
+        assert_eq!(BsdiffRust::patch_bytes(&old, &untagged, &config).unwrap(), new);
+    }
+
+    #[test]
+    fn test_source_checksum_is_enforced() {
+        let old = b"embedded checksum base file ".repeat(3000);
+        let new = b"embedded checksum next file!".repeat(3100);
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let patch = BsdiffRust::diff_bytes(&old, &new, &config).unwrap();
+        assert_eq!(BsdiffRust::patch_bytes(&old, &patch, &config).unwrap(), new);
+
+        // 大小或开头片段不同：快速预检即失败
+        for other in [old[..old.len() - 1].to_vec(), [b"X", &old[1..]].concat()] {
+            let err = BsdiffRust::patch_bytes(&other, &patch, &config).unwrap_err();
+            match &err {
+                BsdiffError::BaseMismatch { expected_size, actual_size, .. } => {
+                    assert_eq!((*expected_size, *actual_size), (old.len() as u64, other.len() as u64));
+                }
+                other => panic!("unexpected error: {:?}", other),
+            }
+            assert_eq!(err.code(), Some(ErrorCode::BaseMismatch));
+        }
+
+        // 预检范围之后的差异由完整摘要发现，错误的基准文件在解码前即被拒绝
+        let mut other = old.clone();
+        *other.last_mut().unwrap() ^= 1;
+        let err = BsdiffRust::patch_bytes(&other, &patch, &config).unwrap_err();
+        match err {
+            BsdiffError::SourceMismatch { expected, actual, .. } => {
+                assert_eq!(expected, FileDigest::of_bytes(&old));
+                assert_eq!(actual, FileDigest::of_bytes(&other));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        // 无摘要的旧版补丁：默认放行，严格模式拒绝
+        let mut raw = Vec::new();
+        bsdiff::diff(&old, &new, &mut raw).unwrap();
+        let legacy = zstd::encode_all(&raw[..], 3).unwrap();
+        assert_eq!(BsdiffRust::patch_bytes(&old, &legacy, &config).unwrap(), new);
+        let strict = OptimizationConfig { strict: true, ..config.clone() };
+        let err = BsdiffRust::patch_bytes(&old, &legacy, &strict).unwrap_err();
+        assert!(matches!(err, BsdiffError::MissingSourceChecksum { .. }));
+        assert_eq!(BsdiffRust::patch_bytes(&old, &patch, &strict).unwrap(), new);
+    }
+
+    #[test]
+    fn test_windowed_diff() {
+        let old: Vec<u8> = (0..60_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8).collect();
+        // 局部修改 + 插入，使后续数据整体偏移
+        let mut new = old.clone();
+        new[5_000..5_100].fill(0xEE);
+        new.splice(20_000..20_000, b"inserted bytes ".repeat(20));
+        new.truncate(55_000);
+
+        let config = OptimizationConfig { use_fast_temp_dir: false, window_size: Some(4096), ..Default::default() };
+        let patch = BsdiffRust::diff_bytes(&old, &new, &config).unwrap();
+        assert_eq!(BsdiffRust::patch_bytes(&old, &patch, &config).unwrap(), new);
+
+        // 窗口之间插入只含 seek 的空控制块
+        let mut cursor = io::Cursor::new(&patch);
+        let header = PatchHeader::read_from(&mut cursor).unwrap().unwrap();
+        let raw = zstd::decode_all(&patch[cursor.position() as usize..patch.len() - PAYLOAD_TRAILER_LEN]).unwrap();
+        let mut seeks = 0;
+        bsdiff40::for_each_entry(&raw, |entry, _, _| {
+            seeks += (entry.mix_len == 0 && entry.copy_len == 0) as u64;
+            Ok(())
+        }).unwrap();
+        assert!(seeks > 0);
+        assert!(header.control_blocks.unwrap() > seeks);
+        assert!(patch.len() < new.len() / 4);
+
+        // 旧文件远大于/小于新文件、新文件为空、BSDIFF40 输出
+        for (old, new) in [(&old[..], &new[..8_000]), (&old[..3_000], &new[..]), (&old[..], &[][..])] {
+            let patch = BsdiffRust::diff_bytes(old, new, &config).unwrap();
+            assert_eq!(BsdiffRust::patch_bytes(old, &patch, &config).unwrap(), new);
+        }
+        let classic = OptimizationConfig { format: PatchFormat::Bsdiff40, ..config.clone() };
+        let patch = BsdiffRust::diff_bytes(&old, &new, &classic).unwrap();
+        assert_eq!(BsdiffRust::patch_bytes(&old, &patch, &classic).unwrap(), new);
+        let delta = OptimizationConfig { format: PatchFormat::Vcdiff, ..config.clone() };
+        let patch = BsdiffRust::diff_bytes(&old, &new, &delta).unwrap();
+        assert!(vcdiff::is_vcdiff(&patch));
+        assert_eq!(BsdiffRust::patch_bytes(&old, &patch, &config).unwrap(), new);
+        assert!(BsdiffRust::patch_bytes(&old, &patch, &OptimizationConfig { strict: true, ..config.clone() }).is_err());
+
+        // 并行 diff 的输出与线程数无关
+        let serial = BsdiffRust::diff_bytes(&old, &new, &OptimizationConfig { threads: Some(1), ..config.clone() }).unwrap();
+        let parallel = BsdiffRust::diff_bytes(&old, &new, &OptimizationConfig { threads: Some(4), ..config.clone() }).unwrap();
+        assert_eq!(serial, parallel);
+    }
+
+    #[test]
+    fn test_uncompressed_patch() {
+        let old = b"uncompressed payload base ".repeat(40);
+        let new = b"uncompressed payload next!".repeat(43);
+        let config = OptimizationConfig { use_fast_temp_dir: false, compression: Compression::None, ..Default::default() };
+        let patch = BsdiffRust::diff_bytes(&old, &new, &config).unwrap();
+
+        // 压缩算法记录在补丁头中，应用时自动识别
+        let mut cursor = io::Cursor::new(&patch);
+        let header = PatchHeader::read_from(&mut cursor).unwrap().unwrap();
+        assert_eq!(header.compression, Compression::None);
+        let payload = &patch[cursor.position() as usize..];
+        assert_eq!(ControlEntry::read_from(&mut &payload[..]).unwrap().map(|_| ()), Some(()));
+        assert_eq!(BsdiffRust::patch_bytes(&old, &patch, &OptimizationConfig::default()).unwrap(), new);
+
+        // 重新压缩保留未压缩的负载
+        let patch_file = NamedTempFile::new().unwrap();
+        let recompressed = NamedTempFile::new().unwrap();
+        fs::write(&patch_file, &patch).unwrap();
+        BsdiffRust::recompress_patch(patch_file.path(), recompressed.path(), &config).unwrap();
+        assert_eq!(fs::read(recompressed.path()).unwrap(), patch);
+
+        let classic = OptimizationConfig { format: PatchFormat::Bsdiff40, ..config };
+        assert!(BsdiffRust::diff_bytes(&old, &new, &classic).is_err());
+    }
+
+    #[test]
+    fn test_alternative_compression() {
+        let old = b"alternative codec base payload ".repeat(300);
+        let new = b"alternative codec next payload!".repeat(320);
+        for compression in [Compression::Xz, Compression::Brotli, Compression::Gzip] {
+            let config = OptimizationConfig { use_fast_temp_dir: false, compression, ..Default::default() };
+            if !codecs::is_available(compression) {
+                assert!(BsdiffRust::diff_bytes(&old, &new, &config).is_err());
+                continue;
+            }
+            let patch = BsdiffRust::diff_bytes(&old, &new, &config).unwrap();
+            let header = PatchHeader::read_from(&mut io::Cursor::new(&patch)).unwrap().unwrap();
+            assert_eq!(header.compression, compression);
+            assert_eq!(BsdiffRust::patch_bytes(&old, &patch, &OptimizationConfig::default()).unwrap(), new, "{}", compression.name());
+
+            // 重新压缩按同一算法重新编码，原始 bsdiff 流不变
+            let patch_file = NamedTempFile::new().unwrap();
+            let recompressed = NamedTempFile::new().unwrap();
+            fs::write(&patch_file, &patch).unwrap();
+            BsdiffRust::recompress_patch(patch_file.path(), recompressed.path(), &config).unwrap();
+            let recompressed = fs::read(recompressed.path()).unwrap();
+            assert_eq!(BsdiffRust::patch_bytes(&old, &recompressed, &OptimizationConfig::default()).unwrap(), new);
+
+            let info = crate::utils::get_patch_info_from_bytes(&patch).unwrap();
+            assert_eq!(info.compression, compression.name());
+            assert!(info.uncompressed_size.is_some_and(|size| size > 0));
+
+            // 负载损坏时返回错误而不是错误的输出
+            let mut corrupt = patch.clone();
+            let last = corrupt.len() - PAYLOAD_TRAILER_LEN - 2;
+            corrupt[last] ^= 0xff;
+            assert!(BsdiffRust::patch_bytes(&old, &corrupt, &OptimizationConfig::default()).is_err());
+        }
+        // 字典只作用于 zstd
+        let dictionary = Dictionary::new(b"alternative codec dictionary").unwrap();
+        let config = OptimizationConfig { compression: Compression::Gzip, dictionary: Some(dictionary), ..Default::default() };
+        assert!(BsdiffRust::diff_bytes(&old, &new, &config).is_err());
+    }
+
+    #[test]
+    fn test_signed_patch() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let old_path = dir.path().join("old.bin");
+        let patch_path = dir.path().join("patch.bin");
+        let out_path = dir.path().join("out.bin");
+        let old = b"signed update base ".repeat(60);
+        let new = b"signed update next!".repeat(64);
+        fs::write(&old_path, &old).unwrap();
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let mut patch = BsdiffRust::diff_bytes(&old, &new, &config).unwrap();
+        fs::write(&patch_path, &patch).unwrap();
+
+        let private_key = [3u8; 32];
+        let public_key = signing::public_key_from_private(&private_key).unwrap();
+        let signature = signing::sign_patch(&patch_path, &private_key).unwrap();
+        assert_eq!(signature, signing::sign_patch_bytes(&patch, &private_key).unwrap());
+        let signed = OptimizationConfig { signature: Some(PatchSignature::new(&public_key, &signature).unwrap()), ..config };
+        let (old_str, patch_str, out_str) = (&old_path, &patch_path, &out_path);
+        BsdiffRust::patch_optimized(old_str, out_str, patch_str, &signed).unwrap();
+        assert_eq!(fs::read(&out_path).unwrap(), new);
+        fs::remove_file(&out_path).unwrap();
+
+        // 篡改后的补丁在应用前被拒绝，不产生输出
+        let last = patch.len() - 1;
+        patch[last] ^= 1;
+        fs::write(&patch_path, &patch).unwrap();
+        let err = BsdiffRust::patch_optimized(old_str, out_str, patch_str, &signed).unwrap_err();
+        assert!(matches!(err, BsdiffError::InvalidSignature { .. }));
+        assert!(!out_path.exists());
+        assert!(BsdiffRust::patch_bytes(&old, &patch, &signed).is_err());
+
+        // 签名先于补丁头的任何检查：对错误的基准文件应用篡改的补丁，报告的是签名无效
+        let err = BsdiffRust::patch_bytes(&new, &patch, &signed).unwrap_err();
+        assert!(matches!(err, BsdiffError::InvalidSignature { .. }), "{}", err);
+    }
+
+    #[test]
+    fn test_cancelled_operations_leave_no_output() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let old_path = dir.path().join("old.bin");
+        let new_path = dir.path().join("new.bin");
+        let patch_path = dir.path().join("patch.bin");
+        let out_path = dir.path().join("out.bin");
+        let old = b"cancellable operation base ".repeat(50);
+        let new = b"cancellable operation next!".repeat(52);
+        fs::write(&old_path, &old).unwrap();
+        fs::write(&new_path, &new).unwrap();
+
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        BsdiffRust::diff_optimized(
+            &old_path,
+            &new_path,
+            &patch_path,
+            &config
+        ).unwrap();
+
+        let token = CancelToken::new();
+        token.cancel();
+        let cancelled = OptimizationConfig { cancel: Some(token), ..config };
+
+        let err = BsdiffRust::patch_optimized(
+            &old_path,
+            &out_path,
+            &patch_path,
+            &cancelled
+        ).unwrap_err();
+        assert!(matches!(err, BsdiffError::Cancelled));
+        assert!(!out_path.exists());
+
+        let err = BsdiffRust::diff_optimized(
+            &old_path,
+            &new_path,
+            &out_path,
+            &cancelled
+        ).unwrap_err();
+        assert!(matches!(err, BsdiffError::Cancelled));
+        assert!(!out_path.exists());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+
+        // 超时同样中止 diff 且不留下输出；窗口 diff 在后缀排序中检查期限
+        let timed_out = OptimizationConfig { cancel: Some(CancelToken::new().with_timeout(Duration::ZERO)), ..cancelled };
+        for window_size in [None, Some(256)] {
+            let config = OptimizationConfig { window_size, ..timed_out.clone() };
+            let err = BsdiffRust::diff_optimized(
+                &old_path,
+                &new_path,
+                &out_path,
+                &config
+            ).unwrap_err();
+            assert!(matches!(err, BsdiffError::TimedOut { timeout_ms: 0 }));
+            assert!(!out_path.exists());
+        }
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+        let roomy = OptimizationConfig { cancel: Some(CancelToken::new().with_timeout(Duration::from_secs(60))), ..timed_out };
+        BsdiffRust::diff_optimized(&old_path, &new_path, &out_path, &roomy).unwrap();
+    }
+
+    #[test]
+    fn test_bsdiff40_format_option() {
+        let old = b"classic format option old ".repeat(40);
+        let new = b"classic format option NEW!".repeat(42);
+        let old_file = NamedTempFile::new().unwrap();
+        let new_file = NamedTempFile::new().unwrap();
+        let patch_file = NamedTempFile::new().unwrap();
+        fs::write(&old_file, &old).unwrap();
+        fs::write(&new_file, &new).unwrap();
+
+        let config = OptimizationConfig { use_fast_temp_dir: false, format: PatchFormat::Bsdiff40, ..Default::default() };
+        BsdiffRust::diff_optimized(
+            old_file.path(),
+            new_file.path(),
+            patch_file.path(),
+            &config
+        ).unwrap();
+        let patch = fs::read(patch_file.path()).unwrap();
+        assert!(bsdiff40::is_bsdiff40(&patch));
+
+        // 应用时按魔数自动识别
+        let generated = NamedTempFile::new().unwrap();
+        BsdiffRust::patch_optimized(
+            old_file.path(),
+            generated.path(),
+            patch_file.path(),
+            &OptimizationConfig { use_fast_temp_dir: false, ..Default::default() }
+        ).unwrap();
+        assert_eq!(fs::read(generated.path()).unwrap(), new);
+        assert_eq!(BsdiffRust::patch_bytes(&old, &BsdiffRust::diff_bytes(&old, &new, &config).unwrap(), &config).unwrap(), new);
+
+        let mut corrupt = patch.clone();
+        corrupt.truncate(patch.len() - 4);
+        let result = BsdiffRust::patch_bytes(&old, &corrupt, &config);
+        assert!(matches!(result.unwrap_err(), BsdiffError::CorruptPatch { .. }));
+    }
+
+    #[test]
+    fn test_diff_and_patch_bytes() {
+        let old = b"in-memory buffer diff old ".repeat(50);
+        let new = b"in-memory buffer diff NEW!".repeat(52);
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+
+        let patch = BsdiffRust::diff_bytes(&old, &new, &config).unwrap();
+        assert_eq!(BsdiffRust::patch_bytes(&old, &patch, &config).unwrap(), new);
+
+        // 与基于文件的补丁字节一致
+        let old_file = NamedTempFile::new().unwrap();
+        let new_file = NamedTempFile::new().unwrap();
+        fs::write(&old_file, &old).unwrap();
+        fs::write(&new_file, &new).unwrap();
+        let from_files = BsdiffRust::diff_to_writer(
+            old_file.path(),
+            new_file.path(),
+            Vec::new(),
+            &config
+        ).unwrap();
+        assert_eq!(patch, from_files);
+
+        // 从文件应用补丁到内存
+        let patch_file = NamedTempFile::new().unwrap();
+        fs::write(&patch_file, &patch).unwrap();
+        let old_path = old_file.path();
+        assert_eq!(BsdiffRust::patch_to_bytes(old_path, patch_file.path(), &config).unwrap(), new);
+        assert!(BsdiffRust::patch_to_bytes(old_path, Path::new("/nonexistent/patch"), &config).is_err());
+
+        // 流式写出
+        let mut streamed = Vec::new();
+        assert_eq!(BsdiffRust::patch_bytes_to_writer(&old, &patch, &mut streamed, &config).unwrap(), new.len() as u64);
+        assert_eq!(streamed, new);
+
+        // 追加模式
+        let mut appended = old.clone();
+        appended.extend_from_slice(b"appended tail");
+        let append = OptimizationConfig { mode: DiffMode::Append, ..config.clone() };
+        let patch = BsdiffRust::diff_bytes(&old, &appended, &append).unwrap();
+        assert_eq!(BsdiffRust::patch_bytes(&old, &patch, &config).unwrap(), appended);
+
+        assert!(BsdiffRust::patch_bytes(&old, b"not a patch", &config).is_err());
+    }
+
+    #[test]
+    fn test_mixed_buffer_and_file_operands() {
+        let old = b"mixed operand base content ".repeat(60);
+        let mut new = old.clone();
+        new[200..260].fill(b'*');
+        new.extend_from_slice(b"mixed tail");
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let patch = BsdiffRust::diff_bytes(&old, &new, &config).unwrap();
+        fs::write(path("old"), &old).unwrap();
+        fs::write(path("patch"), &patch).unwrap();
+
+        // 旧文件在内存中，补丁在磁盘上
+        assert_eq!(BsdiffRust::patch_bytes_to_file(&old, &path("patch"), &path("a"), &config).unwrap(), new.len() as u64);
+        assert_eq!(fs::read(path("a")).unwrap(), new);
+        let dry_run = OptimizationConfig { dry_run: true, ..config.clone() };
+        assert_eq!(BsdiffRust::patch_bytes_to_file(&old, &path("patch"), &path("dry"), &dry_run).unwrap(), new.len() as u64);
+        assert!(!dir.path().join("dry").exists());
+        assert!(BsdiffRust::patch_bytes_to_file(&old, &path("missing"), &path("x"), &config).is_err());
+
+        // 旧文件在磁盘上，补丁在内存中
+        assert_eq!(BsdiffRust::patch_file_with_patch_bytes(&path("old"), &patch, &path("b"), &config).unwrap(), new.len() as u64);
+        assert_eq!(fs::read(path("b")).unwrap(), new);
+        assert!(BsdiffRust::patch_file_with_patch_bytes(&path("old"), &patch, &path("dry"), &dry_run).is_err());
+        assert!(BsdiffRust::patch_file_with_patch_bytes(&path("missing"), &patch, &path("x"), &config).is_err());
+
+        // 源文件校验失败时不留下输出
+        let err = BsdiffRust::patch_bytes_to_file(&new, &path("patch"), &path("bad"), &config).unwrap_err();
+        assert!(matches!(err, BsdiffError::BaseMismatch { .. }));
+        assert!(BsdiffRust::patch_file_with_patch_bytes(&path("old"), b"not a patch", &path("bad"), &config).is_err());
+        assert!(!dir.path().join("bad").exists());
+
+        // 新旧数据都在内存中，补丁流式写入磁盘，与内存中生成的补丁逐字节相同
+        BsdiffRust::diff_bytes_to_file(&old, &new, &path("streamed.patch"), &config).unwrap();
+        assert_eq!(fs::read(path("streamed.patch")).unwrap(), patch);
+        let no_overwrite = OptimizationConfig { overwrite: OverwritePolicy::Error, ..config.clone() };
+        assert!(BsdiffRust::diff_bytes_to_file(&old, &new, &path("streamed.patch"), &no_overwrite).is_err());
+    }
+
+    #[test]
+    fn test_mmap_output() {
+        let old = b"memory mapped output base ".repeat(400);
+        let mut new = old.clone();
+        new[100..180].fill(b'#');
+        new.extend_from_slice(b" with a tail");
+        let config = OptimizationConfig { use_fast_temp_dir: false, mmap_output: true, ..Default::default() };
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old"), &old).unwrap();
+        fs::write(path("patch"), BsdiffRust::diff_bytes(&old, &new, &config).unwrap()).unwrap();
+
+        BsdiffRust::patch_optimized(&path("old"), &path("new"), &path("patch"), &config).unwrap();
+        assert_eq!(fs::read(path("new")).unwrap(), new);
+
+        let region = new.len() as u64 + 100;
+        assert_eq!(BsdiffRust::patch_to_fixed_region(&path("old"), &path("region"), &path("patch"), region, true, &config).unwrap(), region);
+        let padded = fs::read(path("region")).unwrap();
+        assert_eq!(&padded[..new.len()], &new[..]);
+        assert!(padded[new.len()..].iter().all(|b| *b == 0));
+
+        // 无补丁头的旧版补丁退回普通写入
+        let mut raw = Vec::new();
+        bsdiff::diff(&old, &new, &mut raw).unwrap();
+        fs::write(path("legacy"), zstd::encode_all(&raw[..], 3).unwrap()).unwrap();
+        BsdiffRust::patch_optimized(&path("old"), &path("legacy_new"), &path("legacy"), &config).unwrap();
+        assert_eq!(fs::read(path("legacy_new")).unwrap(), new);
+
+        // 输出超出预分配大小时报错
+        let mut writer = MmapWriter { map: memmap2::MmapMut::map_anon(4).unwrap(), pos: 0 };
+        assert!(writer.write_all(b"12345").is_err());
+    }
+
+    #[test]
+    fn test_temp_dir() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let temp = tempfile::TempDir::new_in(dir.path()).unwrap();
+        let path = |name: &str| dir.path().join(name);
+        let config = OptimizationConfig { temp_dir: Some(temp.path().to_path_buf()), ..Default::default() };
+
+        let temp_path = BsdiffRust::get_optimal_output_path(&path("patch"), &config, 1024).unwrap();
+        assert_eq!(temp_path.parent(), Some(temp.path()));
+        let name = temp_path.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with(&format!("bsdiff_{}_", std::process::id())) && name.ends_with("_patch"));
+        // 同名输出每次得到不同的临时文件
+        assert_ne!(BsdiffRust::get_optimal_output_path(&path("patch"), &config, 1024).unwrap(), temp_path);
+        // 空间不足时写在输出旁边
+        assert_eq!(BsdiffRust::get_optimal_output_path(&path("patch"), &config, u64::MAX / 2).unwrap(), path("patch"));
+        assert_eq!(
+            BsdiffRust::get_streaming_output_path(&path("new"), &config, u64::MAX / 2).unwrap(),
+            paths::with_suffix(&path("new"), ".partial")
+        );
+
+        let old = b"temp dir base ".repeat(300);
+        let mut new = old.clone();
+        new[50..90].fill(b'*');
+        fs::write(path("old"), &old).unwrap();
+        fs::write(path("new"), &new).unwrap();
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("patch"), &config).unwrap();
+        BsdiffRust::patch_optimized(&path("old"), &path("out"), &path("patch"), &config).unwrap();
+        assert_eq!(fs::read(path("out")).unwrap(), new);
+        assert_eq!(fs::read_dir(temp.path()).unwrap().count(), 0);
+
+        let missing = OptimizationConfig { temp_dir: Some(dir.path().join("missing")), ..Default::default() };
+        assert!(BsdiffRust::get_optimal_output_path(&path("patch"), &missing, 0).is_err());
+    }
+
+    #[test]
+    fn test_concurrent_same_name_outputs() {
+        // 不同目录下的同名文件并发 diff/patch，共享同一个临时目录
+        let temp = tempfile::TempDir::new().unwrap();
+        let config = OptimizationConfig { temp_dir: Some(temp.path().to_path_buf()), ..Default::default() };
+        let dirs: Vec<tempfile::TempDir> = (0..8).map(|_| tempfile::TempDir::new().unwrap()).collect();
+        std::thread::scope(|scope| {
+            for (i, dir) in dirs.iter().enumerate() {
+                let config = &config;
+                scope.spawn(move || {
+                    let path = |name: &str| dir.path().join(name);
+                    let old = format!("concurrent base #{} ", i).repeat(1500).into_bytes();
+                    let new = format!("concurrent next #{} ", i * 31).repeat(1500 + i * 10).into_bytes();
+                    fs::write(path("old.bin"), &old).unwrap();
+                    fs::write(path("new.bin"), &new).unwrap();
+                    for _ in 0..5 {
+                        BsdiffRust::diff_optimized(&path("old.bin"), &path("new.bin"), &path("update.patch"), config).unwrap();
+                        BsdiffRust::patch_optimized(&path("old.bin"), &path("out.bin"), &path("update.patch"), config).unwrap();
+                        assert_eq!(fs::read(path("out.bin")).unwrap(), new);
+                    }
+                });
+            }
+        });
+        assert_eq!(fs::read_dir(temp.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let old = b"dry run base content ".repeat(300);
+        let mut new = old.clone();
+        new[40..120].fill(b'~');
+        let config = OptimizationConfig { use_fast_temp_dir: false, dry_run: true, ..Default::default() };
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old"), &old).unwrap();
+        let mut patch = BsdiffRust::diff_bytes(&old, &new, &config).unwrap();
+        fs::write(path("patch"), &patch).unwrap();
+
+        let size = BsdiffRust::patch_optimized(&path("old"), &path("new"), &path("patch"), &config).unwrap();
+        assert_eq!(size, new.len() as u64);
+        assert!(!dir.path().join("new").exists());
+
+        // 目标摘要不符时报错，同样不产生输出
+        let sha = FileDigest::of_bytes(&new).hash;
+        let at = patch.windows(sha.len()).position(|w| w == sha).unwrap();
+        patch[at] ^= 0xFF;
+        fs::write(path("patch"), &patch).unwrap();
+        let err = BsdiffRust::patch_optimized(&path("old"), &path("new"), &path("patch"), &config).unwrap_err();
+        assert!(matches!(err, BsdiffError::TargetMismatch { .. }));
+        assert!(!dir.path().join("new").exists());
+    }
+
+    #[test]
+    fn test_patch_into_slice() {
+        let old = b"slice output base ".repeat(200);
+        let mut new = old.clone();
+        new[10..60].fill(b'%');
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old"), &old).unwrap();
+        fs::write(path("patch"), BsdiffRust::diff_bytes(&old, &new, &config).unwrap()).unwrap();
+
+        assert_eq!(BsdiffRust::declared_output_size(&path("patch")).unwrap(), Some(new.len() as u64));
+        let mut output = vec![0u8; new.len()];
+        BsdiffRust::patch_into_slice(&path("old"), &path("patch"), &mut output, &config).unwrap();
+        assert_eq!(output, new);
+        // 缓冲区大小与新文件不符时报错
+        assert!(BsdiffRust::patch_into_slice(&path("old"), &path("patch"), &mut vec![0u8; new.len() - 1], &config).is_err());
+        assert!(BsdiffRust::patch_into_slice(&path("old"), &path("patch"), &mut vec![0u8; new.len() + 1], &config).is_err());
+
+        let classic = OptimizationConfig { format: PatchFormat::Bsdiff40, ..config };
+        fs::write(path("classic"), BsdiffRust::diff_bytes(&old, &new, &classic).unwrap()).unwrap();
+        assert_eq!(BsdiffRust::declared_output_size(&path("classic")).unwrap(), None);
+    }
+
+    #[test]
+    fn test_encrypted_patch() {
+        let old = b"encrypted patch base ".repeat(300);
+        let mut new = old.clone();
+        new[100..180].fill(b'#');
+        let key = EncryptionKey::new(&[0x5a; 32]).unwrap();
+        let plain = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let config = OptimizationConfig { encryption_key: Some(key), ..plain.clone() };
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old"), &old).unwrap();
+        fs::write(path("new"), &new).unwrap();
+
+        // 文件与内存两条 diff 路径封装相同的补丁，随机 nonce 使信封互不相同
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("patch"), &config).unwrap();
+        let patch = fs::read(path("patch")).unwrap();
+        assert!(encryption::is_encrypted(&patch));
+        let key = config.encryption_key.as_ref().unwrap();
+        let in_memory = BsdiffRust::diff_bytes(&old, &new, &config).unwrap();
+        assert_ne!(in_memory, patch);
+        assert_eq!(
+            encryption::open(key, in_memory, &path("patch")).unwrap(),
+            encryption::open(key, patch.clone(), &path("patch")).unwrap()
+        );
+        assert_eq!(BsdiffRust::patch_bytes(&old, &patch, &config).unwrap(), new);
+        BsdiffRust::patch_optimized(&path("old"), &path("out"), &path("patch"), &config).unwrap();
+        assert_eq!(fs::read(path("out")).unwrap(), new);
+        let dry_run = OptimizationConfig { dry_run: true, ..config.clone() };
+        assert_eq!(BsdiffRust::patch_optimized(&path("old"), &path("dry"), &path("patch"), &dry_run).unwrap(), new.len() as u64);
+
+        // 缺少密钥、密钥错误与信封被篡改都不产生输出
+        let err = BsdiffRust::patch_optimized(&path("old"), &path("missing"), &path("patch"), &plain).unwrap_err();
+        assert!(matches!(err, BsdiffError::Encrypted { .. }));
+        let wrong = OptimizationConfig { encryption_key: Some(EncryptionKey::new(&[0xa5; 32]).unwrap()), ..plain.clone() };
+        let err = BsdiffRust::patch_optimized(&path("old"), &path("wrong"), &path("patch"), &wrong).unwrap_err();
+        assert!(matches!(err, BsdiffError::DecryptionFailed { .. }));
+        let mut tampered = patch.clone();
+        tampered[patch.len() / 2] ^= 1;
+        fs::write(path("tampered"), &tampered).unwrap();
+        let err = BsdiffRust::patch_optimized(&path("old"), &path("bad"), &path("tampered"), &config).unwrap_err();
+        assert!(matches!(err, BsdiffError::DecryptionFailed { .. }));
+        for name in ["dry", "missing", "wrong", "bad"] {
+            assert!(!dir.path().join(name).exists(), "{}", name);
+        }
+
+        // 密钥对未加密的补丁没有影响
+        let unencrypted = BsdiffRust::diff_bytes(&old, &new, &plain).unwrap();
+        assert_eq!(BsdiffRust::patch_bytes(&old, &unencrypted, &config).unwrap(), new);
+        let info = crate::utils::get_patch_info(&path("patch")).unwrap();
+        assert_eq!((info.container, info.target_size), ("encrypted", None));
+    }
+
+    #[test]
+    fn test_deterministic_output() {
+        let old: Vec<u8> = (0..300_000u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let mut new = old.clone();
+        new.splice(50_000..50_000, b"inserted block".repeat(100));
+        new[200_000..210_000].fill(7);
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old"), &old).unwrap();
+        fs::write(path("new"), &new).unwrap();
+
+        // 安全模式、线程数与文件/内存路径都不影响输出
+        let config = OptimizationConfig {
+            use_fast_temp_dir: false,
+            deterministic: true,
+            compression_level: 9,
+            window_size: Some(64 * 1024),
+            ..Default::default()
+        };
+        let expected = BsdiffRust::diff_bytes(&old, &new, &config).unwrap();
+        for variant in [
+            OptimizationConfig { safe_mode: true, ..config.clone() },
+            OptimizationConfig { threads: Some(1), ..config.clone() },
+            OptimizationConfig { threads: Some(4), overlap_hashing: false, ..config.clone() },
+        ] {
+            assert_eq!(BsdiffRust::diff_bytes(&old, &new, &variant).unwrap(), expected);
+            BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("patch"), &variant).unwrap();
+            assert_eq!(fs::read(path("patch")).unwrap(), expected);
+        }
+        assert_eq!(BsdiffRust::patch_bytes(&old, &expected, &config).unwrap(), new);
+
+        // 固定的帧参数：不记录内容大小与校验和
+        let mut cursor = io::Cursor::new(&expected);
+        PatchHeader::read_from(&mut cursor).unwrap().unwrap();
+        let frame = &expected[cursor.position() as usize..];
+        assert_eq!(zstd::zstd_safe::get_frame_content_size(frame).ok(), Some(None));
+        assert_eq!(frame[4] & 0x04, 0, "checksum flag set");
+    }
+
+    #[test]
+    fn test_blake3_hash_algorithm() {
+        let old: Vec<u8> = (0..400_000u32).map(|i| (i.wrapping_mul(2654435761) >> 11) as u8).collect();
+        let mut new = old.clone();
+        new[100_000..120_000].fill(3);
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old"), &old).unwrap();
+        fs::write(path("new"), &new).unwrap();
+
+        let config = OptimizationConfig { use_fast_temp_dir: false, hash_algorithm: HashAlgorithm::Blake3, ..Default::default() };
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("patch"), &config).unwrap();
+        let header = BsdiffRust::read_patch_header(&mut File::open(path("patch")).unwrap(), &path("patch")).unwrap().unwrap();
+        assert_eq!(header.hash_algorithm(), HashAlgorithm::Blake3);
+        assert_ne!(header.flags & header::FLAG_HASH_ALGORITHM, 0);
+        assert_eq!(header.source, Some(FileDigest::of_bytes_with(HashAlgorithm::Blake3, &old)));
+        assert_eq!(header.target, Some(FileDigest::of_bytes_with(HashAlgorithm::Blake3, &new)));
+
+        // 应用与校验无需指定算法
+        let patch_config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        BsdiffRust::patch_optimized(&path("old"), &path("out"), &path("patch"), &patch_config).unwrap();
+        assert_eq!(fs::read(path("out")).unwrap(), new);
+        assert!(crate::utils::verify_patch(&path("old"), &path("new"), &path("patch"), &patch_config).unwrap());
+        fs::write(path("other"), &old).unwrap();
+        assert!(!crate::utils::verify_patch(&path("old"), &path("other"), &path("patch"), &patch_config).unwrap());
+        let err = BsdiffRust::patch_optimized(&path("new"), &path("bad"), &path("patch"), &patch_config).unwrap_err();
+        assert!(matches!(err, BsdiffError::SourceMismatch { .. }));
+
+        // 官方测试向量；分批流式计算与一次性计算一致
+        let abc = FileDigest::of_bytes_with(HashAlgorithm::Blake3, b"abc");
+        assert_eq!(abc.hex(), "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85");
+        let large: Vec<u8> = old.iter().copied().cycle().take(9 << 20).collect();
+        let mut hasher = Hasher::new(HashAlgorithm::Blake3);
+        for part in large.chunks(64 * 1024 + 1) {
+            hasher.update(part);
+        }
+        assert_eq!(hasher.finalize(), FileDigest::of_bytes_with(HashAlgorithm::Blake3, &large).hash);
+
+        // 源与目标摘要算法必须一致；标志位与算法字段不一致的头部被拒绝
+        let mixed = PatchHeader { source: Some(FileDigest::of_bytes(&old)), ..header.clone() };
+        assert!(mixed.write_to(&mut Vec::new()).is_err());
+        let mut bytes = Vec::new();
+        header.write_to(&mut bytes).unwrap();
+        let flags_at = MAGIC.len() + 2;
+        assert_eq!(bytes[flags_at], header.flags);
+        bytes[flags_at] &= !header::FLAG_HASH_ALGORITHM;
+        assert!(PatchHeader::read_from(&mut io::Cursor::new(&bytes)).is_err());
+    }
+
+    #[test]
+    fn test_diff_range() {
+        // 磁盘镜像：分区前后的内容与分区偏移都可能变化
+        let old_part: Vec<u8> = (0..100_000u32).map(|i| (i * 31 % 251) as u8).collect();
+        let mut new_part = old_part.clone();
+        new_part[40_000..40_100].fill(9);
+        let old_image = [vec![1u8; 4097], old_part.clone(), vec![2u8; 3000]].concat();
+        let new_image = [vec![3u8; 1000], new_part.clone(), vec![4u8; 10]].concat();
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old.img"), &old_image).unwrap();
+        fs::write(path("new.img"), &new_image).unwrap();
+        fs::write(path("old.part"), &old_part).unwrap();
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+
+        BsdiffRust::diff_range(
+            &path("old.img"), (4097, old_part.len() as u64),
+            &path("new.img"), (1000, new_part.len() as u64),
+            &path("patch"), &config
+        ).unwrap();
+        BsdiffRust::patch_optimized(&path("old.part"), &path("new.part"), &path("patch"), &config).unwrap();
+        assert_eq!(fs::read(path("new.part")).unwrap(), new_part);
+
+        // 空区间，以及超出文件末尾或溢出的区间
+        BsdiffRust::diff_range(&path("old.img"), (0, 0), &path("new.img"), (5, 0), &path("empty"), &config).unwrap();
+        assert!(BsdiffRust::patch_bytes(b"", &fs::read(path("empty")).unwrap(), &config).unwrap().is_empty());
+        let end = old_image.len() as u64;
+        for range in [(end - 10, 11), (end + 1, 0), (u64::MAX, 2)] {
+            let err = BsdiffRust::diff_range(&path("old.img"), range, &path("new.img"), (0, 10), &path("bad"), &config).unwrap_err();
+            assert!(err.to_string().contains("exceeds"), "{}", err);
+        }
+        assert!(!Path::new(&path("bad")).exists());
+    }
+
+    #[test]
+    fn test_auto_compression_level() {
+        let old: Vec<u8> = (0..1_500_000u32).map(|i| (i * 7 % 253) as u8).collect();
+        let mut new = old.clone();
+        new[700_000..700_500].fill(1);
+        for (old, new) in [(&old[..], &new[..]), (&old[..1000], &new[..900])] {
+            let auto = AutoLevel::new();
+            let config = OptimizationConfig { use_fast_temp_dir: false, auto_level: Some(auto.clone()), ..Default::default() };
+            let patch = BsdiffRust::diff_bytes(old, new, &config).unwrap();
+            assert!(auto_level::CANDIDATE_LEVELS.contains(&auto.chosen().unwrap()));
+            assert_eq!(BsdiffRust::patch_bytes(old, &patch, &config).unwrap(), new);
+        }
+
+        // 安全模式限制选定的级别，可复现模式不做选择
+        let auto = AutoLevel::new();
+        let config = OptimizationConfig { safe_mode: true, auto_level: Some(auto.clone()), ..Default::default() };
+        BsdiffRust::diff_bytes(&old[..1000], &new[..900], &config).unwrap();
+        assert!(auto.chosen().unwrap() <= SAFE_MODE_MAX_LEVEL);
+        let auto = AutoLevel::new();
+        let config = OptimizationConfig { deterministic: true, auto_level: Some(auto.clone()), ..Default::default() };
+        BsdiffRust::diff_bytes(&old[..1000], &new[..900], &config).unwrap();
+        assert_eq!(auto.chosen(), None);
+    }
+
+    #[test]
+    fn test_sparse_output() {
+        let old: Vec<u8> = (0..20_000u32).map(|i| (i * 13 % 251) as u8).collect();
+        let new = [old[..5000].to_vec(), vec![0u8; 2 << 20], old[7000..7100].to_vec(), vec![0u8; (1 << 20) + 17]].concat();
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old"), &old).unwrap();
+        fs::write(path("new"), &new).unwrap();
+        let config = OptimizationConfig { sparse: true, mmap_output: true, ..Default::default() };
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("patch"), &config).unwrap();
+
+        assert_eq!(BsdiffRust::patch_optimized(&path("old"), &path("out"), &path("patch"), &config).unwrap(), new.len() as u64);
+        assert_eq!(fs::read(path("out")).unwrap(), new);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let allocated = fs::metadata(path("out")).unwrap().blocks() * 512;
+            assert!(allocated < 1 << 20, "{} bytes allocated", allocated);
+        }
+
+        // 补零到固定区域大小同样以空洞写出
+        let region = new.len() as u64 + 10_000;
+        BsdiffRust::patch_to_fixed_region(&path("old"), &path("region"), &path("patch"), region, true, &config).unwrap();
+        let padded = fs::read(path("region")).unwrap();
+        assert_eq!(padded.len() as u64, region);
+        assert_eq!(&padded[..new.len()], &new[..]);
+        assert!(padded[new.len()..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn test_dictionary_patch() {
+        let old = b"{\"sprite\":\"hero\",\"frames\":[0,1,2,3],\"speed\":1.0}".repeat(4);
+        let new = b"{\"sprite\":\"hero\",\"frames\":[0,1,2,3,4,5],\"speed\":1.5}".repeat(4);
+        let dictionary = Dictionary::new(&BsdiffRust::diff_bytes(&old, &new, &OptimizationConfig {
+            compression: Compression::None,
+            ..Default::default()
+        }).unwrap()).unwrap();
+        let plain = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let config = OptimizationConfig { dictionary: Some(dictionary.clone()), ..plain.clone() };
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old"), &old).unwrap();
+        fs::write(path("new"), &new).unwrap();
+
+        // 字典 ID 与标志位写入补丁头，文件与内存两条路径结果相同，字典使补丁更小
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("patch"), &config).unwrap();
+        let patch = fs::read(path("patch")).unwrap();
+        assert_eq!(BsdiffRust::diff_bytes(&old, &new, &config).unwrap(), patch);
+        let header = PatchHeader::read_from(&mut io::Cursor::new(&patch)).unwrap().unwrap();
+        assert_eq!(header.dictionary_id, Some(dictionary.id()));
+        assert_eq!(header.flags & header::FLAG_DICTIONARY, header::FLAG_DICTIONARY);
+        assert!(patch.len() < BsdiffRust::diff_bytes(&old, &new, &plain).unwrap().len());
+        assert_eq!(BsdiffRust::patch_bytes(&old, &patch, &config).unwrap(), new);
+        BsdiffRust::patch_optimized(&path("old"), &path("out"), &path("patch"), &config).unwrap();
+        assert_eq!(fs::read(path("out")).unwrap(), new);
+        let stats = crate::utils::get_diff_stats(&path("patch"), Some(&dictionary)).unwrap();
+        assert_eq!(stats.copied_bytes + stats.literal_bytes, new.len() as u64);
+        assert_eq!(crate::utils::get_patch_info(&path("patch")).unwrap().dictionary_id, Some(dictionary.id()));
+
+        // 缺少字典或字典不同时不产生输出
+        let err = BsdiffRust::patch_optimized(&path("old"), &path("missing"), &path("patch"), &plain).unwrap_err();
+        assert!(matches!(err, BsdiffError::DictionaryMismatch { actual: None, .. }));
+        let wrong = OptimizationConfig { dictionary: Some(Dictionary::new(b"other dictionary").unwrap()), ..plain.clone() };
+        let err = BsdiffRust::patch_optimized(&path("old"), &path("wrong"), &path("patch"), &wrong).unwrap_err();
+        assert!(matches!(err, BsdiffError::DictionaryMismatch { actual: Some(_), .. }));
+        assert!(!dir.path().join("missing").exists() && !dir.path().join("wrong").exists());
+
+        // 字典对普通补丁没有影响，且只适用于 zstd 格式与压缩
+        let unencoded = BsdiffRust::diff_bytes(&old, &new, &plain).unwrap();
+        assert_eq!(BsdiffRust::patch_bytes(&old, &unencoded, &config).unwrap(), new);
+        assert!(BsdiffRust::diff_bytes(&old, &new, &OptimizationConfig { compression: Compression::None, ..config.clone() }).is_err());
+        assert!(BsdiffRust::diff_bytes(&old, &new, &OptimizationConfig { format: PatchFormat::Bsdiff40, ..config.clone() }).is_err());
+    }
+
+    #[test]
+    fn test_exe_transform() {
+        // 代码段前部是被调用的函数，后部是大量 call/jmp；新版本零散插入指令，之后的位移各不相同
+        let code = |patched: bool| {
+            let mut text = vec![0xC3u8; 256];
+            for i in 0..4000u32 {
+                let hash = i.wrapping_mul(2654435761);
+                if patched && hash >> 28 == 0 {
+                    text.extend_from_slice(&[0x0F, 0x1F, 0x00]);
+                }
+                let at = text.len() as i64;
+                let target = (hash >> 24) as i64;
+                text.push(if i % 3 == 0 { 0xE9 } else { 0xE8 });
+                text.extend_from_slice(&((target - (at + 5)) as i32).to_le_bytes());
+                text.extend_from_slice(&[0x48, 0x89, 0xC7]);
+            }
+            crate::exe_transform::test_elf(&text)
+        };
+        let (old, new) = (code(false), code(true));
+        let plain = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let config = OptimizationConfig { exe_transform: true, ..plain.clone() };
+
+        let patch = BsdiffRust::diff_bytes(&old, &new, &config).unwrap();
+        let header = PatchHeader::read_from(&mut io::Cursor::new(&patch)).unwrap().unwrap();
+        assert!(header.exe_transform.is_some());
+        assert_eq!(BsdiffRust::patch_bytes(&old, &patch, &plain).unwrap(), new);
+        assert!(patch.len() * 4 < BsdiffRust::diff_bytes(&old, &new, &plain).unwrap().len());
+
+        // 文件路径与非可执行输入
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old"), &old).unwrap();
+        fs::write(path("new"), &new).unwrap();
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("patch"), &config).unwrap();
+        BsdiffRust::patch_optimized(&path("old"), &path("out"), &path("patch"), &plain).unwrap();
+        assert_eq!(fs::read(path("out")).unwrap(), new);
+        let patch = BsdiffRust::diff_bytes(b"plain old data", b"plain new data", &config).unwrap();
+        assert!(PatchHeader::read_from(&mut io::Cursor::new(&patch)).unwrap().unwrap().exe_transform.is_none());
+
+        let bsdiff40 = OptimizationConfig { format: PatchFormat::Bsdiff40, ..config };
+        assert!(BsdiffRust::diff_bytes(&old, &new, &bsdiff40).is_err());
+    }
+
+    #[test]
+    fn test_exe_transform_real_binary() {
+        // gcc 编译的真实 ELF (生成方法见 src/testdata/exe/gen.py)：新版本在代码段前部插入一个函数
+        let old = include_bytes!("testdata/exe/old.elf");
+        let new = include_bytes!("testdata/exe/new.elf");
+        let plain = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let config = OptimizationConfig { exe_transform: true, ..plain.clone() };
+
+        let patch = BsdiffRust::diff_bytes(old, new, &config).unwrap();
+        let header = PatchHeader::read_from(&mut io::Cursor::new(&patch)).unwrap().unwrap();
+        assert!(header.exe_transform.is_some());
+        assert_eq!(BsdiffRust::patch_bytes(old, &patch, &plain).unwrap(), new);
+        let untransformed = BsdiffRust::diff_bytes(old, new, &plain).unwrap();
+        assert!(patch.len() * 100 < untransformed.len() * 95, "{} vs {}", patch.len(), untransformed.len());
+
+        // 代码整体后移、相对偏移不变：变换后的绝对目标全部改变，保留直接 diff 的补丁
+        let code = |shift: usize| {
+            let mut text = vec![0x90u8; shift];
+            for i in 0..2000u32 {
+                text.push(0xE8);
+                text.extend_from_slice(&((i.wrapping_mul(2654435761) >> 20) as i32 - 2048).to_le_bytes());
+                text.extend_from_slice(&[0x48, 0x89, 0xC7]);
+            }
+            crate::exe_transform::test_elf(&text)
+        };
+        let (old, new) = (code(0), code(64));
+        let patch = BsdiffRust::diff_bytes(&old, &new, &config).unwrap();
+        let header = PatchHeader::read_from(&mut io::Cursor::new(&patch)).unwrap().unwrap();
+        assert!(header.exe_transform.is_none());
+        assert_eq!(patch, BsdiffRust::diff_bytes(&old, &new, &plain).unwrap());
+        assert_eq!(BsdiffRust::patch_bytes(&old, &patch, &plain).unwrap(), new);
+    }
+
+    #[test]
+    #[cfg(feature = "zlib")]
+    fn test_archive_transform() {
+        // 新版本改动每个条目中的少量行：压缩后整个条目都不同，展开后只有零散差异
+        let source = |version: usize, file: usize| -> Vec<u8> {
+            (0..3000).flat_map(|line| {
+                let value = if line % 97 == file { version } else { 0 };
+                format!("export const v{}_{} = {};\n", file, line, value).into_bytes()
+            }).collect()
+        };
+        let archive = |version: usize| {
+            let files: Vec<_> = (0..8).map(|file| (format!("src/{}.js", file), source(version, file))).collect();
+            let entries: Vec<_> = files.iter().map(|(name, data)| (name.as_str(), &data[..], Some(6))).collect();
+            crate::archive_transform::test_zip(&entries)
+        };
+        let (old, new) = (archive(1), archive(2));
+        let plain = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let config = OptimizationConfig { archive_transform: true, ..plain.clone() };
+
+        let patch = BsdiffRust::diff_bytes(&old, &new, &config).unwrap();
+        let header = PatchHeader::read_from(&mut io::Cursor::new(&patch)).unwrap().unwrap();
+        assert_eq!(header.archive_transform.map(|transform| transform.entries), Some(8));
+        assert_eq!(header.flags & header::FLAG_ARCHIVE_TRANSFORM, header::FLAG_ARCHIVE_TRANSFORM);
+        assert_eq!(BsdiffRust::patch_bytes(&old, &patch, &plain).unwrap(), new);
+        assert!(patch.len() * 4 < BsdiffRust::diff_bytes(&old, &new, &plain).unwrap().len());
+        // 旧文件不同则展开结果不同，由源文件摘要拦截
+        assert!(BsdiffRust::patch_bytes(&new, &patch, &plain).is_err());
+
+        // 文件路径、试运行与非 ZIP 输入
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old"), &old).unwrap();
+        fs::write(path("new"), &new).unwrap();
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("patch"), &config).unwrap();
+        BsdiffRust::patch_optimized(&path("old"), &path("out"), &path("patch"), &plain).unwrap();
+        assert_eq!(fs::read(path("out")).unwrap(), new);
+        let dry_run = OptimizationConfig { dry_run: true, ..plain.clone() };
+        BsdiffRust::patch_optimized(&path("old"), &path("dry"), &path("patch"), &dry_run).unwrap();
+        assert!(!path("dry").exists());
+        let patch = BsdiffRust::diff_bytes(b"plain old data", b"plain new data", &config).unwrap();
+        assert!(PatchHeader::read_from(&mut io::Cursor::new(&patch)).unwrap().unwrap().archive_transform.is_none());
+
+        let bsdiff40 = OptimizationConfig { format: PatchFormat::Bsdiff40, ..config };
+        assert!(BsdiffRust::diff_bytes(&old, &new, &bsdiff40).is_err());
+    }
+
+    #[test]
+    fn test_patch_chain() {
+        let versions: Vec<Vec<u8>> = (0..4u8)
+            .map(|v| format!("release {} of the app ", v).repeat(60 + v as usize * 5).into_bytes())
+            .collect();
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: String| dir.path().join(name);
+        fs::write(path("v0".into()), &versions[0]).unwrap();
+        let patches: Vec<PathBuf> = (0..3)
+            .map(|v| {
+                let patch = path(format!("p{}", v));
+                fs::write(&patch, BsdiffRust::diff_bytes(&versions[v], &versions[v + 1], &config).unwrap()).unwrap();
+                patch
+            })
+            .collect();
+
+        let written = BsdiffRust::patch_chain(&path("v0".into()), &patches, &path("out".into()), &config).unwrap();
+        assert_eq!(written, versions[3].len() as u64);
+        assert_eq!(fs::read(path("out".into())).unwrap(), versions[3]);
+
+        // 跳过中间补丁：下一步的源文件校验失败，不产生输出
+        let skipped = [patches[0].clone(), patches[2].clone()];
+        let err = BsdiffRust::patch_chain(&path("v0".into()), &skipped, &path("bad".into()), &config).unwrap_err();
+        assert!(matches!(err, BsdiffError::BaseMismatch { .. }));
+        assert!(!dir.path().join("bad").exists());
+        assert!(BsdiffRust::patch_chain(&path("v0".into()), &[], &path("bad".into()), &config).is_err());
+
+    }
+
+    #[test]
+    fn test_target_digest_checked() {
+        let old = b"target digest base ".repeat(200);
+        let mut new = old.clone();
+        new[30..90].fill(b'#');
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old"), &old).unwrap();
+
+        // 默认 SHA-256 与 BLAKE3 补丁在应用时都校验输出与目标摘要，不符时不产生输出
+        for algorithm in [HashAlgorithm::Sha256, HashAlgorithm::Blake3] {
+            let config = OptimizationConfig { use_fast_temp_dir: false, hash_algorithm: algorithm, ..Default::default() };
+            let mut patch = BsdiffRust::diff_bytes(&old, &new, &config).unwrap();
+            let hash = FileDigest::of_bytes_with(algorithm, &new).hash;
+            let at = patch.windows(hash.len()).position(|w| w == hash).unwrap();
+            patch[at] ^= 0xFF;
+            fs::write(path("patch"), &patch).unwrap();
+            let err = BsdiffRust::patch_optimized(&path("old"), &path("new"), &path("patch"), &config).unwrap_err();
+            assert!(matches!(err, BsdiffError::TargetMismatch { .. }), "{:?}: {}", algorithm, err);
+            assert!(!path("new").exists());
+            let err = BsdiffRust::patch_bytes(&old, &patch, &config).unwrap_err();
+            assert!(matches!(err, BsdiffError::TargetMismatch { .. }), "{:?}: {}", algorithm, err);
+        }
+    }
+
+    #[test]
+    fn test_patch_in_place() {
+        let old = b"running binary v1 ".repeat(50);
+        let new = b"running binary v2!".repeat(55);
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let patch = BsdiffRust::diff_bytes(&old, &new, &config).unwrap();
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let target = dir.path().join("app.bin");
+        let patch_file = dir.path().join("app.patch");
+        fs::write(&target, &old).unwrap();
+        fs::write(&patch_file, &patch).unwrap();
+        #[cfg(unix)]
+        fs::set_permissions(&target, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+
+        let written = BsdiffRust::patch_in_place(&target, &patch_file, &config).unwrap();
+        assert_eq!(written, new.len() as u64);
+        assert_eq!(fs::read(&target).unwrap(), new);
+        #[cfg(unix)]
+        assert_eq!(std::os::unix::fs::PermissionsExt::mode(&fs::metadata(&target).unwrap().permissions()) & 0o777, 0o755);
+        let entries = || fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(entries(), 2);
+
+        // 再次应用时源文件校验失败：原文件保持不变，不留下临时文件或备份
+        assert!(BsdiffRust::patch_in_place(&target, &patch_file, &config).is_err());
+        assert_eq!(fs::read(&target).unwrap(), new);
+        assert_eq!(entries(), 2);
+
+        // 损坏的补丁同样不影响原文件
+        fs::write(&target, &old).unwrap();
+        let mut corrupt = patch.clone();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0xFF;
+        fs::write(&patch_file, &corrupt).unwrap();
+        assert!(BsdiffRust::patch_in_place(&target, &patch_file, &config).is_err());
+        assert_eq!(fs::read(&target).unwrap(), old);
+        assert_eq!(entries(), 2);
+
+        // 同目录临时名每次都不同：同一进程内并发的原地更新不会共用临时文件
+        let sibling = || sibling_temp_path(&target, "bsdiff-tmp").unwrap();
+        assert_ne!(sibling(), sibling());
+        assert_eq!(sibling().parent(), Some(dir.path()));
+    }
+
+    #[test]
+    fn test_patch_to_fixed_region() {
+        let old = b"flash partition old image ".repeat(30);
+        let new = b"flash partition NEW image!".repeat(32);
         let old_file = NamedTempFile::new().unwrap();
         let new_file = NamedTempFile::new().unwrap();
         let patch_file = NamedTempFile::new().unwrap();
-        
-        fs::write(&old_file, old_content).unwrap();
-        fs::write(&new_file, new_content).unwrap();
-        
-        // 测试最优配置
+        fs::write(&old_file, &old).unwrap();
+        fs::write(&new_file, &new).unwrap();
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
         BsdiffRust::diff_optimized(
-            old_file.path().to_str().unwrap(),
-            new_file.path().to_str().unwrap(),
-            patch_file.path().to_str().unwrap(),
-            &OptimizationConfig::default()
+            old_file.path(),
+            new_file.path(),
+            patch_file.path(),
+            &config
         ).unwrap();
-        
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let out = dir.path().join("region.bin");
+        let region = |patch: &Path, region_size: u64, zero_pad: bool| {
+            BsdiffRust::patch_to_fixed_region(old_file.path(), &out, patch, region_size, zero_pad, &config)
+        };
+        let exceeds = |result: Result<u64, BsdiffError>| {
+            matches!(result.unwrap_err(), BsdiffError::TargetExceedsRegion { .. })
+        };
+
+        // 目标超出分区：写入前拒绝，不创建输出
+        assert!(exceeds(region(patch_file.path(), new.len() as u64 - 1, false)));
+        assert!(!out.exists());
+
+        assert_eq!(region(patch_file.path(), 4096, false).unwrap(), new.len() as u64);
+        assert_eq!(fs::read(&out).unwrap(), new);
+        assert_eq!(region(patch_file.path(), 4096, true).unwrap(), 4096);
+        let padded = fs::read(&out).unwrap();
+        assert_eq!(&padded[..new.len()], &new[..]);
+        assert!(padded[new.len()..].iter().all(|b| *b == 0));
+
+        // 无补丁头的旧版补丁通过扫描控制块确定大小
+        let mut raw = Vec::new();
+        bsdiff::diff(&old, &new, &mut raw).unwrap();
+        let legacy = NamedTempFile::new().unwrap();
+        fs::write(&legacy, zstd::encode_all(&raw[..], 3).unwrap()).unwrap();
+        fs::remove_file(&out).unwrap();
+        assert!(exceeds(region(legacy.path(), 100, false)));
+        assert!(!out.exists());
+        assert_eq!(region(legacy.path(), new.len() as u64, false).unwrap(), new.len() as u64);
+    }
+
+    #[test]
+    fn test_block_progress() {
+        use std::sync::{Arc, Mutex};
+
+        let old_content: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        // 块重排并插入无关数据，生成多个控制块
+        let mut new_content = old_content[60_000..].to_vec();
+        new_content.extend((0..3000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8));
+        new_content.extend_from_slice(&old_content[..60_000]);
+
+        let old_file = NamedTempFile::new().unwrap();
+        let new_file = NamedTempFile::new().unwrap();
+        let patch_file = NamedTempFile::new().unwrap();
+        fs::write(&old_file, &old_content).unwrap();
+        fs::write(&new_file, &new_content).unwrap();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let config = OptimizationConfig {
+            use_fast_temp_dir: false,
+            block_progress: Some(Arc::new(move |applied, total| sink.lock().unwrap().push((applied, total)))),
+            ..Default::default()
+        };
+        BsdiffRust::diff_optimized(
+            old_file.path(),
+            new_file.path(),
+            patch_file.path(),
+            &config
+        ).unwrap();
+
+        // 补丁头记录的控制块总数与原始流一致
+        let mut raw = Vec::new();
+        bsdiff::diff(&old_content, &new_content, &mut raw).unwrap();
+        let mut count = 0u64;
+        let mut cursor = io::Cursor::new(&raw);
+        while let Some(entry) = ControlEntry::read_from(&mut cursor).unwrap() {
+            count += 1;
+            cursor.seek(SeekFrom::Current((entry.mix_len + entry.copy_len) as i64)).unwrap();
+        }
+        let (header, _) = BsdiffRust::open_patch(patch_file.path(), None).unwrap();
+        let total = header.unwrap().control_blocks.unwrap();
+        assert!(total > 1);
+        assert_eq!(total, count);
+
         let generated_file = NamedTempFile::new().unwrap();
         BsdiffRust::patch_optimized(
-            old_file.path().to_str().unwrap(),
-            generated_file.path().to_str().unwrap(),
-            patch_file.path().to_str().unwrap(),
-            &OptimizationConfig::default()
+            old_file.path(),
+            generated_file.path(),
+            patch_file.path(),
+            &config
         ).unwrap();
-        
-        let generated_content = fs::read(generated_file.path()).unwrap();
-        assert_eq!(generated_content, new_content);
+        assert_eq!(fs::read(generated_file.path()).unwrap(), new_content);
+        let block_events = events.lock().unwrap().clone();
+        assert!(block_events.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(block_events.iter().all(|event| event.1 == total));
+        assert_eq!(block_events.last().copied(), Some((total, total)));
+
+        // 控制块数与补丁头不一致视为补丁损坏
+        let header = PatchHeader { control_blocks: Some(total + 1), ..PatchHeader::default() };
+        let mut patch = Vec::new();
+        header.write_to(&mut patch).unwrap();
+        patch.extend_from_slice(&zstd::encode_all(&raw[..], 3).unwrap());
+        let result = BsdiffRust::patch_from_reader(&old_content, io::Cursor::new(&patch), patch.len() as u64, Path::new("crafted"), &config);
+        assert!(matches!(result.unwrap_err(), BsdiffError::CorruptPatch { .. }));
     }
 
+    #[test]
+    fn test_arguments_likely_swapped() {
+        let old_file = NamedTempFile::new().unwrap();
+        let patch_file = NamedTempFile::new().unwrap();
+        fs::write(&old_file, b"\x7fELF\x02\x01\x01 fake executable").unwrap();
+        fs::write(&patch_file, b"\x89PNG\r\n\x1a\n fake image").unwrap();
+
+        let generated_file = NamedTempFile::new().unwrap();
+        let err = BsdiffRust::patch_optimized(
+            old_file.path(),
+            generated_file.path(),
+            patch_file.path(),
+            &OptimizationConfig { use_fast_temp_dir: false, ..Default::default() }
+        ).unwrap_err();
+        assert!(matches!(err, BsdiffError::ArgumentsLikelySwapped { detected: "a PNG image", .. }
+        ));
 
+        // 无法识别的格式不做拦截
+        fs::write(&patch_file, b"plain text").unwrap();
+        assert_eq!(BsdiffRust::sniff_file(patch_file.path()).unwrap(), SniffedKind::Unknown);
+        assert!(BsdiffRust::check_arguments_order(
+            old_file.path(),
+            patch_file.path()
+        ).is_ok());
+    }
 
     #[test]
-    fn test_default_methods() {
-        let old_content = b"Test content for default methods.";
-        let new_content = b"Test content for default methods with changes.";
-        
+    fn test_bidirectional_diff() {
+        let a_content = b"Release A: the quick brown fox jumps over the lazy dog.".repeat(20);
+        let b_content = b"Release B: the quick red fox leaps over the lazy dog!!".repeat(21);
+
+        let a_file = NamedTempFile::new().unwrap();
+        let b_file = NamedTempFile::new().unwrap();
+        let forward_patch = NamedTempFile::new().unwrap();
+        let reverse_patch = NamedTempFile::new().unwrap();
+        fs::write(&a_file, &a_content).unwrap();
+        fs::write(&b_file, &b_content).unwrap();
+
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        BsdiffRust::diff_bidirectional(
+            a_file.path(),
+            b_file.path(),
+            forward_patch.path(),
+            reverse_patch.path(),
+            &config
+        ).unwrap();
+
+        // 正向与反向补丁的源/目标摘要互换
+        let (forward_header, _) = BsdiffRust::open_patch(forward_patch.path(), None).unwrap();
+        let (reverse_header, _) = BsdiffRust::open_patch(reverse_patch.path(), None).unwrap();
+        let (forward_header, reverse_header) = (forward_header.unwrap(), reverse_header.unwrap());
+        assert_eq!(forward_header.source, Some(FileDigest::of_bytes(&a_content)));
+        assert_eq!(forward_header.target, reverse_header.source);
+        assert_eq!(forward_header.source, reverse_header.target);
+
+        let generated_b = NamedTempFile::new().unwrap();
+        BsdiffRust::patch_optimized(
+            a_file.path(),
+            generated_b.path(),
+            forward_patch.path(),
+            &config
+        ).unwrap();
+        assert_eq!(fs::read(generated_b.path()).unwrap(), b_content);
+
+        let generated_a = NamedTempFile::new().unwrap();
+        BsdiffRust::patch_optimized(
+            b_file.path(),
+            generated_a.path(),
+            reverse_patch.path(),
+            &config
+        ).unwrap();
+        assert_eq!(fs::read(generated_a.path()).unwrap(), a_content);
+    }
+    #[test]
+    fn test_safe_mode_and_retry() {
+        let old_content = b"safe mode old content ".repeat(50);
+        let new_content = b"safe mode new content!".repeat(55);
         let old_file = NamedTempFile::new().unwrap();
         let new_file = NamedTempFile::new().unwrap();
         let patch_file = NamedTempFile::new().unwrap();
-        
-        fs::write(&old_file, old_content).unwrap();
-        fs::write(&new_file, new_content).unwrap();
-        
-        // 测试默认方法 (内部使用最优配置)
-        BsdiffRust::diff(
-            old_file.path().to_str().unwrap(),
-            new_file.path().to_str().unwrap(),
-            patch_file.path().to_str().unwrap()
+        fs::write(&old_file, &old_content).unwrap();
+        fs::write(&new_file, &new_content).unwrap();
+
+        // 安全模式生成的补丁可被正常模式应用
+        let safe = OptimizationConfig { use_fast_temp_dir: false, compression_level: 19, safe_mode: true, ..Default::default() };
+        BsdiffRust::diff_optimized(
+            old_file.path(),
+            new_file.path(),
+            patch_file.path(),
+            &safe
         ).unwrap();
-        
         let generated_file = NamedTempFile::new().unwrap();
-        BsdiffRust::patch(
-            old_file.path().to_str().unwrap(),
-            generated_file.path().to_str().unwrap(),
-            patch_file.path().to_str().unwrap()
+        BsdiffRust::patch_optimized(
+            old_file.path(),
+            generated_file.path(),
+            patch_file.path(),
+            &OptimizationConfig { use_fast_temp_dir: false, safe_mode: false, ..Default::default() }
         ).unwrap();
-        
-        let generated_content = fs::read(generated_file.path()).unwrap();
-        assert_eq!(generated_content, new_content);
+        assert_eq!(fs::read(generated_file.path()).unwrap(), new_content);
+
+        // 仅 zstd 内部错误触发一次安全模式重试
+        let normal = OptimizationConfig { safe_mode: false, ..Default::default() };
+        let mut attempts = Vec::new();
+        let result = BsdiffRust::with_safe_mode_retry(&normal, "test", |config| {
+            attempts.push(config.safe_mode);
+            if config.safe_mode {
+                Ok(())
+            } else {
+                Err(io::Error::other("Allocation error : not enough memory").into())
+            }
+        });
+        assert!(result.is_ok());
+        assert_eq!(attempts, vec![false, true]);
+
+        attempts.clear();
+        let result: Result<(), _> = BsdiffRust::with_safe_mode_retry(&normal, "test", |config| {
+            attempts.push(config.safe_mode);
+            Err(io::Error::other("Data corruption detected").into())
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, vec![false]);
+    }
+
+    #[test]
+    fn test_recompress_patch() {
+        let old_content = b"recompress me: lorem ipsum dolor sit amet ".repeat(40);
+        let new_content = b"recompress me: lorem ipsum dolor sit AMET!".repeat(45);
+        let old_file = NamedTempFile::new().unwrap();
+        let new_file = NamedTempFile::new().unwrap();
+        let patch_file = NamedTempFile::new().unwrap();
+        let recompressed = NamedTempFile::new().unwrap();
+        fs::write(&old_file, &old_content).unwrap();
+        fs::write(&new_file, &new_content).unwrap();
+
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        BsdiffRust::diff_optimized(
+            old_file.path(),
+            new_file.path(),
+            patch_file.path(),
+            &config
+        ).unwrap();
+        BsdiffRust::recompress_patch(
+            patch_file.path(),
+            recompressed.path(),
+            &OptimizationConfig { compression_level: 19, ..config.clone() }
+        ).unwrap();
+
+        // 头部字节与原始 bsdiff 流不变，仅压缩层改变
+        let original_patch = fs::read(patch_file.path()).unwrap();
+        let recompressed_patch = fs::read(recompressed.path()).unwrap();
+        let mut cursor = io::Cursor::new(&original_patch);
+        assert!(PatchHeader::read_from(&mut cursor).unwrap().is_some());
+        let header_len = cursor.position() as usize;
+        assert_eq!(&recompressed_patch[..header_len], &original_patch[..header_len]);
+        assert_eq!(
+            BsdiffRust::raw_stream_digest(Compression::Zstd, None, &recompressed_patch[header_len..recompressed_patch.len() - PAYLOAD_TRAILER_LEN]).unwrap(),
+            BsdiffRust::raw_stream_digest(Compression::Zstd, None, &original_patch[header_len..original_patch.len() - PAYLOAD_TRAILER_LEN]).unwrap()
+        );
+
+        let generated_file = NamedTempFile::new().unwrap();
+        BsdiffRust::patch_optimized(
+            old_file.path(),
+            generated_file.path(),
+            recompressed.path(),
+            &config
+        ).unwrap();
+        assert_eq!(fs::read(generated_file.path()).unwrap(), new_content);
+    }
+
+    #[test]
+    fn test_overlap_hashing_matches_serial() {
+        let old_content: Vec<u8> = (0..50_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut new_content = old_content.clone();
+        new_content[1000..1100].fill(0xAA);
+        new_content.extend_from_slice(b"tail");
+        let old_file = NamedTempFile::new().unwrap();
+        let new_file = NamedTempFile::new().unwrap();
+        fs::write(&old_file, &old_content).unwrap();
+        fs::write(&new_file, &new_content).unwrap();
+
+        let write = |overlap_hashing: bool| {
+            let config = OptimizationConfig { overlap_hashing, ..Default::default() };
+            BsdiffRust::diff_to_writer(
+                old_file.path(),
+                new_file.path(),
+                Vec::new(),
+                &config
+            ).unwrap()
+        };
+        let overlapped = write(true);
+        assert_eq!(overlapped, write(false));
+
+        let header = PatchHeader::read_from(&mut io::Cursor::new(&overlapped)).unwrap();
+        assert_eq!(header.unwrap().target, Some(FileDigest::of_bytes(&new_content)));
+    }
+
+    #[test]
+    fn test_patch_fanout() {
+        let old_content = b"fan-out base content ".repeat(60);
+        let old_file = NamedTempFile::new().unwrap();
+        fs::write(&old_file, &old_content).unwrap();
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+
+        let mut jobs = Vec::new();
+        let mut expected = Vec::new();
+        let mut keep = Vec::new();
+        for i in 0..4 {
+            let new_content = format!("fan-out base content #{} ", i).repeat(60 + i).into_bytes();
+            let new_file = NamedTempFile::new().unwrap();
+            let patch_file = NamedTempFile::new().unwrap();
+            let out_file = NamedTempFile::new().unwrap();
+            fs::write(&new_file, &new_content).unwrap();
+            BsdiffRust::diff_optimized(
+                old_file.path(),
+                new_file.path(),
+                patch_file.path(),
+                &config
+            ).unwrap();
+            jobs.push((patch_file.path().to_path_buf(), out_file.path().to_path_buf()));
+            expected.push(new_content);
+            keep.push((new_file, patch_file, out_file));
+        }
+
+        BsdiffRust::patch_fanout(old_file.path(), &jobs, Some(2), &config).unwrap();
+        for ((_, out), content) in jobs.iter().zip(&expected) {
+            assert_eq!(&fs::read(out).unwrap(), content);
+        }
+
+        // 缺失的补丁在开始前即报错
+        jobs.push(("/nonexistent/patch.bin".into(), "/nonexistent/out".into()));
+        assert!(BsdiffRust::patch_fanout(old_file.path(), &jobs, None, &config).is_err());
+    }
+
+    #[test]
+    fn test_diff_batch() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let old_content = b"batch diff base content ".repeat(50);
+        let old_path = dir.path().join("old.bin");
+        fs::write(&old_path, &old_content).unwrap();
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+
+        let mut pairs = Vec::new();
+        for i in 0..3 {
+            let new_path = dir.path().join(format!("new{}.bin", i));
+            fs::write(&new_path, format!("batch diff next content #{} ", i).repeat(50 + i)).unwrap();
+            pairs.push((old_path.clone(), new_path, dir.path().join(format!("patch{}.bin", i))));
+        }
+        // 单项失败只影响该项的结果
+        pairs.insert(1, ("/nonexistent/old".into(), pairs[0].1.clone(), dir.path().join("bad.bin")));
+
+        let results = BsdiffRust::diff_batch(&pairs, Some(2), &config).unwrap();
+        assert_eq!(results.len(), pairs.len());
+        assert!(results[1].is_err());
+        for (i, (old, new, patch)) in pairs.iter().enumerate().filter(|(i, _)| *i != 1) {
+            assert_eq!(results[i], Ok(fs::metadata(patch).unwrap().len()));
+            let patched = BsdiffRust::patch_bytes(&fs::read(old).unwrap(), &fs::read(patch).unwrap(), &config).unwrap();
+            assert_eq!(patched, fs::read(new).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_corrupt_patch_is_rejected() {
+        let old = b"hardened decoder old content".repeat(10);
+        let new = b"hardened decoder new content".repeat(11);
+        let digests = PatchHeader::with_digests(FileDigest::of_bytes(&old), FileDigest::of_bytes(&new));
+        let config = OptimizationConfig::default();
+        let patch_bytes = |header: &PatchHeader, raw: &[u8]| {
+            let mut patch = Vec::new();
+            header.write_to(&mut patch).unwrap();
+            patch.extend_from_slice(&zstd::encode_all(raw, 3).unwrap());
+            patch
+        };
+        let apply = |patch: &[u8], config: &OptimizationConfig| {
+            BsdiffRust::patch_from_reader(&old, io::Cursor::new(patch), patch.len() as u64, Path::new("crafted"), config)
+        };
+        let is_corrupt = |result: Result<Vec<u8>, BsdiffError>| {
+            matches!(result.unwrap_err(), BsdiffError::CorruptPatch { .. })
+        };
+
+        let mut raw = Vec::new();
+        bsdiff::diff(&old, &new, &mut raw).unwrap();
+        assert_eq!(apply(&patch_bytes(&digests, &raw), &config).unwrap(), new);
+
+        // 控制块声明超过目标大小的输出 (解压炸弹)
+        let mut bomb = ControlEntry { mix_len: 0, copy_len: 1 << 40, seek: 0 }.to_bytes().to_vec();
+        bomb.extend_from_slice(&[0u8; 1024]);
+        assert!(is_corrupt(apply(&patch_bytes(&digests, &bomb), &config)));
+
+        // 旧版补丁没有目标大小时，受 max_output_size 限制
+        let legacy = zstd::encode_all(&bomb[..], 3).unwrap();
+        let limited = OptimizationConfig { max_output_size: Some(1 << 20), ..Default::default() };
+        let err = apply(&legacy, &limited).unwrap_err();
+        assert!(matches!(err, BsdiffError::LimitExceeded { resource: "output", .. }));
+
+        // 差分块越过旧文件末尾、seek 为负、数据截断、输出短于声明
+        let past_end = ControlEntry { mix_len: old.len() as u64 + 1, copy_len: 0, seek: 0 }.to_bytes();
+        assert!(is_corrupt(apply(&patch_bytes(&PatchHeader::default(), &past_end), &config)));
+        let negative = [ControlEntry { mix_len: 0, copy_len: 0, seek: -1 }.to_bytes(), ControlEntry::default().to_bytes()].concat();
+        assert!(is_corrupt(apply(&patch_bytes(&PatchHeader::default(), &negative), &config)));
+        assert!(is_corrupt(apply(&patch_bytes(&digests, &raw[..raw.len() - 1]), &config)));
+        assert!(is_corrupt(apply(&patch_bytes(&digests, &[]), &config)));
+
+        // 损坏的补丁头
+        let mut bad_header = patch_bytes(&digests, &raw);
+        bad_header[MAGIC.len() + 1..MAGIC.len() + 5].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(is_corrupt(apply(&bad_header, &config)));
+    }
+
+    #[test]
+    fn test_resource_limits() {
+        let old: Vec<u8> = (0..200_000u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let mut new = old.clone();
+        new[50_000..60_000].fill(9);
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old"), &old).unwrap();
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let patch = BsdiffRust::diff_bytes(&old, &new, &config).unwrap();
+        fs::write(path("patch"), &patch).unwrap();
+        let limit_of = |err: BsdiffError| match err {
+            BsdiffError::LimitExceeded { resource, required, .. } => Some((resource, required)),
+            _ => None,
+        };
+
+        // 声明的目标大小超过 maxOutputSize：写出任何数据前拒绝
+        let small_output = OptimizationConfig { max_output_size: Some(1000), ..config.clone() };
+        let err = BsdiffRust::patch_optimized(&path("old"), &path("out"), &path("patch"), &small_output).unwrap_err();
+        assert_eq!(limit_of(err), Some(("output", Some(new.len() as u64))));
+        assert!(!dir.path().join("out").exists() && !dir.path().join("out.partial").exists());
+        let roomy = OptimizationConfig { max_output_size: Some(new.len() as u64), max_memory: Some(64 << 20), ..config.clone() };
+        assert_eq!(BsdiffRust::patch_optimized(&path("old"), &path("out"), &path("patch"), &roomy).unwrap(), new.len() as u64);
+
+        // 补丁头声明 1 PB 的目标：mmap 输出在扩展文件之前拒绝，或在 maxMemory 之内改为流式写出
+        let mut raw = Vec::new();
+        bsdiff::diff(&old, &new, &mut raw).unwrap();
+        let mut huge = Vec::new();
+        PatchHeader::with_digests(FileDigest::of_bytes(&old), FileDigest { size: 1 << 50, ..FileDigest::of_bytes(&new) }).write_to(&mut huge).unwrap();
+        huge.extend_from_slice(&zstd::encode_all(&raw[..], 3).unwrap());
+        fs::write(path("huge"), &huge).unwrap();
+        let mapped = OptimizationConfig { mmap_output: true, max_output_size: Some(1 << 30), ..config.clone() };
+        let err = BsdiffRust::patch_optimized(&path("old"), &path("huge.out"), &path("huge"), &mapped).unwrap_err();
+        assert_eq!(limit_of(err), Some(("output", Some(1 << 50))));
+        assert!(!dir.path().join("huge.out").exists() && !dir.path().join("huge.out.partial").exists());
+        let mapped = OptimizationConfig { mmap_output: true, max_memory: Some(64 << 20), ..config.clone() };
+        let err = BsdiffRust::patch_optimized(&path("old"), &path("huge.out"), &path("huge"), &mapped).unwrap_err();
+        assert!(matches!(err, BsdiffError::CorruptPatch { .. }), "{:?}", err);
+        assert!(!dir.path().join("huge.out").exists());
+
+        // 以指定 zstd 窗口压缩的补丁 (128 KB 窗口小于新文件)
+        let with_window = |window_log: u32| {
+            let mut patch = Vec::new();
+            PatchHeader::with_digests(FileDigest::of_bytes(&old), FileDigest::of_bytes(&new)).write_to(&mut patch).unwrap();
+            let mut encoder = zstd::stream::Encoder::new(&mut patch, 3).unwrap();
+            encoder.window_log(window_log).unwrap();
+            encoder.include_contentsize(false).unwrap();
+            encoder.write_all(&raw).unwrap();
+            encoder.finish().unwrap();
+            patch
+        };
+        let narrow = with_window(17);
+
+        // 输出到内存时结果计入 maxMemory；流式写出时不计入
+        let small_memory = OptimizationConfig { max_memory: Some(150_000), ..config.clone() };
+        assert_eq!(limit_of(BsdiffRust::patch_bytes(&old, &narrow, &small_memory).unwrap_err()), Some(("memory", Some(new.len() as u64))));
+        assert_eq!(BsdiffRust::patch_bytes_to_writer(&old, &narrow, io::sink(), &small_memory).unwrap(), new.len() as u64);
+
+        // zstd 帧的解码窗口超过 maxMemory
+        let err = BsdiffRust::patch_bytes_to_writer(&old, &with_window(24), io::sink(), &small_memory).unwrap_err();
+        assert_eq!(limit_of(err), Some(("memory", None)));
+
+        // 整体读入内存的补丁格式
+        let classic = BsdiffRust::diff_bytes(&old, &new, &OptimizationConfig { format: PatchFormat::Bsdiff40, ..config.clone() }).unwrap();
+        let tiny = OptimizationConfig { max_memory: Some(16), ..config.clone() };
+        let err = BsdiffRust::patch_bytes_to_writer(&old, &classic, io::sink(), &tiny).unwrap_err();
+        assert_eq!(limit_of(err), Some(("memory", Some(classic.len() as u64))));
+    }
+
+    #[test]
+    fn test_arbitrary_patch_bytes_never_panic() {
+        let old = b"fuzz base".repeat(20);
+        let new = b"fuzz next".repeat(21);
+        let mut valid = Vec::new();
+        PatchHeader::with_digests(FileDigest::of_bytes(&old), FileDigest::of_bytes(&new)).write_to(&mut valid).unwrap();
+        let mut raw = Vec::new();
+        bsdiff::diff(&old, &new, &mut raw).unwrap();
+        valid.extend_from_slice(&zstd::encode_all(&raw[..], 3).unwrap());
+
+        let config = OptimizationConfig { max_output_size: Some(1 << 20), ..Default::default() };
+        let mut state = 0x2545_F491u32;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+        for _ in 0..500 {
+            let mut patch = valid.clone();
+            for _ in 0..1 + next() % 4 {
+                let i = next() as usize % patch.len();
+                patch[i] = next() as u8;
+            }
+            patch.truncate(next() as usize % (patch.len() + 1) + patch.len() / 2);
+            let _ = BsdiffRust::patch_from_reader(&old, io::Cursor::new(&patch), patch.len() as u64, Path::new("fuzz"), &config);
+        }
+    }
+
+    #[test]
+    fn test_diff_prepared_matches_diff() {
+        let old_content = b"prepared base shared by many variants ".repeat(30);
+        let old_file = NamedTempFile::new().unwrap();
+        fs::write(&old_file, &old_content).unwrap();
+        let base = PreparedBase::new(old_content.clone());
+        let base_digest = FileDigest::of_bytes(base.old());
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+
+        for i in 0..2 {
+            let new_content = format!("prepared base shared by variant #{} ", i).repeat(31).into_bytes();
+            let new_file = NamedTempFile::new().unwrap();
+            let prepared_patch = NamedTempFile::new().unwrap();
+            let plain_patch = NamedTempFile::new().unwrap();
+            fs::write(&new_file, &new_content).unwrap();
+
+            BsdiffRust::diff_prepared(&base, base_digest, new_file.path(), prepared_patch.path(), &config).unwrap();
+            BsdiffRust::diff_optimized(
+                old_file.path(),
+                new_file.path(),
+                plain_patch.path(),
+                &config
+            ).unwrap();
+            assert_eq!(fs::read(prepared_patch.path()).unwrap(), fs::read(plain_patch.path()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_overwrite_policy() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        let old = b"overwrite policy base content ".repeat(40);
+        let new = b"overwrite policy next content!".repeat(41);
+        fs::write(path("old"), &old).unwrap();
+        fs::write(path("new"), &new).unwrap();
+        fs::write(path("patch"), b"existing patch").unwrap();
+        fs::write(path("out"), b"existing output").unwrap();
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+
+        // error：已存在的输出保持原样
+        let refuse = OptimizationConfig { overwrite: OverwritePolicy::Error, ..config.clone() };
+        let err = BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("patch"), &refuse).unwrap_err();
+        assert!(matches!(err, BsdiffError::OutputExists { .. }));
+        assert_eq!(fs::read(path("patch")).unwrap(), b"existing patch");
+
+        // backup：旧内容移到 .bak
+        let backup = OptimizationConfig { overwrite: OverwritePolicy::Backup, ..config.clone() };
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("patch"), &backup).unwrap();
+        assert_eq!(fs::read(path("patch.bak")).unwrap(), b"existing patch");
+        let err = BsdiffRust::patch_optimized(&path("old"), &path("out"), &path("patch"), &refuse).unwrap_err();
+        assert!(matches!(err, BsdiffError::OutputExists { .. }));
+        BsdiffRust::patch_optimized(&path("old"), &path("out"), &path("patch"), &backup).unwrap();
+        assert_eq!(fs::read(path("out")).unwrap(), new);
+        assert_eq!(fs::read(path("out.bak")).unwrap(), b"existing output");
+
+        // error 不影响不存在的输出与试运行；replace 直接覆盖
+        BsdiffRust::patch_optimized(&path("old"), &path("fresh"), &path("patch"), &refuse).unwrap();
+        let dry_run = OptimizationConfig { dry_run: true, ..refuse };
+        assert_eq!(BsdiffRust::patch_optimized(&path("old"), &path("out"), &path("patch"), &dry_run).unwrap(), new.len() as u64);
+        BsdiffRust::patch_optimized(&path("old"), &path("out.bak"), &path("patch"), &config).unwrap();
+        assert_eq!(fs::read(path("out.bak")).unwrap(), new);
+        assert_eq!(OverwritePolicy::parse("backup").unwrap(), OverwritePolicy::Backup);
+        assert!(OverwritePolicy::parse("skip").is_err());
+    }
+
+    #[test]
+    fn test_finalize_output_across_filesystems() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let final_path = dir.path().join("out.bin");
+        fs::write(&final_path, b"previous").unwrap();
+
+        // 直接走复制路径：内容与权限保留，输出目录不留暂存文件
+        let source = tempfile::TempDir::new().unwrap();
+        let temp_path = source.path().join("temp.bin");
+        fs::write(&temp_path, b"copied across devices").unwrap();
+        BsdiffRust::copy_across_devices(&temp_path, &final_path, &RetryPolicy::NONE).unwrap();
+        assert_eq!(fs::read(&final_path).unwrap(), b"copied across devices");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+        assert!(BsdiffRust::copy_across_devices(&source.path().join("missing"), &final_path, &RetryPolicy::NONE).is_err());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        // 临时文件位于另一文件系统 (如 /dev/shm) 时 rename 返回 EXDEV，finalize_output 回退到复制
+        let shm = tempfile::TempDir::new_in(BsdiffRust::get_fast_temp_dir()).unwrap();
+        let temp_path = shm.path().join("temp.bin");
+        fs::write(&temp_path, b"finalized output").unwrap();
+        BsdiffRust::finalize_output(&temp_path, &final_path, &OptimizationConfig::default()).unwrap();
+        assert_eq!(fs::read(&final_path).unwrap(), b"finalized output");
+        assert!(!temp_path.exists());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_full_patch_without_base() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        let new = b"brand new file shipped in a directory update ".repeat(60);
+        fs::write(path("new"), &new).unwrap();
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+
+        BsdiffRust::create_full_patch(&path("new"), &path("patch"), &config).unwrap();
+        let info = crate::utils::get_patch_info(&path("patch")).unwrap();
+        assert_eq!((info.source_size, info.target_size), (Some(0), Some(new.len() as u64)));
+        assert!(fs::metadata(path("patch")).unwrap().len() < new.len() as u64);
+        assert_eq!(BsdiffRust::apply_full_patch(&path("out"), &path("patch"), &config).unwrap(), new.len() as u64);
+        assert_eq!(fs::read(path("out")).unwrap(), new);
+
+        // 空文件与试运行
+        fs::write(path("empty"), b"").unwrap();
+        BsdiffRust::create_full_patch(&path("empty"), &path("empty.patch"), &config).unwrap();
+        assert_eq!(BsdiffRust::apply_full_patch(&path("empty.out"), &path("empty.patch"), &config).unwrap(), 0);
+        let dry_run = OptimizationConfig { dry_run: true, ..config.clone() };
+        assert_eq!(BsdiffRust::apply_full_patch(&path("dry"), &path("patch"), &dry_run).unwrap(), new.len() as u64);
+        assert!(!Path::new(&path("dry")).exists());
+
+        // 普通补丁需要旧文件，缺少新文件时报 FileNotFound
+        fs::write(path("old"), b"some previous version ".repeat(20)).unwrap();
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("regular"), &config).unwrap();
+        let err = BsdiffRust::apply_full_patch(&path("x"), &path("regular"), &config).unwrap_err();
+        assert!(matches!(err, BsdiffError::BaseMismatch { actual_size: 0, .. }));
+        let err = BsdiffRust::create_full_patch(&path("missing"), &path("p2"), &config).unwrap_err();
+        assert!(matches!(err, BsdiffError::FileNotFound { .. }));
+    }
+
+    #[test]
+    fn test_durable_patch() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old"), b"durable old contents ".repeat(100)).unwrap();
+        fs::write(path("new"), b"durable NEW contents ".repeat(110)).unwrap();
+        let config = OptimizationConfig { use_fast_temp_dir: false, durable: true, ..Default::default() };
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("patch"), &config).unwrap();
+        for mmap_output in [false, true] {
+            let config = OptimizationConfig { mmap_output, ..config.clone() };
+            BsdiffRust::patch_optimized(&path("old"), &path("out"), &path("patch"), &config).unwrap();
+            assert_eq!(fs::read(path("out")).unwrap(), fs::read(path("new")).unwrap());
+        }
+        BsdiffRust::patch_chain(&path("old"), &[path("patch")], &path("chained"), &config).unwrap();
+        assert_eq!(fs::read(path("chained")).unwrap(), fs::read(path("new")).unwrap());
+        assert!(BsdiffRust::sync_output(&path("missing")).is_err());
+    }
+
+    #[test]
+    fn test_block_delta_algorithm() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        let old: Vec<u8> = (0..300_000u32).flat_map(|i| (i.wrapping_mul(2_654_435_761)).to_le_bytes()).collect();
+        let mut new = old.clone();
+        new[500_000..500_100].fill(7);
+        new.splice(900_000..900_000, b"block delta insert".iter().copied());
+        fs::write(path("old"), &old).unwrap();
+        fs::write(path("new"), &new).unwrap();
+
+        let config = OptimizationConfig { use_fast_temp_dir: false, algorithm: DiffAlgorithm::BlockDelta, ..Default::default() };
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("patch"), &config).unwrap();
+        assert!(fs::metadata(path("patch")).unwrap().len() < 64 * 1024);
+        BsdiffRust::patch_optimized(&path("old"), &path("out"), &path("patch"), &OptimizationConfig::default()).unwrap();
+        assert_eq!(fs::read(path("out")).unwrap(), new);
+        assert!(DiffAlgorithm::parse("rdiff").is_err());
+    }
+
+    #[test]
+    fn test_patch_cache() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old"), b"patch cache base content ".repeat(40)).unwrap();
+        fs::write(path("new"), b"patch cache next content!".repeat(41)).unwrap();
+        let config = OptimizationConfig { use_fast_temp_dir: false, cache_dir: Some(path("cache")), ..Default::default() };
+        let entries = || -> Vec<PathBuf> {
+            fs::read_dir(path("cache")).map_or(Vec::new(), |dirs| {
+                dirs.flat_map(|sub| fs::read_dir(sub.unwrap().path()).unwrap().map(|entry| entry.unwrap().path())).collect()
+            })
+        };
+
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("p1"), &config).unwrap();
+        let cached = entries();
+        assert_eq!(cached.len(), 1);
+        assert_eq!(fs::read(&cached[0]).unwrap(), fs::read(path("p1")).unwrap());
+
+        // 命中时直接复制缓存的补丁，不再运行 diff
+        fs::write(&cached[0], b"cached patch").unwrap();
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("p2"), &config).unwrap();
+        assert_eq!(fs::read(path("p2")).unwrap(), b"cached patch");
+
+        // 选项或输入不同则是新的条目；加密补丁不缓存
+        let level = OptimizationConfig { compression_level: 9, ..config.clone() };
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("p3"), &level).unwrap();
+        BsdiffRust::diff_optimized(&path("new"), &path("old"), &path("p4"), &config).unwrap();
+        assert_eq!(entries().len(), 3);
+        let encrypted = OptimizationConfig { encryption_key: Some(EncryptionKey::new(&[7; 32]).unwrap()), ..config.clone() };
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("p5"), &encrypted).unwrap();
+        assert_eq!(entries().len(), 3);
+        BsdiffRust::patch_optimized(&path("old"), &path("out"), &path("p3"), &config).unwrap();
+        assert_eq!(fs::read(path("out")).unwrap(), fs::read(path("new")).unwrap());
+    }
+
+    #[test]
+    fn test_fallback_to_full() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        // 旧文件是新文件加上大量随机改动：差分数据全是噪声，远不如直接压缩新文件
+        let new = b"the quick brown fox jumps over the lazy dog ".repeat(1500);
+        let old: Vec<u8> = new
+            .iter()
+            .enumerate()
+            .map(|(i, b)| if i % 5 == 0 { (i as u32).wrapping_mul(2_654_435_761).to_le_bytes()[3] } else { *b })
+            .collect();
+        fs::write(path("old"), &old).unwrap();
+        fs::write(path("new"), &new).unwrap();
+        let config = OptimizationConfig { use_fast_temp_dir: false, fallback_to_full: Some(0.05), ..Default::default() };
+
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("full"), &config).unwrap();
+        let info = crate::utils::get_patch_info(&path("full")).unwrap();
+        assert!(info.full_file);
+        assert_eq!(info.source_size, Some(old.len() as u64));
+        assert!(info.size < new.len() as u64 / 20);
+        assert!(crate::utils::get_diff_stats(&path("full"), None).unwrap().full_file);
+        BsdiffRust::patch_optimized(&path("old"), &path("out"), &path("full"), &config).unwrap();
+        assert_eq!(fs::read(path("out")).unwrap(), new);
+        // 源文件摘要照常校验
+        let err = BsdiffRust::patch_to_bytes(&path("new"), &path("full"), &config).unwrap_err();
+        assert!(matches!(err, BsdiffError::BaseMismatch { .. }));
+
+        // 未超过阈值时保留差分补丁
+        let lenient = OptimizationConfig { fallback_to_full: Some(0.5), ..config.clone() };
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("delta"), &lenient).unwrap();
+        assert!(!crate::utils::get_patch_info(&path("delta")).unwrap().full_file);
+        assert!(!crate::utils::get_diff_stats(&path("delta"), None).unwrap().full_file);
+
+        let patch = BsdiffRust::diff_bytes(&old, &new, &config).unwrap();
+        assert!(PatchHeader::read_from(&mut io::Cursor::new(&patch)).unwrap().unwrap().full_file);
+        assert_eq!(BsdiffRust::patch_bytes(&old, &patch, &config).unwrap(), new);
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old"), b"audited old contents ".repeat(100)).unwrap();
+        fs::write(path("new"), b"audited NEW contents ".repeat(110)).unwrap();
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("patch"), &config).unwrap();
+
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let audit_log = AuditLog::callback(std::sync::Arc::new(move |line: &str| {
+            let event = crate::json::parse(line).unwrap().as_object().unwrap().clone();
+            sink.lock().unwrap().push(event);
+        }));
+        let config = OptimizationConfig { audit_log: Some(audit_log), ..config };
+        let names = || -> Vec<String> {
+            events.lock().unwrap().drain(..).map(|event| event["event"].as_str().unwrap().to_string()).collect()
+        };
+
+        BsdiffRust::patch_optimized(&path("old"), &path("out"), &path("patch"), &config).unwrap();
+        assert_eq!(names(), [
+            "patch_started", "header_validated", "source_checked", "payload_checked", "target_checked", "bytes_written", "rename_committed",
+            "patch_finished",
+        ]);
+
+        // 基准文件不对：记录失败事件与错误码，不写出任何内容
+        BsdiffRust::patch_optimized(&path("new"), &path("bad"), &path("patch"), &config).unwrap_err();
+        let failed = events.lock().unwrap().pop().unwrap();
+        assert_eq!(failed["event"].as_str(), Some("patch_failed"));
+        assert_eq!(failed["code"].as_str(), Some("ERR_BASE_MISMATCH"));
+        assert_eq!(names(), ["patch_started"]);
     }
-}
\ No newline at end of file
+}
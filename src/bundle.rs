@@ -0,0 +1,405 @@
+//! 多文件补丁包：一次更新涉及的多个文件各自生成补丁，按名称建立索引后拼接为一个归档，
+//! 只需分发一个文件；应用时按名称映射到各自的旧文件与输出路径
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+use crate::bsdiff_rust::{BsdiffRust, OptimizationConfig};
+use crate::dir_diff::{self, DirDiffSummary, DIR_MAGIC};
+use crate::error::BsdiffError;
+use crate::paths;
+use crate::reflink;
+
+/// 补丁包魔数
+pub const BUNDLE_MAGIC: [u8; 5] = *b"BSRB\x01";
+
+/// 当前补丁包格式版本
+pub const BUNDLE_FORMAT_VERSION: u8 = 1;
+
+/// 条目数上限，防止损坏的补丁包触发超大分配
+const MAX_ENTRIES: u32 = 1 << 20;
+
+/// 打包条目：`old` → `new` 的补丁以 `name` 存入包中
+#[derive(Debug, Clone)]
+pub struct BundleEntry {
+    pub old: PathBuf,
+    pub new: PathBuf,
+    /// 包内名称 (通常为相对路径)，应用时据此查找映射
+    pub name: String,
+}
+
+/// 应用目标：包内某个名称对应的旧文件与输出路径
+#[derive(Debug, Clone)]
+pub struct BundleTarget {
+    pub old: PathBuf,
+    pub new: PathBuf,
+}
+
+/// 补丁包索引项；offset 相对于文件起始位置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleIndexEntry {
+    pub name: String,
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// 并行生成各条目的补丁并打包，返回索引 (各补丁在包中的位置与大小)
+///
+/// 布局: `BUNDLE_MAGIC | version: u8 | count: u32 LE | 索引 | 补丁数据`，
+/// 索引项为 `name_len: u16 LE | name (UTF-8) | offset: u64 LE | length: u64 LE`。
+/// `config.dedup` 时内容完全相同的补丁 (如多处重复的资源) 只写入一份，多个索引项指向同一位置。
+pub fn create_bundle(
+    entries: &[BundleEntry],
+    bundle_file: &Path,
+    config: &OptimizationConfig
+) -> Result<Vec<BundleIndexEntry>, BsdiffError> {
+    let count = u32::try_from(entries.len())
+        .ok()
+        .filter(|count| *count > 0 && *count <= MAX_ENTRIES)
+        .ok_or("A bundle needs between 1 and 1048576 entries")?;
+    let mut names = HashSet::new();
+    for entry in entries {
+        if entry.name.is_empty() || u16::try_from(entry.name.len()).is_err() {
+            return Err(format!("Invalid bundle entry name: {:?}", entry.name).into());
+        }
+        if !names.insert(entry.name.as_str()) {
+            return Err(format!("Duplicate bundle entry: {}", entry.name).into());
+        }
+    }
+
+    let config = OptimizationConfig { progress: None, block_progress: None, phase_progress: None, ..config.clone() };
+    let patches = entries
+        .par_iter()
+        .map(|entry| {
+            BsdiffRust::diff_to_writer(&entry.old, &entry.new, Vec::new(), &config)
+                .map_err(|e| format!("{}: {}", entry.name, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // 索引位于数据之前，先算出索引长度
+    let index_len: usize = entries.iter().map(|entry| 2 + entry.name.len() + 16).sum();
+    let mut offset = (BUNDLE_MAGIC.len() + 1 + 4 + index_len) as u64;
+    let mut index = Vec::with_capacity(entries.len());
+    let mut stored: HashMap<&[u8], u64> = HashMap::new();
+    let mut unique = Vec::with_capacity(patches.len());
+    for (entry, patch) in entries.iter().zip(&patches) {
+        let shared = if config.dedup { stored.get(&patch[..]).copied() } else { None };
+        let patch_offset = match shared {
+            Some(shared) => shared,
+            None => {
+                let start = offset;
+                stored.insert(patch, start);
+                unique.push(patch);
+                offset += patch.len() as u64;
+                start
+            }
+        };
+        index.push(BundleIndexEntry { name: entry.name.clone(), offset: patch_offset, length: patch.len() as u64 });
+    }
+
+    let out_path = BsdiffRust::get_optimal_output_path(bundle_file, &config, offset)?;
+    let result = (|| -> std::io::Result<()> {
+        let mut writer = BufWriter::with_capacity(64 * 1024, File::create(&out_path)?);
+        writer.write_all(&BUNDLE_MAGIC)?;
+        writer.write_all(&[BUNDLE_FORMAT_VERSION])?;
+        writer.write_all(&count.to_le_bytes())?;
+        for item in &index {
+            writer.write_all(&(item.name.len() as u16).to_le_bytes())?;
+            writer.write_all(item.name.as_bytes())?;
+            writer.write_all(&item.offset.to_le_bytes())?;
+            writer.write_all(&item.length.to_le_bytes())?;
+        }
+        for patch in &unique {
+            writer.write_all(patch)?;
+        }
+        writer.flush()
+    })();
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&out_path);
+        return Err(e.into());
+    }
+
+    BsdiffRust::finalize_output(&out_path, bundle_file, &config)?;
+    Ok(index)
+}
+
+/// 读取补丁包索引，并校验各条目位于文件范围内
+pub fn read_bundle_index(bundle_file: &Path) -> Result<Vec<BundleIndexEntry>, BsdiffError> {
+    let mut reader = BufReader::new(File::open(bundle_file)?);
+    let bundle_len = reader.get_ref().metadata()?.len();
+    let corrupt = |reason: &str| format!("Corrupt bundle {}: {}", bundle_file.display(), reason);
+
+    let mut fixed = [0u8; BUNDLE_MAGIC.len() + 1 + 4];
+    reader.read_exact(&mut fixed).map_err(|_| format!("Not a patch bundle: {}", bundle_file.display()))?;
+    if fixed[..BUNDLE_MAGIC.len()] != BUNDLE_MAGIC {
+        return Err(format!("Not a patch bundle: {}", bundle_file.display()).into());
+    }
+    let version = fixed[BUNDLE_MAGIC.len()];
+    if version > BUNDLE_FORMAT_VERSION {
+        return Err(format!("Unsupported bundle format version {} (max {})", version, BUNDLE_FORMAT_VERSION).into());
+    }
+    let count = u32::from_le_bytes(fixed[BUNDLE_MAGIC.len() + 1..].try_into().expect("4-byte count"));
+    if count > MAX_ENTRIES {
+        return Err(corrupt("too many entries").into());
+    }
+
+    let mut index = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let mut len = [0u8; 2];
+        reader.read_exact(&mut len).map_err(|_| corrupt("truncated index"))?;
+        let mut name = vec![0u8; u16::from_le_bytes(len) as usize];
+        reader.read_exact(&mut name).map_err(|_| corrupt("truncated index"))?;
+        let mut words = [0u8; 16];
+        reader.read_exact(&mut words).map_err(|_| corrupt("truncated index"))?;
+        let offset = u64::from_le_bytes(words[..8].try_into().expect("8-byte offset"));
+        let length = u64::from_le_bytes(words[8..].try_into().expect("8-byte length"));
+        if offset.checked_add(length).is_none_or(|end| end > bundle_len) {
+            return Err(corrupt("entry exceeds file length").into());
+        }
+        let name = String::from_utf8(name).map_err(|_| corrupt("entry name is not UTF-8"))?;
+        index.push(BundleIndexEntry { name, offset, length });
+    }
+    Ok(index)
+}
+
+/// 应用补丁包：`mapping` 须恰好覆盖包内每个名称，各条目并行应用，返回各名称生成的新文件大小
+///
+/// 名称映射在读取任何补丁前整体校验；单个条目失败时返回带名称的错误，其余条目可能已经写出。
+/// 设置了 `config.base_dir` 时补丁包与映射中的所有路径都必须位于该目录之内。
+pub fn apply_bundle(
+    bundle_file: &Path,
+    mapping: &BTreeMap<String, BundleTarget>,
+    config: &OptimizationConfig
+) -> Result<Vec<(String, u64)>, BsdiffError> {
+    let confined;
+    let (bundle_file, mapping) = match &config.base_dir {
+        Some(base) => {
+            let targets = mapping
+                .iter()
+                .map(|(name, target)| {
+                    let target = BundleTarget { old: paths::confine(base, &target.old)?, new: paths::confine(base, &target.new)? };
+                    Ok((name.clone(), target))
+                })
+                .collect::<Result<BTreeMap<_, _>, BsdiffError>>()?;
+            confined = (paths::confine(base, bundle_file)?, targets);
+            (confined.0.as_path(), &confined.1)
+        }
+        None => (bundle_file, mapping),
+    };
+    let index = read_bundle_index(bundle_file)?;
+    if let Some(entry) = index.iter().find(|entry| !mapping.contains_key(&entry.name)) {
+        return Err(format!("No mapping for bundle entry {}", entry.name).into());
+    }
+    if let Some(name) = mapping.keys().find(|name| !index.iter().any(|entry| &entry.name == *name)) {
+        return Err(format!("Bundle {} has no entry named {}", bundle_file.display(), name).into());
+    }
+
+    // 沙箱内不使用共享临时目录，临时输出写在目标旁边
+    let use_fast_temp_dir = config.use_fast_temp_dir && config.base_dir.is_none();
+    let config = OptimizationConfig { progress: None, block_progress: None, phase_progress: None, use_fast_temp_dir, ..config.clone() };
+    index
+        .par_iter()
+        .map(|entry| {
+            let target = &mapping[&entry.name];
+            let apply = || -> Result<u64, BsdiffError> {
+                let mut file = File::open(bundle_file)?;
+                file.seek(SeekFrom::Start(entry.offset))?;
+                let mut patch = vec![0u8; usize::try_from(entry.length)?];
+                file.read_exact(&mut patch)?;
+                BsdiffRust::patch_file_with_patch_bytes(&target.old, &patch, &target.new, &config)
+            };
+            apply().map(|size| (entry.name.clone(), size)).map_err(|e| format!("{}: {}", entry.name, e))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(Into::into)
+}
+
+/// 并排应用补丁包：在 `target_dir` 生成更新后的完整目录树，`source_dir` 保持不变
+///
+/// 包内名称视为相对于两个目录的路径：同名旧文件打补丁，`source_dir` 中未被包提及的文件
+/// 按 `config.link_mode` 克隆、硬链接或复制。目录差分包 (diffDir 的输出) 交给 [`dir_diff::patch_dir`]。
+/// 新目录先在 `<target_dir>.partial` 中构建，全部成功后再移动到 `target_dir`；`target_dir` 必须不存在，
+/// 之后交换目录即可切换或回滚版本。
+pub fn apply_bundle_to_dir(
+    bundle_file: &Path,
+    source_dir: &Path,
+    target_dir: &Path,
+    config: &OptimizationConfig
+) -> Result<DirDiffSummary, BsdiffError> {
+    let (bundle_file, source_dir, target_dir) = match &config.base_dir {
+        Some(base) => (paths::confine(base, bundle_file)?, paths::confine(base, source_dir)?, paths::confine(base, target_dir)?),
+        None => (bundle_file.to_path_buf(), source_dir.to_path_buf(), target_dir.to_path_buf()),
+    };
+    let mut magic = [0u8; DIR_MAGIC.len()];
+    let is_dir_bundle = File::open(&bundle_file)?.read_exact(&mut magic).is_ok() && magic == DIR_MAGIC;
+    if is_dir_bundle {
+        return dir_diff::patch_dir(&source_dir, &target_dir, &bundle_file, config);
+    }
+    if target_dir.exists() {
+        return Err(format!("Output directory already exists: {}", target_dir.display()).into());
+    }
+    let index = read_bundle_index(&bundle_file)?;
+    let old_files = dir_diff::list_files(&source_dir)?;
+    let staging = paths::with_suffix(&target_dir, ".partial");
+    let mut mapping = BTreeMap::new();
+    for entry in &index {
+        let relative = dir_diff::safe_relative_path(&entry.name)?;
+        let old = old_files
+            .get(&entry.name)
+            .ok_or_else(|| format!("Bundle does not match {}: missing {}", source_dir.display(), entry.name))?;
+        mapping.insert(entry.name.clone(), BundleTarget { old: old.clone(), new: staging.join(relative) });
+    }
+
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&staging)?;
+    let build = || -> Result<DirDiffSummary, BsdiffError> {
+        for target in mapping.values() {
+            if let Some(parent) = target.new.parent() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        apply_bundle(&bundle_file, &mapping, config)?;
+        let mut summary = DirDiffSummary { patched: mapping.len() as u32, ..Default::default() };
+        for (path, source) in old_files.iter().filter(|(path, _)| !mapping.contains_key(*path)) {
+            let target = staging.join(dir_diff::safe_relative_path(path)?);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            reflink::link_or_copy(source, &target, config.link_mode)?;
+            summary.unchanged += 1;
+        }
+        Ok(summary)
+    };
+    match build() {
+        Ok(summary) => {
+            config.retry.run("Renaming output directory", || fs::rename(&staging, &target_dir))?;
+            Ok(summary)
+        }
+        Err(e) => {
+            let _ = fs::remove_dir_all(&staging);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let mut entries = Vec::new();
+        let mut mapping = BTreeMap::new();
+        for i in 0..3 {
+            let old = format!("bundle file {} old content ", i).repeat(30 + i);
+            let new = format!("bundle file {} NEW content ", i).repeat(31 + i);
+            std::fs::write(path(&format!("old{}", i)), &old).unwrap();
+            std::fs::write(path(&format!("new{}", i)), &new).unwrap();
+            let name = format!("bin/file{}.dat", i);
+            entries.push(BundleEntry { old: path(&format!("old{}", i)), new: path(&format!("new{}", i)), name: name.clone() });
+            mapping.insert(name, BundleTarget { old: path(&format!("old{}", i)), new: path(&format!("out{}", i)) });
+        }
+
+        let index = create_bundle(&entries, &path("update.bundle"), &config).unwrap();
+        assert_eq!(read_bundle_index(&path("update.bundle")).unwrap(), index);
+        let applied = apply_bundle(&path("update.bundle"), &mapping, &config).unwrap();
+        assert_eq!(applied.len(), 3);
+        for (i, (name, size)) in applied.iter().enumerate() {
+            assert_eq!(name, &entries[i].name);
+            let expected = std::fs::read(path(&format!("new{}", i))).unwrap();
+            assert_eq!(std::fs::read(path(&format!("out{}", i))).unwrap(), expected);
+            assert_eq!(*size, expected.len() as u64);
+        }
+
+        // 映射缺少或多出名称时在应用前拒绝
+        let mut partial = mapping.clone();
+        partial.remove("bin/file1.dat");
+        assert!(apply_bundle(&path("update.bundle"), &partial, &config).unwrap_err().to_string().contains("bin/file1.dat"));
+        let mut extra = mapping.clone();
+        extra.insert("missing".into(), BundleTarget { old: path("old0"), new: path("x") });
+        assert!(apply_bundle(&path("update.bundle"), &extra, &config).is_err());
+        assert!(!std::path::Path::new(&path("x")).exists());
+
+        // 去重：内容相同的补丁只写入一份
+        let mut shared = entries.clone();
+        shared.push(BundleEntry { name: "copy/file0.dat".into(), ..entries[0].clone() });
+        let plain = create_bundle(&shared, &path("plain.bundle"), &config).unwrap();
+        let dedup_config = OptimizationConfig { dedup: true, ..config.clone() };
+        let deduped = create_bundle(&shared, &path("dedup.bundle"), &dedup_config).unwrap();
+        assert_eq!((deduped[3].offset, deduped[3].length), (deduped[0].offset, deduped[0].length));
+        let saved = std::fs::metadata(path("plain.bundle")).unwrap().len() - std::fs::metadata(path("dedup.bundle")).unwrap().len();
+        assert_eq!(saved, plain[3].length);
+        let mut copy_mapping = mapping.clone();
+        copy_mapping.insert("copy/file0.dat".into(), BundleTarget { old: path("old0"), new: path("copy0") });
+        apply_bundle(&path("dedup.bundle"), &copy_mapping, &config).unwrap();
+        assert_eq!(std::fs::read(path("copy0")).unwrap(), std::fs::read(path("new0")).unwrap());
+
+        // 沙箱：映射的输出位于沙箱外时在写入前拒绝
+        let jailed = OptimizationConfig { base_dir: Some(dir.path().to_path_buf()), ..config.clone() };
+        apply_bundle(&path("update.bundle"), &mapping, &jailed).unwrap();
+        let mut escaping = mapping.clone();
+        escaping.get_mut("bin/file1.dat").unwrap().new = dir.path().join("../escaped");
+        assert!(apply_bundle(&path("update.bundle"), &escaping, &jailed).is_err());
+        assert!(!dir.path().join("../escaped").exists());
+
+        // 重名、空列表、截断的包
+        let duplicate = vec![entries[0].clone(), entries[0].clone()];
+        assert!(create_bundle(&duplicate, &path("dup.bundle"), &config).is_err());
+        assert!(create_bundle(&[], &path("empty.bundle"), &config).is_err());
+        let data = std::fs::read(path("update.bundle")).unwrap();
+        std::fs::write(path("short.bundle"), &data[..data.len() - 1]).unwrap();
+        assert!(read_bundle_index(&path("short.bundle")).is_err());
+        assert!(read_bundle_index(&path("old0")).is_err());
+    }
+
+    #[test]
+    fn test_apply_bundle_to_dir() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let files = [("bin/app", b"app v1 ".repeat(100)), ("lib/keep.so", b"library".repeat(50)), ("readme", b"hello".to_vec())];
+        for (name, data) in &files {
+            fs::create_dir_all(path("v1").join(name).parent().unwrap()).unwrap();
+            fs::write(path("v1").join(name), data).unwrap();
+        }
+        fs::write(path("app2"), b"app v2 ".repeat(101)).unwrap();
+        fs::write(path("readme2"), b"hello again").unwrap();
+        let entries = [
+            BundleEntry { old: path("v1/bin/app"), new: path("app2"), name: "bin/app".into() },
+            BundleEntry { old: path("v1/readme"), new: path("readme2"), name: "readme".into() },
+        ];
+        create_bundle(&entries, &path("update.bundle"), &config).unwrap();
+
+        let summary = apply_bundle_to_dir(&path("update.bundle"), &path("v1"), &path("v2"), &config).unwrap();
+        assert_eq!(summary, DirDiffSummary { patched: 2, unchanged: 1, ..Default::default() });
+        assert_eq!(fs::read(path("v2/bin/app")).unwrap(), fs::read(path("app2")).unwrap());
+        assert_eq!(fs::read(path("v2/readme")).unwrap(), b"hello again");
+        assert_eq!(fs::read(path("v2/lib/keep.so")).unwrap(), files[1].1);
+        // 源目录保持不变
+        for (name, data) in &files {
+            assert_eq!(&fs::read(path("v1").join(name)).unwrap(), data);
+        }
+        assert!(!path("v2.partial").exists());
+
+        // 目标已存在、源目录缺少包中的文件时拒绝，不留下半成品
+        assert!(apply_bundle_to_dir(&path("update.bundle"), &path("v1"), &path("v2"), &config).is_err());
+        fs::remove_file(path("v1/readme")).unwrap();
+        assert!(apply_bundle_to_dir(&path("update.bundle"), &path("v1"), &path("v3"), &config).is_err());
+        assert!(!path("v3").exists() && !path("v3.partial").exists());
+
+        // 目录差分包交给 patch_dir
+        dir_diff::diff_dir(&path("v1"), &path("v2"), &path("update.bsrd"), &config).unwrap();
+        apply_bundle_to_dir(&path("update.bsrd"), &path("v1"), &path("v4"), &config).unwrap();
+        assert_eq!(dir_diff::list_files(&path("v4")).unwrap().len(), 3);
+        assert_eq!(fs::read(path("v4/readme")).unwrap(), b"hello again");
+    }
+}
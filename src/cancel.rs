@@ -0,0 +1,78 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::error::BsdiffError;
+
+/// 取消标记：由调用方 (如 JavaScript 的 AbortSignal) 设置，
+/// diff/patch 在处理数据块之间检查，发现已取消时尽快返回 [`BsdiffError::Cancelled`]；
+/// 设置了期限时，超过期限后返回 [`BsdiffError::TimedOut`]
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    flag: Arc<AtomicBool>,
+    /// 截止时刻及设置的时长 (用于错误信息)
+    deadline: Option<(Instant, Duration)>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 从现在起 `timeout` 后视为超时 (克隆共享取消状态，期限各自保留)
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = Some((Instant::now() + timeout, timeout));
+        self
+    }
+
+    /// 沿用 `other` 的期限 (用于合并 AbortSignal 与 timeoutMs)
+    pub fn with_deadline_of(mut self, other: &CancelToken) -> Self {
+        self.deadline = other.deadline;
+        self
+    }
+
+    /// 请求取消 (可从任意线程调用)
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+
+    /// 已取消或已超时时返回对应的错误
+    pub(crate) fn interruption(&self) -> Option<BsdiffError> {
+        if self.is_cancelled() {
+            return Some(BsdiffError::Cancelled);
+        }
+        match self.deadline {
+            Some((at, timeout)) if Instant::now() >= at => {
+                Some(BsdiffError::TimedOut { timeout_ms: timeout.as_millis().try_into().unwrap_or(u64::MAX) })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// 已取消或已超时时返回包装了对应 [`BsdiffError`] 的 I/O 错误，便于在读写路径中中断
+#[inline]
+pub(crate) fn check(token: Option<&CancelToken>) -> io::Result<()> {
+    match token.and_then(CancelToken::interruption) {
+        Some(error) => Err(io::Error::other(error)),
+        None => Ok(()),
+    }
+}
+
+/// 取出错误 (含被 I/O 错误包装的情况) 中的取消或超时原因
+pub(crate) fn interruption_of(error: &(dyn std::error::Error + 'static)) -> Option<BsdiffError> {
+    let inner = match error.downcast_ref::<io::Error>() {
+        Some(e) => e.get_ref()? as &(dyn std::error::Error + 'static),
+        None => error,
+    };
+    match inner.downcast_ref::<BsdiffError>()? {
+        BsdiffError::Cancelled => Some(BsdiffError::Cancelled),
+        BsdiffError::TimedOut { timeout_ms } => Some(BsdiffError::TimedOut { timeout_ms: *timeout_ms }),
+        _ => None,
+    }
+}
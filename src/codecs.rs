@@ -0,0 +1,233 @@
+//! zstd 以外的补丁负载压缩算法：xz、brotli 与 gzip
+//!
+//! 这些算法使用固定参数 (`compressionLevel`、字典与自动级别只作用于 zstd)：
+//! xz 预设 6、brotli 质量 11 且窗口 16 MiB、gzip 级别 9。xz、brotli 与 gzip 分别由 `xz`、`brotli`、`zlib`
+//! feature 控制 (默认开启，gzip 复用压缩包变换的 flate2)，未编译进来时生成与应用这类补丁都返回
+//! "not available in this build" 错误。
+
+use std::io::{self, Read, Write};
+
+use crate::header::Compression;
+
+/// xz 预设级别
+#[cfg(feature = "xz")]
+const XZ_PRESET: u32 = 6;
+
+/// brotli 质量 (0-11)
+#[cfg(feature = "brotli")]
+const BROTLI_QUALITY: u32 = 11;
+
+/// brotli 窗口大小的以 2 为底的对数 (16 MiB，标准 brotli 允许的最大值)
+#[cfg(feature = "brotli")]
+const BROTLI_WINDOW_LOG: u32 = 24;
+
+/// brotli 编码器的内部缓冲区大小
+#[cfg(feature = "brotli")]
+const BROTLI_BUFFER: usize = 64 * 1024;
+
+/// `compression` 的编解码器是否编译进了当前构建
+pub fn is_available(compression: Compression) -> bool {
+    match compression {
+        Compression::None | Compression::Zstd => true,
+        Compression::Xz => cfg!(feature = "xz"),
+        Compression::Gzip => cfg!(feature = "zlib"),
+        Compression::Brotli => cfg!(feature = "brotli"),
+    }
+}
+
+fn unavailable(compression: Compression) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("Compression backend {} is not available in this build", compression.name()),
+    )
+}
+
+/// xz / brotli / gzip 负载的流式编码器
+pub(crate) enum Encoder<W: Write> {
+    #[cfg(feature = "xz")]
+    Xz(xz2::write::XzEncoder<W>),
+    #[cfg(feature = "brotli")]
+    Brotli(Box<brotli::CompressorWriter<ErrorSlot<W>>>),
+    #[cfg(feature = "zlib")]
+    Gzip(flate2::write::GzEncoder<W>),
+    /// 三种算法都未编译进来时的占位 (无法构造)
+    #[cfg(not(any(feature = "xz", feature = "brotli", feature = "zlib")))]
+    #[allow(dead_code)]
+    Unavailable(std::convert::Infallible, std::marker::PhantomData<W>),
+}
+
+impl<W: Write> Encoder<W> {
+    /// 创建 `compression` 的编码器；zstd 与不压缩由调用方处理
+    pub(crate) fn new(compression: Compression, writer: W) -> io::Result<Self> {
+        #[cfg(not(any(feature = "xz", feature = "brotli", feature = "zlib")))]
+        let _ = writer;
+        match compression {
+            #[cfg(feature = "xz")]
+            Compression::Xz => Ok(Self::Xz(xz2::write::XzEncoder::new(writer, XZ_PRESET))),
+            #[cfg(feature = "brotli")]
+            Compression::Brotli => Ok(Self::Brotli(Box::new(brotli::CompressorWriter::new(
+                ErrorSlot { inner: writer, error: None },
+                BROTLI_BUFFER,
+                BROTLI_QUALITY,
+                BROTLI_WINDOW_LOG,
+            )))),
+            #[cfg(feature = "zlib")]
+            Compression::Gzip => Ok(Self::Gzip(flate2::write::GzEncoder::new(writer, flate2::Compression::best()))),
+            Compression::None | Compression::Zstd => {
+                Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not handled by this encoder", compression.name())))
+            }
+            #[allow(unreachable_patterns)]
+            other => Err(unavailable(other)),
+        }
+    }
+
+    /// 写出流的结尾并返回底层写入器
+    pub(crate) fn finish(self) -> io::Result<W> {
+        match self {
+            #[cfg(feature = "xz")]
+            Self::Xz(encoder) => encoder.finish(),
+            #[cfg(feature = "brotli")]
+            Self::Brotli(encoder) => {
+                // into_inner 会吞掉写出结尾时的错误，由 ErrorSlot 记录后在这里返回
+                let slot = encoder.into_inner();
+                match slot.error {
+                    Some(error) => Err(error),
+                    None => Ok(slot.inner),
+                }
+            }
+            #[cfg(feature = "zlib")]
+            Self::Gzip(encoder) => encoder.finish(),
+            #[cfg(not(any(feature = "xz", feature = "brotli", feature = "zlib")))]
+            Self::Unavailable(never, _) => match never {},
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        #[cfg(not(any(feature = "xz", feature = "brotli", feature = "zlib")))]
+        let _ = buf;
+        match self {
+            #[cfg(feature = "xz")]
+            Self::Xz(encoder) => encoder.write(buf),
+            #[cfg(feature = "brotli")]
+            Self::Brotli(encoder) => encoder.write(buf),
+            #[cfg(feature = "zlib")]
+            Self::Gzip(encoder) => encoder.write(buf),
+            #[cfg(not(any(feature = "xz", feature = "brotli", feature = "zlib")))]
+            Self::Unavailable(never, _) => match *never {},
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "xz")]
+            Self::Xz(encoder) => encoder.flush(),
+            #[cfg(feature = "brotli")]
+            Self::Brotli(encoder) => encoder.flush(),
+            #[cfg(feature = "zlib")]
+            Self::Gzip(encoder) => encoder.flush(),
+            #[cfg(not(any(feature = "xz", feature = "brotli", feature = "zlib")))]
+            Self::Unavailable(never, _) => match *never {},
+        }
+    }
+}
+
+/// 记录第一个写入错误的写入器，用于 brotli 结束流时丢弃错误的 `into_inner`
+#[cfg(feature = "brotli")]
+pub(crate) struct ErrorSlot<W> {
+    inner: W,
+    error: Option<io::Error>,
+}
+
+#[cfg(feature = "brotli")]
+impl<W: Write> Write for ErrorSlot<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(error) = &self.error {
+            return Err(io::Error::new(error.kind(), error.to_string()));
+        }
+        self.inner.write(buf).inspect_err(|error| self.error = Some(io::Error::new(error.kind(), error.to_string())))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// 创建 xz / brotli / gzip 负载的解码器；给出 `max_memory` 时 xz 拒绝解码内存需求超过该大小的流
+pub(crate) fn decoder<'a, R: Read + 'a>(
+    compression: Compression,
+    reader: R,
+    max_memory: Option<u64>
+) -> io::Result<Box<dyn Read + 'a>> {
+    #[cfg(not(feature = "xz"))]
+    let _ = max_memory;
+    #[cfg(not(any(feature = "xz", feature = "brotli", feature = "zlib")))]
+    let _ = reader;
+    match compression {
+        #[cfg(feature = "xz")]
+        Compression::Xz => {
+            let stream = xz2::stream::Stream::new_stream_decoder(max_memory.unwrap_or(u64::MAX), 0)?;
+            Ok(Box::new(xz2::read::XzDecoder::new_stream(io::BufReader::with_capacity(64 * 1024, reader), stream)))
+        }
+        #[cfg(feature = "brotli")]
+        Compression::Brotli => Ok(Box::new(brotli::Decompressor::new(reader, 64 * 1024))),
+        #[cfg(feature = "zlib")]
+        Compression::Gzip => Ok(Box::new(flate2::read::GzDecoder::new(io::BufReader::with_capacity(64 * 1024, reader)))),
+        Compression::None | Compression::Zstd => {
+            Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not handled by this decoder", compression.name())))
+        }
+        #[allow(unreachable_patterns)]
+        other => Err(unavailable(other)),
+    }
+}
+
+#[cfg(all(test, any(feature = "xz", feature = "brotli", feature = "zlib")))]
+mod tests {
+    use super::*;
+
+    fn round_trip(compression: Compression, data: &[u8]) -> Vec<u8> {
+        let mut encoder = Encoder::new(compression, Vec::new()).unwrap();
+        encoder.write_all(data).unwrap();
+        let encoded = encoder.finish().unwrap();
+        let mut decoded = Vec::new();
+        decoder(compression, encoded.as_slice(), None).unwrap().read_to_end(&mut decoded).unwrap();
+        decoded
+    }
+
+    #[test]
+    fn test_codecs_round_trip() {
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8 ^ (i / 4096) as u8).collect();
+        for compression in [Compression::Xz, Compression::Brotli, Compression::Gzip] {
+            if !is_available(compression) {
+                assert!(Encoder::new(compression, Vec::new()).is_err());
+                continue;
+            }
+            assert_eq!(round_trip(compression, &data), data, "{}", compression.name());
+            assert_eq!(round_trip(compression, b""), b"", "{}", compression.name());
+        }
+    }
+
+    #[test]
+    fn test_codecs_reject_truncated_stream() {
+        let data = vec![7u8; 100_000];
+        for compression in [Compression::Xz, Compression::Brotli, Compression::Gzip].into_iter().filter(|c| is_available(*c)) {
+            let mut encoder = Encoder::new(compression, Vec::new()).unwrap();
+            encoder.write_all(&data).unwrap();
+            let encoded = encoder.finish().unwrap();
+            let truncated = &encoded[..encoded.len() - 4];
+            let mut decoded = Vec::new();
+            assert!(decoder(compression, truncated, None).unwrap().read_to_end(&mut decoded).is_err(), "{}", compression.name());
+        }
+    }
+
+    #[cfg(feature = "xz")]
+    #[test]
+    fn test_xz_memory_limit() {
+        let mut encoder = Encoder::new(Compression::Xz, Vec::new()).unwrap();
+        encoder.write_all(&[1u8; 1024]).unwrap();
+        let encoded = encoder.finish().unwrap();
+        let mut decoded = Vec::new();
+        assert!(decoder(Compression::Xz, encoded.as_slice(), Some(1024)).unwrap().read_to_end(&mut decoded).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use std::io::{self, Read, Write};
+
+/// bsdiff 控制块长度 (3 个 64 位整数)
+pub const CONTROL_SIZE: usize = 24;
+
+/// bsdiff 控制三元组
+///
+/// 原始 bsdiff 流由若干 `控制块 | mix_len 字节差分数据 | copy_len 字节新增数据` 组成。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ControlEntry {
+    /// 与旧文件逐字节相加的差分数据长度
+    pub mix_len: u64,
+    /// 直接拷贝到输出的新增数据长度
+    pub copy_len: u64,
+    /// 处理完本块后旧文件位置的偏移量
+    pub seek: i64,
+}
+
+impl ControlEntry {
+    /// 编码为 24 字节控制块
+    pub fn to_bytes(self) -> [u8; CONTROL_SIZE] {
+        let mut buf = [0u8; CONTROL_SIZE];
+        buf[0..8].copy_from_slice(&self.mix_len.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.copy_len.to_le_bytes());
+        buf[16..24].copy_from_slice(&offtout(self.seek));
+        buf
+    }
+
+    /// 从 24 字节控制块解码
+    pub fn from_bytes(buf: &[u8; CONTROL_SIZE]) -> Self {
+        let mut word = [0u8; 8];
+        word.copy_from_slice(&buf[0..8]);
+        let mix_len = u64::from_le_bytes(word);
+        word.copy_from_slice(&buf[8..16]);
+        let copy_len = u64::from_le_bytes(word);
+        word.copy_from_slice(&buf[16..24]);
+        Self {
+            mix_len,
+            copy_len,
+            seek: offtin(word),
+        }
+    }
+
+    /// 读取控制块；在块边界处遇到流结束时返回 `None`
+    pub fn read_from<R: Read + ?Sized>(reader: &mut R) -> io::Result<Option<Self>> {
+        let mut buf = [0u8; CONTROL_SIZE];
+        let mut filled = 0;
+        while filled < CONTROL_SIZE {
+            let n = reader.read(&mut buf[filled..])?;
+            if n == 0 {
+                if filled == 0 {
+                    return Ok(None);
+                }
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated bsdiff control block"));
+            }
+            filled += n;
+        }
+        Ok(Some(Self::from_bytes(&buf)))
+    }
+
+    /// 写入控制块
+    #[inline]
+    pub fn write_to<W: Write + ?Sized>(self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.to_bytes())
+    }
+}
+
+/// 编码为 bsdiff 使用的符号-幅值小端 i64
+#[inline]
+pub(crate) fn offtout(x: i64) -> [u8; 8] {
+    if x >= 0 {
+        x.to_le_bytes()
+    } else {
+        (x.unsigned_abs() | (1 << 63)).to_le_bytes()
+    }
+}
+
+/// 解码符号-幅值小端 i64
+#[inline]
+pub(crate) fn offtin(buf: [u8; 8]) -> i64 {
+    let y = i64::from_le_bytes(buf);
+    if y & (1 << 63) == 0 {
+        y
+    } else {
+        -(y & !(1 << 63))
+    }
+}
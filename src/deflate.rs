@@ -0,0 +1,159 @@
+//! 原始 DEFLATE (RFC 1951) 的解压与压缩，仅用于 ZIP 条目的解压后 diff
+//!
+//! 使用 flate2 的 zlib 后端并静态链接内置的 zlib，diff 与应用补丁的机器上同一级别的压缩输出逐字节一致，
+//! 应用补丁时才能把解压后的条目重新压缩回原来的字节。其他实现 (7-Zip、Info-ZIP、SIMD 优化的 zlib 分支)
+//! 的输出一般无法复现，这些条目保持压缩状态原样 diff。
+//!
+//! 关闭 `zlib` feature 时 (如 wasm32 构建) 没有可复现的压缩器：解压总是失败，压缩返回错误。
+
+#[cfg(feature = "zlib")]
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+
+use crate::error::BsdiffError;
+
+type Result<T> = std::result::Result<T, BsdiffError>;
+
+/// 逐段压缩/解压时每次提供的输出空间
+#[cfg(feature = "zlib")]
+const CHUNK: usize = 64 * 1024;
+
+/// 原始 deflate 流的窗口大小 (zlib 的 windowBits 15)
+#[cfg(feature = "zlib")]
+const WINDOW_BITS: u8 = 15;
+
+/// 当前构建是否包含 zlib (压缩包变换需要)
+pub const AVAILABLE: bool = cfg!(feature = "zlib");
+
+/// 解压原始 DEFLATE 流，返回解压数据与消耗的压缩字节数；输出超过 `limit` 字节或数据损坏时返回 `None`
+#[cfg(feature = "zlib")]
+pub fn inflate(data: &[u8], limit: usize) -> Option<(Vec<u8>, usize)> {
+    let mut decompress = Decompress::new(false);
+    let mut buffer = vec![0u8; CHUNK];
+    let mut out = Vec::new();
+    loop {
+        let (read, written) = (decompress.total_in(), decompress.total_out());
+        let status = decompress.decompress(&data[read as usize..], &mut buffer, FlushDecompress::None).ok()?;
+        let produced = (decompress.total_out() - written) as usize;
+        if out.len() + produced > limit {
+            return None;
+        }
+        out.extend_from_slice(&buffer[..produced]);
+        match status {
+            Status::StreamEnd => return Some((out, decompress.total_in() as usize)),
+            // 没有任何进展：输入已耗尽而流未结束 (截断)
+            _ if (read, written) == (decompress.total_in(), decompress.total_out()) => return None,
+            _ => {}
+        }
+    }
+}
+
+/// 以 zlib 的 `level` (1-9，默认 windowBits 15、memLevel 8、默认策略) 压缩为原始 DEFLATE 流
+#[cfg(feature = "zlib")]
+pub fn deflate(data: &[u8], level: u8) -> Result<Vec<u8>> {
+    if !(1..=9).contains(&level) {
+        return Err(format!("Unsupported deflate level {}", level).into());
+    }
+    let mut out = Vec::with_capacity(data.len() / 2 + 64);
+    compress(data, level, |chunk| {
+        out.extend_from_slice(chunk);
+        true
+    })?;
+    Ok(out)
+}
+
+/// 以 zlib 的 `level` 压缩 `data` 能否逐字节得到 `expected`；输出一旦不同立即停止
+#[cfg(feature = "zlib")]
+pub fn reproduces(data: &[u8], level: u8, expected: &[u8]) -> bool {
+    if !(1..=9).contains(&level) {
+        return false;
+    }
+    let mut matched = 0;
+    let complete = compress(data, level, |chunk| {
+        let same = expected.get(matched..matched + chunk.len()) == Some(chunk);
+        matched += chunk.len();
+        same
+    });
+    complete.unwrap_or(false) && matched == expected.len()
+}
+
+/// 压缩并把输出逐段交给 `sink`；`sink` 返回 false 时提前停止并返回 `Ok(false)`
+#[cfg(feature = "zlib")]
+fn compress(data: &[u8], level: u8, mut sink: impl FnMut(&[u8]) -> bool) -> Result<bool> {
+    let mut compress = Compress::new_with_window_bits(Compression::new(level as u32), false, WINDOW_BITS);
+    let mut buffer = Vec::with_capacity(CHUNK);
+    loop {
+        buffer.clear();
+        let consumed = compress.total_in() as usize;
+        let status = compress.compress_vec(&data[consumed..], &mut buffer, FlushCompress::Finish)?;
+        if !buffer.is_empty() && !sink(&buffer) {
+            return Ok(false);
+        }
+        if status == Status::StreamEnd {
+            return Ok(true);
+        }
+    }
+}
+
+#[cfg(not(feature = "zlib"))]
+pub fn inflate(_data: &[u8], _limit: usize) -> Option<(Vec<u8>, usize)> {
+    None
+}
+
+#[cfg(not(feature = "zlib"))]
+pub fn deflate(_data: &[u8], _level: u8) -> Result<Vec<u8>> {
+    Err("Deflate is not available in this build (zlib feature disabled)".into())
+}
+
+#[cfg(not(feature = "zlib"))]
+pub fn reproduces(_data: &[u8], _level: u8, _expected: &[u8]) -> bool {
+    false
+}
+
+#[cfg(all(test, feature = "zlib"))]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_deflate_matches_zlib() {
+        // 期望值由 zlib 1.2.13 生成: compressobj(level, DEFLATED, -15, 8, Z_DEFAULT_STRATEGY)
+        let data: Vec<u8> = (0..2000usize).map(|i| b"abcdefghij"[(i * i % 7 + i / 50) % 10]).collect();
+        let expected = [
+            (1, "95944911004108c4b472c3f817b0546160f38a804e4734cc42e53f2cddd314a022cacd01323b3c1aa02623a7005e65bd6e80ae961901e8d1f774009e89d81b005775790200075f35e0e06b081c7c0d8183af2170f035040ebe86c0c1d71038f81a02075f43e0e06b0879f8b5803cfc92401e7e49200fbf2490875f12c8c32f09e4e19704f2f04b0279f825813cfc9240cbae41cb9e4ecb1e41cb9e4dcb3e49cb5e49cb5e4dcb3e4acb6e42cbaefa01"),
+            (6, "95d44911c0400cc440acbeede50f2003217a350055c9bc22caed3fd1991d0e98aac94840f756d602e6bafa06f0a6e7ed0276d6ee0cb0e7eff9015e98c53b40baa73d03c0e002061730b880c1050c2e607001830b185cc0e002061730b880c1050c2e607001830b185cc0e002061730b880c1053dbb173d7b273d7b153d7b2f3dfb353dfb343dfb2c3dfb393d7b183dbbfb07"),
+            (9, "95d34915c05008c550adf0191ef817502434ab2b2027e6f95ebafde755443d077466c70b40953252809eca9a066c57af0450cb660ca0f15d1fc03eb3b70308f7b035000c7ec0e0070c7ec0e0070c7ec0e0070c7ec0e0070c7ec0e0070c7e381e3df0e8c2a3371e5d7874c3a30f1e7df0e886474f3c7ae0d1138f2e3c7ae1d10b8f2e3cbae3d10d8fee78f40f"),
+        ];
+        for (level, compressed) in expected {
+            let compressed = hex(compressed);
+            assert_eq!(deflate(&data, level).unwrap(), compressed, "level {}", level);
+            assert!(reproduces(&data, level, &compressed));
+            assert_eq!(inflate(&compressed, data.len()), Some((data.clone(), compressed.len())));
+        }
+        assert_eq!(deflate(&[], 6).unwrap(), [0x03, 0x00]);
+        assert!(deflate(&data, 0).is_err());
+        assert!(!reproduces(&data, 5, &hex(expected[0].1)));
+    }
+
+    #[test]
+    fn test_inflate_roundtrip() {
+        // 跨越窗口滑动与多个块：文本、重复、不可压缩数据混合
+        let mut state = 1u32;
+        let mut data: Vec<u8> = (0..60_000).map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 24) as u8
+        }).collect();
+        data.extend((0..150_000usize).map(|i| b"function return const let "[(i + i / 997) % 26]));
+        data.extend(std::iter::repeat_n(0u8, 40_000));
+        for level in 1..=9 {
+            let compressed = deflate(&data, level).unwrap();
+            assert_eq!(inflate(&compressed, data.len()), Some((data.clone(), compressed.len())), "level {}", level);
+            assert!(reproduces(&data, level, &compressed));
+        }
+        let compressed = deflate(&data, 6).unwrap();
+        assert_eq!(inflate(&compressed, data.len() - 1), None);
+        assert_eq!(inflate(&compressed[..compressed.len() / 2], usize::MAX), None);
+    }
+}
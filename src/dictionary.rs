@@ -0,0 +1,99 @@
+//! 共享 zstd 字典：同一批相似资源生成的大量小补丁共用一个字典压缩负载，显著提升压缩率。
+//! 字典 ID 记录在补丁头中，应用补丁时必须提供 ID 相同的字典
+
+use std::io::Read;
+use std::sync::Arc;
+use std::path::PathBuf;
+
+use crate::bsdiff_rust::BsdiffRust;
+use crate::error::BsdiffError;
+use crate::header::sha256;
+
+/// 默认的字典大小上限 (与 zstd 命令行 --maxdict 默认值一致)
+pub const DEFAULT_MAX_SIZE: usize = 110 * 1024;
+/// 训练时每个补丁最多取用的原始 bsdiff 流长度
+const MAX_SAMPLE_LEN: u64 = 1 << 20;
+
+/// zstd 字典及其 ID；克隆只复制引用
+#[derive(Clone, PartialEq, Eq)]
+pub struct Dictionary {
+    id: u32,
+    data: Arc<[u8]>,
+}
+
+impl Dictionary {
+    /// 训练生成的字典使用其自带的 ID；原始内容字典 (无 zstd 字典头) 的 ID 取内容 SHA-256 的前 4 字节
+    pub fn new(data: &[u8]) -> Result<Self, BsdiffError> {
+        if data.is_empty() {
+            return Err("Dictionary is empty".into());
+        }
+        let id = match zstd::zstd_safe::get_dict_id_from_dict(data) {
+            Some(id) => id.get(),
+            None => u32::from_le_bytes(sha256(data)[..4].try_into().expect("digest is longer than 4 bytes")).max(1),
+        };
+        Ok(Self { id, data: data.into() })
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl std::fmt::Debug for Dictionary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Dictionary").field("id", &self.id).field("len", &self.data.len()).finish()
+    }
+}
+
+/// 以已有补丁为样本训练字典：解码出各补丁的原始 bsdiff 流 (每个最多取前 1 MB) 交给 zstd 训练器。
+/// 样本过少或内容过于单一时 zstd 会拒绝训练并返回错误
+pub fn train_dictionary(patch_files: &[PathBuf], max_size: usize) -> Result<Vec<u8>, BsdiffError> {
+    if patch_files.is_empty() {
+        return Err("At least one sample patch is required to train a dictionary".into());
+    }
+    let samples = patch_files
+        .iter()
+        .map(|patch_file| -> Result<Vec<u8>, BsdiffError> {
+            let (_, decoder) = BsdiffRust::open_patch(patch_file, None)?;
+            let mut raw = Vec::new();
+            decoder.take(MAX_SAMPLE_LEN).read_to_end(&mut raw)?;
+            Ok(raw)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    zstd::dict::from_samples(&samples, max_size)
+        .map_err(|e| format!("Dictionary training failed ({}); provide more or larger sample patches", e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bsdiff_rust::OptimizationConfig;
+
+    #[test]
+    fn test_dictionary() {
+        let raw = Dictionary::new(b"raw content dictionary").unwrap();
+        assert_eq!(raw, Dictionary::new(b"raw content dictionary").unwrap());
+        assert_ne!(raw.id(), Dictionary::new(b"another dictionary").unwrap().id());
+        assert!(Dictionary::new(b"").is_err());
+
+        // 一组相似资源的补丁训练出的字典带有 zstd 字典 ID
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let patches: Vec<PathBuf> = (0..64u32)
+            .map(|i| {
+                let old = format!("{{\"asset\":{},\"name\":\"sprite\",\"frames\":[1,2,3]}}", i).repeat(20).into_bytes();
+                let new = format!("{{\"asset\":{},\"name\":\"sprite-v2\",\"frames\":[1,2,3,{}]}}", i, i * 7).repeat(20).into_bytes();
+                let path = dir.path().join(format!("{}.patch", i));
+                std::fs::write(&path, BsdiffRust::diff_bytes(&old, &new, &config).unwrap()).unwrap();
+                path
+            })
+            .collect();
+        let trained = Dictionary::new(&train_dictionary(&patches, 4096).unwrap()).unwrap();
+        assert_eq!(Some(trained.id()), zstd::zstd_safe::get_dict_id_from_dict(trained.as_bytes()).map(|id| id.get()));
+        assert!(train_dictionary(&[], 4096).is_err());
+    }
+}
@@ -0,0 +1,1150 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use rayon::prelude::*;
+
+use crate::bsdiff_rust::{BsdiffRust, LinkMode, OptimizationConfig};
+use crate::error::BsdiffError;
+use crate::fastcdc;
+use crate::header::{sha256, DIGEST_LEN};
+use crate::paths;
+use crate::reflink;
+use crate::similarity::estimate_similarity_files;
+
+/// 目录差分包魔数
+pub const DIR_MAGIC: [u8; 5] = *b"BSRD\x01";
+
+/// 当前目录差分包格式版本；版本 2 增加了去重块条目，版本 3 增加了移动并修改的文件，
+/// 版本 4 增加了符号链接与文件元数据。写出时使用包内条目所需的最低版本，旧版本仍可读取不含新条目的差分包
+pub const DIR_FORMAT_VERSION: u8 = 4;
+
+/// 条目数上限，防止损坏的差分包触发超大分配
+const MAX_ENTRIES: u32 = 1 << 20;
+
+const OP_ADD: u8 = 1;
+const OP_DELETE: u8 = 2;
+const OP_PATCH: u8 = 3;
+const OP_RENAME: u8 = 4;
+const OP_CHUNK: u8 = 5;
+const OP_OLD_CHUNK: u8 = 6;
+const OP_ASSEMBLE: u8 = 7;
+const OP_MOVE_PATCH: u8 = 8;
+const OP_SYMLINK: u8 = 9;
+const OP_METADATA: u8 = 10;
+
+/// 记录与恢复的权限位：rwx 三组，不含 setuid/setgid/sticky
+#[cfg(unix)]
+const MODE_MASK: u32 = 0o777;
+
+/// 同名的已删除文件与新增文件相似度至少为此值时，才尝试按 "移动 + 补丁" 存放
+const MIN_MOVE_SIMILARITY: f64 = 0.5;
+
+/// 差分包条目，路径均为以 `/` 分隔的相对路径
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DirEntry {
+    /// 新增文件：原始大小 + Zstd 压缩的完整内容
+    Add { path: String, size: u64, data: Vec<u8> },
+    /// 删除文件
+    Delete { path: String },
+    /// 修改文件：单文件 bsdiff 补丁
+    Patch { path: String, patch: Vec<u8> },
+    /// 内容不变的移动：旧路径不再保留
+    Rename { from: String, to: String },
+    /// 去重块：原始大小 + Zstd 压缩的内容。块与旧目录块按出现顺序统一编号，供 `Assemble` 引用
+    Chunk { size: u64, data: Vec<u8> },
+    /// 旧目录中已有的块：旧文件中的区间及其 SHA-256，应用时读取并校验
+    OldChunk { path: String, offset: u64, size: u64, digest: [u8; DIGEST_LEN] },
+    /// 由先前定义的块依次拼接而成的新增文件
+    Assemble { path: String, chunks: Vec<u32> },
+    /// 移动并修改的文件：对旧路径的文件应用补丁得到新路径的文件，旧路径不再保留
+    MovePatch { from: String, to: String, patch: Vec<u8> },
+    /// 符号链接：目标按原样保存，应用时在所有文件写出之后创建
+    Symlink { path: String, target: String },
+    /// 文件元数据：权限位与可选的修改时间 (自 Unix 纪元起的纳秒)
+    Metadata { path: String, mode: u32, mtime: Option<i64> },
+}
+
+impl DirEntry {
+    /// 条目携带的数据字节数，用于估算差分包大小
+    fn payload_len(&self) -> u64 {
+        match self {
+            DirEntry::Add { data, .. } | DirEntry::Chunk { data, .. } => data.len() as u64,
+            DirEntry::Patch { patch, .. } | DirEntry::MovePatch { patch, .. } => patch.len() as u64,
+            DirEntry::Assemble { chunks, .. } => chunks.len() as u64 * 4,
+            DirEntry::Delete { .. }
+            | DirEntry::Rename { .. }
+            | DirEntry::OldChunk { .. }
+            | DirEntry::Symlink { .. }
+            | DirEntry::Metadata { .. } => 0,
+        }
+    }
+
+    /// 读取该条目所需的最低格式版本
+    fn min_version(&self) -> u8 {
+        match self {
+            DirEntry::Symlink { .. } | DirEntry::Metadata { .. } => 4,
+            DirEntry::MovePatch { .. } => 3,
+            DirEntry::Chunk { .. } | DirEntry::OldChunk { .. } | DirEntry::Assemble { .. } => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// 去重分块的状态：旧目录中的块与已写入差分包的块，均按 SHA-256 索引
+struct ChunkStore<'a> {
+    old: HashMap<[u8; DIGEST_LEN], (&'a str, u64, u64)>,
+    ids: HashMap<[u8; DIGEST_LEN], u32>,
+}
+
+impl<'a> ChunkStore<'a> {
+    /// 对旧目录中的所有文件分块 (并行)，建立块摘要到旧文件区间的索引
+    fn index(old_files: &'a BTreeMap<String, PathBuf>) -> Result<Self, BsdiffError> {
+        let chunked = old_files
+            .par_iter()
+            .map(|(path, source)| {
+                let data = fs::read(source).map_err(|e| format!("{}: {}", path, e))?;
+                Ok(fastcdc::chunks(&data)
+                    .into_iter()
+                    .map(|range| (sha256(&data[range.clone()]), (path.as_str(), range.start as u64, range.len() as u64)))
+                    .collect::<Vec<_>>())
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Self { old: chunked.into_iter().flatten().collect(), ids: HashMap::new() })
+    }
+
+    /// 把新增文件写为块引用：首次出现的块追加为 `OldChunk` (旧目录中已有) 或 `Chunk` 条目，之后只引用编号
+    fn add_file(&mut self, path: &str, data: &[u8], level: i32, entries: &mut Vec<DirEntry>) -> Result<(), BsdiffError> {
+        let mut chunks = Vec::new();
+        for range in fastcdc::chunks(data) {
+            let chunk = &data[range];
+            let digest = sha256(chunk);
+            let id = match self.ids.get(&digest) {
+                Some(id) => *id,
+                None => {
+                    let id = u32::try_from(self.ids.len()).map_err(|_| "Too many chunks in directory diff")?;
+                    entries.push(match self.old.get(&digest) {
+                        Some(&(from, offset, size)) => DirEntry::OldChunk { path: from.to_string(), offset, size, digest },
+                        None => DirEntry::Chunk { size: chunk.len() as u64, data: zstd::encode_all(chunk, level)? },
+                    });
+                    self.ids.insert(digest, id);
+                    id
+                }
+            };
+            chunks.push(id);
+        }
+        entries.push(DirEntry::Assemble { path: path.to_string(), chunks });
+        Ok(())
+    }
+}
+
+/// 目录差分/应用统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DirDiffSummary {
+    pub added: u32,
+    pub deleted: u32,
+    pub patched: u32,
+    pub renamed: u32,
+    pub unchanged: u32,
+}
+
+/// 递归比较两个目录，生成包含逐文件补丁与增删/重命名信息的差分包
+///
+/// 布局: `DIR_MAGIC | version: u8 | count: u32 LE | 条目...`。
+/// 只处理普通文件：空目录不记录，遇到符号链接直接报错。
+/// 新增文件与某个已删除文件内容相同时记为重命名；与同名的已删除文件相似时记为 "移动 + 补丁"，
+/// 只在补丁比压缩后的完整内容更小时采用。
+/// `config.dedup` 时新增文件按 FastCDC 分块存放，跨文件重复或旧目录中已有的块只存一次。
+/// `config.preserve_metadata` 时不再拒绝符号链接：新目录中的链接按目标记录 (旧目录中的链接忽略)，
+/// 并记录每个文件的权限位，`config.preserve_mtimes` 时还记录修改时间。
+pub fn diff_dir(old_dir: &Path, new_dir: &Path, bundle_file: &Path, config: &OptimizationConfig) -> Result<DirDiffSummary, BsdiffError> {
+    // 包中新增文件以明文存放，逐项加密并不能保护目录内容
+    if config.encryption_key.is_some() {
+        return Err("Directory bundles cannot be encrypted".into());
+    }
+    // patchDir 不接收字典，包内补丁必须能独立解码
+    if config.dictionary.is_some() {
+        return Err("Directory bundles cannot use a zstd dictionary".into());
+    }
+    let keep_metadata = config.preserve_metadata || config.preserve_mtimes;
+    let (old_files, _) = list_tree(Path::new(old_dir), keep_metadata)?;
+    let (new_files, new_links) = list_tree(Path::new(new_dir), keep_metadata)?;
+    let config = OptimizationConfig { progress: None, block_progress: None, phase_progress: None, ..config.clone() };
+
+    // 两侧都存在的文件：内容不同时生成补丁 (并行)
+    let common: Vec<&String> = new_files.keys().filter(|path| old_files.contains_key(*path)).collect();
+    let patches: Vec<Result<Option<DirEntry>, String>> = common
+        .par_iter()
+        .map(|path| {
+            let old = fs::read(&old_files[*path]).map_err(|e| format!("{}: {}", path, e))?;
+            let new = fs::read(&new_files[*path]).map_err(|e| format!("{}: {}", path, e))?;
+            if old == new {
+                return Ok(None);
+            }
+            let patch = BsdiffRust::diff_bytes(&old, &new, &config).map_err(|e| format!("{}: {}", path, e))?;
+            Ok(Some(DirEntry::Patch { path: (*path).clone(), patch }))
+        })
+        .collect();
+
+    let mut summary = DirDiffSummary::default();
+    let mut entries = Vec::new();
+    for patch in patches {
+        match patch? {
+            Some(entry) => {
+                summary.patched += 1;
+                entries.push(entry);
+            }
+            None => summary.unchanged += 1,
+        }
+    }
+
+    // 只在旧目录中的文件按内容摘要索引，新增文件内容相同时记为重命名
+    let mut removed: BTreeMap<[u8; DIGEST_LEN], Vec<&String>> = BTreeMap::new();
+    for path in old_files.keys().filter(|path| !new_files.contains_key(*path)) {
+        removed.entry(sha256(&fs::read(&old_files[path])?)).or_default().push(path);
+    }
+    let mut renamed_from = HashSet::new();
+    let mut added = Vec::new();
+    for path in new_files.keys().filter(|path| !old_files.contains_key(*path)) {
+        let source = removed.get(&sha256(&fs::read(&new_files[path])?)).and_then(|candidates| {
+            candidates.iter().find(|candidate| !renamed_from.contains(**candidate)).copied()
+        });
+        match source {
+            Some(from) => {
+                renamed_from.insert(from);
+                entries.push(DirEntry::Rename { from: from.clone(), to: path.clone() });
+                summary.renamed += 1;
+            }
+            None => added.push(path),
+        }
+    }
+
+    // 移动并修改的文件：与同名的已删除文件生成补丁 (并行)，补丁不比压缩后的完整内容小时仍按新增存放
+    let unmatched: Vec<&String> = removed.values().flatten().filter(|path| !renamed_from.contains(**path)).copied().collect();
+    let moves: Vec<Result<Option<(&String, DirEntry)>, String>> = moved_sources(&added, &unmatched, &old_files, &new_files)?
+        .into_par_iter()
+        .map(|(from, to)| {
+            let old = fs::read(&old_files[from]).map_err(|e| format!("{}: {}", from, e))?;
+            let new = fs::read(&new_files[to]).map_err(|e| format!("{}: {}", to, e))?;
+            let patch = BsdiffRust::diff_bytes(&old, &new, &config).map_err(|e| format!("{}: {}", to, e))?;
+            let full = zstd::encode_all(&new[..], config.compression_level).map_err(|e| format!("{}: {}", to, e))?;
+            Ok((patch.len() < full.len()).then(|| (from, DirEntry::MovePatch { from: from.clone(), to: to.clone(), patch })))
+        })
+        .collect();
+    let mut moved_to = HashSet::new();
+    for result in moves {
+        if let Some((from, entry)) = result? {
+            if let DirEntry::MovePatch { to, .. } = &entry {
+                moved_to.insert(to.clone());
+            }
+            renamed_from.insert(from);
+            entries.push(entry);
+            summary.renamed += 1;
+        }
+    }
+
+    let mut chunk_store = if config.dedup { Some(ChunkStore::index(&old_files)?) } else { None };
+    for path in added.into_iter().filter(|path| !moved_to.contains(path.as_str())) {
+        let data = fs::read(&new_files[path])?;
+        match &mut chunk_store {
+            Some(store) => store.add_file(path, &data, config.compression_level, &mut entries)?,
+            None => {
+                let compressed = zstd::encode_all(&data[..], config.compression_level)?;
+                entries.push(DirEntry::Add { path: path.clone(), size: data.len() as u64, data: compressed });
+            }
+        }
+        summary.added += 1;
+    }
+    for path in removed.values().flatten().filter(|path| !renamed_from.contains(**path)) {
+        entries.push(DirEntry::Delete { path: (*path).clone() });
+        summary.deleted += 1;
+    }
+    if keep_metadata {
+        for (path, target) in new_links {
+            entries.push(DirEntry::Symlink { path, target });
+        }
+        for (path, source) in &new_files {
+            entries.push(file_metadata(path, source, config.preserve_mtimes)?);
+        }
+    }
+
+    let payload_size = entries.iter().map(DirEntry::payload_len).sum();
+    let out_path = BsdiffRust::get_optimal_output_path(bundle_file, &config, payload_size)?;
+    let mut writer = BufWriter::with_capacity(64 * 1024, File::create(&out_path)?);
+    write_bundle(&mut writer, &entries)?;
+    writer.flush()?;
+    drop(writer);
+
+    BsdiffRust::finalize_output(&out_path, bundle_file, &config)?;
+    Ok(summary)
+}
+
+/// 为内容与所有已删除文件都不同的新增文件寻找移动来源：在未被使用的已删除文件中
+/// 取文件名相同且相似度最高的一个，相似度低于 `MIN_MOVE_SIMILARITY` 时不配对
+fn moved_sources<'a>(
+    added: &[&'a String],
+    unmatched: &[&'a String],
+    old_files: &BTreeMap<String, PathBuf>,
+    new_files: &BTreeMap<String, PathBuf>,
+) -> Result<Vec<(&'a String, &'a String)>, BsdiffError> {
+    let file_name = |path: &str| path.rsplit('/').next().unwrap_or(path).to_string();
+    let mut by_name: HashMap<String, Vec<&String>> = HashMap::new();
+    for path in unmatched {
+        by_name.entry(file_name(path)).or_default().push(path);
+    }
+
+    let mut pairs = Vec::new();
+    let mut used = HashSet::new();
+    for to in added {
+        let Some(candidates) = by_name.get(&file_name(to)) else { continue };
+        let mut best = None;
+        for from in candidates.iter().filter(|from| !used.contains(**from)) {
+            let score = estimate_similarity_files(&old_files[*from], &new_files[*to])?.score;
+            if score >= MIN_MOVE_SIMILARITY && best.is_none_or(|(_, best_score)| score > best_score) {
+                best = Some((*from, score));
+            }
+        }
+        if let Some((from, _)) = best {
+            used.insert(from);
+            pairs.push((from, *to));
+        }
+    }
+    Ok(pairs)
+}
+
+/// 将目录差分包应用到旧目录，在 `new_dir` 生成新目录树
+///
+/// 新目录先在 `<new_dir>.partial` 中构建，全部成功后再移动到 `new_dir`；
+/// `new_dir` 必须不存在。差分包路径不可信，包含绝对路径或 `..` 时拒绝。
+/// 设置了 `config.base_dir` 时三个参数与写出的每个文件都必须位于该目录之内，
+/// 符号链接的目标也不能是绝对路径或越出新目录树。
+/// 差分包记录了元数据时旧目录中的符号链接被忽略，新目录中的链接完全按差分包重建。
+/// 内容未变的文件按 `config.link_mode` 克隆、硬链接或复制。
+pub fn patch_dir(old_dir: &Path, new_dir: &Path, bundle_file: &Path, config: &OptimizationConfig) -> Result<DirDiffSummary, BsdiffError> {
+    let (old_dir, new_dir, bundle_file) = match &config.base_dir {
+        Some(base) => (paths::confine(base, old_dir)?, paths::confine(base, new_dir)?, paths::confine(base, bundle_file)?),
+        None => (old_dir.to_path_buf(), new_dir.to_path_buf(), bundle_file.to_path_buf()),
+    };
+    let (old_dir, new_dir, bundle_file) = (old_dir.as_path(), new_dir.as_path(), bundle_file.as_path());
+    if Path::new(new_dir).exists() {
+        return Err(format!("Output directory already exists: {}", new_dir.display()).into());
+    }
+    let entries = read_bundle(&fs::read(bundle_file)?)?;
+    let has_metadata = entries.iter().any(|entry| matches!(entry, DirEntry::Symlink { .. } | DirEntry::Metadata { .. }));
+    let (old_files, _) = list_tree(Path::new(old_dir), has_metadata)?;
+
+    let staging = paths::with_suffix(new_dir, ".partial");
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&staging)?;
+    match build_new_tree(&old_files, &entries, &staging, config) {
+        Ok(summary) => {
+            config.retry.run("Renaming output directory", || fs::rename(&staging, new_dir))?;
+            Ok(summary)
+        }
+        Err(e) => {
+            let _ = fs::remove_dir_all(&staging);
+            Err(e)
+        }
+    }
+}
+
+/// 按差分包条目在 `staging` 中构建新目录树
+fn build_new_tree(
+    old_files: &BTreeMap<String, PathBuf>,
+    entries: &[DirEntry],
+    staging: &Path,
+    config: &OptimizationConfig,
+) -> Result<DirDiffSummary, BsdiffError> {
+    let old_file = |path: &str| {
+        old_files
+            .get(path)
+            .ok_or_else(|| format!("Bundle does not match old directory: missing {}", path))
+    };
+    let target = |path: &str| -> Result<PathBuf, BsdiffError> {
+        let target = staging.join(safe_relative_path(path)?);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match &config.base_dir {
+            Some(base) => paths::confine(base, &target),
+            None => Ok(target),
+        }
+    };
+
+    // 需要恢复元数据的文件不与旧文件共享 inode
+    let with_metadata: HashSet<&str> = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            DirEntry::Metadata { path, .. } => Some(path.as_str()),
+            _ => None,
+        })
+        .collect();
+    let unchanged = |source: &Path, path: &str| -> Result<(), BsdiffError> {
+        let mode = match config.link_mode {
+            LinkMode::HardLink if with_metadata.contains(path) => LinkMode::Reflink,
+            mode => mode,
+        };
+        reflink::link_or_copy(source, &target(path)?, mode)?;
+        Ok(())
+    };
+
+    let mut summary = DirDiffSummary::default();
+    let mut consumed = BTreeSet::new();
+    let mut patches = Vec::new();
+    let mut chunks = Vec::new();
+    let mut links = Vec::new();
+    let mut metadata = Vec::new();
+    for entry in entries {
+        match entry {
+            DirEntry::Add { path, size, data } => {
+                fs::write(target(path)?, decode_exact(data, *size, path)?)?;
+                summary.added += 1;
+            }
+            DirEntry::Chunk { .. } | DirEntry::OldChunk { .. } => chunks.push(entry),
+            DirEntry::Assemble { path, chunks: ids } => {
+                let mut writer = BufWriter::with_capacity(64 * 1024, File::create(target(path)?)?);
+                for id in ids {
+                    let chunk = chunks
+                        .get(*id as usize)
+                        .ok_or_else(|| format!("Corrupt bundle: {} references undefined chunk {}", path, id))?;
+                    writer.write_all(&read_chunk(chunk, old_files)?)?;
+                }
+                writer.flush()?;
+                summary.added += 1;
+            }
+            DirEntry::Delete { path } => {
+                old_file(path)?;
+                consumed.insert(path.as_str());
+                summary.deleted += 1;
+            }
+            DirEntry::Patch { path, patch } => {
+                patches.push((old_file(path)?, target(path)?, patch));
+                consumed.insert(path.as_str());
+                summary.patched += 1;
+            }
+            DirEntry::Rename { from, to } => {
+                unchanged(old_file(from)?, to)?;
+                consumed.insert(from.as_str());
+                summary.renamed += 1;
+            }
+            DirEntry::MovePatch { from, to, patch } => {
+                patches.push((old_file(from)?, target(to)?, patch));
+                consumed.insert(from.as_str());
+                summary.renamed += 1;
+            }
+            DirEntry::Symlink { path, target: link } => {
+                if config.base_dir.is_some() && !link_stays_inside(path, link) {
+                    return Err(format!("Symlink escapes the new directory: {} -> {}", path, link).into());
+                }
+                links.push((path, link));
+            }
+            DirEntry::Metadata { path, mode, mtime } => metadata.push((path, *mode, *mtime)),
+        }
+    }
+
+    let errors: Vec<String> = patches
+        .par_iter()
+        .filter_map(|(old, new, patch)| {
+            let result = fs::read(old)
+                .map_err(|e| e.to_string())
+                .and_then(|old_data| BsdiffRust::patch_bytes(&old_data, patch, config).map_err(|e| e.to_string()))
+                .and_then(|new_data| fs::write(new, new_data).map_err(|e| e.to_string()));
+            result.err().map(|e| format!("{}: {}", old.display(), e))
+        })
+        .collect();
+    if !errors.is_empty() {
+        return Err(format!("{} of {} file patches failed: {}", errors.len(), patches.len(), errors.join("; ")).into());
+    }
+
+    // 未被差分包提及的旧文件原样保留
+    for (path, source) in old_files.iter().filter(|(path, _)| !consumed.contains(path.as_str())) {
+        unchanged(source, path)?;
+        summary.unchanged += 1;
+    }
+
+    // 元数据在所有文件写出之后恢复；符号链接最后创建，此后不再经由差分包中的路径写入任何内容
+    for (path, mode, mtime) in metadata {
+        restore_metadata(&target(path)?, mode, mtime).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    let root = fs::canonicalize(staging)?;
+    for (path, link) in links {
+        let target = target(path)?;
+        // 父目录经由先前创建的链接指向别处时拒绝，避免在新目录树之外创建链接
+        let parent = target.parent().map(fs::canonicalize).transpose()?;
+        if !parent.is_some_and(|parent| parent.starts_with(&root)) {
+            return Err(format!("Unsafe path in bundle: {}", path).into());
+        }
+        create_symlink(link, &target).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    Ok(summary)
+}
+
+/// 读取文件的权限位 (非 Unix 平台只区分只读) 与可选的修改时间
+fn file_metadata(path: &str, source: &Path, with_mtime: bool) -> Result<DirEntry, BsdiffError> {
+    let metadata = fs::metadata(source).map_err(|e| format!("{}: {}", path, e))?;
+    #[cfg(unix)]
+    let mode = std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & MODE_MASK;
+    #[cfg(not(unix))]
+    let mode = if metadata.permissions().readonly() { 0o444 } else { 0o644 };
+    let mtime = if with_mtime {
+        let nanos = match metadata.modified()?.duration_since(UNIX_EPOCH) {
+            Ok(after) => i64::try_from(after.as_nanos()),
+            Err(before) => i64::try_from(before.duration().as_nanos()).map(|nanos| -nanos),
+        };
+        Some(nanos.map_err(|_| format!("{}: modification time out of range", path))?)
+    } else {
+        None
+    };
+    Ok(DirEntry::Metadata { path: path.to_string(), mode, mtime })
+}
+
+/// 恢复文件的修改时间与权限位；先改时间，权限位可能使文件变为只读
+fn restore_metadata(file: &Path, mode: u32, mtime: Option<i64>) -> io::Result<()> {
+    if let Some(nanos) = mtime {
+        let time = if nanos >= 0 {
+            UNIX_EPOCH + Duration::from_nanos(nanos as u64)
+        } else {
+            UNIX_EPOCH - Duration::from_nanos(nanos.unsigned_abs())
+        };
+        File::options().write(true).open(file)?.set_modified(time)?;
+    }
+    #[cfg(unix)]
+    fs::set_permissions(file, std::os::unix::fs::PermissionsExt::from_mode(mode & MODE_MASK))?;
+    #[cfg(not(unix))]
+    {
+        let mut permissions = fs::metadata(file)?.permissions();
+        permissions.set_readonly(mode & 0o222 == 0);
+        fs::set_permissions(file, permissions)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &str, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn create_symlink(_target: &str, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "symlinks are not supported on this platform"))
+}
+
+/// 链接目标是否留在新目录树内：不是绝对路径，且从链接所在目录起逐级解析 `..` 不越过根目录 (按字面判断)
+fn link_stays_inside(path: &str, target: &str) -> bool {
+    let mut depth = path.split('/').count() - 1;
+    for component in Path::new(target).components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// 解压条目数据并校验解压后的大小
+fn decode_exact(data: &[u8], size: u64, path: &str) -> Result<Vec<u8>, BsdiffError> {
+    let mut content = Vec::new();
+    zstd::stream::Decoder::new(data)?.take(size.saturating_add(1)).read_to_end(&mut content)?;
+    if content.len() as u64 != size {
+        return Err(format!("Corrupt bundle: {} decodes to {} bytes, expected {}", path, content.len(), size).into());
+    }
+    Ok(content)
+}
+
+/// 取出去重块的内容：包内的块解压后校验大小，旧目录中的块读取后校验摘要
+fn read_chunk(chunk: &DirEntry, old_files: &BTreeMap<String, PathBuf>) -> Result<Vec<u8>, BsdiffError> {
+    match chunk {
+        DirEntry::Chunk { size, data } => decode_exact(data, *size, "chunk"),
+        DirEntry::OldChunk { path, offset, size, digest } => {
+            let source = old_files
+                .get(path)
+                .ok_or_else(|| format!("Bundle does not match old directory: missing {}", path))?;
+            let mut file = File::open(source)?;
+            file.seek(SeekFrom::Start(*offset))?;
+            let mut content = Vec::new();
+            file.take(*size).read_to_end(&mut content)?;
+            if content.len() as u64 != *size || sha256(&content) != *digest {
+                return Err(format!("Bundle does not match old directory: {} has changed", path).into());
+            }
+            Ok(content)
+        }
+        _ => Err("Corrupt bundle: not a chunk".into()),
+    }
+}
+
+/// 递归列出目录下的普通文件：`/` 分隔的相对路径 → 实际路径
+pub(crate) fn list_files(root: &Path) -> Result<BTreeMap<String, PathBuf>, BsdiffError> {
+    Ok(list_tree(root, false)?.0)
+}
+
+/// 目录树中的普通文件 (相对路径 → 实际路径) 与符号链接 (相对路径 → 链接目标)
+type Tree = (BTreeMap<String, PathBuf>, BTreeMap<String, String>);
+
+/// 递归列出目录下的普通文件与符号链接；`with_links` 为 false 时遇到符号链接报错
+fn list_tree(root: &Path, with_links: bool) -> Result<Tree, BsdiffError> {
+    if !root.is_dir() {
+        return Err(BsdiffError::FileNotFound { kind: "Directory", path: root.display().to_string() });
+    }
+    let mut files = BTreeMap::new();
+    let mut links = BTreeMap::new();
+    let mut pending = vec![(root.to_path_buf(), String::new())];
+    while let Some((dir, prefix)) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let name = entry.file_name().into_string()
+                .map_err(|name| format!("Non UTF-8 file name in {}: {:?}", dir.display(), name))?;
+            let relative = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+            let file_type = entry.file_type()?;
+            if file_type.is_symlink() {
+                if !with_links {
+                    return Err(format!("Symlinks are not supported: {}", entry.path().display()).into());
+                }
+                let target = fs::read_link(entry.path())?.into_os_string().into_string()
+                    .map_err(|target| format!("Non UTF-8 symlink target in {}: {:?}", entry.path().display(), target))?;
+                links.insert(relative, target);
+            } else if file_type.is_dir() {
+                pending.push((entry.path(), relative));
+            } else {
+                files.insert(relative, entry.path());
+            }
+        }
+    }
+    Ok((files, links))
+}
+
+/// 校验差分包中的相对路径，拒绝绝对路径与 `..`
+pub(crate) fn safe_relative_path(path: &str) -> Result<PathBuf, BsdiffError> {
+    let relative = PathBuf::from(path);
+    let safe = !path.is_empty() && relative.components().all(|component| matches!(component, Component::Normal(_)));
+    if !safe {
+        return Err(format!("Unsafe path in bundle: {}", path).into());
+    }
+    Ok(relative)
+}
+
+fn write_bundle<W: Write>(writer: &mut W, entries: &[DirEntry]) -> Result<(), BsdiffError> {
+    let count = u32::try_from(entries.len())
+        .ok()
+        .filter(|count| *count <= MAX_ENTRIES)
+        .ok_or("Too many files in directory diff")?;
+    let version = entries.iter().map(DirEntry::min_version).max().unwrap_or(1);
+    writer.write_all(&DIR_MAGIC)?;
+    writer.write_all(&[version])?;
+    writer.write_all(&count.to_le_bytes())?;
+    for entry in entries {
+        match entry {
+            DirEntry::Add { path, size, data } => {
+                writer.write_all(&[OP_ADD])?;
+                write_path(writer, path)?;
+                writer.write_all(&size.to_le_bytes())?;
+                write_blob(writer, data)?;
+            }
+            DirEntry::Delete { path } => {
+                writer.write_all(&[OP_DELETE])?;
+                write_path(writer, path)?;
+            }
+            DirEntry::Patch { path, patch } => {
+                writer.write_all(&[OP_PATCH])?;
+                write_path(writer, path)?;
+                write_blob(writer, patch)?;
+            }
+            DirEntry::Rename { from, to } => {
+                writer.write_all(&[OP_RENAME])?;
+                write_path(writer, from)?;
+                write_path(writer, to)?;
+            }
+            DirEntry::MovePatch { from, to, patch } => {
+                writer.write_all(&[OP_MOVE_PATCH])?;
+                write_path(writer, from)?;
+                write_path(writer, to)?;
+                write_blob(writer, patch)?;
+            }
+            DirEntry::Symlink { path, target } => {
+                writer.write_all(&[OP_SYMLINK])?;
+                write_path(writer, path)?;
+                write_path(writer, target)?;
+            }
+            DirEntry::Metadata { path, mode, mtime } => {
+                writer.write_all(&[OP_METADATA])?;
+                write_path(writer, path)?;
+                writer.write_all(&mode.to_le_bytes())?;
+                match mtime {
+                    Some(mtime) => {
+                        writer.write_all(&[1])?;
+                        writer.write_all(&mtime.to_le_bytes())?;
+                    }
+                    None => writer.write_all(&[0])?,
+                }
+            }
+            DirEntry::Chunk { size, data } => {
+                writer.write_all(&[OP_CHUNK])?;
+                writer.write_all(&size.to_le_bytes())?;
+                write_blob(writer, data)?;
+            }
+            DirEntry::OldChunk { path, offset, size, digest } => {
+                writer.write_all(&[OP_OLD_CHUNK])?;
+                write_path(writer, path)?;
+                writer.write_all(&offset.to_le_bytes())?;
+                writer.write_all(&size.to_le_bytes())?;
+                writer.write_all(digest)?;
+            }
+            DirEntry::Assemble { path, chunks } => {
+                writer.write_all(&[OP_ASSEMBLE])?;
+                write_path(writer, path)?;
+                let count = u32::try_from(chunks.len()).map_err(|_| format!("Too many chunks in {}", path))?;
+                writer.write_all(&count.to_le_bytes())?;
+                for id in chunks {
+                    writer.write_all(&id.to_le_bytes())?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_path<W: Write>(writer: &mut W, path: &str) -> Result<(), BsdiffError> {
+    let len = u16::try_from(path.len()).map_err(|_| format!("Path too long: {}", path))?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(path.as_bytes())?;
+    Ok(())
+}
+
+fn write_blob<W: Write>(writer: &mut W, data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u64).to_le_bytes())?;
+    writer.write_all(data)
+}
+
+/// 解析差分包，所有长度都按剩余数据校验
+fn read_bundle(data: &[u8]) -> Result<Vec<DirEntry>, BsdiffError> {
+    let mut reader = BundleReader { data };
+    if reader.bytes(DIR_MAGIC.len()).ok() != Some(&DIR_MAGIC[..]) {
+        return Err("Not a directory diff bundle".into());
+    }
+    let version = reader.bytes(1)?[0];
+    if version > DIR_FORMAT_VERSION {
+        return Err(format!(
+            "Unsupported directory diff format version: {} (supported up to {})",
+            version, DIR_FORMAT_VERSION
+        ).into());
+    }
+    let count = u32::from_le_bytes(reader.bytes(4)?.try_into()?);
+    if count > MAX_ENTRIES {
+        return Err(format!("Corrupt bundle: {} entries", count).into());
+    }
+
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let entry = match reader.bytes(1)?[0] {
+            OP_ADD => {
+                let path = reader.path()?;
+                let size = reader.u64()?;
+                DirEntry::Add { path, size, data: reader.blob()?.to_vec() }
+            }
+            OP_DELETE => DirEntry::Delete { path: reader.path()? },
+            OP_PATCH => DirEntry::Patch { path: reader.path()?, patch: reader.blob()?.to_vec() },
+            OP_RENAME => DirEntry::Rename { from: reader.path()?, to: reader.path()? },
+            OP_MOVE_PATCH => DirEntry::MovePatch { from: reader.path()?, to: reader.path()?, patch: reader.blob()?.to_vec() },
+            OP_SYMLINK => DirEntry::Symlink { path: reader.path()?, target: reader.path()? },
+            OP_METADATA => {
+                let path = reader.path()?;
+                let mode = u32::from_le_bytes(reader.bytes(4)?.try_into()?);
+                let mtime = match reader.bytes(1)?[0] {
+                    0 => None,
+                    1 => Some(reader.u64()? as i64),
+                    flag => return Err(format!("Corrupt bundle: bad metadata flag {}", flag).into()),
+                };
+                DirEntry::Metadata { path, mode, mtime }
+            }
+            OP_CHUNK => {
+                let size = reader.u64()?;
+                DirEntry::Chunk { size, data: reader.blob()?.to_vec() }
+            }
+            OP_OLD_CHUNK => {
+                let path = reader.path()?;
+                let (offset, size) = (reader.u64()?, reader.u64()?);
+                DirEntry::OldChunk { path, offset, size, digest: reader.bytes(DIGEST_LEN)?.try_into()? }
+            }
+            OP_ASSEMBLE => {
+                let path = reader.path()?;
+                let count = u32::from_le_bytes(reader.bytes(4)?.try_into()?) as usize;
+                let ids = reader.bytes(count.checked_mul(4).ok_or("Corrupt bundle: truncated")?)?;
+                let chunks = ids.chunks_exact(4).map(|id| u32::from_le_bytes(id.try_into().expect("4-byte chunk id"))).collect();
+                DirEntry::Assemble { path, chunks }
+            }
+            op => return Err(format!("Corrupt bundle: unknown entry type {}", op).into()),
+        };
+        entries.push(entry);
+    }
+    if !reader.data.is_empty() {
+        return Err("Corrupt bundle: trailing data".into());
+    }
+    Ok(entries)
+}
+
+struct BundleReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> BundleReader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], BsdiffError> {
+        if len > self.data.len() {
+            return Err("Corrupt bundle: truncated".into());
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn u64(&mut self) -> Result<u64, BsdiffError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into()?))
+    }
+
+    fn path(&mut self) -> Result<String, BsdiffError> {
+        let len = u16::from_le_bytes(self.bytes(2)?.try_into()?) as usize;
+        Ok(String::from_utf8(self.bytes(len)?.to_vec()).map_err(|_| "Corrupt bundle: path is not UTF-8")?)
+    }
+
+    fn blob(&mut self) -> Result<&'a [u8], BsdiffError> {
+        let len = usize::try_from(self.u64()?).map_err(|_| "Corrupt bundle: truncated")?;
+        self.bytes(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write_tree(root: &Path, files: &[(&str, Vec<u8>)]) {
+        for (path, data) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, data).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_diff_and_patch_dir() {
+        let dir = TempDir::new().unwrap();
+        let (old, new) = (dir.path().join("old"), dir.path().join("new"));
+        let app = b"application binary v1 ".repeat(200);
+        let mut app2 = app.clone();
+        app2[100..120].fill(b'!');
+        write_tree(&old, &[
+            ("bin/app", app.clone()),
+            ("lib/keep.so", b"unchanged library".repeat(10)),
+            ("assets/logo.png", b"logo bytes".repeat(30)),
+            ("obsolete.txt", b"remove me".to_vec()),
+        ]);
+        write_tree(&new, &[
+            ("bin/app", app2),
+            ("lib/keep.so", b"unchanged library".repeat(10)),
+            ("res/images/logo.png", b"logo bytes".repeat(30)),
+            ("docs/readme.md", b"brand new file".to_vec()),
+        ]);
+
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let bundle = dir.path().join("update.bsrd");
+        let summary = diff_dir(&old, &new, &bundle, &config).unwrap();
+        let expected = DirDiffSummary { added: 1, deleted: 1, patched: 1, renamed: 1, unchanged: 1 };
+        assert_eq!(summary, expected);
+
+        let out = dir.path().join("out");
+        let applied = patch_dir(&old, &out, &bundle, &config).unwrap();
+        assert_eq!(applied, expected);
+        let rebuilt = list_files(&out).unwrap();
+        let wanted = list_files(&new).unwrap();
+        assert_eq!(rebuilt.keys().collect::<Vec<_>>(), wanted.keys().collect::<Vec<_>>());
+        for (path, source) in &wanted {
+            assert_eq!(fs::read(&rebuilt[path]).unwrap(), fs::read(source).unwrap(), "{}", path);
+        }
+
+        // 输出目录已存在时拒绝
+        assert!(patch_dir(&old, &out, &bundle, &config).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_link_unchanged_files() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = TempDir::new().unwrap();
+        let (old, new) = (dir.path().join("old"), dir.path().join("new"));
+        write_tree(&old, &[("keep.bin", b"unchanged".repeat(100)), ("moved.bin", b"moved".repeat(100)), ("app", b"v1".to_vec())]);
+        write_tree(&new, &[("keep.bin", b"unchanged".repeat(100)), ("sub/moved.bin", b"moved".repeat(100)), ("app", b"v2".to_vec())]);
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let bundle = dir.path().join("update.bsrd");
+        diff_dir(&old, &new, &bundle, &config).unwrap();
+
+        let inode = |path: PathBuf| fs::metadata(path).unwrap().ino();
+        let same_inode = |out: &Path, path: &str, from: &str| inode(out.join(path)) == inode(old.join(from));
+        for mode in [LinkMode::Copy, LinkMode::Reflink, LinkMode::HardLink] {
+            let out = dir.path().join(format!("out-{:?}", mode));
+            let config = OptimizationConfig { link_mode: mode, ..config.clone() };
+            patch_dir(&old, &out, &bundle, &config).unwrap();
+            assert_eq!(fs::read(out.join("keep.bin")).unwrap(), b"unchanged".repeat(100));
+            assert_eq!(fs::read(out.join("sub/moved.bin")).unwrap(), b"moved".repeat(100));
+            let linked = mode == LinkMode::HardLink;
+            assert_eq!((same_inode(&out, "keep.bin", "keep.bin"), same_inode(&out, "sub/moved.bin", "moved.bin")), (linked, linked));
+        }
+
+        // 记录了元数据的文件不硬链接，恢复权限不会改动旧文件
+        let bundle = dir.path().join("meta.bsrd");
+        diff_dir(&old, &new, &bundle, &OptimizationConfig { preserve_metadata: true, ..config.clone() }).unwrap();
+        let out = dir.path().join("out-meta");
+        patch_dir(&old, &out, &bundle, &OptimizationConfig { link_mode: LinkMode::HardLink, ..config }).unwrap();
+        assert!(!same_inode(&out, "keep.bin", "keep.bin"));
+        assert_eq!(fs::read(out.join("keep.bin")).unwrap(), b"unchanged".repeat(100));
+    }
+
+    #[test]
+    fn test_moved_and_modified_files() {
+        let dir = TempDir::new().unwrap();
+        let (old, new) = (dir.path().join("old"), dir.path().join("new"));
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let mut noise = |len: usize| -> Vec<u8> {
+            (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect()
+        };
+        let module = noise(64 * 1024);
+        let mut edited = module.clone();
+        edited[1000..1016].fill(0);
+        let (config_v1, config_v2) = (noise(8 * 1024), noise(8 * 1024));
+        let other = noise(32 * 1024);
+        write_tree(&old, &[
+            ("src/module.bin", module.clone()),
+            ("src/config.dat", config_v1),
+            ("src/other.bin", other.clone()),
+        ]);
+        write_tree(&new, &[
+            ("lib/core/module.bin", edited),
+            // 同名但内容完全不同：仍按新增 + 删除存放
+            ("lib/config.dat", config_v2),
+            // 名称不同的文件不配对
+            ("lib/renamed.bin", [&other[..], b"tail"].concat()),
+        ]);
+
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let bundle = dir.path().join("update.bsrd");
+        let summary = diff_dir(&old, &new, &bundle, &config).unwrap();
+        assert_eq!(summary, DirDiffSummary { added: 2, deleted: 2, patched: 0, renamed: 1, unchanged: 0 });
+        let data = fs::read(&bundle).unwrap();
+        assert_eq!(data[DIR_MAGIC.len()], 3);
+        // 补丁远小于完整的模块内容
+        assert!(data.len() < 64 * 1024, "{} bytes", data.len());
+        let entries = read_bundle(&data).unwrap();
+        assert!(entries.iter().any(|entry| matches!(entry, DirEntry::MovePatch { from, to, .. } if from == "src/module.bin" && to == "lib/core/module.bin")));
+
+        let out = dir.path().join("out");
+        assert_eq!(patch_dir(&old, &out, &bundle, &config).unwrap(), summary);
+        let wanted = list_files(&new).unwrap();
+        let rebuilt = list_files(&out).unwrap();
+        assert_eq!(rebuilt.keys().collect::<Vec<_>>(), wanted.keys().collect::<Vec<_>>());
+        for (path, source) in &wanted {
+            assert_eq!(fs::read(&rebuilt[path]).unwrap(), fs::read(source).unwrap(), "{}", path);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_preserve_metadata() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+        use std::time::SystemTime;
+
+        let dir = TempDir::new().unwrap();
+        let (old, new) = (dir.path().join("old"), dir.path().join("new"));
+        write_tree(&old, &[("bin/tool", b"#!/bin/sh\necho v1\n".to_vec()), ("lib/libfoo.so.1", b"foo v1".to_vec())]);
+        symlink("libfoo.so.1", old.join("lib/libfoo.so")).unwrap();
+        write_tree(&new, &[
+            ("bin/tool", b"#!/bin/sh\necho v2\n".to_vec()),
+            ("lib/libfoo.so.2", b"foo v2".to_vec()),
+            ("share/readme", b"docs".to_vec()),
+        ]);
+        symlink("libfoo.so.2", new.join("lib/libfoo.so")).unwrap();
+        symlink("../lib", new.join("bin/lib")).unwrap();
+        fs::set_permissions(new.join("bin/tool"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::set_permissions(new.join("share/readme"), fs::Permissions::from_mode(0o600)).unwrap();
+        let stamp = SystemTime::UNIX_EPOCH + Duration::new(1_600_000_000, 123_456_789);
+        File::options().write(true).open(new.join("share/readme")).unwrap().set_modified(stamp).unwrap();
+
+        // 默认仍拒绝符号链接
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let bundle = dir.path().join("update.bsrd");
+        assert!(diff_dir(&old, &new, &bundle, &config).is_err());
+
+        let config = OptimizationConfig { preserve_mtimes: true, ..config };
+        diff_dir(&old, &new, &bundle, &config).unwrap();
+        assert_eq!(fs::read(&bundle).unwrap()[DIR_MAGIC.len()], DIR_FORMAT_VERSION);
+        let out = dir.path().join("out");
+        patch_dir(&old, &out, &bundle, &config).unwrap();
+        assert_eq!(fs::read_link(out.join("lib/libfoo.so")).unwrap(), Path::new("libfoo.so.2"));
+        assert_eq!(fs::read(out.join("bin/lib/libfoo.so")).unwrap(), b"foo v2");
+        assert_eq!(fs::read(out.join("bin/tool")).unwrap(), b"#!/bin/sh\necho v2\n");
+        let mode = |path: &str| fs::metadata(out.join(path)).unwrap().permissions().mode() & MODE_MASK;
+        assert_eq!(mode("bin/tool"), 0o755);
+        assert_eq!(mode("share/readme"), 0o600);
+        assert_eq!(fs::metadata(out.join("share/readme")).unwrap().modified().unwrap(), stamp);
+
+        // 沙箱中拒绝越出新目录树的链接目标
+        assert!(link_stays_inside("bin/lib", "../lib"));
+        assert!(!link_stays_inside("bin/lib", "../../etc"));
+        assert!(!link_stays_inside("lib", "/etc"));
+        let mut bundle = Vec::new();
+        write_bundle(&mut bundle, &[DirEntry::Symlink { path: "escape".to_string(), target: "../..".to_string() }]).unwrap();
+        fs::write(dir.path().join("escape.bsrd"), &bundle).unwrap();
+        let jailed = OptimizationConfig { base_dir: Some(dir.path().to_path_buf()), ..config.clone() };
+        let err = patch_dir(&old, &dir.path().join("out2"), &dir.path().join("escape.bsrd"), &jailed).unwrap_err();
+        assert!(err.to_string().contains("escapes"), "{}", err);
+
+        // 链接之后的条目不能经由链接写到新目录树之外
+        let outside = dir.path().join("outside");
+        fs::create_dir(&outside).unwrap();
+        let mut bundle = Vec::new();
+        write_bundle(&mut bundle, &[
+            DirEntry::Symlink { path: "a".to_string(), target: outside.display().to_string() },
+            DirEntry::Symlink { path: "a/b".to_string(), target: "x".to_string() },
+        ]).unwrap();
+        fs::write(dir.path().join("nested.bsrd"), &bundle).unwrap();
+        assert!(patch_dir(&old, &dir.path().join("out3"), &dir.path().join("nested.bsrd"), &config).is_err());
+        assert!(fs::read_dir(&outside).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn test_dedup_chunks() {
+        let dir = TempDir::new().unwrap();
+        let (old, new) = (dir.path().join("old"), dir.path().join("new"));
+        // 不可压缩的资源数据，重复只能靠去重消除
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut noise = |len: usize| -> Vec<u8> {
+            (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect()
+        };
+        let texture = noise(200 * 1024);
+        let sound = noise(150 * 1024);
+        let pack = [&texture[..], &noise(10 * 1024)[..], &sound[..]].concat();
+        write_tree(&old, &[("sounds/theme.ogg", sound.clone()), ("readme.txt", b"v1".to_vec())]);
+        write_tree(&new, &[
+            ("sounds/theme.ogg", sound.clone()),
+            ("readme.txt", b"v1".to_vec()),
+            ("levels/1/texture.dds", texture.clone()),
+            ("levels/2/texture.dds", [&texture[..], b"variant"].concat()),
+            ("packs/all.pak", pack),
+            ("empty.dat", Vec::new()),
+        ]);
+
+        let plain_config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let config = OptimizationConfig { dedup: true, ..plain_config.clone() };
+        let (plain, deduped) = (dir.path().join("plain.bsrd"), dir.path().join("dedup.bsrd"));
+        let summary = diff_dir(&old, &new, &plain, &plain_config).unwrap();
+        assert_eq!(diff_dir(&old, &new, &deduped, &config).unwrap(), summary);
+        assert_eq!(fs::read(&plain).unwrap()[DIR_MAGIC.len()], 1);
+        assert_eq!(fs::read(&deduped).unwrap()[DIR_MAGIC.len()], 2);
+        // 纹理只存一次，包内的音效引用旧目录中的数据
+        let (plain_len, dedup_len) = (fs::metadata(&plain).unwrap().len(), fs::metadata(&deduped).unwrap().len());
+        assert!(dedup_len < 260 * 1024, "{} bytes", dedup_len);
+        assert!(plain_len > 750 * 1024, "{} bytes", plain_len);
+        let entries = read_bundle(&fs::read(&deduped).unwrap()).unwrap();
+        assert!(entries.iter().any(|entry| matches!(entry, DirEntry::OldChunk { path, .. } if path == "sounds/theme.ogg")));
+
+        let out = dir.path().join("out");
+        assert_eq!(patch_dir(&old, &out, &deduped, &config).unwrap(), summary);
+        let wanted = list_files(&new).unwrap();
+        let rebuilt = list_files(&out).unwrap();
+        assert_eq!(rebuilt.keys().collect::<Vec<_>>(), wanted.keys().collect::<Vec<_>>());
+        for (path, source) in &wanted {
+            assert_eq!(fs::read(&rebuilt[path]).unwrap(), fs::read(source).unwrap(), "{}", path);
+        }
+
+        // 旧目录中被引用的数据改变时拒绝，且不留下输出
+        let mut changed = sound.clone();
+        changed[100_000] ^= 0xFF;
+        fs::write(old.join("sounds/theme.ogg"), changed).unwrap();
+        let out = dir.path().join("out2");
+        assert!(patch_dir(&old, &out, &deduped, &config).unwrap_err().to_string().contains("sounds/theme.ogg"));
+        assert!(!out.exists());
+
+        // 引用未定义的块
+        let mut bundle = Vec::new();
+        write_bundle(&mut bundle, &[DirEntry::Assemble { path: "x".to_string(), chunks: vec![0] }]).unwrap();
+        fs::write(dir.path().join("bad.bsrd"), &bundle).unwrap();
+        assert!(patch_dir(&old, &dir.path().join("out3"), &dir.path().join("bad.bsrd"), &config).is_err());
+        assert!(read_bundle(&bundle[..bundle.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_base_dir_jail() {
+        let dir = TempDir::new().unwrap();
+        let base = dir.path().join("base");
+        let (old, new) = (base.join("old"), dir.path().join("new"));
+        write_tree(&old, &[("a.txt", b"old a".repeat(20))]);
+        write_tree(&new, &[("a.txt", b"new a".repeat(20)), ("sub/b.txt", b"b".to_vec())]);
+        let bundle = base.join("update.bsrd");
+        diff_dir(&old, &new, &bundle, &OptimizationConfig { use_fast_temp_dir: false, ..Default::default() }).unwrap();
+
+        let config = OptimizationConfig { base_dir: Some(base.clone()), ..Default::default() };
+        // 相对路径基于沙箱根目录解析
+        patch_dir(Path::new("old"), Path::new("out"), Path::new("update.bsrd"), &config).unwrap();
+        assert_eq!(fs::read(base.join("out/sub/b.txt")).unwrap(), b"b");
+
+        // 输出、旧目录或差分包位于沙箱外时在读取前拒绝
+        let outside = dir.path().join("out");
+        assert!(patch_dir(&old, &outside, &bundle, &config).unwrap_err().to_string().contains("escapes base directory"));
+        assert!(patch_dir(&old, Path::new("../out"), &bundle, &config).is_err());
+        assert!(patch_dir(&new, &base.join("out2"), &bundle, &config).is_err());
+        assert!(!outside.exists() && !base.join("out2").exists());
+
+        #[cfg(unix)]
+        {
+            // 经由符号链接逃逸
+            fs::create_dir(dir.path().join("elsewhere")).unwrap();
+            std::os::unix::fs::symlink(dir.path().join("elsewhere"), base.join("link")).unwrap();
+            assert!(patch_dir(&old, &base.join("link/out"), &bundle, &config).is_err());
+            assert!(fs::read_dir(dir.path().join("elsewhere")).unwrap().next().is_none());
+        }
+    }
+
+    #[test]
+    fn test_bundle_rejects_unsafe_paths() {
+        let dir = TempDir::new().unwrap();
+        let old = dir.path().join("old");
+        write_tree(&old, &[("a.txt", b"a".to_vec())]);
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+
+        for path in ["../escape.txt", "/etc/passwd", ""] {
+            let mut bundle = Vec::new();
+            let data = zstd::encode_all(&b"x"[..], 3).unwrap();
+            write_bundle(&mut bundle, &[DirEntry::Add { path: path.to_string(), size: 1, data }]).unwrap();
+            let bundle_file = dir.path().join("evil.bsrd");
+            fs::write(&bundle_file, &bundle).unwrap();
+
+            let out = dir.path().join("out");
+            assert!(patch_dir(&old, &out, &bundle_file, &config).is_err());
+            assert!(!out.exists() && !dir.path().join("out.partial").exists());
+        }
+        assert!(!dir.path().join("escape.txt").exists());
+
+        // 截断的差分包
+        let mut bundle = Vec::new();
+        write_bundle(&mut bundle, &[DirEntry::Delete { path: "a.txt".to_string() }]).unwrap();
+        assert!(read_bundle(&bundle[..bundle.len() - 1]).is_err());
+        assert_eq!(read_bundle(&bundle).unwrap(), vec![DirEntry::Delete { path: "a.txt".to_string() }]);
+    }
+}
@@ -0,0 +1,116 @@
+//! 补丁加密信封：对完整补丁 (含补丁头) 做 AES-256-GCM 加密，分发渠道不可信时防止泄露新版本内容。
+//!
+//! 信封格式：`BSRE\x01` 魔数 (5 字节) + nonce (12 字节) + 密文 + 认证标签 (16 字节)，
+//! 魔数与 nonce 作为附加认证数据。每次加密使用操作系统随机数生成的 nonce，
+//! 相同补丁加密后也互不相同，密文不会泄露两个补丁是否一致
+
+use std::path::Path;
+
+use aes_gcm::aead::{AeadInPlace, KeyInit, OsRng};
+use aes_gcm::{AeadCore, Aes256Gcm, Nonce, Tag};
+
+use crate::error::BsdiffError;
+
+/// 加密信封魔数
+pub const ENVELOPE_MAGIC: [u8; 5] = *b"BSRE\x01";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const HEADER_LEN: usize = ENVELOPE_MAGIC.len() + NONCE_LEN;
+/// 单次加密的最大明文长度：32 位计数器最多 2^32 - 2 个分组
+const MAX_PLAINTEXT_LEN: u64 = ((1u64 << 32) - 2) * 16;
+
+/// 32 字节 AES-256 密钥；Debug 输出不包含密钥内容
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; KEY_LEN]);
+
+impl EncryptionKey {
+    pub fn new(key: &[u8]) -> Result<Self, BsdiffError> {
+        let key = key
+            .try_into()
+            .map_err(|_| format!("Encryption key must be {} bytes, got {}", KEY_LEN, key.len()))?;
+        Ok(Self(key))
+    }
+}
+
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+/// 数据是否以加密信封魔数开头
+pub fn is_encrypted(head: &[u8]) -> bool {
+    head.starts_with(&ENVELOPE_MAGIC)
+}
+
+/// 把补丁封装进加密信封
+pub fn seal(key: &EncryptionKey, patch: &[u8]) -> Result<Vec<u8>, BsdiffError> {
+    if patch.len() as u64 > MAX_PLAINTEXT_LEN {
+        return Err(format!("Patch is too large to encrypt ({} bytes, limit {})", patch.len(), MAX_PLAINTEXT_LEN).into());
+    }
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let mut envelope = Vec::with_capacity(HEADER_LEN + patch.len() + TAG_LEN);
+    envelope.extend_from_slice(&ENVELOPE_MAGIC);
+    envelope.extend_from_slice(&nonce);
+    envelope.extend_from_slice(patch);
+    let (header, body) = envelope.split_at_mut(HEADER_LEN);
+    let tag = Aes256Gcm::new(key.0.as_ref().into())
+        .encrypt_in_place_detached(&nonce, header, body)
+        .map_err(|_| "AES-256-GCM encryption failed")?;
+    envelope.extend_from_slice(&tag);
+    Ok(envelope)
+}
+
+/// 校验并解密加密信封，返回其中的补丁；密钥错误或信封被篡改时返回 [`BsdiffError::DecryptionFailed`]
+pub fn open(key: &EncryptionKey, mut envelope: Vec<u8>, patch_file: &Path) -> Result<Vec<u8>, BsdiffError> {
+    if !is_encrypted(&envelope) || envelope.len() < HEADER_LEN + TAG_LEN {
+        return Err(BsdiffError::CorruptPatch {
+            patch_file: patch_file.display().to_string(),
+            reason: "encrypted envelope is truncated".to_string(),
+        });
+    }
+    let tag = Tag::clone_from_slice(&envelope.split_off(envelope.len() - TAG_LEN));
+    let nonce = Nonce::clone_from_slice(&envelope[ENVELOPE_MAGIC.len()..HEADER_LEN]);
+    let (header, body) = envelope.split_at_mut(HEADER_LEN);
+    if Aes256Gcm::new(key.0.as_ref().into()).decrypt_in_place_detached(&nonce, header, body, &tag).is_err() {
+        return Err(BsdiffError::DecryptionFailed { patch_file: patch_file.display().to_string() });
+    }
+    envelope.drain(..HEADER_LEN);
+    Ok(envelope)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let key = EncryptionKey::new(&[3u8; KEY_LEN]).unwrap();
+        let patch = b"BSRZ pretend patch payload".to_vec();
+        let envelope = seal(&key, &patch).unwrap();
+        assert!(is_encrypted(&envelope));
+        assert_eq!(envelope.len(), HEADER_LEN + patch.len() + TAG_LEN);
+        assert!(!envelope.windows(patch.len()).any(|window| window == patch));
+        // 每次加密使用新的随机 nonce：相同补丁得到不同的信封，都能解密
+        let again = seal(&key, &patch).unwrap();
+        assert_ne!(again[ENVELOPE_MAGIC.len()..HEADER_LEN], envelope[ENVELOPE_MAGIC.len()..HEADER_LEN]);
+        assert_ne!(again[HEADER_LEN..], envelope[HEADER_LEN..]);
+        assert_eq!(open(&key, again, Path::new("p")).unwrap(), patch);
+        assert_eq!(open(&key, envelope.clone(), Path::new("p")).unwrap(), patch);
+
+        let wrong = EncryptionKey::new(&[4u8; KEY_LEN]).unwrap();
+        let err = open(&wrong, envelope.clone(), Path::new("p")).unwrap_err();
+        assert!(matches!(err, BsdiffError::DecryptionFailed { .. }));
+        for index in [ENVELOPE_MAGIC.len(), HEADER_LEN, envelope.len() - 1] {
+            let mut tampered = envelope.clone();
+            tampered[index] ^= 1;
+            assert!(open(&key, tampered, Path::new("p")).is_err());
+        }
+        let err = open(&key, envelope[..HEADER_LEN + 3].to_vec(), Path::new("p")).unwrap_err();
+        assert!(matches!(err, BsdiffError::CorruptPatch { .. }));
+
+        assert!(EncryptionKey::new(&[0u8; 16]).is_err());
+        assert_eq!(format!("{:?}", key), "EncryptionKey(..)");
+    }
+}
@@ -0,0 +1,434 @@
+use std::fmt;
+use std::io;
+
+use crate::header::FileDigest;
+
+/// 本库所有 API 返回的错误
+#[derive(Debug)]
+pub enum BsdiffError {
+    /// 输入文件或目录不存在；`kind` 描述其角色 (如 "Old file"、"Patch file")
+    FileNotFound {
+        kind: &'static str,
+        path: String,
+    },
+    /// 补丁参数不像补丁文件，很可能与旧文件参数位置颠倒
+    ArgumentsLikelySwapped {
+        patch_file: String,
+        detected: &'static str,
+    },
+    /// 文件既没有补丁头魔数，也不是旧版 zstd 补丁或 BSDIFF40 补丁
+    NotAPatch {
+        patch_file: String,
+    },
+    /// 补丁内容损坏或被篡改 (长度越界、数据截断、解压失败等)
+    CorruptPatch {
+        patch_file: String,
+        reason: String,
+    },
+    /// 旧文件与补丁头记录的源文件摘要不一致 (补丁不是基于该文件生成的)
+    SourceMismatch {
+        patch_file: String,
+        expected: FileDigest,
+        actual: FileDigest,
+    },
+    /// 应用前的快速预检未通过：旧文件大小 (或开头片段) 与补丁头记录的不同，补丁不可能应用成功
+    BaseMismatch {
+        patch_file: String,
+        expected_size: u64,
+        actual_size: u64,
+    },
+    /// 严格模式下补丁未携带源文件摘要，无法校验
+    MissingSourceChecksum {
+        patch_file: String,
+    },
+    /// 操作被调用方取消
+    Cancelled,
+    /// 补丁生成的文件大于目标分区，写入前即拒绝
+    TargetExceedsRegion {
+        patch_file: String,
+        target_size: u64,
+        region_size: u64,
+    },
+    /// 补丁与提供的 Ed25519 签名不符 (被篡改，或由其他私钥签名)
+    InvalidSignature {
+        patch_file: String,
+    },
+    /// 补丁生成的文件与补丁头记录的目标文件摘要不一致
+    TargetMismatch {
+        patch_file: String,
+        expected: FileDigest,
+        actual: FileDigest,
+    },
+    /// 补丁被加密，但未提供解密密钥
+    Encrypted {
+        patch_file: String,
+    },
+    /// 加密补丁的认证标签校验失败 (密钥错误，或信封被篡改)
+    DecryptionFailed {
+        patch_file: String,
+    },
+    /// 补丁负载使用 zstd 字典压缩，但未提供字典或提供的字典 ID 不同
+    DictionaryMismatch {
+        patch_file: String,
+        expected: u32,
+        actual: Option<u32>,
+    },
+    /// 应用补丁需要的输出或内存超过调用方设置的上限 (`resource` 为 "output" 或 "memory")；
+    /// 事先无法得知总量时 `required` 为 `None`
+    LimitExceeded {
+        patch_file: String,
+        resource: &'static str,
+        limit: u64,
+        required: Option<u64>,
+    },
+    /// 生成补丁超过调用方设置的时长上限
+    TimedOut {
+        timeout_ms: u64,
+    },
+    /// 输出文件已存在且覆盖策略为 `error`
+    OutputExists {
+        path: String,
+    },
+    /// 输出所在目录的剩余空间不足以容纳预计的输出，写入前即拒绝
+    InsufficientSpace {
+        path: String,
+        available: u64,
+        required: u64,
+    },
+    /// 读写文件、压缩或解压时的 I/O 错误
+    Io(io::Error),
+    /// 其他错误 (参数无效、不支持的格式等)，保留原始错误
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl fmt::Display for BsdiffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BsdiffError::FileNotFound { kind, path } => write!(f, "{} not found: {}", kind, path),
+            BsdiffError::ArgumentsLikelySwapped { patch_file, detected } => write!(
+                f,
+                "Patch file {} looks like {}, not a bsdiff patch; the old file and patch arguments may be in the wrong order",
+                patch_file, detected
+            ),
+            BsdiffError::NotAPatch { patch_file } => {
+                write!(f, "{} is not a bsdiff-rust patch (unrecognized magic bytes)", patch_file)
+            }
+            BsdiffError::CorruptPatch { patch_file, reason } => {
+                write!(f, "Corrupt patch {}: {}", patch_file, reason)
+            }
+            BsdiffError::SourceMismatch { patch_file, expected, actual } => write!(
+                f,
+                "Patch {} was made for a different old file: expected {} bytes ({} {}), got {} bytes ({} {})",
+                patch_file, expected.size, expected.algorithm.name(), expected.hex(), actual.size, actual.algorithm.name(), actual.hex()
+            ),
+            BsdiffError::BaseMismatch { patch_file, expected_size, actual_size } if expected_size != actual_size => write!(
+                f,
+                "Patch {} was made for a {}-byte old file, but the old file has {} bytes",
+                patch_file, expected_size, actual_size
+            ),
+            BsdiffError::BaseMismatch { patch_file, .. } => write!(
+                f,
+                "Patch {} was made for a different old file: the first {} bytes differ",
+                patch_file, crate::header::SOURCE_PREFIX_LEN
+            ),
+            BsdiffError::MissingSourceChecksum { patch_file } => write!(
+                f,
+                "Patch {} has no source checksum and cannot be verified in strict mode",
+                patch_file
+            ),
+            BsdiffError::Cancelled => write!(f, "Operation cancelled"),
+            BsdiffError::TargetExceedsRegion { patch_file, target_size, region_size } => write!(
+                f,
+                "Patch {} produces {} bytes, which exceeds the {}-byte target region",
+                patch_file, target_size, region_size
+            ),
+            BsdiffError::InvalidSignature { patch_file } => write!(
+                f,
+                "Patch {} failed signature verification; it may have been tampered with or signed by a different key",
+                patch_file
+            ),
+            BsdiffError::TargetMismatch { patch_file, expected, actual } => write!(
+                f,
+                "Patch {} produced a file that does not match its recorded target: expected {} bytes ({} {}), got {} bytes ({} {})",
+                patch_file, expected.size, expected.algorithm.name(), expected.hex(), actual.size, actual.algorithm.name(), actual.hex()
+            ),
+            BsdiffError::Encrypted { patch_file } => {
+                write!(f, "Patch {} is encrypted; a decryption key is required to apply it", patch_file)
+            }
+            BsdiffError::DecryptionFailed { patch_file } => write!(
+                f,
+                "Patch {} could not be decrypted; the key is wrong or the patch has been tampered with",
+                patch_file
+            ),
+            BsdiffError::DictionaryMismatch { patch_file, expected, actual: None } => {
+                write!(f, "Patch {} was compressed with zstd dictionary {}; provide it to apply the patch", patch_file, expected)
+            }
+            BsdiffError::DictionaryMismatch { patch_file, expected, actual: Some(actual) } => write!(
+                f,
+                "Patch {} was compressed with zstd dictionary {}, but dictionary {} was provided",
+                patch_file, expected, actual
+            ),
+            BsdiffError::LimitExceeded { patch_file, resource, limit, required: Some(required) } => write!(
+                f,
+                "Patch {} needs {} bytes of {}, which exceeds the {}-byte limit",
+                patch_file, required, resource, limit
+            ),
+            BsdiffError::LimitExceeded { patch_file, resource, limit, required: None } => {
+                write!(f, "Patch {} needs more than the {}-byte {} limit", patch_file, limit, resource)
+            }
+            BsdiffError::TimedOut { timeout_ms } => write!(f, "Operation timed out after {} ms", timeout_ms),
+            BsdiffError::OutputExists { path } => write!(f, "Output file {} already exists", path),
+            BsdiffError::InsufficientSpace { path, available, required } => write!(
+                f,
+                "Not enough disk space in {}: about {} bytes needed, {} available",
+                path, required, available
+            ),
+            BsdiffError::Io(error) => error.fmt(f),
+            BsdiffError::Other(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for BsdiffError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BsdiffError::Io(error) => error.source(),
+            BsdiffError::Other(error) => error.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BsdiffError {
+    /// 流式读写中以 `io::Error::other` 传出的 `BsdiffError` 还原为原错误
+    fn from(error: io::Error) -> Self {
+        if error.get_ref().is_some_and(|inner| inner.is::<BsdiffError>()) {
+            if let Ok(inner) = error.into_inner().unwrap().downcast::<BsdiffError>() {
+                return *inner;
+            }
+            unreachable!("checked above");
+        }
+        BsdiffError::Io(error)
+    }
+}
+
+impl From<BsdiffError> for io::Error {
+    fn from(error: BsdiffError) -> Self {
+        match error {
+            BsdiffError::Io(error) => error,
+            error => io::Error::other(error),
+        }
+    }
+}
+
+impl From<String> for BsdiffError {
+    fn from(message: String) -> Self {
+        BsdiffError::Other(message.into())
+    }
+}
+
+impl From<&str> for BsdiffError {
+    fn from(message: &str) -> Self {
+        BsdiffError::Other(message.into())
+    }
+}
+
+/// 依赖库与标准库的其他错误归入 [`BsdiffError::Other`]，仍可经 [`ErrorCode::of`] 归类
+macro_rules! impl_from_other {
+    ($($error:ty),* $(,)?) => {
+        $(impl From<$error> for BsdiffError {
+            fn from(error: $error) -> Self {
+                BsdiffError::Other(Box::new(error))
+            }
+        })*
+    };
+}
+
+impl_from_other!(
+    std::collections::TryReserveError,
+    std::num::TryFromIntError,
+    std::array::TryFromSliceError,
+    rayon::ThreadPoolBuildError,
+);
+
+#[cfg(feature = "zlib")]
+impl_from_other!(flate2::CompressError);
+
+impl BsdiffError {
+    /// 错误所属的错误码，无法归入任何一类时返回 `None`
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            BsdiffError::FileNotFound { .. } => Some(ErrorCode::FileNotFound),
+            BsdiffError::ArgumentsLikelySwapped { .. } | BsdiffError::NotAPatch { .. } | BsdiffError::CorruptPatch { .. } => {
+                Some(ErrorCode::CorruptPatch)
+            }
+            BsdiffError::SourceMismatch { .. }
+            | BsdiffError::MissingSourceChecksum { .. }
+            | BsdiffError::InvalidSignature { .. }
+            | BsdiffError::TargetMismatch { .. } => Some(ErrorCode::HashMismatch),
+            BsdiffError::BaseMismatch { .. } => Some(ErrorCode::BaseMismatch),
+            BsdiffError::Cancelled => Some(ErrorCode::Cancelled),
+            BsdiffError::TargetExceedsRegion { .. } => None,
+            BsdiffError::Encrypted { .. } | BsdiffError::DecryptionFailed { .. } => Some(ErrorCode::DecryptionFailed),
+            BsdiffError::DictionaryMismatch { .. } => Some(ErrorCode::DictionaryMismatch),
+            BsdiffError::LimitExceeded { .. } => Some(ErrorCode::LimitExceeded),
+            BsdiffError::TimedOut { .. } => Some(ErrorCode::Timeout),
+            BsdiffError::OutputExists { .. } => Some(ErrorCode::OutputExists),
+            BsdiffError::InsufficientSpace { .. } => Some(ErrorCode::InsufficientSpace),
+            BsdiffError::Io(error) => ErrorCode::of(error),
+            BsdiffError::Other(error) => ErrorCode::of(error.as_ref()),
+        }
+    }
+}
+
+/// 稳定的错误码，JavaScript 调用方通过 `err.code` 分支，无需解析错误信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// 输入文件或目录不存在
+    FileNotFound,
+    /// 没有读写权限
+    PermissionDenied,
+    /// 补丁损坏、被截断或根本不是补丁
+    CorruptPatch,
+    /// 源文件、目标文件或签名校验不通过
+    HashMismatch,
+    /// 旧文件大小或开头片段与补丁记录的不同 (快速预检，未读取补丁负载)
+    BaseMismatch,
+    /// 内存不足 (含 zstd 分配失败)
+    OutOfMemory,
+    /// 操作被调用方取消
+    Cancelled,
+    /// 加密补丁缺少密钥、密钥错误或被篡改
+    DecryptionFailed,
+    /// 字典压缩的补丁缺少字典或字典不符
+    DictionaryMismatch,
+    /// 超过 maxOutputSize 或 maxMemory 上限
+    LimitExceeded,
+    /// 超过 timeoutMs 时长上限
+    Timeout,
+    /// 输出文件已存在 (overwrite: 'error')
+    OutputExists,
+    /// 磁盘空间不足 (写入前的预检或写入时的 ENOSPC)
+    InsufficientSpace,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::FileNotFound => "ERR_FILE_NOT_FOUND",
+            ErrorCode::PermissionDenied => "ERR_PERMISSION_DENIED",
+            ErrorCode::CorruptPatch => "ERR_CORRUPT_PATCH",
+            ErrorCode::HashMismatch => "ERR_HASH_MISMATCH",
+            ErrorCode::BaseMismatch => "ERR_BASE_MISMATCH",
+            ErrorCode::OutOfMemory => "ERR_OUT_OF_MEMORY",
+            ErrorCode::Cancelled => "ERR_CANCELLED",
+            ErrorCode::DecryptionFailed => "ERR_DECRYPTION_FAILED",
+            ErrorCode::DictionaryMismatch => "ERR_DICTIONARY_MISMATCH",
+            ErrorCode::LimitExceeded => "ERR_LIMIT_EXCEEDED",
+            ErrorCode::Timeout => "ERR_TIMEOUT",
+            ErrorCode::OutputExists => "ERR_OUTPUT_EXISTS",
+            ErrorCode::InsufficientSpace => "ERR_INSUFFICIENT_SPACE",
+        }
+    }
+
+    pub fn parse(code: &str) -> Option<Self> {
+        [
+            ErrorCode::FileNotFound,
+            ErrorCode::PermissionDenied,
+            ErrorCode::CorruptPatch,
+            ErrorCode::HashMismatch,
+            ErrorCode::BaseMismatch,
+            ErrorCode::OutOfMemory,
+            ErrorCode::Cancelled,
+            ErrorCode::DecryptionFailed,
+            ErrorCode::DictionaryMismatch,
+            ErrorCode::LimitExceeded,
+            ErrorCode::Timeout,
+            ErrorCode::OutputExists,
+            ErrorCode::InsufficientSpace,
+        ]
+        .into_iter()
+        .find(|known| known.as_str() == code)
+    }
+
+    /// 归类任意错误：依次检查 [`BsdiffError`]、I/O 错误 (含其包装的内部错误) 与 `source` 链
+    pub fn of(error: &(dyn std::error::Error + 'static)) -> Option<Self> {
+        if let Some(error) = error.downcast_ref::<BsdiffError>() {
+            return error.code();
+        }
+        if error.is::<std::collections::TryReserveError>() {
+            return Some(ErrorCode::OutOfMemory);
+        }
+        if let Some(error) = error.downcast_ref::<io::Error>() {
+            match error.kind() {
+                io::ErrorKind::NotFound => return Some(ErrorCode::FileNotFound),
+                io::ErrorKind::PermissionDenied => return Some(ErrorCode::PermissionDenied),
+                io::ErrorKind::OutOfMemory => return Some(ErrorCode::OutOfMemory),
+                io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded => return Some(ErrorCode::InsufficientSpace),
+                _ => {}
+            }
+            // zstd 分配失败以普通 I/O 错误的形式返回
+            if error.raw_os_error().is_none() && error.to_string() == "Allocation error : not enough memory" {
+                return Some(ErrorCode::OutOfMemory);
+            }
+            if let Some(inner) = error.get_ref() {
+                return Self::of(inner);
+            }
+        }
+        error.source().and_then(Self::of)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_code() {
+        let corrupt: Box<dyn std::error::Error> = BsdiffError::CorruptPatch { patch_file: "p".into(), reason: "truncated".into() }.into();
+        assert_eq!(ErrorCode::of(corrupt.as_ref()), Some(ErrorCode::CorruptPatch));
+        let missing = BsdiffError::FileNotFound { kind: "Old file", path: "a.bin".into() };
+        assert_eq!(missing.to_string(), "Old file not found: a.bin");
+        assert_eq!(ErrorCode::of(&missing), Some(ErrorCode::FileNotFound));
+
+        let io_missing = std::fs::File::open("/nonexistent/bsdiff-error-code").unwrap_err();
+        assert_eq!(ErrorCode::of(&io_missing), Some(ErrorCode::FileNotFound));
+        assert_eq!(ErrorCode::of(&io::Error::other(BsdiffError::Cancelled)), Some(ErrorCode::Cancelled));
+        assert_eq!(ErrorCode::of(&io::Error::other("Allocation error : not enough memory")), Some(ErrorCode::OutOfMemory));
+        assert_eq!(ErrorCode::of(&io::Error::other("something else")), None);
+
+        assert_eq!(ErrorCode::parse(ErrorCode::HashMismatch.as_str()), Some(ErrorCode::HashMismatch));
+        assert_eq!(ErrorCode::parse("GenericFailure"), None);
+        assert_eq!(ErrorCode::of(&BsdiffError::Encrypted { patch_file: "p".into() }), Some(ErrorCode::DecryptionFailed));
+        assert_eq!(ErrorCode::parse("ERR_DECRYPTION_FAILED"), Some(ErrorCode::DecryptionFailed));
+        let mismatch = BsdiffError::DictionaryMismatch { patch_file: "p".into(), expected: 7, actual: None };
+        assert_eq!(ErrorCode::of(&mismatch), Some(ErrorCode::DictionaryMismatch));
+        assert_eq!(ErrorCode::parse("ERR_DICTIONARY_MISMATCH"), Some(ErrorCode::DictionaryMismatch));
+        let limit = BsdiffError::LimitExceeded { patch_file: "p".into(), resource: "output", limit: 1024, required: Some(4096) };
+        assert_eq!(limit.to_string(), "Patch p needs 4096 bytes of output, which exceeds the 1024-byte limit");
+        assert_eq!(ErrorCode::of(&limit), Some(ErrorCode::LimitExceeded));
+        assert_eq!(ErrorCode::parse("ERR_LIMIT_EXCEEDED"), Some(ErrorCode::LimitExceeded));
+        let timeout = io::Error::other(BsdiffError::TimedOut { timeout_ms: 250 });
+        assert_eq!(timeout.to_string(), "Operation timed out after 250 ms");
+        assert_eq!(ErrorCode::of(&timeout), Some(ErrorCode::Timeout));
+        assert_eq!(ErrorCode::parse("ERR_TIMEOUT"), Some(ErrorCode::Timeout));
+        let exists = BsdiffError::OutputExists { path: "out.bin".into() };
+        assert_eq!(exists.to_string(), "Output file out.bin already exists");
+        assert_eq!(ErrorCode::of(&exists), Some(ErrorCode::OutputExists));
+        let space = BsdiffError::InsufficientSpace { path: "/out".into(), available: 10, required: 20 };
+        assert_eq!(space.to_string(), "Not enough disk space in /out: about 20 bytes needed, 10 available");
+        assert_eq!(ErrorCode::of(&space), Some(ErrorCode::InsufficientSpace));
+        assert_eq!(ErrorCode::of(&io::Error::from(io::ErrorKind::StorageFull)), Some(ErrorCode::InsufficientSpace));
+        assert_eq!(ErrorCode::parse("ERR_INSUFFICIENT_SPACE"), Some(ErrorCode::InsufficientSpace));
+
+        // 包装在 I/O 错误中的 BsdiffError 转换时还原，其他 I/O 错误按类别归类
+        assert!(matches!(BsdiffError::from(io::Error::other(BsdiffError::Cancelled)), BsdiffError::Cancelled));
+        let io_missing = BsdiffError::from(std::fs::File::open("/nonexistent/bsdiff-error-code").unwrap_err());
+        assert!(matches!(io_missing, BsdiffError::Io(_)));
+        assert_eq!(io_missing.code(), Some(ErrorCode::FileNotFound));
+        assert_eq!(io::Error::from(io_missing).kind(), io::ErrorKind::NotFound);
+        let reserve = BsdiffError::from(Vec::<u8>::new().try_reserve(usize::MAX).unwrap_err());
+        assert_eq!(reserve.code(), Some(ErrorCode::OutOfMemory));
+        assert_eq!(BsdiffError::from("bad option").code(), None);
+    }
+}
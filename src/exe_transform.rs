@@ -0,0 +1,452 @@
+//! 可执行文件感知的预处理 (思路同 Courgette/Zucchini 与 xz 的 BCJ 过滤器)
+//!
+//! 重新编译后插入或删除的代码会让其后大量 call/jmp 指令的相对偏移一起变化，bsdiff 只能把它们
+//! 逐条记为差异。diff 前把 PE/ELF/Mach-O 代码段中相对跳转的目标改写为 (文件偏移意义上的)
+//! 绝对地址，调用同一函数的指令在新旧文件中就变成相同的字节。调用者与被调用者一起移动时效果相反，
+//! 因此 diff 时会与不变换的补丁比较，保留较小的一个。
+//! 变换只改写代码段内的操作数字节，严格可逆；使用的架构与代码区间记录在补丁头中，
+//! 应用补丁时先对旧文件做同样的变换，再对输出做逆变换。
+
+use crate::error::BsdiffError;
+
+/// 补丁头中最多记录的代码区间数 (新旧文件合计)，保证字段不超过补丁头单字段上限
+const MAX_RANGES: usize = 512;
+
+/// 指令集
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    /// x86 / x86-64：改写 `E8` (call) 与 `E9` (jmp) 的 32 位相对偏移
+    X86,
+    /// AArch64：改写 `BL` 指令的 26 位相对偏移
+    Arm64,
+}
+
+impl Arch {
+    pub fn id(self) -> u8 {
+        match self {
+            Arch::X86 => 1,
+            Arch::Arm64 => 2,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Arch::X86),
+            2 => Some(Arch::Arm64),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Arch::X86 => "x86",
+            Arch::Arm64 => "arm64",
+        }
+    }
+}
+
+/// 文件中的代码区间 (文件偏移)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeRange {
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// 补丁使用的可执行文件变换：架构与新旧文件各自的代码区间
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExeTransform {
+    pub arch: Arch,
+    pub old_ranges: Vec<CodeRange>,
+    pub new_ranges: Vec<CodeRange>,
+}
+
+impl ExeTransform {
+    /// 新旧文件都是同一架构的可执行文件时返回变换，否则返回 `None` (按普通文件 diff)
+    pub fn plan(old: &[u8], new: &[u8]) -> Option<Self> {
+        let (old_arch, old_ranges) = detect(old)?;
+        let (new_arch, new_ranges) = detect(new)?;
+        if old_arch != new_arch || old_ranges.len() + new_ranges.len() > MAX_RANGES {
+            return None;
+        }
+        Some(Self { arch: old_arch, old_ranges, new_ranges })
+    }
+
+    /// 对旧文件做正向变换 (diff 与应用补丁时都需要)
+    pub fn encode_old(&self, old: &[u8]) -> Result<Vec<u8>, BsdiffError> {
+        let mut data = old.to_vec();
+        transform(self.arch, &mut data, &self.old_ranges, true)?;
+        Ok(data)
+    }
+
+    /// 对新文件做正向变换 (diff 时)
+    pub fn encode_new(&self, new: &[u8]) -> Result<Vec<u8>, BsdiffError> {
+        let mut data = new.to_vec();
+        transform(self.arch, &mut data, &self.new_ranges, true)?;
+        Ok(data)
+    }
+
+    /// 对补丁输出做逆变换，还原新文件 (应用补丁时)
+    pub fn decode_new(&self, data: &mut [u8]) -> Result<(), BsdiffError> {
+        transform(self.arch, data, &self.new_ranges, false)
+    }
+
+    /// 补丁头字段：`arch: u8 | old_count: u16 LE | new_count: u16 LE | (offset: u64 LE, len: u64 LE)*`
+    pub fn to_field(&self) -> Vec<u8> {
+        let mut field = vec![self.arch.id()];
+        field.extend_from_slice(&(self.old_ranges.len() as u16).to_le_bytes());
+        field.extend_from_slice(&(self.new_ranges.len() as u16).to_le_bytes());
+        for range in self.old_ranges.iter().chain(&self.new_ranges) {
+            field.extend_from_slice(&range.offset.to_le_bytes());
+            field.extend_from_slice(&range.len.to_le_bytes());
+        }
+        field
+    }
+
+    pub fn from_field(value: &[u8]) -> Option<Self> {
+        let arch = Arch::from_id(*value.first()?)?;
+        let old_count = u16_at(value, 1, false)? as usize;
+        let new_count = u16_at(value, 3, false)? as usize;
+        if value.len() != 5 + (old_count + new_count) * 16 {
+            return None;
+        }
+        let mut ranges = value[5..]
+            .chunks_exact(16)
+            .map(|chunk| CodeRange { offset: u64_at(chunk, 0, false).unwrap(), len: u64_at(chunk, 8, false).unwrap() });
+        let old_ranges = ranges.by_ref().take(old_count).collect();
+        let new_ranges = ranges.collect();
+        Some(Self { arch, old_ranges, new_ranges })
+    }
+}
+
+/// 在 `ranges` 内正向 (`encode`) 或逆向改写相对跳转目标；区间超出数据范围时报错
+fn transform(arch: Arch, data: &mut [u8], ranges: &[CodeRange], encode: bool) -> Result<(), BsdiffError> {
+    for range in ranges {
+        let end = range
+            .offset
+            .checked_add(range.len)
+            .filter(|end| *end <= data.len() as u64)
+            .ok_or_else(|| format!("Executable code range {}+{} exceeds the file ({} bytes)", range.offset, range.len, data.len()))?;
+        let (start, end) = (range.offset as usize, end as usize);
+        match arch {
+            Arch::X86 => transform_x86(data, start, end, encode),
+            Arch::Arm64 => transform_arm64(data, start, end, encode),
+        }
+    }
+    Ok(())
+}
+
+/// x86：`E8`/`E9` 后的 rel32 加上 (减去) 下一条指令的偏移。只改写操作码之后被跳过的 4 个字节，
+/// 操作码本身不变，因此逆变换扫描到的位置与正向完全一致
+///
+/// 同 xz 的 BCJ 过滤器，只改写最高字节为 `00` 或 `FF` 的操作数 (±16 MiB 内的跳转)，并在 25 位内取模、
+/// 符号扩展，结果的最高字节仍是 `00` 或 `FF`，逆变换据此识别同一批操作数。指令中间偶然出现的
+/// `E8`/`E9` 后面多是任意字节，不满足条件就原样保留，不会把本来相同的字节改成不同的值。
+fn transform_x86(data: &mut [u8], start: usize, end: usize, encode: bool) {
+    const MASK: u32 = (1 << 25) - 1;
+    let mut i = start;
+    while i + 5 <= end {
+        if data[i] != 0xE8 && data[i] != 0xE9 {
+            i += 1;
+            continue;
+        }
+        let operand = &mut data[i + 1..i + 5];
+        if operand[3] == 0x00 || operand[3] == 0xFF {
+            let value = u32::from_le_bytes([operand[0], operand[1], operand[2], operand[3]]);
+            let pc = (i + 5) as u32;
+            let value = if encode { value.wrapping_add(pc) } else { value.wrapping_sub(pc) } & MASK;
+            // 第 24 位符号扩展到最高字节
+            let value = ((value << 7) as i32 >> 7) as u32;
+            operand.copy_from_slice(&value.to_le_bytes());
+        }
+        i += 5;
+    }
+}
+
+/// AArch64：`BL` 的 imm26 (以 4 字节为单位) 加上 (减去) 指令所在的字序号，模 2^26
+fn transform_arm64(data: &mut [u8], start: usize, end: usize, encode: bool) {
+    const BL_MASK: u32 = 0xFC00_0000;
+    const BL_OPCODE: u32 = 0x9400_0000;
+    const IMM_MASK: u32 = 0x03FF_FFFF;
+
+    let mut i = start.next_multiple_of(4);
+    while i + 4 <= end {
+        let instruction = u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+        if instruction & BL_MASK == BL_OPCODE {
+            let pc = (i / 4) as u32;
+            let imm = instruction & IMM_MASK;
+            let imm = if encode { imm.wrapping_add(pc) } else { imm.wrapping_sub(pc) } & IMM_MASK;
+            data[i..i + 4].copy_from_slice(&(BL_OPCODE | imm).to_le_bytes());
+        }
+        i += 4;
+    }
+}
+
+/// 识别 PE/ELF/Mach-O 可执行文件，返回架构与按偏移排序、互不重叠的代码区间；
+/// 不支持的格式或架构、没有代码段时返回 `None`
+pub fn detect(data: &[u8]) -> Option<(Arch, Vec<CodeRange>)> {
+    let (arch, ranges) = if data.starts_with(b"\x7fELF") {
+        detect_elf(data)?
+    } else if data.starts_with(b"MZ") {
+        detect_pe(data)?
+    } else {
+        detect_macho(data)?
+    };
+    let ranges = normalize(ranges, data.len() as u64);
+    (!ranges.is_empty()).then_some((arch, ranges))
+}
+
+/// 丢弃越界或为空的区间，排序并合并重叠区间 (重叠区间被改写两次会破坏可逆性)
+fn normalize(mut ranges: Vec<CodeRange>, file_len: u64) -> Vec<CodeRange> {
+    ranges.retain(|range| range.len > 0 && range.offset.checked_add(range.len).is_some_and(|end| end <= file_len));
+    ranges.sort_by_key(|range| range.offset);
+    let mut merged: Vec<CodeRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.offset <= last.offset + last.len => {
+                last.len = last.len.max(range.offset + range.len - last.offset);
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+fn detect_elf(data: &[u8]) -> Option<(Arch, Vec<CodeRange>)> {
+    const SHT_NOBITS: u32 = 8;
+    const SHF_EXECINSTR: u64 = 0x4;
+
+    let is_64 = match data.get(4)? {
+        1 => false,
+        2 => true,
+        _ => return None,
+    };
+    let big_endian = *data.get(5)? == 2;
+    let arch = match u16_at(data, 18, big_endian)? {
+        3 | 62 => Arch::X86,
+        183 => Arch::Arm64,
+        _ => return None,
+    };
+    let (shoff, shentsize, shnum) = if is_64 {
+        (u64_at(data, 0x28, big_endian)?, u16_at(data, 0x3A, big_endian)?, u16_at(data, 0x3C, big_endian)?)
+    } else {
+        (u32_at(data, 0x20, big_endian)? as u64, u16_at(data, 0x2E, big_endian)?, u16_at(data, 0x30, big_endian)?)
+    };
+
+    let mut ranges = Vec::new();
+    for index in 0..shnum as u64 {
+        let base = usize::try_from(shoff.checked_add(index * shentsize as u64)?).ok()?;
+        let sh_type = u32_at(data, base + 4, big_endian)?;
+        let (flags, offset, size) = if is_64 {
+            (u64_at(data, base + 8, big_endian)?, u64_at(data, base + 0x18, big_endian)?, u64_at(data, base + 0x20, big_endian)?)
+        } else {
+            (
+                u32_at(data, base + 8, big_endian)? as u64,
+                u32_at(data, base + 0x10, big_endian)? as u64,
+                u32_at(data, base + 0x14, big_endian)? as u64,
+            )
+        };
+        if sh_type != SHT_NOBITS && flags & SHF_EXECINSTR != 0 {
+            ranges.push(CodeRange { offset, len: size });
+        }
+    }
+    Some((arch, ranges))
+}
+
+fn detect_pe(data: &[u8]) -> Option<(Arch, Vec<CodeRange>)> {
+    const IMAGE_SCN_CNT_CODE: u32 = 0x0000_0020;
+    const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+
+    let pe = u32_at(data, 0x3C, false)? as usize;
+    if data.get(pe..pe.checked_add(4)?)? != b"PE\0\0" {
+        return None;
+    }
+    let arch = match u16_at(data, pe + 4, false)? {
+        0x014C | 0x8664 => Arch::X86,
+        0xAA64 => Arch::Arm64,
+        _ => return None,
+    };
+    let sections = u16_at(data, pe + 6, false)?;
+    let table = pe + 24 + u16_at(data, pe + 20, false)? as usize;
+
+    let mut ranges = Vec::new();
+    for index in 0..sections as usize {
+        let base = table + index * 40;
+        let size = u32_at(data, base + 16, false)? as u64;
+        let offset = u32_at(data, base + 20, false)? as u64;
+        let characteristics = u32_at(data, base + 36, false)?;
+        if characteristics & (IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE) != 0 {
+            ranges.push(CodeRange { offset, len: size });
+        }
+    }
+    Some((arch, ranges))
+}
+
+fn detect_macho(data: &[u8]) -> Option<(Arch, Vec<CodeRange>)> {
+    const LC_SEGMENT: u32 = 0x1;
+    const LC_SEGMENT_64: u32 = 0x19;
+    const S_ATTR_PURE_INSTRUCTIONS: u32 = 0x8000_0000;
+    const S_ATTR_SOME_INSTRUCTIONS: u32 = 0x0000_0400;
+
+    // 仅支持小端单架构文件 (不含 fat/universal 二进制)
+    let is_64 = match u32_at(data, 0, false)? {
+        0xFEED_FACE => false,
+        0xFEED_FACF => true,
+        _ => return None,
+    };
+    let arch = match u32_at(data, 4, false)? {
+        0x0000_0007 | 0x0100_0007 => Arch::X86,
+        0x0100_000C => Arch::Arm64,
+        _ => return None,
+    };
+    let ncmds = u32_at(data, 16, false)?;
+
+    let mut ranges = Vec::new();
+    let mut cmd_pos = if is_64 { 32 } else { 28 };
+    for _ in 0..ncmds {
+        let cmd = u32_at(data, cmd_pos, false)?;
+        let cmdsize = u32_at(data, cmd_pos + 4, false)? as usize;
+        if cmdsize < 8 {
+            return None;
+        }
+        let (section_start, section_size, nsects) = match cmd {
+            LC_SEGMENT_64 => (cmd_pos + 72, 80, u32_at(data, cmd_pos + 64, false)?),
+            LC_SEGMENT => (cmd_pos + 56, 68, u32_at(data, cmd_pos + 48, false)?),
+            _ => (0, 0, 0),
+        };
+        for index in 0..nsects as usize {
+            let base = section_start + index * section_size;
+            let (size, offset, flags) = if is_64 {
+                (u64_at(data, base + 40, false)?, u32_at(data, base + 48, false)? as u64, u32_at(data, base + 64, false)?)
+            } else {
+                (u32_at(data, base + 36, false)? as u64, u32_at(data, base + 40, false)? as u64, u32_at(data, base + 56, false)?)
+            };
+            if flags & (S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS) != 0 {
+                ranges.push(CodeRange { offset, len: size });
+            }
+        }
+        cmd_pos = cmd_pos.checked_add(cmdsize)?;
+    }
+    Some((arch, ranges))
+}
+
+fn bytes_at<const N: usize>(data: &[u8], offset: usize) -> Option<[u8; N]> {
+    data.get(offset..offset.checked_add(N)?)?.try_into().ok()
+}
+
+fn u16_at(data: &[u8], offset: usize, big_endian: bool) -> Option<u16> {
+    let bytes = bytes_at(data, offset)?;
+    Some(if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+}
+
+fn u32_at(data: &[u8], offset: usize, big_endian: bool) -> Option<u32> {
+    let bytes = bytes_at(data, offset)?;
+    Some(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+}
+
+fn u64_at(data: &[u8], offset: usize, big_endian: bool) -> Option<u64> {
+    let bytes = bytes_at(data, offset)?;
+    Some(if big_endian { u64::from_be_bytes(bytes) } else { u64::from_le_bytes(bytes) })
+}
+
+/// 构造只含一个 `.text` 节的最小 ELF64 x86-64 文件 (测试用)
+#[cfg(test)]
+pub(crate) fn test_elf(text: &[u8]) -> Vec<u8> {
+    let text_offset = 64u64;
+    let shoff = text_offset + text.len() as u64;
+    let mut elf = vec![0u8; 64];
+    elf[..6].copy_from_slice(b"\x7fELF\x02\x01");
+    elf[18..20].copy_from_slice(&62u16.to_le_bytes());
+    elf[0x28..0x30].copy_from_slice(&shoff.to_le_bytes());
+    elf[0x3A..0x3C].copy_from_slice(&64u16.to_le_bytes());
+    elf[0x3C..0x3E].copy_from_slice(&2u16.to_le_bytes());
+    elf.extend_from_slice(text);
+    // 0 号节为空节，1 号节为 .text (SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR)
+    elf.extend_from_slice(&[0u8; 64]);
+    let mut section = [0u8; 64];
+    section[4..8].copy_from_slice(&1u32.to_le_bytes());
+    section[8..16].copy_from_slice(&6u64.to_le_bytes());
+    section[0x18..0x20].copy_from_slice(&text_offset.to_le_bytes());
+    section[0x20..0x28].copy_from_slice(&(text.len() as u64).to_le_bytes());
+    elf.extend_from_slice(&section);
+    elf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_and_roundtrip() {
+        let mut text = Vec::new();
+        for i in 0..200u32 {
+            text.extend_from_slice(&[0x55, 0xE8]);
+            text.extend_from_slice(&(i.wrapping_mul(2654435761)).to_le_bytes());
+            text.extend_from_slice(&[0xE9, 0x01]);
+        }
+        let elf = test_elf(&text);
+        let (arch, ranges) = detect(&elf).unwrap();
+        assert_eq!(arch, Arch::X86);
+        assert_eq!(ranges, [CodeRange { offset: 64, len: text.len() as u64 }]);
+
+        let transform = ExeTransform::plan(&elf, &elf).unwrap();
+        let mut encoded = transform.encode_new(&elf).unwrap();
+        assert_ne!(encoded, elf);
+        assert_eq!(&encoded[..64], &elf[..64]);
+        transform.decode_new(&mut encoded).unwrap();
+        assert_eq!(encoded, elf);
+        assert_eq!(ExeTransform::from_field(&transform.to_field()), Some(transform.clone()));
+
+        // AArch64 BL 变换同样可逆
+        let mut code: Vec<u8> = (0..64u32).flat_map(|i| (0x9400_0000 | (i * 977)).to_le_bytes()).collect();
+        let original = code.clone();
+        transform_arm64(&mut code, 0, original.len(), true);
+        assert_ne!(code, original);
+        transform_arm64(&mut code, 0, original.len(), false);
+        assert_eq!(code, original);
+
+        // x86 只改写 ±16 MiB 内的跳转，其余操作数原样保留；任意字节 (含大偏移处) 都可逆
+        let mut code = vec![0xE8, 0x10, 0x00, 0x00, 0x00, 0xE8, 0x10, 0x00, 0x00, 0x7F];
+        transform_x86(&mut code, 0, 10, true);
+        assert_eq!(code, [0xE8, 0x15, 0x00, 0x00, 0x00, 0xE8, 0x10, 0x00, 0x00, 0x7F]);
+        let mut state = 0x2545F491u32;
+        let noise: Vec<u8> = (0..1 << 16).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            if state.is_multiple_of(7) { 0xE8 } else { state as u8 }
+        }).collect();
+        for offset in [0usize, (1 << 24) - 100, 1 << 25] {
+            let mut data = vec![0u8; offset];
+            data.extend_from_slice(&noise);
+            let len = data.len();
+            transform_x86(&mut data, offset, len, true);
+            assert_ne!(&data[offset..], &noise[..]);
+            transform_x86(&mut data, offset, len, false);
+            assert_eq!(&data[offset..], &noise[..]);
+        }
+
+        // 非可执行文件、区间越界
+        assert_eq!(detect(b"plain text, not an executable"), None);
+        assert_eq!(detect(&elf[..100]), None);
+        let bad = ExeTransform { new_ranges: vec![CodeRange { offset: 0, len: 1 << 40 }], ..transform };
+        assert!(bad.decode_new(&mut encoded).is_err());
+        assert_eq!(ExeTransform::from_field(&[9, 0, 0, 0, 0]), None);
+    }
+
+    #[test]
+    fn test_detect_pe() {
+        let mut pe = vec![0u8; 0x200];
+        pe[..2].copy_from_slice(b"MZ");
+        pe[0x3C..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        pe[0x80..0x84].copy_from_slice(b"PE\0\0");
+        pe[0x84..0x86].copy_from_slice(&0x8664u16.to_le_bytes());
+        pe[0x86..0x88].copy_from_slice(&1u16.to_le_bytes());
+        pe[0x94..0x96].copy_from_slice(&0u16.to_le_bytes());
+        let section = 0x80 + 24;
+        pe[section..section + 5].copy_from_slice(b".text");
+        pe[section + 16..section + 20].copy_from_slice(&0x100u32.to_le_bytes());
+        pe[section + 20..section + 24].copy_from_slice(&0x100u32.to_le_bytes());
+        pe[section + 36..section + 40].copy_from_slice(&0x6000_0020u32.to_le_bytes());
+        assert_eq!(detect(&pe), Some((Arch::X86, vec![CodeRange { offset: 0x100, len: 0x100 }])));
+    }
+}
@@ -0,0 +1,120 @@
+//! FastCDC 内容定义分块 (Xia et al., 2016)：gear 滚动哈希 + 归一化分块
+//!
+//! 切点只由附近的内容决定，插入或删除数据只影响相邻的一两个块，
+//! 不同文件 (或同一文件的不同版本) 中相同的数据因此切出相同的块，可按摘要去重。
+
+use std::ops::Range;
+
+/// 最小块大小：切点不早于此处
+pub const MIN_CHUNK: usize = 4 * 1024;
+/// 平均块大小 (2 的幂)
+pub const AVG_CHUNK: usize = 16 * 1024;
+/// 最大块大小：到此仍未找到切点时强制切分
+pub const MAX_CHUNK: usize = 64 * 1024;
+
+/// 归一化分块：平均大小之前用更严格的掩码 (多 1 位)，之后用更宽松的掩码 (少 1 位)，
+/// 块大小集中在平均值附近。gear 哈希左移累积，高位覆盖最近 64 个字节，因此取高位作掩码
+const MASK_SMALL: u64 = !(u64::MAX >> (AVG_CHUNK.trailing_zeros() + 1));
+const MASK_LARGE: u64 = !(u64::MAX >> (AVG_CHUNK.trailing_zeros() - 1));
+
+/// gear 表：由固定种子的 SplitMix64 生成，分块结果在任意平台上一致
+fn gear() -> &'static [u64; 256] {
+    static GEAR: std::sync::OnceLock<[u64; 256]> = std::sync::OnceLock::new();
+    GEAR.get_or_init(|| {
+        let mut state = 0x6273_6469_6666_6364u64;
+        let mut table = [0u64; 256];
+        for entry in table.iter_mut() {
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            *entry = z ^ (z >> 31);
+        }
+        table
+    })
+}
+
+/// 数据开头第一个块的长度
+fn cut_point(data: &[u8]) -> usize {
+    if data.len() <= MIN_CHUNK {
+        return data.len();
+    }
+    let end = data.len().min(MAX_CHUNK);
+    let normal = AVG_CHUNK.min(end);
+    let gear = gear();
+    let mut hash = 0u64;
+    for (i, &byte) in data.iter().enumerate().take(end).skip(MIN_CHUNK) {
+        hash = (hash << 1).wrapping_add(gear[byte as usize]);
+        let mask = if i < normal { MASK_SMALL } else { MASK_LARGE };
+        if hash & mask == 0 {
+            return i + 1;
+        }
+    }
+    end
+}
+
+/// 把数据切分为连续的块，返回各块的范围；空数据不产生任何块
+pub fn chunks(data: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::with_capacity(data.len() / AVG_CHUNK + 1);
+    let mut start = 0;
+    while start < data.len() {
+        let end = start + cut_point(&data[start..]);
+        ranges.push(start..end);
+        start = end;
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 确定性的伪随机数据 (xorshift)，不可压缩也没有重复
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed | 1;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chunk_bounds() {
+        assert!(chunks(&[]).is_empty());
+        assert_eq!(chunks(&[7u8; 100]), vec![0..100]);
+
+        let data = noise(1 << 20, 1);
+        let ranges = chunks(&data);
+        assert_eq!(ranges.first().unwrap().start, 0);
+        assert_eq!(ranges.last().unwrap().end, data.len());
+        for pair in ranges.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+        }
+        for range in &ranges[..ranges.len() - 1] {
+            assert!(range.len() >= MIN_CHUNK && range.len() <= MAX_CHUNK, "{:?}", range);
+        }
+        // 平均块大小接近目标值
+        let average = data.len() / ranges.len();
+        assert!(average > AVG_CHUNK / 2 && average < AVG_CHUNK * 2, "average {}", average);
+
+        // 全零数据没有切点，按最大块切分
+        assert!(chunks(&vec![0u8; 3 * MAX_CHUNK]).iter().all(|range| range.len() == MAX_CHUNK));
+    }
+
+    #[test]
+    fn test_insertion_only_shifts_nearby_chunks() {
+        let data = noise(512 * 1024, 2);
+        let mut edited = data[..100_000].to_vec();
+        edited.extend_from_slice(b"inserted bytes");
+        edited.extend_from_slice(&data[100_000..]);
+
+        let blocks = |data: &[u8]| chunks(data).into_iter().map(|range| data[range].to_vec()).collect::<Vec<_>>();
+        let (before, after) = (blocks(&data), blocks(&edited));
+        let shared = after.iter().filter(|block| before.contains(block)).count();
+        assert!(shared + 3 >= before.len(), "only {} of {} chunks survived", shared, before.len());
+    }
+}
@@ -0,0 +1,150 @@
+//! 调用方预先打开的文件描述符：沙箱化的 Electron 应用由文件系统代理打开文件，原生模块只拿到描述符，
+//! 补丁与输出都通过描述符读写，不再按路径打开
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use crate::bsdiff_rust::{BsdiffRust, OptimizationConfig};
+use crate::error::BsdiffError;
+
+/// 补丁或输出参数：文件路径或已打开的文件
+#[derive(Debug)]
+pub enum FileArg {
+    Path(PathBuf),
+    Fd(File),
+}
+
+/// 复制调用方的文件描述符，得到独立的 [`File`]；调用方的描述符保持打开，仍由调用方关闭
+#[cfg(unix)]
+pub fn dup_fd(fd: i64) -> io::Result<File> {
+    use std::os::fd::BorrowedFd;
+
+    let fd = i32::try_from(fd).ok().filter(|fd| *fd >= 0).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid file descriptor: {}", fd)))?;
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // 上面已确认描述符有效；只在复制期间借用
+    let borrowed = unsafe { BorrowedFd::borrow_raw(fd) };
+    Ok(File::from(borrowed.try_clone_to_owned()?))
+}
+
+/// 复制调用方的文件描述符 (当前平台不支持)
+#[cfg(not(unix))]
+pub fn dup_fd(_fd: i64) -> io::Result<File> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "File descriptor arguments are only supported on Unix platforms"))
+}
+
+/// 生成补丁 (`old_file` 为 `None` 时生成完整补丁)，从描述符的当前位置写入；
+/// 写入的是普通文件时截断其后的旧内容
+pub fn diff_to_fd(
+    old_file: Option<&Path>,
+    new_file: &Path,
+    out: &File,
+    config: &OptimizationConfig
+) -> Result<(), BsdiffError> {
+    let writer = BufWriter::with_capacity(64 * 1024, out.try_clone()?);
+    let mut writer = match old_file {
+        Some(old_file) => BsdiffRust::diff_to_writer(old_file, new_file, writer, config)?,
+        None => {
+            let new = BsdiffRust::create_single_memory_map(new_file)?;
+            let mut writer = writer;
+            writer.write_all(&BsdiffRust::diff_bytes(&[], &new, config)?)?;
+            writer
+        }
+    };
+    writer.flush()?;
+    Ok(truncate_at_position(out)?)
+}
+
+/// 应用补丁，补丁与输出可以分别是路径或描述符；返回新文件大小
+///
+/// 补丁描述符须指向可 seek 的普通文件，从文件开头读取；输出描述符从当前位置写入，
+/// 出错时其中可能已有部分数据 (路径输出仍是先写临时文件再改名)。不支持试运行。
+pub fn patch_with(
+    old_file: Option<&Path>,
+    new: &FileArg,
+    patch: &FileArg,
+    config: &OptimizationConfig
+) -> Result<u64, BsdiffError> {
+    let patch_file = match patch {
+        FileArg::Path(path) => path.as_path(),
+        FileArg::Fd(_) if config.dry_run => return Err("dryRun needs a patch path".into()),
+        FileArg::Fd(_) => Path::new("<fd>"),
+    };
+    let open_patch = || -> io::Result<(File, u64)> {
+        let mut file = match patch {
+            FileArg::Path(path) => File::open(path)?,
+            FileArg::Fd(file) => file.try_clone()?,
+        };
+        file.rewind()?;
+        let total = file.metadata()?.len();
+        Ok((file, total))
+    };
+    let old = old_file.map(BsdiffRust::create_single_memory_map).transpose()?;
+    let old_data = old.as_deref().unwrap_or(&[]);
+
+    match new {
+        FileArg::Path(new_file) => {
+            BsdiffRust::prepare_output(new_file, config)?;
+            BsdiffRust::patch_reader_to_file(old_data, open_patch, patch_file, new_file, None, config)
+        }
+        FileArg::Fd(out) => {
+            let (reader, total) = open_patch()?;
+            let mut writer = BufWriter::with_capacity(64 * 1024, out.try_clone()?);
+            let written = BsdiffRust::patch_reader_into(old_data, reader, total, patch_file, &mut writer, config)?;
+            writer.flush()?;
+            truncate_at_position(out)?;
+            if config.durable {
+                out.sync_all()?;
+            }
+            Ok(written)
+        }
+    }
+}
+
+/// 普通文件在当前位置截断，覆盖写入较短的内容时不留下旧数据；管道等不可 seek 的描述符不处理
+fn truncate_at_position(file: &File) -> io::Result<()> {
+    if file.metadata()?.is_file() {
+        let mut handle = file;
+        let end = handle.stream_position()?;
+        file.set_len(end)?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs::{self, OpenOptions};
+    use std::os::fd::AsRawFd;
+
+    #[test]
+    fn test_patch_through_descriptors() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old"), b"descriptor old contents ".repeat(50)).unwrap();
+        fs::write(path("new"), b"descriptor NEW contents ".repeat(52)).unwrap();
+        // 输出文件原有内容更长，写入后应被截断
+        fs::write(path("patch"), vec![0xEE; 1 << 16]).unwrap();
+        fs::write(path("out"), vec![0xEE; 1 << 16]).unwrap();
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+
+        let patch_out = OpenOptions::new().write(true).open(path("patch")).unwrap();
+        let patch_fd = dup_fd(patch_out.as_raw_fd() as i64).unwrap();
+        diff_to_fd(Some(&path("old")), &path("new"), &patch_fd, &config).unwrap();
+        assert!(fs::metadata(path("patch")).unwrap().len() < 1 << 16);
+
+        let patch_in = FileArg::Fd(dup_fd(File::open(path("patch")).unwrap().as_raw_fd() as i64).unwrap());
+        let out = FileArg::Fd(dup_fd(OpenOptions::new().write(true).open(path("out")).unwrap().as_raw_fd() as i64).unwrap());
+        let size = patch_with(Some(&path("old")), &out, &patch_in, &config).unwrap();
+        assert_eq!(fs::read(path("out")).unwrap(), fs::read(path("new")).unwrap());
+        assert_eq!(size, fs::metadata(path("new")).unwrap().len());
+
+        // 描述符补丁 + 路径输出
+        patch_with(Some(&path("old")), &FileArg::Path(path("out2")), &patch_in, &config).unwrap();
+        assert_eq!(fs::read(path("out2")).unwrap(), fs::read(path("new")).unwrap());
+        assert!(dup_fd(-1).is_err());
+        assert!(dup_fd(1 << 30).is_err());
+    }
+}
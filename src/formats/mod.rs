@@ -0,0 +1,3 @@
+//! 本库补丁头 + Zstd 与 BSDIFF40 以外的第三方补丁格式
+
+pub(crate) mod vcdiff;
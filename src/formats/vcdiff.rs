@@ -0,0 +1,656 @@
+//! VCDIFF (RFC 3284) 增量格式：与 xdelta3 及 HTTP delta 编码 (RFC 3229) 部署互通
+//!
+//! 编码器把原始 bsdiff 流转换为 VCDIFF 指令：差分为零的区间变为 COPY，其余字节与新增数据变为 ADD，
+//! 使用默认指令表与地址缓存，不做二次压缩。解码器支持默认指令表下的全部指令、
+//! VCD_SOURCE/VCD_TARGET 窗口、应用头部，以及 xdelta3 的窗口 Adler-32 校验扩展。
+
+use std::io::{self, Write};
+use std::sync::OnceLock;
+
+use crate::bsdiff40;
+use crate::cancel::{self, CancelToken};
+
+/// VCDIFF 魔数 ("VCD" 高位置位 + 版本 0)
+pub const VCDIFF_MAGIC: [u8; 4] = [0xD6, 0xC3, 0xC4, 0x00];
+
+// Hdr_Indicator
+const VCD_DECOMPRESS: u8 = 0x01;
+const VCD_CODETABLE: u8 = 0x02;
+const VCD_APPHEADER: u8 = 0x04;
+
+// Win_Indicator
+const VCD_SOURCE: u8 = 0x01;
+const VCD_TARGET: u8 = 0x02;
+/// xdelta3 扩展：窗口带目标数据的 Adler-32 校验
+const VCD_ADLER32: u8 = 0x04;
+
+/// 编码时的目标窗口大小，与 xdelta3 默认值一致
+const WINDOW_SIZE: usize = 1 << 23;
+
+/// 短于此长度的相同区间并入 ADD (COPY 指令本身的开销更大)
+const MIN_COPY: usize = 4;
+
+const NEAR_SIZE: usize = 4;
+const SAME_SIZE: usize = 3;
+
+/// 判断数据是否为 VCDIFF 补丁
+pub fn is_vcdiff(data: &[u8]) -> bool {
+    data.starts_with(&VCDIFF_MAGIC)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Inst {
+    Noop,
+    Add,
+    Run,
+    Copy(u8),
+}
+
+/// 指令表项：最多两条指令及其大小 (0 表示大小另行编码)
+#[derive(Debug, Clone, Copy)]
+struct Code {
+    inst: [Inst; 2],
+    size: [u8; 2],
+}
+
+/// RFC 3284 第 5.6 节的默认指令表
+fn default_code_table() -> &'static [Code; 256] {
+    static TABLE: OnceLock<[Code; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let single = |inst, size| Code { inst: [inst, Inst::Noop], size: [size, 0] };
+        let mut codes = Vec::with_capacity(256);
+        codes.push(single(Inst::Run, 0));
+        codes.extend((0..=17).map(|size| single(Inst::Add, size)));
+        for mode in 0..9 {
+            codes.push(single(Inst::Copy(mode), 0));
+            codes.extend((4..=18).map(|size| single(Inst::Copy(mode), size)));
+        }
+        for mode in 0..6 {
+            for add in 1..=4 {
+                codes.extend((4..=6).map(|copy| Code { inst: [Inst::Add, Inst::Copy(mode)], size: [add, copy] }));
+            }
+        }
+        for mode in 6..9 {
+            codes.extend((1..=4).map(|add| Code { inst: [Inst::Add, Inst::Copy(mode)], size: [add, 4] }));
+        }
+        codes.extend((0..9).map(|mode| Code { inst: [Inst::Copy(mode), Inst::Add], size: [4, 1] }));
+        codes.try_into().expect("default code table has 256 entries")
+    })
+}
+
+/// 单条 ADD 的指令码 (大小不在表中时为 1，大小另行编码)
+fn add_code(size: u64) -> u8 {
+    if (1..=17).contains(&size) { 1 + size as u8 } else { 1 }
+}
+
+/// 单条 COPY 的指令码 (大小不在表中时为该模式的 0 号，大小另行编码)
+fn copy_code(mode: u8, size: u64) -> u8 {
+    let base = 19 + 16 * mode;
+    if (4..=18).contains(&size) { base + (size as u8 - 3) } else { base }
+}
+
+/// ADD + COPY 组合指令码
+fn add_copy_code(add: u64, mode: u8, copy: u64) -> Option<u8> {
+    match (add, mode, copy) {
+        (1..=4, 0..=5, 4..=6) => Some(163 + mode * 12 + (add as u8 - 1) * 3 + (copy as u8 - 4)),
+        (1..=4, 6..=8, 4) => Some(235 + (mode - 6) * 4 + (add as u8 - 1)),
+        _ => None,
+    }
+}
+
+/// VCDIFF 变长整数：大端 7 位分组，除最后一组外最高位置 1
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    let mut buf = [0u8; 10];
+    let mut start = buf.len();
+    loop {
+        start -= 1;
+        buf[start] = (value & 0x7f) as u8 | if start == buf.len() - 1 { 0 } else { 0x80 };
+        value >>= 7;
+        if value == 0 {
+            break;
+        }
+    }
+    out.extend_from_slice(&buf[start..]);
+}
+
+fn varint_len(value: u64) -> usize {
+    (64 - value.leading_zeros() as usize).max(1).div_ceil(7)
+}
+
+fn corrupt(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Corrupt VCDIFF delta: {}", reason))
+}
+
+/// 补丁中的一段字节，按顺序读取
+struct Section<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Section<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        let byte = *self.data.get(self.pos).ok_or_else(|| corrupt("unexpected end of data"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn take(&mut self, len: u64) -> io::Result<&'a [u8]> {
+        let end = usize::try_from(len).ok().and_then(|len| self.pos.checked_add(len)).filter(|end| *end <= self.data.len());
+        let end = end.ok_or_else(|| corrupt("section too short"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn varint(&mut self) -> io::Result<u64> {
+        let mut value = 0u64;
+        for _ in 0..10 {
+            let byte = self.byte()?;
+            value = value.checked_mul(128).ok_or_else(|| corrupt("integer overflow"))? | u64::from(byte & 0x7f);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(corrupt("integer too long"))
+    }
+}
+
+/// RFC 3284 第 5.1 节的地址缓存 (s_near = 4, s_same = 3)
+struct AddressCache {
+    near: [u64; NEAR_SIZE],
+    next_slot: usize,
+    same: [u64; SAME_SIZE * 256],
+}
+
+impl AddressCache {
+    fn new() -> Self {
+        Self { near: [0; NEAR_SIZE], next_slot: 0, same: [0; SAME_SIZE * 256] }
+    }
+
+    fn update(&mut self, addr: u64) {
+        self.near[self.next_slot] = addr;
+        self.next_slot = (self.next_slot + 1) % NEAR_SIZE;
+        self.same[(addr % (SAME_SIZE as u64 * 256)) as usize] = addr;
+    }
+
+    /// 选择编码最短的模式，返回 (模式, 编码值)；same 模式的编码值为单字节
+    fn encode(&mut self, addr: u64, here: u64) -> (u8, u64) {
+        let same_index = (addr % (SAME_SIZE as u64 * 256)) as usize;
+        let best = if self.same[same_index] == addr {
+            (2 + NEAR_SIZE as u8 + (same_index / 256) as u8, addr % 256)
+        } else {
+            let mut best = (0u8, addr);
+            let mut candidates = vec![(1u8, here - addr)];
+            candidates.extend(self.near.iter().enumerate().filter(|(_, near)| addr >= **near).map(|(i, near)| (2 + i as u8, addr - near)));
+            for candidate in candidates {
+                if varint_len(candidate.1) < varint_len(best.1) {
+                    best = candidate;
+                }
+            }
+            best
+        };
+        self.update(addr);
+        best
+    }
+
+    fn decode(&mut self, mode: u8, here: u64, addresses: &mut Section) -> io::Result<u64> {
+        let addr = match mode {
+            0 => addresses.varint()?,
+            1 => here.checked_sub(addresses.varint()?).ok_or_else(|| corrupt("bad HERE address"))?,
+            m if (m as usize) < 2 + NEAR_SIZE => self.near[m as usize - 2].checked_add(addresses.varint()?).ok_or_else(|| corrupt("bad NEAR address"))?,
+            m => self.same[(m as usize - 2 - NEAR_SIZE) * 256 + addresses.byte()? as usize],
+        };
+        if addr >= here {
+            return Err(corrupt("COPY address beyond current position"));
+        }
+        self.update(addr);
+        Ok(addr)
+    }
+}
+
+/// 编码前的窗口内操作
+enum Op {
+    /// 从旧文件 `addr` 处复制 `len` 字节
+    Copy { addr: u64, len: u64 },
+    /// 窗口数据区中接下来的 `len` 字节
+    Add { len: u64 },
+}
+
+/// 逐个收集目标窗口的操作，满一个窗口即编码写出
+struct WindowEncoder {
+    out: Vec<u8>,
+    ops: Vec<Op>,
+    data: Vec<u8>,
+    target_len: usize,
+}
+
+impl WindowEncoder {
+    fn add(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let len = bytes.len().min(WINDOW_SIZE - self.target_len);
+            self.data.extend_from_slice(&bytes[..len]);
+            match self.ops.last_mut() {
+                Some(Op::Add { len: pending }) => *pending += len as u64,
+                _ => self.ops.push(Op::Add { len: len as u64 }),
+            }
+            bytes = &bytes[len..];
+            self.advance(len);
+        }
+    }
+
+    fn copy(&mut self, mut addr: u64, mut len: usize) {
+        while len > 0 {
+            let part = len.min(WINDOW_SIZE - self.target_len);
+            self.ops.push(Op::Copy { addr, len: part as u64 });
+            addr += part as u64;
+            len -= part;
+            self.advance(part);
+        }
+    }
+
+    fn advance(&mut self, len: usize) {
+        self.target_len += len;
+        if self.target_len == WINDOW_SIZE {
+            self.flush();
+        }
+    }
+
+    /// 编码当前窗口：源数据段取窗口内所有 COPY 覆盖的旧文件区间
+    fn flush(&mut self) {
+        if self.target_len == 0 {
+            return;
+        }
+        let segment = self.ops.iter().filter_map(|op| match op {
+            Op::Copy { addr, len } => Some((*addr, addr + len)),
+            Op::Add { .. } => None,
+        });
+        let (segment_pos, segment_end) = segment.fold((u64::MAX, 0), |(lo, hi), (start, end)| (lo.min(start), hi.max(end)));
+        let segment_len = segment_end.saturating_sub(segment_pos);
+
+        let mut inst = Vec::new();
+        let mut addresses = Vec::new();
+        let mut cache = AddressCache::new();
+        let mut here = segment_len;
+        // 待写出的 ADD：大小 1-4 时可与后续 COPY 合并为一条组合指令
+        let mut pending_add: Option<u64> = None;
+        let emit_add = |inst: &mut Vec<u8>, size: u64| {
+            inst.push(add_code(size));
+            if add_code(size) == 1 {
+                write_varint(inst, size);
+            }
+        };
+        for op in &self.ops {
+            match *op {
+                Op::Add { len } => {
+                    if let Some(size) = pending_add.replace(len) {
+                        emit_add(&mut inst, size);
+                    }
+                }
+                Op::Copy { addr, len } => {
+                    let (mode, value) = cache.encode(addr - segment_pos, here);
+                    match pending_add.take().map(|add| (add, add_copy_code(add, mode, len))) {
+                        Some((_, Some(code))) => inst.push(code),
+                        pending => {
+                            if let Some((add, _)) = pending {
+                                emit_add(&mut inst, add);
+                            }
+                            inst.push(copy_code(mode, len));
+                            if !(4..=18).contains(&len) {
+                                write_varint(&mut inst, len);
+                            }
+                        }
+                    }
+                    if mode >= 2 + NEAR_SIZE as u8 {
+                        addresses.push(value as u8);
+                    } else {
+                        write_varint(&mut addresses, value);
+                    }
+                }
+            }
+            here += match *op {
+                Op::Add { len } | Op::Copy { len, .. } => len,
+            };
+        }
+        if let Some(size) = pending_add {
+            emit_add(&mut inst, size);
+        }
+
+        let mut delta = Vec::with_capacity(self.data.len() + inst.len() + addresses.len() + 32);
+        write_varint(&mut delta, self.target_len as u64);
+        delta.push(0); // Delta_Indicator：不做二次压缩
+        write_varint(&mut delta, self.data.len() as u64);
+        write_varint(&mut delta, inst.len() as u64);
+        write_varint(&mut delta, addresses.len() as u64);
+        delta.extend_from_slice(&self.data);
+        delta.extend_from_slice(&inst);
+        delta.extend_from_slice(&addresses);
+
+        if segment_len > 0 {
+            self.out.push(VCD_SOURCE);
+            write_varint(&mut self.out, segment_len);
+            write_varint(&mut self.out, segment_pos);
+        } else {
+            self.out.push(0);
+        }
+        write_varint(&mut self.out, delta.len() as u64);
+        self.out.extend_from_slice(&delta);
+
+        self.ops.clear();
+        self.data.clear();
+        self.target_len = 0;
+    }
+}
+
+/// 原始 bsdiff 流 → VCDIFF
+///
+/// 差分字节为零的区间 (至少 [`MIN_COPY`] 字节) 编码为从旧文件 COPY，其余字节按新文件内容 ADD，
+/// 因此需要旧文件数据。
+pub(crate) fn from_raw(old: &[u8], raw: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = WindowEncoder { out: VCDIFF_MAGIC.to_vec(), ops: Vec::new(), data: Vec::new(), target_len: 0 };
+    encoder.out.push(0); // Hdr_Indicator
+    let mut old_pos: i64 = 0;
+    let mut literal = Vec::new();
+    bsdiff40::for_each_entry(raw, |entry, diff, extra| {
+        let start = usize::try_from(old_pos).ok().filter(|start| start.saturating_add(diff.len()) <= old.len());
+        let start = start.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "bsdiff control block out of range"))?;
+        let old = &old[start..start + diff.len()];
+        let mut i = 0;
+        while i < diff.len() {
+            let same = diff[i..].iter().take_while(|byte| **byte == 0).count();
+            if same >= MIN_COPY {
+                encoder.copy((start + i) as u64, same);
+                i += same;
+                continue;
+            }
+            let end = i + same.max(1);
+            literal.clear();
+            literal.extend(old[i..end].iter().zip(&diff[i..end]).map(|(o, d)| o.wrapping_add(*d)));
+            encoder.add(&literal);
+            i = end;
+        }
+        encoder.add(extra);
+        old_pos += entry.mix_len as i64 + entry.seek;
+        Ok(())
+    })?;
+    encoder.flush();
+    Ok(encoder.out)
+}
+
+/// 解析后的窗口
+struct Window<'a> {
+    indicator: u8,
+    segment_len: u64,
+    segment_pos: u64,
+    target_len: u64,
+    checksum: Option<u32>,
+    data: &'a [u8],
+    inst: &'a [u8],
+    addresses: &'a [u8],
+}
+
+/// 解析文件头与全部窗口 (只做结构校验，不执行指令)
+fn parse(patch: &[u8]) -> io::Result<Vec<Window<'_>>> {
+    let mut input = Section::new(patch);
+    if !is_vcdiff(input.take(VCDIFF_MAGIC.len() as u64)?) {
+        return Err(corrupt("bad magic"));
+    }
+    let header = input.byte()?;
+    if header & VCD_DECOMPRESS != 0 {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "VCDIFF secondary compression is not supported (create the delta with xdelta3 -S none)"));
+    }
+    if header & VCD_CODETABLE != 0 {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "VCDIFF application-defined code tables are not supported"));
+    }
+    if header & !VCD_APPHEADER != 0 {
+        return Err(corrupt("unknown header indicator bits"));
+    }
+    if header & VCD_APPHEADER != 0 {
+        let len = input.varint()?;
+        input.take(len)?;
+    }
+
+    let mut windows = Vec::new();
+    while !input.is_empty() {
+        let indicator = input.byte()?;
+        if indicator & !(VCD_SOURCE | VCD_TARGET | VCD_ADLER32) != 0 || indicator & (VCD_SOURCE | VCD_TARGET) == VCD_SOURCE | VCD_TARGET {
+            return Err(corrupt("bad window indicator"));
+        }
+        let (segment_len, segment_pos) = match indicator & (VCD_SOURCE | VCD_TARGET) {
+            0 => (0, 0),
+            _ => (input.varint()?, input.varint()?),
+        };
+        let delta_len = input.varint()?;
+        let mut delta = Section::new(input.take(delta_len)?);
+        let target_len = delta.varint()?;
+        if delta.byte()? != 0 {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "VCDIFF secondary compression is not supported (create the delta with xdelta3 -S none)"));
+        }
+        let (data_len, inst_len, addr_len) = (delta.varint()?, delta.varint()?, delta.varint()?);
+        let checksum = match indicator & VCD_ADLER32 {
+            0 => None,
+            _ => Some(u32::from_be_bytes(delta.take(4)?.try_into().expect("4-byte checksum"))),
+        };
+        let (data, inst, addresses) = (delta.take(data_len)?, delta.take(inst_len)?, delta.take(addr_len)?);
+        if !delta.is_empty() {
+            return Err(corrupt("delta encoding length mismatch"));
+        }
+        windows.push(Window { indicator, segment_len, segment_pos, target_len, checksum, data, inst, addresses });
+    }
+    Ok(windows)
+}
+
+/// VCDIFF 补丁声明的目标大小 (各窗口目标大小之和)
+pub(crate) fn target_size(patch: &[u8]) -> io::Result<u64> {
+    parse(patch)?.iter().try_fold(0u64, |total, window| total.checked_add(window.target_len)).ok_or_else(|| corrupt("target size overflow"))
+}
+
+/// 应用 VCDIFF 补丁，逐窗口写入 `writer`，输出不超过 `max_output` 字节，返回写入的字节数
+///
+/// 只有补丁使用 VCD_TARGET 窗口时才在内存中保留已生成的目标数据。
+pub(crate) fn apply<W: Write + ?Sized>(
+    old: &[u8],
+    patch: &[u8],
+    writer: &mut W,
+    max_output: u64,
+    cancel: Option<&CancelToken>,
+) -> io::Result<u64> {
+    let windows = parse(patch)?;
+    let total = windows.iter().try_fold(0u64, |total, window| total.checked_add(window.target_len));
+    let total = total.ok_or_else(|| corrupt("target size overflows"))?;
+    if total > max_output {
+        return Err(io::Error::new(io::ErrorKind::FileTooLarge, format!("target size {} exceeds the output limit", total)));
+    }
+    let keep_history = windows.iter().any(|window| window.indicator & VCD_TARGET != 0);
+    let codes = default_code_table();
+    let mut history = Vec::new();
+    let mut target = Vec::new();
+    let mut written = 0u64;
+
+    for window in windows {
+        cancel::check(cancel)?;
+        let segment_range = usize::try_from(window.segment_pos).ok()
+            .zip(usize::try_from(window.segment_len).ok())
+            .and_then(|(pos, len)| Some(pos..pos.checked_add(len)?));
+        let segment_range = segment_range.ok_or_else(|| corrupt("bad source segment"))?;
+        let segment = match window.indicator & (VCD_SOURCE | VCD_TARGET) {
+            VCD_SOURCE => old.get(segment_range),
+            VCD_TARGET => history.get(segment_range),
+            _ => Some(&[][..]),
+        };
+        let segment: &[u8] = segment.ok_or_else(|| corrupt("source segment out of range"))?;
+
+        target.clear();
+        target.reserve(window.target_len.min(WINDOW_SIZE as u64) as usize);
+        let (mut data, mut inst, mut addresses) = (Section::new(window.data), Section::new(window.inst), Section::new(window.addresses));
+        let mut cache = AddressCache::new();
+        while !inst.is_empty() {
+            let code = codes[inst.byte()? as usize];
+            for (kind, size) in code.inst.into_iter().zip(code.size) {
+                if kind == Inst::Noop {
+                    continue;
+                }
+                let size = match size {
+                    0 => inst.varint()?,
+                    size => u64::from(size),
+                };
+                if size > window.target_len - target.len() as u64 {
+                    return Err(corrupt("instructions overrun the target window"));
+                }
+                match kind {
+                    Inst::Add => target.extend_from_slice(data.take(size)?),
+                    Inst::Run => {
+                        let byte = data.byte()?;
+                        target.resize(target.len() + size as usize, byte);
+                    }
+                    Inst::Copy(mode) => {
+                        let here = segment.len() as u64 + target.len() as u64;
+                        let addr = cache.decode(mode, here, &mut addresses)? as usize;
+                        let size = size as usize;
+                        if addr + size <= segment.len() {
+                            target.extend_from_slice(&segment[addr..addr + size]);
+                        } else {
+                            // 跨入目标窗口的复制允许与输出重叠，逐字节进行
+                            for offset in addr..addr + size {
+                                let byte = match offset.checked_sub(segment.len()) {
+                                    None => segment[offset],
+                                    Some(index) => target[index],
+                                };
+                                target.push(byte);
+                            }
+                        }
+                    }
+                    Inst::Noop => unreachable!(),
+                }
+            }
+        }
+        if target.len() as u64 != window.target_len || !data.is_empty() || !addresses.is_empty() {
+            return Err(corrupt("window sections do not match the target size"));
+        }
+        if window.checksum.is_some_and(|checksum| checksum != adler32(&target)) {
+            return Err(corrupt("window checksum mismatch"));
+        }
+        writer.write_all(&target)?;
+        written += target.len() as u64;
+        if keep_history {
+            history.extend_from_slice(&target);
+        }
+    }
+    Ok(written)
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += u32::from(*byte);
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply_all(old: &[u8], patch: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        apply(old, patch, &mut out, u64::MAX, None)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let old: Vec<u8> = (0..200_000u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let mut new = old.clone();
+        new[1000..1010].copy_from_slice(b"0123456789");
+        new.splice(50_000..50_000, b"inserted block".repeat(30));
+        new.truncate(180_000);
+        let mut raw = Vec::new();
+        bsdiff::diff(&old, &new, &mut raw).unwrap();
+
+        let patch = from_raw(&old, &raw).unwrap();
+        assert!(is_vcdiff(&patch));
+        assert!(patch.len() < new.len() / 10);
+        assert_eq!(apply_all(&old, &patch).unwrap(), new);
+        assert_eq!(target_size(&patch).unwrap(), new.len() as u64);
+
+        // 空目标
+        let mut raw = Vec::new();
+        bsdiff::diff(&old, &[], &mut raw).unwrap();
+        assert_eq!(apply_all(&old, &from_raw(&old, &raw).unwrap()).unwrap(), b"");
+
+        // 超出输出上限、截断
+        assert!(apply(&old, &patch, &mut Vec::new(), 100, None).is_err());
+        assert!(apply_all(&old, &patch[..patch.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_decode_xdelta3_features() {
+        // 应用头部 + VCD_SOURCE 窗口 (ADD+COPY 组合指令、same 模式地址、Adler-32 校验)
+        // + VCD_TARGET 窗口 (RUN、与输出重叠的 COPY)
+        let old = b"abcdefgh";
+        let mut patch = VCDIFF_MAGIC.to_vec();
+        patch.extend_from_slice(&[VCD_APPHEADER, 3, b'a', b'p', b'p']);
+
+        // 窗口 1: 目标 "Xabcd" + "abcd" = "Xabcdabcd"
+        let target1 = b"Xabcdabcd";
+        let mut delta = vec![9, 0, 1, 2, 2, b'X'];
+        delta.extend_from_slice(&[add_copy_code(1, 0, 4).unwrap(), copy_code(6, 4)]);
+        delta.extend_from_slice(&[0, 0]); // SELF 0，随后 same 模式命中地址 0
+        let mut window = vec![VCD_SOURCE | VCD_ADLER32, 8, 0];
+        let mut body = delta[..5].to_vec();
+        body.extend_from_slice(&adler32(target1).to_be_bytes());
+        body.extend_from_slice(&delta[5..]);
+        window.push(body.len() as u8);
+        window.extend_from_slice(&body);
+        patch.extend_from_slice(&window);
+
+        // 窗口 2: 以窗口 1 的目标 "Xa" 为源段，RUN 3 个 'z'，再从地址 2 重叠复制 4 字节 → "zzzz"
+        let code_run = 0u8;
+        let delta = [7, 0, 1, 3, 1, b'z', code_run, 3, copy_code(0, 4), 2];
+        patch.extend_from_slice(&[VCD_TARGET, 2, 0, delta.len() as u8]);
+        patch.extend_from_slice(&delta);
+
+        assert_eq!(apply_all(old, &patch).unwrap(), b"Xabcdabcdzzzzzzz");
+
+        // 校验和不符
+        let mut tampered = patch.clone();
+        let checksum_at = 4 + 5 + 4 + 5;
+        tampered[checksum_at] ^= 0xff;
+        assert!(apply_all(old, &tampered).is_err());
+
+        // 二次压缩不支持
+        let mut compressed = VCDIFF_MAGIC.to_vec();
+        compressed.extend_from_slice(&[VCD_DECOMPRESS, 2]);
+        assert_eq!(apply_all(old, &compressed).unwrap_err().kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn test_code_table() {
+        let codes = default_code_table();
+        assert_eq!(codes[add_code(17) as usize].size[0], 17);
+        assert_eq!(codes[copy_code(8, 18) as usize].inst[0], Inst::Copy(8));
+        let combo = codes[add_copy_code(4, 8, 4).unwrap() as usize];
+        assert_eq!((combo.inst, combo.size), ([Inst::Add, Inst::Copy(8)], [4, 4]));
+        let combo = codes[add_copy_code(3, 5, 6).unwrap() as usize];
+        assert_eq!((combo.inst, combo.size), ([Inst::Add, Inst::Copy(5)], [3, 6]));
+
+        let mut out = Vec::new();
+        write_varint(&mut out, 123456789);
+        assert_eq!(out, [0xBA, 0xEF, 0x9A, 0x15]); // RFC 3284 第 2 节示例
+        assert_eq!(Section::new(&out).varint().unwrap(), 123456789);
+        assert_eq!(varint_len(123456789), 4);
+    }
+}
@@ -0,0 +1,774 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::archive_transform::ArchiveTransform;
+use crate::error::BsdiffError;
+use crate::exe_transform::ExeTransform;
+use crate::json;
+
+/// 摘要长度 (SHA-256 与 BLAKE3 均为 32 字节)
+pub const DIGEST_LEN: usize = 32;
+
+/// 一次性计算 SHA-256
+pub(crate) fn sha256(data: &[u8]) -> [u8; DIGEST_LEN] {
+    Sha256::digest(data).into()
+}
+
+/// 补丁容器魔数
+pub const MAGIC: [u8; 5] = *b"BSRZ\x01";
+
+/// 当前补丁容器格式版本 (v2 起头部带压缩算法 id 与标志位)
+pub const FORMAT_VERSION: u8 = 2;
+
+/// zstd 帧魔数，无补丁头的旧版补丁以此开头
+pub const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// 标志位：负载基于可执行文件变换后的数据生成，应用时需逆变换 (见 [`TAG_EXE_TRANSFORM`] 字段)
+pub const FLAG_EXE_TRANSFORM: u8 = 0x01;
+
+/// 标志位：负载使用共享 zstd 字典压缩，应用时需提供 ID 相同的字典 (见 [`TAG_DICTIONARY`] 字段)
+pub const FLAG_DICTIONARY: u8 = 0x02;
+
+/// 标志位：源/目标摘要使用 SHA-256 以外的算法 (见 [`TAG_HASH_ALGORITHM`] 字段)；
+/// 不认识该标志位的旧版本会拒绝补丁，而不是把摘要当作 SHA-256 比较
+pub const FLAG_HASH_ALGORITHM: u8 = 0x04;
+
+/// 标志位：负载之后带 [`PAYLOAD_TRAILER_LEN`] 字节的尾部 (负载长度与 CRC-32C)，应用补丁时边读边校验
+pub const FLAG_PAYLOAD_CRC: u8 = 0x08;
+
+/// 标志位：负载基于解压展开后的 ZIP 数据生成，应用时需重新压缩条目 (见 [`TAG_ARCHIVE_TRANSFORM`] 字段)；
+/// 不认识该标志位的旧版本会拒绝补丁，而不是把展开后的数据当作新文件写出
+pub const FLAG_ARCHIVE_TRANSFORM: u8 = 0x10;
+
+/// 当前已定义的标志位；读取时拒绝未知标志位，避免以错误的语义解码
+const KNOWN_FLAGS: u8 = FLAG_EXE_TRANSFORM | FLAG_DICTIONARY | FLAG_HASH_ALGORITHM | FLAG_PAYLOAD_CRC | FLAG_ARCHIVE_TRANSFORM;
+
+/// 负载尾部长度: `payload_len: u64 LE | crc32c: u32 LE | TRAILER_MAGIC`
+pub const PAYLOAD_TRAILER_LEN: usize = 16;
+
+/// 负载尾部魔数
+const TRAILER_MAGIC: [u8; 4] = *b"BSRT";
+
+/// 内置 diff 算法 (`prepared.rs`) 的版本，即其移植自的 bsdiff 版本；写入补丁头，算法改动时同步更新
+pub const ALGORITHM_VERSION: &str = "0.2.1";
+
+/// 头部字段总长度上限，防止损坏的补丁触发超大分配
+const MAX_FIELDS_LEN: u32 = 64 * 1024;
+
+// === 字段标签 ===
+const TAG_BSDIFF_VERSION: u8 = 0x01;
+const TAG_SOURCE: u8 = 0x02;
+const TAG_TARGET: u8 = 0x03;
+const TAG_CONTROL_BLOCKS: u8 = 0x04;
+const TAG_METADATA: u8 = 0x05;
+const TAG_EXE_TRANSFORM: u8 = 0x06;
+const TAG_DICTIONARY: u8 = 0x07;
+const TAG_HASH_ALGORITHM: u8 = 0x08;
+const TAG_SOURCE_PREFIX: u8 = 0x09;
+const TAG_FULL_FILE: u8 = 0x0A;
+const TAG_ARCHIVE_TRANSFORM: u8 = 0x0B;
+
+/// 源文件开头片段的长度：补丁头记录其 SHA-256，应用前无需哈希整个旧文件即可发现用错了基准文件
+pub const SOURCE_PREFIX_LEN: usize = 64 * 1024;
+
+/// 元数据 JSON 的长度上限 (字节)
+pub const MAX_METADATA_LEN: usize = 16 * 1024;
+
+/// 补丁负载的压缩算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    /// 不压缩，负载即原始 bsdiff 流
+    None,
+    #[default]
+    Zstd,
+    /// xz (LZMA2)，需要 `xz` feature
+    Xz,
+    /// 需要 `brotli` feature
+    Brotli,
+    Gzip,
+}
+
+impl Compression {
+    pub fn id(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Zstd => 1,
+            Compression::Xz => 2,
+            Compression::Brotli => 3,
+            Compression::Gzip => 4,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Zstd),
+            2 => Some(Compression::Xz),
+            3 => Some(Compression::Brotli),
+            4 => Some(Compression::Gzip),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Zstd => "zstd",
+            Compression::Xz => "xz",
+            Compression::Brotli => "brotli",
+            Compression::Gzip => "gzip",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self, BsdiffError> {
+        let compression = match name {
+            "none" => Compression::None,
+            "zstd" => Compression::Zstd,
+            // lzma 作为 xz 的别名 (负载始终是 .xz 容器)
+            "xz" | "lzma" => Compression::Xz,
+            "brotli" => Compression::Brotli,
+            "gzip" => Compression::Gzip,
+            other => {
+                return Err(format!("Unknown compression: {} (expected zstd, xz, brotli, gzip or none)", other).into());
+            }
+        };
+        if !crate::codecs::is_available(compression) {
+            return Err(format!("Compression backend {} is not available in this build", compression.name()).into());
+        }
+        Ok(compression)
+    }
+}
+
+/// 源/目标文件摘要的哈希算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    /// 树状结构可多线程计算，多 GB 文件的校验远快于 SHA-256
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn id(self) -> u8 {
+        match self {
+            HashAlgorithm::Sha256 => 0,
+            HashAlgorithm::Blake3 => 1,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(HashAlgorithm::Sha256),
+            1 => Some(HashAlgorithm::Blake3),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self, BsdiffError> {
+        match name {
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            other => Err(format!("Unknown hash algorithm: {} (expected \"sha256\" or \"blake3\")", other).into()),
+        }
+    }
+}
+
+/// BLAKE3 按该大小批量计算，使流式输入 (如 64KB 写入) 也能多线程计算子树
+const BLAKE3_BATCH_LEN: usize = 4 << 20;
+
+/// 按算法增量计算摘要
+#[derive(Clone)]
+pub enum Hasher {
+    Sha256(Sha256),
+    Blake3 { hasher: Box<blake3::Hasher>, pending: Vec<u8> },
+}
+
+impl Hasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3 { hasher: Box::default(), pending: Vec::new() },
+        }
+    }
+
+    /// 追加数据
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Blake3 { hasher, pending } => {
+                if pending.is_empty() && data.len() >= BLAKE3_BATCH_LEN {
+                    hasher.update_rayon(data);
+                    return;
+                }
+                pending.extend_from_slice(data);
+                if pending.len() >= BLAKE3_BATCH_LEN {
+                    hasher.update_rayon(pending);
+                    pending.clear();
+                }
+            }
+        }
+    }
+
+    pub fn finalize(self) -> [u8; DIGEST_LEN] {
+        match self {
+            Hasher::Sha256(hasher) => hasher.finalize().into(),
+            Hasher::Blake3 { mut hasher, pending } => {
+                hasher.update(&pending);
+                *hasher.finalize().as_bytes()
+            }
+        }
+    }
+}
+
+/// 判断数据是否以 zstd 帧开头 (旧版无头补丁)
+pub fn is_zstd_frame(head: &[u8]) -> bool {
+    head.starts_with(&ZSTD_MAGIC)
+}
+
+/// 旧文件开头片段的 SHA-256：只哈希前 [`SOURCE_PREFIX_LEN`] 字节，与文件大小无关
+pub fn source_prefix_digest(old: &[u8]) -> [u8; DIGEST_LEN] {
+    sha256(&old[..old.len().min(SOURCE_PREFIX_LEN)])
+}
+
+/// 文件摘要 (大小 + 哈希)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileDigest {
+    pub size: u64,
+    pub algorithm: HashAlgorithm,
+    pub hash: [u8; DIGEST_LEN],
+}
+
+impl FileDigest {
+    /// 计算内存数据的 SHA-256 摘要
+    pub fn of_bytes(data: &[u8]) -> Self {
+        Self::of_bytes_with(HashAlgorithm::Sha256, data)
+    }
+
+    /// 用指定算法计算内存数据的摘要 (BLAKE3 多线程)
+    pub fn of_bytes_with(algorithm: HashAlgorithm, data: &[u8]) -> Self {
+        let hash = match algorithm {
+            HashAlgorithm::Sha256 => sha256(data),
+            HashAlgorithm::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                if data.len() >= BLAKE3_BATCH_LEN {
+                    hasher.update_rayon(data);
+                } else {
+                    hasher.update(data);
+                }
+                *hasher.finalize().as_bytes()
+            }
+        };
+        Self { size: data.len() as u64, algorithm, hash }
+    }
+
+    /// 流式计算文件的 SHA-256 摘要 (常量内存)
+    pub fn of_file(path: &Path) -> io::Result<Self> {
+        Self::of_file_with(HashAlgorithm::Sha256, path)
+    }
+
+    /// 用指定算法流式计算文件摘要
+    pub fn of_file_with(algorithm: HashAlgorithm, path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::with_capacity(64 * 1024, File::open(path)?);
+        let mut hasher = Hasher::new(algorithm);
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut size = 0u64;
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            size += n as u64;
+        }
+        Ok(Self {
+            size,
+            algorithm,
+            hash: hasher.finalize(),
+        })
+    }
+
+    /// 十六进制哈希
+    pub fn hex(&self) -> String {
+        self.hash.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn to_field(self) -> [u8; 8 + DIGEST_LEN] {
+        let mut field = [0u8; 8 + DIGEST_LEN];
+        field[..8].copy_from_slice(&self.size.to_le_bytes());
+        field[8..].copy_from_slice(&self.hash);
+        field
+    }
+
+    /// 字段中不含算法，先按 SHA-256 解析，读完整个头部后再按 [`TAG_HASH_ALGORITHM`] 修正
+    fn from_field(value: &[u8]) -> Option<Self> {
+        if value.len() != 8 + DIGEST_LEN {
+            return None;
+        }
+        let mut size = [0u8; 8];
+        size.copy_from_slice(&value[..8]);
+        let mut hash = [0u8; DIGEST_LEN];
+        hash.copy_from_slice(&value[8..]);
+        Some(Self {
+            size: u64::from_le_bytes(size),
+            algorithm: HashAlgorithm::Sha256,
+            hash,
+        })
+    }
+}
+
+/// 补丁文件头
+///
+/// 布局: `MAGIC | format_version: u8 | compression: u8 | flags: u8 | fields_len: u32 LE | fields`，
+/// 每个字段为 `tag: u8 | len: u16 LE | value`，未知字段在读取时被跳过。
+/// v1 头部没有 compression/flags 两个字节，读取时视为 zstd、无标志位；写入总是使用当前版本。
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchHeader {
+    /// 补丁容器格式版本
+    pub format_version: u8,
+    /// 负载压缩算法
+    pub compression: Compression,
+    /// 标志位 (写入时按字段自动设置 [`FLAG_EXE_TRANSFORM`]、[`FLAG_DICTIONARY`] 与 [`FLAG_HASH_ALGORITHM`])
+    pub flags: u8,
+    /// 生成补丁的 diff 算法版本 (见 [`ALGORITHM_VERSION`])
+    pub bsdiff_version: String,
+    /// 旧文件 (补丁源) 摘要
+    pub source: Option<FileDigest>,
+    /// 新文件 (补丁目标) 摘要
+    pub target: Option<FileDigest>,
+    /// 原始 bsdiff 流中的控制块数量，用于按块上报 patch 进度
+    pub control_blocks: Option<u64>,
+    /// 调用方附加的键值元数据 (如应用版本、发布渠道)，以 JSON 对象存储；为空时不写入
+    pub metadata: BTreeMap<String, String>,
+    /// 可执行文件变换 (架构与新旧文件的代码区间)，diff 时启用 exe_transform 且输入为可执行文件时写入
+    pub exe_transform: Option<ExeTransform>,
+    /// 压缩负载所用 zstd 字典的 ID，diff 时提供字典则写入
+    pub dictionary_id: Option<u32>,
+    /// 负载之后是否带 CRC-32C 尾部 (写入时据此设置 [`FLAG_PAYLOAD_CRC`]，尾部由 [`PayloadWriter`] 写出)
+    pub payload_crc: bool,
+    /// 旧文件前 [`SOURCE_PREFIX_LEN`] 字节 (文件更短时为整个文件) 的 SHA-256，应用补丁前快速预检
+    pub source_prefix: Option<[u8; DIGEST_LEN]>,
+    /// 负载是以空内容为旧文件生成的 bsdiff 流 (即完整的新文件)，diff 启用 `fallback_to_full` 且差分补丁过大时写入。
+    /// 负载不读取旧文件，不认识该字段的旧版本照常应用；源文件摘要仍照常记录与校验
+    pub full_file: bool,
+    /// 压缩包变换 (展开后的新文件大小)，diff 时启用 archive_transform 且新文件是含可复现条目的 ZIP 时写入
+    pub archive_transform: Option<ArchiveTransform>,
+}
+
+impl Default for PatchHeader {
+    fn default() -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            compression: Compression::Zstd,
+            flags: 0,
+            bsdiff_version: ALGORITHM_VERSION.to_string(),
+            source: None,
+            target: None,
+            control_blocks: None,
+            metadata: BTreeMap::new(),
+            exe_transform: None,
+            dictionary_id: None,
+            payload_crc: false,
+            source_prefix: None,
+            full_file: false,
+            archive_transform: None,
+        }
+    }
+}
+
+impl PatchHeader {
+    /// 创建带源/目标摘要的头部
+    pub fn with_digests(source: FileDigest, target: FileDigest) -> Self {
+        Self {
+            source: Some(source),
+            target: Some(target),
+            ..Default::default()
+        }
+    }
+
+    /// 记录旧文件开头片段的摘要 (见 [`source_prefix_digest`])
+    pub fn with_source_prefix(self, old: &[u8]) -> Self {
+        Self { source_prefix: Some(source_prefix_digest(old)), ..self }
+    }
+
+    /// 源/目标摘要使用的哈希算法 (没有摘要时为 SHA-256)
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.source.or(self.target).map_or(HashAlgorithm::Sha256, |digest| digest.algorithm)
+    }
+
+    /// 写入头部
+    pub fn write_to<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        let algorithm = self.hash_algorithm();
+        if self.source.into_iter().chain(self.target).any(|digest| digest.algorithm != algorithm) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Source and target digests use different hash algorithms"));
+        }
+        let mut fields = Vec::new();
+        Self::push_field(&mut fields, TAG_BSDIFF_VERSION, self.bsdiff_version.as_bytes())?;
+        if let Some(source) = self.source {
+            Self::push_field(&mut fields, TAG_SOURCE, &source.to_field())?;
+        }
+        if let Some(target) = self.target {
+            Self::push_field(&mut fields, TAG_TARGET, &target.to_field())?;
+        }
+        if let Some(prefix) = &self.source_prefix {
+            Self::push_field(&mut fields, TAG_SOURCE_PREFIX, prefix)?;
+        }
+        if let Some(count) = self.control_blocks {
+            Self::push_field(&mut fields, TAG_CONTROL_BLOCKS, &count.to_le_bytes())?;
+        }
+        if self.full_file {
+            Self::push_field(&mut fields, TAG_FULL_FILE, &[])?;
+        }
+        if !self.metadata.is_empty() {
+            let json = metadata_to_json(&self.metadata);
+            if json.len() > MAX_METADATA_LEN {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Patch metadata is {} bytes as JSON, the limit is {}", json.len(), MAX_METADATA_LEN),
+                ));
+            }
+            Self::push_field(&mut fields, TAG_METADATA, json.as_bytes())?;
+        }
+        let mut flags = self.flags & !(FLAG_EXE_TRANSFORM | FLAG_DICTIONARY | FLAG_HASH_ALGORITHM | FLAG_PAYLOAD_CRC | FLAG_ARCHIVE_TRANSFORM);
+        if let Some(transform) = &self.exe_transform {
+            Self::push_field(&mut fields, TAG_EXE_TRANSFORM, &transform.to_field())?;
+            flags |= FLAG_EXE_TRANSFORM;
+        }
+        if let Some(id) = self.dictionary_id {
+            Self::push_field(&mut fields, TAG_DICTIONARY, &id.to_le_bytes())?;
+            flags |= FLAG_DICTIONARY;
+        }
+        if algorithm != HashAlgorithm::Sha256 {
+            Self::push_field(&mut fields, TAG_HASH_ALGORITHM, &[algorithm.id()])?;
+            flags |= FLAG_HASH_ALGORITHM;
+        }
+        if self.payload_crc {
+            flags |= FLAG_PAYLOAD_CRC;
+        }
+        if let Some(transform) = &self.archive_transform {
+            Self::push_field(&mut fields, TAG_ARCHIVE_TRANSFORM, &transform.to_field())?;
+            flags |= FLAG_ARCHIVE_TRANSFORM;
+        }
+
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&[FORMAT_VERSION, self.compression.id(), flags])?;
+        writer.write_all(&(fields.len() as u32).to_le_bytes())?;
+        writer.write_all(&fields)?;
+        Ok(())
+    }
+
+    /// 读取头部；没有魔数时视为旧版裸 zstd 补丁，回退到起始位置并返回 `None`
+    pub fn read_from<R: Read + Seek>(reader: &mut R) -> Result<Option<Self>, BsdiffError> {
+        let mut magic = [0u8; MAGIC.len()];
+        let mut filled = 0;
+        while filled < magic.len() {
+            let n = reader.read(&mut magic[filled..])?;
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        if filled < magic.len() || magic != MAGIC {
+            reader.seek(SeekFrom::Start(0))?;
+            return Ok(None);
+        }
+
+        let mut version = [0u8; 1];
+        reader.read_exact(&mut version)?;
+        let format_version = version[0];
+        if format_version > FORMAT_VERSION {
+            return Err(format!(
+                "Unsupported patch format version: {} (supported up to {})",
+                format_version, FORMAT_VERSION
+            ).into());
+        }
+
+        let (compression, flags) = if format_version >= 2 {
+            let mut ids = [0u8; 2];
+            reader.read_exact(&mut ids)?;
+            let compression = Compression::from_id(ids[0])
+                .ok_or_else(|| format!("Unsupported patch compression id: {}", ids[0]))?;
+            if ids[1] & !KNOWN_FLAGS != 0 {
+                return Err(format!("Unsupported patch flags: {:#04x}", ids[1]).into());
+            }
+            (compression, ids[1])
+        } else {
+            (Compression::Zstd, 0)
+        };
+
+        let mut fields_len = [0u8; 4];
+        reader.read_exact(&mut fields_len)?;
+        let fields_len = u32::from_le_bytes(fields_len);
+        if fields_len > MAX_FIELDS_LEN {
+            return Err(format!("Corrupt patch header: fields length {} too large", fields_len).into());
+        }
+        let mut fields = vec![0u8; fields_len as usize];
+        reader.read_exact(&mut fields)?;
+
+        let mut header = Self {
+            format_version,
+            compression,
+            flags,
+            bsdiff_version: String::new(),
+            source: None,
+            target: None,
+            control_blocks: None,
+            metadata: BTreeMap::new(),
+            exe_transform: None,
+            dictionary_id: None,
+            payload_crc: flags & FLAG_PAYLOAD_CRC != 0,
+            source_prefix: None,
+            full_file: false,
+            archive_transform: None,
+        };
+        let mut hash_algorithm = None;
+        let mut pos = 0;
+        while pos < fields.len() {
+            if pos + 3 > fields.len() {
+                return Err("Corrupt patch header: truncated field".into());
+            }
+            let tag = fields[pos];
+            let len = u16::from_le_bytes([fields[pos + 1], fields[pos + 2]]) as usize;
+            let value = fields
+                .get(pos + 3..pos + 3 + len)
+                .ok_or("Corrupt patch header: truncated field")?;
+            match tag {
+                TAG_BSDIFF_VERSION => header.bsdiff_version = String::from_utf8_lossy(value).into_owned(),
+                TAG_SOURCE => header.source = Some(FileDigest::from_field(value).ok_or("Corrupt patch header: bad source digest")?),
+                TAG_TARGET => header.target = Some(FileDigest::from_field(value).ok_or("Corrupt patch header: bad target digest")?),
+                TAG_SOURCE_PREFIX => {
+                    header.source_prefix = Some(value.try_into().map_err(|_| "Corrupt patch header: bad source prefix digest")?);
+                }
+                TAG_FULL_FILE => header.full_file = true,
+                TAG_CONTROL_BLOCKS => {
+                    let count: [u8; 8] = value.try_into().map_err(|_| "Corrupt patch header: bad control block count")?;
+                    header.control_blocks = Some(u64::from_le_bytes(count));
+                }
+                TAG_METADATA => {
+                    header.metadata = std::str::from_utf8(value)
+                        .ok()
+                        .and_then(metadata_from_json)
+                        .ok_or("Corrupt patch header: bad metadata")?;
+                }
+                TAG_EXE_TRANSFORM => {
+                    header.exe_transform = Some(ExeTransform::from_field(value).ok_or("Corrupt patch header: bad executable transform")?);
+                }
+                TAG_ARCHIVE_TRANSFORM => {
+                    header.archive_transform = Some(ArchiveTransform::from_field(value).ok_or("Corrupt patch header: bad archive transform")?);
+                }
+                TAG_DICTIONARY => {
+                    let id: [u8; 4] = value.try_into().map_err(|_| "Corrupt patch header: bad dictionary id")?;
+                    header.dictionary_id = Some(u32::from_le_bytes(id));
+                }
+                TAG_HASH_ALGORITHM => {
+                    let id = match value {
+                        [id] => *id,
+                        _ => return Err("Corrupt patch header: bad hash algorithm".into()),
+                    };
+                    hash_algorithm = Some(HashAlgorithm::from_id(id).ok_or_else(|| format!("Unsupported hash algorithm id: {}", id))?);
+                }
+                _ => {}
+            }
+            pos += 3 + len;
+        }
+        if (flags & FLAG_EXE_TRANSFORM != 0) != header.exe_transform.is_some() {
+            return Err("Corrupt patch header: executable transform flag does not match its field".into());
+        }
+        if (flags & FLAG_ARCHIVE_TRANSFORM != 0) != header.archive_transform.is_some() {
+            return Err("Corrupt patch header: archive transform flag does not match its field".into());
+        }
+        if header.exe_transform.is_some() && header.archive_transform.is_some() {
+            return Err("Corrupt patch header: executable and archive transforms cannot be combined".into());
+        }
+        if (flags & FLAG_DICTIONARY != 0) != header.dictionary_id.is_some() {
+            return Err("Corrupt patch header: dictionary flag does not match its field".into());
+        }
+        if (flags & FLAG_HASH_ALGORITHM != 0) != hash_algorithm.is_some() {
+            return Err("Corrupt patch header: hash algorithm flag does not match its field".into());
+        }
+        if let Some(algorithm) = hash_algorithm {
+            for digest in [&mut header.source, &mut header.target].into_iter().flatten() {
+                digest.algorithm = algorithm;
+            }
+        }
+
+        Ok(Some(header))
+    }
+
+    /// 判断生成补丁的算法版本是否与当前版本存在实质差异 (比较 major.minor)
+    pub fn is_algorithm_compatible(&self) -> bool {
+        fn major_minor(version: &str) -> Vec<&str> {
+            version.split('.').take(2).collect()
+        }
+        major_minor(&self.bsdiff_version) == major_minor(ALGORITHM_VERSION)
+    }
+
+    #[inline]
+    fn push_field(fields: &mut Vec<u8>, tag: u8, value: &[u8]) -> io::Result<()> {
+        let len = u16::try_from(value.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Patch header field too large"))?;
+        fields.push(tag);
+        fields.extend_from_slice(&len.to_le_bytes());
+        fields.extend_from_slice(value);
+        Ok(())
+    }
+}
+
+/// 编码负载尾部
+pub fn payload_trailer(payload_len: u64, crc: u32) -> [u8; PAYLOAD_TRAILER_LEN] {
+    let mut trailer = [0u8; PAYLOAD_TRAILER_LEN];
+    trailer[..8].copy_from_slice(&payload_len.to_le_bytes());
+    trailer[8..12].copy_from_slice(&crc.to_le_bytes());
+    trailer[12..].copy_from_slice(&TRAILER_MAGIC);
+    trailer
+}
+
+/// 解析负载尾部，返回 (负载长度, CRC-32C)；长度或魔数不符 (如补丁被截断) 时返回 `None`
+pub fn parse_payload_trailer(trailer: &[u8]) -> Option<(u64, u32)> {
+    let trailer: &[u8; PAYLOAD_TRAILER_LEN] = trailer.try_into().ok()?;
+    (trailer[12..] == TRAILER_MAGIC).then(|| {
+        (u64::from_le_bytes(trailer[..8].try_into().unwrap()), u32::from_le_bytes(trailer[8..12].try_into().unwrap()))
+    })
+}
+
+/// 写出补丁负载并累计 CRC-32C，[`Self::finish`] 时追加负载尾部
+pub struct PayloadWriter<W: Write> {
+    inner: W,
+    crc: u32,
+    len: u64,
+}
+
+impl<W: Write> PayloadWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, crc: 0, len: 0 }
+    }
+
+    /// 写出尾部，返回内部写入器
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(&payload_trailer(self.len, self.crc))?;
+        Ok(self.inner)
+    }
+
+    /// 不写尾部，直接返回内部写入器 (补丁头未声明尾部时)
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for PayloadWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.crc = crc32c::crc32c_append(self.crc, &buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// 读取补丁负载 (读取器位于补丁头之后)
+///
+/// 带尾部时始终保留最后 [`PAYLOAD_TRAILER_LEN`] 字节不交给调用方，读到末尾时校验负载长度与 CRC-32C，
+/// 截断或损坏的补丁返回 `InvalidData`；不需要定位，网络流同样适用。不带尾部时原样读到末尾。
+pub struct PayloadReader<R: Read> {
+    inner: R,
+    checked: bool,
+    held: Vec<u8>,
+    crc: u32,
+    len: u64,
+    done: bool,
+}
+
+impl<R: Read> PayloadReader<R> {
+    /// `checked` 为补丁头的 [`PatchHeader::payload_crc`]
+    pub fn new(inner: R, checked: bool) -> Self {
+        Self { inner, checked, held: Vec::new(), crc: 0, len: 0, done: false }
+    }
+
+    /// 读完剩余负载并校验尾部；解码器可能在负载末尾之前停止读取，应用结束后调用
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.checked {
+            io::copy(self, &mut io::sink())?;
+        }
+        Ok(())
+    }
+
+    fn verify_trailer(&self) -> io::Result<()> {
+        let corrupt = |reason: String| io::Error::new(io::ErrorKind::InvalidData, reason);
+        let (len, crc) = parse_payload_trailer(&self.held)
+            .ok_or_else(|| corrupt("payload trailer missing, the patch is truncated".to_string()))?;
+        if len != self.len {
+            return Err(corrupt(format!("payload is {} bytes, trailer declares {}", self.len, len)));
+        }
+        if crc != self.crc {
+            return Err(corrupt(format!("payload CRC-32C mismatch: computed {:08x}, trailer declares {:08x}", self.crc, crc)));
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for PayloadReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.checked {
+            return self.inner.read(buf);
+        }
+        if buf.is_empty() || self.done {
+            return Ok(0);
+        }
+        loop {
+            if self.held.len() > PAYLOAD_TRAILER_LEN {
+                let n = (self.held.len() - PAYLOAD_TRAILER_LEN).min(buf.len());
+                buf[..n].copy_from_slice(&self.held[..n]);
+                self.held.drain(..n);
+                self.crc = crc32c::crc32c_append(self.crc, &buf[..n]);
+                self.len += n as u64;
+                return Ok(n);
+            }
+            let start = self.held.len();
+            self.held.resize(start + buf.len().max(8 * 1024), 0);
+            let read = self.inner.read(&mut self.held[start..]);
+            self.held.truncate(start + *read.as_ref().unwrap_or(&0));
+            if read? == 0 {
+                self.verify_trailer()?;
+                self.done = true;
+                return Ok(0);
+            }
+        }
+    }
+}
+/// 将元数据编码为紧凑的 JSON 对象 (键按字典序排列，输出可复现)
+pub fn metadata_to_json(metadata: &BTreeMap<String, String>) -> String {
+    let mut out = String::from("{");
+    for (i, (key, value)) in metadata.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        json::push_string(&mut out, key);
+        out.push(':');
+        json::push_string(&mut out, value);
+    }
+    out.push('}');
+    out
+}
+
+/// 解析只含字符串值的 JSON 对象；格式不符时返回 `None`
+pub fn metadata_from_json(json: &str) -> Option<BTreeMap<String, String>> {
+    json::parse(json)?
+        .as_object()?
+        .iter()
+        .map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+        .collect()
+}
+
@@ -0,0 +1,416 @@
+//! 从 URL 直接应用补丁：HTTP 响应体 (ureq + rustls，支持 http:// 与 https://) 流式交给补丁解码器，
+//! 不落地临时下载文件。补丁头 (含源文件摘要) 最先到达，旧文件不符时在下载负载前即中止。
+//!
+//! 重定向由本模块逐跳处理：跳到其他源 (协议、主机或端口不同) 后不再发送调用方给出的请求头，
+//! `Authorization` 等凭据不会泄露给第三方主机；不跟随从 https 到 http 的降级重定向。
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+use url::Url;
+
+use crate::bsdiff_rust::{BsdiffRust, OptimizationConfig};
+use crate::error::BsdiffError;
+
+/// 最多跟随的重定向次数
+const MAX_REDIRECTS: usize = 5;
+/// 可回退的响应体前缀长度：足够容纳补丁头 (元数据上限 16 KB) 与魔数识别
+const REWIND_LIMIT: usize = 1 << 20;
+/// 签名补丁需要整体读入内存验签，未给出 `max_size` 时的默认上限
+const SIGNED_PATCH_LIMIT: u64 = 512 << 20;
+
+/// 请求选项
+#[derive(Debug, Clone, Default)]
+pub struct HttpOptions {
+    /// 附加请求头 (如 `Authorization`)，只发送给与请求 URL 同源的地址
+    pub headers: Vec<(String, String)>,
+    /// 解析、连接、发送请求与等待响应头的超时，`None` 表示不超时；响应体的读取不受此限制
+    pub timeout: Option<Duration>,
+    /// 补丁大小上限 (字节)：Content-Length 或实际收到的响应体超过时中止下载
+    pub max_size: Option<u64>,
+}
+
+/// 解析并检查请求或重定向的 URL：只接受 http/https，拒绝 URL 中的账号密码，去掉片段
+fn parse_url(url: &str) -> io::Result<Url> {
+    let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid URL {}: {}", url, reason));
+    let mut parsed = Url::parse(url).map_err(|e| invalid(&e.to_string()))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(invalid("only http:// and https:// URLs are supported"));
+    }
+    if parsed.host_str().is_none_or(str::is_empty) {
+        return Err(invalid("missing host"));
+    }
+    if !parsed.username().is_empty() || parsed.password().is_some() {
+        return Err(invalid("credentials in the URL are not supported; pass an Authorization header"));
+    }
+    parsed.set_fragment(None);
+    Ok(parsed)
+}
+
+/// 解析重定向的 Location：按 RFC 3986 相对当前 URL 解析 (含不以 / 开头的相对路径)
+fn resolve(current: &Url, location: &str) -> io::Result<Url> {
+    let next = current.join(location)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Bad redirect Location {:?}: {}", location, e)))?;
+    let next = parse_url(next.as_str())?;
+    if current.scheme() == "https" && next.scheme() == "http" {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("Refusing to follow a redirect from https to {}", next)));
+    }
+    Ok(next)
+}
+
+/// HTTP 响应体；给出上限时超过即报错
+pub(crate) struct Body {
+    reader: ureq::BodyReader<'static>,
+    remaining: Option<u64>,
+}
+
+impl Read for Body {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        if let Some(remaining) = &mut self.remaining {
+            *remaining = remaining.checked_sub(n as u64)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "HTTP response body exceeds the patch size limit"))?;
+        }
+        Ok(n)
+    }
+}
+
+/// 成功的 GET 响应
+pub(crate) struct Response {
+    pub content_length: Option<u64>,
+    pub body: Body,
+}
+
+fn agent(options: &HttpOptions) -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .max_redirects(0)
+        .http_status_as_error(false)
+        .user_agent("bsdiff-rust")
+        .timeout_resolve(options.timeout)
+        .timeout_connect(options.timeout)
+        .timeout_send_request(options.timeout)
+        .timeout_recv_response(options.timeout)
+        .build()
+        .into()
+}
+
+/// 发送 GET 请求并跟随重定向，返回 200 响应；其他状态码转换为对应的 I/O 错误
+pub(crate) fn get(url: &str, options: &HttpOptions) -> io::Result<Response> {
+    for (name, value) in &options.headers {
+        let bad = |text: &str| text.bytes().any(|byte| byte == b'\r' || byte == b'\n');
+        if name.is_empty() || name.contains(':') || bad(name) || bad(value) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid HTTP header: {:?}", name)));
+        }
+    }
+
+    let agent = agent(options);
+    let origin = parse_url(url)?.origin();
+    let mut current = parse_url(url)?;
+    // 一旦跳到其他源，之后的请求 (即使又跳回来) 都不再带调用方的请求头
+    let mut send_headers = true;
+    for _ in 0..=MAX_REDIRECTS {
+        send_headers &= current.origin() == origin;
+        let mut request = agent.get(current.as_str());
+        if send_headers {
+            for (name, value) in &options.headers {
+                request = request.header(name.as_str(), value.trim());
+            }
+        }
+        let response = request.call().map_err(ureq::Error::into_io)?;
+        let status = response.status().as_u16();
+        let header = |name: &str| response.headers().get(name).and_then(|value| value.to_str().ok());
+        match status {
+            200 => {
+                let content_length = header("content-length")
+                    .map(|value| value.parse::<u64>().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Bad Content-Length")))
+                    .transpose()?
+                    .filter(|_| header("transfer-encoding").is_none());
+                if let (Some(len), Some(limit)) = (content_length, options.max_size) {
+                    if len > limit {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("Patch at {} is {} bytes, over the {} byte limit", url, len, limit),
+                        ));
+                    }
+                }
+                let reader = response.into_body().into_reader();
+                return Ok(Response { content_length, body: Body { reader, remaining: options.max_size } });
+            }
+            301 | 302 | 303 | 307 | 308 => {
+                let location = header("location")
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("HTTP {} without a Location header", status)))?;
+                current = resolve(&current, location)?;
+            }
+            _ => {
+                let kind = match status {
+                    404 | 410 => io::ErrorKind::NotFound,
+                    401 | 403 => io::ErrorKind::PermissionDenied,
+                    _ => io::ErrorKind::Other,
+                };
+                let reason = response.status().canonical_reason().unwrap_or_default();
+                return Err(io::Error::new(kind, format!("HTTP {} {} fetching {}", status, reason, url)));
+            }
+        }
+    }
+    Err(io::Error::other(format!("Too many redirects fetching {}", url)))
+}
+
+/// 为只能顺序读取的响应体提供有限的回退：前 `limit` 字节边读边缓存，
+/// 供补丁解析时回到开头识别魔数与补丁头；读过缓存范围后只能继续顺序读取
+pub(crate) struct Rewindable<R> {
+    inner: R,
+    head: Vec<u8>,
+    pos: u64,
+    limit: usize,
+    streaming: bool,
+}
+
+impl<R: Read> Rewindable<R> {
+    pub(crate) fn new(inner: R, limit: usize) -> Self {
+        Self { inner, head: Vec::new(), pos: 0, limit, streaming: false }
+    }
+}
+
+impl<R: Read> Read for Rewindable<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(cached) = self.head.get(self.pos as usize..).filter(|cached| !cached.is_empty()) {
+            let n = cached.len().min(buf.len());
+            buf[..n].copy_from_slice(&cached[..n]);
+            self.pos += n as u64;
+            return Ok(n);
+        }
+        let n = self.inner.read(buf)?;
+        if !self.streaming {
+            if self.head.len() + n <= self.limit {
+                self.head.extend_from_slice(&buf[..n]);
+            } else {
+                self.streaming = true;
+                self.head = Vec::new();
+            }
+        }
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read> Seek for Rewindable<R> {
+    fn seek(&mut self, target: SeekFrom) -> io::Result<u64> {
+        let target = match target {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+            SeekFrom::End(_) => None,
+        };
+        match target {
+            Some(offset) if offset == self.pos => Ok(offset),
+            Some(offset) if !self.streaming && offset <= self.head.len() as u64 => {
+                self.pos = offset;
+                Ok(offset)
+            }
+            _ => Err(io::Error::new(io::ErrorKind::Unsupported, "HTTP patch stream can only rewind within its first bytes")),
+        }
+    }
+}
+
+/// 从 `url` 流式下载补丁并应用到 `old_file`，写入 `new_file`，返回新文件大小
+///
+/// 输出先写入临时路径，成功后原子替换；下载或校验失败时不产生输出。
+/// 配置了签名时需先读完整个补丁才能校验，此时补丁缓存在内存中，
+/// 大小受 `max_size` 限制 (未给出时为 512 MiB)。
+pub fn patch_from_url(
+    old_file: &Path,
+    url: &str,
+    new_file: &Path,
+    options: &HttpOptions,
+    config: &OptimizationConfig
+) -> Result<u64, BsdiffError> {
+    if !Path::new(old_file).exists() {
+        return Err(BsdiffError::FileNotFound { kind: "Old file", path: old_file.display().to_string() });
+    }
+    if config.dry_run {
+        return Err("dryRun is not supported by patchFromUrl".into());
+    }
+    parse_url(url)?;
+    let old_mmap = BsdiffRust::create_single_memory_map(old_file)?;
+    let options = match config.signature {
+        Some(_) => HttpOptions { max_size: Some(options.max_size.unwrap_or(SIGNED_PATCH_LIMIT)), ..options.clone() },
+        None => options.clone(),
+    };
+    let open_patch = || {
+        let response = get(url, &options)?;
+        Ok((Rewindable::new(response.body, REWIND_LIMIT), response.content_length.unwrap_or(0)))
+    };
+    BsdiffRust::patch_reader_to_file(&old_mmap, open_patch, Path::new(url), new_file, None, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    /// 在本地端口上依次返回给定的原始响应，并把收到的请求头发回
+    fn serve(responses: Vec<Vec<u8>>) -> (u16, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|n| n > 0) && line.trim_end() != "" {
+                    request.push_str(line.trim_end());
+                    request.push('\n');
+                    line.clear();
+                }
+                let _ = sender.send(request);
+                let _ = stream.write_all(&response);
+            }
+        });
+        (port, receiver)
+    }
+
+    fn ok(body: &[u8]) -> Vec<u8> {
+        [format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).as_bytes(), body].concat()
+    }
+
+    fn redirect(location: &str) -> Vec<u8> {
+        format!("HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\n\r\n", location).into_bytes()
+    }
+
+    fn has_header(request: &str, name: &str) -> bool {
+        request.lines().any(|line| line.to_ascii_lowercase().starts_with(&format!("{}:", name.to_ascii_lowercase())))
+    }
+
+    #[test]
+    fn test_parse_url() {
+        let url = parse_url("http://cdn.example.com/a/b.patch?v=2#x").unwrap();
+        assert_eq!(url.as_str(), "http://cdn.example.com/a/b.patch?v=2");
+        assert_eq!(parse_url("https://[::1]:8443?x").unwrap().as_str(), "https://[::1]:8443/?x");
+        let base = parse_url("http://host/dir/old.patch").unwrap();
+        assert_eq!(resolve(&base, "/other").unwrap().as_str(), "http://host/other");
+        assert_eq!(resolve(&base, "new.patch").unwrap().as_str(), "http://host/dir/new.patch");
+        assert_eq!(resolve(&base, "../up?q").unwrap().as_str(), "http://host/up?q");
+        assert_eq!(resolve(&base, "//mirror/p").unwrap().as_str(), "http://mirror/p");
+        assert_eq!(resolve(&base, "https://mirror/p").unwrap().host_str(), Some("mirror"));
+        let secure = parse_url("https://host/p").unwrap();
+        assert_eq!(resolve(&secure, "http://host/p").unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        for bad in ["ftp://x/p", "http://", "http://u:p@host/", "http://host:99999/", "not a url"] {
+            assert!(parse_url(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_get_framing_and_redirects() {
+        let chunked = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nX-Trailer: 1\r\n\r\n".to_vec();
+        let (port, requests) = serve(vec![
+            ok(b"plain body"),
+            chunked,
+            redirect("moved"),
+            ok(b"moved"),
+            b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec(),
+        ]);
+        let base = format!("http://127.0.0.1:{}", port);
+        let options = HttpOptions { headers: vec![("Authorization".into(), "Bearer t".into())], timeout: Some(Duration::from_secs(5)), ..Default::default() };
+        let read_all = |path: &str| -> io::Result<(Option<u64>, Vec<u8>)> {
+            let mut response = get(&format!("{}{}", base, path), &options)?;
+            let mut body = Vec::new();
+            response.body.read_to_end(&mut body)?;
+            Ok((response.content_length, body))
+        };
+
+        assert_eq!(read_all("/p").unwrap(), (Some(10), b"plain body".to_vec()));
+        let request = requests.recv().unwrap();
+        assert!(request.starts_with("GET /p HTTP/1.1\n") && request.contains("authorization: Bearer t\n"), "{}", request);
+        assert_eq!(read_all("/chunked").unwrap(), (None, b"hello world".to_vec()));
+        // 不以 / 开头的相对 Location 按当前路径解析；同源重定向保留请求头
+        assert_eq!(read_all("/dir/old").unwrap(), (Some(5), b"moved".to_vec()));
+        let moved = requests.iter().nth(2).unwrap();
+        assert!(moved.starts_with("GET /dir/moved ") && has_header(&moved, "authorization"), "{}", moved);
+        assert_eq!(read_all("/missing").err().unwrap().kind(), io::ErrorKind::NotFound);
+
+        let injected = HttpOptions { headers: vec![("X".into(), "a\r\nEvil: 1".into())], ..Default::default() };
+        assert!(get(&base, &injected).is_err());
+    }
+
+    #[test]
+    fn test_cross_origin_redirect_drops_headers() {
+        let (mirror, mirror_requests) = serve(vec![redirect("/back"), ok(b"mirror")]);
+        let (origin, origin_requests) = serve(vec![
+            redirect(&format!("http://127.0.0.1:{}/p", mirror)),
+            ok(b"back"),
+        ]);
+        let options = HttpOptions {
+            headers: vec![("Authorization".into(), "Bearer secret".into()), ("X-Api-Key".into(), "k".into())],
+            timeout: Some(Duration::from_secs(5)),
+            ..Default::default()
+        };
+        let mut response = get(&format!("http://127.0.0.1:{}/start", origin), &options).unwrap();
+        let mut body = Vec::new();
+        response.body.read_to_end(&mut body).unwrap();
+        assert_eq!(body, b"mirror");
+        assert!(has_header(&origin_requests.recv().unwrap(), "authorization"));
+        // 其他源收到的请求不带任何调用方给出的请求头
+        let request = mirror_requests.recv().unwrap();
+        assert!(!has_header(&request, "authorization") && !has_header(&request, "x-api-key"), "{}", request);
+        drop(origin_requests);
+    }
+
+    #[test]
+    fn test_max_size() {
+        let (port, _requests) = serve(vec![ok(&[1u8; 100]), b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n".iter().chain(&[2u8; 100]).copied().collect()]);
+        let options = HttpOptions { max_size: Some(50), timeout: Some(Duration::from_secs(5)), ..Default::default() };
+        let url = format!("http://127.0.0.1:{}/p", port);
+        // Content-Length 超限时不读响应体
+        assert!(get(&url, &options).is_err());
+        // 未声明长度时读到超限处中止
+        let mut body = Vec::new();
+        assert!(get(&url, &options).unwrap().body.read_to_end(&mut body).is_err());
+    }
+
+    #[test]
+    fn test_rewindable() {
+        let data: Vec<u8> = (0..100u8).collect();
+        let mut reader = Rewindable::new(&data[..], 16);
+        let mut head = [0u8; 10];
+        reader.read_exact(&mut head).unwrap();
+        reader.rewind().unwrap();
+        assert_eq!(reader.stream_position().unwrap(), 0);
+        let mut all = Vec::new();
+        reader.read_to_end(&mut all).unwrap();
+        assert_eq!(all, data);
+        // 超出缓存范围后无法回退
+        assert!(reader.rewind().is_err());
+        assert_eq!(reader.stream_position().unwrap(), 100);
+    }
+
+    #[test]
+    fn test_patch_from_url() {
+        let old = b"patch from url base content ".repeat(200);
+        let mut new = old.clone();
+        new[300..360].fill(b'@');
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let patch = BsdiffRust::diff_bytes(&old, &new, &config).unwrap();
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        std::fs::write(path("old"), &old).unwrap();
+        std::fs::write(path("other"), &new).unwrap();
+
+        let (port, _requests) = serve(vec![ok(&patch), ok(&patch)]);
+        let url = format!("http://127.0.0.1:{}/update.patch", port);
+        let options = HttpOptions { timeout: Some(Duration::from_secs(5)), ..Default::default() };
+        let size = patch_from_url(&path("old"), &url, &path("new"), &options, &config).unwrap();
+        assert_eq!(size, new.len() as u64);
+        assert_eq!(std::fs::read(path("new")).unwrap(), new);
+
+        // 旧文件不符时在补丁头处即失败，不产生输出
+        let err = patch_from_url(&path("other"), &url, &path("bad"), &options, &config).unwrap_err();
+        assert!(matches!(err, BsdiffError::BaseMismatch { .. }));
+        assert!(!dir.path().join("bad").exists());
+        assert!(patch_from_url(&path("missing"), &url, &path("bad"), &options, &config).is_err());
+    }
+}
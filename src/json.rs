@@ -0,0 +1,236 @@
+//! 最小化的 JSON 读写，供补丁头元数据与目录清单使用 (无第三方依赖)
+
+use std::collections::BTreeMap;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// 嵌套深度上限，防止恶意输入耗尽栈空间
+const MAX_DEPTH: usize = 64;
+
+/// JSON 值；数字保留原始文本，由调用方按需解析
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(BTreeMap<String, Value>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// 非负整数 (不接受小数、指数或负号)
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(raw) if raw.bytes().all(|b| b.is_ascii_digit()) => raw.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&BTreeMap<String, Value>> {
+        match self {
+            Value::Object(map) => Some(map),
+            _ => None,
+        }
+    }
+}
+
+/// 以 JSON 字符串字面量形式追加 `value`
+pub fn push_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// 解析完整的 JSON 文档；格式不符 (含尾随内容、重复键) 时返回 `None`
+pub fn parse(json: &str) -> Option<Value> {
+    let mut chars = json.chars().peekable();
+    let value = parse_value(&mut chars, 0)?;
+    skip_ws(&mut chars);
+    chars.next().is_none().then_some(value)
+}
+
+fn skip_ws(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+}
+
+fn parse_value(chars: &mut Peekable<Chars>, depth: usize) -> Option<Value> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+    skip_ws(chars);
+    match *chars.peek()? {
+        '{' => {
+            chars.next();
+            let mut map = BTreeMap::new();
+            skip_ws(chars);
+            if chars.next_if_eq(&'}').is_some() {
+                return Some(Value::Object(map));
+            }
+            loop {
+                skip_ws(chars);
+                let key = parse_string(chars)?;
+                skip_ws(chars);
+                if chars.next()? != ':' {
+                    return None;
+                }
+                let value = parse_value(chars, depth + 1)?;
+                if map.insert(key, value).is_some() {
+                    return None;
+                }
+                skip_ws(chars);
+                match chars.next()? {
+                    ',' => continue,
+                    '}' => return Some(Value::Object(map)),
+                    _ => return None,
+                }
+            }
+        }
+        '[' => {
+            chars.next();
+            let mut items = Vec::new();
+            skip_ws(chars);
+            if chars.next_if_eq(&']').is_some() {
+                return Some(Value::Array(items));
+            }
+            loop {
+                items.push(parse_value(chars, depth + 1)?);
+                skip_ws(chars);
+                match chars.next()? {
+                    ',' => continue,
+                    ']' => return Some(Value::Array(items)),
+                    _ => return None,
+                }
+            }
+        }
+        '"' => parse_string(chars).map(Value::String),
+        't' => parse_literal(chars, "true", Value::Bool(true)),
+        'f' => parse_literal(chars, "false", Value::Bool(false)),
+        'n' => parse_literal(chars, "null", Value::Null),
+        _ => parse_number(chars),
+    }
+}
+
+fn parse_literal(chars: &mut Peekable<Chars>, literal: &str, value: Value) -> Option<Value> {
+    literal.chars().all(|expected| chars.next() == Some(expected)).then_some(value)
+}
+
+fn parse_number(chars: &mut Peekable<Chars>) -> Option<Value> {
+    let mut raw = String::new();
+    let digits = |chars: &mut Peekable<Chars>, raw: &mut String| {
+        let start = raw.len();
+        while let Some(c) = chars.next_if(char::is_ascii_digit) {
+            raw.push(c);
+        }
+        raw.len() > start
+    };
+
+    // -?(0|[1-9][0-9]*)(\.[0-9]+)?([eE][+-]?[0-9]+)?
+    raw.extend(chars.next_if_eq(&'-'));
+    if !digits(chars, &mut raw) || (raw.trim_start_matches('-').starts_with('0') && raw.trim_start_matches('-').len() > 1) {
+        return None;
+    }
+    if let Some(dot) = chars.next_if_eq(&'.') {
+        raw.push(dot);
+        if !digits(chars, &mut raw) {
+            return None;
+        }
+    }
+    if let Some(e) = chars.next_if(|c| matches!(c, 'e' | 'E')) {
+        raw.push(e);
+        raw.extend(chars.next_if(|c| matches!(c, '+' | '-')));
+        if !digits(chars, &mut raw) {
+            return None;
+        }
+    }
+    Some(Value::Number(raw))
+}
+
+fn hex4(chars: &mut Peekable<Chars>) -> Option<u32> {
+    (0..4).try_fold(0, |acc, _| Some(acc * 16 + chars.next()?.to_digit(16)?))
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    if chars.next()? != '"' {
+        return None;
+    }
+    let mut out = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(out),
+            '\\' => match chars.next()? {
+                '"' => out.push('"'),
+                '\\' => out.push('\\'),
+                '/' => out.push('/'),
+                'b' => out.push('\u{8}'),
+                'f' => out.push('\u{c}'),
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                't' => out.push('\t'),
+                'u' => {
+                    let high = hex4(chars)?;
+                    let code = if (0xd800..0xdc00).contains(&high) {
+                        // UTF-16 代理对
+                        if chars.next()? != '\\' || chars.next()? != 'u' {
+                            return None;
+                        }
+                        let low = hex4(chars)?;
+                        if !(0xdc00..0xe000).contains(&low) {
+                            return None;
+                        }
+                        0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                    } else {
+                        high
+                    };
+                    out.push(char::from_u32(code)?);
+                }
+                _ => return None,
+            },
+            c if (c as u32) < 0x20 => return None,
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let value = parse(r#" {"a": [1, -2.5e3, true, null], "b": {"c": "é"}} "#).unwrap();
+        let object = value.as_object().unwrap();
+        assert_eq!(
+            object["a"],
+            Value::Array(vec![Value::Number("1".into()), Value::Number("-2.5e3".into()), Value::Bool(true), Value::Null])
+        );
+        assert_eq!(object["b"].as_object().unwrap()["c"].as_str(), Some("é"));
+        assert_eq!(parse("18446744073709551615").unwrap().as_u64(), Some(u64::MAX));
+        assert_eq!(parse("-1").unwrap().as_u64(), None);
+
+        for bad in ["", "{", "[1,]", "{\"a\":1,}", "{\"a\":1,\"a\":2}", "+1", ".5", "1.", "01", "-", "1e", "tru", "{} x", &"[".repeat(100)] {
+            assert_eq!(parse(bad), None, "{}", bad);
+        }
+
+        let mut out = String::new();
+        push_string(&mut out, "q\"\\\n\u{1}");
+        assert_eq!(parse(&out).unwrap().as_str(), Some("q\"\\\n\u{1}"));
+    }
+}
@@ -1,188 +1,74 @@
-use napi::bindgen_prelude::*;
-use napi_derive::napi;
-
+//! bsdiff-rust 核心库：补丁生成与应用、补丁容器与各类工具函数，不依赖 napi，可直接供其他 Rust 项目使用。
+//! Node.js 绑定位于 [`bindings`]，由默认开启的 `napi` feature 控制；`--no-default-features` 时只构建核心库。
+
+pub mod ab_update;
+mod archive_transform;
+pub mod audit;
+mod auto_level;
+pub mod block_delta;
+pub mod bsdiff40;
 mod bsdiff_rust;
-mod utils;
-use bsdiff_rust::BsdiffRust;
-use utils::{verify_patch as verify_patch_util, get_patch_info, get_file_size, check_file_access, get_compression_ratio};
-
-fn call_bsdiff(
-  old_str: &str,
-  new_str: &str,
-  patch: &str,
-) -> Result<()> {
-  BsdiffRust::diff(old_str, new_str, patch)
-    .map_err(|e| Error::from_reason(e.to_string()))
-}
-
-fn call_bspatch(
-  old_str: &str,
-  new_str: &str,
-  patch: &str,
-) -> Result<()> {
-  BsdiffRust::patch(old_str, new_str, patch)
-    .map_err(|e| Error::from_reason(e.to_string()))
-}
-
-#[napi]
-pub fn diff_sync(old_str: String, new_str: String, patch: String) -> Result<()> {
-  call_bsdiff(&old_str, &new_str, &patch)
-}
-
-#[napi]
-pub fn patch_sync(old_str: String, new_str: String, patch: String) -> Result<()> {
-  call_bspatch(&old_str, &new_str, &patch)
-}
-
-/// 验证补丁文件完整性
-#[napi]
-pub fn verify_patch_sync(old_str: String, new_str: String, patch: String) -> Result<bool> {
-  verify_patch_util(&old_str, &new_str, &patch)
-    .map_err(|e| Error::from_reason(e.to_string()))
-}
-
-/// 获取补丁文件信息
-#[napi]
-pub fn get_patch_info_sync(patch: String) -> Result<PatchInfoJs> {
-  let info = get_patch_info(&patch)
-    .map_err(|e| Error::from_reason(e.to_string()))?;
-  
-  Ok(PatchInfoJs {
-    size: info.size as f64,
-    compressed: info.compressed,
-  })
+pub mod bundle;
+mod cancel;
+pub mod codecs;
+mod control;
+mod deflate;
+pub mod dictionary;
+pub mod dir_diff;
+mod encryption;
+mod error;
+mod exe_transform;
+mod fastcdc;
+pub mod fd;
+mod formats;
+pub mod header;
+#[cfg(feature = "http")]
+pub mod http;
+mod json;
+mod logger;
+pub mod manifest;
+mod patch_cache;
+pub mod patch_chunks;
+pub mod patch_set;
+pub mod paths;
+pub mod preflight;
+mod prepared;
+pub mod progress;
+mod reflink;
+pub mod report;
+mod retry;
+pub mod shm;
+pub mod signing;
+pub mod similarity;
+mod throttle;
+pub mod utils;
+pub mod volumes;
+
+#[cfg(feature = "napi")]
+mod array_buffer;
+#[cfg(feature = "napi")]
+pub mod bindings;
+#[cfg(feature = "napi")]
+mod stream;
+
+// 供命令行工具 (src/bin/cli.rs) 与其他 Rust 项目使用的 API
+pub use audit::AuditLog;
+pub use auto_level::AutoLevel;
+pub use bsdiff40::PatchFormat;
+pub use bsdiff_rust::{BsdiffRust, DEFAULT_FALLBACK_THRESHOLD, DiffAlgorithm, DiffMode, LinkMode, OptimizationConfig, OverwritePolicy};
+pub use cancel::CancelToken;
+pub use dictionary::Dictionary;
+pub use encryption::EncryptionKey;
+pub use error::{BsdiffError, ErrorCode};
+pub use header::{Compression, HashAlgorithm};
+pub use logger::{set_logger, LogLevel};
+pub use retry::RetryPolicy;
+pub use signing::PatchSignature;
+pub use throttle::WriteLimit;
+
+/// 供 `cargo fuzz` 使用的内存补丁入口：任意输入只能返回错误，不能 panic 或无界分配
+#[cfg(fuzzing)]
+pub fn fuzz_patch(old: &[u8], patch: &[u8]) -> std::result::Result<Vec<u8>, BsdiffError> {
+  let config = OptimizationConfig { max_output_size: Some(64 * 1024 * 1024), ..Default::default() };
+  BsdiffRust::patch_from_reader(old, std::io::Cursor::new(patch), patch.len() as u64, std::path::Path::new("<fuzz>"), &config)
 }
-
-/// 获取文件大小
-#[napi]
-pub fn get_file_size_sync(file_path: String) -> Result<f64> {
-  get_file_size(&file_path)
-    .map(|size| size as f64)
-    .map_err(|e| Error::from_reason(e.to_string()))
-}
-
-/// 检查文件访问权限
-#[napi]
-pub fn check_file_access_sync(file_path: String) -> Result<()> {
-  check_file_access(&file_path)
-    .map_err(|e| Error::from_reason(e.to_string()))
-}
-
-/// 获取压缩比信息
-#[napi]
-pub fn get_compression_ratio_sync(old_str: String, new_str: String, patch: String) -> Result<CompressionRatioJs> {
-  let ratio = get_compression_ratio(&old_str, &new_str, &patch)
-    .map_err(|e| Error::from_reason(e.to_string()))?;
-  
-  Ok(CompressionRatioJs {
-    old_size: ratio.old_size as f64,
-    new_size: ratio.new_size as f64,
-    patch_size: ratio.patch_size as f64,
-    ratio: ratio.ratio,
-  })
-}
-
-/// JavaScript 补丁信息结构
-#[napi(object)]
-pub struct PatchInfoJs {
-  pub size: f64,
-  pub compressed: bool,
-}
-
-/// JavaScript 压缩比信息结构
-#[napi(object)]
-pub struct CompressionRatioJs {
-  pub old_size: f64,
-  pub new_size: f64,
-  pub patch_size: f64,
-  pub ratio: f64,
-}
-
-// 简化的异步版本，暂时不包含进度回调
-pub struct DiffTask {
-  old_str: String,
-  new_str: String,
-  patch: String,
-}
-
-#[napi]
-impl Task for DiffTask {
-  type Output = ();
-  type JsValue = ();
-
-  fn compute(&mut self) -> Result<Self::Output> {
-    call_bsdiff(&self.old_str, &self.new_str, &self.patch)
-  }
-
-  fn resolve(&mut self, _env: Env, _output: Self::Output) -> Result<Self::JsValue> {
-    Ok(())
-  }
-}
-
-pub struct PatchTask {
-  old_str: String,
-  new_str: String,
-  patch: String,
-}
-
-#[napi]
-impl Task for PatchTask {
-  type Output = ();
-  type JsValue = ();
-
-  fn compute(&mut self) -> Result<Self::Output> {
-    call_bspatch(&self.old_str, &self.new_str, &self.patch)
-  }
-
-  fn resolve(&mut self, _env: Env, _output: Self::Output) -> Result<Self::JsValue> {
-    Ok(())
-  }
-}
-
-pub struct VerifyPatchTask {
-  old_str: String,
-  new_str: String,
-  patch: String,
-}
-
-#[napi]
-impl Task for VerifyPatchTask {
-  type Output = bool;
-  type JsValue = bool;
-
-  fn compute(&mut self) -> Result<Self::Output> {
-    verify_patch_util(&self.old_str, &self.new_str, &self.patch)
-      .map_err(|e| Error::from_reason(e.to_string()))
-  }
-
-  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
-    Ok(output)
-  }
-}
-
-#[napi]
-pub fn diff(
-  old_str: String,
-  new_str: String,
-  patch: String,
-) -> Result<AsyncTask<DiffTask>> {
-  Ok(AsyncTask::new(DiffTask { old_str, new_str, patch }))
-}
-
-#[napi]
-pub fn patch(
-  old_str: String,
-  new_str: String,
-  patch: String,
-) -> Result<AsyncTask<PatchTask>> {
-  Ok(AsyncTask::new(PatchTask { old_str, new_str, patch }))
-}
-
-#[napi]
-pub fn verify_patch(
-  old_str: String,
-  new_str: String,
-  patch: String,
-) -> Result<AsyncTask<VerifyPatchTask>> {
-  Ok(AsyncTask::new(VerifyPatchTask { old_str, new_str, patch }))
-}
\ No newline at end of file
@@ -0,0 +1,50 @@
+use std::sync::{Arc, RwLock};
+
+/// 日志级别
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Info,
+    Warn,
+}
+
+impl LogLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+        }
+    }
+}
+
+/// 日志回调，可能在任意工作线程中被调用
+pub type LogCallback = Arc<dyn Fn(LogLevel, &str) + Send + Sync>;
+
+static LOGGER: RwLock<Option<LogCallback>> = RwLock::new(None);
+
+/// 设置全局日志回调，传入 `None` 取消
+pub fn set_logger(callback: Option<LogCallback>) {
+    if let Ok(mut logger) = LOGGER.write() {
+        *logger = callback;
+    }
+}
+
+/// 输出一条日志，未设置回调时静默忽略
+pub fn log(level: LogLevel, message: &str) {
+    if let Ok(logger) = LOGGER.read() {
+        if let Some(callback) = logger.as_ref() {
+            callback(level, message);
+        }
+    }
+}
+
+/// 输出警告日志
+#[inline]
+pub fn warn(message: &str) {
+    log(LogLevel::Warn, message);
+}
+
+/// 输出信息日志
+#[inline]
+pub fn info(message: &str) {
+    log(LogLevel::Info, message);
+}
@@ -0,0 +1,254 @@
+//! 目录清单：记录安装目录中每个文件的大小与 SHA-256，
+//! 应用目录差分包前用它检查安装是否被改动或损坏。
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use rayon::prelude::*;
+
+use crate::dir_diff::list_files;
+use crate::error::BsdiffError;
+use crate::header::{FileDigest, HashAlgorithm, DIGEST_LEN};
+use crate::json::{self, Value};
+use crate::patch_set::parse_digest;
+
+/// 当前清单格式版本
+pub const MANIFEST_VERSION: u64 = 1;
+
+/// 目录清单：`/` 分隔的相对路径 → 文件摘要
+pub type Manifest = BTreeMap<String, FileDigest>;
+
+/// 与清单不一致的文件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestMismatch {
+    /// 清单中的文件在目录中不存在
+    Missing { path: String, expected: FileDigest },
+    /// 文件大小或内容与清单不同
+    Modified { path: String, expected: FileDigest, actual: FileDigest },
+}
+
+impl ManifestMismatch {
+    pub fn path(&self) -> &str {
+        match self {
+            ManifestMismatch::Missing { path, .. } | ManifestMismatch::Modified { path, .. } => path,
+        }
+    }
+}
+
+/// 从旧清单更新到新清单所需的一步操作
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateOperation {
+    /// 文件内容变化，下载差分补丁
+    Patch { path: String, old_size: u64, new_size: u64, estimated_size: u64 },
+    /// 新增文件，下载完整内容
+    Add { path: String, size: u64, estimated_size: u64 },
+    /// 删除文件
+    Delete { path: String },
+    /// 内容不变、路径改变 (按摘要识别)，本地移动即可，无需下载
+    Rename { from: String, to: String, size: u64 },
+}
+
+impl UpdateOperation {
+    /// 该操作预计需要下载的字节数
+    pub fn estimated_size(&self) -> u64 {
+        match self {
+            UpdateOperation::Patch { estimated_size, .. } | UpdateOperation::Add { estimated_size, .. } => *estimated_size,
+            UpdateOperation::Delete { .. } | UpdateOperation::Rename { .. } => 0,
+        }
+    }
+}
+
+/// 更新计划：操作列表 (先按新路径排列 patch/add/rename，再列出 delete) 与预计下载总量
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpdatePlan {
+    pub operations: Vec<UpdateOperation>,
+    /// 内容未变的文件数
+    pub unchanged: usize,
+    /// 所有操作预计下载字节数之和
+    pub download_size: u64,
+}
+
+/// 估算差分补丁大小：新增的字节按原样计入，其余按新文件的 1/8 估计 (zstd 压缩后的 bsdiff
+/// 补丁对小改动的典型比例)，且不超过新文件本身 (此时不如直接下载完整文件)
+pub(crate) fn estimate_patch_size(old_size: u64, new_size: u64) -> u64 {
+    (new_size.saturating_sub(old_size) + new_size / 8).min(new_size)
+}
+
+/// 比较两个清单，规划更新所需的操作；与 `diff_dir` 相同，新增文件的摘要与某个被删除的文件
+/// 相同时视为重命名 (按路径顺序取第一个未被使用的候选)。只使用清单，不读取任何文件
+pub fn plan_update(old: &Manifest, new: &Manifest) -> UpdatePlan {
+    let mut plan = UpdatePlan::default();
+    let mut removed: BTreeMap<[u8; DIGEST_LEN], Vec<&String>> = BTreeMap::new();
+    for path in old.keys().filter(|path| !new.contains_key(*path)) {
+        removed.entry(old[path].hash).or_default().push(path);
+    }
+
+    let mut renamed_from = HashSet::new();
+    for (path, digest) in new {
+        let operation = match old.get(path) {
+            Some(previous) if previous == digest => {
+                plan.unchanged += 1;
+                continue;
+            }
+            Some(previous) => UpdateOperation::Patch {
+                path: path.clone(),
+                old_size: previous.size,
+                new_size: digest.size,
+                estimated_size: estimate_patch_size(previous.size, digest.size),
+            },
+            None => match removed.get(&digest.hash).and_then(|candidates| {
+                candidates.iter().find(|candidate| !renamed_from.contains(**candidate)).copied()
+            }) {
+                Some(from) => {
+                    renamed_from.insert(from);
+                    UpdateOperation::Rename { from: from.clone(), to: path.clone(), size: digest.size }
+                }
+                None => UpdateOperation::Add { path: path.clone(), size: digest.size, estimated_size: digest.size },
+            },
+        };
+        plan.download_size += operation.estimated_size();
+        plan.operations.push(operation);
+    }
+
+    for path in removed.values().flatten().filter(|path| !renamed_from.contains(**path)) {
+        plan.operations.push(UpdateOperation::Delete { path: (*path).clone() });
+    }
+    plan
+}
+
+/// 递归计算目录下所有普通文件的摘要 (并行)
+pub fn create_manifest(dir: &Path) -> Result<Manifest, BsdiffError> {
+    let files = list_files(dir)?;
+    let manifest: Result<Manifest, String> = files
+        .into_par_iter()
+        .map(|(path, source)| {
+            let digest = FileDigest::of_file(&source)
+                .map_err(|e| format!("Cannot read {}: {}", source.display(), e))?;
+            Ok((path, digest))
+        })
+        .collect();
+    Ok(manifest?)
+}
+
+/// 按清单校验目录，返回缺失或被修改的文件 (按路径排序)；清单之外的文件不影响结果
+pub fn verify_manifest(dir: &Path, manifest: &Manifest) -> Result<Vec<ManifestMismatch>, BsdiffError> {
+    let files = list_files(dir)?;
+    let results: Vec<Result<Option<ManifestMismatch>, String>> = manifest
+        .par_iter()
+        .map(|(path, expected)| {
+            let Some(source) = files.get(path) else {
+                return Ok(Some(ManifestMismatch::Missing { path: path.clone(), expected: *expected }));
+            };
+            let actual = FileDigest::of_file(source)
+                .map_err(|e| format!("Cannot read {}: {}", source.display(), e))?;
+            Ok((actual != *expected).then(|| ManifestMismatch::Modified { path: path.clone(), expected: *expected, actual }))
+        })
+        .collect();
+
+    let mut mismatches = Vec::new();
+    for result in results {
+        mismatches.extend(result?);
+    }
+    Ok(mismatches)
+}
+
+/// 将清单编码为 JSON：`{"version":1,"files":{"<path>":{"size":N,"sha256":"<hex>"}}}`，
+/// 每个文件占一行，便于比较与版本管理
+pub fn manifest_to_json(manifest: &Manifest) -> String {
+    let mut out = format!("{{\n  \"version\": {},\n  \"files\": {{", MANIFEST_VERSION);
+    for (i, (path, digest)) in manifest.iter().enumerate() {
+        out.push_str(if i > 0 { ",\n    " } else { "\n    " });
+        json::push_string(&mut out, path);
+        out.push_str(&format!(": {{\"size\": {}, \"sha256\": \"{}\"}}", digest.size, digest.hex()));
+    }
+    out.push_str(if manifest.is_empty() { "}\n}\n" } else { "\n  }\n}\n" });
+    out
+}
+
+/// 解析 `manifest_to_json` 生成的清单
+pub fn manifest_from_json(text: &str) -> Result<Manifest, BsdiffError> {
+    let invalid = |reason: &str| format!("Invalid manifest: {}", reason);
+    let root = json::parse(text).ok_or_else(|| invalid("not valid JSON"))?;
+    let root = root.as_object().ok_or_else(|| invalid("expected an object"))?;
+    match root.get("version").and_then(Value::as_u64) {
+        Some(version) if version <= MANIFEST_VERSION => {}
+        Some(version) => return Err(format!("Unsupported manifest version: {} (supported up to {})", version, MANIFEST_VERSION).into()),
+        None => return Err(invalid("missing version").into()),
+    }
+
+    let files = root.get("files").and_then(Value::as_object).ok_or_else(|| invalid("missing files"))?;
+    let mut manifest = Manifest::new();
+    for (path, entry) in files {
+        let entry = entry.as_object().ok_or_else(|| invalid(&format!("entry for {} is not an object", path)))?;
+        let size = entry.get("size").and_then(Value::as_u64).ok_or_else(|| invalid(&format!("bad size for {}", path)))?;
+        let sha256 = entry.get("sha256").and_then(Value::as_str).ok_or_else(|| invalid(&format!("missing sha256 for {}", path)))?;
+        manifest.insert(path.clone(), FileDigest { size, algorithm: HashAlgorithm::Sha256, hash: parse_digest(sha256)? });
+    }
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_create_and_verify_manifest() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join("lib")).unwrap();
+        fs::write(dir.path().join("app.bin"), b"application binary").unwrap();
+        fs::write(dir.path().join("lib/core.so"), b"core library").unwrap();
+        fs::write(dir.path().join("lib/\"quoted\".dat"), b"").unwrap();
+        let root = dir.path();
+
+        let manifest = create_manifest(root).unwrap();
+        assert_eq!(manifest.len(), 3);
+        assert_eq!(manifest["lib/core.so"], FileDigest::of_bytes(b"core library"));
+        let text = manifest_to_json(&manifest);
+        assert_eq!(manifest_from_json(&text).unwrap(), manifest);
+        assert_eq!(manifest_from_json(&manifest_to_json(&Manifest::new())).unwrap(), Manifest::new());
+        assert!(verify_manifest(root, &manifest).unwrap().is_empty());
+
+        // 清单之外的新文件不算不一致
+        fs::write(dir.path().join("user.cfg"), b"settings").unwrap();
+        fs::write(dir.path().join("app.bin"), b"application binarY").unwrap();
+        fs::remove_file(dir.path().join("lib/core.so")).unwrap();
+        let mismatches = verify_manifest(root, &manifest).unwrap();
+        assert_eq!(mismatches.iter().map(ManifestMismatch::path).collect::<Vec<_>>(), ["app.bin", "lib/core.so"]);
+        assert!(matches!(mismatches[0], ManifestMismatch::Modified { .. }));
+        assert!(matches!(mismatches[1], ManifestMismatch::Missing { .. }));
+
+        for bad in ["[]", "{\"files\":{}}", "{\"version\":2,\"files\":{}}", "{\"version\":1,\"files\":{\"a\":{\"size\":-1,\"sha256\":\"00\"}}}"] {
+            assert!(manifest_from_json(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_plan_update() {
+        let manifest = |files: &[(&str, &[u8])]| -> Manifest {
+            files.iter().map(|(path, data)| (path.to_string(), FileDigest::of_bytes(data))).collect()
+        };
+        let big = vec![7u8; 8000];
+        let old = manifest(&[("same.txt", b"same"), ("app.bin", &big[..4000]), ("old/name.dat", b"moved"), ("gone.txt", b"gone")]);
+        let new = manifest(&[("same.txt", b"same"), ("app.bin", &big), ("new/name.dat", b"moved"), ("fresh.txt", b"fresh!")]);
+
+        let plan = plan_update(&old, &new);
+        assert_eq!(plan.unchanged, 1);
+        assert_eq!(
+            plan.operations,
+            vec![
+                UpdateOperation::Patch { path: "app.bin".into(), old_size: 4000, new_size: 8000, estimated_size: 5000 },
+                UpdateOperation::Add { path: "fresh.txt".into(), size: 6, estimated_size: 6 },
+                UpdateOperation::Rename { from: "old/name.dat".into(), to: "new/name.dat".into(), size: 5 },
+                UpdateOperation::Delete { path: "gone.txt".into() },
+            ]
+        );
+        assert_eq!(plan.download_size, 5006);
+
+        // 补丁估计不超过完整文件；相同清单无需任何操作
+        assert_eq!(estimate_patch_size(8000, 16), 2);
+        assert_eq!(estimate_patch_size(0, 100), 100);
+        assert_eq!(plan_update(&new, &new), UpdatePlan { unchanged: 4, ..Default::default() });
+    }
+}
@@ -0,0 +1,89 @@
+//! 补丁缓存：以新旧文件摘要与影响补丁内容的选项为键，把生成的补丁保存在磁盘目录中，
+//! 再次请求相同的文件对时直接复制缓存的补丁 (CI 反复构建出相同产物时很常见)。
+//!
+//! 缓存文件位于 `<cache_dir>/<键前两位>/<键>.patch`，写入时先写临时文件再改名，
+//! 并发的 diff 不会读到写了一半的补丁。缓存不会自动清理。
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::bsdiff_rust::OptimizationConfig;
+use crate::header::{sha256, FileDigest, ALGORITHM_VERSION};
+use crate::logger;
+
+/// 缓存键格式版本；键的组成变化时递增，旧条目自然失效
+const CACHE_KEY_VERSION: u32 = 1;
+
+/// 计算缓存键 (64 位十六进制)；加密补丁每次使用随机 nonce，不缓存，返回 `None`
+pub(crate) fn cache_key(old_file: &Path, new_file: &Path, config: &OptimizationConfig) -> io::Result<Option<String>> {
+    if config.encryption_key.is_some() {
+        return Ok(None);
+    }
+    let old = FileDigest::of_file_with(config.hash_algorithm, old_file)?;
+    let new = FileDigest::of_file_with(config.hash_algorithm, new_file)?;
+    let dictionary = config.dictionary.as_ref().map(|dictionary| sha256(dictionary.as_bytes()));
+    // 只包含影响补丁字节的选项；进度回调、临时目录、覆盖策略等不影响结果
+    let options = format!(
+        "{:?}|{}|{:?}|{}|{:?}|{:?}|{:?}|{:?}|{:?}|{}|{}|{:?}|{}|{:?}|{:?}|{:?}",
+        config.mode,
+        config.compression_level,
+        config.auto_level.is_some(),
+        config.safe_mode,
+        config.format,
+        config.compression,
+        config.window_size,
+        config.threads,
+        config.metadata,
+        config.exe_transform,
+        config.archive_transform,
+        dictionary,
+        config.deterministic,
+        config.hash_algorithm,
+        config.algorithm,
+        config.fallback_to_full,
+    );
+
+    let mut hasher = Sha256::new();
+    hasher.update(format!("bsdiff-rust patch cache {} {}\n", CACHE_KEY_VERSION, ALGORITHM_VERSION).as_bytes());
+    for digest in [&old, &new] {
+        hasher.update(digest.size.to_le_bytes());
+        hasher.update(digest.hash);
+    }
+    hasher.update(options.as_bytes());
+    Ok(Some(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()))
+}
+
+fn entry_path(cache_dir: &Path, key: &str) -> PathBuf {
+    cache_dir.join(&key[..2]).join(format!("{}.patch", key))
+}
+
+/// 缓存命中时把补丁复制到 `out`，返回是否命中
+pub(crate) fn fetch(cache_dir: &Path, key: &str, out: &Path) -> io::Result<bool> {
+    let entry = entry_path(cache_dir, key);
+    match fs::copy(&entry, out) {
+        Ok(_) => {
+            logger::info(&format!("Using cached patch {}", entry.display()));
+            Ok(true)
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// 把刚生成的补丁存入缓存；失败只记录警告，不影响 diff 结果
+pub(crate) fn store(cache_dir: &Path, key: &str, patch: &Path) {
+    let entry = entry_path(cache_dir, key);
+    let temp = entry.with_extension(format!("tmp-{}", std::process::id()));
+    let result = entry
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::copy(patch, &temp))
+        .and_then(|_| fs::rename(&temp, &entry));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp);
+        logger::warn(&format!("Could not store patch in cache {}: {}", cache_dir.display(), e));
+    }
+}
@@ -0,0 +1,427 @@
+//! 分片补丁：把大补丁拆成编号的分片文件，便于在不稳定的网络上断点续传。
+//! 每个分片自带 CRC-32，下载方可单独校验并只重新下载损坏的分片。
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::bsdiff_rust::{BsdiffRust, OptimizationConfig};
+use crate::error::BsdiffError;
+use crate::header::{FileDigest, DIGEST_LEN};
+use crate::paths;
+
+/// 分片文件魔数
+pub const PART_MAGIC: [u8; 5] = *b"BSRC\x01";
+
+/// 当前分片格式版本
+pub const PART_FORMAT_VERSION: u8 = 1;
+
+/// 分片头长度: 魔数 | version: u8 | index: u32 | count: u32 | patch_size: u64 | patch_sha256 | offset: u64 | length: u32 | crc32: u32 (均为 LE)
+const PART_HEADER_SIZE: usize = PART_MAGIC.len() + 1 + 4 + 4 + 8 + DIGEST_LEN + 8 + 4 + 4;
+
+/// 分片数上限，防止过小的分片大小生成海量文件
+const MAX_PARTS: u32 = 1 << 20;
+
+/// 分片头信息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchPart {
+    /// 分片序号 (从 0 开始)
+    pub index: u32,
+    /// 分片总数
+    pub count: u32,
+    /// 完整补丁大小
+    pub patch_size: u64,
+    /// 完整补丁的 SHA-256，用于识别分片属于哪个补丁
+    pub patch_sha256: [u8; DIGEST_LEN],
+    /// 分片数据在完整补丁中的偏移
+    pub offset: u64,
+    /// 分片数据长度
+    pub length: u32,
+    /// 分片数据的 CRC-32 (IEEE)
+    pub crc32: u32,
+}
+
+impl PatchPart {
+    /// 十六进制的完整补丁 SHA-256
+    pub fn patch_sha256_hex(&self) -> String {
+        self.patch_sha256.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn to_bytes(&self) -> [u8; PART_HEADER_SIZE] {
+        let mut buf = [0u8; PART_HEADER_SIZE];
+        let mut pos = 0;
+        let mut put = |bytes: &[u8]| {
+            buf[pos..pos + bytes.len()].copy_from_slice(bytes);
+            pos += bytes.len();
+        };
+        put(&PART_MAGIC);
+        put(&[PART_FORMAT_VERSION]);
+        put(&self.index.to_le_bytes());
+        put(&self.count.to_le_bytes());
+        put(&self.patch_size.to_le_bytes());
+        put(&self.patch_sha256);
+        put(&self.offset.to_le_bytes());
+        put(&self.length.to_le_bytes());
+        put(&self.crc32.to_le_bytes());
+        buf
+    }
+
+    fn read_from<R: Read>(reader: &mut R, part_file: &Path) -> Result<Self, BsdiffError> {
+        let mut buf = [0u8; PART_HEADER_SIZE];
+        reader.read_exact(&mut buf).map_err(|_| format!("Not a patch part file: {}", part_file.display()))?;
+        if buf[..PART_MAGIC.len()] != PART_MAGIC {
+            return Err(format!("Not a patch part file: {}", part_file.display()).into());
+        }
+        let version = buf[PART_MAGIC.len()];
+        if version > PART_FORMAT_VERSION {
+            return Err(format!(
+                "Unsupported patch part format version: {} (supported up to {})",
+                version, PART_FORMAT_VERSION
+            ).into());
+        }
+
+        let mut pos = PART_MAGIC.len() + 1;
+        let mut take = |len: usize| {
+            let field = &buf[pos..pos + len];
+            pos += len;
+            field
+        };
+        let index = u32::from_le_bytes(take(4).try_into()?);
+        let count = u32::from_le_bytes(take(4).try_into()?);
+        let patch_size = u64::from_le_bytes(take(8).try_into()?);
+        let patch_sha256 = take(DIGEST_LEN).try_into()?;
+        let offset = u64::from_le_bytes(take(8).try_into()?);
+        let length = u32::from_le_bytes(take(4).try_into()?);
+        let crc32 = u32::from_le_bytes(take(4).try_into()?);
+        if count == 0 || count > MAX_PARTS || index >= count || offset.checked_add(length as u64).is_none_or(|end| end > patch_size) {
+            return Err(format!("Corrupt patch part header: {}", part_file.display()).into());
+        }
+        Ok(Self { index, count, patch_size, patch_sha256, offset, length, crc32 })
+    }
+
+    /// 是否与另一分片属于同一补丁
+    fn same_patch(&self, other: &Self) -> bool {
+        self.count == other.count && self.patch_size == other.patch_size && self.patch_sha256 == other.patch_sha256
+    }
+}
+
+/// 按 `chunk_size` 字节拆分补丁，分片写入 `<patch_file>.000`、`<patch_file>.001`……，返回分片路径
+pub fn split_patch(patch_file: &Path, chunk_size: u64, config: &OptimizationConfig) -> Result<Vec<PathBuf>, BsdiffError> {
+    if chunk_size == 0 || chunk_size > u32::MAX as u64 {
+        return Err(format!("Invalid chunk size: {} (must be 1..={})", chunk_size, u32::MAX).into());
+    }
+    let FileDigest { size: patch_size, hash: patch_sha256, .. } = FileDigest::of_file(patch_file)
+        .map_err(|e| format!("Cannot read patch {}: {}", patch_file.display(), e))?;
+    if patch_size == 0 {
+        return Err(format!("Patch is empty: {}", patch_file.display()).into());
+    }
+    let count = u32::try_from(patch_size.div_ceil(chunk_size))
+        .ok()
+        .filter(|count| *count <= MAX_PARTS)
+        .ok_or_else(|| format!("Chunk size {} would split {} into more than {} parts", chunk_size, patch_file.display(), MAX_PARTS))?;
+
+    let width = count.saturating_sub(1).to_string().len().max(3);
+    let mut reader = BufReader::with_capacity(64 * 1024, File::open(patch_file)?);
+    let mut chunk = Vec::new();
+    let mut paths = Vec::with_capacity(count as usize);
+    for index in 0..count {
+        let offset = index as u64 * chunk_size;
+        chunk.clear();
+        (&mut reader).take(chunk_size.min(patch_size - offset)).read_to_end(&mut chunk)?;
+        if offset + chunk.len() as u64 != (offset + chunk_size).min(patch_size) {
+            return Err(format!("Patch file changed while splitting: {}", patch_file.display()).into());
+        }
+        let part = PatchPart { index, count, patch_size, patch_sha256, offset, length: chunk.len() as u32, crc32: crc32(&chunk) };
+
+        let path = paths::with_suffix(patch_file, &format!(".{:0width$}", index, width = width));
+        let out_path = BsdiffRust::get_optimal_output_path(&path, config, chunk.len() as u64)?;
+        let mut writer = BufWriter::new(File::create(&out_path)?);
+        writer.write_all(&part.to_bytes())?;
+        writer.write_all(&chunk)?;
+        writer.flush()?;
+        drop(writer);
+        BsdiffRust::finalize_output(&out_path, &path, config)?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// 校验单个分片 (头与 CRC-32)，返回分片信息；下载方据此决定是否重新下载该分片
+pub fn check_patch_part(part_file: &Path) -> Result<PatchPart, BsdiffError> {
+    let mut reader = BufReader::with_capacity(64 * 1024, File::open(part_file)?);
+    let part = PatchPart::read_from(&mut reader, part_file)?;
+    check_payload(&mut reader, &part, part_file, |_| Ok(()))?;
+    Ok(part)
+}
+
+/// 将分片 (顺序任意) 合并为完整补丁，写入前逐片校验 CRC，最后校验完整补丁的 SHA-256
+pub fn join_patch(parts: &[PathBuf], out_file: &Path, config: &OptimizationConfig) -> Result<(), BsdiffError> {
+    let ordered = order_parts(parts)?;
+    let out_path = BsdiffRust::get_optimal_output_path(out_file, config, ordered[0].1.patch_size)?;
+    let result = (|| {
+        let mut writer = BufWriter::with_capacity(64 * 1024, File::create(&out_path)?);
+        verify_parts(&ordered, &mut writer)?;
+        writer.flush()?;
+        Ok(())
+    })();
+    match result {
+        Ok(()) => BsdiffRust::finalize_output(&out_path, out_file, config),
+        Err(e) => {
+            let _ = std::fs::remove_file(&out_path);
+            Err(e)
+        }
+    }
+}
+
+/// 直接从分片应用补丁，不合并出完整补丁文件；应用前校验所有分片的 CRC 与完整补丁的 SHA-256
+pub fn patch_from_chunks(old_file: &Path, new_file: &Path, parts: &[PathBuf], config: &OptimizationConfig) -> Result<(), BsdiffError> {
+    let ordered = order_parts(parts)?;
+    verify_parts(&ordered, &mut io::sink())?;
+
+    let old_mmap = BsdiffRust::create_single_memory_map(old_file)?;
+    let patch_size = ordered[0].1.patch_size;
+    let open_patch = || Ok((ChunkReader { parts: &ordered, pos: 0, current: None }, patch_size));
+    BsdiffRust::patch_reader_to_file(&old_mmap, open_patch, &ordered[0].0, new_file, None, config)?;
+    Ok(())
+}
+
+/// 读取各分片头，确认属于同一补丁且序号齐全，按序号排序
+fn order_parts(parts: &[PathBuf]) -> Result<Vec<(PathBuf, PatchPart)>, BsdiffError> {
+    let mut ordered = Vec::with_capacity(parts.len());
+    for part_file in parts {
+        let mut reader = File::open(part_file).map_err(|e| format!("Cannot read patch part {}: {}", part_file.display(), e))?;
+        ordered.push((part_file.clone(), PatchPart::read_from(&mut reader, part_file)?));
+    }
+    let first = ordered.first().map(|(_, part)| part.clone()).ok_or("No patch parts given")?;
+    if let Some((part_file, _)) = ordered.iter().find(|(_, part)| !part.same_patch(&first)) {
+        return Err(format!("Patch part {} belongs to a different patch than {}", part_file.display(), ordered[0].0.display()).into());
+    }
+    ordered.sort_by_key(|(_, part)| part.index);
+    if let Some(pair) = ordered.windows(2).find(|pair| pair[0].1.index == pair[1].1.index) {
+        return Err(format!("Duplicate patch part {}: {} and {}", pair[0].1.index, pair[0].0.display(), pair[1].0.display()).into());
+    }
+    if ordered.len() != first.count as usize {
+        let missing: Vec<String> = (0..first.count)
+            .filter(|index| ordered.binary_search_by_key(index, |(_, part)| part.index).is_err())
+            .take(20)
+            .map(|index| index.to_string())
+            .collect();
+        return Err(format!("Missing patch parts ({} of {} present): {}", ordered.len(), first.count, missing.join(", ")).into());
+    }
+
+    let mut expected = 0u64;
+    for (part_file, part) in &ordered {
+        if part.offset != expected {
+            return Err(format!("Corrupt patch part header: {}", part_file.display()).into());
+        }
+        expected += part.length as u64;
+    }
+    if expected != first.patch_size {
+        return Err(format!("Patch parts cover {} of {} bytes", expected, first.patch_size).into());
+    }
+    Ok(ordered)
+}
+
+/// 按序读取分片数据写入 `sink`，逐片校验 CRC-32，最后校验完整补丁的 SHA-256
+fn verify_parts<W: Write + ?Sized>(ordered: &[(PathBuf, PatchPart)], sink: &mut W) -> Result<(), BsdiffError> {
+    let mut hasher = Sha256::new();
+    for (part_file, part) in ordered {
+        let mut reader = BufReader::with_capacity(64 * 1024, File::open(part_file)?);
+        reader.seek(SeekFrom::Start(PART_HEADER_SIZE as u64))?;
+        check_payload(&mut reader, part, part_file, |chunk| {
+            hasher.update(chunk);
+            sink.write_all(chunk)
+        })?;
+    }
+    if <[u8; DIGEST_LEN]>::from(hasher.finalize()) != ordered[0].1.patch_sha256 {
+        return Err("Joined patch does not match the SHA-256 recorded in its parts".into());
+    }
+    Ok(())
+}
+
+/// 读取分片数据 (须恰好为头中声明的长度)，逐块交给 `emit` 并校验 CRC-32
+fn check_payload<R: Read, F>(reader: &mut R, part: &PatchPart, part_file: &Path, mut emit: F) -> Result<(), BsdiffError>
+where
+    F: FnMut(&[u8]) -> io::Result<()>,
+{
+    let mut crc = Crc32::default();
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut remaining = part.length as u64;
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        if n as u64 > remaining {
+            return Err(format!("Corrupt patch part {}: trailing data", part_file.display()).into());
+        }
+        crc.update(&buffer[..n]);
+        emit(&buffer[..n])?;
+        remaining -= n as u64;
+    }
+    if remaining != 0 {
+        return Err(format!("Corrupt patch part {}: truncated ({} bytes missing)", part_file.display(), remaining).into());
+    }
+    if crc.finish() != part.crc32 {
+        return Err(format!("Corrupt patch part {}: CRC mismatch", part_file.display()).into());
+    }
+    Ok(())
+}
+
+/// 把排好序的分片数据当作一个连续的补丁读取，按需打开分片文件
+struct ChunkReader<'a> {
+    parts: &'a [(PathBuf, PatchPart)],
+    pos: u64,
+    /// 当前打开的分片序号及其文件 (已定位到 `pos`)
+    current: Option<(usize, BufReader<File>)>,
+}
+
+impl Read for ChunkReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let index = self.parts.partition_point(|(_, part)| part.offset + part.length as u64 <= self.pos);
+        let Some((part_file, part)) = self.parts.get(index) else {
+            return Ok(0);
+        };
+        if self.current.as_ref().is_none_or(|(current, _)| *current != index) {
+            let mut file = BufReader::with_capacity(64 * 1024, File::open(part_file)?);
+            file.seek(SeekFrom::Start(PART_HEADER_SIZE as u64 + (self.pos - part.offset)))?;
+            self.current = Some((index, file));
+        }
+        let (_, file) = self.current.as_mut().expect("current part opened");
+        let left = part.offset + part.length as u64 - self.pos;
+        let len = (buf.len() as u64).min(left) as usize;
+        let n = file.read(&mut buf[..len])?;
+        if n == 0 && len > 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("Patch part truncated: {}", part_file.display())));
+        }
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for ChunkReader<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let total = self.parts.last().map_or(0, |(_, part)| part.offset + part.length as u64);
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => total.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative position"))?;
+        if target != self.pos {
+            self.pos = target;
+            self.current = None;
+        }
+        Ok(self.pos)
+    }
+}
+
+/// CRC-32 (IEEE 802.3，与 zlib/gzip 相同)
+fn crc32_table() -> &'static [u32; 256] {
+    static TABLE: std::sync::OnceLock<[u32; 256]> = std::sync::OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0u32; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut crc = i as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            }
+            *entry = crc;
+        }
+        table
+    })
+}
+
+struct Crc32(u32);
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self(!0)
+    }
+}
+
+impl Crc32 {
+    fn update(&mut self, data: &[u8]) {
+        let table = crc32_table();
+        self.0 = data.iter().fold(self.0, |crc, &b| (crc >> 8) ^ table[((crc as u8) ^ b) as usize]);
+    }
+
+    fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::default();
+    crc.update(data);
+    crc.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_split_join_and_patch_from_chunks() {
+        let dir = TempDir::new().unwrap();
+        let old: Vec<u8> = (0..200_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut new = old.clone();
+        new[1000..1100].fill(9);
+        new.extend_from_slice(b"appended tail");
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let patch = BsdiffRust::diff_bytes(&old, &new, &config).unwrap();
+
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old"), &old).unwrap();
+        fs::write(path("p"), &patch).unwrap();
+
+        let parts = split_patch(&path("p"), 100, &config).unwrap();
+        assert_eq!(parts.len(), patch.len().div_ceil(100));
+        assert_eq!(parts[0], paths::with_suffix(&path("p"), ".000"));
+        let info = check_patch_part(&parts[1]).unwrap();
+        assert_eq!((info.index, info.count, info.offset, info.length), (1, parts.len() as u32, 100, 100));
+
+        // 顺序任意
+        let mut shuffled = parts.clone();
+        shuffled.reverse();
+        join_patch(&shuffled, &path("joined"), &config).unwrap();
+        assert_eq!(fs::read(path("joined")).unwrap(), patch);
+
+        patch_from_chunks(&path("old"), &path("new"), &shuffled, &config).unwrap();
+        assert_eq!(fs::read(path("new")).unwrap(), new);
+
+        // 缺少分片
+        let err = join_patch(&parts[1..], &path("joined2"), &config).unwrap_err();
+        assert!(err.to_string().contains("Missing patch parts"), "{}", err);
+        assert!(!dir.path().join("joined2").exists());
+
+        // 损坏的分片：CRC 不匹配，且只影响该分片
+        let mut bytes = fs::read(&parts[2]).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        fs::write(&parts[2], &bytes).unwrap();
+        assert!(check_patch_part(&parts[2]).unwrap_err().to_string().contains("CRC mismatch"));
+        assert!(check_patch_part(&parts[0]).is_ok());
+        assert!(patch_from_chunks(&path("old"), &path("new2"), &parts, &config).is_err());
+        assert!(!dir.path().join("new2").exists());
+
+        // 混入其他补丁的分片
+        fs::write(path("q"), b"another patch").unwrap();
+        let other = split_patch(&path("q"), 100, &config).unwrap();
+        let mut mixed = parts.clone();
+        mixed[2] = other[0].clone();
+        assert!(join_patch(&mixed, &path("joined3"), &config).unwrap_err().to_string().contains("different patch"));
+
+        assert!(split_patch(&path("p"), 0, &config).is_err());
+    }
+}
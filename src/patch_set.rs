@@ -0,0 +1,249 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::bsdiff_rust::{BsdiffRust, OptimizationConfig};
+use crate::error::BsdiffError;
+use crate::header::{FileDigest, HashAlgorithm, PatchHeader, DIGEST_LEN};
+
+/// 补丁集容器魔数
+pub const SET_MAGIC: [u8; 5] = *b"BSRS\x01";
+
+/// 当前补丁集格式版本
+pub const SET_FORMAT_VERSION: u8 = 1;
+
+/// 单条索引长度: from 摘要 | to 摘要 | offset: u64 LE | length: u64 LE
+const INDEX_ENTRY_SIZE: usize = DIGEST_LEN * 2 + 16;
+
+/// 索引条目数上限，防止损坏的补丁集触发超大分配
+const MAX_ENTRIES: u32 = 1 << 20;
+
+/// 补丁集条目：`from` → `to` 的补丁文件
+#[derive(Debug, Clone)]
+pub struct PatchSetEntry {
+    /// 源版本文件 SHA-256 (十六进制)
+    pub from: String,
+    /// 目标版本文件 SHA-256 (十六进制)
+    pub to: String,
+    /// 补丁文件路径
+    pub patch: PathBuf,
+}
+
+/// 补丁集索引项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IndexEntry {
+    from: [u8; DIGEST_LEN],
+    to: [u8; DIGEST_LEN],
+    offset: u64,
+    length: u64,
+}
+
+impl IndexEntry {
+    fn to_bytes(self) -> [u8; INDEX_ENTRY_SIZE] {
+        let mut buf = [0u8; INDEX_ENTRY_SIZE];
+        buf[..DIGEST_LEN].copy_from_slice(&self.from);
+        buf[DIGEST_LEN..DIGEST_LEN * 2].copy_from_slice(&self.to);
+        buf[DIGEST_LEN * 2..DIGEST_LEN * 2 + 8].copy_from_slice(&self.offset.to_le_bytes());
+        buf[DIGEST_LEN * 2 + 8..].copy_from_slice(&self.length.to_le_bytes());
+        buf
+    }
+
+    fn from_bytes(buf: &[u8]) -> Self {
+        let mut from = [0u8; DIGEST_LEN];
+        from.copy_from_slice(&buf[..DIGEST_LEN]);
+        let mut to = [0u8; DIGEST_LEN];
+        to.copy_from_slice(&buf[DIGEST_LEN..DIGEST_LEN * 2]);
+        let mut word = [0u8; 8];
+        word.copy_from_slice(&buf[DIGEST_LEN * 2..DIGEST_LEN * 2 + 8]);
+        let offset = u64::from_le_bytes(word);
+        word.copy_from_slice(&buf[DIGEST_LEN * 2 + 8..]);
+        Self {
+            from,
+            to,
+            offset,
+            length: u64::from_le_bytes(word),
+        }
+    }
+}
+
+/// 将多个补丁打包为一个补丁集文件
+///
+/// 布局: `SET_MAGIC | version: u8 | count: u32 LE | 索引 | 补丁数据`，
+/// 索引中的 offset 相对于文件起始位置。
+pub fn build_patch_set(entries: &[PatchSetEntry], out_file: &Path, config: &OptimizationConfig) -> Result<(), BsdiffError> {
+    let count = u32::try_from(entries.len())
+        .ok()
+        .filter(|count| *count <= MAX_ENTRIES)
+        .ok_or("Too many patch set entries")?;
+
+    // 先确定每个补丁的位置，索引位于数据之前
+    let mut offset = (SET_MAGIC.len() + 1 + 4 + entries.len() * INDEX_ENTRY_SIZE) as u64;
+    let mut index: Vec<IndexEntry> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let from = parse_digest(&entry.from)?;
+        let to = parse_digest(&entry.to)?;
+        if index.iter().any(|existing| existing.from == from && existing.to == to) {
+            return Err(format!("Duplicate patch set entry: {} -> {}", entry.from, entry.to).into());
+        }
+        check_entry_digests(entry, &from, &to)?;
+
+        let length = std::fs::metadata(&entry.patch)
+            .map_err(|e| format!("Cannot read patch {}: {}", entry.patch.display(), e))?
+            .len();
+        index.push(IndexEntry { from, to, offset, length });
+        offset += length;
+    }
+
+    let out_path = BsdiffRust::get_optimal_output_path(out_file, config, offset)?;
+    let mut writer = BufWriter::with_capacity(64 * 1024, File::create(&out_path)?);
+    writer.write_all(&SET_MAGIC)?;
+    writer.write_all(&[SET_FORMAT_VERSION])?;
+    writer.write_all(&count.to_le_bytes())?;
+    for item in &index {
+        writer.write_all(&item.to_bytes())?;
+    }
+    for (entry, item) in entries.iter().zip(&index) {
+        let copied = io::copy(&mut File::open(&entry.patch)?.take(item.length), &mut writer)?;
+        if copied != item.length {
+            return Err(format!("Patch file changed while building set: {}", entry.patch.display()).into());
+        }
+    }
+    writer.flush()?;
+    drop(writer);
+
+    BsdiffRust::finalize_output(&out_path, out_file, config)
+}
+
+/// 从补丁集中按 (from, to) 提取单个补丁
+pub fn extract_patch(set_file: &Path, from: &str, to: &str, out_file: &Path, config: &OptimizationConfig) -> Result<(), BsdiffError> {
+    let from = parse_digest(from)?;
+    let to = parse_digest(to)?;
+
+    let mut reader = BufReader::with_capacity(64 * 1024, File::open(set_file)?);
+    let set_len = reader.get_ref().metadata()?.len();
+    let index = read_index(&mut reader)?;
+    let item = index
+        .iter()
+        .find(|item| item.from == from && item.to == to)
+        .ok_or_else(|| format!("No patch from {} to {} in set {}", to_hex(&from), to_hex(&to), set_file.display()))?;
+    if item.offset.checked_add(item.length).is_none_or(|end| end > set_len) {
+        return Err(format!("Corrupt patch set: entry exceeds file length in {}", set_file.display()).into());
+    }
+
+    reader.seek(SeekFrom::Start(item.offset))?;
+    let out_path = BsdiffRust::get_optimal_output_path(out_file, config, item.length)?;
+    let mut writer = BufWriter::with_capacity(64 * 1024, File::create(&out_path)?);
+    io::copy(&mut reader.take(item.length), &mut writer)?;
+    writer.flush()?;
+    drop(writer);
+
+    BsdiffRust::finalize_output(&out_path, out_file, config)
+}
+
+/// 读取补丁集索引
+fn read_index<R: Read>(reader: &mut R) -> Result<Vec<IndexEntry>, BsdiffError> {
+    let mut fixed = [0u8; SET_MAGIC.len() + 1 + 4];
+    reader.read_exact(&mut fixed).map_err(|_| "Not a patch set file")?;
+    if fixed[..SET_MAGIC.len()] != SET_MAGIC {
+        return Err("Not a patch set file".into());
+    }
+    let version = fixed[SET_MAGIC.len()];
+    if version > SET_FORMAT_VERSION {
+        return Err(format!(
+            "Unsupported patch set format version: {} (supported up to {})",
+            version, SET_FORMAT_VERSION
+        ).into());
+    }
+    let count = u32::from_le_bytes(fixed[SET_MAGIC.len() + 1..].try_into()?);
+    if count > MAX_ENTRIES {
+        return Err(format!("Corrupt patch set: {} entries", count).into());
+    }
+
+    let mut raw = vec![0u8; count as usize * INDEX_ENTRY_SIZE];
+    reader.read_exact(&mut raw)?;
+    Ok(raw.chunks_exact(INDEX_ENTRY_SIZE).map(IndexEntry::from_bytes).collect())
+}
+
+/// 补丁自带摘要时，必须与条目声明的 from/to 一致
+fn check_entry_digests(entry: &PatchSetEntry, from: &[u8; DIGEST_LEN], to: &[u8; DIGEST_LEN]) -> Result<(), BsdiffError> {
+    let header = BsdiffRust::read_patch_header(&mut File::open(&entry.patch)?, &entry.patch)?;
+    if let Some(header) = header {
+        let PatchHeader { source, target, .. } = header;
+        // 条目的 from/to 是 SHA-256，只有同为 SHA-256 的摘要才能比较
+        let differs = |digest: Option<FileDigest>, expected: &[u8; DIGEST_LEN]| {
+            digest.is_some_and(|digest| digest.algorithm == HashAlgorithm::Sha256 && &digest.hash != expected)
+        };
+        if differs(source, from) || differs(target, to) {
+            return Err(format!("Patch {} does not match entry {} -> {}", entry.patch.display(), entry.from, entry.to).into());
+        }
+    }
+    Ok(())
+}
+
+/// 解析十六进制 SHA-256
+pub(crate) fn parse_digest(hex: &str) -> Result<[u8; DIGEST_LEN], BsdiffError> {
+    let hex = hex.trim();
+    if hex.len() != DIGEST_LEN * 2 || !hex.is_ascii() {
+        return Err(format!("Invalid SHA-256 hex digest: {}", hex).into());
+    }
+    let mut digest = [0u8; DIGEST_LEN];
+    for (i, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .map_err(|_| format!("Invalid SHA-256 hex digest: {}", hex))?;
+    }
+    Ok(digest)
+}
+
+fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::FileDigest;
+    use std::fs;
+    use tempfile::{NamedTempFile, TempDir};
+
+    #[test]
+    fn test_build_and_extract_patch_set() {
+        let dir = TempDir::new().unwrap();
+        let versions: Vec<Vec<u8>> = (0..3u8)
+            .map(|v| format!("release {} payload ", v).repeat(40 + v as usize).into_bytes())
+            .collect();
+        let digests: Vec<String> = versions
+            .iter()
+            .map(|data| to_hex(&FileDigest::of_bytes(data).hash))
+            .collect();
+
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let mut entries = Vec::new();
+        for v in 0..2 {
+            let old = dir.path().join(format!("v{}", v));
+            let new = dir.path().join(format!("v{}", v + 1));
+            let patch = dir.path().join(format!("p{}", v));
+            fs::write(&old, &versions[v]).unwrap();
+            fs::write(&new, &versions[v + 1]).unwrap();
+            BsdiffRust::diff_optimized(&old, &new, &patch, &config).unwrap();
+            entries.push(PatchSetEntry {
+                from: digests[v].clone(),
+                to: digests[v + 1].clone(),
+                patch: patch.clone(),
+            });
+        }
+
+        let set = dir.path().join("set.bin");
+        build_patch_set(&entries, &set, &config).unwrap();
+
+        let extracted = NamedTempFile::new().unwrap();
+        extract_patch(&set, &digests[1], &digests[2], extracted.path(), &config).unwrap();
+        assert_eq!(fs::read(extracted.path()).unwrap(), fs::read(&entries[1].patch).unwrap());
+
+        // 缺失的版本组合
+        assert!(extract_patch(&set, &digests[0], &digests[2], extracted.path(), &config).is_err());
+
+        // 条目摘要与补丁头不一致
+        let wrong = vec![PatchSetEntry { to: digests[2].clone(), ..entries[0].clone() }];
+        assert!(build_patch_set(&wrong, &set, &config).is_err());
+    }
+}
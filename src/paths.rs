@@ -0,0 +1,290 @@
+//! 入口路径规范化：Windows 上超过 MAX_PATH 的路径改写为 `\\?\` (verbatim) 形式，UNC 共享路径改写为
+//! `\\?\UNC\server\share`，verbatim 路径中的 `/` 与 `.`/`..` 按 Win32 的规则预先处理 (verbatim 路径不会再被系统解析)。
+//! 其他平台原样使用传入的路径。
+//!
+//! 以字节 (Node.js 的 `Buffer` 路径) 给出的路径在 Unix 上按原始字节转换为 `OsStr`，不要求是 UTF-8；
+//! Windows 上与 Node.js 一致按 UTF-8 解码。
+//!
+//! [`confine`] 把路径限制在沙箱根目录 (`baseDir`) 之内，供应用服务器下发的目录差分包/补丁包时使用。
+
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::BsdiffError;
+
+/// 不加 `\\?\` 前缀时 Win32 可用的路径长度：MAX_PATH (260) 减去创建目录时为 8.3 文件名保留的 12 个字符
+const SHORT_PATH_LIMIT: usize = 248;
+
+const VERBATIM_PREFIX: &str = r"\\?\";
+const VERBATIM_UNC_PREFIX: &str = r"\\?\UNC\";
+
+/// 在路径末尾直接追加后缀 (如 `.partial`)，不经过 UTF-8 转换
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+/// 将 JavaScript/命令行传入的路径转换为本平台可直接打开的形式；非 Windows 平台原样返回
+pub fn native(path: String) -> PathBuf {
+    if cfg!(windows) {
+        let cwd = || std::env::current_dir().ok().and_then(|dir| dir.to_str().map(str::to_string));
+        windows_native(&path, &cwd).into()
+    } else {
+        path.into()
+    }
+}
+
+/// 同 [`native`]，接受任意 `OsString` (如命令行参数)；无法表示为 Unicode 的路径原样返回
+pub fn native_os(path: OsString) -> PathBuf {
+    match path.into_string() {
+        Ok(path) => native(path),
+        Err(path) => path.into(),
+    }
+}
+
+/// 将字节形式的路径转换为 [`PathBuf`]：Unix 上保留原始字节，其他平台要求是 UTF-8
+pub fn from_bytes(path: &[u8]) -> Result<PathBuf, BsdiffError> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Ok(native_os(std::ffi::OsStr::from_bytes(path).to_os_string()))
+    }
+    #[cfg(not(unix))]
+    {
+        let path = std::str::from_utf8(path).map_err(|_| "Path is not valid UTF-8")?;
+        Ok(native(path.to_string()))
+    }
+}
+
+/// [`from_bytes`] 的逆操作：Unix 上返回原始字节，其他平台返回 (有损的) UTF-8
+pub fn into_bytes(path: OsString) -> Vec<u8> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        path.into_vec()
+    }
+    #[cfg(not(unix))]
+    {
+        path.to_string_lossy().into_owned().into_bytes()
+    }
+}
+
+/// 把 `path` 限制在沙箱根目录 `base` 之内，返回规范化后的绝对路径
+///
+/// 相对路径基于 `base` 解析；已存在的部分展开符号链接后必须仍位于 `base` 下，
+/// 尚不存在的末尾部分不能包含 `..`，也不能是悬空的符号链接 (写入时会跟随到沙箱外)。
+pub fn confine(base: &Path, path: &Path) -> Result<PathBuf, BsdiffError> {
+    let base = base.canonicalize().map_err(|e| format!("Invalid base directory {}: {}", base.display(), e))?;
+    let escapes = || format!("Path escapes base directory {}: {}", base.display(), path.display());
+    let joined = base.join(path);
+
+    // 从最深的已存在祖先开始规范化，其后尚不存在的各级名称原样追加
+    let mut missing = Vec::new();
+    let mut current = joined.as_path();
+    let resolved = loop {
+        match current.canonicalize() {
+            Ok(resolved) => break resolved,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                if std::fs::symlink_metadata(current).is_ok() {
+                    return Err(format!("Dangling symlink in sandboxed path: {}", current.display()).into());
+                }
+                missing.push(current.file_name().ok_or_else(escapes)?);
+                current = current.parent().ok_or_else(escapes)?;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    };
+    let confined = missing.into_iter().rev().fold(resolved, |path, name| path.join(name));
+    if !confined.starts_with(&base) {
+        return Err(escapes().into());
+    }
+    Ok(confined)
+}
+
+/// [`native`] 的 Windows 规则；`cwd` 仅在需要把较长的相对路径改写为绝对路径时调用
+///
+/// - `\\.\` 设备路径原样返回
+/// - `\\?\` 路径把 `/` 换成 `\` 并消去 `.`/`..`，前缀保持不变
+/// - 其余路径统一使用 `\`；不超过 [`SHORT_PATH_LIMIT`] 时保持原样 (相对路径仍相对于当前目录)，
+///   超过时转为绝对路径并加上 `\\?\` 或 `\\?\UNC\` 前缀
+fn windows_native(path: &str, cwd: &dyn Fn() -> Option<String>) -> String {
+    if path.starts_with(r"\\.\") || path.starts_with("//./") {
+        return path.to_string();
+    }
+    let path = path.replace('/', "\\");
+    if let Some(rest) = path.strip_prefix(VERBATIM_UNC_PREFIX) {
+        return match split_unc(rest) {
+            Some((share, tail)) => format!("{}{}{}", VERBATIM_UNC_PREFIX, share, resolve(tail)),
+            None => path,
+        };
+    }
+    if let Some(rest) = path.strip_prefix(VERBATIM_PREFIX) {
+        return match split_drive(rest) {
+            Some((drive, tail)) => format!("{}{}{}", VERBATIM_PREFIX, drive, resolve(tail)),
+            None => path,
+        };
+    }
+    if path.len() <= SHORT_PATH_LIMIT {
+        return path;
+    }
+
+    if let Some(rest) = path.strip_prefix(r"\\") {
+        return match split_unc(rest) {
+            Some((share, tail)) => format!("{}{}{}", VERBATIM_UNC_PREFIX, share, resolve(tail)),
+            None => path,
+        };
+    }
+    if let Some((drive, tail)) = split_drive(&path) {
+        // `C:foo` 相对于该盘的当前目录，无法可靠地改写
+        return if tail.starts_with('\\') { format!("{}{}{}", VERBATIM_PREFIX, drive, resolve(tail)) } else { path };
+    }
+    let Some(cwd) = cwd().map(|cwd| cwd.replace('/', "\\")) else {
+        return path;
+    };
+    // `\foo` 位于当前盘根目录下，其余相对路径接在当前目录之后
+    let absolute = match path.starts_with('\\') {
+        true => format!("{}{}", split_drive(&cwd).map_or("", |(drive, _)| drive), path),
+        false => format!("{}\\{}", cwd.trim_end_matches('\\'), path),
+    };
+    if absolute.starts_with(r"\\") || split_drive(&absolute).is_some_and(|(_, tail)| tail.starts_with('\\')) {
+        windows_native(&absolute, &|| None)
+    } else {
+        path
+    }
+}
+
+/// 拆出盘符 (`C:`) 与其后的部分
+fn split_drive(path: &str) -> Option<(&str, &str)> {
+    let bytes = path.as_bytes();
+    (bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':').then(|| path.split_at(2))
+}
+
+/// 拆出 UNC 路径 (已去掉开头的 `\\`) 中的 `server\share` 与其后的部分
+fn split_unc(path: &str) -> Option<(&str, &str)> {
+    let server_end = path.find('\\').filter(|end| *end > 0)?;
+    let share_end = path[server_end + 1..].find('\\').map_or(path.len(), |end| server_end + 1 + end);
+    (share_end > server_end + 1).then(|| path.split_at(share_end))
+}
+
+/// 按 Win32 规则消去根之后的 `.`、`..` 与重复的分隔符 (`..` 不会越过根)，保留结尾的分隔符
+fn resolve(tail: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in tail.split('\\') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    let mut resolved = String::with_capacity(tail.len() + 1);
+    for part in &parts {
+        resolved.push('\\');
+        resolved.push_str(part);
+    }
+    if resolved.is_empty() || (tail.ends_with('\\') && !parts.is_empty()) {
+        resolved.push('\\');
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn long_name() -> String {
+        "segment".repeat(40)
+    }
+
+    #[test]
+    fn test_windows_paths() {
+        let no_cwd = || None;
+        // 较短的路径只统一分隔符
+        assert_eq!(windows_native("dir/sub/a.bin", &no_cwd), r"dir\sub\a.bin");
+        assert_eq!(windows_native(r"C:\x\..\y.bin", &no_cwd), r"C:\x\..\y.bin");
+        assert_eq!(windows_native(r"\\server\share\a.bin", &no_cwd), r"\\server\share\a.bin");
+
+        // 长路径加上 verbatim 前缀，`.`/`..` 预先消去
+        let name = long_name();
+        assert_eq!(
+            windows_native(&format!(r"C:\updates\.\tmp\..\{}\app.bin", name), &no_cwd),
+            format!(r"\\?\C:\updates\{}\app.bin", name)
+        );
+        assert_eq!(windows_native(&format!("D:/{}/..", name), &no_cwd), r"\\?\D:\");
+        // 网络共享
+        assert_eq!(
+            windows_native(&format!(r"\\fileserver\builds\{}\patch.bin", name), &no_cwd),
+            format!(r"\\?\UNC\fileserver\builds\{}\patch.bin", name)
+        );
+        assert_eq!(
+            windows_native(&format!("//fileserver/builds/{}/patch.bin", name), &no_cwd),
+            format!(r"\\?\UNC\fileserver\builds\{}\patch.bin", name)
+        );
+        // 长的相对路径与当前盘根路径基于当前目录
+        let cwd = || Some(r"C:\work\".to_string());
+        assert_eq!(windows_native(&format!(r"{}\a.bin", name), &cwd), format!(r"\\?\C:\work\{}\a.bin", name));
+        assert_eq!(windows_native(&format!(r"\{}\a.bin", name), &cwd), format!(r"\\?\C:\{}\a.bin", name));
+        let unc_cwd = || Some(r"\\nas\home\me".to_string());
+        assert_eq!(windows_native(&format!("../{}", name), &unc_cwd), format!(r"\\?\UNC\nas\home\{}", name));
+        // 无法确定绝对路径时原样返回
+        assert_eq!(windows_native(&format!("C:{}", name), &cwd), format!("C:{}", name));
+        assert_eq!(windows_native(&name, &no_cwd), name);
+
+        // 已有 verbatim 前缀的路径：修正分隔符与 `..`，不论长短
+        assert_eq!(windows_native(r"\\?\C:\a/b\..\c.bin", &no_cwd), r"\\?\C:\a\c.bin");
+        assert_eq!(windows_native(r"\\?\UNC\srv\share\x\.\y", &no_cwd), r"\\?\UNC\srv\share\x\y");
+        assert_eq!(windows_native(r"\\?\UNC\srv\share", &no_cwd), r"\\?\UNC\srv\share\");
+        assert_eq!(windows_native(r"\\?\Volume{1234}\a", &no_cwd), r"\\?\Volume{1234}\a");
+        // 设备路径不改写
+        assert_eq!(windows_native(r"\\.\PhysicalDrive0", &no_cwd), r"\\.\PhysicalDrive0");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_byte_paths() {
+        // 非 UTF-8 的文件名按原始字节往返，追加后缀不丢失字节
+        let bytes = b"/tmp/\xffname\xfe.bin".to_vec();
+        let path = from_bytes(&bytes).unwrap();
+        assert!(path.to_str().is_none());
+        assert_eq!(into_bytes(path.clone().into_os_string()), bytes);
+        assert_eq!(into_bytes(with_suffix(&path, ".partial").into_os_string()), [&bytes[..], b".partial"].concat());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_confine() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let base = dir.path().join("base");
+        std::fs::create_dir_all(base.join("app/data")).unwrap();
+        std::fs::create_dir(dir.path().join("outside")).unwrap();
+        let canonical = base.canonicalize().unwrap();
+
+        assert_eq!(confine(&base, Path::new("app/data/new.bin")).unwrap(), canonical.join("app/data/new.bin"));
+        assert_eq!(confine(&base, &base.join("app/x/y")).unwrap(), canonical.join("app/x/y"));
+        assert_eq!(confine(&base, Path::new("app/../app/data")).unwrap(), canonical.join("app/data"));
+        assert_eq!(confine(&base, Path::new("")).unwrap(), canonical);
+
+        for escaping in ["../outside/x", "/etc/passwd", "app/missing/../../../outside", "missing/.."] {
+            assert!(confine(&base, Path::new(escaping)).is_err(), "{}", escaping);
+        }
+
+        // 指向沙箱外的符号链接 (含悬空链接) 被拒绝，沙箱内的链接允许
+        std::os::unix::fs::symlink(dir.path().join("outside"), base.join("link")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("outside/none"), base.join("dangling")).unwrap();
+        std::os::unix::fs::symlink(base.join("app"), base.join("inner")).unwrap();
+        assert!(confine(&base, Path::new("link/file")).is_err());
+        assert!(confine(&base, Path::new("dangling")).is_err());
+        assert_eq!(confine(&base, Path::new("inner/data")).unwrap(), canonical.join("app/data"));
+        assert!(confine(&dir.path().join("no-such-base"), Path::new("x")).is_err());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_native_is_identity_elsewhere() {
+        let path = format!(r"\\server\share\{}/a.bin", long_name());
+        assert_eq!(native(path.clone()), PathBuf::from(path));
+    }
+}
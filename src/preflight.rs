@@ -0,0 +1,133 @@
+//! diff 前的预检：报告补丁将写入的临时目录、临时目录与目标位置的剩余空间、预计峰值内存以及能否内存映射输入，
+//! 供安装程序与构建脚本在真正开始前发现磁盘或内存不足。`diff` 自身也会在写入前检查目标位置的剩余空间。
+
+use std::path::{Path, PathBuf};
+
+use crate::block_delta;
+use crate::bsdiff_rust::{self, BsdiffRust, DiffAlgorithm, OptimizationConfig};
+use crate::error::BsdiffError;
+use crate::manifest;
+
+/// 压缩负载在内存中缓冲 (补丁头需要控制块总数) 时额外计入的 zstd 工作内存
+const ENCODER_MEMORY: u64 = 16 << 20;
+
+/// diff 预检结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preflight {
+    /// 补丁先写入的快速临时目录；未启用或空间不足时为 `None` (直接写在补丁路径旁边)
+    pub temp_dir: Option<PathBuf>,
+    /// 临时目录的剩余空间 (字节)；不使用临时目录或无法获取时为 `None`
+    pub temp_dir_free: Option<u64>,
+    /// 补丁路径所在的目录
+    pub destination_dir: PathBuf,
+    /// 目标目录的剩余空间 (字节)；无法获取时为 `None`
+    pub destination_free: Option<u64>,
+    /// 预计补丁大小 (字节，按新文件大小与增长量估算)
+    pub estimated_patch_size: u64,
+    /// 预计峰值堆内存 (字节，不含内存映射的输入)
+    pub estimated_peak_memory: u64,
+    /// 新旧文件都能内存映射 (否则 diff 会失败)
+    pub mmap: bool,
+    /// 目标目录的剩余空间足以容纳预计大小的补丁 (无法获取剩余空间时视为足够)
+    pub sufficient_space: bool,
+}
+
+/// 预检 `old_file` → `new_file` 的 diff，不读取文件内容也不创建任何文件
+pub fn preflight(old_file: &Path, new_file: &Path, patch_file: &Path, config: &OptimizationConfig) -> Result<Preflight, BsdiffError> {
+    BsdiffRust::validate_files(old_file, new_file)?;
+    let old_len = std::fs::metadata(old_file)?.len();
+    let new_len = std::fs::metadata(new_file)?.len();
+    let estimated_patch_size = manifest::estimate_patch_size(old_len, new_len);
+    let temp_dir = BsdiffRust::fast_temp_dir(patch_file, config, new_len)?;
+    let destination_dir = destination_dir(patch_file);
+    let destination_free = bsdiff_rust::available_space(&destination_dir);
+    Ok(Preflight {
+        temp_dir_free: temp_dir.as_deref().and_then(bsdiff_rust::available_space),
+        temp_dir,
+        sufficient_space: destination_free.is_none_or(|free| free >= estimated_patch_size),
+        destination_dir,
+        destination_free,
+        estimated_patch_size,
+        estimated_peak_memory: estimate_diff_memory(old_len, estimated_patch_size, config),
+        mmap: BsdiffRust::create_memory_maps(old_file, new_file).is_ok(),
+    })
+}
+
+/// 写入前检查补丁路径所在目录能否容纳 `required` 字节，不足时返回 [`BsdiffError::InsufficientSpace`]，
+/// 而不是在写到一半时因 ENOSPC 失败。无法获取剩余空间时不检查
+pub(crate) fn check_destination_space(patch_file: &Path, required: u64) -> Result<(), BsdiffError> {
+    let dir = destination_dir(patch_file);
+    match bsdiff_rust::available_space(&dir) {
+        Some(available) if available < required => {
+            Err(BsdiffError::InsufficientSpace { path: dir.display().to_string(), available, required })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// 输出文件所在目录；相对路径的文件名本身视为当前目录
+fn destination_dir(output: &Path) -> PathBuf {
+    match output.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// 估算 diff 的峰值堆内存
+///
+/// bsdiff 的后缀数组与排序辅助数组各为 (旧文件 + 1) 个 `isize`；分窗口时只为并行的窗口各自排序约两个窗口大小的旧文件区间。
+/// 块级增量只索引每个旧文件块 (弱校验和与排序表) 与固定大小的过滤器。另加内存中缓冲的压缩负载 (约为补丁大小)
+pub fn estimate_diff_memory(old_len: u64, patch_size: u64, config: &OptimizationConfig) -> u64 {
+    const SUFFIX_BYTES: u64 = 2 * std::mem::size_of::<isize>() as u64;
+    let index = match config.algorithm {
+        DiffAlgorithm::BlockDelta => {
+            let blocks = old_len / block_delta::block_size_for(old_len) as u64;
+            blocks.saturating_mul(12).saturating_add(2 << 20)
+        }
+        DiffAlgorithm::Bsdiff => match config.window_size.filter(|window| *window < old_len) {
+            Some(window) => {
+                let threads = config.threads.unwrap_or_else(rayon::current_num_threads).max(1) as u64;
+                let per_window = window.saturating_mul(2).saturating_add(1).min(old_len + 1);
+                per_window.saturating_mul(SUFFIX_BYTES).saturating_mul(threads)
+            }
+            None => (old_len + 1).saturating_mul(SUFFIX_BYTES),
+        },
+    };
+    index.saturating_add(patch_size).saturating_add(ENCODER_MEMORY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_preflight() {
+        let dir = TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("old"), vec![1u8; 4096]).unwrap();
+        fs::write(path("new"), vec![2u8; 8192]).unwrap();
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+
+        let report = preflight(&path("old"), &path("new"), &path("out.patch"), &config).unwrap();
+        assert_eq!(report.temp_dir, None);
+        assert_eq!(report.destination_dir, dir.path());
+        assert_eq!(report.estimated_patch_size, 4096 + 1024);
+        assert!(report.estimated_peak_memory >= 4097 * 16);
+        assert!(report.mmap && report.sufficient_space);
+
+        let temp = OptimizationConfig { temp_dir: Some(path("")), use_fast_temp_dir: true, ..Default::default() };
+        assert_eq!(preflight(&path("old"), &path("new"), &path("out.patch"), &temp).unwrap().temp_dir, Some(path("")));
+        assert!(preflight(&path("missing"), &path("new"), &path("out.patch"), &config).is_err());
+
+        // 分窗口时按并行窗口估算，远小于整个旧文件的后缀数组
+        let windowed = OptimizationConfig { window_size: Some(1024), threads: Some(1), ..config.clone() };
+        assert!(estimate_diff_memory(1 << 30, 0, &windowed) < estimate_diff_memory(1 << 30, 0, &config) / 100);
+        assert_eq!(destination_dir(Path::new("p")), PathBuf::from("."));
+        if cfg!(unix) {
+            assert!(check_destination_space(&path("out.patch"), u64::MAX).is_err_and(|e| matches!(e, BsdiffError::InsufficientSpace { .. })));
+        }
+        assert!(check_destination_space(&path("out.patch"), 0).is_ok());
+    }
+}
@@ -0,0 +1,460 @@
+#![allow(non_snake_case)]
+//! 可复用后缀数组的 bsdiff
+//!
+//! 移植自 bsdiff 0.2.1 的 `diff.rs`，仅把旧文件后缀数组的构建拆分出来以便复用；
+//! 生成的补丁与 `bsdiff::diff` 逐字节一致 (测试以 bsdiff crate 为对照)。修改算法时需同步更新 `header::ALGORITHM_VERSION`。
+/*-
+ * Copyright 2003-2005 Colin Percival
+ * Copyright 2012 Matthew Endsley
+ * Modified 2017 Pieter-Jan Briers
+ * All rights reserved
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted providing that the following conditions
+ * are met:
+ * 1. Redistributions of source code must retain the above copyright
+ *    notice, this list of conditions and the following disclaimer.
+ * 2. Redistributions in binary form must reproduce the above copyright
+ *    notice, this list of conditions and the following disclaimer in the
+ *    documentation and/or other materials provided with the distribution.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE AUTHOR ``AS IS'' AND ANY EXPRESS OR
+ * IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+ * WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+ * ARE DISCLAIMED.  IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY
+ * DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS
+ * OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION)
+ * HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT,
+ * STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING
+ * IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+ * POSSIBILITY OF SUCH DAMAGE.
+ */
+
+use std::cmp::Ordering;
+use std::io;
+use std::io::Write;
+
+use crate::cancel::{self, CancelToken};
+
+/// 后缀排序中每处理这么多个分组检查一次取消标记
+const CANCEL_CHECK_INTERVAL: usize = 1 << 12;
+
+/// 预先计算好后缀数组的旧文件，可对多个新文件重复 diff
+pub struct PreparedBase<D: AsRef<[u8]>> {
+    old: D,
+    /// qsufsort 生成的后缀数组 (长度为 old.len() + 1)
+    suffix: Vec<isize>,
+}
+
+impl<D: AsRef<[u8]>> PreparedBase<D> {
+    /// 构建旧文件的后缀数组 (耗时与 diff 本身相当，只需执行一次)
+    pub fn new(old: D) -> Self {
+        Self::new_cancellable(old, None).expect("suffix sort without a cancel token cannot fail")
+    }
+
+    /// 同 [`Self::new`]，后缀排序过程中定期检查取消标记 (含超时)，已取消时中止
+    pub(crate) fn new_cancellable(old: D, cancel: Option<&CancelToken>) -> io::Result<Self> {
+        let data = old.as_ref();
+        let mut suffix = vec![0; data.len() + 1];
+        let mut V = vec![0; data.len() + 1];
+        qsufsort(&mut suffix, &mut V, data, cancel)?;
+        Ok(Self { old, suffix })
+    }
+
+    /// 旧文件内容
+    pub fn old(&self) -> &[u8] {
+        self.old.as_ref()
+    }
+
+    /// 对新文件生成原始 bsdiff 流，输出与 `bsdiff::diff(old, new, writer)` 相同
+    pub fn diff<W: Write + ?Sized>(&self, new: &[u8], writer: &mut W) -> io::Result<()> {
+        let mut writer = writer;
+        bsdiff_internal(&self.suffix, self.old.as_ref(), new, &mut writer)
+    }
+}
+
+#[inline]
+fn usz(i: isize) -> usize {
+    debug_assert!(i >= 0);
+    i as usize
+}
+
+struct SplitParams {
+    start: usize,
+    len: usize,
+}
+
+fn split_internal(
+    I: &mut [isize],
+    V: &mut [isize],
+    start: usize,
+    len: usize,
+    h: usize,
+) -> Option<SplitParams> {
+    if len < 16 {
+        let mut k = start;
+        while k < start + len {
+            let mut j = 1;
+            let mut x = V[usz(I[k] + h as isize)];
+            let mut i = 1;
+            while k + i < start + len {
+                if V[usz(I[k + i] + h as isize)] < x {
+                    x = V[usz(I[k + i] + h as isize)];
+                    j = 0;
+                }
+                if V[usz(I[k + i] + h as isize)] == x {
+                    I.swap(k + j, k + i);
+                    j += 1;
+                }
+                i += 1;
+            }
+            for &Ii in &I[k..k + j] {
+                V[usz(Ii)] = k as isize + j as isize - 1;
+            }
+            if j == 1 {
+                I[k] = -1;
+            }
+            k += j;
+        }
+
+        None
+    } else {
+        let x = V[usz(I[start + len / 2] + h as isize)];
+        let mut jj = 0;
+        let mut kk = 0;
+        for &Ii in &I[start..start + len] {
+            if V[usz(Ii + h as isize)] < x {
+                jj += 1;
+            }
+            if V[usz(Ii + h as isize)] == x {
+                kk += 1;
+            }
+        }
+        let jj = jj + start;
+        let kk = kk + jj;
+        let mut j = 0;
+        let mut k = 0;
+        let mut i = start;
+        while i < jj {
+            match V[usz(I[i] + h as isize)].cmp(&x) {
+                Ordering::Less => i += 1,
+                Ordering::Equal => {
+                    I.swap(i, jj + j);
+                    j += 1;
+                }
+                Ordering::Greater => {
+                    I.swap(i, kk + k);
+                    k += 1;
+                }
+            }
+        }
+        while jj + j < kk {
+            if V[usz(I[jj + j] + h as isize)] == x {
+                j += 1;
+            } else {
+                I.swap(jj + j, kk + k);
+                k += 1;
+            }
+        }
+        if jj > start {
+            split(I, V, start, jj - start, h);
+        }
+        for &Ii in &I[jj..kk] {
+            V[usz(Ii)] = kk as isize - 1;
+        }
+        if jj == kk - 1 {
+            I[jj] = -1;
+        }
+
+        if start + len > kk {
+            Some(SplitParams {
+                start: kk,
+                len: start + len - kk,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+fn split(I: &mut [isize], V: &mut [isize], start: usize, len: usize, h: usize) {
+    let mut ret = Some(SplitParams { start, len });
+    while let Some(params) = ret {
+        ret = split_internal(I, V, params.start, params.len, h);
+    }
+}
+
+/// 生成原始 bsdiff 流 (同 `bsdiff::diff`)，后缀排序与写出期间均可被取消标记中断
+pub(crate) fn diff<W: Write + ?Sized>(old: &[u8], new: &[u8], cancel: Option<&CancelToken>, writer: &mut W) -> io::Result<()> {
+    cancel::check(cancel)?;
+    PreparedBase::new_cancellable(old, cancel)?.diff(new, writer)
+}
+
+fn qsufsort(I: &mut [isize], V: &mut [isize], old: &[u8], cancel: Option<&CancelToken>) -> io::Result<()> {
+    let mut buckets: [isize; 256] = [0; 256];
+    for &o in old {
+        buckets[o as usize] += 1;
+    }
+    for i in 1..256 {
+        buckets[i] += buckets[i - 1];
+    }
+    for i in (1..256).rev() {
+        buckets[i] = buckets[i - 1];
+    }
+    buckets[0] = 0;
+    for (i, old) in old.iter().copied().enumerate() {
+        buckets[old as usize] += 1;
+        I[usz(buckets[old as usize])] = i as isize;
+    }
+    I[0] = old.len() as isize;
+    for (i, old) in old.iter().copied().enumerate() {
+        V[i] = buckets[old as usize];
+    }
+    V[old.len()] = 0;
+    for i in 1..256 {
+        if buckets[i] == buckets[i - 1] + 1 {
+            I[usz(buckets[i])] = -1;
+        }
+    }
+    I[0] = -1;
+    let mut h = 1;
+    let mut groups = 0usize;
+    while I[0] != -(old.len() as isize + 1) {
+        cancel::check(cancel)?;
+        let mut len = 0;
+        let mut i = 0;
+        while i < old.len() as isize + 1 {
+            if I[usz(i)] < 0 {
+                len -= I[usz(i)];
+                i = i - I[usz(i)];
+            } else {
+                if len != 0 {
+                    I[usz(i - len)] = -len;
+                }
+                len = V[usz(I[usz(i)])] + 1 - i;
+                groups += 1;
+                if groups.is_multiple_of(CANCEL_CHECK_INTERVAL) {
+                    cancel::check(cancel)?;
+                }
+                split(I, V, usz(i), usz(len), h);
+                i += len;
+                len = 0;
+            }
+        }
+        if len != 0 {
+            I[usz(i - len)] = -len;
+        }
+        h += h;
+    }
+    for (i, v) in V[0..=old.len()].iter().copied().enumerate() {
+        I[usz(v)] = i as isize;
+    }
+    Ok(())
+}
+
+fn matchlen(old: &[u8], new: &[u8]) -> usize {
+    old.iter().zip(new).take_while(|(a, b)| a == b).count()
+}
+
+fn search(I: &[isize], old: &[u8], new: &[u8]) -> (isize, usize) {
+    if I.len() < 3 {
+        let x = matchlen(&old[usz(I[0])..], new);
+        let y = matchlen(&old[usz(I[I.len() - 1])..], new);
+        if x > y {
+            (I[0], x)
+        } else {
+            (I[I.len() - 1], y)
+        }
+    } else {
+        let mid = (I.len() - 1) / 2;
+        let left = &old[usz(I[mid])..];
+        let right = new;
+        let len_to_check = left.len().min(right.len());
+        if left[..len_to_check] < right[..len_to_check] {
+            search(&I[mid..], old, new)
+        } else {
+            search(&I[..=mid], old, new)
+        }
+    }
+}
+
+#[inline]
+fn offtout(x: isize, buf: &mut [u8]) {
+    // so it works on 32-bit platforms
+    let x64 = x as i64;
+    if x64 >= 0 {
+        buf.copy_from_slice(&x64.to_le_bytes());
+    } else {
+        let tmp = (-x64) as u64 | (1u64 << 63);
+        buf.copy_from_slice(&tmp.to_le_bytes());
+    }
+}
+
+fn bsdiff_internal(I: &[isize], old: &[u8], new: &[u8], writer: &mut dyn Write) -> io::Result<()> {
+    let mut buffer = Vec::new();
+
+    let mut scan = 0;
+    let mut len = 0usize;
+    let mut pos = 0usize;
+    let mut lastscan = 0;
+    let mut lastpos = 0;
+    let mut lastoffset = 0isize;
+    while scan < new.len() {
+        let mut oldscore = 0;
+        scan += len;
+        let mut scsc = scan;
+        while scan < new.len() {
+            let (p, l) = search(&I[..=old.len()], old, &new[scan..]);
+            pos = usz(p);
+            len = l;
+            while scsc < scan + len {
+                if scsc as isize + lastoffset < old.len() as _
+                    && (old[usz(scsc as isize + lastoffset)] == new[scsc])
+                {
+                    oldscore += 1;
+                }
+                scsc += 1;
+            }
+            if len == oldscore && (len != 0) || len > oldscore + 8 {
+                break;
+            }
+            if scan as isize + lastoffset < old.len() as _
+                && (old[usz(scan as isize + lastoffset)] == new[scan])
+            {
+                oldscore -= 1;
+            }
+            scan += 1;
+        }
+        if !(len != oldscore || scan == new.len()) {
+            continue;
+        }
+        let mut s = 0;
+        let mut Sf = 0;
+        let mut lenf = 0usize;
+        let mut i = 0usize;
+        while lastscan + i < scan && (lastpos + i < old.len() as _) {
+            if old[lastpos + i] == new[lastscan + i] {
+                s += 1;
+            }
+            i += 1;
+            if s * 2 - i as isize <= Sf * 2 - lenf as isize {
+                continue;
+            }
+            Sf = s;
+            lenf = i;
+        }
+        let mut lenb = 0;
+        if scan < new.len() {
+            let mut s = 0isize;
+            let mut Sb = 0;
+            let mut i = 1;
+            while scan >= lastscan + i && (pos >= i) {
+                if old[pos - i] == new[scan - i] {
+                    s += 1;
+                }
+                if s * 2 - i as isize > Sb * 2 - lenb as isize {
+                    Sb = s;
+                    lenb = i;
+                }
+                i += 1;
+            }
+        }
+        if lastscan + lenf > scan - lenb {
+            let overlap = lastscan + lenf - (scan - lenb);
+            let mut s = 0;
+            let mut Ss = 0;
+            let mut lens = 0;
+            for i in 0..overlap {
+                if new[lastscan + lenf - overlap + i] == old[lastpos + lenf - overlap + i] {
+                    s += 1;
+                }
+                if new[scan - lenb + i] == old[pos - lenb + i] {
+                    s -= 1;
+                }
+                if s > Ss {
+                    Ss = s;
+                    lens = i + 1;
+                }
+            }
+            lenf = lenf + lens - overlap;
+            lenb -= lens;
+        }
+        let mut buf: [u8; 24] = [0; 24];
+        offtout(lenf as _, &mut buf[..8]);
+        offtout(
+            scan as isize - lenb as isize - (lastscan + lenf) as isize,
+            &mut buf[8..16],
+        );
+        offtout(
+            pos as isize - lenb as isize - (lastpos + lenf) as isize,
+            &mut buf[16..24],
+        );
+        writer.write_all(&buf[..24])?;
+
+        buffer.clear();
+        buffer.extend(
+            new[lastscan..lastscan + lenf]
+                .iter()
+                .zip(&old[lastpos..lastpos + lenf])
+                .map(|(n, o)| n.wrapping_sub(*o)),
+        );
+        writer.write_all(&buffer)?;
+
+        let write_len = scan - lenb - (lastscan + lenf);
+        let write_start = lastscan + lenf;
+        writer.write_all(&new[write_start..write_start + write_len])?;
+
+        lastscan = scan - lenb;
+        lastpos = pos - lenb;
+        lastoffset = pos as isize - scan as isize;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_bsdiff_crate() {
+        let old: Vec<u8> = (0..20_000u32).map(|i| (i * 13 % 251) as u8).collect();
+        let prepared = PreparedBase::new(old.clone());
+        for variant in 0..3u8 {
+            let mut new = old.clone();
+            new[100 * variant as usize..100 * variant as usize + 50].fill(variant);
+            new.extend_from_slice(&[variant; 300]);
+
+            let mut expected = Vec::new();
+            bsdiff::diff(&old, &new, &mut expected).unwrap();
+            let mut actual = Vec::new();
+            prepared.diff(&new, &mut actual).unwrap();
+            assert_eq!(actual, expected);
+        }
+
+        let empty = PreparedBase::new(Vec::new());
+        let mut expected = Vec::new();
+        bsdiff::diff(&[], b"abc", &mut expected).unwrap();
+        let mut actual = Vec::new();
+        empty.diff(b"abc", &mut actual).unwrap();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_cancellable_diff() {
+        let old: Vec<u8> = (0..50_000u32).map(|i| (i * 7 % 253) as u8).collect();
+        let mut new = old.clone();
+        new[1000..1100].fill(9);
+        let mut expected = Vec::new();
+        bsdiff::diff(&old, &new, &mut expected).unwrap();
+        let mut actual = Vec::new();
+        diff(&old, &new, Some(&CancelToken::new()), &mut actual).unwrap();
+        assert_eq!(actual, expected);
+
+        // 期限已过：后缀排序开始前即以超时中止
+        let expired = CancelToken::new().with_timeout(std::time::Duration::ZERO);
+        let err = PreparedBase::new_cancellable(&old[..], Some(&expired)).err().unwrap();
+        assert!(matches!(cancel::interruption_of(&err), Some(crate::error::BsdiffError::TimedOut { timeout_ms: 0 })));
+    }
+}
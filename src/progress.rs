@@ -0,0 +1,231 @@
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::cancel::{self, CancelToken};
+use crate::control::{ControlEntry, CONTROL_SIZE};
+
+/// 进度回调 `(已处理字节数, 总字节数)`
+///
+/// 回调在执行 diff/patch 的线程上同步调用 (napi 异步任务中即 libuv 工作线程)，
+/// 同一次操作内不会并发调用，但不同操作可能在不同线程上同时调用同一个回调，
+/// 因此要求 `Send + Sync`。回调应尽快返回，耗时操作会直接拖慢 diff/patch。
+pub type ProgressCallback = Arc<dyn Fn(u64, u64) + Send + Sync>;
+
+/// 进度阶段：界面可据此区分 "准备中" (read/decode) 与 "安装中" (apply/write)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressPhase {
+    /// 读取并校验输入 (diff 映射新旧文件并计算摘要，patch 校验旧文件摘要)
+    Read,
+    /// 解析补丁 (签名校验、解密、补丁头)；仅 patch
+    Decode,
+    /// 主体计算：diff 按新文件字节数、patch 按补丁字节数推进
+    Apply,
+    /// 写出结果 (移动到最终位置、落盘)
+    Write,
+}
+
+impl ProgressPhase {
+    pub fn name(self) -> &'static str {
+        match self {
+            ProgressPhase::Read => "read",
+            ProgressPhase::Decode => "decode",
+            ProgressPhase::Apply => "apply",
+            ProgressPhase::Write => "write",
+        }
+    }
+}
+
+/// 分阶段的进度事件；`eta_ms` 为按当前阶段速率估算的剩余时间，尚无法估算时为 `None`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressEvent {
+    pub phase: ProgressPhase,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    pub eta_ms: Option<u64>,
+}
+
+/// 分阶段进度回调，线程语义同 [`ProgressCallback`]
+pub type PhaseCallback = Arc<dyn Fn(&ProgressEvent) + Send + Sync>;
+
+/// 上报一个阶段的开始 (`done` 为 0) 或结束 (`done == total`) ，用于不逐字节推进的阶段
+pub(crate) fn report_phase(callback: Option<&PhaseCallback>, phase: ProgressPhase, done: u64, total: u64) {
+    if let Some(callback) = callback {
+        let eta_ms = (done >= total).then_some(0);
+        callback(&ProgressEvent { phase, bytes_done: done, bytes_total: total, eta_ms });
+    }
+}
+
+/// 上报节流：每前进总量的 1% 才调用一次回调，完成时必定调用一次
+const PROGRESS_STEPS: u64 = 100;
+
+/// 节流后的进度上报器；可同时上报原始进度与分阶段事件
+pub(crate) struct ProgressReporter {
+    callback: Option<ProgressCallback>,
+    phase: Option<(PhaseCallback, ProgressPhase)>,
+    total: u64,
+    step: u64,
+    done: u64,
+    last_reported: Option<u64>,
+    /// 首次上报的时间与字节数，估算剩余时间时只计入之后的速率
+    started: Option<(Instant, u64)>,
+}
+
+impl ProgressReporter {
+    pub(crate) fn new(callback: ProgressCallback, total: u64) -> Self {
+        Self::with_callbacks(Some(callback), None, total)
+    }
+
+    /// 两个回调都未设置时返回 `None`
+    pub(crate) fn of(callback: Option<&ProgressCallback>, phase: Option<(&PhaseCallback, ProgressPhase)>, total: u64) -> Option<Self> {
+        (callback.is_some() || phase.is_some()).then(|| {
+            Self::with_callbacks(callback.cloned(), phase.map(|(callback, phase)| (callback.clone(), phase)), total)
+        })
+    }
+
+    fn with_callbacks(callback: Option<ProgressCallback>, phase: Option<(PhaseCallback, ProgressPhase)>, total: u64) -> Self {
+        Self {
+            callback,
+            phase,
+            total,
+            step: (total / PROGRESS_STEPS).max(1),
+            done: 0,
+            last_reported: None,
+            started: None,
+        }
+    }
+
+    /// 前进 `delta` 字节
+    #[inline]
+    pub(crate) fn advance(&mut self, delta: u64) {
+        self.set(self.done.saturating_add(delta));
+    }
+
+    /// 设置已处理字节数
+    pub(crate) fn set(&mut self, done: u64) {
+        self.done = done.min(self.total);
+        self.started.get_or_insert_with(|| (Instant::now(), self.done));
+        let due = match self.last_reported {
+            None => true,
+            Some(last) => self.done >= last + self.step || (self.done == self.total && last != self.total),
+        };
+        if due {
+            self.last_reported = Some(self.done);
+            if let Some(callback) = &self.callback {
+                callback(self.done, self.total);
+            }
+            if let Some((callback, phase)) = &self.phase {
+                let eta_ms = self.eta_ms();
+                callback(&ProgressEvent { phase: *phase, bytes_done: self.done, bytes_total: self.total, eta_ms });
+            }
+        }
+    }
+
+    /// 按首次上报以来的平均速率估算剩余毫秒数
+    fn eta_ms(&self) -> Option<u64> {
+        if self.done >= self.total {
+            return Some(0);
+        }
+        let (started, start_done) = self.started?;
+        let progressed = self.done - start_done;
+        if progressed == 0 {
+            return None;
+        }
+        let elapsed = started.elapsed().as_millis();
+        u64::try_from(elapsed * u128::from(self.total - self.done) / u128::from(progressed)).ok()
+    }
+}
+
+/// 解析写出的 bsdiff 流：统计控制块数量，可按已覆盖的新文件字节数上报 diff 进度，
+/// 并在每次写入前检查取消标记
+pub(crate) struct DiffProgressWriter<W: Write> {
+    inner: W,
+    reporter: Option<ProgressReporter>,
+    cancel: Option<CancelToken>,
+    control: [u8; CONTROL_SIZE],
+    control_filled: usize,
+    payload_remaining: u64,
+    control_blocks: u64,
+}
+
+impl<W: Write> DiffProgressWriter<W> {
+    pub(crate) fn new(inner: W, reporter: Option<ProgressReporter>, cancel: Option<CancelToken>) -> Self {
+        Self {
+            inner,
+            reporter,
+            cancel,
+            control: [0u8; CONTROL_SIZE],
+            control_filled: 0,
+            payload_remaining: 0,
+            control_blocks: 0,
+        }
+    }
+
+    /// 目前已写出的完整控制块数量
+    pub(crate) fn control_blocks(&self) -> u64 {
+        self.control_blocks
+    }
+
+    /// 跟踪写出的数据，返回其中新文件负载的字节数
+    fn track(&mut self, mut buf: &[u8]) -> u64 {
+        let mut payload = 0u64;
+        while !buf.is_empty() {
+            if self.payload_remaining > 0 {
+                let n = self.payload_remaining.min(buf.len() as u64);
+                self.payload_remaining -= n;
+                payload += n;
+                buf = &buf[n as usize..];
+            } else {
+                let n = (CONTROL_SIZE - self.control_filled).min(buf.len());
+                self.control[self.control_filled..self.control_filled + n].copy_from_slice(&buf[..n]);
+                self.control_filled += n;
+                buf = &buf[n..];
+                if self.control_filled == CONTROL_SIZE {
+                    let entry = ControlEntry::from_bytes(&self.control);
+                    self.payload_remaining = entry.mix_len.saturating_add(entry.copy_len);
+                    self.control_filled = 0;
+                    self.control_blocks += 1;
+                }
+            }
+        }
+        payload
+    }
+}
+
+impl<W: Write> Write for DiffProgressWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        cancel::check(self.cancel.as_ref())?;
+        let written = self.inner.write(buf)?;
+        let payload = self.track(&buf[..written]);
+        if let Some(reporter) = self.reporter.as_mut().filter(|_| payload > 0) {
+            reporter.advance(payload);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// 统计读取字节数并上报进度的读取器
+pub(crate) struct ProgressReader<R: Read> {
+    inner: R,
+    reporter: ProgressReporter,
+}
+
+impl<R: Read> ProgressReader<R> {
+    /// `already_read` 为包装前已经读取的字节数 (如补丁头)
+    pub(crate) fn new(inner: R, mut reporter: ProgressReporter, already_read: u64) -> Self {
+        reporter.set(already_read);
+        Self { inner, reporter }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.reporter.advance(n as u64);
+        Ok(n)
+    }
+}
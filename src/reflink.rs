@@ -0,0 +1,59 @@
+//! 内容不变的文件的快速生成：写时复制克隆 (reflink) 或硬链接，文件系统不支持时退回普通复制
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::bsdiff_rust::LinkMode;
+
+/// 按 `mode` 在 `target` (不能已存在) 生成与 `source` 内容相同的文件：
+/// 硬链接失败时尝试克隆，克隆失败时复制
+pub(crate) fn link_or_copy(source: &Path, target: &Path, mode: LinkMode) -> io::Result<()> {
+    if mode == LinkMode::HardLink && fs::hard_link(source, target).is_ok() {
+        return Ok(());
+    }
+    if mode != LinkMode::Copy && reflink(source, target).is_ok() {
+        return Ok(());
+    }
+    fs::copy(source, target).map(|_| ())
+}
+
+/// 以写时复制克隆 `source` 到新文件 `target`，连同权限位；失败时不留下 `target`
+#[cfg(target_os = "linux")]
+pub(crate) fn reflink(source: &Path, target: &Path) -> io::Result<()> {
+    use std::fs::{File, OpenOptions};
+    use std::os::unix::io::AsRawFd;
+
+    let src = File::open(source)?;
+    let dst = OpenOptions::new().write(true).create_new(true).open(target)?;
+    let result = if unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONE, src.as_raw_fd()) } == 0 {
+        src.metadata().and_then(|metadata| dst.set_permissions(metadata.permissions()))
+    } else {
+        Err(io::Error::last_os_error())
+    };
+    if result.is_err() {
+        drop(dst);
+        let _ = fs::remove_file(target);
+    }
+    result
+}
+
+/// 以写时复制克隆 `source` 到新文件 `target` (APFS clonefile，连同权限位与时间戳)
+#[cfg(target_os = "macos")]
+pub(crate) fn reflink(source: &Path, target: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = |path: &Path| CString::new(path.as_os_str().as_bytes()).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput));
+    let (src, dst) = (c_path(source)?, c_path(target)?);
+    if unsafe { libc::clonefile(src.as_ptr(), dst.as_ptr(), 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// 以写时复制克隆文件 (当前平台不支持)
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub(crate) fn reflink(_source: &Path, _target: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Reflinks are not supported on this platform"))
+}
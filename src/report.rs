@@ -0,0 +1,303 @@
+//! diff 报告：在内存中运行 diff 但不写出补丁，统计新文件相对旧文件的变更区域、修改/插入/删除的字节数
+//! 与变更内容的熵，供分析看板跟踪每个版本二进制文件的变动程度
+
+use std::collections::BinaryHeap;
+use std::cmp::Reverse;
+use std::path::Path;
+
+use crate::bsdiff40;
+use crate::bsdiff_rust::{BsdiffRust, OptimizationConfig};
+use crate::error::BsdiffError;
+use crate::utils::check_file_access;
+
+/// 相距不超过该字节数的变更合并为同一区域，避免零散的单字节改动 (如重定位的指针) 拆出海量区域
+const REGION_GAP: u64 = 16;
+
+/// 报告中列出的区域数上限；超过时保留最大的区域
+pub const MAX_REPORTED_REGIONS: usize = 256;
+
+/// 新文件中的一段变更区域
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangedRegion {
+    /// 区域在新文件中的偏移
+    pub offset: u64,
+    /// 区域长度 (含区域内相距不超过 16 字节的未变字节)
+    pub length: u64,
+    /// 区域内相对旧文件改动的字节数
+    pub modified_bytes: u64,
+    /// 区域内旧文件中没有对应内容、直接插入的字节数
+    pub inserted_bytes: u64,
+    /// 区域内变更字节的香农熵 (比特/字节，0-8)；接近 8 表示压缩或加密数据，补丁难以压缩
+    pub entropy: f64,
+}
+
+/// diff 报告
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffReport {
+    pub old_size: u64,
+    pub new_size: u64,
+    /// bsdiff 控制块数
+    pub control_blocks: u64,
+    /// 与旧文件对应位置相同的新文件字节数
+    pub unchanged_bytes: u64,
+    /// 由旧文件对应位置改动而来的新文件字节数
+    pub modified_bytes: u64,
+    /// 旧文件中没有对应内容、直接插入的新文件字节数
+    pub inserted_bytes: u64,
+    /// 未被新文件引用的旧文件字节数
+    pub deleted_bytes: u64,
+    /// 变更区域总数
+    pub region_count: u64,
+    /// 变更区域 (按偏移排序)，最多 [`MAX_REPORTED_REGIONS`] 个最大的区域
+    pub regions: Vec<ChangedRegion>,
+    /// 所有变更字节的香农熵 (比特/字节，0-8)
+    pub changed_entropy: f64,
+}
+
+impl DiffReport {
+    /// 变动率 (0-1)：修改与插入的字节占新文件的比例
+    pub fn churn(&self) -> f64 {
+        if self.new_size == 0 {
+            0.0
+        } else {
+            (self.modified_bytes + self.inserted_bytes) as f64 / self.new_size as f64
+        }
+    }
+
+    /// 序列化为 JSON (只含数字字段，不含文件内容)
+    pub fn to_json(&self) -> String {
+        let mut out = format!(
+            "{{\n  \"oldSize\": {},\n  \"newSize\": {},\n  \"controlBlocks\": {},\n  \"unchangedBytes\": {},\n  \"modifiedBytes\": {},\n  \
+             \"insertedBytes\": {},\n  \"deletedBytes\": {},\n  \"churn\": {:.6},\n  \"changedEntropy\": {:.4},\n  \"regionCount\": {},\n  \"regions\": [",
+            self.old_size, self.new_size, self.control_blocks, self.unchanged_bytes, self.modified_bytes,
+            self.inserted_bytes, self.deleted_bytes, self.churn(), self.changed_entropy, self.region_count
+        );
+        for (i, region) in self.regions.iter().enumerate() {
+            out.push_str(if i > 0 { ",\n    " } else { "\n    " });
+            out.push_str(&format!(
+                "{{\"offset\": {}, \"length\": {}, \"modifiedBytes\": {}, \"insertedBytes\": {}, \"entropy\": {:.4}}}",
+                region.offset, region.length, region.modified_bytes, region.inserted_bytes, region.entropy
+            ));
+        }
+        out.push_str(if self.regions.is_empty() { "]\n}\n" } else { "\n  ]\n}\n" });
+        out
+    }
+}
+
+/// 对两个文件生成 diff 报告 (内存映射读取)；`config` 中的 `algorithm`、`window_size`、`threads` 与 `cancel` 生效
+pub fn diff_report(old_file: &Path, new_file: &Path, config: &OptimizationConfig) -> Result<DiffReport, BsdiffError> {
+    check_file_access(old_file)?;
+    check_file_access(new_file)?;
+    let old = BsdiffRust::create_single_memory_map(old_file)?;
+    let new = BsdiffRust::create_single_memory_map(new_file)?;
+    diff_report_bytes(&old, &new, config)
+}
+
+/// 对内存中的数据生成 diff 报告
+pub fn diff_report_bytes(old: &[u8], new: &[u8], config: &OptimizationConfig) -> Result<DiffReport, BsdiffError> {
+    let mut raw = Vec::new();
+    BsdiffRust::diff_raw(old, new, config, &mut raw)?;
+
+    let mut report = DiffReport {
+        old_size: old.len() as u64,
+        new_size: new.len() as u64,
+        control_blocks: 0,
+        unchanged_bytes: 0,
+        modified_bytes: 0,
+        inserted_bytes: 0,
+        deleted_bytes: 0,
+        region_count: 0,
+        regions: Vec::new(),
+        changed_entropy: 0.0,
+    };
+    let mut regions = RegionTracker::default();
+    let mut histogram = [0u64; 256];
+    let mut used_old = Vec::new();
+    let (mut old_pos, mut new_pos) = (0i64, 0u64);
+    bsdiff40::for_each_entry(&raw, |entry, diff, extra| {
+        report.control_blocks += 1;
+        if !diff.is_empty() {
+            used_old.push((old_pos.max(0) as u64, (old_pos + diff.len() as i64).max(0) as u64));
+        }
+        for (i, byte) in diff.iter().enumerate() {
+            if *byte == 0 {
+                report.unchanged_bytes += 1;
+                continue;
+            }
+            let pos = new_pos + i as u64;
+            let value = new[pos as usize];
+            histogram[value as usize] += 1;
+            regions.add(pos, value, false);
+            report.modified_bytes += 1;
+        }
+        new_pos += diff.len() as u64;
+        for (i, value) in extra.iter().enumerate() {
+            histogram[*value as usize] += 1;
+            regions.add(new_pos + i as u64, *value, true);
+        }
+        report.inserted_bytes += extra.len() as u64;
+        new_pos += extra.len() as u64;
+        old_pos += entry.mix_len as i64 + entry.seek;
+        Ok(())
+    })?;
+    regions.close();
+
+    // 旧文件中被差分引用的区间可能重叠，合并后求未引用的字节数
+    used_old.sort_unstable();
+    let mut covered = 0u64;
+    let mut end = 0u64;
+    for (start, stop) in used_old {
+        let (start, stop) = (start.max(end).min(report.old_size), stop.min(report.old_size));
+        if stop > start {
+            covered += stop - start;
+            end = stop;
+        }
+    }
+    report.deleted_bytes = report.old_size - covered;
+    report.region_count = regions.count;
+    report.regions = regions.largest.into_iter().map(|Reverse(ByLength(region))| region).collect();
+    report.regions.sort_by_key(|region| region.offset);
+    report.changed_entropy = entropy(&histogram);
+    Ok(report)
+}
+
+/// 香农熵 (比特/字节)
+fn entropy(histogram: &[u64; 256]) -> f64 {
+    let total: u64 = histogram.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+    histogram
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// 按长度比较区域，用于保留最大的若干个
+struct ByLength(ChangedRegion);
+
+impl PartialEq for ByLength {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for ByLength {}
+
+impl PartialOrd for ByLength {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ByLength {
+    /// 长度相同时偏移靠前的区域更大，结果与遍历顺序无关
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.length.cmp(&other.0.length).then(other.0.offset.cmp(&self.0.offset))
+    }
+}
+
+/// 按新文件偏移递增地收集变更字节，合并为区域并保留最大的 [`MAX_REPORTED_REGIONS`] 个
+#[derive(Default)]
+struct RegionTracker {
+    /// 当前区域及其变更字节的直方图
+    open: Option<(ChangedRegion, Box<[u64; 256]>)>,
+    count: u64,
+    largest: BinaryHeap<Reverse<ByLength>>,
+}
+
+impl RegionTracker {
+    fn add(&mut self, pos: u64, value: u8, inserted: bool) {
+        if self.open.as_ref().is_some_and(|(region, _)| pos > region.offset + region.length + REGION_GAP) {
+            self.close();
+        }
+        let (region, histogram) = self.open.get_or_insert_with(|| {
+            let region = ChangedRegion { offset: pos, length: 0, modified_bytes: 0, inserted_bytes: 0, entropy: 0.0 };
+            (region, Box::new([0; 256]))
+        });
+        region.length = pos + 1 - region.offset;
+        if inserted {
+            region.inserted_bytes += 1;
+        } else {
+            region.modified_bytes += 1;
+        }
+        histogram[value as usize] += 1;
+    }
+
+    fn close(&mut self) {
+        let Some((mut region, histogram)) = self.open.take() else {
+            return;
+        };
+        self.count += 1;
+        region.entropy = entropy(&histogram);
+        self.largest.push(Reverse(ByLength(region)));
+        if self.largest.len() > MAX_REPORTED_REGIONS {
+            self.largest.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noise(len: usize, mut seed: u32) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_report() {
+        let config = OptimizationConfig::default();
+        let old = noise(100_000, 1);
+        let same = diff_report_bytes(&old, &old, &config).unwrap();
+        assert_eq!((same.unchanged_bytes, same.modified_bytes, same.inserted_bytes, same.deleted_bytes), (100_000, 0, 0, 0));
+        assert_eq!((same.region_count, same.churn()), (0, 0.0));
+        assert!(same.regions.is_empty());
+
+        // 两处改动、一段不可压缩的插入、删除开头 1000 字节
+        let mut new = old[1000..].to_vec();
+        new[5000] ^= 0xFF;
+        new[5010] ^= 0xFF;
+        new.splice(60_000..60_000, noise(4096, 7));
+        let report = diff_report_bytes(&old, &new, &config).unwrap();
+        assert_eq!(report.new_size, new.len() as u64);
+        assert_eq!(report.unchanged_bytes + report.modified_bytes + report.inserted_bytes, report.new_size);
+        assert!(report.deleted_bytes >= 1000 && report.deleted_bytes < 1100, "{:?}", report.deleted_bytes);
+        assert!(report.inserted_bytes >= 4096 && report.inserted_bytes < 4200, "{}", report.inserted_bytes);
+        assert_eq!(report.region_count, 2);
+        assert_eq!((report.regions[0].offset, report.regions[0].length, report.regions[0].modified_bytes), (5000, 11, 2));
+        assert!(report.regions[1].offset <= 60_000 && report.regions[1].inserted_bytes >= 4096);
+        assert!(report.regions[1].entropy > 7.5, "{}", report.regions[1].entropy);
+        assert!(report.churn() > 0.035 && report.churn() < 0.045, "{}", report.churn());
+
+        let json = crate::json::parse(&report.to_json()).unwrap();
+        let json = json.as_object().unwrap();
+        assert_eq!(json["insertedBytes"].as_u64(), Some(report.inserted_bytes));
+        assert!(matches!(&json["regions"], crate::json::Value::Array(regions) if regions.len() == 2));
+    }
+
+    #[test]
+    fn test_report_keeps_largest_regions() {
+        let old = vec![0u8; 200_000];
+        let mut new = old.clone();
+        for i in 0..400 {
+            let start = i * 400;
+            let len = 1 + i % 7;
+            new[start..start + len].fill(0xAB);
+        }
+        let report = diff_report_bytes(&old, &new, &OptimizationConfig::default()).unwrap();
+        assert_eq!(report.region_count, 400);
+        assert_eq!(report.regions.len(), MAX_REPORTED_REGIONS);
+        assert!(report.regions.windows(2).all(|pair| pair[0].offset < pair[1].offset));
+        assert!(report.regions.iter().all(|region| region.length >= 3));
+    }
+}
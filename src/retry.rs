@@ -0,0 +1,107 @@
+//! 暂时性文件系统错误的重试：Windows 上杀毒软件或索引服务短暂锁住刚写出的文件时，
+//! rename/创建会以拒绝访问或共享冲突失败，稍等片刻再试通常即可成功
+
+use std::io;
+use std::time::Duration;
+
+use crate::error::BsdiffError;
+use crate::logger;
+
+/// 单次重试等待时间的上限
+const MAX_DELAY: Duration = Duration::from_secs(5);
+
+/// 重试策略：最多尝试 `attempts` 次，第 n 次重试前等待 `delay × 2^(n-1)` (不超过 5 秒)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// 总尝试次数 (含首次)，1 表示不重试
+    pub attempts: u32,
+    /// 首次重试前的等待时间
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// Windows 上默认重试 (共 5 次，首次等待 100ms)；其他平台不重试
+    fn default() -> Self {
+        if cfg!(windows) {
+            Self { attempts: 5, delay: Duration::from_millis(100) }
+        } else {
+            Self::NONE
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 不重试
+    pub const NONE: Self = Self { attempts: 1, delay: Duration::ZERO };
+
+    pub fn new(attempts: u32, delay: Duration) -> Result<Self, BsdiffError> {
+        if attempts == 0 {
+            return Err("Retry attempts must be at least 1".into());
+        }
+        Ok(Self { attempts, delay })
+    }
+
+    /// 执行 `op`，遇到暂时性错误时按策略退避重试；`what` 用于日志
+    pub fn run<T, F>(&self, what: &str, mut op: F) -> io::Result<T>
+    where
+        F: FnMut() -> io::Result<T>,
+    {
+        let mut delay = self.delay;
+        for attempt in 1.. {
+            match op() {
+                Err(e) if attempt < self.attempts && is_transient(&e) => {
+                    logger::warn(&format!("{} failed ({}), retrying in {} ms ({}/{})", what, e, delay.as_millis(), attempt, self.attempts - 1));
+                    std::thread::sleep(delay);
+                    delay = (delay * 2).min(MAX_DELAY);
+                }
+                result => return result,
+            }
+        }
+        unreachable!("retry loop returns")
+    }
+}
+
+/// 是否为可能很快消失的错误：文件被占用 (共享/锁冲突、EBUSY)，Windows 上还包括拒绝访问
+fn is_transient(e: &io::Error) -> bool {
+    // ERROR_SHARING_VIOLATION / ERROR_LOCK_VIOLATION
+    const WINDOWS_LOCK_ERRORS: [i32; 2] = [32, 33];
+    match e.kind() {
+        io::ErrorKind::ResourceBusy => true,
+        io::ErrorKind::PermissionDenied => cfg!(windows),
+        _ => cfg!(windows) && e.raw_os_error().is_some_and(|code| WINDOWS_LOCK_ERRORS.contains(&code)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_policy() {
+        assert!(RetryPolicy::new(0, Duration::ZERO).is_err());
+        let policy = RetryPolicy::new(3, Duration::from_millis(1)).unwrap();
+
+        let mut calls = 0;
+        let result = policy.run("rename", || {
+            calls += 1;
+            if calls < 3 { Err(io::Error::from(io::ErrorKind::ResourceBusy)) } else { Ok(calls) }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        // 次数用尽时返回最后一次的错误
+        let mut calls = 0;
+        let result: io::Result<()> = policy.run("rename", || {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::ResourceBusy))
+        });
+        assert_eq!((result.unwrap_err().kind(), calls), (io::ErrorKind::ResourceBusy, 3));
+
+        // 非暂时性错误不重试
+        let mut calls = 0;
+        let result: io::Result<()> = RetryPolicy::new(5, Duration::from_secs(10)).unwrap().run("rename", || {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::NotFound))
+        });
+        assert_eq!((result.unwrap_err().kind(), calls), (io::ErrorKind::NotFound, 1));
+    }
+}
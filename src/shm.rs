@@ -0,0 +1,69 @@
+use std::path::Path;
+
+use crate::bsdiff_rust::{BsdiffRust, OptimizationConfig};
+use crate::error::BsdiffError;
+
+/// 生成补丁并写入命名 POSIX 共享内存对象，返回写入的字节数
+pub fn diff_to_shm(
+    old_file: &Path,
+    new_file: &Path,
+    shm_name: &str,
+    config: &OptimizationConfig,
+) -> Result<u64, BsdiffError> {
+    let patch = BsdiffRust::diff_to_writer(old_file, new_file, Vec::new(), config)?;
+    write_shm(shm_name, &patch)
+}
+
+/// 将数据写入命名共享内存对象 (同名对象会被替换)
+#[cfg(unix)]
+pub fn write_shm(name: &str, data: &[u8]) -> Result<u64, BsdiffError> {
+    use std::ffi::CString;
+    use std::fs::File;
+    use std::os::unix::io::FromRawFd;
+    use memmap2::MmapOptions;
+
+    // POSIX 要求共享内存名以 '/' 开头
+    let name = if name.starts_with('/') { name.to_string() } else { format!("/{}", name) };
+    let c_name = CString::new(name).map_err(|_| "Invalid shared memory name")?;
+
+    // 先解除旧对象，保证得到一个全新的对象 (macOS 不允许对已有对象再次 ftruncate)
+    unsafe { libc::shm_unlink(c_name.as_ptr()) };
+    let fd = unsafe {
+        libc::shm_open(c_name.as_ptr(), libc::O_CREAT | libc::O_EXCL | libc::O_RDWR, 0o600 as libc::c_uint)
+    };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let file = unsafe { File::from_raw_fd(fd) };
+    file.set_len(data.len() as u64)?;
+
+    if !data.is_empty() {
+        let mut map = unsafe { MmapOptions::new().map_mut(&file)? };
+        map.copy_from_slice(data);
+        map.flush()?;
+    }
+    Ok(data.len() as u64)
+}
+
+/// 将数据写入命名共享内存对象 (当前平台不支持)
+#[cfg(not(unix))]
+pub fn write_shm(_name: &str, _data: &[u8]) -> Result<u64, BsdiffError> {
+    Err("Shared memory output is only supported on Unix platforms".into())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_write_shm() {
+        let name = format!("bsdiff_rust_test_{}", std::process::id());
+        let written = write_shm(&name, b"shared patch bytes").unwrap();
+        assert_eq!(written, 18);
+
+        let path = format!("/dev/shm/{}", name);
+        assert_eq!(fs::read(&path).unwrap(), b"shared patch bytes");
+        fs::remove_file(&path).unwrap();
+    }
+}
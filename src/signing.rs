@@ -0,0 +1,118 @@
+//! 补丁的分离式 Ed25519 签名：签名覆盖整个补丁文件的字节，与补丁分开分发，
+//! 应用前校验以防止被篡改的补丁被安装。签名为标准 Ed25519，可用其他实现 (如 Node.js `crypto.verify`) 校验
+
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use crate::error::BsdiffError;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey, PUBLIC_KEY_LENGTH, SECRET_KEY_LENGTH, SIGNATURE_LENGTH};
+
+/// 应用补丁前需要校验的公钥与签名
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchSignature {
+    public_key: VerifyingKey,
+    signature: Signature,
+}
+
+impl PatchSignature {
+    /// `public_key` 为 32 字节原始 Ed25519 公钥，`signature` 为 64 字节签名
+    pub fn new(public_key: &[u8], signature: &[u8]) -> Result<Self, BsdiffError> {
+        let public_key: &[u8; PUBLIC_KEY_LENGTH] = public_key
+            .try_into()
+            .map_err(|_| format!("Ed25519 public key must be {} bytes, got {}", PUBLIC_KEY_LENGTH, public_key.len()))?;
+        let public_key = VerifyingKey::from_bytes(public_key).map_err(|_| "Ed25519 public key is not a valid curve point")?;
+        let signature = Signature::from_slice(signature)
+            .map_err(|_| format!("Ed25519 signature must be {} bytes, got {}", SIGNATURE_LENGTH, signature.len()))?;
+        Ok(Self { public_key, signature })
+    }
+
+    /// 严格校验整个补丁的签名 (拒绝非规范编码与小阶点)，签名有效时返回 true
+    pub(crate) fn verify(&self, patch: &[u8]) -> bool {
+        self.public_key.verify_strict(patch, &self.signature).is_ok()
+    }
+}
+
+/// 解析私钥：32 字节种子，或 libsodium/TweetNaCl 格式的 64 字节 (种子 + 公钥)
+fn parse_private_key(private_key: &[u8]) -> Result<SigningKey, BsdiffError> {
+    match private_key.len() {
+        SECRET_KEY_LENGTH => Ok(SigningKey::from_bytes(private_key.try_into().expect("seed length checked"))),
+        64 => {
+            let key = SigningKey::from_bytes(private_key[..SECRET_KEY_LENGTH].try_into().expect("seed length checked"));
+            if key.verifying_key().as_bytes()[..] != private_key[SECRET_KEY_LENGTH..] {
+                return Err("Ed25519 private key is inconsistent: the public half does not match the seed".into());
+            }
+            Ok(key)
+        }
+        len => Err(format!("Ed25519 private key must be 32 bytes (seed) or 64 bytes (seed + public key), got {}", len).into()),
+    }
+}
+
+/// 由私钥计算对应的公钥，供 `PatchSignature` 使用
+pub fn public_key_from_private(private_key: &[u8]) -> Result<[u8; PUBLIC_KEY_LENGTH], BsdiffError> {
+    Ok(parse_private_key(private_key)?.verifying_key().to_bytes())
+}
+
+/// 对内存中的补丁签名，返回 64 字节分离式签名
+pub fn sign_patch_bytes(patch: &[u8], private_key: &[u8]) -> Result<[u8; SIGNATURE_LENGTH], BsdiffError> {
+    Ok(parse_private_key(private_key)?.sign(patch).to_bytes())
+}
+
+/// 对补丁文件签名，返回 64 字节分离式签名
+pub fn sign_patch(patch_file: &Path, private_key: &[u8]) -> Result<[u8; SIGNATURE_LENGTH], BsdiffError> {
+    let key = parse_private_key(private_key)?;
+    let mut patch = Vec::new();
+    BufReader::new(File::open(patch_file)?).read_to_end(&mut patch)?;
+    Ok(key.sign(&patch).to_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_sign_and_verify_patch() {
+        let seed = [42u8; SECRET_KEY_LENGTH];
+        let public = public_key_from_private(&seed).unwrap();
+        let patch = b"BSRZ patch bytes".repeat(10_000);
+        let signature = PatchSignature::new(&public, &sign_patch_bytes(&patch, &seed).unwrap()).unwrap();
+        assert!(signature.verify(&patch));
+        assert!(!signature.verify(&patch[1..]));
+
+        // 64 字节私钥 (种子 + 公钥) 生成同样的签名
+        let mut full_key = seed.to_vec();
+        full_key.extend_from_slice(&public);
+        assert_eq!(sign_patch_bytes(&patch, &full_key).unwrap(), sign_patch_bytes(&patch, &seed).unwrap());
+        full_key[40] ^= 1;
+        assert!(sign_patch_bytes(&patch, &full_key).is_err());
+
+        assert!(sign_patch_bytes(&patch, &[0u8; 16]).is_err());
+        assert!(PatchSignature::new(&public[1..], &[0u8; SIGNATURE_LENGTH]).is_err());
+        assert!(PatchSignature::new(&public, &[0u8; 63]).is_err());
+    }
+
+    #[test]
+    fn test_rfc8032_vector() {
+        // RFC 8032 7.1 TEST 2 (1 字节消息)
+        let seed = from_hex("4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb");
+        let public = from_hex("3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c");
+        let signature = from_hex(
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        );
+        assert_eq!(public_key_from_private(&seed).unwrap()[..], public[..]);
+        assert_eq!(sign_patch_bytes(&[0x72], &seed).unwrap()[..], signature[..]);
+        assert!(PatchSignature::new(&public, &signature).unwrap().verify(&[0x72]));
+
+        // 小阶公钥 (单位点) 即使签名方程成立也被严格验签拒绝
+        let mut identity = [0u8; PUBLIC_KEY_LENGTH];
+        identity[0] = 1;
+        let mut forged = [0u8; SIGNATURE_LENGTH];
+        forged[0] = 1;
+        assert!(!PatchSignature::new(&identity, &forged).unwrap().verify(b"anything"));
+    }
+}
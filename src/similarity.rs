@@ -0,0 +1,157 @@
+//! 快速相似度估算：不运行完整 diff，按内容定义的采样点比较新旧文件的滚动哈希，
+//! 估算新文件中可由旧文件复制的比例与补丁大小范围，供更新服务器决定发增量还是全量。
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::bsdiff_rust::BsdiffRust;
+use crate::error::BsdiffError;
+use crate::utils::check_file_access;
+
+/// 每个文件最多采样的哈希数 (约)，决定估算耗时与精度
+const TARGET_SAMPLES: u64 = 1 << 16;
+/// 估算压缩率时从新文件中截取的片段数与片段大小
+const COMPRESS_SAMPLES: usize = 8;
+const COMPRESS_SAMPLE_LEN: usize = 16 * 1024;
+/// 补丁头与控制流的固定开销 (估算值)
+const PATCH_OVERHEAD: u64 = 128;
+
+/// 相似度估算结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimilarityEstimate {
+    /// 相似度 (0-1)：新文件的采样窗口中能在旧文件里找到的比例
+    pub score: f64,
+    /// 预计补丁大小下限 (字节)
+    pub min_patch_size: u64,
+    /// 预计补丁大小上限 (字节)
+    pub max_patch_size: u64,
+}
+
+/// Gear 滚动哈希表，由 splitmix64 在编译期生成
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state = 0x9E37_79B9_7F4A_7C15u64;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// 估算新旧数据的相似度与补丁大小范围，耗时与文件大小线性相关，不分配与文件等大的内存
+pub fn estimate_similarity(old: &[u8], new: &[u8]) -> SimilarityEstimate {
+    let score = if new.is_empty() {
+        1.0
+    } else if old.is_empty() {
+        0.0
+    } else {
+        // 两个文件使用相同的采样掩码，同样的内容在任意偏移处都会被采到
+        let bits = (old.len().max(new.len()) as u64 / TARGET_SAMPLES).checked_ilog2().map_or(0, |bits| bits + 1);
+        let (old_samples, new_samples) = rayon::join(|| sample(old, bits), || sample(new, bits));
+        if new_samples.is_empty() {
+            (old == new) as u8 as f64
+        } else {
+            let old_set: HashSet<u64> = old_samples.into_iter().collect();
+            let matched = new_samples.iter().filter(|hash| old_set.contains(hash)).count();
+            matched as f64 / new_samples.len() as f64
+        }
+    };
+
+    // 未匹配部分按新文件的压缩率计入补丁，匹配部分的差分字节几乎全为 0
+    let ratio = compress_ratio(new);
+    let literal = (1.0 - score) * new.len() as f64 * ratio;
+    let full = new.len() as f64 * ratio;
+    let min_patch_size = (literal * 0.5) as u64 + PATCH_OVERHEAD;
+    let max_patch_size = ((literal * 1.5 + score * new.len() as f64 * 0.01).min(full) as u64 + PATCH_OVERHEAD).max(min_patch_size);
+    SimilarityEstimate { score, min_patch_size, max_patch_size }
+}
+
+/// 估算两个文件的相似度 (内存映射读取)
+pub fn estimate_similarity_files(old_file: &Path, new_file: &Path) -> Result<SimilarityEstimate, BsdiffError> {
+    check_file_access(old_file)?;
+    check_file_access(new_file)?;
+    let old = BsdiffRust::create_single_memory_map(old_file)?;
+    let new = BsdiffRust::create_single_memory_map(new_file)?;
+    Ok(estimate_similarity(&old, &new))
+}
+
+/// 内容定义采样：gear 哈希高 `bits` 位全为 0 的位置取样 (`bits` 为 0 时每个位置都取样)
+fn sample(data: &[u8], bits: u32) -> Vec<u64> {
+    // gear 哈希每步左移一位，64 字节之前的内容已完全移出，窗口填满前不取样
+    const WINDOW: usize = 64;
+    let mut hash = 0u64;
+    let mut samples = Vec::new();
+    for (i, byte) in data.iter().enumerate() {
+        hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+        if i + 1 >= WINDOW && (bits == 0 || hash >> (64 - bits) == 0) {
+            samples.push(hash);
+        }
+    }
+    samples
+}
+
+/// 从新文件均匀截取片段估算 zstd 压缩率 (0-1)
+fn compress_ratio(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 1.0;
+    }
+    let stride = (data.len() / COMPRESS_SAMPLES).max(COMPRESS_SAMPLE_LEN);
+    let sample: Vec<u8> = data
+        .chunks(stride)
+        .flat_map(|chunk| &chunk[..chunk.len().min(COMPRESS_SAMPLE_LEN)])
+        .copied()
+        .collect();
+    match zstd::bulk::compress(&sample, 3) {
+        Ok(compressed) => (compressed.len() as f64 / sample.len() as f64).clamp(0.01, 1.0),
+        Err(_) => 1.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bsdiff_rust::OptimizationConfig;
+
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                (state >> 56) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_estimate_similarity() {
+        let old = noise(200_000, 1);
+        assert_eq!(estimate_similarity(&old, &old).score, 1.0);
+        assert_eq!(estimate_similarity(&old, &noise(200_000, 7)).score, 0.0);
+        assert_eq!(estimate_similarity(&old, b"").score, 1.0);
+        assert_eq!(estimate_similarity(b"", &old).score, 0.0);
+
+        // 插入与替换后内容大部分仍可在旧文件中找到，采样不受偏移影响
+        let mut new = old.clone();
+        new.splice(1000..1000, noise(5000, 3));
+        new[100_000..120_000].copy_from_slice(&noise(20_000, 5));
+        let estimate = estimate_similarity(&old, &new);
+        assert!(estimate.score > 0.8 && estimate.score < 0.95, "{:?}", estimate);
+
+        // 预测范围覆盖实际补丁大小
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let actual = BsdiffRust::diff_bytes(&old, &new, &config).unwrap().len() as u64;
+        assert!(estimate.min_patch_size <= actual && actual <= estimate.max_patch_size, "{:?} vs {}", estimate, actual);
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        std::fs::write(path("old"), &old).unwrap();
+        std::fs::write(path("new"), &new).unwrap();
+        assert_eq!(estimate_similarity_files(&path("old"), &path("new")).unwrap(), estimate);
+        assert!(estimate_similarity_files(&path("old"), &path("missing")).is_err());
+    }
+}
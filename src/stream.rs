@@ -0,0 +1,263 @@
+//! Node.js 流适配：在主线程上把 Readable/Writable 包装为线程安全函数，
+//! 供 libuv 工作线程以阻塞方式读取输入、写出输出
+
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::pin::pin;
+use std::sync::{mpsc, Arc};
+use std::task::{Context, Poll, Wake};
+use std::thread::{self, Thread};
+
+use napi::bindgen_prelude::*;
+use napi::sys;
+use napi::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi_derive::napi;
+
+use crate::bsdiff_rust::BsdiffRust;
+use crate::error::BsdiffError;
+use crate::paths;
+
+/// 写入 Writable 的块大小
+pub(crate) const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// 流式输入：文件路径、文件描述符、Buffer，或 Readable 流 (任意异步可迭代对象)
+pub type StreamSourceJs<'env> = Either4<String, u32, Buffer, Object<'env>>;
+
+/// 流式输出：文件描述符或 Writable 流
+pub type StreamSinkJs<'env> = Either<u32, Object<'env>>;
+
+/// 异步迭代器 `next()` 的结果
+#[napi(object)]
+pub struct IteratorResultJs {
+    pub done: Option<bool>,
+    pub value: Option<Either<Buffer, String>>,
+}
+
+/// 异步迭代器的 `next()`，已绑定到迭代器
+type NextChunkJs = ThreadsafeFunction<(), Promise<IteratorResultJs>, (), Status, false>;
+
+/// Writable 的 `write(chunk, callback)`，已绑定到流
+type WriteChunkJs = ThreadsafeFunction<(Vec<u8>, mpsc::Sender<io::Result<()>>), (), FnArgs<(Buffer, RawValue)>, Status, false>;
+
+/// Writable 的 `end(callback)`，已绑定到流
+type EndStreamJs = ThreadsafeFunction<mpsc::Sender<io::Result<()>>, (), RawValue, Status, false>;
+
+/// 在 JavaScript 线程回调中临时创建的值，原样作为参数传回
+pub(crate) struct RawValue(sys::napi_value);
+
+impl ToNapiValue for RawValue {
+    unsafe fn to_napi_value(_env: sys::napi_env, value: Self) -> Result<sys::napi_value> {
+        Ok(value.0)
+    }
+}
+
+/// 流式输入源，在主线程上创建，在工作线程上读取
+pub(crate) enum StreamSource {
+    Path(PathBuf),
+    Fd(ManuallyDrop<File>),
+    Buffer(Buffer),
+    Readable(NextChunkJs),
+}
+
+/// 读取完成的输入数据
+pub(crate) enum SourceData<'a> {
+    Mapped(memmap2::Mmap),
+    Owned(Vec<u8>),
+    Borrowed(&'a [u8]),
+}
+
+impl Deref for SourceData<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Mapped(map) => map,
+            Self::Owned(data) => data,
+            Self::Borrowed(data) => data,
+        }
+    }
+}
+
+impl StreamSource {
+    pub(crate) fn from_js(env: &Env, value: StreamSourceJs) -> Result<Self> {
+        Ok(match value {
+            Either4::A(path) => Self::Path(paths::native(path)),
+            Either4::B(fd) => Self::Fd(borrow_fd(fd)?),
+            Either4::C(buffer) => Self::Buffer(buffer),
+            Either4::D(object) => {
+                let symbol = env
+                    .get_global()?
+                    .get_named_property::<Function>("Symbol")?
+                    .get_named_property::<Unknown>("asyncIterator")?;
+                let factory = object.get_property::<_, Unknown>(symbol)?;
+                if factory.get_type()? != ValueType::Function {
+                    return Err(Error::new(Status::InvalidArg, "Expected a file path, file descriptor, Buffer or Readable stream"));
+                }
+                let iterator = unsafe { factory.cast::<Function<(), Object>>()? }.apply(object, ())?;
+                let next = iterator.get_named_property::<Function<(), Promise<IteratorResultJs>>>("next")?;
+                Self::Readable(next.bind(iterator)?.build_threadsafe_function().callee_handled::<false>().build_callback(|_| Ok(()))?)
+            }
+        })
+    }
+
+    /// 读取全部输入：路径使用内存映射，其余读入内存
+    pub(crate) fn load(&self) -> std::result::Result<SourceData<'_>, BsdiffError> {
+        match self {
+            Self::Path(path) => Ok(SourceData::Mapped(BsdiffRust::create_single_memory_map(path)?)),
+            Self::Fd(file) => {
+                let mut data = Vec::new();
+                (&**file).read_to_end(&mut data)?;
+                Ok(SourceData::Owned(data))
+            }
+            Self::Buffer(buffer) => Ok(SourceData::Borrowed(buffer)),
+            Self::Readable(next) => {
+                let mut data = Vec::new();
+                loop {
+                    let (sender, receiver) = mpsc::channel();
+                    next.call_with_return_value((), ThreadsafeFunctionCallMode::NonBlocking, move |result, _| {
+                        let _ = sender.send(result);
+                        Ok(())
+                    });
+                    let promise = receiver
+                        .recv()
+                        .map_err(|_| io::Error::other("Readable stream was released"))?
+                        .map_err(|e| BsdiffError::from(e.to_string()))?;
+                    let chunk = block_on(promise).map_err(|e| BsdiffError::from(e.to_string()))?;
+                    if chunk.done.unwrap_or(false) {
+                        return Ok(SourceData::Owned(data));
+                    }
+                    match chunk.value {
+                        Some(Either::A(buffer)) => data.extend_from_slice(&buffer),
+                        _ => return Err("Readable stream must yield Buffer chunks (do not set an encoding)".into()),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 流式输出目标，在主线程上创建，在工作线程上写入
+///
+/// 写入 Writable 时逐块等待 `write` 回调，天然遵守背压；调用方应在外层加缓冲以合并小块写入。
+pub(crate) enum StreamSink {
+    Fd(ManuallyDrop<File>),
+    Writable { write: WriteChunkJs, end: EndStreamJs },
+}
+
+impl StreamSink {
+    pub(crate) fn from_js(value: StreamSinkJs) -> Result<Self> {
+        Ok(match value {
+            Either::A(fd) => Self::Fd(borrow_fd(fd)?),
+            Either::B(object) => {
+                let is_function = |name| -> Result<bool> { Ok(object.get_named_property::<Unknown>(name)?.get_type()? == ValueType::Function) };
+                if !is_function("write")? || !is_function("end")? {
+                    return Err(Error::new(Status::InvalidArg, "Expected a file descriptor or Writable stream"));
+                }
+                let write = object.get_named_property::<Function<FnArgs<(Buffer, RawValue)>, ()>>("write")?;
+                let end = object.get_named_property::<Function<RawValue, ()>>("end")?;
+                Self::Writable {
+                    write: write.bind(object)?.build_threadsafe_function().callee_handled::<false>().build_callback(|ctx| {
+                        let (chunk, sender): (Vec<u8>, _) = ctx.value;
+                        Ok((Buffer::from(chunk), completion_callback(&ctx.env, sender)?).into())
+                    })?,
+                    end: end.bind(object)?.build_threadsafe_function().callee_handled::<false>().build_callback(|ctx| {
+                        completion_callback(&ctx.env, ctx.value)
+                    })?,
+                }
+            }
+        })
+    }
+
+    /// 结束输出：Writable 调用 `end()` 并等待 `finish`，文件描述符保持打开由调用方关闭
+    pub(crate) fn finish(&mut self) -> io::Result<()> {
+        match self {
+            Self::Fd(file) => file.flush(),
+            Self::Writable { end, .. } => {
+                let (sender, receiver) = mpsc::channel();
+                end.call(sender, ThreadsafeFunctionCallMode::NonBlocking);
+                wait_completion(receiver)
+            }
+        }
+    }
+}
+
+impl Write for StreamSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Fd(file) => file.write(buf),
+            Self::Writable { write, .. } => {
+                let (sender, receiver) = mpsc::channel();
+                write.call((buf.to_vec(), sender), ThreadsafeFunctionCallMode::NonBlocking);
+                wait_completion(receiver)?;
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Fd(file) => file.flush(),
+            Self::Writable { .. } => Ok(()),
+        }
+    }
+}
+
+/// 创建 Node 风格的完成回调 `(err) => void`，结果发送给等待中的工作线程
+fn completion_callback(env: &Env, sender: mpsc::Sender<io::Result<()>>) -> Result<RawValue> {
+    let callback = env.create_function_from_closure::<(), (), _>("onComplete", move |ctx| {
+        let result = match ctx.length() {
+            0 => Ok(()),
+            _ => match ctx.get::<Option<Unknown>>(0)? {
+                None => Ok(()),
+                // 以 BrokenPipe 上报，避免被补丁解码误判为补丁损坏
+                Some(error) => Err(io::Error::new(io::ErrorKind::BrokenPipe, error.coerce_to_string()?.into_utf8()?.into_owned()?)),
+            },
+        };
+        let _ = sender.send(result);
+        Ok(())
+    })?;
+    Ok(RawValue(callback.raw()))
+}
+
+fn wait_completion(receiver: mpsc::Receiver<io::Result<()>>) -> io::Result<()> {
+    receiver.recv().map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Writable stream was released"))?
+}
+
+/// 借用调用方的文件描述符，不取得所有权 (不会关闭)
+#[cfg(unix)]
+fn borrow_fd(fd: u32) -> Result<ManuallyDrop<File>> {
+    use std::os::unix::io::FromRawFd;
+    let fd = i32::try_from(fd).map_err(|_| Error::new(Status::InvalidArg, format!("Invalid file descriptor: {}", fd)))?;
+    Ok(ManuallyDrop::new(unsafe { File::from_raw_fd(fd) }))
+}
+
+#[cfg(not(unix))]
+fn borrow_fd(_fd: u32) -> Result<ManuallyDrop<File>> {
+    Err(Error::new(Status::InvalidArg, "File descriptors are only supported on Unix"))
+}
+
+/// 唤醒等待线程的 waker
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// 在工作线程上阻塞等待 JavaScript Promise 完成
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut context = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+        thread::park();
+    }
+}
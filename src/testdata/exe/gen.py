@@ -0,0 +1,36 @@
+# 生成 old.elf / new.elf 的 C 源码：150 个互相调用且调用 libc (PLT) 与公共辅助函数的函数，
+# 新版本在代码段前部插入一个函数，其后的代码整体移动。
+#
+#   python3 gen.py 1 > old.c && python3 gen.py 2 > new.c
+#   gcc -O2 -fno-inline -o old.elf old.c && gcc -O2 -fno-inline -o new.elf new.c && strip old.elf new.elf
+#
+# 现有文件由 Debian 12 的 gcc 12.2.0 (x86-64) 生成。
+import sys
+
+version = int(sys.argv[1])
+count = 150
+out = ["#include <stdio.h>\n#include <string.h>\n#include <stdlib.h>\n", "static char buf[4096];\n"]
+for i in range(8):
+    out.append(f"__attribute__((noinline)) int helper{i}(const char *s){{ return (int)strlen(s) * {i + 1} + s[0]; }}\n")
+if version == 2:
+    out.append("__attribute__((noinline)) int helper_new(const char *s){ int n = (int)strlen(s); "
+               "for (int i = 0; i < n; i++) n ^= s[i] << (i & 7); return n; }\n")
+for i in range(count):
+    body = [f"__attribute__((noinline)) int f{i}(const char *s, int x) {{\n  int r = x + {i};\n"]
+    body.append(f"  memcpy(buf + {i % 64}, s, {i % 32 + 1});\n")
+    body.append(f"  r += helper{i % 8}(buf);\n")
+    if i % 3 == 0:
+        body.append(f"  r ^= (int)strlen(s + {i % 5});\n")
+    if i % 4 == 1:
+        body.append(f"  if (r > {i * 13}) snprintf(buf, sizeof buf, \"%d-{i}\", r);\n")
+    if i % 5 == 2:
+        body.append(f"  r += helper{(i + 3) % 8}(s) + atoi(buf);\n")
+    if i > 0:
+        body.append(f"  if (x > 0) r += f{(i * 7) % i}(s, x - 1);\n")
+    if version == 2 and i == 3:
+        body.append("  r += helper_new(s);\n")
+    body.append("  return r;\n}\n")
+    out.append("".join(body))
+out.append(f"int main(int c, char **v){{ int s = 0; for (int i = 0; i < c; i++) s += f{count - 1}(v[i], c); "
+           "printf(\"%d\\n\", s); return 0; }\n")
+sys.stdout.write("".join(out))
@@ -0,0 +1,129 @@
+//! 写入限速：令牌桶按写出的字节计费，超出速率时让写入线程休眠，
+//! 避免多 GB 的补丁应用占满磁盘带宽、拖慢前台应用。
+
+use std::io::{self, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error::BsdiffError;
+
+/// 单次写入的最大字节数：大块写入被拆开，休眠更均匀
+const MAX_WRITE_CHUNK: usize = 64 * 1024;
+
+/// 写入速率上限 (字节/秒)。克隆共享同一个令牌桶，同一次操作的多个并行写入合计不超过上限
+#[derive(Debug, Clone)]
+pub struct WriteLimit {
+    bytes_per_sec: u64,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// 可用额度 (字节)，为负表示已透支、需要等待
+    available: f64,
+    refilled: Instant,
+}
+
+impl WriteLimit {
+    pub fn new(bytes_per_sec: u64) -> Result<Self, BsdiffError> {
+        if bytes_per_sec == 0 {
+            return Err("Write limit must be greater than 0 bytes per second".into());
+        }
+        Ok(Self {
+            bytes_per_sec,
+            bucket: Arc::new(Mutex::new(Bucket { available: 0.0, refilled: Instant::now() })),
+        })
+    }
+
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bytes_per_sec
+    }
+
+    /// 突发额度：最多攒下约 100ms 的写入量 (至少一个写入块)，空闲之后不会一次性猛写
+    fn burst(&self) -> f64 {
+        (self.bytes_per_sec as f64 / 10.0).max(MAX_WRITE_CHUNK as f64)
+    }
+
+    /// 记入 `len` 字节，额度透支时休眠到补足为止
+    pub fn consume(&self, len: usize) {
+        let rate = self.bytes_per_sec as f64;
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.refilled).as_secs_f64() * rate;
+            bucket.available = (bucket.available + refill).min(self.burst()) - len as f64;
+            bucket.refilled = now;
+            -bucket.available / rate
+        };
+        if wait > 0.0 {
+            std::thread::sleep(Duration::from_secs_f64(wait));
+        }
+    }
+}
+
+/// 按 [`WriteLimit`] 限速的写入器；没有限速时直接转发
+pub struct ThrottledWriter<W> {
+    inner: W,
+    limit: Option<WriteLimit>,
+}
+
+impl<W> ThrottledWriter<W> {
+    pub fn new(inner: W, limit: Option<WriteLimit>) -> Self {
+        Self { inner, limit }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for ThrottledWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(limit) = &self.limit else {
+            return self.inner.write(buf);
+        };
+        let written = self.inner.write(&buf[..buf.len().min(MAX_WRITE_CHUNK)])?;
+        limit.consume(written);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Seek> Seek for ThrottledWriter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_limit() {
+        assert!(WriteLimit::new(0).is_err());
+
+        // 突发额度之外的 400KB 以 2MB/s 写出，约需 200ms
+        let limit = WriteLimit::new(2 * 1024 * 1024).unwrap();
+        let mut writer = ThrottledWriter::new(Vec::new(), Some(limit.clone()));
+        let start = Instant::now();
+        writer.write_all(&vec![7u8; 400 * 1024]).unwrap();
+        let elapsed = start.elapsed();
+        assert_eq!(writer.into_inner().len(), 400 * 1024);
+        assert!(elapsed >= Duration::from_millis(150), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+
+        // 克隆共享额度：紧接着再写同样多的数据也要等待
+        let start = Instant::now();
+        ThrottledWriter::new(io::sink(), Some(limit)).write_all(&vec![7u8; 400 * 1024]).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(150));
+
+        // 不限速时直接转发
+        let start = Instant::now();
+        ThrottledWriter::new(io::sink(), None).write_all(&vec![7u8; 64 << 20]).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
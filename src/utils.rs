@@ -1,12 +1,52 @@
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::{Read, BufReader};
-use zstd::stream::Decoder as ZstdDecoder;
+use std::io::{self, Read, Seek, BufReader, Write};
+use std::path::Path;
+
+use crate::bsdiff40;
+use crate::bsdiff_rust::{BsdiffRust, OptimizationConfig};
+use crate::codecs;
+use crate::control::{offtin, ControlEntry};
+use crate::dictionary::Dictionary;
+use crate::encryption;
+use crate::error::BsdiffError;
+use crate::formats::vcdiff;
+use crate::header::{Compression, FileDigest, HashAlgorithm, Hasher, PatchHeader, ALGORITHM_VERSION, FORMAT_VERSION, PAYLOAD_TRAILER_LEN};
+use crate::patch_set;
 
 /// 补丁文件信息
 #[derive(Debug, Clone)]
 pub struct PatchInfo {
     pub size: u64,
     pub compressed: bool,
+    /// 容器类型: "bsrz" (带补丁头)、"zstd" (旧版无头补丁)、"bsdiff40" 或 "vcdiff"
+    pub container: &'static str,
+    /// 补丁头格式版本 (仅 bsrz)
+    pub format_version: Option<u8>,
+    /// 负载压缩算法
+    pub compression: &'static str,
+    /// 补丁头标志位
+    pub flags: u8,
+    /// 生成补丁的 diff 算法版本 (仅 bsrz)
+    pub bsdiff_version: Option<String>,
+    /// 补丁头记录的旧文件大小
+    pub source_size: Option<u64>,
+    /// 补丁头记录的新文件大小
+    pub target_size: Option<u64>,
+    /// 负载解压后的大小 (bsdiff 控制流与差分数据)；BSDIFF40、加密与字典压缩的补丁为 `None`
+    pub uncompressed_size: Option<u64>,
+    /// 补丁头记录的旧文件摘要 (十六进制，算法见 `hash_algorithm`)
+    pub source_sha256: Option<String>,
+    /// 补丁头记录的新文件摘要 (十六进制，算法见 `hash_algorithm`)
+    pub target_sha256: Option<String>,
+    /// 文件摘要算法: "sha256" 或 "blake3" (仅 bsrz)
+    pub hash_algorithm: Option<&'static str>,
+    /// 补丁头中的键值元数据 (仅 bsrz，未设置时为空)
+    pub metadata: BTreeMap<String, String>,
+    /// 压缩负载所用 zstd 字典的 ID (仅 bsrz)
+    pub dictionary_id: Option<u32>,
+    /// 补丁直接携带完整的新文件，不引用旧文件内容 (仅 bsrz)
+    pub full_file: bool,
 }
 
 /// 压缩比信息
@@ -18,8 +58,107 @@ pub struct CompressionRatio {
     pub ratio: f64, // 百分比
 }
 
-/// 验证补丁文件完整性
-pub fn verify_patch(old_file: &str, new_file: &str, patch_file: &str) -> Result<bool, Box<dyn std::error::Error>> {
+/// 补丁的 diff 统计：控制块数、从旧文件复制 (差分) 与新增字面量的字节数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiffStats {
+    /// 控制块 (控制三元组) 数
+    pub control_blocks: u64,
+    /// 以旧文件为基准差分生成的字节数
+    pub copied_bytes: u64,
+    /// 补丁中直接携带的新增字节数
+    pub literal_bytes: u64,
+    /// 最长的单段新增数据
+    pub largest_literal_run: u64,
+    /// 补丁直接携带完整的新文件 (`fallback_to_full` 回退)
+    pub full_file: bool,
+}
+
+impl DiffStats {
+    fn add(&mut self, entry: &ControlEntry) {
+        self.control_blocks += 1;
+        self.copied_bytes = self.copied_bytes.saturating_add(entry.mix_len);
+        self.literal_bytes = self.literal_bytes.saturating_add(entry.copy_len);
+        self.largest_literal_run = self.largest_literal_run.max(entry.copy_len);
+    }
+
+    /// 新增字面量占新文件的比例 (0-1)；接近 1 时补丁几乎等同完整下载
+    pub fn literal_ratio(&self) -> f64 {
+        let total = self.copied_bytes.saturating_add(self.literal_bytes);
+        if total == 0 {
+            0.0
+        } else {
+            self.literal_bytes as f64 / total as f64
+        }
+    }
+}
+
+/// 补丁中的一个 bsdiff 控制三元组及其在新旧文件中的位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControlRecord {
+    /// 以旧文件为基准差分生成的字节数
+    pub mix_len: u64,
+    /// 补丁中直接携带的新增字节数
+    pub copy_len: u64,
+    /// 处理完本块后旧文件位置的偏移量
+    pub seek: i64,
+    /// 差分数据对应的旧文件起始位置
+    pub old_offset: i64,
+    /// 本块输出在新文件中的起始位置
+    pub new_offset: u64,
+}
+
+/// inspect_patch 默认最多返回的控制块数
+pub const DEFAULT_INSPECT_LIMIT: usize = 10_000;
+
+/// 详细文件访问检查结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileAccess {
+    /// 路径存在 (符号链接指向的目标存在)
+    pub exists: bool,
+    /// 是普通文件 (跟随符号链接)
+    pub is_file: bool,
+    /// 可以打开读取
+    pub readable: bool,
+    /// 路径本身是符号链接
+    pub is_symlink: bool,
+    /// 因权限不足无法访问
+    pub permission_denied: bool,
+}
+
+/// 补丁自检结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfCheck {
+    pub ok: bool,
+    /// 失败原因
+    pub reason: Option<String>,
+}
+
+/// 算法版本信息
+#[derive(Debug, Clone)]
+pub struct AlgorithmInfo {
+    /// 内置 diff 算法的版本 (移植自的 bsdiff 版本)
+    pub algorithm_version: String,
+    pub zstd_version: String,
+    pub format_version: u8,
+}
+
+/// 验证补丁文件完整性 (`config` 中的取消标记在应用补丁时生效)
+pub fn verify_patch(old_file: &Path, new_file: &Path, patch_file: &Path, config: &OptimizationConfig) -> Result<bool, BsdiffError> {
+    // 有目标摘要的补丁：应用补丁时已流式校验输出与目标摘要，只需 (并行地) 比较新文件与目标摘要
+    let header = BsdiffRust::read_patch_header(&mut File::open(patch_file)?, patch_file).ok().flatten();
+    if let Some(target) = header.and_then(|h| h.target) {
+        let old_mmap = BsdiffRust::create_single_memory_map(old_file)?;
+        let new_mmap = BsdiffRust::create_single_memory_map(new_file)?;
+        let patch_handle = File::open(patch_file)?;
+        let total = patch_handle.metadata()?.len();
+        let (applied, new_digest) = rayon::join(
+            || BsdiffRust::patch_reader_into(&old_mmap, patch_handle, total, patch_file, &mut io::sink(), config),
+            || FileDigest::of_bytes_with(target.algorithm, &new_mmap),
+        );
+        applied?;
+        return Ok(new_digest == target);
+    }
+
     // 读取文件
     let mut old_data = Vec::new();
     let mut reader = BufReader::new(File::open(old_file)?);
@@ -29,48 +168,366 @@ pub fn verify_patch(old_file: &str, new_file: &str, patch_file: &str) -> Result<
     let mut reader = BufReader::new(File::open(new_file)?);
     reader.read_to_end(&mut new_data)?;
     
-    // 应用补丁到临时数据
-    let patch_file = File::open(patch_file)?;
-    let mut reader = ZstdDecoder::new(patch_file)?;
-    let mut patched_data = Vec::new();
-    
-    bsdiff::patch(&old_data, &mut reader, &mut patched_data)?;
+    // 应用补丁到临时数据 (自动识别 BSDIFF40 格式)
+    let patch_handle = File::open(patch_file)?;
+    let total = patch_handle.metadata()?.len();
+    let patched_data = BsdiffRust::patch_from_reader(&old_data, patch_handle, total, patch_file, config)?;
     
     // 比较结果
     Ok(patched_data == new_data)
 }
 
-/// 获取补丁文件信息
-pub fn get_patch_info(patch_file: &str) -> Result<PatchInfo, Box<dyn std::error::Error>> {
-    let metadata = std::fs::metadata(patch_file)?;
-    Ok(PatchInfo {
-        size: metadata.len(),
-        compressed: true, // 我们总是使用 zstd 压缩
+/// 只用旧文件与补丁校验 (客户端通常没有新文件)：流式应用补丁并计算输出的 SHA-256，
+/// 与期望的十六进制摘要比较；输出既不落盘也不保留在内存中
+pub fn verify_patch_against_hash(
+    old_file: &Path,
+    patch_file: &Path,
+    expected_sha256: &str,
+    config: &OptimizationConfig
+) -> Result<bool, BsdiffError> {
+    let expected = patch_set::parse_digest(expected_sha256)?;
+    let old_mmap = BsdiffRust::create_single_memory_map(old_file)?;
+    let patch_handle = File::open(patch_file)?;
+    let total = patch_handle.metadata()?.len();
+    let mut hasher = HashWriter(Hasher::new(HashAlgorithm::Sha256));
+    BsdiffRust::patch_reader_into(&old_mmap, patch_handle, total, patch_file, &mut hasher, config)?;
+    Ok(hasher.0.finalize() == expected)
+}
+
+/// 只计算摘要、丢弃数据的写入器
+pub(crate) struct HashWriter(pub(crate) Hasher);
+
+impl Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// 不依赖旧文件自检补丁 (不应用补丁)：校验补丁头、完整解压 Zstd 负载并遍历 bsdiff 控制流，
+/// 确认输出长度与补丁头声明的目标大小一致。BSDIFF40 补丁校验 bzip2 CRC 与控制块，
+/// VCDIFF 补丁校验各窗口结构
+pub fn validate_patch_self(patch_file: &Path) -> Result<SelfCheck, BsdiffError> {
+    check_file_access(patch_file)?;
+    Ok(match check_patch_stream(patch_file) {
+        Ok(()) => SelfCheck { ok: true, reason: None },
+        Err(e) => SelfCheck { ok: false, reason: Some(e.to_string()) },
     })
 }
 
+fn check_patch_stream(patch_file: &Path) -> Result<(), BsdiffError> {
+    let mut head = Vec::new();
+    File::open(patch_file)?.take(bsdiff40::BSDIFF40_MAGIC.len() as u64).read_to_end(&mut head)?;
+    if bsdiff40::is_bsdiff40(&head) {
+        bsdiff40::from_bsdiff40(&std::fs::read(patch_file)?)?;
+        return Ok(());
+    }
+    if vcdiff::is_vcdiff(&head) {
+        vcdiff::target_size(&std::fs::read(patch_file)?)?;
+        return Ok(());
+    }
+
+    let (header, decoder) = BsdiffRust::open_patch(patch_file, None)?;
+    let mut reader = BufReader::with_capacity(64 * 1024, decoder);
+    let source_size = header.as_ref().and_then(|h| h.source).map(|source| source.size);
+
+    let mut old_pos: i64 = 0;
+    let mut new_size: u64 = 0;
+    let mut control_blocks: u64 = 0;
+    while let Some(entry) = ControlEntry::read_from(&mut reader)? {
+        control_blocks += 1;
+        // 差分数据读取的旧文件区间必须在源文件范围内
+        if let Some(source_size) = source_size {
+            let end = old_pos.checked_add_unsigned(entry.mix_len).ok_or("Corrupt control block: offset overflow")?;
+            if old_pos < 0 || end as u64 > source_size {
+                return Err(format!("Corrupt control block: old range {}..{} outside source of {} bytes", old_pos, end, source_size).into());
+            }
+        }
+        let payload_len = entry.mix_len.checked_add(entry.copy_len).ok_or("Corrupt control block: length overflow")?;
+        let skipped = io::copy(&mut (&mut reader).take(payload_len), &mut io::sink())?;
+        if skipped != payload_len {
+            return Err(format!("Truncated bsdiff stream: expected {} more bytes, got {}", payload_len, skipped).into());
+        }
+        new_size = new_size.checked_add(payload_len).ok_or("Corrupt control block: length overflow")?;
+        old_pos = old_pos
+            .checked_add_unsigned(entry.mix_len)
+            .and_then(|pos| pos.checked_add(entry.seek))
+            .ok_or("Corrupt control block: offset overflow")?;
+    }
+
+    if let Some(target) = header.as_ref().and_then(|h| h.target) {
+        if new_size != target.size {
+            return Err(format!("Target size mismatch: stream produces {} bytes, header declares {}", new_size, target.size).into());
+        }
+    }
+    if let Some(declared) = header.and_then(|h| h.control_blocks) {
+        if control_blocks != declared {
+            return Err(format!("Control block count mismatch: stream has {}, header declares {}", control_blocks, declared).into());
+        }
+    }
+    Ok(())
+}
+
+/// 遍历补丁的 bsdiff 控制流，统计复制与新增字节数 (不需要旧文件)；VCDIFF 补丁不支持，
+/// 字典压缩的补丁需提供相同的字典
+pub fn get_diff_stats(patch_file: &Path, dictionary: Option<&Dictionary>) -> Result<DiffStats, BsdiffError> {
+    let header = PatchHeader::read_from(&mut BufReader::new(File::open(patch_file)?)).ok().flatten();
+    let mut stats = DiffStats { full_file: header.is_some_and(|header| header.full_file), ..Default::default() };
+    for_each_control_entry(patch_file, dictionary, "Diff statistics", |entry| {
+        stats.add(entry);
+        true
+    })?;
+    Ok(stats)
+}
+
+/// 列出补丁的 bsdiff 控制块 (跳过前 `offset` 个，最多返回 `limit` 个)，用于排查补丁为何偏大；
+/// 不需要旧文件，VCDIFF 补丁不支持，字典压缩的补丁需提供相同的字典
+pub fn inspect_patch(
+    patch_file: &Path,
+    offset: usize,
+    limit: usize,
+    dictionary: Option<&Dictionary>,
+) -> Result<Vec<ControlRecord>, BsdiffError> {
+    let mut records = Vec::new();
+    let (mut index, mut old_pos, mut new_pos) = (0usize, 0i64, 0u64);
+    for_each_control_entry(patch_file, dictionary, "Control entries", |entry| {
+        if records.len() >= limit {
+            return false;
+        }
+        if index >= offset {
+            records.push(ControlRecord {
+                mix_len: entry.mix_len,
+                copy_len: entry.copy_len,
+                seek: entry.seek,
+                old_offset: old_pos,
+                new_offset: new_pos,
+            });
+        }
+        index += 1;
+        new_pos = new_pos.saturating_add(entry.mix_len).saturating_add(entry.copy_len);
+        old_pos = old_pos.saturating_add_unsigned(entry.mix_len).saturating_add(entry.seek);
+        true
+    })?;
+    Ok(records)
+}
+
+/// 依次访问补丁的 bsdiff 控制块 (跳过其后的数据)，`visit` 返回 `false` 时停止；
+/// `what` 用于 VCDIFF 补丁 (没有控制块) 的错误信息
+fn for_each_control_entry<F>(patch_file: &Path, dictionary: Option<&Dictionary>, what: &str, mut visit: F) -> Result<(), BsdiffError>
+where
+    F: FnMut(&ControlEntry) -> bool,
+{
+    let mut head = Vec::new();
+    File::open(patch_file)?.take(bsdiff40::BSDIFF40_MAGIC.len() as u64).read_to_end(&mut head)?;
+    if bsdiff40::is_bsdiff40(&head) {
+        let raw = bsdiff40::from_bsdiff40(&std::fs::read(patch_file)?)?;
+        let mut done = false;
+        bsdiff40::for_each_entry(&raw, |entry, _, _| {
+            done = done || !visit(&entry);
+            Ok(())
+        })?;
+        return Ok(());
+    }
+    if vcdiff::is_vcdiff(&head) {
+        return Err(format!("{} are not available for VCDIFF patches", what).into());
+    }
+
+    let (_, decoder) = BsdiffRust::open_patch(patch_file, dictionary)?;
+    let mut reader = BufReader::with_capacity(64 * 1024, decoder);
+    while let Some(entry) = ControlEntry::read_from(&mut reader)? {
+        if !visit(&entry) {
+            break;
+        }
+        let payload_len = entry.mix_len.checked_add(entry.copy_len).ok_or("Corrupt control block: length overflow")?;
+        let skipped = io::copy(&mut (&mut reader).take(payload_len), &mut io::sink())?;
+        if skipped != payload_len {
+            return Err(format!("Truncated bsdiff stream: expected {} more bytes, got {}", payload_len, skipped).into());
+        }
+    }
+    Ok(())
+}
+
+/// 获取补丁文件信息：解析容器头部，不是补丁文件时返回错误
+pub fn get_patch_info(patch_file: &Path) -> Result<PatchInfo, BsdiffError> {
+    let data = BsdiffRust::create_single_memory_map(patch_file)?;
+    patch_info_of(&data, patch_file)
+}
+
+/// 同 [`get_patch_info`]，解析内存中的补丁 (如对象存储中的 blob)，不访问磁盘
+pub fn get_patch_info_from_bytes(patch: &[u8]) -> Result<PatchInfo, BsdiffError> {
+    patch_info_of(patch, Path::new("<buffer>"))
+}
+
+/// 解析补丁数据的容器头部；`patch_file` 仅用于错误信息
+fn patch_info_of(data: &[u8], patch_file: &Path) -> Result<PatchInfo, BsdiffError> {
+    let mut file = io::Cursor::new(data);
+    let size = data.len() as u64;
+    let mut info = PatchInfo {
+        size,
+        compressed: true,
+        container: "zstd",
+        format_version: None,
+        compression: "zstd",
+        flags: 0,
+        bsdiff_version: None,
+        source_size: None,
+        target_size: None,
+        uncompressed_size: None,
+        source_sha256: None,
+        target_sha256: None,
+        hash_algorithm: None,
+        metadata: BTreeMap::new(),
+        dictionary_id: None,
+        full_file: false,
+    };
+
+    // BSDIFF40: 魔数 | 控制块长度 | 差分块长度 | 新文件大小
+    let mut classic = [0u8; 32];
+    if file.read_exact(&mut classic).is_ok() && bsdiff40::is_bsdiff40(&classic) {
+        let new_size = offtin(classic[24..32].try_into()?);
+        return Ok(PatchInfo {
+            container: "bsdiff40",
+            compression: "bzip2",
+            target_size: u64::try_from(new_size).ok(),
+            ..info
+        });
+    }
+    file.rewind()?;
+
+    // VCDIFF: 目标大小为各窗口目标大小之和
+    let mut magic = [0u8; vcdiff::VCDIFF_MAGIC.len()];
+    if file.read_exact(&mut magic).is_ok() && vcdiff::is_vcdiff(&magic) {
+        return Ok(PatchInfo {
+            compressed: false,
+            container: "vcdiff",
+            compression: "none",
+            target_size: Some(vcdiff::target_size(data)?),
+            uncompressed_size: Some(size),
+            ..info
+        });
+    }
+    file.rewind()?;
+
+    // 加密信封：补丁头在密文中，只能报告容器类型与大小
+    let mut magic = [0u8; encryption::ENVELOPE_MAGIC.len()];
+    if file.read_exact(&mut magic).is_ok() && encryption::is_encrypted(&magic) {
+        return Ok(PatchInfo { container: "encrypted", compression: "unknown", ..info });
+    }
+    file.rewind()?;
+
+    let header = BsdiffRust::read_patch_header(&mut file, patch_file)?;
+    let compression = header.as_ref().map_or(Compression::Zstd, |header| header.compression);
+    // 字典压缩的负载不提供字典无法解码
+    if header.as_ref().is_none_or(|header| header.dictionary_id.is_none()) {
+        let trailer = if header.as_ref().is_some_and(|header| header.payload_crc) { PAYLOAD_TRAILER_LEN } else { 0 };
+        let payload = &data[..data.len().saturating_sub(trailer)];
+        info.uncompressed_size = payload_uncompressed_size(payload, file.stream_position()?, compression).ok();
+    }
+    if let Some(header) = header {
+        info.container = "bsrz";
+        info.compressed = header.compression != Compression::None;
+        info.format_version = Some(header.format_version);
+        info.compression = header.compression.name();
+        info.flags = header.flags;
+        info.source_size = header.source.map(|source| source.size);
+        info.target_size = header.target.map(|target| target.size);
+        info.source_sha256 = header.source.map(|source| source.hex());
+        info.target_sha256 = header.target.map(|target| target.hex());
+        info.hash_algorithm = Some(header.hash_algorithm().name());
+        info.bsdiff_version = Some(header.bsdiff_version);
+        info.metadata = header.metadata;
+        info.dictionary_id = header.dictionary_id;
+        info.full_file = header.full_file;
+    }
+    Ok(info)
+}
+
+/// 补丁负载 (从 `offset` 开始) 解压后的字节数：zstd 帧头都记录了内容大小时直接求和，
+/// 否则 (流式压缩的帧不记录，以及 xz / brotli / gzip 负载) 流式解压计数，内存占用与补丁大小无关
+fn payload_uncompressed_size(data: &[u8], offset: u64, compression: Compression) -> Result<u64, BsdiffError> {
+    let mut payload = data.get(offset as usize..).ok_or("Patch payload offset out of range")?;
+    match compression {
+        Compression::None => return Ok(payload.len() as u64),
+        Compression::Zstd => {}
+        compression => return Ok(io::copy(&mut codecs::decoder(compression, payload, None)?, &mut io::sink())?),
+    }
+
+    let mut total = 0u64;
+    while !payload.is_empty() {
+        let content_size = zstd::zstd_safe::get_frame_content_size(payload).ok().flatten();
+        let frame_len = zstd::zstd_safe::find_frame_compressed_size(payload).map_err(|_| "Corrupt zstd frame in patch payload")?;
+        match content_size {
+            Some(size) => total += size,
+            None => {
+                let decoder = zstd::stream::read::Decoder::with_buffer(&payload[..frame_len])?.single_frame();
+                total += io::copy(&mut BufReader::new(decoder), &mut io::sink())?;
+            }
+        }
+        payload = &payload[frame_len..];
+    }
+    Ok(total)
+}
+
 /// 计算文件大小（用于进度显示）
-pub fn get_file_size(file_path: &str) -> Result<u64, Box<dyn std::error::Error>> {
+pub fn get_file_size(file_path: &Path) -> Result<u64, BsdiffError> {
     let metadata = std::fs::metadata(file_path)?;
     Ok(metadata.len())
 }
 
 /// 检查文件是否存在且可读
-pub fn check_file_access(file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+pub fn check_file_access(file_path: &Path) -> Result<(), BsdiffError> {
     let path = std::path::Path::new(file_path);
     if !path.exists() {
-        return Err(format!("File not found: {}", file_path).into());
+        return Err(BsdiffError::FileNotFound { kind: "File", path: file_path.display().to_string() });
     }
     if !path.is_file() {
-        return Err(format!("Path is not a file: {}", file_path).into());
+        return Err(format!("Path is not a file: {}", file_path.display()).into());
     }
     // 尝试打开文件以验证可读性
     File::open(file_path)?;
     Ok(())
 }
 
+/// 详细检查文件访问情况，区分不存在、目录、断开的符号链接与权限问题
+pub fn check_file_access_detailed(file_path: &Path) -> FileAccess {
+    let mut access = FileAccess::default();
+
+    match std::fs::symlink_metadata(file_path) {
+        Ok(metadata) => access.is_symlink = metadata.file_type().is_symlink(),
+        Err(e) => {
+            access.permission_denied = e.kind() == io::ErrorKind::PermissionDenied;
+            return access;
+        }
+    }
+
+    // 跟随符号链接；断开的链接在此处表现为不存在
+    match std::fs::metadata(file_path) {
+        Ok(metadata) => {
+            access.exists = true;
+            access.is_file = metadata.is_file();
+        }
+        Err(e) => {
+            access.permission_denied = e.kind() == io::ErrorKind::PermissionDenied;
+            return access;
+        }
+    }
+
+    // 探测打开
+    if access.is_file {
+        match File::open(file_path) {
+            Ok(_) => access.readable = true,
+            Err(e) => access.permission_denied = e.kind() == io::ErrorKind::PermissionDenied,
+        }
+    }
+    access
+}
+
 /// 获取压缩比信息
-pub fn get_compression_ratio(old_file: &str, new_file: &str, patch_file: &str) -> Result<CompressionRatio, Box<dyn std::error::Error>> {
+pub fn get_compression_ratio(old_file: &Path, new_file: &Path, patch_file: &Path) -> Result<CompressionRatio, BsdiffError> {
     let old_size = get_file_size(old_file)?;
     let new_size = get_file_size(new_file)?;
     let patch_size = get_file_size(patch_file)?;
@@ -90,3 +547,270 @@ pub fn get_compression_ratio(old_file: &str, new_file: &str, patch_file: &str) -
     })
 }
 
+
+/// 获取编译进来的算法版本信息
+pub fn algorithm_info() -> AlgorithmInfo {
+    AlgorithmInfo {
+        algorithm_version: ALGORITHM_VERSION.to_string(),
+        zstd_version: zstd::zstd_safe::version_string().to_string(),
+        format_version: FORMAT_VERSION,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bsdiff_rust::OptimizationConfig;
+    use crate::bsdiff40::PatchFormat;
+    use crate::header::{self, FileDigest};
+    use std::fs;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_validate_patch_self() {
+        let old_file = NamedTempFile::new().unwrap();
+        let new_file = NamedTempFile::new().unwrap();
+        let patch_file = NamedTempFile::new().unwrap();
+        fs::write(&old_file, b"self check old content ".repeat(30)).unwrap();
+        fs::write(&new_file, b"self check new content!".repeat(33)).unwrap();
+        BsdiffRust::diff_optimized(
+            old_file.path(),
+            new_file.path(),
+            patch_file.path(),
+            &OptimizationConfig { use_fast_temp_dir: false, ..Default::default() }
+        ).unwrap();
+        let patch_path = patch_file.path();
+        assert_eq!(validate_patch_self(patch_path).unwrap(), SelfCheck { ok: true, reason: None });
+
+        // 截断的补丁
+        let patch = fs::read(patch_file.path()).unwrap();
+        fs::write(&patch_file, &patch[..patch.len() - 8]).unwrap();
+        let check = validate_patch_self(patch_path).unwrap();
+        assert!(!check.ok);
+        assert!(check.reason.is_some());
+
+        // 负载中翻转字节
+        let mut corrupted = patch.clone();
+        let last = corrupted.len() - 20;
+        corrupted[last] ^= 0xFF;
+        fs::write(&patch_file, &corrupted).unwrap();
+        assert!(!validate_patch_self(patch_path).unwrap().ok);
+
+        // BSDIFF40 补丁校验 bzip2 CRC
+        let old = fs::read(old_file.path()).unwrap();
+        let new = fs::read(new_file.path()).unwrap();
+        let classic = OptimizationConfig { use_fast_temp_dir: false, format: PatchFormat::Bsdiff40, ..Default::default() };
+        let mut patch = BsdiffRust::diff_bytes(&old, &new, &classic).unwrap();
+        fs::write(&patch_file, &patch).unwrap();
+        assert!(validate_patch_self(patch_path).unwrap().ok);
+        let last = patch.len() - 12;
+        patch[last] ^= 0x01;
+        fs::write(&patch_file, &patch).unwrap();
+        assert!(!validate_patch_self(patch_path).unwrap().ok);
+
+        // 补丁文件不存在时返回错误
+        assert!(validate_patch_self(Path::new("/nonexistent/patch.bin")).is_err());
+    }
+
+    #[test]
+    fn test_verify_patch_against_hash() {
+        let old_file = NamedTempFile::new().unwrap();
+        let patch_file = NamedTempFile::new().unwrap();
+        let old = b"hash verify old content ".repeat(30);
+        let new = b"hash verify new content!".repeat(33);
+        fs::write(&old_file, &old).unwrap();
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        fs::write(&patch_file, BsdiffRust::diff_bytes(&old, &new, &config).unwrap()).unwrap();
+        let (old_path, patch_path) = (old_file.path(), patch_file.path());
+
+        let expected = FileDigest::of_bytes(&new).hex();
+        assert!(verify_patch_against_hash(old_path, patch_path, &expected, &config).unwrap());
+        assert!(verify_patch_against_hash(old_path, patch_path, &expected.to_uppercase(), &config).unwrap());
+        let other = FileDigest::of_bytes(&old).hex();
+        assert!(!verify_patch_against_hash(old_path, patch_path, &other, &config).unwrap());
+        assert!(verify_patch_against_hash(old_path, patch_path, "not-a-digest", &config).is_err());
+
+        // 旧文件不符时补丁头摘要校验失败
+        fs::write(&old_file, &new).unwrap();
+        assert!(verify_patch_against_hash(old_path, patch_path, &expected, &config).is_err());
+
+        // 与新文件比较时，取消等错误保留原类型 (供绑定层设置错误码)
+        fs::write(&old_file, &old).unwrap();
+        let new_file = NamedTempFile::new().unwrap();
+        fs::write(&new_file, &new).unwrap();
+        assert!(verify_patch(old_path, new_file.path(), patch_path, &config).unwrap());
+        let cancel = crate::CancelToken::new();
+        cancel.cancel();
+        let cancelled = OptimizationConfig { cancel: Some(cancel), ..config.clone() };
+        let err = verify_patch(old_path, new_file.path(), patch_path, &cancelled).unwrap_err();
+        assert!(matches!(err, BsdiffError::Cancelled), "{:?}", err);
+    }
+
+    #[test]
+    fn test_get_patch_info() {
+        let old = b"patch info base content ".repeat(20);
+        let new = b"patch info next content!".repeat(22);
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let patch_file = NamedTempFile::new().unwrap();
+        fs::write(&patch_file, BsdiffRust::diff_bytes(&old, &new, &config).unwrap()).unwrap();
+
+        let info = get_patch_info(patch_file.path()).unwrap();
+        assert_eq!(info.container, "bsrz");
+        assert_eq!(info.format_version, Some(FORMAT_VERSION));
+        assert_eq!(info.compression, "zstd");
+        assert_eq!(info.bsdiff_version.as_deref(), Some(ALGORITHM_VERSION));
+        assert_eq!(info.source_size, Some(old.len() as u64));
+        assert_eq!(info.target_size, Some(new.len() as u64));
+        assert_eq!(info.source_sha256, Some(FileDigest::of_bytes(&old).hex()));
+        assert_eq!(info.target_sha256, Some(FileDigest::of_bytes(&new).hex()));
+        let mut raw = Vec::new();
+        bsdiff::diff(&old, &new, &mut raw).unwrap();
+        assert_eq!(info.uncompressed_size, Some(raw.len() as u64));
+        let stored = OptimizationConfig { compression: Compression::None, ..config.clone() };
+        fs::write(&patch_file, BsdiffRust::diff_bytes(&old, &new, &stored).unwrap()).unwrap();
+        let info = get_patch_info(patch_file.path()).unwrap();
+        assert_eq!((info.compression, info.uncompressed_size), ("none", Some(raw.len() as u64)));
+        fs::write(&patch_file, BsdiffRust::diff_bytes(&old, &new, &config).unwrap()).unwrap();
+        assert!(info.metadata.is_empty());
+
+        // 元数据原样写入补丁头并读出，特殊字符经过 JSON 转义
+        let metadata: BTreeMap<String, String> = [("appVersion", "1.2.3"), ("channel", "beta"), ("note", "say \"hi\"\n\u{1}✓😀")]
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let tagged = OptimizationConfig { metadata: metadata.clone(), ..config.clone() };
+        let patch = BsdiffRust::diff_bytes(&old, &new, &tagged).unwrap();
+        assert_eq!(BsdiffRust::patch_bytes(&old, &patch, &config).unwrap(), new);
+        fs::write(&patch_file, &patch).unwrap();
+        assert_eq!(get_patch_info(patch_file.path()).unwrap().metadata, metadata);
+        assert_eq!(header::metadata_from_json(&header::metadata_to_json(&metadata)), Some(metadata.clone()));
+        let parsed = header::metadata_from_json(" { \"a\" : \"\\u00e9\\ud83d\\ude00\\/\" } ").unwrap();
+        assert_eq!(parsed["a"], "é😀/");
+        for bad in ["", "{", "{\"a\":1}", "{\"a\":\"b\",}", "{\"a\":\"\\ud83d\"}", "{} x"] {
+            assert_eq!(header::metadata_from_json(bad), None, "{}", bad);
+        }
+        let classic = OptimizationConfig { format: PatchFormat::Bsdiff40, ..tagged };
+        assert!(BsdiffRust::diff_bytes(&old, &new, &classic).is_err());
+
+        let delta = OptimizationConfig { format: PatchFormat::Vcdiff, ..config };
+        fs::write(&patch_file, BsdiffRust::diff_bytes(&old, &new, &delta).unwrap()).unwrap();
+        let info = get_patch_info(patch_file.path()).unwrap();
+        assert_eq!(info.container, "vcdiff");
+        assert_eq!(info.compression, "none");
+        assert_eq!(info.target_size, Some(new.len() as u64));
+
+        let not_patch = NamedTempFile::new().unwrap();
+        fs::write(&not_patch, b"plain text, not a patch").unwrap();
+        let err = get_patch_info(not_patch.path()).unwrap_err();
+        assert!(err.to_string().contains("not a bsdiff-rust patch"));
+
+        // 内存中的补丁与同内容文件解析结果一致
+        let patch = BsdiffRust::diff_bytes(&old, &new, &OptimizationConfig { metadata: metadata.clone(), use_fast_temp_dir: false, ..Default::default() }).unwrap();
+        fs::write(&patch_file, &patch).unwrap();
+        let from_file = get_patch_info(patch_file.path()).unwrap();
+        let from_bytes = get_patch_info_from_bytes(&patch).unwrap();
+        assert_eq!((from_bytes.size, from_bytes.target_sha256, from_bytes.metadata), (from_file.size, from_file.target_sha256, from_file.metadata));
+        assert_eq!(from_bytes.uncompressed_size, Some(raw.len() as u64));
+        assert!(get_patch_info_from_bytes(b"plain text, not a patch").is_err());
+        assert!(get_patch_info_from_bytes(&[]).is_err());
+    }
+
+    #[test]
+    fn test_get_diff_stats() {
+        let old = b"diff stats base content ".repeat(40);
+        let mut new = old.clone();
+        new[100..140].fill(b'=');
+        let tail: Vec<u8> = (0..300u32).map(|i| (i.wrapping_mul(2654435761) >> 24) as u8).collect();
+        new.extend_from_slice(&tail);
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let patch_file = NamedTempFile::new().unwrap();
+        let path = patch_file.path();
+
+        fs::write(&patch_file, BsdiffRust::diff_bytes(&old, &new, &config).unwrap()).unwrap();
+        let stats = get_diff_stats(path, None).unwrap();
+        assert!(stats.control_blocks >= 1);
+        assert_eq!(stats.copied_bytes + stats.literal_bytes, new.len() as u64);
+        assert!(stats.largest_literal_run >= tail.len() as u64);
+        assert!(stats.literal_ratio() > 0.0 && stats.literal_ratio() < 1.0);
+
+        // BSDIFF40 的控制流与 zstd 补丁一致
+        let classic = OptimizationConfig { format: PatchFormat::Bsdiff40, ..config.clone() };
+        fs::write(&patch_file, BsdiffRust::diff_bytes(&old, &new, &classic).unwrap()).unwrap();
+        assert_eq!(get_diff_stats(path, None).unwrap(), stats);
+
+        let delta = OptimizationConfig { format: PatchFormat::Vcdiff, ..config };
+        fs::write(&patch_file, BsdiffRust::diff_bytes(&old, &new, &delta).unwrap()).unwrap();
+        assert!(get_diff_stats(path, None).is_err());
+        assert_eq!(DiffStats::default().literal_ratio(), 0.0);
+    }
+
+    #[test]
+    fn test_inspect_patch() {
+        let old: Vec<u8> = (0..20_000u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let mut new = old.clone();
+        for start in [15_000, 9_000, 5_000, 1_000] {
+            new.splice(start..start, (0..200u32).map(|i| (i.wrapping_mul(40503) >> 5) as u8 ^ start as u8));
+        }
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        let patch_file = NamedTempFile::new().unwrap();
+        let path = patch_file.path();
+        fs::write(&patch_file, BsdiffRust::diff_bytes(&old, &new, &config).unwrap()).unwrap();
+
+        // 控制块依次覆盖整个新文件，与 diff 统计一致
+        let records = inspect_patch(path, 0, DEFAULT_INSPECT_LIMIT, None).unwrap();
+        let stats = get_diff_stats(path, None).unwrap();
+        assert_eq!(records.len() as u64, stats.control_blocks);
+        assert_eq!(records.iter().map(|r| r.copy_len).sum::<u64>(), stats.literal_bytes);
+        assert_eq!(records[0].new_offset, 0);
+        assert_eq!(records[0].old_offset, 0);
+        for pair in records.windows(2) {
+            assert_eq!(pair[1].new_offset, pair[0].new_offset + pair[0].mix_len + pair[0].copy_len);
+            assert_eq!(pair[1].old_offset, pair[0].old_offset + pair[0].mix_len as i64 + pair[0].seek);
+        }
+        let last = records.last().unwrap();
+        assert_eq!(last.new_offset + last.mix_len + last.copy_len, new.len() as u64);
+
+        // 分页
+        assert!(records.len() > 2);
+        assert_eq!(inspect_patch(path, 1, 2, None).unwrap(), records[1..3]);
+        assert!(inspect_patch(path, records.len(), 10, None).unwrap().is_empty());
+        assert!(inspect_patch(path, 0, 0, None).unwrap().is_empty());
+
+        let delta = OptimizationConfig { format: PatchFormat::Vcdiff, ..config };
+        fs::write(&patch_file, BsdiffRust::diff_bytes(&old, &new, &delta).unwrap()).unwrap();
+        assert!(inspect_patch(path, 0, 10, None).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_file_access_detailed() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, b"data").unwrap();
+        let broken = dir.path().join("broken");
+        std::os::unix::fs::symlink(dir.path().join("missing"), &broken).unwrap();
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+
+        assert_eq!(
+            check_file_access_detailed(&file),
+            FileAccess { exists: true, is_file: true, readable: true, ..Default::default() }
+        );
+        assert_eq!(
+            check_file_access_detailed(&link),
+            FileAccess { exists: true, is_file: true, readable: true, is_symlink: true, ..Default::default() }
+        );
+        assert_eq!(
+            check_file_access_detailed(&broken),
+            FileAccess { is_symlink: true, ..Default::default() }
+        );
+        assert_eq!(
+            check_file_access_detailed(dir.path()),
+            FileAccess { exists: true, ..Default::default() }
+        );
+        assert_eq!(
+            check_file_access_detailed(&dir.path().join("missing")),
+            FileAccess::default()
+        );
+    }
+}
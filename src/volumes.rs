@@ -0,0 +1,377 @@
+//! 分卷补丁：按固定大小把补丁切成 `<patch>.001`、`<patch>.002`…… 原样的分卷，另写一个索引文件，
+//! 适用于限制单个文件大小的分发渠道。分卷直接拼接即为完整补丁，应用时按索引或目录顺序读取，无需先合并
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+
+use crate::bsdiff_rust::{BsdiffRust, OptimizationConfig};
+use crate::error::BsdiffError;
+
+/// 分卷索引魔数
+pub const VOLUME_MAGIC: [u8; 5] = *b"BSRV\x01";
+
+/// 当前分卷索引格式版本
+pub const VOLUME_FORMAT_VERSION: u8 = 1;
+
+/// 分卷数上限，防止过小的分卷大小生成海量文件
+const MAX_VOLUMES: u32 = 1 << 20;
+
+/// 索引中单个条目的最小字节数 (`name_len` + 至少一字节的文件名 + `length`)
+const MIN_ENTRY_SIZE: u64 = 2 + 1 + 8;
+
+/// 按条目数预分配的上限；条目数来自文件内容，超出部分随读取增长
+const MAX_PREALLOCATED: usize = 1024;
+
+/// 单个分卷：文件路径与其字节数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Volume {
+    pub path: PathBuf,
+    pub length: u64,
+}
+
+/// 把 `patch` 按 `volume_size` 字节切分为 `<index_file>.001`、`<index_file>.002`……，
+/// 最后写出索引 `index_file`，返回各分卷路径
+///
+/// 索引布局: `VOLUME_MAGIC | version: u8 | count: u32 LE | patch_size: u64 LE | 条目`，
+/// 条目为 `name_len: u16 LE | name (UTF-8，与索引同目录的文件名) | length: u64 LE`。
+/// 上次输出遗留的多余分卷会被删除，目录中只剩本次的分卷。
+pub fn write_volumes(
+    patch: &Path,
+    index_file: &Path,
+    volume_size: u64,
+    config: &OptimizationConfig
+) -> Result<Vec<PathBuf>, BsdiffError> {
+    if volume_size == 0 {
+        return Err("Volume size must be greater than 0".into());
+    }
+    let patch_size = std::fs::metadata(patch)?.len();
+    let count = u32::try_from(patch_size.div_ceil(volume_size).max(1))
+        .ok()
+        .filter(|count| *count <= MAX_VOLUMES)
+        .ok_or_else(|| format!("Volume size {} would split {} into more than {} volumes", volume_size, index_file.display(), MAX_VOLUMES))?;
+    let index_name = index_file
+        .file_name()
+        .ok_or("Invalid file path")?
+        .to_str()
+        .ok_or_else(|| format!("Volume index name is not UTF-8: {}", index_file.display()))?;
+
+    let width = count.to_string().len().max(3);
+    let volume_name = |number: u32| format!("{}.{:0width$}", index_name, number, width = width);
+    let mut reader = BufReader::with_capacity(64 * 1024, File::open(patch)?);
+    let mut volumes = Vec::with_capacity((count as usize).min(MAX_PREALLOCATED));
+    for number in 1..=count {
+        let path = index_file.with_file_name(volume_name(number));
+        let length = volume_size.min(patch_size - (number as u64 - 1) * volume_size);
+        let out_path = BsdiffRust::get_optimal_output_path(&path, config, length)?;
+        let result = (|| -> io::Result<()> {
+            let mut writer = BufWriter::with_capacity(64 * 1024, File::create(&out_path)?);
+            if io::copy(&mut (&mut reader).take(length), &mut writer)? != length {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "patch changed while writing volumes"));
+            }
+            writer.flush()
+        })();
+        if let Err(e) = result {
+            let _ = std::fs::remove_file(&out_path);
+            return Err(e.into());
+        }
+        BsdiffRust::finalize_output(&out_path, &path, config)?;
+        if config.durable {
+            BsdiffRust::sync_output(&path)?;
+        }
+        volumes.push(Volume { path, length });
+    }
+    for number in count + 1.. {
+        if std::fs::remove_file(index_file.with_file_name(volume_name(number))).is_err() {
+            break;
+        }
+    }
+
+    let mut index = Vec::new();
+    index.extend_from_slice(&VOLUME_MAGIC);
+    index.push(VOLUME_FORMAT_VERSION);
+    index.extend_from_slice(&count.to_le_bytes());
+    index.extend_from_slice(&patch_size.to_le_bytes());
+    for (number, volume) in (1..).zip(&volumes) {
+        let name = volume_name(number);
+        index.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        index.extend_from_slice(name.as_bytes());
+        index.extend_from_slice(&volume.length.to_le_bytes());
+    }
+    let out_path = BsdiffRust::get_optimal_output_path(index_file, config, index.len() as u64)?;
+    if let Err(e) = std::fs::write(&out_path, &index) {
+        let _ = std::fs::remove_file(&out_path);
+        return Err(e.into());
+    }
+    BsdiffRust::finalize_output(&out_path, index_file, config)?;
+    if config.durable {
+        BsdiffRust::sync_output(index_file)?;
+    }
+    Ok(volumes.into_iter().map(|volume| volume.path).collect())
+}
+
+/// 识别分卷补丁：`patch_file` 为分卷索引时按索引返回分卷，为目录时按其中的索引或编号分卷返回；
+/// 普通补丁文件返回 `None`。所有分卷都须存在且大小与索引一致
+pub fn find_volumes(patch_file: &Path) -> Result<Option<Vec<Volume>>, BsdiffError> {
+    if patch_file.is_dir() {
+        return volumes_in_dir(patch_file).map(Some);
+    }
+    if !is_volume_index(patch_file) {
+        return Ok(None);
+    }
+    read_volume_index(patch_file).map(Some)
+}
+
+/// 是否为分卷索引文件 (按魔数判断)
+fn is_volume_index(path: &Path) -> bool {
+    let mut magic = [0u8; VOLUME_MAGIC.len()];
+    File::open(path).and_then(|mut file| file.read_exact(&mut magic)).is_ok() && magic == VOLUME_MAGIC
+}
+
+/// 读取分卷索引，分卷路径相对于索引所在目录
+pub fn read_volume_index(index_file: &Path) -> Result<Vec<Volume>, BsdiffError> {
+    let file = File::open(index_file)?;
+    let index_size = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let corrupt = |reason: &str| format!("Corrupt volume index {}: {}", index_file.display(), reason);
+
+    let mut fixed = [0u8; VOLUME_MAGIC.len() + 1 + 4 + 8];
+    reader.read_exact(&mut fixed).map_err(|_| format!("Not a volume index: {}", index_file.display()))?;
+    if fixed[..VOLUME_MAGIC.len()] != VOLUME_MAGIC {
+        return Err(format!("Not a volume index: {}", index_file.display()).into());
+    }
+    let version = fixed[VOLUME_MAGIC.len()];
+    if version > VOLUME_FORMAT_VERSION {
+        return Err(format!("Unsupported volume index format version {} (max {})", version, VOLUME_FORMAT_VERSION).into());
+    }
+    let count = u32::from_le_bytes(fixed[VOLUME_MAGIC.len() + 1..VOLUME_MAGIC.len() + 5].try_into().expect("4-byte count"));
+    let patch_size = u64::from_le_bytes(fixed[VOLUME_MAGIC.len() + 5..].try_into().expect("8-byte size"));
+    if count == 0 || count > MAX_VOLUMES {
+        return Err(corrupt("invalid volume count").into());
+    }
+    // 条目数不能超过索引剩余字节所能容纳的数量
+    if index_size.saturating_sub(fixed.len() as u64) / MIN_ENTRY_SIZE < count as u64 {
+        return Err(corrupt("volume count exceeds the index size").into());
+    }
+
+    let dir = index_file.parent().unwrap_or(Path::new(""));
+    let mut volumes = Vec::with_capacity((count as usize).min(MAX_PREALLOCATED));
+    for _ in 0..count {
+        let mut len = [0u8; 2];
+        reader.read_exact(&mut len).map_err(|_| corrupt("truncated index"))?;
+        let mut name = vec![0u8; u16::from_le_bytes(len) as usize];
+        reader.read_exact(&mut name).map_err(|_| corrupt("truncated index"))?;
+        let mut length = [0u8; 8];
+        reader.read_exact(&mut length).map_err(|_| corrupt("truncated index"))?;
+        let name = String::from_utf8(name).map_err(|_| corrupt("volume name is not UTF-8"))?;
+        // 只接受同目录下的文件名，索引不能指向其他位置
+        let mut components = Path::new(&name).components();
+        if !matches!((components.next(), components.next()), (Some(Component::Normal(_)), None)) {
+            return Err(corrupt(&format!("invalid volume name {:?}", name)).into());
+        }
+        volumes.push(Volume { path: dir.join(&name), length: u64::from_le_bytes(length) });
+    }
+    if volumes.iter().try_fold(0u64, |total, volume| total.checked_add(volume.length)) != Some(patch_size) {
+        return Err(corrupt("volume sizes do not add up to the patch size").into());
+    }
+    for volume in &volumes {
+        let actual = std::fs::metadata(&volume.path)
+            .map_err(|e| format!("Missing volume {}: {}", volume.path.display(), e))?
+            .len();
+        if actual != volume.length {
+            return Err(format!("Volume {} has {} bytes, the index records {}", volume.path.display(), actual, volume.length).into());
+        }
+    }
+    Ok(volumes)
+}
+
+/// 目录中的分卷：有且仅有一个索引时按索引读取，否则取同名、编号从 1 连续的 `<name>.001`…… 文件
+fn volumes_in_dir(dir: &Path) -> Result<Vec<Volume>, BsdiffError> {
+    let mut indexes = Vec::new();
+    let mut numbered: Vec<(String, u32, PathBuf)> = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        if is_volume_index(&path) {
+            indexes.push(path);
+            continue;
+        }
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if let Some((stem, number)) = name.rsplit_once('.') {
+            if number.len() >= 3 && number.bytes().all(|byte| byte.is_ascii_digit()) {
+                if let Ok(number) = number.parse() {
+                    numbered.push((stem.to_string(), number, path.clone()));
+                }
+            }
+        }
+    }
+    match indexes.len() {
+        0 => {}
+        1 => return read_volume_index(&indexes[0]),
+        _ => return Err(format!("More than one volume index in {}", dir.display()).into()),
+    }
+
+    numbered.sort();
+    let stem = numbered.first().map(|(stem, _, _)| stem.clone()).ok_or_else(|| format!("No patch volumes in {}", dir.display()))?;
+    if numbered.iter().any(|(other, _, _)| *other != stem) {
+        return Err(format!("Volumes of more than one patch in {}", dir.display()).into());
+    }
+    let mut volumes = Vec::with_capacity(numbered.len());
+    for (expected, (_, number, path)) in (1..).zip(numbered) {
+        if number != expected {
+            return Err(format!("Missing volume {} of {} in {}", expected, stem, dir.display()).into());
+        }
+        let length = std::fs::metadata(&path)?.len();
+        volumes.push(Volume { path, length });
+    }
+    Ok(volumes)
+}
+
+/// 直接从分卷应用补丁，不合并出完整补丁文件；返回新文件大小
+pub fn patch_from_volumes(
+    old_file: &Path,
+    new_file: &Path,
+    volumes: &[Volume],
+    config: &OptimizationConfig
+) -> Result<u64, BsdiffError> {
+    if config.dry_run {
+        return Err("dryRun is not supported for multi-volume patches".into());
+    }
+    let first = &volumes.first().ok_or("No patch volumes given")?.path;
+    let old_mmap = BsdiffRust::create_single_memory_map(old_file)?;
+    BsdiffRust::prepare_output(new_file, config)?;
+
+    let mut offsets = Vec::with_capacity(volumes.len());
+    let mut total = 0u64;
+    for volume in volumes {
+        offsets.push(total);
+        total += volume.length;
+    }
+    let open_patch = || Ok((VolumeReader { volumes, offsets: &offsets, pos: 0, current: None }, total));
+    BsdiffRust::patch_reader_to_file(&old_mmap, open_patch, first, new_file, None, config)
+}
+
+/// 把分卷当作一个连续的补丁读取，按需打开分卷文件
+struct VolumeReader<'a> {
+    volumes: &'a [Volume],
+    /// 各分卷在完整补丁中的起始偏移
+    offsets: &'a [u64],
+    pos: u64,
+    /// 当前打开的分卷序号及其文件 (已定位到 `pos`)
+    current: Option<(usize, BufReader<File>)>,
+}
+
+impl Read for VolumeReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let index = self.offsets.partition_point(|offset| *offset <= self.pos).saturating_sub(1);
+        let Some(volume) = self.volumes.get(index).filter(|volume| self.pos < self.offsets[index] + volume.length) else {
+            return Ok(0);
+        };
+        if self.current.as_ref().is_none_or(|(current, _)| *current != index) {
+            let mut file = BufReader::with_capacity(64 * 1024, File::open(&volume.path)?);
+            file.seek(SeekFrom::Start(self.pos - self.offsets[index]))?;
+            self.current = Some((index, file));
+        }
+        let (_, file) = self.current.as_mut().expect("current volume opened");
+        let left = self.offsets[index] + volume.length - self.pos;
+        let len = (buf.len() as u64).min(left) as usize;
+        let n = file.read(&mut buf[..len])?;
+        if n == 0 && len > 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("Patch volume truncated: {}", volume.path.display())));
+        }
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for VolumeReader<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let total = self.offsets.last().zip(self.volumes.last()).map_or(0, |(offset, volume)| offset + volume.length);
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => total.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative position"))?;
+        if target != self.pos {
+            self.pos = target;
+            self.current = None;
+        }
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_volumes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        let old: Vec<u8> = (0..200_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut new = old.clone();
+        for i in (0..new.len()).step_by(997) {
+            new[i] ^= 0x5A;
+        }
+        // 不可压缩的新增数据，使补丁跨越多个分卷
+        let mut seed = 1u32;
+        new.extend((0..8000).map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) as u8
+        }));
+        fs::write(path("old"), &old).unwrap();
+        fs::write(path("new"), &new).unwrap();
+        let config = OptimizationConfig { use_fast_temp_dir: false, ..Default::default() };
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &path("full.patch"), &config).unwrap();
+        let full = fs::read(path("full.patch")).unwrap();
+
+        let out = tempfile::tempdir().unwrap();
+        let index = out.path().join("update.patch");
+        let config = OptimizationConfig { volume_size: Some(1000), ..config };
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &index, &config).unwrap();
+        let volumes = find_volumes(&index).unwrap().unwrap();
+        assert!(volumes.len() > 1);
+        assert_eq!(volumes[0].path, out.path().join("update.patch.001"));
+        assert!(volumes.iter().all(|volume| volume.length <= 1000));
+        let joined: Vec<u8> = volumes.iter().flat_map(|volume| fs::read(&volume.path).unwrap()).collect();
+        assert_eq!(joined, full);
+        assert_eq!(find_volumes(&path("full.patch")).unwrap(), None);
+
+        // 按索引应用，或给出分卷所在目录 (有索引或只有编号分卷)
+        BsdiffRust::patch_optimized(&path("old"), &path("out1"), &index, &config).unwrap();
+        assert_eq!(fs::read(path("out1")).unwrap(), new);
+        BsdiffRust::patch_optimized(&path("old"), &path("out2"), out.path(), &config).unwrap();
+        assert_eq!(fs::read(path("out2")).unwrap(), new);
+        fs::remove_file(&index).unwrap();
+        BsdiffRust::patch_optimized(&path("old"), &path("out3"), out.path(), &config).unwrap();
+        assert_eq!(fs::read(path("out3")).unwrap(), new);
+
+        // 缺失分卷在读取补丁前即失败
+        fs::remove_file(out.path().join("update.patch.002")).unwrap();
+        let err = BsdiffRust::patch_optimized(&path("old"), &path("out4"), out.path(), &config).unwrap_err();
+        assert!(err.to_string().contains("Missing volume 2"), "{}", err);
+        assert!(!path("out4").exists());
+
+        // 重新生成更少的分卷时删除多余的旧分卷
+        let config = OptimizationConfig { volume_size: Some(full.len() as u64 / 2 + 1), ..config };
+        BsdiffRust::diff_optimized(&path("old"), &path("new"), &index, &config).unwrap();
+        assert_eq!(find_volumes(out.path()).unwrap().unwrap().len(), 2);
+        assert!(!out.path().join("update.patch.003").exists());
+        fs::write(out.path().join("update.patch.002"), b"short").unwrap();
+        let err = BsdiffRust::patch_optimized(&path("old"), &path("out5"), &index, &config).unwrap_err();
+        assert!(err.to_string().contains("the index records"), "{}", err);
+
+        // 索引声明的分卷数超出文件所能容纳的条目数时直接拒绝
+        let mut forged = fs::read(&index).unwrap();
+        forged[VOLUME_MAGIC.len() + 1..VOLUME_MAGIC.len() + 5].copy_from_slice(&MAX_VOLUMES.to_le_bytes());
+        fs::write(&index, &forged).unwrap();
+        let err = read_volume_index(&index).unwrap_err();
+        assert!(err.to_string().contains("volume count exceeds the index size"), "{}", err);
+    }
+}
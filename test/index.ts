@@ -1,19 +1,123 @@
 import path from 'path'
 import fs from 'fs'
+import os from 'os'
+import http from 'http'
+import crypto from 'crypto'
+import { once } from 'events'
+import { setTimeout } from 'timers/promises'
+import { Readable, Writable } from 'stream'
 import { strict as assert } from 'assert'
 import {
+  PreparedBase,
+  algorithmInfoSync,
+  apply,
+  applyBundle,
+  applyBundleSync,
+  applyBundleToDir,
+  applyBundleToDirSync,
+  applySync,
+  bootSlotSync,
+  buildPatchSetSync,
+  checkFileAccessDetailedSync,
+  checkFileAccessSync,
+  checkPatchPartSync,
+  commitUpdateSync,
+  convertPatchSync,
+  createBundle,
+  createBundleSync,
+  createManifest,
+  createManifestSync,
+  delta,
+  deltaSync,
   diff,
+  diffAsync,
+  diffBatch,
+  diffBatchSync,
+  diffBidirectionalSync,
+  diffBuffer,
+  diffBufferSync,
+  diffBuffersToFile,
+  diffBuffersToFileSync,
+  diffDir,
+  diffDirSync,
+  diffRange,
+  diffRangeSync,
+  diffReport,
+  diffReportSync,
+  diffStream,
   diffSync,
+  diffToShmSync,
+  estimateDelta,
+  estimateDeltaSync,
+  extractPatchSync,
+  getCompressionRatioSync,
+  getDiffStatsSync,
+  getFileSizeSync,
+  getPatchInfo,
+  getPatchInfoFromBuffer,
+  getPatchInfoFromBufferSync,
+  getPatchInfoSync,
+  inspectPatch,
+  inspectPatchSync,
+  joinPatchSync,
   patch,
+  patchAsync,
+  patchBuffer,
+  patchBufferSync,
+  patchBufferToFile,
+  patchBufferToFileSync,
+  patchChain,
+  patchChainSync,
+  patchDir,
+  patchDirSync,
+  patchFanoutSync,
+  patchFileWithPatchBuffer,
+  patchFileWithPatchBufferSync,
+  patchFromChunksSync,
+  patchFromUrl,
+  patchFromUrlSync,
+  patchInPlace,
+  patchInPlaceSync,
+  patchStream,
   patchSync,
+  patchToArrayBuffer,
+  patchToArrayBufferSync,
+  patchToBuffer,
+  patchToBufferSync,
+  patchToFixedRegionSync,
+  planUpdateSync,
+  preflight,
+  preflightSync,
+  prepareUpdateSync,
+  publicKeyFromPrivateSync,
+  recompressPatchSync,
+  rollbackUpdateSync,
+  setLogCallback,
+  signature,
+  signatureSync,
+  signPatch,
+  signPatchSync,
+  slotStateSync,
+  splitPatchSync,
+  stageUpdateSync,
+  trainDictionary,
+  trainDictionarySync,
+  validatePatchSelfSync,
+  verifyManifest,
+  verifyManifestSync,
   verifyPatch,
+  verifyPatchAgainstHash,
+  verifyPatchAgainstHashSync,
+  verifyPatchAsync,
   verifyPatchSync,
-  getPatchInfoSync,
-  getFileSizeSync,
-  checkFileAccessSync,
-  getCompressionRatioSync,
+  type AbSlotsJs,
   type PatchInfoJs,
   type CompressionRatioJs,
+  type DiffOptions,
+  type PatchOptions,
+  type DiffProgressJs,
+  type PatchProgressJs,
+  type ProgressEventJs,
 } from '../index'
 
 describe('bsdiff (rust)', function () {
@@ -150,6 +254,8 @@ describe('bsdiff (rust)', function () {
       assert.strictEqual(typeof info.size, 'number', 'Expected info.size to be a number')
       assert.strictEqual(info.compressed, true, 'Expected info.compressed to be true')
       assert.ok(info.size > 0, 'Expected info.size to be greater than 0')
+      assert.strictEqual(info.container, 'bsrz', 'Expected a headered patch container')
+      assert.strictEqual(info.compression, 'zstd', 'Expected zstd payload compression')
 
       console.log(`📦 补丁信息: 大小=${(info.size / 1024).toFixed(2)} KB, 压缩=${info.compressed}`)
     })
@@ -253,3 +359,967 @@ describe('bsdiff (rust)', function () {
     })
   })
 })
+
+// 生成确定性的伪随机数据 (xorshift)，绑定测试不依赖 resources 中的大文件
+function pseudoRandom(size: number, seed: number): Buffer {
+  const buf = Buffer.alloc(size)
+  let x = seed || 1
+  for (let i = 0; i < size; i++) {
+    x ^= x << 13
+    x ^= x >>> 17
+    x ^= x << 5
+    buf[i] = x & 0xff
+  }
+  return buf
+}
+
+// 在旧数据上做少量改写、插入与删除，得到相似的新数据
+function mutate(old: Buffer, seed: number): Buffer {
+  const extra = pseudoRandom(4096, seed)
+  const changed = Buffer.from(old)
+  extra.copy(changed, 1000, 0, 512)
+  return Buffer.concat([changed.subarray(0, 50000), extra, changed.subarray(60000)])
+}
+
+function sha256(file: string): string {
+  return crypto.createHash('sha256').update(fs.readFileSync(file)).digest('hex')
+}
+
+// assert.throws / assert.rejects 的校验函数：检查 Error 对象的 code 属性
+function withCode(code: string) {
+  return (error: NodeJS.ErrnoException) => error.code === code
+}
+
+// 进度、审计与日志回调经线程安全函数排队，稍后才送达：轮询直到条件满足 (由 mocha 超时兜底)
+async function eventually(check: () => boolean) {
+  while (!check()) {
+    await setTimeout(10)
+  }
+}
+
+// 收集写入内容的 Writable，供 diffStream / patchStream 输出
+function collector(chunks: Buffer[]): Writable {
+  return new Writable({
+    write(chunk, _encoding, callback) {
+      chunks.push(chunk)
+      callback()
+    },
+  })
+}
+
+describe('bsdiff (rust) bindings', function () {
+  let tmp = ''
+  let oldFile = ''
+  let newFile = ''
+  let oldData = Buffer.alloc(0)
+  let newData = Buffer.alloc(0)
+  const file = (name: string) => path.join(tmp, name)
+
+  this.timeout(10000)
+
+  before(() => {
+    tmp = fs.mkdtempSync(path.join(os.tmpdir(), 'bsdiff-test-'))
+    oldData = pseudoRandom(256 * 1024, 1)
+    newData = mutate(oldData, 2)
+    oldFile = file('old.bin')
+    newFile = file('new.bin')
+    fs.writeFileSync(oldFile, oldData)
+    fs.writeFileSync(newFile, newData)
+  })
+
+  after(() => {
+    fs.rmSync(tmp, { recursive: true, force: true })
+  })
+
+  describe('#algorithmInfoSync()', () => {
+    it('should report the compiled versions', () => {
+      const info = algorithmInfoSync()
+      assert.ok(info.algorithmVersion.length > 0)
+      assert.ok(info.zstdVersion.length > 0)
+      assert.strictEqual(info.formatVersion, 2)
+    })
+  })
+
+  describe('Buffer API', () => {
+    it('should diff and patch buffers in memory', async () => {
+      const patchData = diffBufferSync(oldData, newData)
+      assert.ok(patchBufferSync(oldData, patchData).equals(newData))
+
+      const patchAsync = await diffBuffer(oldData, newData, { compressionLevel: 19 })
+      assert.ok((await patchBuffer(oldData, patchAsync)).equals(newData))
+    })
+
+    it('should stream buffer diffs to disk and buffer patches from disk', async () => {
+      const patchFile = file('buffers.patch')
+      const stats = diffBuffersToFileSync(oldData, newData, patchFile, { stats: true })
+      assert.ok(stats.controlBlocks > 0)
+      assert.strictEqual(await diffBuffersToFile(oldData, newData, patchFile), null)
+
+      assert.strictEqual(patchBufferToFileSync(oldData, patchFile, file('buffers.out')), newData.length)
+      assert.ok(fs.readFileSync(file('buffers.out')).equals(newData))
+      assert.strictEqual(await patchBufferToFile(oldData, patchFile, file('buffers-async.out')), newData.length)
+      assert.ok(fs.readFileSync(file('buffers-async.out')).equals(newData))
+    })
+
+    it('should apply a patch held in memory to a file', async () => {
+      const patchData = diffBufferSync(oldData, newData)
+      assert.strictEqual(patchFileWithPatchBufferSync(oldFile, patchData, file('membuf.out')), newData.length)
+      assert.ok(fs.readFileSync(file('membuf.out')).equals(newData))
+      assert.strictEqual(await patchFileWithPatchBuffer(oldFile, patchData, file('membuf-async.out')), newData.length)
+      assert.ok(fs.readFileSync(file('membuf-async.out')).equals(newData))
+    })
+
+    it('should return the patched file as a Buffer or ArrayBuffer', async () => {
+      const patchFile = file('to-buffer.patch')
+      diffSync(oldFile, newFile, patchFile)
+      assert.ok(patchToBufferSync(oldFile, patchFile).equals(newData))
+      assert.ok((await patchToBuffer(oldFile, patchFile)).equals(newData))
+
+      const arrayBuffer = patchToArrayBufferSync(oldFile, patchFile)
+      assert.ok(arrayBuffer instanceof ArrayBuffer)
+      assert.ok(Buffer.from(arrayBuffer).equals(newData))
+      assert.ok(Buffer.from(await patchToArrayBuffer(oldFile, patchFile)).equals(newData))
+    })
+  })
+
+  describe('Diff options', () => {
+    it('should round-trip every compression backend and record it in the header', () => {
+      for (const compression of ['zstd', 'xz', 'brotli', 'gzip', 'none']) {
+        const patchFile = file(`compression-${compression}.patch`)
+        diffSync(oldFile, newFile, patchFile, { compression })
+        assert.strictEqual(getPatchInfoSync(patchFile).compression, compression)
+        assert.ok(patchToBufferSync(oldFile, patchFile).equals(newData), `${compression} round trip failed`)
+      }
+    })
+
+    it('should support classic BSDIFF40 and VCDIFF formats', () => {
+      for (const format of ['bsdiff40', 'vcdiff']) {
+        const patchFile = file(`format-${format}.patch`)
+        diffSync(oldFile, newFile, patchFile, { format })
+        assert.strictEqual(getPatchInfoSync(patchFile).container, format)
+        assert.ok(patchToBufferSync(oldFile, patchFile).equals(newData), `${format} round trip failed`)
+      }
+
+      // 两种格式之间转换无需源文件
+      convertPatchSync(file('format-bsdiff40.patch'), file('converted.patch'), 'zstd')
+      assert.ok(patchToBufferSync(oldFile, file('converted.patch')).equals(newData))
+    })
+
+    it('should report the compression level picked by "auto"', () => {
+      const stats = diffSync(oldFile, newFile, file('auto.patch'), { compressionLevel: 'auto', stats: true })
+      assert.strictEqual(typeof stats.compressionLevel, 'number')
+      assert.ok(patchToBufferSync(oldFile, file('auto.patch')).equals(newData))
+    })
+
+    it('should reject invalid options', () => {
+      assert.throws(
+        () => diffSync(oldFile, newFile, file('invalid.patch'), { compressionLevel: 99 }),
+        /compression level/,
+      )
+      assert.throws(() => diffSync(oldFile, newFile, file('invalid.patch'), { mode: 'bogus' }), /Unknown diff mode/)
+      assert.throws(() => diffSync(oldFile, newFile, file('invalid.patch'), { threads: 0 }), /threads/)
+    })
+
+    it('should embed metadata and the chosen hash algorithm', () => {
+      const patchFile = file('metadata.patch')
+      diffSync(oldFile, newFile, patchFile, { metadata: { appVersion: '1.2.3' }, hashAlgorithm: 'blake3' })
+      const info: PatchInfoJs = getPatchInfoSync(patchFile)
+      assert.deepStrictEqual(info.metadata, { appVersion: '1.2.3' })
+      assert.strictEqual(info.hashAlgorithm, 'blake3')
+      assert.strictEqual(info.sourceSize, oldData.length)
+      assert.strictEqual(info.targetSize, newData.length)
+      assert.ok(patchToBufferSync(oldFile, patchFile).equals(newData))
+    })
+
+    it('should produce byte-identical patches in deterministic mode', () => {
+      diffSync(oldFile, newFile, file('det-a.patch'), { deterministic: true, threads: 4 })
+      diffSync(oldFile, newFile, file('det-b.patch'), { deterministic: true, threads: 1 })
+      assert.ok(fs.readFileSync(file('det-a.patch')).equals(fs.readFileSync(file('det-b.patch'))))
+    })
+
+    it('should round-trip windowed, block-level, append and transform modes', () => {
+      const appended = Buffer.concat([oldData, pseudoRandom(8192, 3)])
+      fs.writeFileSync(file('appended.bin'), appended)
+      const cases: Array<[string, string, Buffer, DiffOptions]> = [
+        ['window', newFile, newData, { windowSize: 64 * 1024, threads: 2 }],
+        ['blockdelta', newFile, newData, { algorithm: 'blockdelta' }],
+        ['append', file('appended.bin'), appended, { mode: 'append' }],
+        ['exe', newFile, newData, { exeTransform: true }],
+        ['archive', newFile, newData, { archiveTransform: true }],
+      ]
+      for (const [name, target, expected, options] of cases) {
+        const patchFile = file(`mode-${name}.patch`)
+        diffSync(oldFile, target, patchFile, options)
+        assert.ok(patchToBufferSync(oldFile, patchFile).equals(expected), `${name} round trip failed`)
+      }
+    })
+
+    it('should fall back to a full-file patch when the delta is mostly noise', () => {
+      // 旧文件是新文件加上大量随机改动：差分数据全是噪声，远不如直接压缩新文件
+      const text = Buffer.from('the quick brown fox jumps over the lazy dog '.repeat(1500))
+      const noisy = Buffer.from(text)
+      const noise = pseudoRandom(noisy.length, 4)
+      for (let i = 0; i < noisy.length; i += 5) {
+        noisy[i] = noise[i]
+      }
+      fs.writeFileSync(file('noisy.bin'), noisy)
+      fs.writeFileSync(file('text.bin'), text)
+      const stats = diffSync(file('noisy.bin'), file('text.bin'), file('full.patch'), {
+        fallbackToFull: 0.05,
+        stats: true,
+      })
+      assert.strictEqual(stats.fullFile, true)
+      assert.strictEqual(getPatchInfoSync(file('full.patch')).fullFile, true)
+      assert.ok(patchToBufferSync(file('noisy.bin'), file('full.patch')).equals(text))
+    })
+
+    it('should diff against an absent old file', () => {
+      diffSync(null, newFile, file('from-empty.patch'))
+      assert.strictEqual(patchSync(null, file('from-empty.out'), file('from-empty.patch')), newData.length)
+      assert.ok(fs.readFileSync(file('from-empty.out')).equals(newData))
+    })
+
+    it('should reuse cached patches and honour tempDir and retry', () => {
+      const cacheDir = file('cache')
+      const tempDir = file('fast-temp')
+      fs.mkdirSync(tempDir)
+      const options = { cacheDir, tempDir, retry: { attempts: 2, delayMs: 10 } }
+      diffSync(oldFile, newFile, file('cached-a.patch'), options)
+      diffSync(oldFile, newFile, file('cached-b.patch'), options)
+      assert.ok(fs.readdirSync(cacheDir).length > 0)
+      assert.ok(fs.readFileSync(file('cached-a.patch')).equals(fs.readFileSync(file('cached-b.patch'))))
+      assert.deepStrictEqual(fs.readdirSync(tempDir), [])
+    })
+  })
+
+  describe('Progress callbacks', () => {
+    it('should report diff progress', async () => {
+      const progress: DiffProgressJs[] = []
+      const events: ProgressEventJs[] = []
+      await diff(oldFile, newFile, file('progress.patch'), {
+        onProgress: (p) => progress.push(p),
+        onProgressEvent: (e) => events.push(e),
+      })
+      await eventually(() => progress.length > 0 && events.some((e) => e.phase === 'write'))
+      assert.ok(progress.every((p) => p.total === newData.length))
+      assert.ok(events.some((e) => e.phase === 'apply'))
+    })
+
+    it('should report patch progress by control block and phase', async () => {
+      diffSync(oldFile, newFile, file('progress.patch'))
+      const progress: PatchProgressJs[] = []
+      const events: ProgressEventJs[] = []
+      await patch(oldFile, file('progress.out'), file('progress.patch'), {
+        onProgress: (p) => progress.push(p),
+        onProgressEvent: (e) => events.push(e),
+      })
+      await eventually(() => progress.some((p) => p.blocksApplied === p.totalBlocks))
+      await eventually(() => events.some((e) => e.phase === 'write'))
+      assert.ok(events.some((e) => e.phase === 'decode'))
+    })
+  })
+
+  describe('AbortSignal', () => {
+    it('should reject with ERR_CANCELLED when the signal is already aborted', async () => {
+      const controller = new AbortController()
+      controller.abort()
+      await assert.rejects(
+        diff(oldFile, newFile, file('aborted.patch'), null, controller.signal),
+        withCode('ERR_CANCELLED'),
+      )
+
+      diffSync(oldFile, newFile, file('abort.patch'))
+      await assert.rejects(
+        patch(oldFile, file('aborted.out'), file('abort.patch'), null, controller.signal),
+        withCode('ERR_CANCELLED'),
+      )
+      assert.ok(!fs.existsSync(file('aborted.out')))
+    })
+
+    it('should accept the signal as the fourth verifyPatch argument', async () => {
+      diffSync(oldFile, newFile, file('legacy.patch'))
+      await assert.rejects(verifyPatch(oldFile, newFile, file('legacy.patch'), AbortSignal.abort()), withCode('ERR_CANCELLED'))
+      assert.strictEqual(await verifyPatch(oldFile, newFile, file('legacy.patch'), new AbortController().signal), true)
+    })
+
+    it('should keep the deprecated async aliases', async () => {
+      assert.strictEqual(await diffAsync(oldFile, newFile, file('alias.patch')), null)
+      assert.strictEqual(await patchAsync(oldFile, file('alias.out'), file('alias.patch')), newData.length)
+      assert.strictEqual(await verifyPatchAsync(oldFile, newFile, file('alias.patch'), { strict: true }), true)
+    })
+  })
+
+  describe('Streams', () => {
+    it('should diff streams into a Writable', async () => {
+      const chunks: Buffer[] = []
+      await diffStream(Readable.from([oldData]), newData, collector(chunks))
+      assert.ok(patchBufferSync(oldData, Buffer.concat(chunks)).equals(newData))
+    })
+
+    it('should apply a patch from a stream to a Writable', async () => {
+      diffSync(oldFile, newFile, file('stream.patch'))
+      const chunks: Buffer[] = []
+      await patchStream(oldData, fs.createReadStream(file('stream.patch')), collector(chunks))
+      assert.ok(Buffer.concat(chunks).equals(newData))
+    })
+  })
+
+  describe('Paths, descriptors and FileHandles', () => {
+    it('should accept Buffer paths', () => {
+      const patchFile = Buffer.from(file('buffer-path.patch'))
+      diffSync(Buffer.from(oldFile), Buffer.from(newFile), patchFile)
+      assert.strictEqual(getFileSizeSync(patchFile), fs.statSync(file('buffer-path.patch')).size)
+      assert.ok(patchToBufferSync(Buffer.from(oldFile), patchFile).equals(newData))
+    })
+
+    it('should write to file descriptors and FileHandles', async function () {
+      if (process.platform === 'win32') {
+        this.skip()
+      }
+      const fd = fs.openSync(file('fd.patch'), 'w')
+      try {
+        diffSync(oldFile, newFile, fd)
+      } finally {
+        fs.closeSync(fd)
+      }
+      assert.ok(patchToBufferSync(oldFile, file('fd.patch')).equals(newData))
+
+      const handle = await fs.promises.open(file('handle.out'), 'w')
+      try {
+        await patch(oldFile, handle, file('fd.patch'))
+      } finally {
+        await handle.close()
+      }
+      assert.ok(fs.readFileSync(file('handle.out')).equals(newData))
+    })
+
+    it('should describe why a file is not accessible', () => {
+      const info = checkFileAccessDetailedSync(oldFile)
+      assert.deepStrictEqual(
+        { exists: info.exists, isFile: info.isFile, readable: info.readable },
+        { exists: true, isFile: true, readable: true },
+      )
+      assert.strictEqual(checkFileAccessDetailedSync(file('missing.bin')).exists, false)
+      assert.strictEqual(checkFileAccessDetailedSync(tmp).isFile, false)
+      assert.throws(() => checkFileAccessSync(file('missing.bin')))
+    })
+  })
+
+  describe('Error codes', () => {
+    it('should set code on errors thrown by the bindings', async () => {
+      assert.throws(() => diffSync(file('missing.bin'), newFile, file('err.patch')), withCode('ERR_FILE_NOT_FOUND'))
+      await assert.rejects(patch(oldFile, file('err.out'), file('missing.patch')), withCode('ERR_FILE_NOT_FOUND'))
+
+      // 补丁头记录了旧文件摘要，换一个旧文件应用时拒绝
+      diffSync(oldFile, newFile, file('err.patch'))
+      assert.throws(() => patchSync(newFile, file('err.out'), file('err.patch')), withCode('ERR_BASE_MISMATCH'))
+
+      fs.writeFileSync(file('corrupt.patch'), pseudoRandom(1024, 5))
+      assert.throws(() => patchSync(oldFile, file('err.out'), file('corrupt.patch')), withCode('ERR_CORRUPT_PATCH'))
+    })
+  })
+
+  describe('Output handling', () => {
+    it('should honour overwrite policies', () => {
+      const patchFile = file('overwrite.patch')
+      fs.writeFileSync(patchFile, 'existing')
+      assert.throws(() => diffSync(oldFile, newFile, patchFile, { overwrite: 'error' }), withCode('ERR_OUTPUT_EXISTS'))
+      diffSync(oldFile, newFile, patchFile, { overwrite: 'backup' })
+      assert.strictEqual(fs.readFileSync(`${patchFile}.bak`, 'utf8'), 'existing')
+
+      fs.writeFileSync(file('overwrite.out'), 'existing')
+      assert.throws(
+        () => patchSync(oldFile, file('overwrite.out'), patchFile, { overwrite: 'error' }),
+        withCode('ERR_OUTPUT_EXISTS'),
+      )
+    })
+
+    it('should simulate a patch without writing in dry-run mode', () => {
+      diffSync(oldFile, newFile, file('dry.patch'))
+      assert.strictEqual(patchSync(oldFile, file('dry.out'), file('dry.patch'), { dryRun: true }), newData.length)
+      assert.ok(!fs.existsSync(file('dry.out')))
+    })
+
+    it('should enforce maxOutputSize', () => {
+      diffSync(oldFile, newFile, file('limit.patch'))
+      assert.throws(
+        () => patchSync(oldFile, file('limit.out'), file('limit.patch'), { maxOutputSize: 1024 }),
+        withCode('ERR_LIMIT_EXCEEDED'),
+      )
+    })
+
+    it('should write through mmap, sparse, durable and rate-limited outputs', () => {
+      diffSync(oldFile, newFile, file('write-modes.patch'))
+      const cases: PatchOptions[] = [
+        { mmapOutput: true },
+        { sparse: true },
+        { durable: true },
+        { maxWriteBytesPerSec: 64 * 1024 * 1024 },
+      ]
+      cases.forEach((options, i) => {
+        patchSync(oldFile, file(`write-mode-${i}.out`), file('write-modes.patch'), options)
+        assert.ok(fs.readFileSync(file(`write-mode-${i}.out`)).equals(newData))
+      })
+    })
+
+    it('should write audit events to a log file and a callback', async () => {
+      diffSync(oldFile, newFile, file('audit.patch'))
+      const events: string[] = []
+      patchSync(oldFile, file('audit.out'), file('audit.patch'), {
+        logFile: file('audit.log'),
+        onAuditEvent: (event) => events.push(event),
+      })
+      const lines = fs.readFileSync(file('audit.log'), 'utf8').trim().split('\n')
+      assert.ok(lines.length > 0)
+      await eventually(() => events.length === lines.length)
+      assert.deepStrictEqual(events, lines)
+      lines.forEach((line) => JSON.parse(line))
+    })
+  })
+
+  describe('Patch inspection', () => {
+    let patchFile = ''
+
+    before(() => {
+      patchFile = file('inspect.patch')
+      diffSync(oldFile, newFile, patchFile)
+    })
+
+    it('should read patch info from files and buffers', async () => {
+      const info = await getPatchInfo(patchFile)
+      assert.strictEqual(info.container, 'bsrz')
+      assert.strictEqual(info.sourceSha256, sha256(oldFile))
+      assert.strictEqual(info.targetSha256, sha256(newFile))
+      assert.ok((info.uncompressedSize ?? 0) > 0)
+
+      const patchData = fs.readFileSync(patchFile)
+      assert.deepStrictEqual(getPatchInfoFromBufferSync(patchData), info)
+      assert.deepStrictEqual(await getPatchInfoFromBuffer(patchData), info)
+    })
+
+    it('should list control entries and diff statistics', async () => {
+      const entries = inspectPatchSync(patchFile)
+      const total = entries.reduce((sum, e) => sum + e.copyLength + e.extraLength, 0)
+      assert.strictEqual(total, newData.length)
+      assert.deepStrictEqual(await inspectPatch(patchFile, { offset: 1, limit: 1 }), entries.slice(1, 2))
+
+      const stats = getDiffStatsSync(patchFile)
+      assert.strictEqual(stats.controlBlocks, entries.length)
+      assert.strictEqual(stats.copiedBytes + stats.literalBytes, newData.length)
+    })
+
+    it('should validate a patch without the old file', () => {
+      assert.deepStrictEqual(validatePatchSelfSync(patchFile), { ok: true })
+      const truncated = file('truncated.patch')
+      fs.writeFileSync(truncated, fs.readFileSync(patchFile).subarray(0, 200))
+      const check = validatePatchSelfSync(truncated)
+      assert.strictEqual(check.ok, false)
+      assert.ok(check.reason)
+    })
+
+    it('should verify against the new file or its hash', async () => {
+      assert.strictEqual(await verifyPatch(oldFile, newFile, patchFile, { strict: true }), true)
+      assert.strictEqual(verifyPatchSync(oldFile, oldFile, patchFile), false)
+      assert.strictEqual(verifyPatchAgainstHashSync(oldFile, patchFile, sha256(newFile)), true)
+      assert.strictEqual(await verifyPatchAgainstHash(oldFile, patchFile, sha256(oldFile)), false)
+    })
+
+    it('should compute the compression ratio', () => {
+      const ratio: CompressionRatioJs = getCompressionRatioSync(oldFile, newFile, patchFile)
+      assert.strictEqual(ratio.newSize, newData.length)
+      assert.ok(ratio.ratio > 0 && ratio.ratio < 100)
+    })
+  })
+
+  describe('Reports, estimates and preflight', () => {
+    it('should report changed regions without writing a patch', async () => {
+      const report = diffReportSync(oldFile, newFile)
+      assert.strictEqual(report.oldSize, oldData.length)
+      assert.strictEqual(report.newSize, newData.length)
+      assert.ok(report.insertedBytes > 0)
+      assert.ok(report.regions.length > 0 && report.regions.length === report.regionCount)
+      assert.deepStrictEqual(await diffReport(oldFile, newFile), report)
+    })
+
+    it('should estimate similarity', async () => {
+      const estimate = estimateDeltaSync(oldFile, newFile)
+      assert.ok(estimate.score > 0.5 && estimate.score <= 1)
+      assert.ok(estimate.minPatchSize <= estimate.maxPatchSize)
+      assert.deepStrictEqual(await estimateDelta(oldFile, newFile), estimate)
+    })
+
+    it('should preflight temp space and memory', async () => {
+      const result = preflightSync(oldFile, newFile, file('preflight.patch'))
+      assert.strictEqual(result.destinationDir, tmp)
+      assert.strictEqual(result.mmap, true)
+      assert.strictEqual(result.sufficientSpace, true)
+      assert.ok(result.estimatedPeakMemory > 0)
+      assert.strictEqual((await preflight(oldFile, newFile, file('preflight.patch'))).destinationDir, tmp)
+    })
+  })
+
+  describe('Range diff', () => {
+    it('should diff a region of each file', async () => {
+      const stats = diffRangeSync(oldFile, 4096, 65536, newFile, 4096, 65536, file('range.patch'), { stats: true })
+      assert.ok(stats.controlBlocks > 0)
+      assert.ok(patchBufferSync(oldData.subarray(4096, 4096 + 65536), fs.readFileSync(file('range.patch'))).equals(
+        newData.subarray(4096, 4096 + 65536),
+      ))
+
+      await diffRange(oldFile, 0, 1024, newFile, 0, 2048, file('range-async.patch'))
+      assert.ok(patchBufferSync(oldData.subarray(0, 1024), fs.readFileSync(file('range-async.patch'))).equals(
+        newData.subarray(0, 2048),
+      ))
+    })
+  })
+
+  describe('Signing and encryption', () => {
+    it('should verify detached signatures before applying', async () => {
+      const patchFile = file('signed.patch')
+      diffSync(oldFile, newFile, patchFile)
+      const privateKey = crypto.randomBytes(32)
+      const publicKey = publicKeyFromPrivateSync(privateKey)
+      const sig = signPatchSync(patchFile, privateKey)
+      assert.strictEqual(sig.length, 64)
+      assert.ok((await signPatch(fs.readFileSync(patchFile), privateKey)).equals(sig))
+
+      patchSync(oldFile, file('signed.out'), patchFile, { publicKey, signature: sig })
+      assert.ok(fs.readFileSync(file('signed.out')).equals(newData))
+
+      const forged = Buffer.from(sig)
+      forged[0] ^= 0xff
+      assert.throws(() => patchSync(oldFile, file('forged.out'), patchFile, { publicKey, signature: forged }))
+      assert.throws(() => patchSync(oldFile, file('forged.out'), patchFile, { publicKey }), /provided together/)
+    })
+
+    it('should encrypt patches at rest', async () => {
+      const key = crypto.randomBytes(32)
+      const patchFile = file('encrypted.patch')
+      diffSync(oldFile, newFile, patchFile, { encrypt: { key } })
+      assert.strictEqual(getPatchInfoSync(patchFile).container, 'encrypted')
+      assert.ok((await patchToBuffer(oldFile, patchFile, { decryptionKey: key })).equals(newData))
+      assert.throws(
+        () => patchSync(oldFile, file('encrypted.out'), patchFile, { decryptionKey: crypto.randomBytes(32) }),
+        withCode('ERR_DECRYPTION_FAILED'),
+      )
+    })
+  })
+
+  describe('Dictionaries', () => {
+    it('should train a dictionary and require it when patching', async () => {
+      const samples: string[] = []
+      for (let i = 0; i < 32; i++) {
+        const oldSample = file(`sample-old-${i}.bin`)
+        const newSample = file(`sample-new-${i}.bin`)
+        const base = pseudoRandom(16 * 1024, 100 + i)
+        fs.writeFileSync(oldSample, base)
+        fs.writeFileSync(newSample, mutate(base, 7))
+        const sample = file(`sample-${i}.patch`)
+        diffSync(oldSample, newSample, sample, { compression: 'none' })
+        samples.push(sample)
+      }
+      const dictionary = trainDictionarySync(samples, 16 * 1024)
+      assert.ok(dictionary.length > 0)
+      assert.ok((await trainDictionary(samples, 16 * 1024)).length > 0)
+
+      const patchFile = file('dictionary.patch')
+      diffSync(oldFile, newFile, patchFile, { dictionary })
+      assert.strictEqual(typeof getPatchInfoSync(patchFile).dictionaryId, 'number')
+      assert.ok(patchToBufferSync(oldFile, patchFile, { dictionary }).equals(newData))
+      assert.throws(() => patchSync(oldFile, file('dictionary.out'), patchFile), withCode('ERR_DICTIONARY_MISMATCH'))
+    })
+  })
+
+  describe('Patch chains, sets and conversions', () => {
+    it('should apply a chain of patches', async () => {
+      const v3 = mutate(newData, 8)
+      fs.writeFileSync(file('v3.bin'), v3)
+      diffSync(oldFile, newFile, file('chain-1.patch'))
+      diffSync(newFile, file('v3.bin'), file('chain-2.patch'))
+      const patches = [file('chain-1.patch'), file('chain-2.patch')]
+
+      assert.strictEqual(patchChainSync(oldFile, patches, file('chain.out')), v3.length)
+      assert.ok(fs.readFileSync(file('chain.out')).equals(v3))
+      assert.strictEqual(await patchChain(oldFile, patches, file('chain-async.out')), v3.length)
+    })
+
+    it('should build a patch set and extract a patch by version hash', () => {
+      diffSync(oldFile, newFile, file('set-entry.patch'))
+      const from = sha256(oldFile)
+      const to = sha256(newFile)
+      buildPatchSetSync([{ from, to, patch: file('set-entry.patch') }], file('versions.set'))
+      extractPatchSync(file('versions.set'), from, to, file('extracted.patch'))
+      assert.ok(fs.readFileSync(file('extracted.patch')).equals(fs.readFileSync(file('set-entry.patch'))))
+      assert.throws(() => extractPatchSync(file('versions.set'), to, from, file('missing.patch')))
+    })
+
+    it('should generate forward and reverse patches', () => {
+      diffBidirectionalSync(oldFile, newFile, file('forward.patch'), file('reverse.patch'))
+      assert.ok(patchToBufferSync(oldFile, file('forward.patch')).equals(newData))
+      assert.ok(patchToBufferSync(newFile, file('reverse.patch')).equals(oldData))
+    })
+
+    it('should recompress a patch at another level', () => {
+      diffSync(oldFile, newFile, file('level-1.patch'), { compressionLevel: 1 })
+      recompressPatchSync(file('level-1.patch'), file('level-19.patch'), 19)
+      assert.ok(patchToBufferSync(oldFile, file('level-19.patch')).equals(newData))
+    })
+  })
+
+  describe('Split and multi-volume patches', () => {
+    it('should split, check and join patch parts', () => {
+      const patchFile = file('split.patch')
+      diffSync(oldFile, newFile, patchFile)
+      const parts = splitPatchSync(patchFile, 1024)
+      assert.ok(parts.length > 1)
+      const first = checkPatchPartSync(parts[0])
+      assert.strictEqual(first.index, 0)
+      assert.strictEqual(first.count, parts.length)
+      assert.strictEqual(first.patchSize, fs.statSync(patchFile).size)
+
+      joinPatchSync([...parts].reverse(), file('joined.patch'))
+      assert.ok(fs.readFileSync(file('joined.patch')).equals(fs.readFileSync(patchFile)))
+      patchFromChunksSync(oldFile, file('chunks.out'), parts)
+      assert.ok(fs.readFileSync(file('chunks.out')).equals(newData))
+    })
+
+    it('should write size-capped volumes and patch from the index', () => {
+      const index = file('volumes.patch')
+      diffSync(oldFile, newFile, index, { volumeSize: 4096 })
+      assert.ok(fs.existsSync(`${index}.002`))
+      assert.ok(fs.statSync(`${index}.001`).size <= 4096)
+      patchSync(oldFile, file('volumes.out'), index)
+      assert.ok(fs.readFileSync(file('volumes.out')).equals(newData))
+    })
+  })
+
+  describe('In-place, fan-out and fixed-region patching', () => {
+    it('should patch a file in place', async () => {
+      diffSync(oldFile, newFile, file('in-place.patch'))
+      fs.copyFileSync(oldFile, file('in-place.bin'))
+      assert.strictEqual(patchInPlaceSync(file('in-place.bin'), file('in-place.patch')), newData.length)
+      assert.ok(fs.readFileSync(file('in-place.bin')).equals(newData))
+
+      fs.copyFileSync(oldFile, file('in-place-async.bin'))
+      assert.strictEqual(await patchInPlace(file('in-place-async.bin'), file('in-place.patch')), newData.length)
+      assert.ok(fs.readFileSync(file('in-place-async.bin')).equals(newData))
+    })
+
+    it('should apply several patches to one old file', () => {
+      const other = mutate(oldData, 9)
+      fs.writeFileSync(file('fanout-new.bin'), other)
+      diffSync(oldFile, newFile, file('fanout-1.patch'))
+      diffSync(oldFile, file('fanout-new.bin'), file('fanout-2.patch'))
+      patchFanoutSync(oldFile, [
+        { patch: file('fanout-1.patch'), out: file('fanout-1.out') },
+        { patch: file('fanout-2.patch'), out: file('fanout-2.out') },
+      ])
+      assert.ok(fs.readFileSync(file('fanout-1.out')).equals(newData))
+      assert.ok(fs.readFileSync(file('fanout-2.out')).equals(other))
+    })
+
+    it('should patch into a fixed-size region', () => {
+      diffSync(oldFile, newFile, file('region.patch'))
+      const regionSize = newData.length + 4096
+      // zeroPad 时剩余空间补零，写入的字节数即区域大小
+      assert.strictEqual(
+        patchToFixedRegionSync(oldFile, file('region.patch'), file('region.out'), regionSize, true),
+        regionSize,
+      )
+      const region = fs.readFileSync(file('region.out'))
+      assert.strictEqual(region.length, regionSize)
+      assert.ok(region.subarray(0, newData.length).equals(newData))
+      assert.throws(() => patchToFixedRegionSync(oldFile, file('region.patch'), file('small.out'), 1024))
+    })
+
+    it('should reuse a prepared old file for several diffs', () => {
+      const base = new PreparedBase(oldFile)
+      base.add(newFile, file('prepared.patch'))
+      assert.ok(patchToBufferSync(oldFile, file('prepared.patch')).equals(newData))
+    })
+  })
+
+  describe('Signature and delta (librsync workflow)', () => {
+    it('should generate a delta from a signature and apply it in place', async () => {
+      signatureSync(oldFile, file('old.sig'))
+      deltaSync(file('old.sig'), newFile, file('delta.patch'))
+      fs.copyFileSync(oldFile, file('delta-target.bin'))
+      assert.strictEqual(applySync(file('delta-target.bin'), file('delta.patch')), newData.length)
+      assert.ok(fs.readFileSync(file('delta-target.bin')).equals(newData))
+
+      await signature(oldFile, file('old-async.sig'))
+      await delta(file('old-async.sig'), newFile, file('delta-async.patch'))
+      fs.copyFileSync(oldFile, file('delta-target-async.bin'))
+      assert.strictEqual(await apply(file('delta-target-async.bin'), file('delta-async.patch')), newData.length)
+      assert.ok(fs.readFileSync(file('delta-target-async.bin')).equals(newData))
+    })
+  })
+
+  describe('Batch diff', () => {
+    it('should report per-item results in input order', async () => {
+      const pairs = [
+        { old: oldFile, new: newFile, patch: file('batch-1.patch') },
+        { old: file('missing.bin'), new: newFile, patch: file('batch-2.patch') },
+      ]
+      const results = diffBatchSync(pairs, null, 2)
+      assert.deepStrictEqual(
+        results.map((r) => r.ok),
+        [true, false],
+      )
+      assert.strictEqual(results[0].patchSize, fs.statSync(file('batch-1.patch')).size)
+      assert.ok(results[1].error)
+      assert.deepStrictEqual(
+        (await diffBatch(pairs)).map((r) => r.ok),
+        [true, false],
+      )
+    })
+  })
+
+  describe('Directories and bundles', () => {
+    let oldDir = ''
+    let newDir = ''
+
+    before(() => {
+      oldDir = file('dir-old')
+      newDir = file('dir-new')
+      fs.mkdirSync(path.join(oldDir, 'sub'), { recursive: true })
+      fs.mkdirSync(path.join(newDir, 'sub'), { recursive: true })
+      fs.writeFileSync(path.join(oldDir, 'app.bin'), oldData)
+      fs.writeFileSync(path.join(newDir, 'app.bin'), newData)
+      fs.writeFileSync(path.join(oldDir, 'same.txt'), 'unchanged')
+      fs.writeFileSync(path.join(newDir, 'same.txt'), 'unchanged')
+      fs.writeFileSync(path.join(oldDir, 'sub', 'moved.bin'), pseudoRandom(8192, 10))
+      fs.writeFileSync(path.join(newDir, 'moved.bin'), pseudoRandom(8192, 10))
+      fs.writeFileSync(path.join(oldDir, 'removed.txt'), 'removed')
+      fs.writeFileSync(path.join(newDir, 'sub', 'added.txt'), 'added')
+    })
+
+    it('should diff and patch directory trees', async () => {
+      const summary = diffDirSync(oldDir, newDir, file('dir.bundle'))
+      assert.deepStrictEqual(summary, { added: 1, deleted: 1, patched: 1, renamed: 1, unchanged: 1 })
+      assert.deepStrictEqual(await diffDir(oldDir, newDir, file('dir-async.bundle')), summary)
+
+      assert.deepStrictEqual(patchDirSync(oldDir, file('dir-out'), file('dir.bundle'), { linkMode: 'copy' }), summary)
+      assert.strictEqual(createManifestSync(file('dir-out')), createManifestSync(newDir))
+      assert.deepStrictEqual(await patchDir(oldDir, file('dir-out-async'), file('dir.bundle')), summary)
+
+      // 目标目录必须不存在
+      assert.throws(() => patchDirSync(oldDir, file('dir-out'), file('dir.bundle')))
+    })
+
+    it('should apply a directory bundle side by side', async () => {
+      diffDirSync(oldDir, newDir, file('side.bundle'))
+      applyBundleToDirSync(file('side.bundle'), oldDir, file('side-out'))
+      assert.strictEqual(createManifestSync(file('side-out')), createManifestSync(newDir))
+      await applyBundleToDir(file('side.bundle'), oldDir, file('side-out-async'))
+      assert.strictEqual(createManifestSync(file('side-out-async')), createManifestSync(newDir))
+      assert.ok(fs.existsSync(path.join(oldDir, 'removed.txt')))
+    })
+
+    it('should resolve paths against baseDir and refuse to escape it', () => {
+      diffDirSync(oldDir, newDir, file('sandbox.bundle'))
+      patchDirSync('dir-old', 'sandbox-out', 'sandbox.bundle', { baseDir: tmp })
+      assert.strictEqual(createManifestSync(file('sandbox-out')), createManifestSync(newDir))
+      assert.throws(() => patchDirSync('dir-old', '../escaped', 'sandbox.bundle', { baseDir: tmp }))
+      assert.ok(!fs.existsSync(path.join(tmp, '..', 'escaped')))
+    })
+
+    it('should preserve symlinks and permissions', function () {
+      if (process.platform === 'win32') {
+        this.skip()
+      }
+      const metaOld = file('meta-old')
+      const metaNew = file('meta-new')
+      fs.mkdirSync(metaOld)
+      fs.mkdirSync(metaNew)
+      fs.writeFileSync(path.join(metaNew, 'run.sh'), '#!/bin/sh\n')
+      fs.chmodSync(path.join(metaNew, 'run.sh'), 0o755)
+      fs.symlinkSync('run.sh', path.join(metaNew, 'link'))
+      diffDirSync(metaOld, metaNew, file('meta.bundle'), { preserveMetadata: true })
+      patchDirSync(metaOld, file('meta-out'), file('meta.bundle'))
+      assert.strictEqual(fs.readlinkSync(file('meta-out/link')), 'run.sh')
+      assert.strictEqual(fs.statSync(file('meta-out/run.sh')).mode & 0o777, 0o755)
+    })
+
+    it('should bundle several file patches', async () => {
+      const entries = [
+        { old: oldFile, new: newFile, name: 'a.bin' },
+        { old: path.join(oldDir, 'same.txt'), new: path.join(newDir, 'same.txt'), name: 'b.txt' },
+      ]
+      const created = createBundleSync(entries, file('files.bundle'))
+      assert.deepStrictEqual(
+        created.map((f) => f.name),
+        ['a.bin', 'b.txt'],
+      )
+      assert.strictEqual((await createBundle(entries, file('files-async.bundle'))).length, 2)
+
+      const mapping = {
+        'a.bin': { old: oldFile, new: file('bundle-a.out') },
+        'b.txt': { old: path.join(oldDir, 'same.txt'), new: file('bundle-b.out') },
+      }
+      const applied = applyBundleSync(file('files.bundle'), mapping)
+      assert.deepStrictEqual(applied, [
+        { name: 'a.bin', size: newData.length },
+        { name: 'b.txt', size: 9 },
+      ])
+      assert.ok(fs.readFileSync(file('bundle-a.out')).equals(newData))
+      assert.strictEqual((await applyBundle(file('files-async.bundle'), mapping)).length, 2)
+      assert.throws(() => applyBundleSync(file('files.bundle'), { 'a.bin': mapping['a.bin'] }))
+    })
+  })
+
+  describe('Manifests and update planning', () => {
+    it('should create and verify manifests', async () => {
+      const dir = file('manifest-dir')
+      fs.mkdirSync(dir)
+      fs.writeFileSync(path.join(dir, 'a.txt'), 'a')
+      fs.writeFileSync(path.join(dir, 'b.txt'), 'b')
+      const manifest = createManifestSync(dir)
+      assert.strictEqual(await createManifest(dir), manifest)
+      assert.deepStrictEqual(verifyManifestSync(dir, manifest), [])
+
+      fs.writeFileSync(path.join(dir, 'a.txt'), 'changed')
+      fs.unlinkSync(path.join(dir, 'b.txt'))
+      const mismatches = await verifyManifest(dir, manifest)
+      assert.deepStrictEqual(
+        mismatches.map((m) => [m.path, m.reason]),
+        [
+          ['a.txt', 'modified'],
+          ['b.txt', 'missing'],
+        ],
+      )
+    })
+
+    it('should plan an update from two manifests', () => {
+      const v1 = file('plan-v1')
+      const v2 = file('plan-v2')
+      fs.mkdirSync(v1)
+      fs.mkdirSync(v2)
+      fs.writeFileSync(path.join(v1, 'app.bin'), oldData)
+      fs.writeFileSync(path.join(v2, 'app.bin'), newData)
+      fs.writeFileSync(path.join(v1, 'old.txt'), 'old')
+      fs.writeFileSync(path.join(v2, 'new.txt'), 'new')
+      const plan = planUpdateSync(createManifestSync(v1), createManifestSync(v2))
+      assert.deepStrictEqual(
+        plan.operations.map((op) => [op.op, op.path]),
+        [
+          ['patch', 'app.bin'],
+          ['add', 'new.txt'],
+          ['delete', 'old.txt'],
+        ],
+      )
+      assert.ok(plan.downloadSize > 0)
+      assert.throws(() => planUpdateSync('not json', '{}'))
+    })
+  })
+
+  describe('A/B slots', () => {
+    it('should stage, boot, commit and roll back updates', () => {
+      const slots: AbSlotsJs = { slotA: file('slot-a'), slotB: file('slot-b'), stateFile: file('slots.json') }
+      fs.mkdirSync(file('slot-a'))
+      assert.strictEqual(slotStateSync(slots).current, 'a')
+
+      // 准备后的槽位目录不存在，由调用方 (如 applyBundleToDir) 写入更新
+      const install = (dir: string) => {
+        fs.mkdirSync(dir)
+        fs.writeFileSync(path.join(dir, 'app.bin'), newData)
+      }
+      assert.strictEqual(prepareUpdateSync(slots), file('slot-b'))
+      install(file('slot-b'))
+      let state = stageUpdateSync(slots, 2)
+      assert.strictEqual(state.pending, 'b')
+      assert.strictEqual(state.triesLeft, 2)
+      assert.strictEqual(bootSlotSync(slots).current, 'b')
+
+      state = commitUpdateSync(slots)
+      assert.strictEqual(state.active, 'b')
+      assert.strictEqual(state.pending, undefined)
+
+      install(prepareUpdateSync(slots))
+      stageUpdateSync(slots)
+      assert.throws(() => prepareUpdateSync(slots))
+      state = rollbackUpdateSync(slots)
+      assert.strictEqual(state.active, 'b')
+      assert.strictEqual(state.current, 'b')
+    })
+  })
+
+  describe('#patchFromUrl()', () => {
+    let server: http.Server
+    let baseUrl = ''
+
+    before(async () => {
+      diffSync(oldFile, newFile, file('served.patch'))
+      server = http.createServer((req, res) => {
+        if (req.url === '/served.patch') {
+          res.end(fs.readFileSync(file('served.patch')))
+        } else {
+          res.statusCode = 404
+          res.end()
+        }
+      })
+      server.listen(0, '127.0.0.1')
+      await once(server, 'listening')
+      const address = server.address()
+      if (address && typeof address === 'object') {
+        baseUrl = `http://127.0.0.1:${address.port}`
+      }
+    })
+
+    after(() => {
+      server.close()
+    })
+
+    it('should download and apply a patch', async () => {
+      const size = await patchFromUrl(oldFile, `${baseUrl}/served.patch`, file('url.out'), { timeout: 5000 })
+      assert.strictEqual(size, newData.length)
+      assert.ok(fs.readFileSync(file('url.out')).equals(newData))
+    })
+
+    it('should reject on HTTP errors and oversized patches', async () => {
+      await assert.rejects(patchFromUrl(oldFile, `${baseUrl}/missing.patch`, file('url-missing.out')))
+      await assert.rejects(
+        patchFromUrl(oldFile, `${baseUrl}/served.patch`, file('url-limit.out'), { maxPatchSize: 16 }),
+        /over the 16 byte limit/,
+      )
+    })
+
+    it('should fail synchronously for unreachable or unsupported URLs', () => {
+      // 同步版本会阻塞事件循环，无法访问本进程内的服务器，只检查失败路径
+      assert.throws(() => patchFromUrlSync(oldFile, 'ftp://127.0.0.1/served.patch', file('url-ftp.out')))
+      assert.throws(() => patchFromUrlSync(oldFile, 'http://127.0.0.1:1/served.patch', file('url-refused.out')))
+      assert.ok(!fs.existsSync(file('url-refused.out')))
+    })
+  })
+
+  describe('#diffToShmSync()', () => {
+    it('should write the patch into named shared memory', function () {
+      if (process.platform !== 'linux') {
+        this.skip()
+      }
+      const name = `/bsdiff-test-${process.pid}`
+      const written = diffToShmSync(oldFile, newFile, name)
+      try {
+        const patchData = fs.readFileSync(`/dev/shm${name}`)
+        assert.strictEqual(patchData.length, written)
+        assert.ok(patchBufferSync(oldData, patchData).equals(newData))
+      } finally {
+        fs.rmSync(`/dev/shm${name}`, { force: true })
+      }
+    })
+  })
+
+  describe('#setLogCallback()', () => {
+    it('should forward library log messages', async () => {
+      const messages: string[] = []
+      setLogCallback((level, message) => messages.push(`${level}: ${message}`))
+      try {
+        await diff(oldFile, newFile, file('logged.patch'), { exeTransform: true })
+        await eventually(() => messages.some((m) => m.includes('executable')))
+      } finally {
+        setLogCallback(null)
+      }
+      assert.ok(messages.some((m) => m.startsWith('info: ') && m.includes('not executables')))
+    })
+  })
+})
@@ -0,0 +1,27 @@
+//! 浏览器端的补丁应用：基于核心库的内存路径 (不使用 mmap 与临时目录，输入输出均为 `Uint8Array`)，
+//! 可直接应用 Node.js 端生成的补丁。
+//!
+//! 构建：`wasm-pack build wasm --target web`
+
+use js_sys::{Error, Reflect};
+use bsdiff_rust_core::{BsdiffError, BsdiffRust, OptimizationConfig};
+use wasm_bindgen::prelude::*;
+
+/// 将核心库错误转换为 JavaScript `Error`，可归类时设置与 Node.js 绑定一致的 `code` (`ERR_*`)
+fn js_error(e: BsdiffError) -> JsValue {
+    let error = Error::new(&e.to_string());
+    if let Some(code) = e.code() {
+        let _ = Reflect::set(&error, &JsValue::from_str("code"), &JsValue::from_str(code.as_str()));
+    }
+    error.into()
+}
+
+/// 对旧文件数据应用补丁，返回新文件数据
+///
+/// 与 Node.js 端 `patchBufferSync` 相同的解码路径：校验补丁头摘要与负载 CRC，损坏的补丁抛出 `ERR_CORRUPT_PATCH`。
+/// 加密或字典压缩的补丁需要密钥或字典，此处不支持。`maxOutputSize` 限制输出大小，超出时抛出 `ERR_LIMIT_EXCEEDED`。
+#[wasm_bindgen]
+pub fn patch(old: &[u8], patch: &[u8], max_output_size: Option<f64>) -> Result<Vec<u8>, JsValue> {
+    let config = OptimizationConfig { max_output_size: max_output_size.map(|size| size as u64), ..Default::default() };
+    BsdiffRust::patch_bytes(old, patch, &config).map_err(js_error)
+}